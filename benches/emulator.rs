@@ -0,0 +1,129 @@
+// Criterion benchmarks of the emulation core: `cargo bench`.
+
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+
+use nes::bus::Bus;
+use nes::cpu6502::{new_cpu, trace, write_trace, CPU};
+use nes::power_on::PowerOnConfig;
+use nes::ppu::tile_decoder::{decode_tiles, decode_tiles_scalar};
+use nes::rom::Rom;
+
+const NESTEST_ROM_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/nestest.nes");
+// Number of instructions of nestest.log, i.e. the whole automation mode run
+const NESTEST_INSTRUCTIONS: u64 = 8991;
+
+fn nestest_cpu() -> CPU {
+    let rom_data = std::fs::read(NESTEST_ROM_PATH).expect("Failed to read nestest.nes");
+    let rom = Rom::parse_nes_rom(rom_data).expect("Failed to parse nestest.nes");
+    let mut cpu = new_cpu(Bus::new(rom));
+    cpu.power_on(&PowerOnConfig::default()).unwrap();
+    cpu
+}
+
+// Instructions per second: the reported throughput is in instructions ("elements").
+fn bench_nestest(c: &mut Criterion) {
+    let mut group = c.benchmark_group("cpu");
+    group.throughput(Throughput::Elements(NESTEST_INSTRUCTIONS));
+    group.bench_function("nestest", |b| {
+        b.iter_batched(
+            || {
+                let mut cpu = nestest_cpu();
+                // Automation mode, see src/nestest.rs
+                cpu.program_counter = 0xC000;
+                cpu
+            },
+            |mut cpu| {
+                for _ in 0..NESTEST_INSTRUCTIONS {
+                    cpu.step();
+                }
+                cpu
+            },
+            BatchSize::LargeInput,
+        )
+    });
+    group.finish();
+}
+
+// The cartridge used by the unit tests: PRG ROM full of NOPs, which never touches the PPU
+fn test_rom_cpu() -> CPU {
+    let mut cpu = new_cpu(Bus::new(Rom::test_rom()));
+    cpu.power_on(&PowerOnConfig::default()).unwrap();
+    cpu
+}
+
+// There is no PPU rendering yet: a frame is the CPU running for one frame worth of cycles,
+// plus the hash of the machine state that stands in for the framebuffer.
+fn bench_frame(c: &mut Criterion) {
+    let mut cpu = test_rom_cpu();
+    c.bench_function("frame/run_frame", |b| b.iter(|| black_box(cpu.run_frame())));
+}
+
+fn bench_savestate(c: &mut Criterion) {
+    let mut cpu = test_rom_cpu();
+    cpu.run_frame();
+    let state = cpu.save_state();
+
+    let mut group = c.benchmark_group("savestate");
+    group.throughput(Throughput::Bytes(state.len() as u64));
+    group.bench_function("save", |b| b.iter(|| black_box(cpu.save_state())));
+    group.bench_function("load", |b| b.iter(|| cpu.load_state(black_box(&state)).unwrap()));
+    group.finish();
+}
+
+// What rollback netplay does for each frame played again: load the state before it, then save
+// the state again and play the frame. Has to stay far below 1 ms to redo several frames in one.
+fn bench_rollback(c: &mut Criterion) {
+    let mut cpu = test_rom_cpu();
+    cpu.run_frame();
+    let state = cpu.save_state();
+    c.bench_function("rollback/replay_frame", |b| {
+        b.iter(|| {
+            cpu.load_state(black_box(&state)).unwrap();
+            black_box(cpu.save_state());
+            black_box(cpu.run_frame())
+        })
+    });
+}
+
+// Formatting one nestest.log line: a new String per line, or a buffer reused from line to line.
+fn bench_trace(c: &mut Criterion) {
+    let mut cpu = nestest_cpu();
+    // LDA ($80),Y, one of the longest lines
+    cpu.write_u8(0x0000, 0xB1);
+    cpu.write_u8(0x0001, 0x80);
+    cpu.program_counter = 0x0000;
+
+    let mut group = c.benchmark_group("trace");
+    group.bench_function("trace", |b| b.iter(|| black_box(trace(black_box(&cpu)))));
+    let mut line = String::new();
+    group.bench_function("write_trace", |b| {
+        b.iter(|| {
+            line.clear();
+            write_trace(black_box(&cpu), &mut line);
+            black_box(line.len())
+        })
+    });
+    group.finish();
+}
+
+// Background tile decoding of one frame (240 scanlines of 33 tiles): SIMD against the scalar fallback.
+fn bench_tile_decoding(c: &mut Criterion) {
+    const TILES: usize = 33 * 240;
+    let low: Vec<u8> = (0..TILES).map(|tile| (tile * 7) as u8).collect();
+    let high: Vec<u8> = (0..TILES).map(|tile| (tile * 13) as u8).collect();
+    let attributes: Vec<u8> = (0..TILES).map(|tile| (tile / 2) as u8 & 3).collect();
+    let mut out = vec![0; TILES * 8];
+
+    let mut group = c.benchmark_group("tile_decoding");
+    group.throughput(Throughput::Elements(TILES as u64 * 8));
+    group.bench_function("scalar", |b| {
+        b.iter(|| decode_tiles_scalar(black_box(&low), black_box(&high), black_box(&attributes), &mut out))
+    });
+    group.bench_function("simd", |b| {
+        b.iter(|| decode_tiles(black_box(&low), black_box(&high), black_box(&attributes), &mut out))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_nestest, bench_frame, bench_savestate, bench_rollback, bench_trace, bench_tile_decoding);
+criterion_main!(benches);
@@ -0,0 +1,148 @@
+use std::collections::VecDeque;
+use std::ops::RangeInclusive;
+
+// Memory access log for the debugger: records the reads and writes the CPU makes to watched
+// addresses, to answer questions like "who wrote $0720?".
+// Only the last `capacity` accesses are kept. Debugger peeks and pokes are not logged.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AccessKind {
+    Read,
+    Write,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MemoryAccess {
+    // Cycle counter and program counter at the start of the instruction doing the access
+    pub cycle: u64,
+    pub pc: u16,
+    pub address: u16,
+    pub value: u8,
+    pub kind: AccessKind,
+}
+
+#[derive(Debug, Clone)]
+pub struct AccessLog {
+    watched: Vec<RangeInclusive<u16>>,
+    entries: VecDeque<MemoryAccess>,
+    capacity: usize,
+    // Instruction being executed, set by the CPU before each instruction
+    cycle: u64,
+    pc: u16,
+}
+
+impl AccessLog {
+    // Empty log watching no address: call `watch` or `watch_range` to select what to record.
+    pub fn new(capacity: usize) -> Self {
+        Self { watched: Vec::new(), entries: VecDeque::with_capacity(capacity), capacity, cycle: 0, pc: 0 }
+    }
+
+    pub fn watch(&mut self, address: u16) {
+        self.watch_range(address..=address);
+    }
+
+    pub fn watch_range(&mut self, range: RangeInclusive<u16>) {
+        self.watched.push(range);
+    }
+
+    pub fn unwatch_all(&mut self) {
+        self.watched.clear();
+    }
+
+    pub fn is_watched(&self, address: u16) -> bool {
+        self.watched.iter().any(|range| range.contains(&address))
+    }
+
+    pub fn set_context(&mut self, cycle: u64, pc: u16) {
+        self.cycle = cycle;
+        self.pc = pc;
+    }
+
+    pub fn record(&mut self, address: u16, value: u8, kind: AccessKind) {
+        if self.capacity == 0 || !self.is_watched(address) {
+            return;
+        }
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(MemoryAccess { cycle: self.cycle, pc: self.pc, address, value, kind });
+    }
+
+    // Logged accesses, oldest first
+    pub fn entries(&self) -> impl Iterator<Item = &MemoryAccess> {
+        self.entries.iter()
+    }
+
+    // Logged writes to `address`, most recent first
+    pub fn writes_to(&self, address: u16) -> impl Iterator<Item = &MemoryAccess> {
+        self.entries.iter().rev().filter(move |access| access.address == address && access.kind == AccessKind::Write)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::access_log::{AccessKind, AccessLog};
+    use crate::bus::Bus;
+    use crate::cpu6502::new_cpu;
+    use crate::rom::Rom;
+
+    #[test]
+    fn test_ring_buffer_keeps_watched_accesses() {
+        let mut log = AccessLog::new(2);
+        log.watch_range(0x0700..=0x07FF);
+        log.record(0x0010, 1, AccessKind::Write);
+        for value in 0..3 {
+            log.record(0x0720, value, AccessKind::Write);
+        }
+        let values: Vec<u8> = log.entries().map(|access| access.value).collect();
+        assert_eq!(values, vec![1, 2]);
+    }
+
+    #[test]
+    fn test_who_wrote_address() {
+        let mut cpu = new_cpu(Bus::new(Rom::test_rom()));
+        // LDA #$05, STA $0720, LDA $0720, INC $0720
+        let program = [0xA9, 0x05, 0x8D, 0x20, 0x07, 0xAD, 0x20, 0x07, 0xEE, 0x20, 0x07];
+        for (offset, byte) in program.iter().enumerate() {
+            cpu.write_u8(0x0600 + offset as u16, *byte);
+        }
+        cpu.program_counter = 0x0600;
+
+        let mut log = AccessLog::new(16);
+        log.watch(0x0720);
+        cpu.bus.enable_access_log(log);
+        for _ in 0..4 {
+            cpu.step();
+        }
+
+        let log = cpu.bus.access_log().unwrap();
+        let accesses: Vec<(u16, u8, AccessKind)> = log.entries().map(|access| (access.pc, access.value, access.kind)).collect();
+        assert_eq!(accesses, vec![
+            (0x0602, 0x05, AccessKind::Write),
+            (0x0605, 0x05, AccessKind::Read),
+            // Read-modify-write: the original value is written back before the result
+            (0x0608, 0x05, AccessKind::Read),
+            (0x0608, 0x05, AccessKind::Write),
+            (0x0608, 0x06, AccessKind::Write),
+        ]);
+        let last_write = log.writes_to(0x0720).next().unwrap();
+        assert_eq!((last_write.pc, last_write.cycle), (0x0608, 10));
+        drop(log);
+
+        assert!(cpu.bus.disable_access_log().is_some());
+        cpu.write_u8(0x0720, 0);
+        assert!(cpu.bus.access_log().is_none());
+    }
+}
@@ -0,0 +1,166 @@
+use std::collections::VecDeque;
+use crate::audio::{Channel, APU_SAMPLE_RATE};
+
+// Per-channel APU state captured once per frame, for music visualizers (oscilloscope, piano roll).
+// The values are the ones of the internal units, so a frontend can also show them raw, e.g. in a
+// debugger view similar to the APU viewer of Mesen.
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct PulseState {
+    // 11-bit timer period
+    pub period: u16,
+    // Output of the envelope or the constant volume (0 - 15)
+    pub volume: u8,
+    pub length_counter: u8,
+    // Duty cycle (0: 12.5%, 1: 25%, 2: 50%, 3: 25% negated) and position in its 8-step sequence
+    pub duty: u8,
+    pub sequence_position: u8,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct TriangleState {
+    pub period: u16,
+    pub length_counter: u8,
+    pub linear_counter: u8,
+    // Position in the 32-step triangle sequence
+    pub sequence_position: u8,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct NoiseState {
+    pub period: u16,
+    pub volume: u8,
+    pub length_counter: u8,
+    // Short mode (93-step sequence), which sounds metallic
+    pub short_mode: bool,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct DmcState {
+    pub period: u16,
+    // Address of the next sample byte and number of bytes left to play
+    pub address: u16,
+    pub bytes_remaining: u16,
+    pub output_level: u8,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ApuState {
+    pub pulse: [PulseState; 2],
+    pub triangle: TriangleState,
+    pub noise: NoiseState,
+    pub dmc: DmcState,
+}
+
+impl ApuState {
+    // Whether the channel is heard: a silenced channel is not drawn by visualizers.
+    pub fn is_playing(&self, channel: Channel) -> bool {
+        match channel {
+            Channel::Pulse1 | Channel::Pulse2 => {
+                let pulse = &self.pulse[channel as usize];
+                // Periods below 8 are muted by the sweep unit
+                pulse.length_counter > 0 && pulse.volume > 0 && pulse.period >= 8
+            }
+            Channel::Triangle => self.triangle.length_counter > 0 && self.triangle.linear_counter > 0,
+            Channel::Noise => self.noise.length_counter > 0 && self.noise.volume > 0,
+            Channel::Dmc => self.dmc.bytes_remaining > 0,
+        }
+    }
+
+    // Frequency of the note played by a tonal channel, in Hz.
+    // The pulse timer is clocked every other CPU cycle with an 8-step sequence, the triangle one
+    // every CPU cycle with a 32-step sequence.
+    pub fn frequency(&self, channel: Channel) -> Option<f64> {
+        let (period, divider) = match channel {
+            Channel::Pulse1 | Channel::Pulse2 => (self.pulse[channel as usize].period, 16.0),
+            Channel::Triangle => (self.triangle.period, 32.0),
+            Channel::Noise | Channel::Dmc => return None,
+        };
+        Some(APU_SAMPLE_RATE / (divider * (period as f64 + 1.0)))
+    }
+
+    // MIDI note number (69 = A4 = 440 Hz) of the note played by a tonal channel, for piano rolls.
+    pub fn midi_note(&self, channel: Channel) -> Option<u8> {
+        let frequency = self.frequency(channel)?;
+        let note = 69.0 + 12.0 * (frequency / 440.0).log2();
+        (0.0..=127.0).contains(&note.round()).then_some(note.round() as u8)
+    }
+}
+
+// States of the last `capacity` frames, oldest first, e.g. to scroll a piano roll.
+#[derive(Debug, Clone)]
+pub struct ApuStateHistory {
+    frames: VecDeque<ApuState>,
+    capacity: usize,
+}
+
+impl ApuStateHistory {
+    pub fn new(capacity: usize) -> Self {
+        Self { frames: VecDeque::with_capacity(capacity), capacity }
+    }
+
+    // Called at the end of each frame with the state of the APU.
+    pub fn push(&mut self, state: ApuState) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.frames.len() == self.capacity {
+            self.frames.pop_front();
+        }
+        self.frames.push_back(state);
+    }
+
+    pub fn frames(&self) -> impl Iterator<Item = &ApuState> {
+        self.frames.iter()
+    }
+
+    pub fn latest(&self) -> Option<&ApuState> {
+        self.frames.back()
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::apu_state::{ApuState, ApuStateHistory, PulseState};
+    use crate::audio::Channel;
+
+    #[test]
+    fn test_notes() {
+        let mut state = ApuState::default();
+        // A4: 1789773 / (16 * 440) - 1
+        state.pulse[1] = PulseState { period: 253, volume: 15, length_counter: 10, ..PulseState::default() };
+        // A3 on the triangle, which is an octave lower than a pulse with the same period
+        state.triangle.period = 253;
+        state.triangle.length_counter = 10;
+
+        assert!((state.frequency(Channel::Pulse2).unwrap() - 440.0).abs() < 1.0);
+        assert_eq!(state.midi_note(Channel::Pulse2), Some(69));
+        assert_eq!(state.midi_note(Channel::Triangle), Some(57));
+        assert_eq!(state.frequency(Channel::Noise), None);
+
+        assert!(state.is_playing(Channel::Pulse2));
+        assert!(!state.is_playing(Channel::Pulse1));
+        // The linear counter also silences the triangle
+        assert!(!state.is_playing(Channel::Triangle));
+    }
+
+    #[test]
+    fn test_history() {
+        let mut history = ApuStateHistory::new(2);
+        for period in 1..=3 {
+            let mut state = ApuState::default();
+            state.dmc.period = period;
+            history.push(state);
+        }
+        assert_eq!(history.frames().map(|state| state.dmc.period).collect::<Vec<u16>>(), vec![2, 3]);
+        assert_eq!(history.latest().unwrap().dmc.period, 3);
+    }
+}
@@ -0,0 +1,172 @@
+use std::cell::Cell;
+use crate::audio::Channel;
+use crate::savestate::{StateReader, StateWriter};
+
+// The APU status register (0x4015), as seen by the CPU:
+//   read:  bits 0-3: length counter of pulse 1, pulse 2, triangle, noise is not zero
+//          bit 4: DMC bytes remaining, bit 5: open bus, bit 6: frame IRQ, bit 7: DMC IRQ
+//          Reading clears the frame IRQ flag (not the DMC one).
+//   write: bits 0-4 enable the channels. Disabling a channel clears its length counter (and the
+//          bytes remaining of the DMC). Writing clears the DMC IRQ flag.
+// The APU channels are not emulated yet: they are expected to update the flags through the setters
+// (see the README roadmap). The CPU follows the IRQ flags after each instruction (see
+// CPU::sync_apu_irqs).
+
+const DMC_ACTIVE: u8 = 0x10;
+const OPEN_BUS: u8 = 0x20;
+const FRAME_IRQ: u8 = 0x40;
+const DMC_IRQ: u8 = 0x80;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ApuStatus {
+    // Bits 0-4: channels whose length counter (or DMC bytes remaining) is not zero
+    active: u8,
+    // Reads are done through `&self` by the bus, hence the Cell.
+    frame_irq: Cell<bool>,
+    dmc_irq: bool,
+}
+
+impl ApuStatus {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn channel_bit(channel: Channel) -> u8 {
+        match channel {
+            Channel::Dmc => DMC_ACTIVE,
+            _ => 1 << channel as u8,
+        }
+    }
+
+    pub fn set_channel_active(&mut self, channel: Channel, active: bool) {
+        if active {
+            self.active |= Self::channel_bit(channel);
+        } else {
+            self.active &= !Self::channel_bit(channel);
+        }
+    }
+
+    pub fn is_channel_active(&self, channel: Channel) -> bool {
+        self.active & Self::channel_bit(channel) != 0
+    }
+
+    pub fn set_frame_irq(&mut self, asserted: bool) {
+        self.frame_irq.set(asserted);
+    }
+
+    pub fn set_dmc_irq(&mut self, asserted: bool) {
+        self.dmc_irq = asserted;
+    }
+
+    pub fn frame_irq(&self) -> bool {
+        self.frame_irq.get()
+    }
+
+    pub fn dmc_irq(&self) -> bool {
+        self.dmc_irq
+    }
+
+    // Value read by the CPU, without the side effect (for debugger views). `open_bus` is the last
+    // value of the data bus, which gives bit 5.
+    pub fn peek(&self, open_bus: u8) -> u8 {
+        let mut value = self.active | (open_bus & OPEN_BUS);
+        if self.frame_irq.get() {
+            value |= FRAME_IRQ;
+        }
+        if self.dmc_irq {
+            value |= DMC_IRQ;
+        }
+        value
+    }
+
+    pub fn read(&self, open_bus: u8) -> u8 {
+        let value = self.peek(open_bus);
+        self.frame_irq.set(false);
+        value
+    }
+
+    pub fn write(&mut self, data: u8) {
+        self.active &= data & 0x1F;
+        self.dmc_irq = false;
+    }
+
+    pub fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.active);
+        writer.write_bool(self.frame_irq.get());
+        writer.write_bool(self.dmc_irq);
+    }
+
+    pub fn load_state(&mut self, reader: &mut StateReader) -> Result<(), String> {
+        self.active = reader.read_u8()? & 0x1F;
+        self.frame_irq.set(reader.read_bool()?);
+        self.dmc_irq = reader.read_bool()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::apu_status::ApuStatus;
+    use crate::audio::Channel;
+    use crate::bus::Bus;
+    use crate::cpu6502::new_cpu;
+    use crate::interrupts::IrqSource;
+    use crate::loader::Loader;
+    use crate::power_on::PowerOnConfig;
+
+    #[test]
+    fn test_read_and_write() {
+        let mut status = ApuStatus::new();
+        assert_eq!(status.read(0x40), 0x00);
+
+        status.set_channel_active(Channel::Pulse1, true);
+        status.set_channel_active(Channel::Noise, true);
+        status.set_channel_active(Channel::Dmc, true);
+        status.set_frame_irq(true);
+        status.set_dmc_irq(true);
+        assert_eq!(status.peek(0x40), 0xD9);
+        // Bit 5 comes from the data bus
+        assert_eq!(status.peek(0xFF), 0xF9);
+
+        // Reading clears the frame IRQ only
+        assert_eq!(status.read(0x40), 0xD9);
+        assert_eq!(status.read(0x40), 0x99);
+
+        // Writing disables the channels whose bit is clear, and clears the DMC IRQ
+        status.write(0x08);
+        assert_eq!(status.read(0x40), 0x08);
+        assert!(status.is_channel_active(Channel::Noise));
+        assert!(!status.is_channel_active(Channel::Dmc));
+        // Enabling a channel does not load its length counter
+        status.write(0x1F);
+        assert_eq!(status.read(0x40), 0x08);
+    }
+
+    #[test]
+    fn test_read_clears_frame_irq_line() {
+        // LDA $4015; LDX $4015; JMP *
+        let program = [0xAD, 0x15, 0x40, 0xAE, 0x15, 0x40, 0x4C, 0x06, 0xC0];
+        let mut cpu = new_cpu(Bus::new(Loader::new(0xC000).build_rom(&program).unwrap()));
+        cpu.power_on(&PowerOnConfig::default()).unwrap();
+        cpu.bus.apu_status_mut().set_frame_irq(true);
+        cpu.bus.apu_status_mut().set_channel_active(Channel::Triangle, true);
+        cpu.sync_apu_irqs();
+        assert!(cpu.is_irq_source_asserted(IrqSource::FrameCounter));
+        assert_eq!(cpu.bus.peek_u8(0x4015), 0x44);
+
+        cpu.step();
+        assert_eq!(cpu.accumulator, 0x44);
+        assert!(!cpu.is_irq_source_asserted(IrqSource::FrameCounter));
+        cpu.step();
+        assert_eq!(cpu.x_register, 0x04);
+
+        // The DMC IRQ is acknowledged by writes
+        cpu.bus.apu_status_mut().set_dmc_irq(true);
+        cpu.sync_apu_irqs();
+        assert!(cpu.is_irq_source_asserted(IrqSource::Dmc));
+        cpu.write_u8(0x4015, 0x00);
+        cpu.sync_apu_irqs();
+        assert!(!cpu.is_irq_asserted());
+        assert_eq!(cpu.bus.peek_u8(0x4015), 0x00);
+    }
+}
@@ -0,0 +1,426 @@
+// Support for compressed ROMs (.zip and .gz), so ROM collections do not have to be extracted first.
+// Both formats use DEFLATE (RFC 1951), which is decoded here without any external dependency.
+
+use crate::hash::crc32;
+
+const GZIP_MAGIC_NUMBERS: &[u8; 2] = &[0x1F, 0x8B];
+const ZIP_LOCAL_HEADER_SIGNATURE: &[u8; 4] = b"PK\x03\x04";
+const ZIP_CENTRAL_HEADER_SIGNATURE: &[u8; 4] = b"PK\x01\x02";
+const ZIP_END_OF_CENTRAL_DIRECTORY_SIGNATURE: &[u8; 4] = b"PK\x05\x06";
+
+// Returns the iNES data contained in `data`.
+// Archives are detected from their magic numbers and decompressed in memory;
+// any other content is returned unchanged.
+pub fn extract_rom(data: Vec<u8>) -> Result<Vec<u8>, String> {
+    if data.starts_with(GZIP_MAGIC_NUMBERS) {
+        extract_gzip(&data)
+    } else if data.starts_with(ZIP_LOCAL_HEADER_SIGNATURE) {
+        extract_zip(&data)
+    } else {
+        Ok(data)
+    }
+}
+
+fn read_u16_le(data: &[u8], offset: usize) -> Result<u16, String> {
+    data.get(offset..offset + 2)
+        .map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]]))
+        .ok_or_else(|| "Archive is truncated".to_string())
+}
+
+fn read_u32_le(data: &[u8], offset: usize) -> Result<u32, String> {
+    data.get(offset..offset + 4)
+        .map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+        .ok_or_else(|| "Archive is truncated".to_string())
+}
+
+// gzip format (RFC 1952): 10 bytes header, optional fields, DEFLATE data, CRC-32 and size.
+fn extract_gzip(data: &[u8]) -> Result<Vec<u8>, String> {
+    const FLAG_HEADER_CRC: u8 = 0b0000_0010;
+    const FLAG_EXTRA: u8 = 0b0000_0100;
+    const FLAG_NAME: u8 = 0b0000_1000;
+    const FLAG_COMMENT: u8 = 0b0001_0000;
+
+    if data.len() < 18 {
+        return Err("Invalid gzip file: File is truncated".to_string());
+    }
+    if data[2] != 8 {
+        return Err(format!("Invalid gzip file: Unsupported compression method {}", data[2]));
+    }
+
+    let flags = data[3];
+    let mut position = 10;
+    if flags & FLAG_EXTRA != 0 {
+        position += 2 + read_u16_le(data, position)? as usize;
+    }
+    // The file name and comment are null terminated strings
+    for flag in [FLAG_NAME, FLAG_COMMENT] {
+        if flags & flag != 0 {
+            let end = data[position.min(data.len())..].iter().position(|byte| *byte == 0)
+                .ok_or_else(|| "Invalid gzip file: Unterminated header string".to_string())?;
+            position += end + 1;
+        }
+    }
+    if flags & FLAG_HEADER_CRC != 0 {
+        position += 2;
+    }
+
+    let trailer = data.len() - 8;
+    if position > trailer {
+        return Err("Invalid gzip file: File is truncated".to_string());
+    }
+    let content = inflate(&data[position..trailer])?;
+
+    if crc32(&content) != read_u32_le(data, trailer)? {
+        return Err("Invalid gzip file: CRC mismatch".to_string());
+    }
+    if content.len() as u32 != read_u32_le(data, trailer + 4)? {
+        return Err("Invalid gzip file: Size mismatch".to_string());
+    }
+    Ok(content)
+}
+
+// zip format: the central directory at the end of the file lists the entries,
+// each entry pointing to a local header followed by its data.
+fn extract_zip(data: &[u8]) -> Result<Vec<u8>, String> {
+    // The end of central directory record is 22 bytes long, followed by a comment of up to 64KB
+    let end_of_directory = (0..=data.len().saturating_sub(22)).rev()
+        .take(22 + 0xFFFF)
+        .find(|offset| data[*offset..].starts_with(ZIP_END_OF_CENTRAL_DIRECTORY_SIGNATURE))
+        .ok_or_else(|| "Invalid zip file: End of central directory not found".to_string())?;
+
+    let entry_count = read_u16_le(data, end_of_directory + 10)?;
+    let mut position = read_u32_le(data, end_of_directory + 16)? as usize;
+
+    for _ in 0..entry_count {
+        if !data[position.min(data.len())..].starts_with(ZIP_CENTRAL_HEADER_SIGNATURE) {
+            return Err("Invalid zip file: Corrupted central directory".to_string());
+        }
+        let method = read_u16_le(data, position + 10)?;
+        let crc = read_u32_le(data, position + 16)?;
+        let compressed_size = read_u32_le(data, position + 20)? as usize;
+        let size = read_u32_le(data, position + 24)? as usize;
+        let name_len = read_u16_le(data, position + 28)? as usize;
+        let extra_len = read_u16_le(data, position + 30)? as usize;
+        let comment_len = read_u16_le(data, position + 32)? as usize;
+        let local_header = read_u32_le(data, position + 42)? as usize;
+        let name = data.get(position + 46..position + 46 + name_len)
+            .ok_or_else(|| "Archive is truncated".to_string())?;
+        position += 46 + name_len + extra_len + comment_len;
+
+        if !String::from_utf8_lossy(name).to_lowercase().ends_with(".nes") {
+            continue;
+        }
+
+        // The local header repeats the name and has its own extra field
+        let data_start = local_header + 30
+            + read_u16_le(data, local_header + 26)? as usize
+            + read_u16_le(data, local_header + 28)? as usize;
+        let compressed = data.get(data_start..data_start + compressed_size)
+            .ok_or_else(|| "Archive is truncated".to_string())?;
+
+        let content = match method {
+            0 => compressed.to_vec(),
+            8 => inflate(compressed)?,
+            _ => return Err(format!("Invalid zip file: Unsupported compression method {}", method)),
+        };
+        if content.len() != size || crc32(&content) != crc {
+            return Err(format!("Invalid zip file: {} is corrupted", String::from_utf8_lossy(name)));
+        }
+        return Ok(content);
+    }
+    Err("No .nes file found in the zip archive".to_string())
+}
+
+// Reads the DEFLATE stream bit by bit, least significant bit first.
+struct BitReader<'a> {
+    data: &'a [u8],
+    position: usize,
+    bit_buffer: u32,
+    bit_count: u32,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, position: 0, bit_buffer: 0, bit_count: 0 }
+    }
+
+    fn bits(&mut self, count: u32) -> Result<u32, String> {
+        while self.bit_count < count {
+            let byte = *self.data.get(self.position).ok_or_else(|| "Compressed data is truncated".to_string())?;
+            self.bit_buffer |= (byte as u32) << self.bit_count;
+            self.position += 1;
+            self.bit_count += 8;
+        }
+        let value = self.bit_buffer & ((1u32 << count) - 1);
+        self.bit_buffer >>= count;
+        self.bit_count -= count;
+        Ok(value)
+    }
+
+    // Stored blocks start on a byte boundary
+    fn align_to_byte(&mut self) {
+        self.bit_buffer = 0;
+        self.bit_count = 0;
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], String> {
+        let bytes = self.data.get(self.position..self.position + len)
+            .ok_or_else(|| "Compressed data is truncated".to_string())?;
+        self.position += len;
+        Ok(bytes)
+    }
+}
+
+// Canonical Huffman code, described by the number of codes of each length
+// and the symbols sorted by code.
+struct Huffman {
+    counts: [u16; 16],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Self {
+        let mut counts = [0u16; 16];
+        for length in lengths {
+            counts[*length as usize] += 1;
+        }
+        counts[0] = 0;
+
+        let mut symbols = Vec::with_capacity(lengths.len());
+        for length in 1..16 {
+            for (symbol, symbol_length) in lengths.iter().enumerate() {
+                if *symbol_length as usize == length {
+                    symbols.push(symbol as u16);
+                }
+            }
+        }
+        Self { counts, symbols }
+    }
+
+    // Codes are read one bit at a time, most significant bit first.
+    fn decode(&self, reader: &mut BitReader) -> Result<u16, String> {
+        let mut code: i32 = 0;
+        let mut first: i32 = 0;
+        let mut index: i32 = 0;
+        for length in 1..16 {
+            code |= reader.bits(1)? as i32;
+            let count = self.counts[length] as i32;
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err("Invalid compressed data: Bad Huffman code".to_string())
+    }
+}
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258,
+];
+const LENGTH_EXTRA_BITS: [u8; 29] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145,
+    8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA_BITS: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13,
+];
+// Order in which the code lengths of the code length alphabet are stored in dynamic blocks
+const CODE_LENGTH_ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+// Decompresses a raw DEFLATE stream.
+pub fn inflate(data: &[u8]) -> Result<Vec<u8>, String> {
+    let mut reader = BitReader::new(data);
+    let mut output = Vec::new();
+
+    loop {
+        let is_last_block = reader.bits(1)? == 1;
+        match reader.bits(2)? {
+            0 => inflate_stored_block(&mut reader, &mut output)?,
+            1 => {
+                let (literals, distances) = fixed_huffman_codes();
+                inflate_huffman_block(&mut reader, &mut output, &literals, &distances)?;
+            }
+            2 => {
+                let (literals, distances) = read_dynamic_huffman_codes(&mut reader)?;
+                inflate_huffman_block(&mut reader, &mut output, &literals, &distances)?;
+            }
+            _ => return Err("Invalid compressed data: Reserved block type".to_string()),
+        }
+        if is_last_block {
+            return Ok(output);
+        }
+    }
+}
+
+fn inflate_stored_block(reader: &mut BitReader, output: &mut Vec<u8>) -> Result<(), String> {
+    reader.align_to_byte();
+    let header = reader.bytes(4)?;
+    let len = u16::from_le_bytes([header[0], header[1]]);
+    let complement = u16::from_le_bytes([header[2], header[3]]);
+    if len != !complement {
+        return Err("Invalid compressed data: Stored block length mismatch".to_string());
+    }
+    output.extend_from_slice(reader.bytes(len as usize)?);
+    Ok(())
+}
+
+fn fixed_huffman_codes() -> (Huffman, Huffman) {
+    let mut lengths = [0u8; 288];
+    lengths[..144].fill(8);
+    lengths[144..256].fill(9);
+    lengths[256..280].fill(7);
+    lengths[280..].fill(8);
+    (Huffman::new(&lengths), Huffman::new(&[5; 30]))
+}
+
+fn read_dynamic_huffman_codes(reader: &mut BitReader) -> Result<(Huffman, Huffman), String> {
+    let literal_count = reader.bits(5)? as usize + 257;
+    let distance_count = reader.bits(5)? as usize + 1;
+    let code_length_count = reader.bits(4)? as usize + 4;
+
+    let mut code_length_lengths = [0u8; 19];
+    for index in CODE_LENGTH_ORDER.iter().take(code_length_count) {
+        code_length_lengths[*index] = reader.bits(3)? as u8;
+    }
+    let code_lengths = Huffman::new(&code_length_lengths);
+
+    // Literal and distance code lengths are stored as a single sequence
+    let mut lengths = Vec::with_capacity(literal_count + distance_count);
+    while lengths.len() < literal_count + distance_count {
+        let symbol = code_lengths.decode(reader)?;
+        let (value, repeat) = match symbol {
+            0..=15 => (symbol as u8, 1),
+            16 => {
+                let previous = *lengths.last().ok_or_else(|| "Invalid compressed data: Nothing to repeat".to_string())?;
+                (previous, 3 + reader.bits(2)?)
+            }
+            17 => (0, 3 + reader.bits(3)?),
+            _ => (0, 11 + reader.bits(7)?),
+        };
+        lengths.extend(std::iter::repeat_n(value, repeat as usize));
+    }
+    if lengths.len() > literal_count + distance_count {
+        return Err("Invalid compressed data: Too many code lengths".to_string());
+    }
+
+    Ok((Huffman::new(&lengths[..literal_count]), Huffman::new(&lengths[literal_count..])))
+}
+
+fn inflate_huffman_block(
+    reader: &mut BitReader,
+    output: &mut Vec<u8>,
+    literals: &Huffman,
+    distances: &Huffman,
+) -> Result<(), String> {
+    loop {
+        let symbol = literals.decode(reader)? as usize;
+        match symbol {
+            0..=255 => output.push(symbol as u8),
+            256 => return Ok(()),
+            _ => {
+                let index = symbol - 257;
+                if index >= LENGTH_BASE.len() {
+                    return Err("Invalid compressed data: Bad length symbol".to_string());
+                }
+                let len = LENGTH_BASE[index] as usize + reader.bits(LENGTH_EXTRA_BITS[index] as u32)? as usize;
+
+                let index = distances.decode(reader)? as usize;
+                if index >= DISTANCE_BASE.len() {
+                    return Err("Invalid compressed data: Bad distance symbol".to_string());
+                }
+                let distance = DISTANCE_BASE[index] as usize + reader.bits(DISTANCE_EXTRA_BITS[index] as u32)? as usize;
+                if distance > output.len() {
+                    return Err("Invalid compressed data: Distance is too far back".to_string());
+                }
+
+                // The copied range can overlap the bytes being written, so copy byte by byte
+                let start = output.len() - distance;
+                for offset in 0..len {
+                    output.push(output[start + offset]);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::archive::{extract_rom, inflate};
+    use crate::rom::Rom;
+
+    // iNES file with 16KB of NOPs as PRG ROM and 8KB of empty CHR ROM, compressed with gzip -9
+    const GZIPPED_ROM: [u8; 74] = [
+        0x1F, 0x8B, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x03, 0xED, 0xC1, 0x31, 0x0D, 0x00, 0x20, 0x0C, 0x00,
+        0xB0, 0xCD, 0x06, 0x7E, 0x78, 0xF7, 0xCC, 0xBF, 0x01, 0x54, 0xEC, 0xC3, 0x02, 0x21, 0x69, 0x5B, 0xBB, 0x57,
+        0x66, 0x5C, 0x07, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x78, 0x2E, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x80, 0xEF, 0x0D, 0x20, 0xE9, 0xC7, 0x9D, 0x10, 0x60,
+        0x00, 0x00,
+    ];
+
+    // Same ROM as "Game.NES" (deflated), stored after a "readme.txt" entry (not compressed)
+    const ZIPPED_ROM: [u8; 271] = [
+        0x50, 0x4B, 0x03, 0x04, 0x14, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x21, 0x00, 0x86, 0xA6, 0x10, 0x36,
+        0x05, 0x00, 0x00, 0x00, 0x05, 0x00, 0x00, 0x00, 0x0A, 0x00, 0x00, 0x00, 0x72, 0x65, 0x61, 0x64, 0x6D, 0x65,
+        0x2E, 0x74, 0x78, 0x74, 0x68, 0x65, 0x6C, 0x6C, 0x6F, 0x50, 0x4B, 0x03, 0x04, 0x14, 0x00, 0x00, 0x00, 0x08,
+        0x00, 0x00, 0x00, 0x21, 0x00, 0x20, 0xE9, 0xC7, 0x9D, 0x38, 0x00, 0x00, 0x00, 0x10, 0x60, 0x00, 0x00, 0x08,
+        0x00, 0x00, 0x00, 0x47, 0x61, 0x6D, 0x65, 0x2E, 0x4E, 0x45, 0x53, 0xED, 0xC1, 0x31, 0x0D, 0x00, 0x20, 0x0C,
+        0x00, 0xB0, 0xCD, 0x06, 0x7E, 0x78, 0xF7, 0xCC, 0xBF, 0x01, 0x54, 0xEC, 0xC3, 0x02, 0x21, 0x69, 0x5B, 0xBB,
+        0x57, 0x66, 0x5C, 0x07, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x78, 0x2E, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x80, 0xEF, 0x0D, 0x50, 0x4B, 0x01, 0x02, 0x14,
+        0x03, 0x14, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x21, 0x00, 0x86, 0xA6, 0x10, 0x36, 0x05, 0x00, 0x00,
+        0x00, 0x05, 0x00, 0x00, 0x00, 0x0A, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x80,
+        0x01, 0x00, 0x00, 0x00, 0x00, 0x72, 0x65, 0x61, 0x64, 0x6D, 0x65, 0x2E, 0x74, 0x78, 0x74, 0x50, 0x4B, 0x01,
+        0x02, 0x14, 0x03, 0x14, 0x00, 0x00, 0x00, 0x08, 0x00, 0x00, 0x00, 0x21, 0x00, 0x20, 0xE9, 0xC7, 0x9D, 0x38,
+        0x00, 0x00, 0x00, 0x10, 0x60, 0x00, 0x00, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x80, 0x01, 0x2D, 0x00, 0x00, 0x00, 0x47, 0x61, 0x6D, 0x65, 0x2E, 0x4E, 0x45, 0x53, 0x50, 0x4B, 0x05,
+        0x06, 0x00, 0x00, 0x00, 0x00, 0x02, 0x00, 0x02, 0x00, 0x6E, 0x00, 0x00, 0x00, 0x8B, 0x00, 0x00, 0x00, 0x00,
+        0x00,
+    ];
+
+    fn assert_is_test_rom(data: Vec<u8>) {
+        assert_eq!(data.len(), 16 + 16384 + 8192);
+        let rom = Rom::parse_nes_rom(data).expect("extracted ROM should parse");
+        assert!(rom.prg_rom.iter().all(|byte| *byte == 0xEA));
+        assert!(rom.chr_rom.iter().all(|byte| *byte == 0x00));
+    }
+
+    #[test]
+    fn test_inflate_stored_and_fixed_blocks() {
+        let stored = [0x01, 0x06, 0x00, 0xF9, 0xFF, 0x73, 0x74, 0x6F, 0x72, 0x65, 0x64];
+        assert_eq!(inflate(&stored).unwrap(), b"stored");
+
+        let fixed = [0xF3, 0x48, 0xCD, 0xC9, 0xC9, 0xD7, 0x51, 0xF0, 0x40, 0xA2, 0x14, 0x01];
+        assert_eq!(inflate(&fixed).unwrap(), b"Hello, Hello, Hello!");
+
+        assert!(inflate(&fixed[..6]).is_err());
+    }
+
+    #[test]
+    fn test_extract_gzip() {
+        assert_is_test_rom(extract_rom(GZIPPED_ROM.to_vec()).expect("gzip should extract"));
+
+        let mut corrupted = GZIPPED_ROM.to_vec();
+        corrupted[66] ^= 0xFF; // CRC
+        assert!(extract_rom(corrupted).is_err());
+    }
+
+    #[test]
+    fn test_extract_zip_finds_nes_entry() {
+        assert_is_test_rom(extract_rom(ZIPPED_ROM.to_vec()).expect("zip should extract"));
+
+        // Renaming the entry leaves no .nes file in the archive
+        let renamed: Vec<u8> = ZIPPED_ROM.iter().enumerate()
+            .map(|(index, byte)| if index == 248 { b'X' } else { *byte })
+            .collect();
+        assert!(extract_rom(renamed).is_err());
+    }
+
+    #[test]
+    fn test_extract_uncompressed_rom() {
+        let data = vec![0x4E, 0x45, 0x53, 0x1A];
+        assert_eq!(extract_rom(data.clone()).unwrap(), data);
+    }
+}
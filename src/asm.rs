@@ -0,0 +1,355 @@
+use crate::cpu6502::{is_unofficial_operand, lookup_operand, AddressingMode, Operand, CPU};
+use crate::labels::Labels;
+
+// Inline assembler, the counterpart of the disassembler: assembles one line of standard 6502
+// syntax at a given address, so the debugger can patch live code ("assemble at cursor", like
+// FCEUX). Everything the disassembler prints can be assembled back:
+//   LDA #$10    LDA $10     LDA $10,X   LDX $10,Y   LDA $1234   LDA $1234,X   LDA $1234,Y
+//   JMP ($1234) LDA ($10,X) LDA ($10),Y ASL A (or ASL)          BNE $C010     .DB $12,$34
+// Values are written $hex, 0xhex, %binary, decimal, a label name, or `*` (the address of the
+// instruction), optionally added or subtracted (`oam+4`, `*-2`); `<` and `>` select the low and
+// high bytes. Zero page addressing is used when the value fits in a byte, unless it is written
+// with 4 hex digits ($0010). Unofficial opcodes are assembled from their name (DCP, LAX, ...) or
+// the nestest one (SAX, ISB, *NOP); a `*` prefix prefers the unofficial variants of NOP and SBC.
+// Everything after a `;` is a comment.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Value {
+    value: u16,
+    // Written with more than 2 hex digits, which forces absolute addressing
+    wide: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Syntax {
+    None,
+    Accumulator,
+    Immediate(Value),
+    Indirect(Value),
+    IndirectX(Value),
+    IndirectY(Value),
+    Address(Value),
+    AddressX(Value),
+    AddressY(Value),
+}
+
+// Names of the instructions in the opcode table, with the other common names of unofficial opcodes
+fn candidate_names(mnemonic: &str) -> &[&str] {
+    match mnemonic {
+        "NOP" => &["NOP", "DOP", "TOP"],
+        "SAX" => &["AAX"],
+        "ISB" | "INS" => &["ISC"],
+        "ANC" => &["AAC"],
+        "ALR" => &["ASR"],
+        "SBX" => &["AXS"],
+        "LXA" => &["ATX"],
+        "ANE" => &["XAA"],
+        "LAS" => &["LAR"],
+        "SHY" => &["SYA"],
+        "SHX" => &["SXA"],
+        "TAS" => &["XAS"],
+        "AHX" | "SHA" => &["AXA"],
+        "DCM" => &["DCP"],
+        _ => &[],
+    }
+}
+
+// Assembles one instruction (or a .DB directive) located at `address`. Returns no byte for an
+// empty line or a comment.
+pub fn assemble(line: &str, address: u16, labels: &Labels) -> Result<Vec<u8>, String> {
+    let line = line.split(';').next().unwrap_or_default().trim();
+    if line.is_empty() {
+        return Ok(Vec::new());
+    }
+    let (mnemonic, operand) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+    let operand: String = operand.chars().filter(|c| !c.is_whitespace()).collect();
+    let mnemonic = mnemonic.to_ascii_uppercase();
+
+    if matches!(mnemonic.as_str(), ".DB" | ".BYTE") {
+        return operand.split(',').map(|value| byte(parse_value(value, address, labels)?.value)).collect();
+    }
+
+    let (mnemonic, prefer_unofficial) = match mnemonic.strip_prefix('*') {
+        Some(mnemonic) => (mnemonic.to_string(), true),
+        None => (mnemonic, false),
+    };
+    let mut names = vec![mnemonic.as_str()];
+    names.extend_from_slice(candidate_names(&mnemonic));
+    if !(0..=255).filter_map(lookup_operand).any(|operand| names.contains(&operand.name)) {
+        return Err(format!("Unknown instruction `{}`", mnemonic));
+    }
+
+    let syntax = parse_syntax(&operand, address, labels)?;
+    for mode in candidate_modes(syntax) {
+        if let Some(instruction) = find_operand(&names, mode, prefer_unofficial) {
+            return encode(&instruction, syntax, address);
+        }
+    }
+    Err(format!("`{}` does not support the operand `{}`", mnemonic, operand))
+}
+
+// Assembles `line` at `address` into the memory of the CPU (PRG ROM included, like the memory
+// editor), using its labels. Returns the address of the next instruction.
+pub fn assemble_at(cpu: &mut CPU, address: u16, line: &str) -> Result<u16, String> {
+    let bytes = assemble(line, address, &cpu.labels)?;
+    for (offset, &byte) in bytes.iter().enumerate() {
+        cpu.bus.poke_u8(address.wrapping_add(offset as u16), byte)?;
+    }
+    Ok(address.wrapping_add(bytes.len() as u16))
+}
+
+fn parse_syntax(operand: &str, address: u16, labels: &Labels) -> Result<Syntax, String> {
+    let upper = operand.to_ascii_uppercase();
+    let value = |text: &str| parse_value(text, address, labels);
+    // Lengths are the same in `operand` and `upper`, the operand being ASCII once parsed
+    let inner = |prefix: usize, suffix: usize| &operand[prefix..operand.len() - suffix];
+
+    Ok(if operand.is_empty() {
+        Syntax::None
+    } else if upper == "A" {
+        Syntax::Accumulator
+    } else if let Some(immediate) = operand.strip_prefix('#') {
+        Syntax::Immediate(value(immediate)?)
+    } else if upper.starts_with('(') && upper.ends_with(",X)") {
+        Syntax::IndirectX(value(inner(1, 3))?)
+    } else if upper.starts_with('(') && upper.ends_with("),Y") {
+        Syntax::IndirectY(value(inner(1, 3))?)
+    } else if upper.starts_with('(') && upper.ends_with(')') {
+        Syntax::Indirect(value(inner(1, 1))?)
+    } else if upper.ends_with(",X") {
+        Syntax::AddressX(value(inner(0, 2))?)
+    } else if upper.ends_with(",Y") {
+        Syntax::AddressY(value(inner(0, 2))?)
+    } else {
+        Syntax::Address(value(operand)?)
+    })
+}
+
+fn candidate_modes(syntax: Syntax) -> Vec<AddressingMode> {
+    let zero_page = |value: Value| !value.wide && value.value <= 0xFF;
+    match syntax {
+        Syntax::None => vec![AddressingMode::Implicit, AddressingMode::Accumulator],
+        Syntax::Accumulator => vec![AddressingMode::Accumulator],
+        Syntax::Immediate(_) => vec![AddressingMode::Immediate],
+        Syntax::Indirect(_) => vec![AddressingMode::Indirect],
+        Syntax::IndirectX(_) => vec![AddressingMode::IndirectX],
+        Syntax::IndirectY(_) => vec![AddressingMode::IndirectY],
+        Syntax::Address(value) if zero_page(value) => vec![AddressingMode::Relative, AddressingMode::ZeroPage, AddressingMode::Absolute],
+        Syntax::Address(_) => vec![AddressingMode::Relative, AddressingMode::Absolute],
+        Syntax::AddressX(value) if zero_page(value) => vec![AddressingMode::ZeroPageX, AddressingMode::AbsoluteX],
+        Syntax::AddressX(_) => vec![AddressingMode::AbsoluteX],
+        Syntax::AddressY(value) if zero_page(value) => vec![AddressingMode::ZeroPageY, AddressingMode::AbsoluteY],
+        Syntax::AddressY(_) => vec![AddressingMode::AbsoluteY],
+    }
+}
+
+// Several opcodes share a name and an addressing mode (e.g. the unofficial NOPs): the official one
+// is used, or the first unofficial one when they are preferred.
+fn find_operand(names: &[&str], mode: AddressingMode, prefer_unofficial: bool) -> Option<Operand> {
+    let mut matching = (0..=255)
+        .filter_map(lookup_operand)
+        .filter(|operand| names.contains(&operand.name) && same_mode(operand.addressing_mode, mode));
+    let first = matching.next()?;
+    if is_unofficial_operand(&first) == prefer_unofficial {
+        return Some(first);
+    }
+    Some(matching.find(|operand| is_unofficial_operand(operand) == prefer_unofficial).unwrap_or(first))
+}
+
+fn same_mode(a: AddressingMode, b: AddressingMode) -> bool {
+    std::mem::discriminant(&a) == std::mem::discriminant(&b)
+}
+
+fn encode(instruction: &Operand, syntax: Syntax, address: u16) -> Result<Vec<u8>, String> {
+    let value = match syntax {
+        Syntax::None | Syntax::Accumulator => return Ok(vec![instruction.opcode]),
+        Syntax::Immediate(value)
+        | Syntax::Indirect(value)
+        | Syntax::IndirectX(value)
+        | Syntax::IndirectY(value)
+        | Syntax::Address(value)
+        | Syntax::AddressX(value)
+        | Syntax::AddressY(value) => value.value,
+    };
+    match instruction.addressing_mode {
+        AddressingMode::Relative => {
+            // Branches are relative to the next instruction (PC + 2)
+            let offset = value as i32 - (address as i32 + 2);
+            let offset = i8::try_from(offset).map_err(|_| format!("Branch target ${:04X} is out of range", value))?;
+            Ok(vec![instruction.opcode, offset as u8])
+        }
+        _ if instruction.bytes == 2 => Ok(vec![instruction.opcode, byte(value)?]),
+        _ => {
+            let [low, high] = value.to_le_bytes();
+            Ok(vec![instruction.opcode, low, high])
+        }
+    }
+}
+
+fn byte(value: u16) -> Result<u8, String> {
+    u8::try_from(value).map_err(|_| format!("${:04X} does not fit in a byte", value))
+}
+
+// A sum of terms, e.g. "oam+4" or "*-2"
+fn parse_value(text: &str, address: u16, labels: &Labels) -> Result<Value, String> {
+    let (select, text) = match text.chars().next() {
+        Some(select @ ('<' | '>')) => (Some(select), &text[1..]),
+        _ => (None, text),
+    };
+    if text.is_empty() {
+        return Err("Missing value".to_string());
+    }
+
+    let mut total = 0i32;
+    let mut wide = false;
+    let mut sign = 1;
+    let mut start = 0;
+    // A leading sign is not an operator, and `*` is only the current address at the start of a term
+    for (index, c) in text.char_indices().chain(std::iter::once((text.len(), '+'))) {
+        if (c == '+' || c == '-') && index > start {
+            let term = parse_term(&text[start..index], address, labels)?;
+            total += sign * term.value as i32;
+            wide |= term.wide;
+            sign = if c == '+' { 1 } else { -1 };
+            start = index + 1;
+        }
+    }
+    let value = u16::try_from(total).map_err(|_| format!("`{}` is out of range", text))?;
+    Ok(match select {
+        Some('<') => Value { value: value & 0xFF, wide: false },
+        Some(_) => Value { value: value >> 8, wide: false },
+        None => Value { value, wide },
+    })
+}
+
+fn parse_term(term: &str, address: u16, labels: &Labels) -> Result<Value, String> {
+    let invalid = || format!("Invalid value `{}`", term);
+    let hex = term.strip_prefix('$').or_else(|| term.strip_prefix("0x")).or_else(|| term.strip_prefix("0X"));
+    if let Some(digits) = hex {
+        let value = u16::from_str_radix(digits, 16).map_err(|_| invalid())?;
+        return Ok(Value { value, wide: digits.len() > 2 });
+    }
+    let value = if term == "*" {
+        address
+    } else if let Some(digits) = term.strip_prefix('%') {
+        u16::from_str_radix(digits, 2).map_err(|_| invalid())?
+    } else if term.starts_with(|c: char| c.is_ascii_digit()) {
+        term.parse().map_err(|_| invalid())?
+    } else {
+        labels.address_of(term).ok_or_else(|| format!("Unknown label `{}`", term))?
+    };
+    Ok(Value { value, wide: false })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::asm::{assemble, assemble_at};
+    use crate::bus::Bus;
+    use crate::cpu6502::{lookup_operand, new_cpu};
+    use crate::disasm::disassemble_instruction;
+    use crate::labels::Labels;
+
+    fn asm(line: &str, address: u16) -> Result<Vec<u8>, String> {
+        assemble(line, address, &Labels::new())
+    }
+
+    #[test]
+    fn test_addressing_modes() {
+        assert_eq!(asm("LDA #$10", 0), Ok(vec![0xA9, 0x10]));
+        assert_eq!(asm("lda $10", 0), Ok(vec![0xA5, 0x10]));
+        assert_eq!(asm("LDA $10,X", 0), Ok(vec![0xB5, 0x10]));
+        assert_eq!(asm("LDX $10,Y", 0), Ok(vec![0xB6, 0x10]));
+        assert_eq!(asm("LDA $1234", 0), Ok(vec![0xAD, 0x34, 0x12]));
+        assert_eq!(asm("LDA $0010", 0), Ok(vec![0xAD, 0x10, 0x00]));
+        assert_eq!(asm("LDA $1234,X", 0), Ok(vec![0xBD, 0x34, 0x12]));
+        // There is no zero page,Y mode for LDA
+        assert_eq!(asm("LDA $10,Y", 0), Ok(vec![0xB9, 0x10, 0x00]));
+        assert_eq!(asm("JMP ($1234)", 0), Ok(vec![0x6C, 0x34, 0x12]));
+        assert_eq!(asm("LDA ($10,X)", 0), Ok(vec![0xA1, 0x10]));
+        assert_eq!(asm("LDA ( $10 ), y ; comment", 0), Ok(vec![0xB1, 0x10]));
+        assert_eq!(asm("ASL A", 0), Ok(vec![0x0A]));
+        assert_eq!(asm("ASL", 0), Ok(vec![0x0A]));
+        assert_eq!(asm("CLC", 0), Ok(vec![0x18]));
+        assert_eq!(asm("LDA #%1010", 0), Ok(vec![0xA9, 0x0A]));
+        assert_eq!(asm("LDA #200", 0), Ok(vec![0xA9, 200]));
+        assert_eq!(asm(".DB $12, 3", 0), Ok(vec![0x12, 0x03]));
+        assert_eq!(asm("  ; only a comment", 0), Ok(vec![]));
+    }
+
+    #[test]
+    fn test_branches_and_values() {
+        assert_eq!(asm("BNE $C010", 0xC000), Ok(vec![0xD0, 0x0E]));
+        assert_eq!(asm("BEQ *", 0xC000), Ok(vec![0xF0, 0xFE]));
+        assert_eq!(asm("BPL *-4", 0xC000), Ok(vec![0x10, 0xFA]));
+        assert_eq!(asm("JMP *", 0xC000), Ok(vec![0x4C, 0x00, 0xC0]));
+        assert!(asm("BNE $C100", 0xC000).unwrap_err().contains("out of range"));
+
+        let mut labels = Labels::new();
+        labels.insert(0x0200, "oam", 0x100);
+        labels.insert(0xC5F2, "reset_handler", 1);
+        labels.insert(0x0010, "counter", 1);
+        assert_eq!(assemble("JSR reset_handler", 0xC000, &labels), Ok(vec![0x20, 0xF2, 0xC5]));
+        assert_eq!(assemble("STA oam+4,X", 0xC000, &labels), Ok(vec![0x9D, 0x04, 0x02]));
+        assert_eq!(assemble("INC counter", 0xC000, &labels), Ok(vec![0xE6, 0x10]));
+        assert_eq!(assemble("LDA #>reset_handler", 0xC000, &labels), Ok(vec![0xA9, 0xC5]));
+        assert_eq!(assemble("LDA #<reset_handler", 0xC000, &labels), Ok(vec![0xA9, 0xF2]));
+        assert!(assemble("JSR missing", 0xC000, &labels).unwrap_err().contains("Unknown label"));
+    }
+
+    #[test]
+    fn test_unofficial_and_errors() {
+        assert_eq!(asm("NOP", 0), Ok(vec![0xEA]));
+        assert_eq!(asm("*NOP", 0), Ok(vec![0x1A]));
+        assert_eq!(asm("NOP $10", 0), Ok(vec![0x04, 0x10]));
+        assert_eq!(asm("SBC #$10", 0), Ok(vec![0xE9, 0x10]));
+        assert_eq!(asm("*SBC #$10", 0), Ok(vec![0xEB, 0x10]));
+        assert_eq!(asm("SAX $10", 0), Ok(vec![0x87, 0x10]));
+        assert_eq!(asm("DCP ($10),Y", 0), Ok(vec![0xD3, 0x10]));
+
+        assert!(asm("FOO $10", 0).unwrap_err().contains("Unknown instruction"));
+        assert!(asm("LDA #$1234", 0).unwrap_err().contains("does not fit"));
+        assert!(asm("STA #$10", 0).unwrap_err().contains("does not support"));
+        assert!(asm("LDA ($1234),Y", 0).is_err());
+        assert!(asm("LDA $12G", 0).is_err());
+    }
+
+    // Every instruction the disassembler prints is assembled back to the same name, addressing
+    // mode and operand
+    #[test]
+    fn test_disassembly_round_trip() {
+        let mut bus = Bus::new_flat();
+        for opcode in 0..=255u8 {
+            let Some(expected) = lookup_operand(opcode) else { continue };
+            let address = 0xC000;
+            for (offset, byte) in [opcode, 0x42, 0x12].into_iter().enumerate() {
+                bus.poke_u8(address + offset as u16, byte).unwrap();
+            }
+            let line = disassemble_instruction(&bus, address);
+            let bytes = asm(&format!("{} {}", line.name, line.operand), address).unwrap_or_else(|error| panic!("{}: {}", line, error));
+            let actual = lookup_operand(bytes[0]).unwrap();
+            assert_eq!(bytes[1..], line.bytes[1..], "{}", line);
+            assert_eq!((actual.name, actual.bytes), (expected.name, expected.bytes), "{}", line);
+        }
+    }
+
+    #[test]
+    fn test_assemble_at() {
+        let mut cpu = new_cpu(Bus::new_flat());
+        let next = assemble_at(&mut cpu, 0x0400, "LDX #$0A").unwrap();
+        let next = assemble_at(&mut cpu, next, "DEX").unwrap();
+        let next = assemble_at(&mut cpu, next, "BNE $0402").unwrap();
+        assert_eq!(next, 0x0405);
+        assert_eq!(disassemble_instruction(&cpu.bus, 0x0403).to_string(), "0403  D0 FD     BNE $0402");
+
+        cpu.program_counter = 0x0400;
+        for _ in 0..21 {
+            cpu.step();
+        }
+        assert_eq!((cpu.x_register, cpu.program_counter), (0, 0x0405));
+
+        // Patching PRG ROM
+        let mut cpu = new_cpu(Bus::new(crate::rom::Rom::test_rom()));
+        assert_eq!(assemble_at(&mut cpu, 0x8000, "JMP $8000"), Ok(0x8003));
+        assert_eq!(cpu.bus.rom().prg_rom[..3], [0x4C, 0x00, 0x80]);
+    }
+}
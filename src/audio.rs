@@ -0,0 +1,607 @@
+use std::collections::VecDeque;
+use std::f64::consts::PI;
+use std::fs::File;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::sync::{Arc, Mutex};
+
+// The APU produces one sample per CPU cycle (NTSC CPU clock).
+pub const APU_SAMPLE_RATE: f64 = 1_789_773.0;
+
+// Converts the APU output to the sample rate of the audio device (usually 44.1 or 48 kHz).
+// The input is low-pass filtered first to remove the frequencies the output rate cannot represent
+// (otherwise they alias as audible noise), then linearly interpolated at the output rate.
+pub struct Resampler {
+    output_rate: f64,
+    // Number of input samples per output sample
+    step: f64,
+    // Time of the next output sample, in input samples, relative to `previous`
+    position: f64,
+    previous: f32,
+    // One-pole low-pass filter, applied twice for a steeper slope
+    filter_coefficient: f32,
+    filter_state: [f32; 2],
+}
+
+impl Resampler {
+    pub fn new(input_rate: f64, output_rate: f64) -> Self {
+        let mut resampler = Self {
+            output_rate,
+            step: 0.0,
+            position: 0.0,
+            previous: 0.0,
+            filter_coefficient: 0.0,
+            filter_state: [0.0; 2],
+        };
+        resampler.set_input_rate(input_rate);
+        resampler
+    }
+
+    // Changes the input rate without resetting the stream, e.g. to follow the emulation speed:
+    // at 2x speed, twice as many APU samples are produced per second, so the input rate doubles.
+    pub fn set_input_rate(&mut self, input_rate: f64) {
+        // Cut slightly below the Nyquist frequency of the output
+        let cutoff = self.output_rate * 0.45;
+        self.step = input_rate / self.output_rate;
+        self.filter_coefficient = (1.0 - (-2.0 * PI * cutoff / input_rate).exp()) as f32;
+    }
+
+    // Resamples `input` and appends the resulting samples to `output`.
+    // The state is kept between calls, so the input can be fed in chunks of any size.
+    pub fn process(&mut self, input: &[f32], output: &mut Vec<f32>) {
+        for sample in input {
+            let current = self.low_pass(*sample);
+            while self.position <= 1.0 {
+                output.push(self.previous + (current - self.previous) * self.position as f32);
+                self.position += self.step;
+            }
+            self.position -= 1.0;
+            self.previous = current;
+        }
+    }
+
+    fn low_pass(&mut self, sample: f32) -> f32 {
+        let mut value = sample;
+        for state in self.filter_state.iter_mut() {
+            *state += (value - *state) * self.filter_coefficient;
+            value = *state;
+        }
+        value
+    }
+}
+
+// Output stage of the console, applied to the mixer output at the output sample rate: two
+// first-order high-pass filters (90 Hz and 440 Hz), which remove the DC offset of the mixer and
+// thin out the bass, and a first-order low-pass filter (14 kHz), which softens the harsh edges of
+// the square waves. See https://www.nesdev.org/wiki/APU_Mixer
+// Disabling them gives the raw mixer output.
+const HIGH_PASS_CUTOFFS: [f64; 2] = [90.0, 440.0];
+const LOW_PASS_CUTOFF: f64 = 14_000.0;
+
+#[derive(Debug, Clone)]
+pub struct HardwareFilters {
+    enabled: bool,
+    high_pass_coefficient: [f32; 2],
+    low_pass_coefficient: f32,
+    // Previous input and output of each high-pass filter, and previous output of the low-pass one
+    high_pass_state: [(f32, f32); 2],
+    low_pass_state: f32,
+}
+
+impl HardwareFilters {
+    pub fn new(sample_rate: f64) -> Self {
+        let rc = |cutoff: f64| 1.0 / (2.0 * PI * cutoff);
+        let dt = 1.0 / sample_rate;
+        Self {
+            enabled: true,
+            high_pass_coefficient: HIGH_PASS_CUTOFFS.map(|cutoff| (rc(cutoff) / (rc(cutoff) + dt)) as f32),
+            low_pass_coefficient: (dt / (rc(LOW_PASS_CUTOFF) + dt)) as f32,
+            high_pass_state: [(0.0, 0.0); 2],
+            low_pass_state: 0.0,
+        }
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        self.reset();
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn reset(&mut self) {
+        self.high_pass_state = [(0.0, 0.0); 2];
+        self.low_pass_state = 0.0;
+    }
+
+    // Filters the samples in place. The state is kept between calls.
+    pub fn process(&mut self, samples: &mut [f32]) {
+        if !self.enabled {
+            return;
+        }
+        for sample in samples.iter_mut() {
+            let mut value = *sample;
+            for (coefficient, (previous_input, previous_output)) in self.high_pass_coefficient.iter().zip(self.high_pass_state.iter_mut()) {
+                *previous_output = coefficient * (*previous_output + value - *previous_input);
+                *previous_input = value;
+                value = *previous_output;
+            }
+            self.low_pass_state += (value - self.low_pass_state) * self.low_pass_coefficient;
+            *sample = self.low_pass_state;
+        }
+    }
+}
+
+// Sound channels of the APU.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Channel {
+    Pulse1,
+    Pulse2,
+    Triangle,
+    Noise,
+    Dmc,
+}
+
+impl Channel {
+    pub const ALL: [Channel; 5] = [Channel::Pulse1, Channel::Pulse2, Channel::Triangle, Channel::Noise, Channel::Dmc];
+}
+
+// Channels that are heard, used to isolate channels while listening to music.
+// Muting only affects the mixer output: a muted channel keeps running (e.g. its length counter
+// is still visible through 0x4015), so games behave the same.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChannelMask {
+    enabled: [bool; 5],
+}
+
+impl Default for ChannelMask {
+    fn default() -> Self {
+        Self { enabled: [true; 5] }
+    }
+}
+
+impl ChannelMask {
+    pub fn set_channel_enabled(&mut self, channel: Channel, enabled: bool) {
+        self.enabled[channel as usize] = enabled;
+    }
+
+    pub fn is_channel_enabled(&self, channel: Channel) -> bool {
+        self.enabled[channel as usize]
+    }
+
+    // Only the given channel is heard.
+    pub fn solo(&mut self, channel: Channel) {
+        for other in Channel::ALL {
+            self.set_channel_enabled(other, other == channel);
+        }
+    }
+
+    // Output of a channel as seen by the mixer.
+    pub fn apply(&self, channel: Channel, output: u8) -> u8 {
+        if self.is_channel_enabled(channel) { output } else { 0 }
+    }
+}
+
+// The APU does not add up its channels: the pulse channels share one DAC and the triangle, noise
+// and DMC channels another, both nonlinear, so a loud channel lowers the volume of the others of its
+// group (e.g. the DMC in drum-heavy music attenuates the triangle and the noise). The output of
+// each group is read from a table of the formulas of https://www.nesdev.org/wiki/APU_Mixer:
+//   pulse_out = 95.52 / (8128 / (pulse1 + pulse2) + 100)
+//   tnd_out = 163.67 / (24329 / (3 * triangle + 2 * noise + dmc) + 100)
+// The output goes from 0.0 to about 1.0.
+const PULSE_TABLE_SIZE: usize = 31;
+const TND_TABLE_SIZE: usize = 203;
+
+#[derive(Debug, Clone)]
+pub struct Mixer {
+    pulse_table: [f32; PULSE_TABLE_SIZE],
+    tnd_table: [f32; TND_TABLE_SIZE],
+    pub mask: ChannelMask,
+}
+
+impl Default for Mixer {
+    fn default() -> Self {
+        let mut pulse_table = [0.0; PULSE_TABLE_SIZE];
+        for (n, value) in pulse_table.iter_mut().enumerate().skip(1) {
+            *value = (95.52 / (8128.0 / n as f64 + 100.0)) as f32;
+        }
+        let mut tnd_table = [0.0; TND_TABLE_SIZE];
+        for (n, value) in tnd_table.iter_mut().enumerate().skip(1) {
+            *value = (163.67 / (24329.0 / n as f64 + 100.0)) as f32;
+        }
+        Self { pulse_table, tnd_table, mask: ChannelMask::default() }
+    }
+}
+
+impl Mixer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Mixes the outputs of the channels, in the order of `Channel::ALL`: 0-15 for the pulse,
+    // triangle and noise channels, 0-127 for the DMC. Muted channels (see `mask`) output 0.
+    pub fn mix(&self, outputs: [u8; 5]) -> f32 {
+        let [pulse1, pulse2, triangle, noise, dmc] = Channel::ALL.map(|channel| self.mask.apply(channel, outputs[channel as usize]) as usize);
+        let pulse = (pulse1.min(15) + pulse2.min(15)).min(PULSE_TABLE_SIZE - 1);
+        let tnd = (3 * triangle.min(15) + 2 * noise.min(15) + dmc.min(127)).min(TND_TABLE_SIZE - 1);
+        self.pulse_table[pulse] + self.tnd_table[tnd]
+    }
+}
+
+struct AudioBufferState {
+    samples: VecDeque<f32>,
+    capacity: usize,
+    // Repeated on underrun, since jumping to silence would produce a click
+    last_sample: f32,
+    underruns: u64,
+    overruns: u64,
+    // Receives the pushed samples too while a dump is running
+    wav_dump: Option<WavWriter>,
+    // First write error of the dump, which stops it; returned by `stop_wav_dump`
+    wav_dump_error: Option<String>,
+}
+
+// Ring buffer between the emulation thread (producer) and the audio callback (consumer).
+// Cloning the buffer gives another handle to the same samples, e.g. one to move into the cpal/SDL callback.
+#[derive(Clone)]
+pub struct AudioBuffer {
+    state: Arc<Mutex<AudioBufferState>>,
+}
+
+impl AudioBuffer {
+    // `capacity` bounds the audio latency: the oldest samples are dropped when the buffer is full.
+    pub fn new(capacity: usize) -> Self {
+        let state = AudioBufferState {
+            samples: VecDeque::with_capacity(capacity),
+            capacity,
+            last_sample: 0.0,
+            underruns: 0,
+            overruns: 0,
+            wav_dump: None,
+            wav_dump_error: None,
+        };
+        Self { state: Arc::new(Mutex::new(state)) }
+    }
+
+    pub fn push_samples(&self, samples: &[f32]) {
+        let mut state = self.state.lock().expect("Audio buffer lock is poisoned");
+        for sample in samples {
+            if state.samples.len() == state.capacity {
+                state.samples.pop_front();
+                state.overruns += 1;
+            }
+            state.samples.push_back(*sample);
+        }
+        let result = state.wav_dump.as_mut().map(|wav| wav.write_samples(samples));
+        if let Some(Err(error)) = result {
+            state.wav_dump = None;
+            state.wav_dump_error = Some(error);
+        }
+    }
+
+    // Writes the samples pushed from now on to a WAV file at `sample_rate` (the output rate of the
+    // resampler), replacing the dump in progress if any (for `Apu::start_wav_dump`).
+    pub fn start_wav_dump(&self, path: &str, sample_rate: u32) -> Result<(), String> {
+        let wav = WavWriter::create(path, sample_rate)?;
+        let mut state = self.state.lock().expect("Audio buffer lock is poisoned");
+        state.wav_dump = Some(wav);
+        state.wav_dump_error = None;
+        Ok(())
+    }
+
+    pub fn is_dumping_wav(&self) -> bool {
+        self.state.lock().expect("Audio buffer lock is poisoned").wav_dump.is_some()
+    }
+
+    // Finishes the WAV file and returns the number of samples written, or the error that stopped the dump.
+    pub fn stop_wav_dump(&self) -> Result<u32, String> {
+        let mut state = self.state.lock().expect("Audio buffer lock is poisoned");
+        if let Some(error) = state.wav_dump_error.take() {
+            return Err(error);
+        }
+        let mut wav = state.wav_dump.take().ok_or("No WAV dump in progress")?;
+        wav.finish()?;
+        Ok(wav.samples_written())
+    }
+
+    // Fills `output` with the buffered samples. Must be called from the audio callback.
+    // When the buffer runs dry, the last sample is repeated and an underrun is counted.
+    pub fn fill(&self, output: &mut [f32]) {
+        let mut state = self.state.lock().expect("Audio buffer lock is poisoned");
+        let available = state.samples.len().min(output.len());
+        for (slot, sample) in output.iter_mut().zip(state.samples.drain(..available)) {
+            *slot = sample;
+        }
+
+        if available > 0 {
+            state.last_sample = output[available - 1];
+        }
+        if available < output.len() {
+            output[available..].fill(state.last_sample);
+            state.underruns += 1;
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.state.lock().expect("Audio buffer lock is poisoned").samples.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.state.lock().expect("Audio buffer lock is poisoned").capacity
+    }
+
+    pub fn underruns(&self) -> u64 {
+        self.state.lock().expect("Audio buffer lock is poisoned").underruns
+    }
+
+    pub fn overruns(&self) -> u64 {
+        self.state.lock().expect("Audio buffer lock is poisoned").overruns
+    }
+}
+
+// Writes the resampled audio stream to a mono 16-bit PCM WAV file (for `Apu::start_wav_dump`).
+// The sizes in the header are only known at the end: they are written by `finish`, which is also
+// called on drop so an interrupted dump still gives a valid file.
+pub struct WavWriter {
+    writer: Option<BufWriter<File>>,
+    // Number of samples written
+    samples: u32,
+}
+
+// Size of the RIFF/WAVE header before the samples
+const WAV_HEADER_SIZE: u32 = 44;
+
+impl WavWriter {
+    pub fn create(path: &str, sample_rate: u32) -> Result<Self, String> {
+        let file = File::create(path).map_err(|e| format!("Cannot create {}: {}", path, e))?;
+        let mut wav = Self { writer: Some(BufWriter::new(file)), samples: 0 };
+        wav.write_header(sample_rate).map_err(|e| format!("Cannot write {}: {}", path, e))?;
+        Ok(wav)
+    }
+
+    fn write_header(&mut self, sample_rate: u32) -> std::io::Result<()> {
+        let writer = self.writer.as_mut().expect("WAV file is open");
+        writer.write_all(b"RIFF")?;
+        // Patched by `finish`
+        writer.write_all(&(WAV_HEADER_SIZE - 8).to_le_bytes())?;
+        writer.write_all(b"WAVEfmt ")?;
+        writer.write_all(&16u32.to_le_bytes())?;
+        // PCM, mono
+        writer.write_all(&1u16.to_le_bytes())?;
+        writer.write_all(&1u16.to_le_bytes())?;
+        writer.write_all(&sample_rate.to_le_bytes())?;
+        // Byte rate, block align and bits per sample
+        writer.write_all(&(sample_rate * 2).to_le_bytes())?;
+        writer.write_all(&2u16.to_le_bytes())?;
+        writer.write_all(&16u16.to_le_bytes())?;
+        writer.write_all(b"data")?;
+        writer.write_all(&0u32.to_le_bytes())
+    }
+
+    // Appends samples in the -1.0 to 1.0 range, clamping the ones outside of it.
+    pub fn write_samples(&mut self, samples: &[f32]) -> Result<(), String> {
+        let writer = self.writer.as_mut().ok_or("WAV dump is finished")?;
+        for sample in samples {
+            let value = (sample.clamp(-1.0, 1.0) * i16::MAX as f32).round() as i16;
+            writer.write_all(&value.to_le_bytes()).map_err(|e| format!("Cannot write WAV dump: {}", e))?;
+        }
+        self.samples = self.samples.saturating_add(samples.len() as u32);
+        Ok(())
+    }
+
+    pub fn samples_written(&self) -> u32 {
+        self.samples
+    }
+
+    // Writes the final sizes in the header and closes the file.
+    pub fn finish(&mut self) -> Result<(), String> {
+        let Some(mut writer) = self.writer.take() else {
+            return Ok(());
+        };
+        let data_size = self.samples.saturating_mul(2);
+        let result = (|| {
+            writer.seek(SeekFrom::Start(4))?;
+            writer.write_all(&(data_size.saturating_add(WAV_HEADER_SIZE - 8)).to_le_bytes())?;
+            writer.seek(SeekFrom::Start(40))?;
+            writer.write_all(&data_size.to_le_bytes())?;
+            writer.flush()
+        })();
+        result.map_err(|e| format!("Cannot finish WAV dump: {}", e))
+    }
+}
+
+impl Drop for WavWriter {
+    fn drop(&mut self) {
+        let _ = self.finish();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::audio::{AudioBuffer, Channel, ChannelMask, HardwareFilters, Mixer, Resampler, WavWriter, APU_SAMPLE_RATE};
+
+    #[test]
+    fn test_resampler_output_rate() {
+        let mut resampler = Resampler::new(APU_SAMPLE_RATE, 48_000.0);
+        let mut output = Vec::new();
+        // One second of input, fed in odd-sized chunks
+        let input = vec![0.5f32; APU_SAMPLE_RATE as usize];
+        for chunk in input.chunks(1_000) {
+            resampler.process(chunk, &mut output);
+        }
+
+        assert!((output.len() as i64 - 48_000).abs() <= 1, "unexpected output length: {}", output.len());
+        // A constant signal goes through the filter unchanged once it has settled
+        assert!((output.last().unwrap() - 0.5).abs() < 0.001);
+    }
+
+    #[test]
+    fn test_resampler_filters_high_frequencies() {
+        let mut resampler = Resampler::new(APU_SAMPLE_RATE, 44_100.0);
+        let mut output = Vec::new();
+        // Square wave at 447 kHz, far above what 44.1 kHz can represent
+        let input: Vec<f32> = (0..100_000).map(|i| if i % 4 < 2 { 1.0 } else { -1.0 }).collect();
+        resampler.process(&input, &mut output);
+
+        let peak = output[output.len() / 2..].iter().fold(0.0f32, |peak, sample| peak.max(sample.abs()));
+        assert!(peak < 0.05, "high frequencies should be attenuated, peak is {}", peak);
+    }
+
+    #[test]
+    fn test_audio_buffer_underrun_and_overrun() {
+        let buffer = AudioBuffer::new(4);
+        buffer.push_samples(&[0.1, 0.2, 0.3, 0.4, 0.5, 0.6]);
+        assert_eq!(buffer.len(), 4);
+        assert_eq!(buffer.overruns(), 2);
+
+        let mut output = [0.0f32; 6];
+        buffer.fill(&mut output);
+        // Oldest samples were dropped, then the last sample is repeated
+        assert_eq!(output, [0.3, 0.4, 0.5, 0.6, 0.6, 0.6]);
+        assert_eq!(buffer.underruns(), 1);
+        assert_eq!(buffer.len(), 0);
+    }
+
+    #[test]
+    fn test_audio_buffer_across_threads() {
+        let buffer = AudioBuffer::new(1024);
+        let producer = buffer.clone();
+        std::thread::spawn(move || producer.push_samples(&[0.25; 512]))
+            .join()
+            .expect("producer thread should not panic");
+
+        let mut output = [0.0f32; 512];
+        buffer.fill(&mut output);
+        assert!(output.iter().all(|sample| *sample == 0.25));
+        assert_eq!(buffer.underruns(), 0);
+    }
+
+    #[test]
+    fn test_channel_mask() {
+        let mut mask = ChannelMask::default();
+        assert!(Channel::ALL.iter().all(|channel| mask.is_channel_enabled(*channel)));
+
+        mask.set_channel_enabled(Channel::Noise, false);
+        assert_eq!(mask.apply(Channel::Noise, 12), 0);
+        assert_eq!(mask.apply(Channel::Pulse1, 12), 12);
+
+        mask.solo(Channel::Triangle);
+        assert!(mask.is_channel_enabled(Channel::Triangle));
+        assert!(!mask.is_channel_enabled(Channel::Pulse1));
+        assert!(!mask.is_channel_enabled(Channel::Dmc));
+    }
+
+    #[test]
+    fn test_wav_writer() {
+        let path = std::env::temp_dir().join(format!("nes_wav_dump_{}.wav", std::process::id()));
+        let path = path.to_str().unwrap();
+        let mut wav = WavWriter::create(path, 44_100).unwrap();
+        wav.write_samples(&[0.0, 1.0, -1.0]).unwrap();
+        wav.write_samples(&[0.5, 2.0]).unwrap();
+        assert_eq!(wav.samples_written(), 5);
+        wav.finish().unwrap();
+        assert!(wav.write_samples(&[0.0]).is_err());
+
+        let data = std::fs::read(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(data.len(), 44 + 10);
+        assert_eq!(&data[0..4], b"RIFF");
+        assert_eq!(u32::from_le_bytes(data[4..8].try_into().unwrap()), 36 + 10);
+        assert_eq!(&data[8..16], b"WAVEfmt ");
+        assert_eq!(u32::from_le_bytes(data[24..28].try_into().unwrap()), 44_100);
+        assert_eq!(u16::from_le_bytes(data[34..36].try_into().unwrap()), 16);
+        assert_eq!(u32::from_le_bytes(data[40..44].try_into().unwrap()), 10);
+        let samples: Vec<i16> = data[44..].chunks(2).map(|bytes| i16::from_le_bytes([bytes[0], bytes[1]])).collect();
+        // Out of range samples are clamped
+        assert_eq!(samples, [0, 32767, -32767, 16384, 32767]);
+    }
+
+    #[test]
+    fn test_audio_buffer_wav_dump() {
+        let path = std::env::temp_dir().join(format!("nes_audio_buffer_dump_{}.wav", std::process::id()));
+        let path = path.to_str().unwrap();
+        let buffer = AudioBuffer::new(2);
+        buffer.push_samples(&[0.75]);
+        buffer.start_wav_dump(path, 48_000).unwrap();
+        assert!(buffer.is_dumping_wav());
+        // Samples dropped by the ring buffer are still dumped
+        buffer.push_samples(&[0.25, -0.5, 1.0]);
+        assert_eq!(buffer.stop_wav_dump(), Ok(3));
+        assert!(!buffer.is_dumping_wav());
+        buffer.push_samples(&[0.5]);
+        assert!(buffer.stop_wav_dump().is_err());
+
+        let data = std::fs::read(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(&data[0..4], b"RIFF");
+        assert_eq!(u32::from_le_bytes(data[24..28].try_into().unwrap()), 48_000);
+        assert_eq!(u32::from_le_bytes(data[40..44].try_into().unwrap()), 6);
+        let samples: Vec<i16> = data[44..].chunks(2).map(|bytes| i16::from_le_bytes([bytes[0], bytes[1]])).collect();
+        assert_eq!(samples, [8192, -16384, 32767]);
+    }
+
+    // Peak amplitude of a filtered sine, once the filters have settled
+    fn filtered_amplitude(filters: &mut HardwareFilters, frequency: f64, sample_rate: f64) -> f32 {
+        let mut samples: Vec<f32> = (0..sample_rate as usize)
+            .map(|index| (2.0 * std::f64::consts::PI * frequency * index as f64 / sample_rate).sin() as f32)
+            .collect();
+        filters.process(&mut samples);
+        samples[samples.len() / 2..].iter().fold(0.0, |peak, sample| peak.max(sample.abs()))
+    }
+
+    #[test]
+    fn test_hardware_filters() {
+        let sample_rate = 96_000.0;
+        let mut filters = HardwareFilters::new(sample_rate);
+
+        // The DC offset of the mixer is removed
+        let mut samples = vec![0.5f32; 48_000];
+        filters.process(&mut samples);
+        assert!(samples[..10].iter().any(|sample| *sample > 0.4));
+        assert!(samples.last().unwrap().abs() < 1e-3);
+
+        filters.reset();
+        let middle = filtered_amplitude(&mut filters, 2_000.0, sample_rate);
+        filters.reset();
+        let bass = filtered_amplitude(&mut filters, 50.0, sample_rate);
+        filters.reset();
+        let treble = filtered_amplitude(&mut filters, 30_000.0, sample_rate);
+        assert!(middle > 0.85, "{}", middle);
+        assert!(bass < 0.2, "{}", bass);
+        assert!(treble < 0.55, "{}", treble);
+
+        // Disabled, the samples are untouched
+        filters.set_enabled(false);
+        assert!(!filters.is_enabled());
+        let mut samples = vec![0.5f32; 16];
+        filters.process(&mut samples);
+        assert_eq!(samples, vec![0.5f32; 16]);
+    }
+
+    #[test]
+    fn test_nonlinear_mixer() {
+        let mut mixer = Mixer::new();
+        assert_eq!(mixer.mix([0; 5]), 0.0);
+        // Values of the formulas
+        assert!((mixer.mix([15, 0, 0, 0, 0]) - 95.52 / (8128.0 / 15.0 + 100.0)).abs() < 1e-6);
+        assert!((mixer.mix([0, 0, 0, 0, 127]) - 163.67 / (24329.0 / 127.0 + 100.0)).abs() < 1e-6);
+        let loudest = mixer.mix([15, 15, 15, 15, 127]);
+        assert!(loudest > 0.99 && loudest < 1.01, "{}", loudest);
+
+        // Two pulses are not twice as loud as one
+        assert!(mixer.mix([15, 15, 0, 0, 0]) < 2.0 * mixer.mix([15, 0, 0, 0, 0]) * 0.9);
+        // A loud DMC attenuates the triangle, but not the pulses
+        let triangle_alone = mixer.mix([0, 0, 15, 0, 0]);
+        let triangle_with_dmc = mixer.mix([0, 0, 15, 0, 127]) - mixer.mix([0, 0, 0, 0, 127]);
+        assert!(triangle_with_dmc < triangle_alone * 0.8);
+        let pulse_with_dmc = mixer.mix([15, 0, 0, 0, 127]) - mixer.mix([0, 0, 0, 0, 127]);
+        assert!((pulse_with_dmc - mixer.mix([15, 0, 0, 0, 0])).abs() < 1e-6);
+
+        mixer.mask.solo(Channel::Dmc);
+        assert_eq!(mixer.mix([15, 15, 15, 15, 127]), mixer.mix([0, 0, 0, 0, 127]));
+    }
+}
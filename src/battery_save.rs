@@ -0,0 +1,182 @@
+use std::path::{Path, PathBuf};
+use crate::cpu6502::CPU;
+
+// Manages the .sav file of games with a battery backed PRG RAM.
+// The save is loaded when the game starts, then written back whenever PRG RAM changed:
+// periodically during play (so a crash does not lose progress) and when the emulator exits.
+pub struct SaveManager {
+    path: PathBuf,
+    enabled: bool,
+    // Dirty PRG RAM is flushed every `flush_interval` frames
+    flush_interval: u32,
+    frames_since_flush: u32,
+}
+
+impl SaveManager {
+    // One minute at 60 FPS
+    pub const DEFAULT_FLUSH_INTERVAL: u32 = 60 * 60;
+
+    // The save file is stored next to the ROM: "game.nes" is saved to "game.sav".
+    pub fn new(rom_path: &str, cpu: &CPU) -> Self {
+        Self {
+            path: Path::new(rom_path).with_extension("sav"),
+            enabled: cpu.bus.rom().has_battery(),
+            flush_interval: Self::DEFAULT_FLUSH_INTERVAL,
+            frames_since_flush: 0,
+        }
+    }
+
+    pub fn set_flush_interval(&mut self, frames: u32) {
+        self.flush_interval = frames.max(1);
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    // Loads the save file into PRG RAM. A missing file is not an error: the game was never saved.
+    // Returns true if a save was loaded.
+    pub fn load(&self, cpu: &mut CPU) -> Result<bool, String> {
+        if !self.enabled {
+            return Ok(false);
+        }
+
+        let data = match std::fs::read(&self.path) {
+            Ok(data) => data,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(false),
+            Err(error) => return Err(format!("Failed to read save file {}: {}", self.path.display(), error)),
+        };
+
+        let prg_ram = cpu.bus.prg_ram_mut();
+        if data.len() != prg_ram.len() {
+            return Err(format!(
+                "Invalid save file {}: Expected {} bytes, got {}",
+                self.path.display(), prg_ram.len(), data.len()
+            ));
+        }
+        prg_ram.copy_from_slice(&data);
+        cpu.bus.clear_prg_ram_dirty();
+        Ok(true)
+    }
+
+    // Must be called once per emulated frame.
+    pub fn on_frame(&mut self, cpu: &mut CPU) -> Result<(), String> {
+        self.frames_since_flush += 1;
+        if self.frames_since_flush >= self.flush_interval {
+            self.flush(cpu)?;
+        }
+        Ok(())
+    }
+
+    // Writes PRG RAM to the save file if it changed since the last flush.
+    // Called periodically, on exit, or manually by the frontend.
+    pub fn flush(&mut self, cpu: &mut CPU) -> Result<(), String> {
+        self.frames_since_flush = 0;
+        if !self.enabled || !cpu.bus.is_prg_ram_dirty() {
+            return Ok(());
+        }
+
+        std::fs::write(&self.path, cpu.bus.prg_ram())
+            .map_err(|error| format!("Failed to write save file {}: {}", self.path.display(), error))?;
+        cpu.bus.clear_prg_ram_dirty();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::battery_save::SaveManager;
+    use crate::bus::Bus;
+    use crate::cpu6502::{new_cpu, CPU};
+    use crate::rom::Rom;
+
+    fn battery_cpu() -> CPU {
+        let mut rom = Rom::test_rom();
+        rom.header.flags_6 |= 0b0000_0010;
+        new_cpu(Bus::new(rom))
+    }
+
+    fn temp_rom_path(name: &str) -> String {
+        let path = std::env::temp_dir().join(format!("{}_{}.nes", name, std::process::id()));
+        path.to_string_lossy().to_string()
+    }
+
+    #[test]
+    fn test_periodic_flush_and_load() {
+        let rom_path = temp_rom_path("battery_save_flush");
+        let mut cpu = battery_cpu();
+        let mut manager = SaveManager::new(&rom_path, &cpu);
+        manager.set_flush_interval(2);
+        assert!(manager.path().to_string_lossy().ends_with(".sav"));
+        let _ = std::fs::remove_file(manager.path());
+
+        // Nothing is written while PRG RAM is clean
+        manager.on_frame(&mut cpu).unwrap();
+        manager.on_frame(&mut cpu).unwrap();
+        assert!(!manager.path().exists());
+
+        cpu.write_u8(0x6000, 0x42);
+        manager.on_frame(&mut cpu).unwrap();
+        assert!(!manager.path().exists());
+        manager.on_frame(&mut cpu).unwrap();
+        assert!(manager.path().exists());
+        assert!(!cpu.bus.is_prg_ram_dirty());
+
+        let mut restarted = battery_cpu();
+        assert_eq!(manager.load(&mut restarted), Ok(true));
+        assert_eq!(restarted.read_u8(0x6000), 0x42);
+
+        std::fs::remove_file(manager.path()).unwrap();
+    }
+
+    #[test]
+    fn test_flush_after_loading_state() {
+        let rom_path = temp_rom_path("battery_save_state");
+        let mut cpu = battery_cpu();
+        let mut manager = SaveManager::new(&rom_path, &cpu);
+        let _ = std::fs::remove_file(manager.path());
+        let unchanged = cpu.save_state();
+        cpu.write_u8(0x6000, 0x42);
+        let state = cpu.save_state();
+        manager.flush(&mut cpu).unwrap();
+
+        // Same PRG RAM content: nothing to write
+        cpu.load_state(&state).unwrap();
+        assert!(!cpu.bus.is_prg_ram_dirty());
+
+        cpu.load_state(&unchanged).unwrap();
+        assert!(cpu.bus.is_prg_ram_dirty());
+        manager.flush(&mut cpu).unwrap();
+        assert_eq!(std::fs::read(manager.path()).unwrap()[0], 0x00);
+
+        std::fs::remove_file(manager.path()).unwrap();
+    }
+
+    #[test]
+    fn test_no_battery() {
+        let rom_path = temp_rom_path("battery_save_disabled");
+        let mut cpu = new_cpu(Bus::new(Rom::test_rom()));
+        let mut manager = SaveManager::new(&rom_path, &cpu);
+        assert!(!manager.is_enabled());
+
+        cpu.write_u8(0x6000, 0x42);
+        manager.flush(&mut cpu).unwrap();
+        assert!(!manager.path().exists());
+        assert_eq!(manager.load(&mut cpu), Ok(false));
+    }
+
+    #[test]
+    fn test_load_rejects_wrong_size() {
+        let rom_path = temp_rom_path("battery_save_wrong_size");
+        let mut cpu = battery_cpu();
+        let manager = SaveManager::new(&rom_path, &cpu);
+        std::fs::write(manager.path(), [0u8; 16]).unwrap();
+
+        assert!(manager.load(&mut cpu).is_err());
+        std::fs::remove_file(manager.path()).unwrap();
+    }
+}
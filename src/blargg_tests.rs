@@ -0,0 +1,206 @@
+// Runs blargg's test ROMs (https://github.com/christopherpow/nes-test-roms) and checks the result
+// they report in PRG RAM:
+// - $6001-$6003 hold the signature DE B0 61 once the status is valid,
+// - $6000 is the status: $80 while running, $81 when the ROM asks for a reset (to be pressed
+//   at least 100 ms later), otherwise the result code (0 = passed),
+// - $6004 holds the zero terminated text also printed on screen.
+//
+// The ROMs are not shipped with the repository. Download them and run:
+//   BLARGG_ROMS_PATH=/path/to/nes-test-roms cargo test blargg
+// Each suite is looked up in the folders whose name starts with the suite name (e.g.
+// "sprite_hit_tests_2005.10.05" for sprite_hit), and every .nes file found in them is run.
+//
+// Two suites are ignored, so that setting BLARGG_ROMS_PATH in CI only runs the ones that can pass:
+// apu_test needs the APU, which is not emulated yet, and sprite_hit (the 2005 suite) predates the
+// $6000 status: its ROMs only show their result on screen, so they time out here.
+// `cargo test blargg -- --include-ignored` runs them too, reporting each failed ROM with the
+// reason.
+
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use crate::nes::Nes;
+use crate::power_on::PowerOnConfig;
+use crate::rom::Rom;
+
+const SIGNATURE: [u8; 3] = [0xDE, 0xB0, 0x61];
+const STATUS_RUNNING: u8 = 0x80;
+const STATUS_RESET: u8 = 0x81;
+// 100 ms, plus a frame of margin
+const RESET_DELAY_FRAMES: u32 = 7;
+// The longest suites (e.g. the all in one cpu_instrs.nes) take about a minute of emulated time
+const MAX_FRAMES: u32 = 60 * 120;
+// CPU/PPU alignments that behave differently (see PowerOnConfig::ppu_alignment), for the suites
+// timing the PPU to the dot
+const EFFECTIVE_PPU_ALIGNMENTS: [u8; 2] = [0, 2];
+
+#[derive(Debug, Clone, PartialEq)]
+enum BlarggResult {
+    Passed,
+    Failed { code: u8, text: String },
+    TimedOut,
+    // The ROM could not be loaded, or the emulator stopped (e.g. on a missing component)
+    Error(String),
+}
+
+fn status_text(prg_ram: &[u8]) -> String {
+    let text = &prg_ram[4..];
+    let length = text.iter().position(|&byte| byte == 0).unwrap_or(text.len());
+    String::from_utf8_lossy(&text[..length]).trim().to_string()
+}
+
+fn run_blargg_rom(rom: Rom, ppu_alignment: u8, max_frames: u32) -> BlarggResult {
+    let mut nes = match Nes::with_config(rom, PowerOnConfig { ppu_alignment, ..PowerOnConfig::default() }) {
+        Ok(nes) => nes,
+        Err(error) => return BlarggResult::Error(error),
+    };
+    let mut reset_in = None;
+    for _ in 0..max_frames {
+        if let Err(panic) = catch_unwind(AssertUnwindSafe(|| nes.run_frame())) {
+            let message = panic
+                .downcast_ref::<&str>()
+                .map(|message| message.to_string())
+                .or_else(|| panic.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| "Unknown panic".to_string());
+            return BlarggResult::Error(message);
+        }
+        if nes.cpu.halted {
+            return BlarggResult::Error(format!("CPU halted at {:04X}", nes.cpu.program_counter));
+        }
+
+        let prg_ram = nes.cpu.bus.prg_ram();
+        if prg_ram[1..4] != SIGNATURE {
+            continue;
+        }
+        match prg_ram[0] {
+            STATUS_RUNNING => {}
+            STATUS_RESET => match reset_in {
+                Some(0) => {
+                    nes.reset();
+                    reset_in = None;
+                }
+                Some(frames) => reset_in = Some(frames - 1),
+                None => reset_in = Some(RESET_DELAY_FRAMES),
+            },
+            0 => return BlarggResult::Passed,
+            code => return BlarggResult::Failed { code, text: status_text(prg_ram) },
+        }
+    }
+    BlarggResult::TimedOut
+}
+
+fn find_roms(directory: &Path, roms: &mut Vec<PathBuf>) {
+    let Ok(entries) = std::fs::read_dir(directory) else {
+        return;
+    };
+    for path in entries.filter_map(|entry| entry.ok()).map(|entry| entry.path()) {
+        if path.is_dir() {
+            find_roms(&path, roms);
+        } else if path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("nes")) {
+            roms.push(path);
+        }
+    }
+}
+
+// Runs every ROM of the suite with each of `ppu_alignments`, reporting the results per alignment.
+fn run_suite(suite: &str, ppu_alignments: &[u8]) {
+    let Ok(directory) = std::env::var("BLARGG_ROMS_PATH") else {
+        println!("BLARGG_ROMS_PATH is not set, skipping the {} test ROMs", suite);
+        return;
+    };
+
+    let mut roms = Vec::new();
+    for entry in std::fs::read_dir(&directory).unwrap_or_else(|error| panic!("Failed to read {}: {}", directory, error)) {
+        let path = entry.unwrap().path();
+        if path.is_dir() && path.file_name().is_some_and(|name| name.to_string_lossy().starts_with(suite)) {
+            find_roms(&path, &mut roms);
+        }
+    }
+    assert!(!roms.is_empty(), "No {} ROM found in {}", suite, directory);
+    roms.sort();
+
+    let mut failures = Vec::new();
+    for path in &roms {
+        for &ppu_alignment in ppu_alignments {
+            let result = match Rom::load_file(&path.to_string_lossy()) {
+                Ok(rom) => run_blargg_rom(rom, ppu_alignment, MAX_FRAMES),
+                Err(error) => BlarggResult::Error(error),
+            };
+            println!("{} (alignment {}): {:?}", path.display(), ppu_alignment, result);
+            if result != BlarggResult::Passed {
+                failures.push(format!("{} (alignment {}): {:?}", path.display(), ppu_alignment, result));
+            }
+        }
+    }
+    let runs = roms.len() * ppu_alignments.len();
+    assert!(failures.is_empty(), "{}/{} {} runs failed:\n{}", failures.len(), runs, suite, failures.join("\n"));
+}
+
+#[test]
+fn test_blargg_cpu_instrs() {
+    run_suite("cpu_instrs", &[0]);
+}
+
+#[test]
+fn test_blargg_instr_timing() {
+    run_suite("instr_timing", &[0]);
+}
+
+#[test]
+fn test_blargg_ppu_vbl_nmi() {
+    run_suite("ppu_vbl_nmi", &EFFECTIVE_PPU_ALIGNMENTS);
+}
+
+#[test]
+#[ignore = "needs the APU"]
+fn test_blargg_apu_test() {
+    run_suite("apu_test", &[0]);
+}
+
+#[test]
+#[ignore = "reports its result on screen only, not in $6000"]
+fn test_blargg_sprite_hit() {
+    run_suite("sprite_hit", &[0]);
+}
+
+// A ROM following the protocol: "ok" with the result code `code`, after asking for a reset once
+fn protocol_rom(code: u8) -> Rom {
+    let mut program = vec![
+        0xA9, STATUS_RUNNING, 0x8D, 0x00, 0x60, // LDA #$80; STA $6000
+    ];
+    for (offset, byte) in SIGNATURE.iter().chain(b"ok\0").enumerate() {
+        program.extend_from_slice(&[0xA9, *byte, 0x8D, 0x01 + offset as u8, 0x60]); // LDA #byte; STA $6001+offset
+    }
+    program.extend_from_slice(&[
+        0xAD, 0x10, 0x60,       // LDA $6010 (0 on the first boot)
+        0xD0, 0x0B,             // BNE done
+        0xEE, 0x10, 0x60,       // INC $6010
+        0xA9, STATUS_RESET,     // LDA #$81
+        0x8D, 0x00, 0x60,       // STA $6000
+        0x4C, 0x00, 0x00,       // JMP (patched below: the reset loop)
+        0xA9, code,             // done: LDA #code
+        0x8D, 0x00, 0x60,       // STA $6000
+        0x4C, 0x00, 0x00,       // JMP (patched below: the final loop)
+    ]);
+    let reset_loop = 0x8000 + program.len() as u16 - 11;
+    let final_loop = 0x8000 + program.len() as u16 - 3;
+    let length = program.len();
+    program[length - 10..length - 8].copy_from_slice(&reset_loop.to_le_bytes());
+    program[length - 2..].copy_from_slice(&final_loop.to_le_bytes());
+
+    let mut rom = Rom::test_rom();
+    rom.prg_rom[..program.len()].copy_from_slice(&program);
+    let prg_length = rom.prg_rom.len();
+    rom.prg_rom[prg_length - 4] = 0x00;
+    rom.prg_rom[prg_length - 3] = 0x80;
+    rom
+}
+
+#[test]
+fn test_protocol_results() {
+    assert_eq!(run_blargg_rom(protocol_rom(0), 0, 60), BlarggResult::Passed);
+    assert_eq!(run_blargg_rom(protocol_rom(3), 2, 60), BlarggResult::Failed { code: 3, text: "ok".to_string() });
+    // The reset is only pressed after 100 ms
+    assert_eq!(run_blargg_rom(protocol_rom(0), 0, 5), BlarggResult::TimedOut);
+    // The test cartridge is full of NOPs and never reports anything
+    assert_eq!(run_blargg_rom(Rom::test_rom(), 0, 10), BlarggResult::TimedOut);
+}
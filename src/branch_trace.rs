@@ -0,0 +1,166 @@
+use std::collections::VecDeque;
+use crate::cpu6502::CPU;
+
+// Branch trace for the debugger: the last control flow changes (taken branches, jumps, calls,
+// returns, BRK and interrupts) with where they came from and went to. When execution ends up
+// somewhere unexpected (e.g. running data after a bad jump table entry), the last entries show
+// how it got there, which a call stack cannot since the faulty jump did not push anything.
+// It is always recorded, and only keeps the last `capacity` changes.
+
+pub const DEFAULT_CAPACITY: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ControlFlowKind {
+    // Taken conditional branch
+    Branch,
+    Jump,
+    Call,
+    Return,
+    ReturnFromInterrupt,
+    Brk,
+    Nmi,
+    Irq,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ControlFlowChange {
+    pub kind: ControlFlowKind,
+    // Address of the instruction (the interrupted one for NMI and IRQ)
+    pub from: u16,
+    pub to: u16,
+    // Cycle counter at the start of the instruction or interrupt sequence
+    pub cycle: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct BranchTrace {
+    changes: VecDeque<ControlFlowChange>,
+    capacity: usize,
+}
+
+impl Default for BranchTrace {
+    fn default() -> Self {
+        Self::new(DEFAULT_CAPACITY)
+    }
+}
+
+impl BranchTrace {
+    pub fn new(capacity: usize) -> Self {
+        Self { changes: VecDeque::with_capacity(capacity), capacity }
+    }
+
+    pub fn record(&mut self, change: ControlFlowChange) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.changes.len() == self.capacity {
+            self.changes.pop_front();
+        }
+        self.changes.push_back(change);
+    }
+
+    // Oldest first
+    pub fn changes(&self) -> impl DoubleEndedIterator<Item = &ControlFlowChange> {
+        self.changes.iter()
+    }
+
+    pub fn last(&self) -> Option<&ControlFlowChange> {
+        self.changes.back()
+    }
+
+    pub fn len(&self) -> usize {
+        self.changes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.changes.clear();
+    }
+
+    // Keeps the most recent changes that fit
+    pub fn set_capacity(&mut self, capacity: usize) {
+        while self.changes.len() > capacity {
+            self.changes.pop_front();
+        }
+        self.capacity = capacity;
+    }
+}
+
+impl CPU {
+    // Called after an instruction that changed the program counter itself, which is now its target.
+    pub fn track_branch(&mut self, name: &str, pc: u16, cycle: u64) {
+        let kind = match name {
+            "JMP" => ControlFlowKind::Jump,
+            "JSR" => ControlFlowKind::Call,
+            "RTS" => ControlFlowKind::Return,
+            "RTI" => ControlFlowKind::ReturnFromInterrupt,
+            "BRK" => ControlFlowKind::Brk,
+            _ => ControlFlowKind::Branch,
+        };
+        self.branch_trace.record(ControlFlowChange { kind, from: pc, to: self.program_counter, cycle });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::branch_trace::{BranchTrace, ControlFlowChange, ControlFlowKind};
+    use crate::bus::Bus;
+    use crate::cpu6502::new_cpu;
+
+    #[test]
+    fn test_ring_buffer() {
+        let change = |from| ControlFlowChange { kind: ControlFlowKind::Jump, from, to: 0, cycle: 0 };
+        let mut trace = BranchTrace::new(2);
+        trace.record(change(1));
+        trace.record(change(2));
+        trace.record(change(3));
+        assert_eq!(trace.changes().map(|change| change.from).collect::<Vec<u16>>(), vec![2, 3]);
+
+        trace.set_capacity(1);
+        assert_eq!(trace.last(), Some(&change(3)));
+        assert_eq!(trace.len(), 1);
+        trace.set_capacity(0);
+        trace.record(change(4));
+        assert!(trace.is_empty());
+    }
+
+    #[test]
+    fn test_recorded_by_the_cpu() {
+        // 0600: LDX #$01; BNE +0 (taken); BEQ +0 (not taken); JSR $0610; JMP $0600
+        // 0610: RTS
+        let mut cpu = new_cpu(Bus::new_flat());
+        let program: [(u16, &[u8]); 2] = [
+            (0x0600, &[0xA2, 0x01, 0xD0, 0x00, 0xF0, 0x00, 0x20, 0x10, 0x06, 0x4C, 0x00, 0x06]),
+            (0x0610, &[0x60]),
+        ];
+        for (address, bytes) in program {
+            for (offset, byte) in bytes.iter().enumerate() {
+                cpu.bus.poke_u8(address + offset as u16, *byte).unwrap();
+            }
+        }
+        cpu.program_counter = 0x0600;
+        for _ in 0..6 {
+            cpu.step();
+        }
+
+        let changes: Vec<(ControlFlowKind, u16, u16)> = cpu.branch_trace.changes().map(|change| (change.kind, change.from, change.to)).collect();
+        assert_eq!(changes, vec![
+            (ControlFlowKind::Branch, 0x0602, 0x0604),
+            (ControlFlowKind::Call, 0x0606, 0x0610),
+            (ControlFlowKind::Return, 0x0610, 0x0609),
+            (ControlFlowKind::Jump, 0x0609, 0x0600),
+        ]);
+        assert_eq!(cpu.branch_trace.changes().next().unwrap().cycle, 2);
+
+        // Interrupts are recorded with the interrupted instruction
+        cpu.bus.poke_u8(0xFFFA, 0x00).unwrap();
+        cpu.bus.poke_u8(0xFFFB, 0x80).unwrap();
+        cpu.request_nmi();
+        cpu.step();
+        let last = *cpu.branch_trace.last().unwrap();
+        assert_eq!((last.kind, last.from, last.to), (ControlFlowKind::Nmi, 0x0602, 0x8000));
+    }
+}
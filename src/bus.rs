@@ -1,4 +1,15 @@
+use std::fmt;
+use std::cell::{Cell, Ref, RefCell};
+use crate::access_log::{AccessKind, AccessLog};
+use crate::apu_status::ApuStatus;
+use crate::joypad::Joypads;
+use crate::mapper::nrom::Nrom;
+use crate::mapper::registry::MapperRegistry;
+use crate::mapper::Mapper;
+use crate::ppu::{Cartridge, Ppu};
 use crate::rom::Rom;
+use crate::family_keyboard::{FamilyBasicKeyboard, FAMILY_BASIC_KEYBOARD_DEVICE};
+use crate::vs_system::VsSystem;
 
 // The 6502 has a 16 bit address bus, which means it can address up to 64KB of memory.
 // This memory is typically divided into several regions, including RAM, ROM, and memory-mapped I/O.
@@ -11,21 +22,498 @@ use crate::rom::Rom;
 // 0x8000 - 0xFFFF: PRG ROM
 // Total memory size: 64KB; 0xFFFF + 1 = 65536 bytes = 0x10000 to include all addresses.
 
+// Areas of the memory map above, used to draw the sections of debugger views.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum MemoryArea {
+    Ram,
+    PpuRegisters,
+    ApuIoRegisters,
+    ExpansionRom,
+    SaveRam,
+    PrgRom,
+}
+
+impl MemoryArea {
+    pub const ALL: [MemoryArea; 6] = [
+        MemoryArea::Ram,
+        MemoryArea::PpuRegisters,
+        MemoryArea::ApuIoRegisters,
+        MemoryArea::ExpansionRom,
+        MemoryArea::SaveRam,
+        MemoryArea::PrgRom,
+    ];
+
+    // Returns the area an address belongs to.
+    pub fn of(addr: u16) -> MemoryArea {
+        match addr {
+            0x0000..=0x1FFF => MemoryArea::Ram,
+            0x2000..=0x3FFF => MemoryArea::PpuRegisters,
+            0x4000..=0x401F => MemoryArea::ApuIoRegisters,
+            0x4020..=0x5FFF => MemoryArea::ExpansionRom,
+            0x6000..=0x7FFF => MemoryArea::SaveRam,
+            0x8000..=0xFFFF => MemoryArea::PrgRom,
+        }
+    }
+
+    // First and last address of the area.
+    pub fn range(&self) -> (u16, u16) {
+        match self {
+            MemoryArea::Ram => (0x0000, 0x1FFF),
+            MemoryArea::PpuRegisters => (0x2000, 0x3FFF),
+            MemoryArea::ApuIoRegisters => (0x4000, 0x401F),
+            MemoryArea::ExpansionRom => (0x4020, 0x5FFF),
+            MemoryArea::SaveRam => (0x6000, 0x7FFF),
+            MemoryArea::PrgRom => (0x8000, 0xFFFF),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            MemoryArea::Ram => "RAM",
+            MemoryArea::PpuRegisters => "PPU Registers",
+            MemoryArea::ApuIoRegisters => "APU and I/O Registers",
+            MemoryArea::ExpansionRom => "Expansion ROM",
+            MemoryArea::SaveRam => "Save RAM",
+            MemoryArea::PrgRom => "PRG ROM",
+        }
+    }
+}
+
+// What an address is wired to at the moment, with the mirrors and the mapper banking resolved
+// (see `Bus::region_of`). Used by debugger views and the code/data logger to annotate addresses.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum MemoryRegion {
+    // Index in the 2KB of internal RAM
+    Ram { index: usize },
+    // 0 (PPUCTRL) to 7 (PPUDATA)
+    PpuRegister { register: u8 },
+    // 0x4000 - 0x4017
+    ApuIoRegister { addr: u16 },
+    // `bank` is the 8KB bank of PRG ROM, `offset` the index of the byte in the whole PRG ROM
+    PrgRom { bank: usize, offset: usize },
+    // Index in the 8KB of PRG RAM
+    PrgRam { offset: usize },
+    // Register of the cartridge hardware, in the expansion area or in PRG RAM space (NINA-001)
+    MapperRegister { addr: u16 },
+    // Nothing drives the data bus
+    OpenBus,
+}
+
+impl fmt::Display for MemoryRegion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MemoryRegion::Ram { index } => write!(f, "RAM ${:04X}", index),
+            MemoryRegion::PpuRegister { register } => write!(f, "PPU register {}", register),
+            MemoryRegion::ApuIoRegister { addr } => write!(f, "APU/IO register ${:04X}", addr),
+            MemoryRegion::PrgRom { bank, offset } => write!(f, "PRG ROM bank {} (offset ${:05X})", bank, offset),
+            MemoryRegion::PrgRam { offset } => write!(f, "PRG RAM ${:04X}", offset),
+            MemoryRegion::MapperRegister { addr } => write!(f, "Mapper register ${:04X}", addr),
+            MemoryRegion::OpenBus => write!(f, "Open bus"),
+        }
+    }
+}
+
+// Memory storing the bytes of an address range, see `Bus::memory_run`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Backing {
+    Flat,
+    InternalRam,
+    PrgRam,
+    PrgRom,
+    // Registers and unmapped regions, which are not backed by memory
+    None,
+}
+
+// Bits of the controller port reads that no controller drives
+const CONTROLLER_OPEN_BUS_BITS: u8 = 0b1110_0000;
+
+// Value of the bits no device drives during a read ("open bus"): the data bus keeps the last value
+// put on it, which for the absolute addressing used to access registers is the high byte of the
+// address, fetched just before.
+fn open_bus(addr: u16) -> u8 {
+    (addr >> 8) as u8
+}
+
 #[derive(Debug)]
-pub(crate) struct Bus {
+pub struct Bus {
     internal_ram: [u8; 0x0800], // 2KB internal RAM (0x0000 - 0x07FF)
     rom: Rom,
+    // Bank switching hardware of the cartridge
+    mapper: Box<dyn Mapper>,
+    // When present, the whole 64KB address space is plain RAM and the memory map above is bypassed.
+    // This is used to run CPU test suites that expect a flat memory.
+    flat_memory: Option<Vec<u8>>,
+    joypads: Joypads,
+    // APU status register (0x4015)
+    apu_status: ApuStatus,
+    // 8KB PRG RAM (0x6000 - 0x7FFF), battery backed on some cartridges
+    prg_ram: [u8; 0x2000],
+    // Set when PRG RAM is written, so battery saves are only flushed when needed
+    prg_ram_dirty: bool,
+    // Coin slots and DIP switches of VS System arcade games
+    vs_system: Option<VsSystem>,
+    // Device plugged into the Famicom expansion port
+    family_keyboard: Option<FamilyBasicKeyboard>,
+    // Debugger log of the accesses to watched addresses. Reads only borrow the bus, hence the RefCell.
+    access_log: Option<RefCell<AccessLog>>,
+    // The PPU is caught up and accessed by reads, which only borrow the bus
+    ppu: RefCell<Ppu>,
+    // CPU cycle of the next access: every cycle is an access, so it is set at the start of each
+    // instruction and counts the accesses from there
+    access_cycle: Cell<u64>,
 }
 
 impl Bus {
-    pub(crate) fn new(rom: Rom) -> Self {
+    pub fn new(rom: Rom) -> Self {
+        Self::with_mappers(rom, &MapperRegistry::builtin())
+    }
+
+    // Creates the mapper of `rom` with `mappers` instead of the built-in ones.
+    pub fn with_mappers(rom: Rom, mappers: &MapperRegistry) -> Self {
+        // Unsupported mappers are rejected by Rom::check_validity; without it, the ROM is read as NROM
+        let mapper = mappers.create(&rom).unwrap_or_else(|_| Box::new(Nrom::new(&rom)));
+        let vs_system = rom.header.is_vs_unisystem().then(|| VsSystem::new(&rom));
+        let family_keyboard = (rom.header.expansion_device() == FAMILY_BASIC_KEYBOARD_DEVICE).then(FamilyBasicKeyboard::new);
+        let mut ppu = Ppu::new(rom.chr_rom.is_empty());
+        if let Some(vs_system) = &vs_system {
+            ppu.set_palette(vs_system.palette());
+        }
         Self {
             internal_ram: [0; 0x0800],
-            rom
+            rom,
+            mapper,
+            flat_memory: None,
+            joypads: Joypads::new(),
+            apu_status: ApuStatus::new(),
+            prg_ram: [0; 0x2000],
+            prg_ram_dirty: false,
+            vs_system,
+            family_keyboard,
+            access_log: None,
+            ppu: RefCell::new(ppu),
+            access_cycle: Cell::new(0),
         }
     }
 
-    pub fn read_u8(&self, mut addr: u16) -> u8 {
+    // Creates a bus where all 64KB are readable and writable RAM, with no mirroring nor I/O.
+    pub fn new_flat() -> Self {
+        let rom = Rom::test_rom();
+        Self {
+            internal_ram: [0; 0x0800],
+            mapper: Box::new(Nrom::new(&rom)),
+            rom,
+            flat_memory: Some(vec![0; 0x10000]),
+            joypads: Joypads::new(),
+            apu_status: ApuStatus::new(),
+            prg_ram: [0; 0x2000],
+            prg_ram_dirty: false,
+            vs_system: None,
+            family_keyboard: None,
+            access_log: None,
+            ppu: RefCell::new(Ppu::new(false)),
+            access_cycle: Cell::new(0),
+        }
+    }
+
+    // Direct access to the 2KB internal RAM, used by savestates.
+    pub fn internal_ram(&self) -> &[u8; 0x0800] {
+        &self.internal_ram
+    }
+
+    pub fn internal_ram_mut(&mut self) -> &mut [u8; 0x0800] {
+        &mut self.internal_ram
+    }
+
+    pub fn prg_ram(&self) -> &[u8; 0x2000] {
+        &self.prg_ram
+    }
+
+    pub fn prg_ram_mut(&mut self) -> &mut [u8; 0x2000] {
+        &mut self.prg_ram
+    }
+
+    pub fn is_prg_ram_dirty(&self) -> bool {
+        self.prg_ram_dirty
+    }
+
+    pub fn clear_prg_ram_dirty(&mut self) {
+        self.prg_ram_dirty = false;
+    }
+
+    pub fn mark_prg_ram_dirty(&mut self) {
+        self.prg_ram_dirty = true;
+    }
+
+    pub fn rom(&self) -> &Rom {
+        &self.rom
+    }
+
+    pub fn vs_system(&self) -> Option<&VsSystem> {
+        self.vs_system.as_ref()
+    }
+
+    pub fn vs_system_mut(&mut self) -> Option<&mut VsSystem> {
+        self.vs_system.as_mut()
+    }
+
+    pub fn family_keyboard(&self) -> Option<&FamilyBasicKeyboard> {
+        self.family_keyboard.as_ref()
+    }
+
+    pub fn family_keyboard_mut(&mut self) -> Option<&mut FamilyBasicKeyboard> {
+        self.family_keyboard.as_mut()
+    }
+
+    // Plugs (or unplugs) the keyboard in the expansion port
+    pub fn set_family_keyboard(&mut self, keyboard: Option<FamilyBasicKeyboard>) {
+        self.family_keyboard = keyboard;
+    }
+
+    // False for the flat memory bus, which has no I/O registers.
+    pub fn has_ppu(&self) -> bool {
+        self.flat_memory.is_none()
+    }
+
+    pub fn ppu(&self) -> Ref<'_, Ppu> {
+        self.ppu.borrow()
+    }
+
+    pub fn ppu_mut(&mut self) -> &mut Ppu {
+        self.ppu.get_mut()
+    }
+
+    // Runs the PPU `dots` more dots, to catch it up from a read.
+    pub fn run_ppu(&self, dots: u64) {
+        self.ppu.borrow_mut().run(dots, self.cartridge());
+    }
+
+    // Both pattern tables in palette `palette_index`, see Ppu::render_pattern_tables.
+    pub fn render_pattern_tables(&self, palette_index: usize) -> Vec<u8> {
+        self.ppu.borrow().render_pattern_tables(self.cartridge(), palette_index)
+    }
+
+    // What the cartridge connects to the PPU bus.
+    fn cartridge(&self) -> Cartridge<'_> {
+        Cartridge { mapper: self.mapper.as_ref(), chr_rom: &self.rom.chr_rom }
+    }
+
+    // Powers the PPU on again, keeping its accuracy level and palette. Its open bus latch decays
+    // after `open_bus_decay` CPU cycles (None disables the decay).
+    pub fn power_on_ppu(&mut self, open_bus_decay: Option<u64>) {
+        let mut ppu = Ppu::new(self.rom.chr_rom.is_empty());
+        ppu.runner.accuracy = self.ppu.get_mut().runner.accuracy;
+        ppu.set_palette(self.ppu.get_mut().output.palette().clone());
+        ppu.open_bus.set_decay(open_bus_decay);
+        *self.ppu.get_mut() = ppu;
+    }
+
+    pub fn mapper(&self) -> &dyn Mapper {
+        self.mapper.as_ref()
+    }
+
+    pub fn mapper_mut(&mut self) -> &mut dyn Mapper {
+        self.mapper.as_mut()
+    }
+
+    // What `addr` is wired to, with the current banks of the mapper. Has no side effect.
+    pub fn region_of(&self, addr: u16) -> MemoryRegion {
+        if self.flat_memory.is_some() {
+            return MemoryRegion::Ram { index: addr as usize };
+        }
+        match addr {
+            0x0000..=0x1FFF => MemoryRegion::Ram { index: (addr & 0x07FF) as usize },
+            0x2000..=0x3FFF => MemoryRegion::PpuRegister { register: (addr & 0x0007) as u8 },
+            0x4000..=0x4017 => MemoryRegion::ApuIoRegister { addr },
+            // CPU test mode registers, disabled on retail consoles
+            0x4018..=0x401F => MemoryRegion::OpenBus,
+            0x4020..=0x5FFF => match self.mapper.peek_expansion(addr) {
+                Some(_) => MemoryRegion::MapperRegister { addr },
+                None => MemoryRegion::OpenBus,
+            },
+            0x6000..=0x7FFF if self.mapper.has_prg_ram_register(addr) => MemoryRegion::MapperRegister { addr },
+            0x6000..=0x7FFF => MemoryRegion::PrgRam { offset: (addr - 0x6000) as usize },
+            0x8000..=0xFFFF => {
+                let offset = self.prg_rom_index(addr);
+                MemoryRegion::PrgRom { bank: offset / 0x2000, offset }
+            }
+        }
+    }
+
+    // Replaces the cartridge hardware, for boards that are not identified by the header alone.
+    pub fn set_mapper(&mut self, mapper: Box<dyn Mapper>) {
+        self.mapper = mapper;
+    }
+
+    // Starts logging the CPU accesses to the addresses watched by `log`.
+    pub fn enable_access_log(&mut self, log: AccessLog) {
+        self.access_log = Some(RefCell::new(log));
+    }
+
+    pub fn disable_access_log(&mut self) -> Option<AccessLog> {
+        self.access_log.take().map(RefCell::into_inner)
+    }
+
+    pub fn access_log(&self) -> Option<Ref<'_, AccessLog>> {
+        self.access_log.as_ref().map(RefCell::borrow)
+    }
+
+    // Called by the CPU before each instruction, so logged accesses know which instruction made them
+    // and the PPU registers know the cycle they are accessed on.
+    pub fn set_access_context(&self, cycle: u64, pc: u16) {
+        self.access_cycle.set(cycle);
+        if let Some(log) = &self.access_log {
+            log.borrow_mut().set_context(cycle, pc);
+        }
+    }
+
+    pub fn access_cycle(&self) -> u64 {
+        self.access_cycle.get()
+    }
+
+    // Cycles without any access, when a DMA halts the CPU.
+    pub fn skip_cycles(&self, cycles: u64) {
+        self.access_cycle.set(self.access_cycle.get() + cycles);
+    }
+
+    fn log_access(&self, addr: u16, value: u8, kind: AccessKind) {
+        if let Some(log) = &self.access_log {
+            log.borrow_mut().record(addr, value, kind);
+        }
+    }
+
+    pub fn apu_status(&self) -> &ApuStatus {
+        &self.apu_status
+    }
+
+    pub fn apu_status_mut(&mut self) -> &mut ApuStatus {
+        &mut self.apu_status
+    }
+
+    pub fn joypads(&self) -> &Joypads {
+        &self.joypads
+    }
+
+    pub fn joypads_mut(&mut self) -> &mut Joypads {
+        &mut self.joypads
+    }
+
+    // Converts a CPU address in cartridge space (0x8000 - 0xFFFF) into an index in PRG ROM.
+    fn prg_rom_index(&self, addr: u16) -> usize {
+        self.mapper.prg_rom_index(addr)
+    }
+
+    // Where `addr` is stored: the memory, the index of the byte in it, and how many consecutive
+    // addresses starting at `addr` are stored contiguously (up to the next mirror or region boundary).
+    fn memory_run(&self, addr: u16) -> (Backing, usize, usize) {
+        if self.flat_memory.is_some() {
+            return (Backing::Flat, addr as usize, 0x10000 - addr as usize);
+        }
+
+        let region = MemoryArea::of(addr);
+        let region_left = region.range().1 as usize + 1 - addr as usize;
+        match region {
+            MemoryArea::Ram => {
+                let index = (addr & 0x07FF) as usize;
+                (Backing::InternalRam, index, (0x0800 - index).min(region_left))
+            }
+            MemoryArea::SaveRam => (Backing::PrgRam, (addr - 0x6000) as usize, region_left),
+            MemoryArea::PrgRom => {
+                // Mappers switch banks of 8KB at the smallest, so a run stops at the next 8KB boundary
+                let index = self.prg_rom_index(addr);
+                let bank_left = 0x2000 - (addr as usize & 0x1FFF);
+                (Backing::PrgRom, index, (self.rom.prg_rom.len() - index).min(bank_left))
+            }
+            _ => (Backing::None, 0, region_left),
+        }
+    }
+
+    fn backing(&self, backing: Backing) -> Option<&[u8]> {
+        match backing {
+            Backing::Flat => self.flat_memory.as_deref(),
+            Backing::InternalRam => Some(&self.internal_ram),
+            Backing::PrgRam => Some(&self.prg_ram),
+            Backing::PrgRom => Some(&self.rom.prg_rom),
+            Backing::None => None,
+        }
+    }
+
+    fn backing_mut(&mut self, backing: Backing) -> Option<&mut [u8]> {
+        match backing {
+            Backing::Flat => self.flat_memory.as_deref_mut(),
+            Backing::InternalRam => Some(&mut self.internal_ram),
+            Backing::PrgRam => Some(&mut self.prg_ram),
+            Backing::PrgRom => Some(&mut self.rom.prg_rom),
+            Backing::None => None,
+        }
+    }
+
+    // Peeks `buffer.len()` bytes starting at `addr`, the address wrapping around after 0xFFFF.
+    // Same result as calling `peek_u8` for each address, but memory is copied a whole run at a time
+    // (e.g. up to the next RAM mirror) instead of going through the memory map for every byte.
+    pub fn read_slice(&self, addr: u16, buffer: &mut [u8]) {
+        let mut address = addr;
+        let mut remaining = buffer;
+        while !remaining.is_empty() {
+            let (backing, index, run) = self.memory_run(address);
+            let (chunk, rest) = remaining.split_at_mut(run.min(remaining.len()));
+            match self.backing(backing) {
+                Some(memory) => chunk.copy_from_slice(&memory[index..index + chunk.len()]),
+                // Registers, expansion area and open bus
+                None => {
+                    for (offset, byte) in chunk.iter_mut().enumerate() {
+                        *byte = self.peek_u8(address.wrapping_add(offset as u16));
+                    }
+                }
+            }
+            address = address.wrapping_add(chunk.len() as u16);
+            remaining = rest;
+        }
+    }
+
+    // Pokes `data` starting at `addr`, like `poke_u8` for each address.
+    // Nothing is written if the range covers a region that cannot be edited.
+    pub fn write_slice(&mut self, addr: u16, data: &[u8]) -> Result<(), String> {
+        let mut address = addr;
+        let mut offset = 0;
+        while offset < data.len() {
+            let (backing, _, run) = self.memory_run(address);
+            if backing == Backing::None {
+                let region = MemoryArea::of(address);
+                return Err(format!("Address {:04X} ({}) cannot be edited", address, region.name()));
+            }
+            offset += run;
+            address = address.wrapping_add(run as u16);
+        }
+
+        let mut address = addr;
+        let mut remaining = data;
+        while !remaining.is_empty() {
+            let (backing, index, run) = self.memory_run(address);
+            let (chunk, rest) = remaining.split_at(run.min(remaining.len()));
+            if let Some(memory) = self.backing_mut(backing) {
+                memory[index..index + chunk.len()].copy_from_slice(chunk);
+            }
+            if backing == Backing::PrgRam {
+                self.prg_ram_dirty = true;
+            }
+            address = address.wrapping_add(chunk.len() as u16);
+            remaining = rest;
+        }
+        Ok(())
+    }
+
+    pub fn read_u8(&self, addr: u16) -> u8 {
+        let value = self.read_memory(addr);
+        self.log_access(addr, value, AccessKind::Read);
+        self.skip_cycles(1);
+        value
+    }
+
+    fn read_memory(&self, addr: u16) -> u8 {
+        if let Some(memory) = &self.flat_memory {
+            return memory[addr as usize];
+        }
+
         match addr {
             // RAM (0x0000 - 0x1FFF)
             // The 2KB RAM is mirrored 4 times. Reading 0x0000 is the same as 0x0800.
@@ -36,34 +524,105 @@ impl Bus {
 
             // PPU Registers (0x2000 - 0x3FFF)
             0x2000..=0x3FFF => {
-                let _mirror_down_addr = addr & 0b0010_0000_0000_0111;
-                todo!("PPU is not supported yet")
+                self.ppu.borrow_mut().read_register(addr & 0x0007, self.access_cycle.get(), self.cartridge())
             }
 
+            // APU status. Bit 5 is not driven and reads as open bus.
+            0x4015 => self.apu_status.read(open_bus(addr)),
+
+            // Controller ports, shared with the VS System inputs and the expansion port.
+            // Bits 5-7 are not connected on the console, so they read as open bus (0x40), which
+            // some games (e.g. Paperboy) expect when comparing the whole byte.
+            0x4016 | 0x4017 => self.controller_port(addr, self.joypads.read((addr - 0x4016) as usize)),
+
+            // Expansion area (0x4020 - 0x5FFF): mapper registers on some boards, open bus without them.
+            0x4020..=0x5FFF => self.mapper.read_expansion(addr).unwrap_or(open_bus(addr)),
+
+            // PRG RAM (0x6000 - 0x7FFF)
+            0x6000..=0x7FFF => self.prg_ram[(addr - 0x6000) as usize],
+
             // Cartridge Space (0x8000 - 0xFFFF)
             0x8000..=0xFFFF => {
-                // Shift address down so 0x8000 becomes 0x0000
-                addr -= 0x8000;
-
-                // Mapper 0 (NROM) Logic:
-                // If PRG ROM is 16KB (len = 16384), it is mirrored.
-                // The CPU expects code at 0xC000, but we only have data up to 0x4000.
-                // So we mirror 0xC000-0xFFFF back to 0x8000-0xBFFF.
-                if self.rom.prg_rom.len() == 16384 && addr >= 16384 {
-                    addr = addr % 16384;
-                }
-                self.rom.prg_rom[addr as usize]
+                self.rom.prg_rom[self.prg_rom_index(addr)]
+            }
+
+            // APU registers are write-only, the test mode registers are disabled: open bus
+            _ => open_bus(addr),
+        }
+    }
+
+    // Value of a read of 0x4016 or 0x4017 returning `joypad_bit` from the controller: the VS System
+    // and Family BASIC keyboard bits are added, the undriven bits read as open bus.
+    fn controller_port(&self, addr: u16, joypad_bit: u8) -> u8 {
+        let port = (addr - 0x4016) as usize;
+        let keyboard = match (&self.family_keyboard, port) {
+            (Some(keyboard), 1) => keyboard.read(),
+            _ => 0,
+        };
+        let vs_system = self.vs_system.as_ref().map_or(0, |vs_system| vs_system.read(port));
+        let vs_system_bits = self.vs_system.as_ref().map_or(0, |_| VsSystem::driven_bits(port));
+        let floating_bits = CONTROLLER_OPEN_BUS_BITS & !vs_system_bits;
+        joypad_bit | vs_system | keyboard | (open_bus(addr) & floating_bits)
+    }
+
+    // Reads memory without any side effect, for tracing and debugger views.
+    // Registers return the value a read would, without changing the hardware state.
+    pub fn peek_u8(&self, addr: u16) -> u8 {
+        if let Some(memory) = &self.flat_memory {
+            return memory[addr as usize];
+        }
+
+        match addr {
+            0x0000..=0x1FFF | 0x6000..=0xFFFF => self.read_memory(addr),
+            0x2000..=0x3FFF => {
+                self.ppu.borrow().peek_register(addr & 0x0007, self.access_cycle.get(), self.cartridge())
             }
+            0x4015 => self.apu_status.peek(open_bus(addr)),
+            0x4016 | 0x4017 => self.controller_port(addr, self.joypads.peek((addr - 0x4016) as usize)),
+            0x4020..=0x5FFF => self.mapper.peek_expansion(addr).unwrap_or(open_bus(addr)),
+            0x4000..=0x4014 | 0x4018..=0x401F => open_bus(addr),
+        }
+    }
 
-            _ => {
-                println!("Memory access at {} not handled", addr);
-                // Handle other address ranges (e.g., APU, Cartridge)
-                0
+    // Writes memory for debugger and memory editor views.
+    // Unlike write_u8, PRG ROM can be patched. Regions that are not emulated yet cannot be edited.
+    pub fn poke_u8(&mut self, addr: u16, data: u8) -> Result<(), String> {
+        if let Some(memory) = &mut self.flat_memory {
+            memory[addr as usize] = data;
+            return Ok(());
+        }
+
+        match MemoryArea::of(addr) {
+            MemoryArea::Ram => {
+                self.internal_ram[(addr & 0x07FF) as usize] = data;
+                Ok(())
+            }
+            MemoryArea::SaveRam => {
+                self.prg_ram[(addr - 0x6000) as usize] = data;
+                self.prg_ram_dirty = true;
+                Ok(())
             }
+            MemoryArea::PrgRom => {
+                let index = self.prg_rom_index(addr);
+                self.rom.prg_rom[index] = data;
+                Ok(())
+            }
+            region => Err(format!("Address {:04X} ({}) cannot be edited", addr, region.name())),
         }
     }
 
     pub fn write_u8(&mut self, addr: u16, data: u8) {
+        self.log_access(addr, data, AccessKind::Write);
+        self.write_memory(addr, data);
+        self.skip_cycles(1);
+    }
+
+    fn write_memory(&mut self, addr: u16, data: u8) {
+        if let Some(memory) = &mut self.flat_memory {
+            memory[addr as usize] = data;
+            return;
+        }
+
         match addr {
             // RAM
             0x0000..=0x1FFF => {
@@ -73,19 +632,55 @@ impl Bus {
 
             // PPU
             0x2000..=0x3FFF => {
-                todo!("PPU is not supported yet")
+                let cartridge = Cartridge { mapper: self.mapper.as_ref(), chr_rom: &self.rom.chr_rom };
+                self.ppu.get_mut().write_register(addr & 0x0007, data, self.access_cycle.get(), cartridge);
             }
 
-            // Cartridge Space
-            0x8000..=0xFFFF => {
-                // PRG ROM is not writable. Ignore writes or log a warning.
-                println!("Attempted write to PRG ROM at address {:04X}", addr);
+            // OAM DMA, done by the CPU (see CPU::oam_dma)
+            0x4014 => {}
+
+            // APU channel enables, also acknowledges the DMC IRQ
+            0x4015 => self.apu_status.write(data),
+
+            // Controller strobe. VS System boards also select their CHR bank with bit 2,
+            // and the expansion port receives bits 0-2.
+            0x4016 => {
+                self.joypads.write(data);
+                self.mapper.write(addr, data);
+                if let Some(keyboard) = &mut self.family_keyboard {
+                    keyboard.write(data);
+                }
             }
 
-            _ => {
-                println!("Memory access at {} not handled", addr);
-                // Handle other address ranges (e.g., APU, Cartridge)
+            // Expansion area, 0x4020 is also the VS System coin counter
+            0x4020..=0x5FFF => {
+                if let (0x4020, Some(vs_system)) = (addr, &mut self.vs_system) {
+                    vs_system.write_coin_counter(data);
+                }
+                self.mapper.write(addr, data);
             }
+
+            // PRG RAM
+            // Some mappers (NINA-001) also have their registers there
+            0x6000..=0x7FFF => {
+                self.prg_ram[(addr - 0x6000) as usize] = data;
+                self.prg_ram_dirty = true;
+                self.mapper.write(addr, data);
+            }
+
+            // Cartridge Space: PRG ROM is not writable, writes go to the mapper registers
+            0x8000..=0xFFFF => {
+                let data = if self.mapper.bus_conflicts() {
+                    data & self.rom.prg_rom[self.prg_rom_index(addr)]
+                } else {
+                    data
+                };
+                self.mapper.write(addr, data);
+            }
+
+            // APU channel and frame counter registers (the APU is not emulated yet) and the
+            // disabled test mode registers
+            _ => {}
         }
     }
 }
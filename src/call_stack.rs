@@ -0,0 +1,222 @@
+use crate::cpu6502::CPU;
+
+// Shadow call stack for the debugger: JSR, BRK and interrupts push a frame, RTS and RTI pop it.
+// Games do not always use the stack the way JSR/RTS intend: jump tables push an address and "return"
+// to it, some routines drop their return address (PLA PLA) to return to their caller's caller.
+// Frames are therefore tied to the stack pointer: a frame whose return address was popped or
+// overwritten is discarded, and returns that do not match the top frame are counted as mismatches.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CallKind {
+    Subroutine,
+    Brk,
+    Nmi,
+    Irq,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CallFrame {
+    pub kind: CallKind,
+    // Address of the JSR or BRK instruction, or the interrupted instruction
+    pub caller: u16,
+    // Subroutine or interrupt handler
+    pub target: u16,
+    // Where the matching RTS or RTI is expected to go
+    pub return_address: u16,
+    // Stack pointer once the return address (and status) were pushed
+    pub stack_pointer: u8,
+    pub cycle: u64,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CallStack {
+    frames: Vec<CallFrame>,
+    mismatches: u64,
+}
+
+impl CallStack {
+    // Innermost call last
+    pub fn frames(&self) -> &[CallFrame] {
+        &self.frames
+    }
+
+    pub fn depth(&self) -> usize {
+        self.frames.len()
+    }
+
+    // Number of RTS/RTI that did not return to the address expected by the top frame
+    pub fn mismatches(&self) -> u64 {
+        self.mismatches
+    }
+
+    pub fn clear(&mut self) {
+        self.frames.clear();
+    }
+
+    pub fn push(&mut self, frame: CallFrame) {
+        // Frames at or below the new return address on the stack were already unwound
+        self.discard_unwound(frame.stack_pointer.saturating_add(1));
+        self.frames.push(frame);
+    }
+
+    // RTS or RTI returned to `address`, leaving the stack pointer at `stack_pointer`.
+    pub fn pop(&mut self, address: u16, stack_pointer: u8) {
+        let top = self.frames.last().copied();
+        self.discard_unwound(stack_pointer);
+        if top.is_none_or(|frame| frame.return_address != address) {
+            self.mismatches += 1;
+        }
+    }
+
+    // Drops the frames whose return address is no longer on the stack, i.e. above `stack_pointer`.
+    // Subroutines called with an empty stack wrap the stack pointer, they are only unwound by a return.
+    fn discard_unwound(&mut self, stack_pointer: u8) {
+        while self.frames.last().is_some_and(|frame| frame.stack_pointer < stack_pointer) {
+            self.frames.pop();
+        }
+    }
+}
+
+impl CPU {
+    pub fn call_stack(&self) -> &CallStack {
+        &self.call_stack
+    }
+
+    // Called after each instruction with the address and cycle counter at its start.
+    pub fn track_call_stack(&mut self, name: &str, pc: u16, cycle: u64) {
+        let kind = match name {
+            "JSR" => CallKind::Subroutine,
+            "BRK" => CallKind::Brk,
+            "RTS" | "RTI" => {
+                self.call_stack.pop(self.program_counter, self.stack_pointer);
+                return;
+            }
+            _ => return,
+        };
+        // JSR pushes the address of its last byte, BRK skips its padding byte
+        let return_address = pc.wrapping_add(if kind == CallKind::Brk { 2 } else { 3 });
+        self.push_call_frame(kind, pc, return_address, cycle);
+    }
+
+    // Interrupts return to the instruction they interrupted
+    pub fn track_interrupt(&mut self, kind: CallKind, interrupted: u16, cycle: u64) {
+        self.push_call_frame(kind, interrupted, interrupted, cycle);
+    }
+
+    fn push_call_frame(&mut self, kind: CallKind, caller: u16, return_address: u16, cycle: u64) {
+        self.call_stack.push(CallFrame {
+            kind,
+            caller,
+            target: self.program_counter,
+            return_address,
+            stack_pointer: self.stack_pointer,
+            cycle,
+        });
+    }
+
+    // Runs until the current subroutine or interrupt handler returns to its caller (or the CPU jams).
+    // Gives up after `max_instructions`, e.g. when stepping out of the main loop. Returns true if
+    // the subroutine returned.
+    pub fn step_out(&mut self, max_instructions: u64) -> bool {
+        let depth = self.call_stack.depth();
+        if depth == 0 {
+            return false;
+        }
+        for _ in 0..max_instructions {
+            if self.halted {
+                return false;
+            }
+            self.step();
+            if self.call_stack.depth() < depth {
+                return true;
+            }
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bus::Bus;
+    use crate::call_stack::CallKind;
+    use crate::cpu6502::{new_cpu, CPU};
+
+    fn cpu_with_program(program: &[u8]) -> CPU {
+        let mut cpu = new_cpu(Bus::new_flat());
+        for (offset, byte) in program.iter().enumerate() {
+            cpu.write_u8(0x0200 + offset as u16, *byte);
+        }
+        cpu.program_counter = 0x0200;
+        cpu.stack_pointer = 0xFD;
+        cpu
+    }
+
+    #[test]
+    fn test_nested_calls() {
+        // 0200: JSR $0210 / 0210: JSR $0220 / 0220: RTS / 0213: RTS
+        let mut program = [0xEA; 0x30];
+        program[0x00..0x03].copy_from_slice(&[0x20, 0x10, 0x02]);
+        program[0x10..0x14].copy_from_slice(&[0x20, 0x20, 0x02, 0x60]);
+        program[0x20] = 0x60;
+        let mut cpu = cpu_with_program(&program);
+
+        cpu.step();
+        cpu.step();
+        let frames = cpu.call_stack().frames();
+        assert_eq!(frames.len(), 2);
+        assert_eq!((frames[0].caller, frames[0].target, frames[0].return_address), (0x0200, 0x0210, 0x0203));
+        assert_eq!((frames[1].caller, frames[1].target), (0x0210, 0x0220));
+
+        assert!(cpu.step_out(100));
+        assert_eq!(cpu.program_counter, 0x0213);
+        assert!(cpu.step_out(100));
+        assert_eq!(cpu.program_counter, 0x0203);
+        assert_eq!(cpu.call_stack().depth(), 0);
+        assert_eq!(cpu.call_stack().mismatches(), 0);
+    }
+
+    #[test]
+    fn test_rts_jump_table_trick() {
+        // Pushes $02FF and "returns" to $0300 without any JSR
+        let mut cpu = cpu_with_program(&[0xA9, 0x02, 0x48, 0xA9, 0xFF, 0x48, 0x60]);
+        for _ in 0..5 {
+            cpu.step();
+        }
+        assert_eq!(cpu.program_counter, 0x0300);
+        assert_eq!(cpu.call_stack().depth(), 0);
+        assert_eq!(cpu.call_stack().mismatches(), 1);
+    }
+
+    #[test]
+    fn test_dropped_return_address() {
+        // 0200: JSR $0210 / 0210: JSR $0220 / 0220: PLA PLA RTS, which returns to 0203
+        let mut program = [0xEA; 0x30];
+        program[0x00..0x03].copy_from_slice(&[0x20, 0x10, 0x02]);
+        program[0x10..0x13].copy_from_slice(&[0x20, 0x20, 0x02]);
+        program[0x20..0x23].copy_from_slice(&[0x68, 0x68, 0x60]);
+        let mut cpu = cpu_with_program(&program);
+        for _ in 0..5 {
+            cpu.step();
+        }
+        assert_eq!(cpu.program_counter, 0x0203);
+        assert_eq!(cpu.call_stack().depth(), 0);
+        assert_eq!(cpu.call_stack().mismatches(), 1);
+    }
+
+    #[test]
+    fn test_interrupt_frame() {
+        let mut cpu = cpu_with_program(&[0xEA, 0xEA]);
+        cpu.write_u8(0xFFFA, 0x00);
+        cpu.write_u8(0xFFFB, 0x04);
+        cpu.write_u8(0x0400, 0x40); // RTI
+        cpu.request_nmi();
+        cpu.step();
+
+        let frame = cpu.call_stack().frames()[0];
+        assert_eq!((frame.kind, frame.caller, frame.target), (CallKind::Nmi, 0x0201, 0x0400));
+        cpu.step();
+        assert_eq!(cpu.program_counter, 0x0201);
+        assert_eq!(cpu.call_stack().depth(), 0);
+        assert_eq!(cpu.call_stack().mismatches(), 0);
+    }
+}
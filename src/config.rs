@@ -0,0 +1,343 @@
+use std::collections::BTreeMap;
+use crate::input_provider::GamepadProfile;
+use crate::joypad::JoypadButton;
+use crate::key_bindings::{Hotkey, KeyAction, KeyBindings};
+use crate::ppu::accuracy::Accuracy;
+
+// Persistent frontend settings, stored as a TOML file.
+// Only the subset of TOML needed by the settings is supported: comments, [sections] (with a
+// quoted part for gamepad names), and `key = value` pairs where the value is a string, an integer or a boolean.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Config {
+    // Joypad buttons and hotkeys, in the [key_bindings] and [hotkeys] sections
+    pub key_bindings: KeyBindings,
+    // .pal file to use instead of the built-in palette
+    pub palette_file: Option<String>,
+    pub audio_latency_ms: u32,
+    pub video_filter: String,
+    pub last_rom_directory: Option<String>,
+    pub savestate_directory: String,
+    // Fast scanline renderer or cycle accurate one
+    pub accuracy: Accuracy,
+    // Gamepad profiles by pad name, in [gamepad."<name>"] sections
+    pub gamepad_profiles: BTreeMap<String, GamepadProfile>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            key_bindings: KeyBindings::default(),
+            palette_file: None,
+            audio_latency_ms: 50,
+            video_filter: "none".to_string(),
+            last_rom_directory: None,
+            savestate_directory: "saves".to_string(),
+            accuracy: Accuracy::default(),
+            gamepad_profiles: BTreeMap::new(),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Value {
+    String(String),
+    Integer(i64),
+    Boolean(bool),
+}
+
+impl Config {
+    // Loads the settings, falling back to the default ones when the file does not exist yet.
+    pub fn load(path: &str) -> Result<Self, String> {
+        match std::fs::read_to_string(path) {
+            Ok(content) => Self::from_toml(&content).map_err(|error| format!("Invalid config file {}: {}", path, error)),
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(error) => Err(format!("Failed to read config file {}: {}", path, error)),
+        }
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        std::fs::write(path, self.to_toml()).map_err(|error| format!("Failed to write config file {}: {}", path, error))
+    }
+
+    // Settings missing from the file keep their default value.
+    pub fn from_toml(content: &str) -> Result<Self, String> {
+        let mut config = Self::default();
+        let mut section = String::new();
+
+        for (index, raw_line) in content.lines().enumerate() {
+            let line_number = index + 1;
+            let line = strip_comment(raw_line).trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            if let Some(name) = line.strip_prefix('[').and_then(|line| line.strip_suffix(']')) {
+                section = name.trim().to_string();
+                if let Some(gamepad) = section.strip_prefix("gamepad.") {
+                    let name = match parse_value(gamepad.trim()) {
+                        Ok(Value::String(name)) => name,
+                        _ => return Err(format!("Line {}: Expected a quoted gamepad name in [{}]", line_number, section)),
+                    };
+                    config.gamepad_profiles.insert(name.clone(), GamepadProfile::default());
+                    section = format!("gamepad.{}", name);
+                } else if section != "key_bindings" && section != "hotkeys" {
+                    return Err(format!("Line {}: Unknown section [{}]", line_number, section));
+                }
+                continue;
+            }
+
+            let (key, value) = line.split_once('=')
+                .ok_or_else(|| format!("Line {}: Expected `key = value`", line_number))?;
+            let key = key.trim();
+            let value = parse_value(value.trim()).map_err(|error| format!("Line {}: {}", line_number, error))?;
+
+            let result = match section.as_str() {
+                "key_bindings" => KeyAction::joypad_from_name(key)
+                    .and_then(|action| set_key_binding(&mut config.key_bindings, action, value)),
+                "hotkeys" => Hotkey::from_name(key)
+                    .and_then(|hotkey| set_key_binding(&mut config.key_bindings, KeyAction::Hotkey(hotkey), value)),
+                _ => match section.strip_prefix("gamepad.") {
+                    Some(name) => set_gamepad(config.gamepad_profiles.get_mut(name).unwrap(), key, value),
+                    None => config.set(key, value),
+                },
+            };
+            result.map_err(|error| format!("Line {}: {}: {}", line_number, key, error))?;
+        }
+        Ok(config)
+    }
+
+    fn set(&mut self, key: &str, value: Value) -> Result<(), String> {
+        match key {
+            "palette_file" => self.palette_file = Some(expect_string(value)?),
+            "audio_latency_ms" => {
+                self.audio_latency_ms = match value {
+                    Value::Integer(integer) => u32::try_from(integer).map_err(|_| "Value is out of range".to_string())?,
+                    _ => return Err("Expected an integer".to_string()),
+                }
+            }
+            "video_filter" => self.video_filter = expect_string(value)?,
+            "last_rom_directory" => self.last_rom_directory = Some(expect_string(value)?),
+            "savestate_directory" => self.savestate_directory = expect_string(value)?,
+            "accuracy" => self.accuracy = Accuracy::from_name(&expect_string(value)?)?,
+            _ => return Err("Unknown setting".to_string()),
+        }
+        Ok(())
+    }
+
+    pub fn to_toml(&self) -> String {
+        let mut toml = String::new();
+        if let Some(palette_file) = &self.palette_file {
+            toml.push_str(&format!("palette_file = {}\n", quote(palette_file)));
+        }
+        toml.push_str(&format!("audio_latency_ms = {}\n", self.audio_latency_ms));
+        toml.push_str(&format!("video_filter = {}\n", quote(&self.video_filter)));
+        if let Some(last_rom_directory) = &self.last_rom_directory {
+            toml.push_str(&format!("last_rom_directory = {}\n", quote(last_rom_directory)));
+        }
+        toml.push_str(&format!("savestate_directory = {}\n", quote(&self.savestate_directory)));
+        toml.push_str(&format!("accuracy = {}\n", quote(self.accuracy.name())));
+
+        // Actions bound by default are written even when unbound (""), so they stay unbound
+        toml.push_str("\n[key_bindings]\n");
+        for button in JoypadButton::ALL {
+            let key = self.key_bindings.key(KeyAction::Joypad { player: 0, button }).unwrap_or("");
+            toml.push_str(&format!("{} = {}\n", button.name(), quote(key)));
+        }
+        for (action, key) in self.key_bindings.iter() {
+            if let KeyAction::Joypad { player: player @ 1.., button } = action {
+                toml.push_str(&format!("{} = {}\n", KeyAction::joypad_name(player, button), quote(key)));
+            }
+        }
+        toml.push_str("\n[hotkeys]\n");
+        for hotkey in Hotkey::ALL {
+            let key = self.key_bindings.key(KeyAction::Hotkey(hotkey)).unwrap_or("");
+            toml.push_str(&format!("{} = {}\n", hotkey.name(), quote(key)));
+        }
+        for (name, profile) in &self.gamepad_profiles {
+            toml.push_str(&format!("\n[gamepad.{}]\n", quote(name)));
+            if let Some(port) = profile.port {
+                toml.push_str(&format!("port = {}\n", port + 1));
+            }
+            toml.push_str(&format!("stick_threshold = {}\n", profile.stick_threshold));
+            for (button, physical) in &profile.buttons {
+                toml.push_str(&format!("{} = {}\n", button, quote(physical)));
+            }
+        }
+        toml
+    }
+}
+
+// An empty key name unbinds the action
+fn set_key_binding(key_bindings: &mut KeyBindings, action: KeyAction, value: Value) -> Result<(), String> {
+    match expect_string(value)?.as_str() {
+        "" => key_bindings.unbind(action),
+        key => {
+            key_bindings.bind(action, key);
+        }
+    }
+    Ok(())
+}
+
+// Setting of a [gamepad."<name>"] section: `port` (1 or 2), `stick_threshold` (percent), or a
+// joypad button name mapped to a physical button.
+fn set_gamepad(profile: &mut GamepadProfile, key: &str, value: Value) -> Result<(), String> {
+    match (key, value) {
+        ("port", Value::Integer(port @ 1..=2)) => profile.port = Some(port as usize - 1),
+        ("port", _) => return Err("Expected 1 or 2".to_string()),
+        ("stick_threshold", Value::Integer(threshold @ 1..=100)) => profile.stick_threshold = threshold as u8,
+        ("stick_threshold", _) => return Err("Expected a percentage (1 to 100)".to_string()),
+        (button, value) => {
+            JoypadButton::from_name(button)?;
+            profile.buttons.insert(button.to_string(), expect_string(value)?);
+        }
+    }
+    Ok(())
+}
+
+// Removes a trailing comment, ignoring '#' characters inside strings.
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    let mut escaped = false;
+    for (index, character) in line.char_indices() {
+        if escaped {
+            escaped = false;
+            continue;
+        }
+        match character {
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            '#' if !in_string => return &line[..index],
+            _ => {}
+        }
+    }
+    line
+}
+
+fn parse_value(value: &str) -> Result<Value, String> {
+    if let Some(content) = value.strip_prefix('"').and_then(|value| value.strip_suffix('"')) {
+        let mut string = String::new();
+        let mut characters = content.chars();
+        while let Some(character) = characters.next() {
+            if character != '\\' {
+                string.push(character);
+                continue;
+            }
+            match characters.next() {
+                Some('"') => string.push('"'),
+                Some('\\') => string.push('\\'),
+                Some('n') => string.push('\n'),
+                Some('t') => string.push('\t'),
+                other => return Err(format!("Unsupported escape sequence \\{}", other.map(String::from).unwrap_or_default())),
+            }
+        }
+        return Ok(Value::String(string));
+    }
+
+    match value {
+        "true" => Ok(Value::Boolean(true)),
+        "false" => Ok(Value::Boolean(false)),
+        _ => value.replace('_', "").parse::<i64>()
+            .map(Value::Integer)
+            .map_err(|_| format!("Invalid value `{}`", value)),
+    }
+}
+
+fn expect_string(value: Value) -> Result<String, String> {
+    match value {
+        Value::String(string) => Ok(string),
+        _ => Err("Expected a string".to_string()),
+    }
+}
+
+fn quote(string: &str) -> String {
+    let escaped = string
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+        .replace('\t', "\\t");
+    format!("\"{}\"", escaped)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::Config;
+    use crate::input_provider::GamepadProfile;
+    use crate::joypad::JoypadButton;
+    use crate::key_bindings::{Hotkey, KeyAction};
+    use crate::ppu::accuracy::Accuracy;
+
+    #[test]
+    fn test_round_trip() {
+        let mut config = Config {
+            palette_file: Some("C:\\palettes\\\"smooth\".pal".to_string()),
+            audio_latency_ms: 80,
+            video_filter: "scanlines".to_string(),
+            last_rom_directory: Some("/home/nes/roms # all".to_string()),
+            accuracy: Accuracy::CycleAccurate,
+            ..Config::default()
+        };
+        config.key_bindings.bind(KeyAction::Joypad { player: 0, button: JoypadButton::A }, "K");
+        config.key_bindings.bind(KeyAction::Joypad { player: 1, button: JoypadButton::Start }, "Space");
+        config.key_bindings.unbind(KeyAction::Hotkey(Hotkey::Screenshot));
+        config.key_bindings.unbind(KeyAction::Joypad { player: 0, button: JoypadButton::Select });
+        let mut profile = GamepadProfile { port: Some(1), stick_threshold: 35, ..GamepadProfile::default() };
+        profile.buttons.insert("a".to_string(), "South".to_string());
+        config.gamepad_profiles.insert("8BitDo \"SN30\" Pro".to_string(), profile);
+        config.gamepad_profiles.insert("Xbox Controller".to_string(), GamepadProfile::default());
+
+        assert_eq!(Config::from_toml(&config.to_toml()), Ok(config));
+    }
+
+    #[test]
+    fn test_partial_file_keeps_defaults() {
+        let config = Config::from_toml(
+            "# Settings\n\
+             audio_latency_ms = 1_00 # ms\n\
+             \n\
+             [key_bindings]\n\
+             start = \"Space\"\n",
+        ).expect("config should parse");
+
+        assert_eq!(config.audio_latency_ms, 100);
+        assert_eq!(config.key_bindings.key(KeyAction::Joypad { player: 0, button: JoypadButton::Start }), Some("Space"));
+        assert_eq!(config.key_bindings.key(KeyAction::Joypad { player: 0, button: JoypadButton::A }), Some("X"));
+        assert_eq!(config.key_bindings.hotkey("F5"), Some(Hotkey::SaveState));
+        assert_eq!(config.video_filter, "none");
+    }
+
+    #[test]
+    fn test_gamepad_profile() {
+        let config = Config::from_toml(
+            "[gamepad.\"Xbox Controller\"]\n\
+             port = 2\n\
+             b = \"West\"\n",
+        ).expect("config should parse");
+
+        let profile = &config.gamepad_profiles["Xbox Controller"];
+        assert_eq!(profile.port, Some(1));
+        assert_eq!(profile.stick_threshold, 50);
+        assert_eq!(profile.buttons["b"], "West");
+        assert_eq!(profile.buttons["a"], "East");
+    }
+
+    #[test]
+    fn test_invalid_files() {
+        assert!(Config::from_toml("unknown = 1").is_err());
+        assert!(Config::from_toml("audio_latency_ms = \"fast\"").is_err());
+        assert!(Config::from_toml("audio_latency_ms = -1").is_err());
+        assert!(Config::from_toml("[window]").is_err());
+        assert!(Config::from_toml("video_filter").is_err());
+        assert!(Config::from_toml("accuracy = \"exact\"").is_err());
+        assert!(Config::from_toml("[key_bindings]\nturbo = \"T\"").is_err());
+        assert!(Config::from_toml("[hotkeys]\nquit = \"Escape\"").is_err());
+        assert!(Config::from_toml("[gamepad.Pad]").is_err());
+        assert!(Config::from_toml("[gamepad.\"Pad\"]\nport = 3").is_err());
+        assert!(Config::from_toml("[gamepad.\"Pad\"]\nstick_threshold = 0").is_err());
+        assert!(Config::from_toml("[gamepad.\"Pad\"]\nturbo = \"North\"").is_err());
+    }
+
+    #[test]
+    fn test_missing_file_loads_defaults() {
+        assert_eq!(Config::load("/nonexistent/config.toml"), Ok(Config::default()));
+    }
+}
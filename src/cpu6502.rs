@@ -1,8 +1,19 @@
-use phf::phf_map;
+use std::fmt::{self, Write};
+use crate::branch_trace::BranchTrace;
 use crate::bus::Bus;
+use crate::call_stack::CallStack;
+use crate::disasm::write_operand;
+use crate::event_viewer::EventLog;
+use crate::execution_counts::ExecutionCounts;
+use crate::freeze::{FreezeTiming, FrozenMemory};
+use crate::instructions::kil::CpuJam;
+use crate::interrupts::InterruptLines;
+use crate::labels::Labels;
+use crate::loader::Loader;
+use crate::scheduler::Scheduler;
 
 #[derive(Debug)]
-pub(crate) struct CPU {
+pub struct CPU {
     // More info about the 6502 registers can be found here:
     // https://www.nesdev.org/obelisk-6502-guide/registers.html
 
@@ -50,14 +61,60 @@ pub(crate) struct CPU {
 
     // Global cycle counter (counts CPU cycles executed)
     pub cycles: u64,
+    // Number of instructions executed, for the performance statistics (not part of savestates)
+    pub instructions: u64,
     // Halting state — some undocumented opcodes (KIL/JAM/HLT) stop the CPU until reset.
     pub halted: bool,
+    // CPU/PPU clock alignment chosen at power-on (0 to 3, see PowerOnConfig::ppu_alignment)
+    pub ppu_alignment: u64,
+    // Overclocking: scanlines added at the end of vblank, during which only the CPU runs (see frame.rs)
+    pub extra_scanlines: u64,
+    // Debug symbols used by the trace to replace addresses with their label
+    pub labels: Labels,
+    // Chip emulated by the core, which decides whether ADC and SBC honor the decimal flag
+    pub variant: CpuVariant,
+    // Analog constant of the unstable XAA and ATX opcodes (see MAGIC_CONSTANT_FF)
+    pub magic_constant: u8,
+    // State of the NMI and IRQ lines (see interrupts.rs)
+    pub interrupts: InterruptLines,
+    // Accuracy option: DMC DMA repeats the read it interrupts (see dmc_dma.rs)
+    pub dmc_dma_read_glitch: bool,
+    // JSR/RTS and interrupt frames, for the debugger backtrace (see call_stack.rs)
+    pub call_stack: CallStack,
+    // Last taken branches, jumps, calls, returns and interrupts (see branch_trace.rs)
+    pub branch_trace: BranchTrace,
+    // Events shown by the event viewer, recorded while enabled (see event_viewer.rs)
+    pub event_log: Option<EventLog>,
+    // Executions of each address and opcode, counted while enabled (see execution_counts.rs)
+    pub execution_counts: Option<Box<ExecutionCounts>>,
+    // Addresses frozen by cheats, written back after each instruction or frame (see freeze.rs)
+    pub frozen_memory: FrozenMemory,
+    // Next cycles the PPU has to be caught up at (see `CPU::run_ppu_events`). Cleared when the cycle
+    // counter goes back (power-on, savestates).
+    pub scheduler: Scheduler,
+}
+
+// XAA and ATX (LXA) OR the accumulator with a "magic" constant before using it. It comes from
+// analog effects on the data bus, so it depends on the chip and even on its temperature.
+// 0xFF is the value used by most emulators (and the historical behavior of this one),
+// 0xEE is the one of the SingleStepTests vectors.
+pub const MAGIC_CONSTANT_FF: u8 = 0xFF;
+pub const MAGIC_CONSTANT_EE: u8 = 0xEE;
+
+// The NES CPU (Ricoh RP2A03) is a 6502 whose decimal mode was removed: the D flag can be set and
+// cleared, but ADC and SBC always compute in binary. The generic NMOS 6502 variant implements BCD
+// arithmetic, so the core can run other 6502 programs and BCD test suites.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum CpuVariant {
+    #[default]
+    Rp2a03,
+    Nmos6502,
 }
 
 // Each flag corresponds to a bit in the status register
 // Values are the bit positions
 #[derive(Debug, Clone, Copy)]
-pub(crate) enum StatusFlag {
+pub enum StatusFlag {
     Carry = 0,
     Zero = 1,
     InterruptDisable = 2,
@@ -69,7 +126,7 @@ pub(crate) enum StatusFlag {
 }
 
 #[derive(Debug, Clone, Copy)]
-pub(crate) enum AddressingMode {
+pub enum AddressingMode {
     Absolute,    // e.g. LDA $1234
     AbsoluteX,   // e.g. LDA $1234,X
     AbsoluteY,   // e.g. LDA $1234,Y
@@ -85,7 +142,7 @@ pub(crate) enum AddressingMode {
     ZeroPageY,   // e.g. LDX $10,Y
 }
 
-pub(crate) fn new_cpu(bus: Bus) -> CPU {
+pub fn new_cpu(bus: Bus) -> CPU {
     CPU {
         program_counter: 0x0000,
         stack_pointer: CPU::STACK_ADDRESS_DEFAULT_COLD_START,
@@ -95,443 +152,485 @@ pub(crate) fn new_cpu(bus: Bus) -> CPU {
         status_register: 0x24, // 0010 0100 (Unused + Interrupt Disable)
         bus,
         cycles: 0,
+        instructions: 0,
         halted: false,
+        ppu_alignment: 0,
+        extra_scanlines: 0,
+        labels: Labels::new(),
+        variant: CpuVariant::default(),
+        magic_constant: MAGIC_CONSTANT_FF,
+        interrupts: InterruptLines::default(),
+        dmc_dma_read_glitch: false,
+        call_stack: CallStack::default(),
+        branch_trace: BranchTrace::default(),
+        event_log: None,
+        execution_counts: None,
+        frozen_memory: FrozenMemory::new(),
+        scheduler: Scheduler::new(),
     }
 }
 
 #[derive(Debug, Clone, Copy)]
 pub struct Operand {
-    opcode: u8,
-    name: &'static str,
+    pub opcode: u8,
+    pub name: &'static str,
     // Function pointer to the instruction handler
     //                    memory value   address
-    handler: fn(&mut CPU, Option<u8>, Option<u16>) -> u8,
-    addressing_mode: AddressingMode,
-    bytes: u8,
-    cycles: u8,
+    pub handler: fn(&mut CPU, Option<u8>, Option<u16>) -> u8,
+    pub addressing_mode: AddressingMode,
+    pub bytes: u8,
+    pub cycles: u8,
 }
 
 // List of all opcodes and their corresponding Operand definitions.
-static OPERAND_MAP: phf::Map<u8, Operand> = phf_map! {
+const OPERANDS: &[Operand] = &[
     // Official Opcode List
     // ADC Instructions
-    0x69u8 => Operand { opcode: 0x69, name: "ADC", handler: CPU::handle_adc, addressing_mode: AddressingMode::Immediate, bytes: 2, cycles: 2 },
-    0x65u8 => Operand { opcode: 0x65, name: "ADC", handler: CPU::handle_adc, addressing_mode: AddressingMode::ZeroPage, bytes: 2, cycles: 3 },
-    0x75u8 => Operand { opcode: 0x75, name: "ADC", handler: CPU::handle_adc, addressing_mode: AddressingMode::ZeroPageX, bytes: 2, cycles: 4 },
-    0x6Du8 => Operand { opcode: 0x6D, name: "ADC", handler: CPU::handle_adc, addressing_mode: AddressingMode::Absolute, bytes: 3, cycles: 4 },
-    0x7Du8 => Operand { opcode: 0x7D, name: "ADC", handler: CPU::handle_adc, addressing_mode: AddressingMode::AbsoluteX, bytes: 3, cycles: 4 /* +1 if page crossed */ },
-    0x79u8 => Operand { opcode: 0x79, name: "ADC", handler: CPU::handle_adc, addressing_mode: AddressingMode::AbsoluteY, bytes: 3, cycles: 4 /* +1 if page crossed */ },
-    0x61u8 => Operand { opcode: 0x61, name: "ADC", handler: CPU::handle_adc, addressing_mode: AddressingMode::IndirectX, bytes: 2, cycles: 6 },
-    0x71u8 => Operand { opcode: 0x71, name: "ADC", handler: CPU::handle_adc, addressing_mode: AddressingMode::IndirectY, bytes: 2, cycles: 5 /* +1 if page crossed */ },
+    Operand { opcode: 0x69, name: "ADC", handler: CPU::handle_adc, addressing_mode: AddressingMode::Immediate, bytes: 2, cycles: 2 },
+    Operand { opcode: 0x65, name: "ADC", handler: CPU::handle_adc, addressing_mode: AddressingMode::ZeroPage, bytes: 2, cycles: 3 },
+    Operand { opcode: 0x75, name: "ADC", handler: CPU::handle_adc, addressing_mode: AddressingMode::ZeroPageX, bytes: 2, cycles: 4 },
+    Operand { opcode: 0x6D, name: "ADC", handler: CPU::handle_adc, addressing_mode: AddressingMode::Absolute, bytes: 3, cycles: 4 },
+    Operand { opcode: 0x7D, name: "ADC", handler: CPU::handle_adc, addressing_mode: AddressingMode::AbsoluteX, bytes: 3, cycles: 4 /* +1 if page crossed */ },
+    Operand { opcode: 0x79, name: "ADC", handler: CPU::handle_adc, addressing_mode: AddressingMode::AbsoluteY, bytes: 3, cycles: 4 /* +1 if page crossed */ },
+    Operand { opcode: 0x61, name: "ADC", handler: CPU::handle_adc, addressing_mode: AddressingMode::IndirectX, bytes: 2, cycles: 6 },
+    Operand { opcode: 0x71, name: "ADC", handler: CPU::handle_adc, addressing_mode: AddressingMode::IndirectY, bytes: 2, cycles: 5 /* +1 if page crossed */ },
 
     // AND Instructions
-    0x29u8 => Operand { opcode: 0x29, name: "AND", handler: CPU::handle_and, addressing_mode: AddressingMode::Immediate, bytes: 2, cycles: 2 },
-    0x25u8 => Operand { opcode: 0x25, name: "AND", handler: CPU::handle_and, addressing_mode: AddressingMode::ZeroPage, bytes: 2, cycles: 3 },
-    0x35u8 => Operand { opcode: 0x35, name: "AND", handler: CPU::handle_and, addressing_mode: AddressingMode::ZeroPageX, bytes: 2, cycles: 4 },
-    0x2Du8 => Operand { opcode: 0x2D, name: "AND", handler: CPU::handle_and, addressing_mode: AddressingMode::Absolute, bytes: 3, cycles: 4 },
-    0x3Du8 => Operand { opcode: 0x3D, name: "AND", handler: CPU::handle_and, addressing_mode: AddressingMode::AbsoluteX, bytes: 3, cycles: 4 /* +1 if page crossed */ }, // TODO
-    0x39u8 => Operand { opcode: 0x39, name: "AND", handler: CPU::handle_and, addressing_mode: AddressingMode::AbsoluteY, bytes: 3, cycles: 4 /* +1 if page crossed */ },
-    0x21u8 => Operand { opcode: 0x21, name: "AND", handler: CPU::handle_and, addressing_mode: AddressingMode::IndirectX, bytes: 2, cycles: 6 },
-    0x31u8 => Operand { opcode: 0x31, name: "AND", handler: CPU::handle_and, addressing_mode: AddressingMode::IndirectY, bytes: 2, cycles: 5 /* +1 if page crossed */ },
+    Operand { opcode: 0x29, name: "AND", handler: CPU::handle_and, addressing_mode: AddressingMode::Immediate, bytes: 2, cycles: 2 },
+    Operand { opcode: 0x25, name: "AND", handler: CPU::handle_and, addressing_mode: AddressingMode::ZeroPage, bytes: 2, cycles: 3 },
+    Operand { opcode: 0x35, name: "AND", handler: CPU::handle_and, addressing_mode: AddressingMode::ZeroPageX, bytes: 2, cycles: 4 },
+    Operand { opcode: 0x2D, name: "AND", handler: CPU::handle_and, addressing_mode: AddressingMode::Absolute, bytes: 3, cycles: 4 },
+    Operand { opcode: 0x3D, name: "AND", handler: CPU::handle_and, addressing_mode: AddressingMode::AbsoluteX, bytes: 3, cycles: 4 /* +1 if page crossed */ },
+    Operand { opcode: 0x39, name: "AND", handler: CPU::handle_and, addressing_mode: AddressingMode::AbsoluteY, bytes: 3, cycles: 4 /* +1 if page crossed */ },
+    Operand { opcode: 0x21, name: "AND", handler: CPU::handle_and, addressing_mode: AddressingMode::IndirectX, bytes: 2, cycles: 6 },
+    Operand { opcode: 0x31, name: "AND", handler: CPU::handle_and, addressing_mode: AddressingMode::IndirectY, bytes: 2, cycles: 5 /* +1 if page crossed */ },
 
     // ASL Instructions
-    0x0Au8 => Operand { opcode: 0x0A, name: "ASL", handler: CPU::handle_asl, addressing_mode: AddressingMode::Accumulator, bytes: 1, cycles: 2 },
-    0x06u8 => Operand { opcode: 0x06, name: "ASL", handler: CPU::handle_asl, addressing_mode: AddressingMode::ZeroPage, bytes: 2, cycles: 5 },
-    0x16u8 => Operand { opcode: 0x16, name: "ASL", handler: CPU::handle_asl, addressing_mode: AddressingMode::ZeroPageX, bytes: 2, cycles: 6 },
-    0x0Eu8 => Operand { opcode: 0x0E, name: "ASL", handler: CPU::handle_asl, addressing_mode: AddressingMode::Absolute, bytes: 3, cycles: 6 },
-    0x1Eu8 => Operand { opcode: 0x1E, name: "ASL", handler: CPU::handle_asl, addressing_mode: AddressingMode::AbsoluteX, bytes: 3, cycles: 7 },
+    Operand { opcode: 0x0A, name: "ASL", handler: CPU::handle_asl, addressing_mode: AddressingMode::Accumulator, bytes: 1, cycles: 2 },
+    Operand { opcode: 0x06, name: "ASL", handler: CPU::handle_asl, addressing_mode: AddressingMode::ZeroPage, bytes: 2, cycles: 5 },
+    Operand { opcode: 0x16, name: "ASL", handler: CPU::handle_asl, addressing_mode: AddressingMode::ZeroPageX, bytes: 2, cycles: 6 },
+    Operand { opcode: 0x0E, name: "ASL", handler: CPU::handle_asl, addressing_mode: AddressingMode::Absolute, bytes: 3, cycles: 6 },
+    Operand { opcode: 0x1E, name: "ASL", handler: CPU::handle_asl, addressing_mode: AddressingMode::AbsoluteX, bytes: 3, cycles: 7 },
 
     // BCC Instructions
-    0x90u8 => Operand { opcode: 0x90, name: "BCC", handler: CPU::handle_bcc, addressing_mode: AddressingMode::Relative, bytes: 2, cycles: 2 /* +1 if branch succeeds or +2 if to a new page */ },
+    Operand { opcode: 0x90, name: "BCC", handler: CPU::handle_bcc, addressing_mode: AddressingMode::Relative, bytes: 2, cycles: 2 /* +1 if branch succeeds or +2 if to a new page */ },
 
     // BCS Instructions
-    0xB0u8 => Operand { opcode: 0xB0, name: "BCS", handler: CPU::handle_bcs, addressing_mode: AddressingMode::Relative, bytes: 2, cycles: 2 /* +1 if branch succeeds or +2 if to a new page */ },
+    Operand { opcode: 0xB0, name: "BCS", handler: CPU::handle_bcs, addressing_mode: AddressingMode::Relative, bytes: 2, cycles: 2 /* +1 if branch succeeds or +2 if to a new page */ },
 
     // BEQ Instructions
-    0xF0u8 => Operand { opcode: 0xF0, name: "BEQ", handler: CPU::handle_beq, addressing_mode: AddressingMode::Relative, bytes: 2, cycles: 2 /* +1 if branch succeeds or +2 if to a new page */ },
+    Operand { opcode: 0xF0, name: "BEQ", handler: CPU::handle_beq, addressing_mode: AddressingMode::Relative, bytes: 2, cycles: 2 /* +1 if branch succeeds or +2 if to a new page */ },
 
     // BIT Instructions
-    0x24u8 => Operand { opcode: 0x24, name: "BIT", handler: CPU::handle_bit, addressing_mode: AddressingMode::ZeroPage, bytes: 2, cycles: 3 },
-    0x2Cu8 => Operand { opcode: 0x2C, name: "BIT", handler: CPU::handle_bit, addressing_mode: AddressingMode::Absolute, bytes: 3, cycles: 4 },
+    Operand { opcode: 0x24, name: "BIT", handler: CPU::handle_bit, addressing_mode: AddressingMode::ZeroPage, bytes: 2, cycles: 3 },
+    Operand { opcode: 0x2C, name: "BIT", handler: CPU::handle_bit, addressing_mode: AddressingMode::Absolute, bytes: 3, cycles: 4 },
 
     // BMI Instructions
-    0x30u8 => Operand { opcode: 0x30, name: "BMI", handler: CPU::handle_bmi, addressing_mode: AddressingMode::Relative, bytes: 2, cycles: 2 /* +1 if branch succeeds or +2 if to a new page */  },
+    Operand { opcode: 0x30, name: "BMI", handler: CPU::handle_bmi, addressing_mode: AddressingMode::Relative, bytes: 2, cycles: 2 /* +1 if branch succeeds or +2 if to a new page */  },
 
     // BNE Instructions
-    0xD0u8 => Operand { opcode: 0xD0, name: "BNE", handler: CPU::handle_bne, addressing_mode: AddressingMode::Relative, bytes: 2, cycles: 2 /* +1 if branch succeeds or +2 if to a new page */  },
+    Operand { opcode: 0xD0, name: "BNE", handler: CPU::handle_bne, addressing_mode: AddressingMode::Relative, bytes: 2, cycles: 2 /* +1 if branch succeeds or +2 if to a new page */  },
 
     // BPL Instructions
-    0x10u8 => Operand { opcode: 0x10, name: "BPL", handler: CPU::handle_bpl, addressing_mode: AddressingMode::Relative, bytes: 2, cycles: 2 /* +1 if branch succeeds or +2 if to a new page */  },
+    Operand { opcode: 0x10, name: "BPL", handler: CPU::handle_bpl, addressing_mode: AddressingMode::Relative, bytes: 2, cycles: 2 /* +1 if branch succeeds or +2 if to a new page */  },
 
     // BRK Instructions
-    0x00u8 => Operand { opcode: 0x00, name: "BRK", handler: CPU::handle_brk, addressing_mode: AddressingMode::Implicit, bytes: 1, cycles: 7 },
+    Operand { opcode: 0x00, name: "BRK", handler: CPU::handle_brk, addressing_mode: AddressingMode::Implicit, bytes: 1, cycles: 7 },
 
     // BVC Instructions
-    0x50u8 => Operand { opcode: 0x50, name: "BVC", handler: CPU::handle_bvc, addressing_mode: AddressingMode::Relative, bytes: 2, cycles: 2 /* +1 if branch succeeds or +2 if to a new page */  },
+    Operand { opcode: 0x50, name: "BVC", handler: CPU::handle_bvc, addressing_mode: AddressingMode::Relative, bytes: 2, cycles: 2 /* +1 if branch succeeds or +2 if to a new page */  },
 
     // BVS Instructions
-    0x70u8 => Operand { opcode: 0x70, name: "BVS", handler: CPU::handle_bvs, addressing_mode: AddressingMode::Relative, bytes: 2, cycles: 2 /* +1 if branch succeeds or +2 if to a new page */  },
+    Operand { opcode: 0x70, name: "BVS", handler: CPU::handle_bvs, addressing_mode: AddressingMode::Relative, bytes: 2, cycles: 2 /* +1 if branch succeeds or +2 if to a new page */  },
 
     // CLC Instructions
-    0x18u8 => Operand { opcode: 0x18, name: "CLC", handler: CPU::handle_clc, addressing_mode: AddressingMode::Implicit, bytes: 1, cycles: 2 },
+    Operand { opcode: 0x18, name: "CLC", handler: CPU::handle_clc, addressing_mode: AddressingMode::Implicit, bytes: 1, cycles: 2 },
 
     // CLD Instructions
-    0xD8u8 => Operand { opcode: 0xD8, name: "CLD", handler: CPU::handle_cld, addressing_mode: AddressingMode::Implicit, bytes: 1, cycles: 2 },
+    Operand { opcode: 0xD8, name: "CLD", handler: CPU::handle_cld, addressing_mode: AddressingMode::Implicit, bytes: 1, cycles: 2 },
 
     // CLI Instructions
-    0x58u8 => Operand { opcode: 0x58, name: "CLI", handler: CPU::handle_cli, addressing_mode: AddressingMode::Implicit, bytes: 1, cycles: 2 },
+    Operand { opcode: 0x58, name: "CLI", handler: CPU::handle_cli, addressing_mode: AddressingMode::Implicit, bytes: 1, cycles: 2 },
 
     // CLV Instructions
-    0xB8u8 => Operand { opcode: 0xB8, name: "CLV", handler: CPU::handle_clv, addressing_mode: AddressingMode::Implicit, bytes: 1, cycles: 2 },
+    Operand { opcode: 0xB8, name: "CLV", handler: CPU::handle_clv, addressing_mode: AddressingMode::Implicit, bytes: 1, cycles: 2 },
 
     // CMP Instructions
-    0xC9u8 => Operand { opcode: 0xC9, name: "CMP", handler: CPU::handle_cmp, addressing_mode: AddressingMode::Immediate, bytes: 2, cycles: 2 },
-    0xC5u8 => Operand { opcode: 0xC5, name: "CMP", handler: CPU::handle_cmp, addressing_mode: AddressingMode::ZeroPage, bytes: 2, cycles: 3 },
-    0xD5u8 => Operand { opcode: 0xD5, name: "CMP", handler: CPU::handle_cmp, addressing_mode: AddressingMode::ZeroPageX, bytes: 2, cycles: 4 },
-    0xCDu8 => Operand { opcode: 0xCD, name: "CMP", handler: CPU::handle_cmp, addressing_mode: AddressingMode::Absolute, bytes: 3, cycles: 4 },
-    0xDDu8 => Operand { opcode: 0xDD, name: "CMP", handler: CPU::handle_cmp, addressing_mode: AddressingMode::AbsoluteX, bytes: 3, cycles: 4 /* +1 if page crossed */ },
-    0xD9u8 => Operand { opcode: 0xD9, name: "CMP", handler: CPU::handle_cmp, addressing_mode: AddressingMode::AbsoluteY, bytes: 3, cycles: 4 /* +1 if page crossed */ },
-    0xC1u8 => Operand { opcode: 0xC1, name: "CMP", handler: CPU::handle_cmp, addressing_mode: AddressingMode::IndirectX, bytes: 2, cycles: 6 },
-    0xD1u8 => Operand { opcode: 0xD1, name: "CMP", handler: CPU::handle_cmp, addressing_mode: AddressingMode::IndirectY, bytes: 2, cycles: 5 /* +1 if page crossed */ },
+    Operand { opcode: 0xC9, name: "CMP", handler: CPU::handle_cmp, addressing_mode: AddressingMode::Immediate, bytes: 2, cycles: 2 },
+    Operand { opcode: 0xC5, name: "CMP", handler: CPU::handle_cmp, addressing_mode: AddressingMode::ZeroPage, bytes: 2, cycles: 3 },
+    Operand { opcode: 0xD5, name: "CMP", handler: CPU::handle_cmp, addressing_mode: AddressingMode::ZeroPageX, bytes: 2, cycles: 4 },
+    Operand { opcode: 0xCD, name: "CMP", handler: CPU::handle_cmp, addressing_mode: AddressingMode::Absolute, bytes: 3, cycles: 4 },
+    Operand { opcode: 0xDD, name: "CMP", handler: CPU::handle_cmp, addressing_mode: AddressingMode::AbsoluteX, bytes: 3, cycles: 4 /* +1 if page crossed */ },
+    Operand { opcode: 0xD9, name: "CMP", handler: CPU::handle_cmp, addressing_mode: AddressingMode::AbsoluteY, bytes: 3, cycles: 4 /* +1 if page crossed */ },
+    Operand { opcode: 0xC1, name: "CMP", handler: CPU::handle_cmp, addressing_mode: AddressingMode::IndirectX, bytes: 2, cycles: 6 },
+    Operand { opcode: 0xD1, name: "CMP", handler: CPU::handle_cmp, addressing_mode: AddressingMode::IndirectY, bytes: 2, cycles: 5 /* +1 if page crossed */ },
 
     // CPX Instructions
-    0xE0u8 => Operand { opcode: 0xE0, name: "CPX", handler: CPU::handle_cpx, addressing_mode: AddressingMode::Immediate, bytes: 2, cycles: 2 },
-    0xE4u8 => Operand { opcode: 0xE4, name: "CPX", handler: CPU::handle_cpx, addressing_mode: AddressingMode::ZeroPage, bytes: 2, cycles: 3 },
-    0xECu8 => Operand { opcode: 0xEC, name: "CPX", handler: CPU::handle_cpx, addressing_mode: AddressingMode::Absolute, bytes: 3, cycles: 4 },
+    Operand { opcode: 0xE0, name: "CPX", handler: CPU::handle_cpx, addressing_mode: AddressingMode::Immediate, bytes: 2, cycles: 2 },
+    Operand { opcode: 0xE4, name: "CPX", handler: CPU::handle_cpx, addressing_mode: AddressingMode::ZeroPage, bytes: 2, cycles: 3 },
+    Operand { opcode: 0xEC, name: "CPX", handler: CPU::handle_cpx, addressing_mode: AddressingMode::Absolute, bytes: 3, cycles: 4 },
 
     // CPY Instructions
-    0xC0u8 => Operand { opcode: 0xC0, name: "CPY", handler: CPU::handle_cpy, addressing_mode: AddressingMode::Immediate, bytes: 2, cycles: 2 },
-    0xC4u8 => Operand { opcode: 0xC4, name: "CPY", handler: CPU::handle_cpy, addressing_mode: AddressingMode::ZeroPage, bytes: 2, cycles: 3 },
-    0xCCu8 => Operand { opcode: 0xCC, name: "CPY", handler: CPU::handle_cpy, addressing_mode: AddressingMode::Absolute, bytes: 3, cycles: 4 },
+    Operand { opcode: 0xC0, name: "CPY", handler: CPU::handle_cpy, addressing_mode: AddressingMode::Immediate, bytes: 2, cycles: 2 },
+    Operand { opcode: 0xC4, name: "CPY", handler: CPU::handle_cpy, addressing_mode: AddressingMode::ZeroPage, bytes: 2, cycles: 3 },
+    Operand { opcode: 0xCC, name: "CPY", handler: CPU::handle_cpy, addressing_mode: AddressingMode::Absolute, bytes: 3, cycles: 4 },
 
     // DEC Instructions
-    0xC6u8 => Operand { opcode: 0xC6, name: "DEC", handler: CPU::handle_dec, addressing_mode: AddressingMode::ZeroPage, bytes: 2, cycles: 5 },
-    0xD6u8 => Operand { opcode: 0xD6, name: "DEC", handler: CPU::handle_dec, addressing_mode: AddressingMode::ZeroPageX, bytes: 2, cycles: 6 },
-    0xCEu8 => Operand { opcode: 0xCE, name: "DEC", handler: CPU::handle_dec, addressing_mode: AddressingMode::Absolute, bytes: 3, cycles: 6 },
-    0xDEu8 => Operand { opcode: 0xDE, name: "DEC", handler: CPU::handle_dec, addressing_mode: AddressingMode::AbsoluteX, bytes: 3, cycles: 7 },
+    Operand { opcode: 0xC6, name: "DEC", handler: CPU::handle_dec, addressing_mode: AddressingMode::ZeroPage, bytes: 2, cycles: 5 },
+    Operand { opcode: 0xD6, name: "DEC", handler: CPU::handle_dec, addressing_mode: AddressingMode::ZeroPageX, bytes: 2, cycles: 6 },
+    Operand { opcode: 0xCE, name: "DEC", handler: CPU::handle_dec, addressing_mode: AddressingMode::Absolute, bytes: 3, cycles: 6 },
+    Operand { opcode: 0xDE, name: "DEC", handler: CPU::handle_dec, addressing_mode: AddressingMode::AbsoluteX, bytes: 3, cycles: 7 },
 
     // DEX Instructions
-    0xCAu8 => Operand { opcode: 0xCA, name: "DEX", handler: CPU::handle_dex, addressing_mode: AddressingMode::Implicit, bytes: 1, cycles: 2 },
+    Operand { opcode: 0xCA, name: "DEX", handler: CPU::handle_dex, addressing_mode: AddressingMode::Implicit, bytes: 1, cycles: 2 },
 
     // DEY Instructions
-    0x88u8 => Operand { opcode: 0x88, name: "DEY", handler: CPU::handle_dey, addressing_mode: AddressingMode::Implicit, bytes: 1, cycles: 2 },
+    Operand { opcode: 0x88, name: "DEY", handler: CPU::handle_dey, addressing_mode: AddressingMode::Implicit, bytes: 1, cycles: 2 },
 
     // EOR Instructions
-    0x49u8 => Operand { opcode: 0x49, name: "EOR", handler: CPU::handle_eor, addressing_mode: AddressingMode::Immediate, bytes: 2, cycles: 2 },
-    0x45u8 => Operand { opcode: 0x45, name: "EOR", handler: CPU::handle_eor, addressing_mode: AddressingMode::ZeroPage, bytes: 2, cycles: 3 },
-    0x55u8 => Operand { opcode: 0x55, name: "EOR", handler: CPU::handle_eor, addressing_mode: AddressingMode::ZeroPageX, bytes: 2, cycles: 4 },
-    0x4Du8 => Operand { opcode: 0x4D, name: "EOR", handler: CPU::handle_eor, addressing_mode: AddressingMode::Absolute, bytes: 3, cycles: 4 },
-    0x5Du8 => Operand { opcode: 0x5D, name: "EOR", handler: CPU::handle_eor, addressing_mode: AddressingMode::AbsoluteX, bytes: 3, cycles: 4 /* +1 if page crossed */ },
-    0x59u8 => Operand { opcode: 0x59, name: "EOR", handler: CPU::handle_eor, addressing_mode: AddressingMode::AbsoluteY, bytes: 3, cycles: 4 /* +1 if page crossed */ },
-    0x41u8 => Operand { opcode: 0x41, name: "EOR", handler: CPU::handle_eor, addressing_mode: AddressingMode::IndirectX, bytes: 2, cycles: 6 },
-    0x51u8 => Operand { opcode: 0x51, name: "EOR", handler: CPU::handle_eor, addressing_mode: AddressingMode::IndirectY, bytes: 2, cycles: 5 /* +1 if page crossed */ },
+    Operand { opcode: 0x49, name: "EOR", handler: CPU::handle_eor, addressing_mode: AddressingMode::Immediate, bytes: 2, cycles: 2 },
+    Operand { opcode: 0x45, name: "EOR", handler: CPU::handle_eor, addressing_mode: AddressingMode::ZeroPage, bytes: 2, cycles: 3 },
+    Operand { opcode: 0x55, name: "EOR", handler: CPU::handle_eor, addressing_mode: AddressingMode::ZeroPageX, bytes: 2, cycles: 4 },
+    Operand { opcode: 0x4D, name: "EOR", handler: CPU::handle_eor, addressing_mode: AddressingMode::Absolute, bytes: 3, cycles: 4 },
+    Operand { opcode: 0x5D, name: "EOR", handler: CPU::handle_eor, addressing_mode: AddressingMode::AbsoluteX, bytes: 3, cycles: 4 /* +1 if page crossed */ },
+    Operand { opcode: 0x59, name: "EOR", handler: CPU::handle_eor, addressing_mode: AddressingMode::AbsoluteY, bytes: 3, cycles: 4 /* +1 if page crossed */ },
+    Operand { opcode: 0x41, name: "EOR", handler: CPU::handle_eor, addressing_mode: AddressingMode::IndirectX, bytes: 2, cycles: 6 },
+    Operand { opcode: 0x51, name: "EOR", handler: CPU::handle_eor, addressing_mode: AddressingMode::IndirectY, bytes: 2, cycles: 5 /* +1 if page crossed */ },
 
     // INC Instructions
-    0xE6u8 => Operand { opcode: 0xE6, name: "INC", handler: CPU::handle_inc, addressing_mode: AddressingMode::ZeroPage, bytes: 2, cycles: 5 },
-    0xF6u8 => Operand { opcode: 0xF6, name: "INC", handler: CPU::handle_inc, addressing_mode: AddressingMode::ZeroPageX, bytes: 2, cycles: 6 },
-    0xEEu8 => Operand { opcode: 0xEE, name: "INC", handler: CPU::handle_inc, addressing_mode: AddressingMode::Absolute, bytes: 3, cycles: 6 },
-    0xFEu8 => Operand { opcode: 0xFE, name: "INC", handler: CPU::handle_inc, addressing_mode: AddressingMode::AbsoluteX, bytes: 3, cycles: 7  },
+    Operand { opcode: 0xE6, name: "INC", handler: CPU::handle_inc, addressing_mode: AddressingMode::ZeroPage, bytes: 2, cycles: 5 },
+    Operand { opcode: 0xF6, name: "INC", handler: CPU::handle_inc, addressing_mode: AddressingMode::ZeroPageX, bytes: 2, cycles: 6 },
+    Operand { opcode: 0xEE, name: "INC", handler: CPU::handle_inc, addressing_mode: AddressingMode::Absolute, bytes: 3, cycles: 6 },
+    Operand { opcode: 0xFE, name: "INC", handler: CPU::handle_inc, addressing_mode: AddressingMode::AbsoluteX, bytes: 3, cycles: 7  },
 
     // INX Instructions
-    0xE8u8 => Operand { opcode: 0xE8, name: "INX", handler: CPU::handle_inx, addressing_mode: AddressingMode::Implicit, bytes: 1, cycles: 2  },
+    Operand { opcode: 0xE8, name: "INX", handler: CPU::handle_inx, addressing_mode: AddressingMode::Implicit, bytes: 1, cycles: 2  },
 
     // INY Instructions
-    0xC8u8 => Operand { opcode: 0xC8, name: "INY", handler: CPU::handle_iny, addressing_mode: AddressingMode::Implicit, bytes: 1, cycles: 2  },
+    Operand { opcode: 0xC8, name: "INY", handler: CPU::handle_iny, addressing_mode: AddressingMode::Implicit, bytes: 1, cycles: 2  },
 
     // JMP Instructions
-    0x4Cu8 => Operand { opcode: 0x4C, name: "JMP", handler: CPU::handle_jmp, addressing_mode: AddressingMode::Absolute, bytes: 3, cycles: 3 },
-    0x6Cu8 => Operand { opcode: 0x6C, name: "JMP", handler: CPU::handle_jmp, addressing_mode: AddressingMode::Indirect, bytes: 3, cycles: 5 },
+    Operand { opcode: 0x4C, name: "JMP", handler: CPU::handle_jmp, addressing_mode: AddressingMode::Absolute, bytes: 3, cycles: 3 },
+    Operand { opcode: 0x6C, name: "JMP", handler: CPU::handle_jmp, addressing_mode: AddressingMode::Indirect, bytes: 3, cycles: 5 },
 
     // JSR Instructions
-    0x20u8 => Operand { opcode: 0x20, name: "JSR", handler: CPU::handle_jsr, addressing_mode: AddressingMode::Absolute, bytes: 3, cycles: 6  },
+    Operand { opcode: 0x20, name: "JSR", handler: CPU::handle_jsr, addressing_mode: AddressingMode::Absolute, bytes: 3, cycles: 6  },
 
     // LDA Instructions
-    0xA9u8 => Operand { opcode: 0xA9, name: "LDA", handler: CPU::handle_lda, addressing_mode: AddressingMode::Immediate, bytes: 2, cycles: 2 },
-    0xA5u8 => Operand { opcode: 0xA5, name: "LDA", handler: CPU::handle_lda, addressing_mode: AddressingMode::ZeroPage, bytes: 2, cycles: 3 },
-    0xB5u8 => Operand { opcode: 0xB5, name: "LDA", handler: CPU::handle_lda, addressing_mode: AddressingMode::ZeroPageX, bytes: 2, cycles: 4 },
-    0xADu8 => Operand { opcode: 0xAD, name: "LDA", handler: CPU::handle_lda, addressing_mode: AddressingMode::Absolute, bytes: 3, cycles: 4 },
-    0xBDu8 => Operand { opcode: 0xBD, name: "LDA", handler: CPU::handle_lda, addressing_mode: AddressingMode::AbsoluteX, bytes: 3, cycles: 4 /* +1 if page crossed */ },
-    0xB9u8 => Operand { opcode: 0xB9, name: "LDA", handler: CPU::handle_lda, addressing_mode: AddressingMode::AbsoluteY, bytes: 3, cycles: 4 /* +1 if page crossed */ },
-    0xA1u8 => Operand { opcode: 0xA1, name: "LDA", handler: CPU::handle_lda, addressing_mode: AddressingMode::IndirectX, bytes: 2, cycles: 6 },
-    0xB1u8 => Operand { opcode: 0xB1, name: "LDA", handler: CPU::handle_lda, addressing_mode: AddressingMode::IndirectY, bytes: 2, cycles: 5 /* +1 if page crossed */  },
+    Operand { opcode: 0xA9, name: "LDA", handler: CPU::handle_lda, addressing_mode: AddressingMode::Immediate, bytes: 2, cycles: 2 },
+    Operand { opcode: 0xA5, name: "LDA", handler: CPU::handle_lda, addressing_mode: AddressingMode::ZeroPage, bytes: 2, cycles: 3 },
+    Operand { opcode: 0xB5, name: "LDA", handler: CPU::handle_lda, addressing_mode: AddressingMode::ZeroPageX, bytes: 2, cycles: 4 },
+    Operand { opcode: 0xAD, name: "LDA", handler: CPU::handle_lda, addressing_mode: AddressingMode::Absolute, bytes: 3, cycles: 4 },
+    Operand { opcode: 0xBD, name: "LDA", handler: CPU::handle_lda, addressing_mode: AddressingMode::AbsoluteX, bytes: 3, cycles: 4 /* +1 if page crossed */ },
+    Operand { opcode: 0xB9, name: "LDA", handler: CPU::handle_lda, addressing_mode: AddressingMode::AbsoluteY, bytes: 3, cycles: 4 /* +1 if page crossed */ },
+    Operand { opcode: 0xA1, name: "LDA", handler: CPU::handle_lda, addressing_mode: AddressingMode::IndirectX, bytes: 2, cycles: 6 },
+    Operand { opcode: 0xB1, name: "LDA", handler: CPU::handle_lda, addressing_mode: AddressingMode::IndirectY, bytes: 2, cycles: 5 /* +1 if page crossed */  },
 
     // LDX Instructions
-    0xA2u8 => Operand { opcode: 0xA2, name: "LDX", handler: CPU::handle_ldx, addressing_mode: AddressingMode::Immediate, bytes: 2, cycles: 2 },
-    0xA6u8 => Operand { opcode: 0xA6, name: "LDX", handler: CPU::handle_ldx, addressing_mode: AddressingMode::ZeroPage, bytes: 2, cycles: 3 },
-    0xB6u8 => Operand { opcode: 0xB6, name: "LDX", handler: CPU::handle_ldx, addressing_mode: AddressingMode::ZeroPageY, bytes: 2, cycles: 4 },
-    0xAEu8 => Operand { opcode: 0xAE, name: "LDX", handler: CPU::handle_ldx, addressing_mode: AddressingMode::Absolute, bytes: 3, cycles: 4 },
-    0xBEu8 => Operand { opcode: 0xBE, name: "LDX", handler: CPU::handle_ldx, addressing_mode: AddressingMode::AbsoluteY, bytes: 3, cycles: 4 /* +1 if page crossed */  },
+    Operand { opcode: 0xA2, name: "LDX", handler: CPU::handle_ldx, addressing_mode: AddressingMode::Immediate, bytes: 2, cycles: 2 },
+    Operand { opcode: 0xA6, name: "LDX", handler: CPU::handle_ldx, addressing_mode: AddressingMode::ZeroPage, bytes: 2, cycles: 3 },
+    Operand { opcode: 0xB6, name: "LDX", handler: CPU::handle_ldx, addressing_mode: AddressingMode::ZeroPageY, bytes: 2, cycles: 4 },
+    Operand { opcode: 0xAE, name: "LDX", handler: CPU::handle_ldx, addressing_mode: AddressingMode::Absolute, bytes: 3, cycles: 4 },
+    Operand { opcode: 0xBE, name: "LDX", handler: CPU::handle_ldx, addressing_mode: AddressingMode::AbsoluteY, bytes: 3, cycles: 4 /* +1 if page crossed */  },
 
     // LDY Instructions
-    0xA0u8 => Operand { opcode: 0xA0, name: "LDY", handler: CPU::handle_ldy, addressing_mode: AddressingMode::Immediate, bytes: 2, cycles: 2 },
-    0xA4u8 => Operand { opcode: 0xA4, name: "LDY", handler: CPU::handle_ldy, addressing_mode: AddressingMode::ZeroPage, bytes: 2, cycles: 3 },
-    0xB4u8 => Operand { opcode: 0xB4, name: "LDY", handler: CPU::handle_ldy, addressing_mode: AddressingMode::ZeroPageX, bytes: 2, cycles: 4 },
-    0xACu8 => Operand { opcode: 0xAC, name: "LDY", handler: CPU::handle_ldy, addressing_mode: AddressingMode::Absolute, bytes: 3, cycles: 4 },
-    0xBCu8 => Operand { opcode: 0xBC, name: "LDY", handler: CPU::handle_ldy, addressing_mode: AddressingMode::AbsoluteX, bytes: 3, cycles: 4 /* +1 if page crossed */  },
+    Operand { opcode: 0xA0, name: "LDY", handler: CPU::handle_ldy, addressing_mode: AddressingMode::Immediate, bytes: 2, cycles: 2 },
+    Operand { opcode: 0xA4, name: "LDY", handler: CPU::handle_ldy, addressing_mode: AddressingMode::ZeroPage, bytes: 2, cycles: 3 },
+    Operand { opcode: 0xB4, name: "LDY", handler: CPU::handle_ldy, addressing_mode: AddressingMode::ZeroPageX, bytes: 2, cycles: 4 },
+    Operand { opcode: 0xAC, name: "LDY", handler: CPU::handle_ldy, addressing_mode: AddressingMode::Absolute, bytes: 3, cycles: 4 },
+    Operand { opcode: 0xBC, name: "LDY", handler: CPU::handle_ldy, addressing_mode: AddressingMode::AbsoluteX, bytes: 3, cycles: 4 /* +1 if page crossed */  },
 
     // LSR Instructions
-    0x4Au8 => Operand { opcode: 0x4A, name: "LSR", handler: CPU::handle_lsr, addressing_mode: AddressingMode::Accumulator, bytes: 1, cycles: 2 },
-    0x46u8 => Operand { opcode: 0x46, name: "LSR", handler: CPU::handle_lsr, addressing_mode: AddressingMode::ZeroPage, bytes: 2, cycles: 5 },
-    0x56u8 => Operand { opcode: 0x56, name: "LSR", handler: CPU::handle_lsr, addressing_mode: AddressingMode::ZeroPageX, bytes: 2, cycles: 6 },
-    0x4Eu8 => Operand { opcode: 0x4E, name: "LSR", handler: CPU::handle_lsr, addressing_mode: AddressingMode::Absolute, bytes: 3, cycles: 6 },
-    0x5Eu8 => Operand { opcode: 0x5E, name: "LSR", handler: CPU::handle_lsr, addressing_mode: AddressingMode::AbsoluteX, bytes: 3, cycles: 7 },
+    Operand { opcode: 0x4A, name: "LSR", handler: CPU::handle_lsr, addressing_mode: AddressingMode::Accumulator, bytes: 1, cycles: 2 },
+    Operand { opcode: 0x46, name: "LSR", handler: CPU::handle_lsr, addressing_mode: AddressingMode::ZeroPage, bytes: 2, cycles: 5 },
+    Operand { opcode: 0x56, name: "LSR", handler: CPU::handle_lsr, addressing_mode: AddressingMode::ZeroPageX, bytes: 2, cycles: 6 },
+    Operand { opcode: 0x4E, name: "LSR", handler: CPU::handle_lsr, addressing_mode: AddressingMode::Absolute, bytes: 3, cycles: 6 },
+    Operand { opcode: 0x5E, name: "LSR", handler: CPU::handle_lsr, addressing_mode: AddressingMode::AbsoluteX, bytes: 3, cycles: 7 },
 
     // NOP Instructions
-    0xEAu8 => Operand { opcode: 0xEA, name: "NOP", handler: CPU::handle_nop, addressing_mode: AddressingMode::Implicit, bytes: 1, cycles: 2 },
+    Operand { opcode: 0xEA, name: "NOP", handler: CPU::handle_nop, addressing_mode: AddressingMode::Implicit, bytes: 1, cycles: 2 },
 
     // ORA Instructions
-    0x09u8 => Operand { opcode: 0x09, name: "ORA", handler: CPU::handle_ora, addressing_mode: AddressingMode::Immediate, bytes: 2, cycles: 2 },
-    0x05u8 => Operand { opcode: 0x05, name: "ORA", handler: CPU::handle_ora, addressing_mode: AddressingMode::ZeroPage, bytes: 2, cycles: 3 },
-    0x15u8 => Operand { opcode: 0x15, name: "ORA", handler: CPU::handle_ora, addressing_mode: AddressingMode::ZeroPageX, bytes: 2, cycles: 4 },
-    0x0Du8 => Operand { opcode: 0x0D, name: "ORA", handler: CPU::handle_ora, addressing_mode: AddressingMode::Absolute, bytes: 3, cycles: 4 },
-    0x1Du8 => Operand { opcode: 0x1D, name: "ORA", handler: CPU::handle_ora, addressing_mode: AddressingMode::AbsoluteX, bytes: 3, cycles: 4 /* +1 if page crossed */ },
-    0x19u8 => Operand { opcode: 0x19, name: "ORA", handler: CPU::handle_ora, addressing_mode: AddressingMode::AbsoluteY, bytes: 3, cycles: 4 /* +1 if page crossed */ },
-    0x01u8 => Operand { opcode: 0x01, name: "ORA", handler: CPU::handle_ora, addressing_mode: AddressingMode::IndirectX, bytes: 2, cycles: 6 },
-    0x11u8 => Operand { opcode: 0x11, name: "ORA", handler: CPU::handle_ora, addressing_mode: AddressingMode::IndirectY, bytes: 2, cycles: 5 /* +1 if page crossed */  },
+    Operand { opcode: 0x09, name: "ORA", handler: CPU::handle_ora, addressing_mode: AddressingMode::Immediate, bytes: 2, cycles: 2 },
+    Operand { opcode: 0x05, name: "ORA", handler: CPU::handle_ora, addressing_mode: AddressingMode::ZeroPage, bytes: 2, cycles: 3 },
+    Operand { opcode: 0x15, name: "ORA", handler: CPU::handle_ora, addressing_mode: AddressingMode::ZeroPageX, bytes: 2, cycles: 4 },
+    Operand { opcode: 0x0D, name: "ORA", handler: CPU::handle_ora, addressing_mode: AddressingMode::Absolute, bytes: 3, cycles: 4 },
+    Operand { opcode: 0x1D, name: "ORA", handler: CPU::handle_ora, addressing_mode: AddressingMode::AbsoluteX, bytes: 3, cycles: 4 /* +1 if page crossed */ },
+    Operand { opcode: 0x19, name: "ORA", handler: CPU::handle_ora, addressing_mode: AddressingMode::AbsoluteY, bytes: 3, cycles: 4 /* +1 if page crossed */ },
+    Operand { opcode: 0x01, name: "ORA", handler: CPU::handle_ora, addressing_mode: AddressingMode::IndirectX, bytes: 2, cycles: 6 },
+    Operand { opcode: 0x11, name: "ORA", handler: CPU::handle_ora, addressing_mode: AddressingMode::IndirectY, bytes: 2, cycles: 5 /* +1 if page crossed */  },
 
     // PHA Instructions
-    0x48u8 => Operand { opcode: 0x48, name: "PHA", handler: CPU::handle_pha, addressing_mode: AddressingMode::Implicit, bytes: 1, cycles: 3  },
+    Operand { opcode: 0x48, name: "PHA", handler: CPU::handle_pha, addressing_mode: AddressingMode::Implicit, bytes: 1, cycles: 3  },
 
     // PHP Instructions
-    0x08u8 => Operand { opcode: 0x08, name: "PHP", handler: CPU::handle_php, addressing_mode: AddressingMode::Implicit, bytes: 1, cycles: 3  },
+    Operand { opcode: 0x08, name: "PHP", handler: CPU::handle_php, addressing_mode: AddressingMode::Implicit, bytes: 1, cycles: 3  },
 
     // PLA Instructions
-    0x68u8 => Operand { opcode: 0x68, name: "PLA", handler: CPU::handle_pla, addressing_mode: AddressingMode::Implicit, bytes: 1, cycles: 4  },
+    Operand { opcode: 0x68, name: "PLA", handler: CPU::handle_pla, addressing_mode: AddressingMode::Implicit, bytes: 1, cycles: 4  },
 
     // PLP Instructions
-    0x28u8 => Operand { opcode: 0x28, name: "PLP", handler: CPU::handle_plp, addressing_mode: AddressingMode::Implicit, bytes: 1, cycles: 4  },
+    Operand { opcode: 0x28, name: "PLP", handler: CPU::handle_plp, addressing_mode: AddressingMode::Implicit, bytes: 1, cycles: 4  },
 
     // ROL Instructions
-    0x2Au8 => Operand { opcode: 0x2A, name: "ROL", handler: CPU::handle_rol, addressing_mode: AddressingMode::Accumulator, bytes: 1, cycles: 2 },
-    0x26u8 => Operand { opcode: 0x26, name: "ROL", handler: CPU::handle_rol, addressing_mode: AddressingMode::ZeroPage, bytes: 2, cycles: 5 },
-    0x36u8 => Operand { opcode: 0x36, name: "ROL", handler: CPU::handle_rol, addressing_mode: AddressingMode::ZeroPageX, bytes: 2, cycles: 6 },
-    0x2Eu8 => Operand { opcode: 0x2E, name: "ROL", handler: CPU::handle_rol, addressing_mode: AddressingMode::Absolute, bytes: 3, cycles: 6 },
-    0x3Eu8 => Operand { opcode: 0x3E, name: "ROL", handler: CPU::handle_rol, addressing_mode: AddressingMode::AbsoluteX, bytes: 3, cycles: 7 },
+    Operand { opcode: 0x2A, name: "ROL", handler: CPU::handle_rol, addressing_mode: AddressingMode::Accumulator, bytes: 1, cycles: 2 },
+    Operand { opcode: 0x26, name: "ROL", handler: CPU::handle_rol, addressing_mode: AddressingMode::ZeroPage, bytes: 2, cycles: 5 },
+    Operand { opcode: 0x36, name: "ROL", handler: CPU::handle_rol, addressing_mode: AddressingMode::ZeroPageX, bytes: 2, cycles: 6 },
+    Operand { opcode: 0x2E, name: "ROL", handler: CPU::handle_rol, addressing_mode: AddressingMode::Absolute, bytes: 3, cycles: 6 },
+    Operand { opcode: 0x3E, name: "ROL", handler: CPU::handle_rol, addressing_mode: AddressingMode::AbsoluteX, bytes: 3, cycles: 7 },
 
     // ROR Instructions
-    0x6Au8 => Operand { opcode: 0x6A, name: "ROR", handler: CPU::handle_ror, addressing_mode: AddressingMode::Accumulator, bytes: 1, cycles: 2 },
-    0x66u8 => Operand { opcode: 0x66, name: "ROR", handler: CPU::handle_ror, addressing_mode: AddressingMode::ZeroPage, bytes: 2, cycles: 5 },
-    0x76u8 => Operand { opcode: 0x76, name: "ROR", handler: CPU::handle_ror, addressing_mode: AddressingMode::ZeroPageX, bytes: 2, cycles: 6 },
-    0x6Eu8 => Operand { opcode: 0x6E, name: "ROR", handler: CPU::handle_ror, addressing_mode: AddressingMode::Absolute, bytes: 3, cycles: 6 },
-    0x7Eu8 => Operand { opcode: 0x7E, name: "ROR", handler: CPU::handle_ror, addressing_mode: AddressingMode::AbsoluteX, bytes: 3, cycles: 7 },
+    Operand { opcode: 0x6A, name: "ROR", handler: CPU::handle_ror, addressing_mode: AddressingMode::Accumulator, bytes: 1, cycles: 2 },
+    Operand { opcode: 0x66, name: "ROR", handler: CPU::handle_ror, addressing_mode: AddressingMode::ZeroPage, bytes: 2, cycles: 5 },
+    Operand { opcode: 0x76, name: "ROR", handler: CPU::handle_ror, addressing_mode: AddressingMode::ZeroPageX, bytes: 2, cycles: 6 },
+    Operand { opcode: 0x6E, name: "ROR", handler: CPU::handle_ror, addressing_mode: AddressingMode::Absolute, bytes: 3, cycles: 6 },
+    Operand { opcode: 0x7E, name: "ROR", handler: CPU::handle_ror, addressing_mode: AddressingMode::AbsoluteX, bytes: 3, cycles: 7 },
 
     // RTI Instructions
-    0x40u8 => Operand { opcode: 0x40, name: "RTI", handler: CPU::handle_rti, addressing_mode: AddressingMode::Implicit, bytes: 1, cycles: 6 },
+    Operand { opcode: 0x40, name: "RTI", handler: CPU::handle_rti, addressing_mode: AddressingMode::Implicit, bytes: 1, cycles: 6 },
 
     // RTS Instructions
-    0x60u8 => Operand { opcode: 0x60, name: "RTS", handler: CPU::handle_rts, addressing_mode: AddressingMode::Implicit, bytes: 1, cycles: 6 },
+    Operand { opcode: 0x60, name: "RTS", handler: CPU::handle_rts, addressing_mode: AddressingMode::Implicit, bytes: 1, cycles: 6 },
 
     // SBC Instructions
-    0xE9u8 => Operand { opcode: 0xE9, name: "SBC", handler: CPU::handle_sbc, addressing_mode: AddressingMode::Immediate, bytes: 2, cycles: 2 },
-    0xE5u8 => Operand { opcode: 0xE5, name: "SBC", handler: CPU::handle_sbc, addressing_mode: AddressingMode::ZeroPage, bytes: 2, cycles: 3 },
-    0xF5u8 => Operand { opcode: 0xF5, name: "SBC", handler: CPU::handle_sbc, addressing_mode: AddressingMode::ZeroPageX, bytes: 2, cycles: 4 },
-    0xEDu8 => Operand { opcode: 0xED, name: "SBC", handler: CPU::handle_sbc, addressing_mode: AddressingMode::Absolute, bytes: 3, cycles: 4 },
-    0xFDu8 => Operand { opcode: 0xFD, name: "SBC", handler: CPU::handle_sbc, addressing_mode: AddressingMode::AbsoluteX, bytes: 3, cycles: 4 /* +1 if page crossed */ },
-    0xF9u8 => Operand { opcode: 0xF9, name: "SBC", handler: CPU::handle_sbc, addressing_mode: AddressingMode::AbsoluteY, bytes: 3, cycles: 4 /* +1 if page crossed */ },
-    0xE1u8 => Operand { opcode: 0xE1, name: "SBC", handler: CPU::handle_sbc, addressing_mode: AddressingMode::IndirectX, bytes: 2, cycles: 6 },
-    0xF1u8 => Operand { opcode: 0xF1, name: "SBC", handler: CPU::handle_sbc, addressing_mode: AddressingMode::IndirectY, bytes: 2, cycles: 5 /* +1 if page crossed */ },
+    Operand { opcode: 0xE9, name: "SBC", handler: CPU::handle_sbc, addressing_mode: AddressingMode::Immediate, bytes: 2, cycles: 2 },
+    Operand { opcode: 0xE5, name: "SBC", handler: CPU::handle_sbc, addressing_mode: AddressingMode::ZeroPage, bytes: 2, cycles: 3 },
+    Operand { opcode: 0xF5, name: "SBC", handler: CPU::handle_sbc, addressing_mode: AddressingMode::ZeroPageX, bytes: 2, cycles: 4 },
+    Operand { opcode: 0xED, name: "SBC", handler: CPU::handle_sbc, addressing_mode: AddressingMode::Absolute, bytes: 3, cycles: 4 },
+    Operand { opcode: 0xFD, name: "SBC", handler: CPU::handle_sbc, addressing_mode: AddressingMode::AbsoluteX, bytes: 3, cycles: 4 /* +1 if page crossed */ },
+    Operand { opcode: 0xF9, name: "SBC", handler: CPU::handle_sbc, addressing_mode: AddressingMode::AbsoluteY, bytes: 3, cycles: 4 /* +1 if page crossed */ },
+    Operand { opcode: 0xE1, name: "SBC", handler: CPU::handle_sbc, addressing_mode: AddressingMode::IndirectX, bytes: 2, cycles: 6 },
+    Operand { opcode: 0xF1, name: "SBC", handler: CPU::handle_sbc, addressing_mode: AddressingMode::IndirectY, bytes: 2, cycles: 5 /* +1 if page crossed */ },
 
     // SEC Instructions
-    0x38u8 => Operand { opcode: 0x38, name: "SEC", handler: CPU::handle_sec, addressing_mode: AddressingMode::Implicit, bytes: 1, cycles: 2  },
+    Operand { opcode: 0x38, name: "SEC", handler: CPU::handle_sec, addressing_mode: AddressingMode::Implicit, bytes: 1, cycles: 2  },
 
     // SED Instructions
-    0xF8u8 => Operand { opcode: 0xF8, name: "SED", handler: CPU::handle_sed, addressing_mode: AddressingMode::Implicit, bytes: 1, cycles: 2  },
+    Operand { opcode: 0xF8, name: "SED", handler: CPU::handle_sed, addressing_mode: AddressingMode::Implicit, bytes: 1, cycles: 2  },
 
     // SEI Instructions
-    0x78u8 => Operand { opcode: 0x78, name: "SEI", handler: CPU::handle_sei, addressing_mode: AddressingMode::Implicit, bytes: 1, cycles: 2  },
+    Operand { opcode: 0x78, name: "SEI", handler: CPU::handle_sei, addressing_mode: AddressingMode::Implicit, bytes: 1, cycles: 2  },
 
     // STA Instructions
-    0x85u8 => Operand { opcode: 0x85, name: "STA", handler: CPU::handle_sta, addressing_mode: AddressingMode::ZeroPage, bytes: 2, cycles: 3 },
-    0x95u8 => Operand { opcode: 0x95, name: "STA", handler: CPU::handle_sta, addressing_mode: AddressingMode::ZeroPageX, bytes: 2, cycles: 4 },
-    0x8Du8 => Operand { opcode: 0x8D, name: "STA", handler: CPU::handle_sta, addressing_mode: AddressingMode::Absolute, bytes: 3, cycles: 4 },
-    0x9Du8 => Operand { opcode: 0x9D, name: "STA", handler: CPU::handle_sta, addressing_mode: AddressingMode::AbsoluteX, bytes: 3, cycles: 5 },
-    0x99u8 => Operand { opcode: 0x99, name: "STA", handler: CPU::handle_sta, addressing_mode: AddressingMode::AbsoluteY, bytes: 3, cycles: 5 },
-    0x81u8 => Operand { opcode: 0x81, name: "STA", handler: CPU::handle_sta, addressing_mode: AddressingMode::IndirectX, bytes: 2, cycles: 6 },
-    0x91u8 => Operand { opcode: 0x91, name: "STA", handler: CPU::handle_sta, addressing_mode: AddressingMode::IndirectY, bytes: 2, cycles: 6 },
+    Operand { opcode: 0x85, name: "STA", handler: CPU::handle_sta, addressing_mode: AddressingMode::ZeroPage, bytes: 2, cycles: 3 },
+    Operand { opcode: 0x95, name: "STA", handler: CPU::handle_sta, addressing_mode: AddressingMode::ZeroPageX, bytes: 2, cycles: 4 },
+    Operand { opcode: 0x8D, name: "STA", handler: CPU::handle_sta, addressing_mode: AddressingMode::Absolute, bytes: 3, cycles: 4 },
+    Operand { opcode: 0x9D, name: "STA", handler: CPU::handle_sta, addressing_mode: AddressingMode::AbsoluteX, bytes: 3, cycles: 5 },
+    Operand { opcode: 0x99, name: "STA", handler: CPU::handle_sta, addressing_mode: AddressingMode::AbsoluteY, bytes: 3, cycles: 5 },
+    Operand { opcode: 0x81, name: "STA", handler: CPU::handle_sta, addressing_mode: AddressingMode::IndirectX, bytes: 2, cycles: 6 },
+    Operand { opcode: 0x91, name: "STA", handler: CPU::handle_sta, addressing_mode: AddressingMode::IndirectY, bytes: 2, cycles: 6 },
 
     // STX Instructions
-    0x86u8 => Operand { opcode: 0x86, name: "STX", handler: CPU::handle_stx, addressing_mode: AddressingMode::ZeroPage, bytes: 2, cycles: 3 },
-    0x96u8 => Operand { opcode: 0x96, name: "STX", handler: CPU::handle_stx, addressing_mode: AddressingMode::ZeroPageY, bytes: 2, cycles: 4 },
-    0x8Eu8 => Operand { opcode: 0x8E, name: "STX", handler: CPU::handle_stx, addressing_mode: AddressingMode::Absolute, bytes: 3, cycles: 4 },
+    Operand { opcode: 0x86, name: "STX", handler: CPU::handle_stx, addressing_mode: AddressingMode::ZeroPage, bytes: 2, cycles: 3 },
+    Operand { opcode: 0x96, name: "STX", handler: CPU::handle_stx, addressing_mode: AddressingMode::ZeroPageY, bytes: 2, cycles: 4 },
+    Operand { opcode: 0x8E, name: "STX", handler: CPU::handle_stx, addressing_mode: AddressingMode::Absolute, bytes: 3, cycles: 4 },
 
     // STY Instructions
-    0x84u8 => Operand { opcode: 0x84, name: "STY", handler: CPU::handle_sty, addressing_mode: AddressingMode::ZeroPage, bytes: 2, cycles: 3 },
-    0x94u8 => Operand { opcode: 0x94, name: "STY", handler: CPU::handle_sty, addressing_mode: AddressingMode::ZeroPageX, bytes: 2, cycles: 4 },
-    0x8Cu8 => Operand { opcode: 0x8C, name: "STY", handler: CPU::handle_sty, addressing_mode: AddressingMode::Absolute, bytes: 3, cycles: 4 },
+    Operand { opcode: 0x84, name: "STY", handler: CPU::handle_sty, addressing_mode: AddressingMode::ZeroPage, bytes: 2, cycles: 3 },
+    Operand { opcode: 0x94, name: "STY", handler: CPU::handle_sty, addressing_mode: AddressingMode::ZeroPageX, bytes: 2, cycles: 4 },
+    Operand { opcode: 0x8C, name: "STY", handler: CPU::handle_sty, addressing_mode: AddressingMode::Absolute, bytes: 3, cycles: 4 },
 
     // TAX Instructions
-    0xAAu8 => Operand { opcode: 0xAA, name: "TAX", handler: CPU::handle_tax, addressing_mode: AddressingMode::Implicit, bytes: 1, cycles: 2  },
+    Operand { opcode: 0xAA, name: "TAX", handler: CPU::handle_tax, addressing_mode: AddressingMode::Implicit, bytes: 1, cycles: 2  },
 
     // TAY Instructions
-    0xA8u8 => Operand { opcode: 0xA8, name: "TAY", handler: CPU::handle_tay, addressing_mode: AddressingMode::Implicit, bytes: 1, cycles: 2  },
+    Operand { opcode: 0xA8, name: "TAY", handler: CPU::handle_tay, addressing_mode: AddressingMode::Implicit, bytes: 1, cycles: 2  },
 
     // TSX Instructions
-    0xBAu8 => Operand { opcode: 0xBA, name: "TSX", handler: CPU::handle_tsx, addressing_mode: AddressingMode::Implicit, bytes: 1, cycles: 2  },
+    Operand { opcode: 0xBA, name: "TSX", handler: CPU::handle_tsx, addressing_mode: AddressingMode::Implicit, bytes: 1, cycles: 2  },
 
     // TXA Instructions
-    0x8Au8 => Operand { opcode: 0x8A, name: "TXA", handler: CPU::handle_txa, addressing_mode: AddressingMode::Implicit, bytes: 1, cycles: 2  },
+    Operand { opcode: 0x8A, name: "TXA", handler: CPU::handle_txa, addressing_mode: AddressingMode::Implicit, bytes: 1, cycles: 2  },
 
     // TXS Instructions
-    0x9Au8 => Operand { opcode: 0x9A, name: "TXS", handler: CPU::handle_txs, addressing_mode: AddressingMode::Implicit, bytes: 1, cycles: 2  },
+    Operand { opcode: 0x9A, name: "TXS", handler: CPU::handle_txs, addressing_mode: AddressingMode::Implicit, bytes: 1, cycles: 2  },
 
     // TYA Instructions
-    0x98u8 => Operand { opcode: 0x98, name: "TYA", handler: CPU::handle_tya, addressing_mode: AddressingMode::Implicit, bytes: 1, cycles: 2  },
+    Operand { opcode: 0x98, name: "TYA", handler: CPU::handle_tya, addressing_mode: AddressingMode::Implicit, bytes: 1, cycles: 2  },
 
     /////// Unofficial Opcode List ///////
     // AAC/ANC Instructions
-    0x0B => Operand { opcode: 0x0B, name: "AAC", handler: CPU::handle_aac, addressing_mode: AddressingMode::Immediate, bytes: 2, cycles: 2  },
-    0x2B => Operand { opcode: 0x2B, name: "AAC", handler: CPU::handle_aac, addressing_mode: AddressingMode::Immediate, bytes: 2, cycles: 2  },
+    Operand { opcode: 0x0B, name: "AAC", handler: CPU::handle_aac, addressing_mode: AddressingMode::Immediate, bytes: 2, cycles: 2  },
+    Operand { opcode: 0x2B, name: "AAC", handler: CPU::handle_aac, addressing_mode: AddressingMode::Immediate, bytes: 2, cycles: 2  },
 
     // AAX/SAX/AXS Instructions
-    0x87u8 => Operand { opcode: 0x87, name: "AAX", handler: CPU::handle_aax, addressing_mode: AddressingMode::ZeroPage, bytes: 2, cycles: 3 },
-    0x97u8 => Operand { opcode: 0x97, name: "AAX", handler: CPU::handle_aax, addressing_mode: AddressingMode::ZeroPageY, bytes: 2, cycles: 4 },
-    0x83u8 => Operand { opcode: 0x83, name: "AAX", handler: CPU::handle_aax, addressing_mode: AddressingMode::IndirectX, bytes: 2, cycles: 6 },
-    0x8Fu8 => Operand { opcode: 0x8F, name: "AAX", handler: CPU::handle_aax, addressing_mode: AddressingMode::Absolute, bytes: 3, cycles: 4 },
+    Operand { opcode: 0x87, name: "AAX", handler: CPU::handle_aax, addressing_mode: AddressingMode::ZeroPage, bytes: 2, cycles: 3 },
+    Operand { opcode: 0x97, name: "AAX", handler: CPU::handle_aax, addressing_mode: AddressingMode::ZeroPageY, bytes: 2, cycles: 4 },
+    Operand { opcode: 0x83, name: "AAX", handler: CPU::handle_aax, addressing_mode: AddressingMode::IndirectX, bytes: 2, cycles: 6 },
+    Operand { opcode: 0x8F, name: "AAX", handler: CPU::handle_aax, addressing_mode: AddressingMode::Absolute, bytes: 3, cycles: 4 },
 
     // AAR
-    0x6Bu8 => Operand { opcode: 0x6B, name: "ARR", handler: CPU::handle_arr, addressing_mode: AddressingMode::Immediate, bytes: 2, cycles: 2 },
+    Operand { opcode: 0x6B, name: "ARR", handler: CPU::handle_arr, addressing_mode: AddressingMode::Immediate, bytes: 2, cycles: 2 },
 
     // ASR/ALR
-    0x4Bu8 => Operand { opcode: 0x4B, name: "ASR", handler: CPU::handle_asr, addressing_mode: AddressingMode::Immediate, bytes: 2, cycles: 2 },
+    Operand { opcode: 0x4B, name: "ASR", handler: CPU::handle_asr, addressing_mode: AddressingMode::Immediate, bytes: 2, cycles: 2 },
 
     // ATX/LXA/OAL
-    0xABu8 => Operand { opcode: 0xAB, name: "ATX", handler: CPU::handle_atx, addressing_mode: AddressingMode::Immediate, bytes: 2, cycles: 2 },
+    Operand { opcode: 0xAB, name: "ATX", handler: CPU::handle_atx, addressing_mode: AddressingMode::Immediate, bytes: 2, cycles: 2 },
 
     // AXA/SHA
-    0x9Fu8 => Operand { opcode: 0x9F, name: "AXA", handler: CPU::handle_axa, addressing_mode: AddressingMode::Immediate, bytes: 3, cycles: 5 },
-    0x93u8 => Operand { opcode: 0x93, name: "AXA", handler: CPU::handle_axa, addressing_mode: AddressingMode::IndirectY, bytes: 2, cycles: 6 },
+    Operand { opcode: 0x9F, name: "AXA", handler: CPU::handle_axa, addressing_mode: AddressingMode::AbsoluteY, bytes: 3, cycles: 5 },
+    Operand { opcode: 0x93, name: "AXA", handler: CPU::handle_axa, addressing_mode: AddressingMode::IndirectY, bytes: 2, cycles: 6 },
 
     // AXS/SBX/SAX
-    0xCBu8 => Operand { opcode: 0xCB, name: "AXS", handler: CPU::handle_axs, addressing_mode: AddressingMode::Immediate, bytes: 2, cycles: 2 },
+    Operand { opcode: 0xCB, name: "AXS", handler: CPU::handle_axs, addressing_mode: AddressingMode::Immediate, bytes: 2, cycles: 2 },
 
     // DCP/DCM
-    0xC7u8 => Operand { opcode: 0xC7, name: "DCP", handler: CPU::handle_dcp, addressing_mode: AddressingMode::ZeroPage, bytes: 2, cycles: 5 },
-    0xD7u8 => Operand { opcode: 0xD7, name: "DCP", handler: CPU::handle_dcp, addressing_mode: AddressingMode::ZeroPageX, bytes: 2, cycles: 6 },
-    0xCFu8 => Operand { opcode: 0xCF, name: "DCP", handler: CPU::handle_dcp, addressing_mode: AddressingMode::Absolute, bytes: 3, cycles: 6 },
-    0xDFu8 => Operand { opcode: 0xDF, name: "DCP", handler: CPU::handle_dcp, addressing_mode: AddressingMode::AbsoluteX, bytes: 3, cycles: 7 },
-    0xDBu8 => Operand { opcode: 0xDB, name: "DCP", handler: CPU::handle_dcp, addressing_mode: AddressingMode::AbsoluteY, bytes: 3, cycles: 7 },
-    0xC3u8 => Operand { opcode: 0xC3, name: "DCP", handler: CPU::handle_dcp, addressing_mode: AddressingMode::IndirectX, bytes: 2, cycles: 8 },
-    0xD3u8 => Operand { opcode: 0xD3, name: "DCP", handler: CPU::handle_dcp, addressing_mode: AddressingMode::IndirectY, bytes: 2, cycles: 8 },
+    Operand { opcode: 0xC7, name: "DCP", handler: CPU::handle_dcp, addressing_mode: AddressingMode::ZeroPage, bytes: 2, cycles: 5 },
+    Operand { opcode: 0xD7, name: "DCP", handler: CPU::handle_dcp, addressing_mode: AddressingMode::ZeroPageX, bytes: 2, cycles: 6 },
+    Operand { opcode: 0xCF, name: "DCP", handler: CPU::handle_dcp, addressing_mode: AddressingMode::Absolute, bytes: 3, cycles: 6 },
+    Operand { opcode: 0xDF, name: "DCP", handler: CPU::handle_dcp, addressing_mode: AddressingMode::AbsoluteX, bytes: 3, cycles: 7 },
+    Operand { opcode: 0xDB, name: "DCP", handler: CPU::handle_dcp, addressing_mode: AddressingMode::AbsoluteY, bytes: 3, cycles: 7 },
+    Operand { opcode: 0xC3, name: "DCP", handler: CPU::handle_dcp, addressing_mode: AddressingMode::IndirectX, bytes: 2, cycles: 8 },
+    Operand { opcode: 0xD3, name: "DCP", handler: CPU::handle_dcp, addressing_mode: AddressingMode::IndirectY, bytes: 2, cycles: 8 },
 
     // DOP/NOP/SKB
-    0x04u8 => Operand { opcode: 0x04, name: "DOP", handler: CPU::handle_dop, addressing_mode: AddressingMode::ZeroPage, bytes: 2, cycles: 3 },
-    0x14u8 => Operand { opcode: 0x14, name: "DOP", handler: CPU::handle_dop, addressing_mode: AddressingMode::ZeroPageX, bytes: 2, cycles: 4 },
-    0x34u8 => Operand { opcode: 0x34, name: "DOP", handler: CPU::handle_dop, addressing_mode: AddressingMode::ZeroPageX, bytes: 2, cycles: 4 },
-    0x44u8 => Operand { opcode: 0x44, name: "DOP", handler: CPU::handle_dop, addressing_mode: AddressingMode::ZeroPage, bytes: 2, cycles: 3 },
-    0x54u8 => Operand { opcode: 0x54, name: "DOP", handler: CPU::handle_dop, addressing_mode: AddressingMode::ZeroPageX, bytes: 2, cycles: 4 },
-    0x64u8 => Operand { opcode: 0x64, name: "DOP", handler: CPU::handle_dop, addressing_mode: AddressingMode::ZeroPage, bytes: 2, cycles: 3 },
-    0x74u8 => Operand { opcode: 0x74, name: "DOP", handler: CPU::handle_dop, addressing_mode: AddressingMode::ZeroPageX, bytes: 2, cycles: 4 },
-    0x80u8 => Operand { opcode: 0x80, name: "DOP", handler: CPU::handle_dop, addressing_mode: AddressingMode::Immediate, bytes: 2, cycles: 2 },
-    0x82u8 => Operand { opcode: 0x82, name: "DOP", handler: CPU::handle_dop, addressing_mode: AddressingMode::Immediate, bytes: 2, cycles: 2 },
-    0x89u8 => Operand { opcode: 0x89, name: "DOP", handler: CPU::handle_dop, addressing_mode: AddressingMode::Immediate, bytes: 2, cycles: 2 },
-    0xC2u8 => Operand { opcode: 0xC2, name: "DOP", handler: CPU::handle_dop, addressing_mode: AddressingMode::Immediate, bytes: 2, cycles: 2 },
-    0xD4u8 => Operand { opcode: 0xD4, name: "DOP", handler: CPU::handle_dop, addressing_mode: AddressingMode::ZeroPageX, bytes: 2, cycles: 4 },
-    0xE2u8 => Operand { opcode: 0xE2, name: "DOP", handler: CPU::handle_dop, addressing_mode: AddressingMode::Immediate, bytes: 2, cycles: 2 },
-    0xF4u8 => Operand { opcode: 0xF4, name: "DOP", handler: CPU::handle_dop, addressing_mode: AddressingMode::ZeroPageX, bytes: 2, cycles: 4 },
+    Operand { opcode: 0x04, name: "DOP", handler: CPU::handle_dop, addressing_mode: AddressingMode::ZeroPage, bytes: 2, cycles: 3 },
+    Operand { opcode: 0x14, name: "DOP", handler: CPU::handle_dop, addressing_mode: AddressingMode::ZeroPageX, bytes: 2, cycles: 4 },
+    Operand { opcode: 0x34, name: "DOP", handler: CPU::handle_dop, addressing_mode: AddressingMode::ZeroPageX, bytes: 2, cycles: 4 },
+    Operand { opcode: 0x44, name: "DOP", handler: CPU::handle_dop, addressing_mode: AddressingMode::ZeroPage, bytes: 2, cycles: 3 },
+    Operand { opcode: 0x54, name: "DOP", handler: CPU::handle_dop, addressing_mode: AddressingMode::ZeroPageX, bytes: 2, cycles: 4 },
+    Operand { opcode: 0x64, name: "DOP", handler: CPU::handle_dop, addressing_mode: AddressingMode::ZeroPage, bytes: 2, cycles: 3 },
+    Operand { opcode: 0x74, name: "DOP", handler: CPU::handle_dop, addressing_mode: AddressingMode::ZeroPageX, bytes: 2, cycles: 4 },
+    Operand { opcode: 0x80, name: "DOP", handler: CPU::handle_dop, addressing_mode: AddressingMode::Immediate, bytes: 2, cycles: 2 },
+    Operand { opcode: 0x82, name: "DOP", handler: CPU::handle_dop, addressing_mode: AddressingMode::Immediate, bytes: 2, cycles: 2 },
+    Operand { opcode: 0x89, name: "DOP", handler: CPU::handle_dop, addressing_mode: AddressingMode::Immediate, bytes: 2, cycles: 2 },
+    Operand { opcode: 0xC2, name: "DOP", handler: CPU::handle_dop, addressing_mode: AddressingMode::Immediate, bytes: 2, cycles: 2 },
+    Operand { opcode: 0xD4, name: "DOP", handler: CPU::handle_dop, addressing_mode: AddressingMode::ZeroPageX, bytes: 2, cycles: 4 },
+    Operand { opcode: 0xE2, name: "DOP", handler: CPU::handle_dop, addressing_mode: AddressingMode::Immediate, bytes: 2, cycles: 2 },
+    Operand { opcode: 0xF4, name: "DOP", handler: CPU::handle_dop, addressing_mode: AddressingMode::ZeroPageX, bytes: 2, cycles: 4 },
 
     // ISC/ISB/INS
-    0xE7u8 => Operand { opcode: 0xE7, name: "ISC", handler: CPU::handle_isc, addressing_mode: AddressingMode::ZeroPage, bytes: 2, cycles: 5 },
-    0xF7u8 => Operand { opcode: 0xF7, name: "ISC", handler: CPU::handle_isc, addressing_mode: AddressingMode::ZeroPageX, bytes: 2, cycles: 6 },
-    0xEFu8 => Operand { opcode: 0xEF, name: "ISC", handler: CPU::handle_isc, addressing_mode: AddressingMode::Absolute, bytes: 3, cycles: 6 },
-    0xFFu8 => Operand { opcode: 0xFF, name: "ISC", handler: CPU::handle_isc, addressing_mode: AddressingMode::AbsoluteX, bytes: 3, cycles: 7 },
-    0xFBu8 => Operand { opcode: 0xFB, name: "ISC", handler: CPU::handle_isc, addressing_mode: AddressingMode::AbsoluteY, bytes: 3, cycles: 7 },
-    0xE3u8 => Operand { opcode: 0xE3, name: "ISC", handler: CPU::handle_isc, addressing_mode: AddressingMode::IndirectX, bytes: 2, cycles: 8 },
-    0xF3u8 => Operand { opcode: 0xF3, name: "ISC", handler: CPU::handle_isc, addressing_mode: AddressingMode::IndirectY, bytes: 2, cycles: 8 },
+    Operand { opcode: 0xE7, name: "ISC", handler: CPU::handle_isc, addressing_mode: AddressingMode::ZeroPage, bytes: 2, cycles: 5 },
+    Operand { opcode: 0xF7, name: "ISC", handler: CPU::handle_isc, addressing_mode: AddressingMode::ZeroPageX, bytes: 2, cycles: 6 },
+    Operand { opcode: 0xEF, name: "ISC", handler: CPU::handle_isc, addressing_mode: AddressingMode::Absolute, bytes: 3, cycles: 6 },
+    Operand { opcode: 0xFF, name: "ISC", handler: CPU::handle_isc, addressing_mode: AddressingMode::AbsoluteX, bytes: 3, cycles: 7 },
+    Operand { opcode: 0xFB, name: "ISC", handler: CPU::handle_isc, addressing_mode: AddressingMode::AbsoluteY, bytes: 3, cycles: 7 },
+    Operand { opcode: 0xE3, name: "ISC", handler: CPU::handle_isc, addressing_mode: AddressingMode::IndirectX, bytes: 2, cycles: 8 },
+    Operand { opcode: 0xF3, name: "ISC", handler: CPU::handle_isc, addressing_mode: AddressingMode::IndirectY, bytes: 2, cycles: 8 },
 
     // KIL/JAM/HLT
-    0x02u8 => Operand { opcode: 0x02, name: "KIL", handler: CPU::handle_kil, addressing_mode: AddressingMode::Implicit, bytes: 1, cycles: 0 },
-    0x12u8 => Operand { opcode: 0x12, name: "KIL", handler: CPU::handle_kil, addressing_mode: AddressingMode::Implicit, bytes: 1, cycles: 0 },
-    0x22u8 => Operand { opcode: 0x22, name: "KIL", handler: CPU::handle_kil, addressing_mode: AddressingMode::Implicit, bytes: 1, cycles: 0 },
-    0x32u8 => Operand { opcode: 0x32, name: "KIL", handler: CPU::handle_kil, addressing_mode: AddressingMode::Implicit, bytes: 1, cycles: 0 },
-    0x42u8 => Operand { opcode: 0x42, name: "KIL", handler: CPU::handle_kil, addressing_mode: AddressingMode::Implicit, bytes: 1, cycles: 0 },
-    0x52u8 => Operand { opcode: 0x52, name: "KIL", handler: CPU::handle_kil, addressing_mode: AddressingMode::Implicit, bytes : 1, cycles: 0 },
-    0x62u8 => Operand { opcode: 0x62, name: "KIL", handler: CPU::handle_kil, addressing_mode: AddressingMode::Implicit, bytes: 1, cycles: 0 },
-    0x72u8 => Operand { opcode: 0x72, name: "KIL", handler: CPU::handle_kil, addressing_mode: AddressingMode::Implicit, bytes: 1, cycles: 0 },
-    0x92u8 => Operand { opcode: 0x92, name: "KIL", handler: CPU::handle_kil, addressing_mode: AddressingMode::Implicit, bytes: 1, cycles: 0 },
-    0xB2u8 => Operand { opcode: 0xB2, name: "KIL", handler: CPU::handle_kil, addressing_mode: AddressingMode::Implicit, bytes: 1, cycles: 0 },
-    0xD2u8 => Operand { opcode: 0xD2, name: "KIL", handler: CPU::handle_kil, addressing_mode: AddressingMode::Implicit, bytes: 1, cycles: 0 },
-    0xF2u8 => Operand { opcode: 0xF2, name: "KIL", handler: CPU::handle_kil, addressing_mode: AddressingMode::Implicit, bytes: 1, cycles: 0 },
+    Operand { opcode: 0x02, name: "KIL", handler: CPU::handle_kil, addressing_mode: AddressingMode::Implicit, bytes: 1, cycles: 0 },
+    Operand { opcode: 0x12, name: "KIL", handler: CPU::handle_kil, addressing_mode: AddressingMode::Implicit, bytes: 1, cycles: 0 },
+    Operand { opcode: 0x22, name: "KIL", handler: CPU::handle_kil, addressing_mode: AddressingMode::Implicit, bytes: 1, cycles: 0 },
+    Operand { opcode: 0x32, name: "KIL", handler: CPU::handle_kil, addressing_mode: AddressingMode::Implicit, bytes: 1, cycles: 0 },
+    Operand { opcode: 0x42, name: "KIL", handler: CPU::handle_kil, addressing_mode: AddressingMode::Implicit, bytes: 1, cycles: 0 },
+    Operand { opcode: 0x52, name: "KIL", handler: CPU::handle_kil, addressing_mode: AddressingMode::Implicit, bytes : 1, cycles: 0 },
+    Operand { opcode: 0x62, name: "KIL", handler: CPU::handle_kil, addressing_mode: AddressingMode::Implicit, bytes: 1, cycles: 0 },
+    Operand { opcode: 0x72, name: "KIL", handler: CPU::handle_kil, addressing_mode: AddressingMode::Implicit, bytes: 1, cycles: 0 },
+    Operand { opcode: 0x92, name: "KIL", handler: CPU::handle_kil, addressing_mode: AddressingMode::Implicit, bytes: 1, cycles: 0 },
+    Operand { opcode: 0xB2, name: "KIL", handler: CPU::handle_kil, addressing_mode: AddressingMode::Implicit, bytes: 1, cycles: 0 },
+    Operand { opcode: 0xD2, name: "KIL", handler: CPU::handle_kil, addressing_mode: AddressingMode::Implicit, bytes: 1, cycles: 0 },
+    Operand { opcode: 0xF2, name: "KIL", handler: CPU::handle_kil, addressing_mode: AddressingMode::Implicit, bytes: 1, cycles: 0 },
 
     // LAR/LAE/LAS
-    0xBBu8 => Operand { opcode: 0xBB, name: "LAR", handler: CPU::handle_lar, addressing_mode: AddressingMode::AbsoluteY, bytes: 3, cycles: 4 /* +1 if page crossed */ },
+    Operand { opcode: 0xBB, name: "LAR", handler: CPU::handle_lar, addressing_mode: AddressingMode::AbsoluteY, bytes: 3, cycles: 4 /* +1 if page crossed */ },
 
     // LAX
-    0xA7u8 => Operand { opcode: 0xA7, name: "LAX", handler: CPU::handle_lax, addressing_mode: AddressingMode::ZeroPage, bytes: 2, cycles: 3 },
-    0xB7u8 => Operand { opcode: 0xB7, name: "LAX", handler: CPU::handle_lax, addressing_mode: AddressingMode::ZeroPageY, bytes: 2, cycles: 4 },
-    0xAFu8 => Operand { opcode: 0xAF, name: "LAX", handler: CPU::handle_lax, addressing_mode: AddressingMode::Absolute, bytes: 3, cycles: 4 },
-    0xBFu8 => Operand { opcode: 0xBF, name: "LAX", handler: CPU::handle_lax, addressing_mode: AddressingMode::AbsoluteY, bytes: 3, cycles: 4 /* +1 if page crossed */ },
-    0xA3u8 => Operand { opcode: 0xA3, name: "LAX", handler: CPU::handle_lax, addressing_mode: AddressingMode::IndirectX, bytes: 2, cycles: 6 },
-    0xB3u8 => Operand { opcode: 0xB3, name: "LAX", handler: CPU::handle_lax, addressing_mode: AddressingMode::IndirectY, bytes: 2, cycles: 5 /* +1 if page crossed */ },
+    Operand { opcode: 0xA7, name: "LAX", handler: CPU::handle_lax, addressing_mode: AddressingMode::ZeroPage, bytes: 2, cycles: 3 },
+    Operand { opcode: 0xB7, name: "LAX", handler: CPU::handle_lax, addressing_mode: AddressingMode::ZeroPageY, bytes: 2, cycles: 4 },
+    Operand { opcode: 0xAF, name: "LAX", handler: CPU::handle_lax, addressing_mode: AddressingMode::Absolute, bytes: 3, cycles: 4 },
+    Operand { opcode: 0xBF, name: "LAX", handler: CPU::handle_lax, addressing_mode: AddressingMode::AbsoluteY, bytes: 3, cycles: 4 /* +1 if page crossed */ },
+    Operand { opcode: 0xA3, name: "LAX", handler: CPU::handle_lax, addressing_mode: AddressingMode::IndirectX, bytes: 2, cycles: 6 },
+    Operand { opcode: 0xB3, name: "LAX", handler: CPU::handle_lax, addressing_mode: AddressingMode::IndirectY, bytes: 2, cycles: 5 /* +1 if page crossed */ },
 
     // NOP
-    0x1Au8 => Operand { opcode: 0x1A, name: "NOP", handler: CPU::handle_nop, addressing_mode: AddressingMode::Implicit, bytes: 1, cycles: 2 },
-    0x3Au8 => Operand { opcode: 0x3A, name: "NOP", handler: CPU::handle_nop, addressing_mode: AddressingMode::Implicit, bytes: 1, cycles: 2 },
-    0x5Au8 => Operand { opcode: 0x5A, name: "NOP", handler: CPU::handle_nop, addressing_mode: AddressingMode::Implicit, bytes: 1, cycles: 2 },
-    0x7Au8 => Operand { opcode: 0x7A, name: "NOP", handler: CPU::handle_nop, addressing_mode: AddressingMode::Implicit, bytes: 1, cycles: 2 },
-    0xDAu8 => Operand { opcode: 0xDA, name: "NOP", handler: CPU::handle_nop, addressing_mode: AddressingMode::Implicit, bytes: 1, cycles: 2 },
-    0xFAu8 => Operand { opcode: 0xFA, name: "NOP", handler: CPU::handle_nop, addressing_mode: AddressingMode::Implicit, bytes: 1, cycles: 2 },
+    Operand { opcode: 0x1A, name: "NOP", handler: CPU::handle_nop, addressing_mode: AddressingMode::Implicit, bytes: 1, cycles: 2 },
+    Operand { opcode: 0x3A, name: "NOP", handler: CPU::handle_nop, addressing_mode: AddressingMode::Implicit, bytes: 1, cycles: 2 },
+    Operand { opcode: 0x5A, name: "NOP", handler: CPU::handle_nop, addressing_mode: AddressingMode::Implicit, bytes: 1, cycles: 2 },
+    Operand { opcode: 0x7A, name: "NOP", handler: CPU::handle_nop, addressing_mode: AddressingMode::Implicit, bytes: 1, cycles: 2 },
+    Operand { opcode: 0xDA, name: "NOP", handler: CPU::handle_nop, addressing_mode: AddressingMode::Implicit, bytes: 1, cycles: 2 },
+    Operand { opcode: 0xFA, name: "NOP", handler: CPU::handle_nop, addressing_mode: AddressingMode::Implicit, bytes: 1, cycles: 2 },
 
     // RLA
-    0x27u8 => Operand { opcode: 0x27, name: "RLA", handler: CPU::handle_rla, addressing_mode: AddressingMode::ZeroPage, bytes: 2, cycles: 5 },
-    0x37u8 => Operand { opcode: 0x37, name: "RLA", handler: CPU::handle_rla, addressing_mode: AddressingMode::ZeroPageX, bytes: 2, cycles: 6 },
-    0x2Fu8 => Operand { opcode: 0x2F, name: "RLA", handler: CPU::handle_rla, addressing_mode: AddressingMode::Absolute, bytes: 3, cycles: 6 },
-    0x3Fu8 => Operand { opcode: 0x3F, name: "RLA", handler: CPU::handle_rla, addressing_mode: AddressingMode::AbsoluteX, bytes: 3, cycles: 7 },
-    0x3Bu8 => Operand { opcode: 0x3B, name: "RLA", handler: CPU::handle_rla, addressing_mode: AddressingMode::AbsoluteY, bytes: 3, cycles: 7 },
-    0x23u8 => Operand { opcode: 0x23, name: "RLA", handler: CPU::handle_rla, addressing_mode: AddressingMode::IndirectX, bytes: 2, cycles: 8 },
-    0x33u8 => Operand { opcode: 0x33, name: "RLA", handler: CPU::handle_rla, addressing_mode: AddressingMode::IndirectY, bytes: 2, cycles: 8 },
+    Operand { opcode: 0x27, name: "RLA", handler: CPU::handle_rla, addressing_mode: AddressingMode::ZeroPage, bytes: 2, cycles: 5 },
+    Operand { opcode: 0x37, name: "RLA", handler: CPU::handle_rla, addressing_mode: AddressingMode::ZeroPageX, bytes: 2, cycles: 6 },
+    Operand { opcode: 0x2F, name: "RLA", handler: CPU::handle_rla, addressing_mode: AddressingMode::Absolute, bytes: 3, cycles: 6 },
+    Operand { opcode: 0x3F, name: "RLA", handler: CPU::handle_rla, addressing_mode: AddressingMode::AbsoluteX, bytes: 3, cycles: 7 },
+    Operand { opcode: 0x3B, name: "RLA", handler: CPU::handle_rla, addressing_mode: AddressingMode::AbsoluteY, bytes: 3, cycles: 7 },
+    Operand { opcode: 0x23, name: "RLA", handler: CPU::handle_rla, addressing_mode: AddressingMode::IndirectX, bytes: 2, cycles: 8 },
+    Operand { opcode: 0x33, name: "RLA", handler: CPU::handle_rla, addressing_mode: AddressingMode::IndirectY, bytes: 2, cycles: 8 },
 
     // RRA
-    0x67u8 => Operand { opcode: 0x67, name: "RRA", handler: CPU::handle_rra, addressing_mode: AddressingMode::ZeroPage, bytes: 2, cycles: 5 },
-    0x77u8 => Operand { opcode: 0x77, name: "RRA", handler: CPU::handle_rra, addressing_mode: AddressingMode::ZeroPageX, bytes: 2, cycles: 6 },
-    0x6Fu8 => Operand { opcode: 0x6F, name: "RRA", handler: CPU::handle_rra, addressing_mode: AddressingMode::Absolute, bytes: 3, cycles: 6 },
-    0x7Fu8 => Operand { opcode: 0x7F, name: "RRA", handler: CPU::handle_rra, addressing_mode: AddressingMode::AbsoluteX, bytes: 3, cycles: 7 },
-    0x7Bu8 => Operand { opcode: 0x7B, name: "RRA", handler: CPU::handle_rra, addressing_mode: AddressingMode::AbsoluteY, bytes: 3, cycles: 7 },
-    0x63u8 => Operand { opcode: 0x63, name: "RRA", handler: CPU::handle_rra, addressing_mode: AddressingMode::IndirectX, bytes: 2, cycles: 8 },
-    0x73u8 => Operand { opcode: 0x73, name: "RRA", handler: CPU::handle_rra, addressing_mode: AddressingMode::IndirectY, bytes: 2, cycles: 8 },
+    Operand { opcode: 0x67, name: "RRA", handler: CPU::handle_rra, addressing_mode: AddressingMode::ZeroPage, bytes: 2, cycles: 5 },
+    Operand { opcode: 0x77, name: "RRA", handler: CPU::handle_rra, addressing_mode: AddressingMode::ZeroPageX, bytes: 2, cycles: 6 },
+    Operand { opcode: 0x6F, name: "RRA", handler: CPU::handle_rra, addressing_mode: AddressingMode::Absolute, bytes: 3, cycles: 6 },
+    Operand { opcode: 0x7F, name: "RRA", handler: CPU::handle_rra, addressing_mode: AddressingMode::AbsoluteX, bytes: 3, cycles: 7 },
+    Operand { opcode: 0x7B, name: "RRA", handler: CPU::handle_rra, addressing_mode: AddressingMode::AbsoluteY, bytes: 3, cycles: 7 },
+    Operand { opcode: 0x63, name: "RRA", handler: CPU::handle_rra, addressing_mode: AddressingMode::IndirectX, bytes: 2, cycles: 8 },
+    Operand { opcode: 0x73, name: "RRA", handler: CPU::handle_rra, addressing_mode: AddressingMode::IndirectY, bytes: 2, cycles: 8 },
 
     // SBC
-    0xEBu8 => Operand { opcode: 0xEB, name: "SBC", handler: CPU::handle_sbc, addressing_mode: AddressingMode::Immediate, bytes: 2, cycles: 2 },
+    Operand { opcode: 0xEB, name: "SBC", handler: CPU::handle_sbc, addressing_mode: AddressingMode::Immediate, bytes: 2, cycles: 2 },
 
     // SLO/ASO
-    0x07u8 => Operand { opcode: 0x07, name: "SLO", handler: CPU::handle_slo, addressing_mode: AddressingMode::ZeroPage, bytes: 2, cycles: 5 },
-    0x17u8 => Operand { opcode: 0x17, name: "SLO", handler: CPU::handle_slo, addressing_mode: AddressingMode::ZeroPageX, bytes: 2, cycles: 6 },
-    0x0Fu8 => Operand { opcode: 0x0F, name: "SLO", handler: CPU::handle_slo, addressing_mode: AddressingMode::Absolute, bytes: 3, cycles: 6 },
-    0x1Fu8 => Operand { opcode: 0x1F, name: "SLO", handler: CPU::handle_slo, addressing_mode: AddressingMode::AbsoluteX, bytes: 3, cycles: 7 },
-    0x1Bu8 => Operand { opcode: 0x1B, name: "SLO", handler: CPU::handle_slo, addressing_mode: AddressingMode::AbsoluteY, bytes: 3, cycles: 7 },
-    0x03u8 => Operand { opcode: 0x03, name: "SLO", handler: CPU::handle_slo, addressing_mode: AddressingMode::IndirectX, bytes: 2, cycles: 8 },
-    0x13u8 => Operand { opcode: 0x13, name: "SLO", handler: CPU::handle_slo, addressing_mode: AddressingMode::IndirectY, bytes: 2, cycles: 8 },
+    Operand { opcode: 0x07, name: "SLO", handler: CPU::handle_slo, addressing_mode: AddressingMode::ZeroPage, bytes: 2, cycles: 5 },
+    Operand { opcode: 0x17, name: "SLO", handler: CPU::handle_slo, addressing_mode: AddressingMode::ZeroPageX, bytes: 2, cycles: 6 },
+    Operand { opcode: 0x0F, name: "SLO", handler: CPU::handle_slo, addressing_mode: AddressingMode::Absolute, bytes: 3, cycles: 6 },
+    Operand { opcode: 0x1F, name: "SLO", handler: CPU::handle_slo, addressing_mode: AddressingMode::AbsoluteX, bytes: 3, cycles: 7 },
+    Operand { opcode: 0x1B, name: "SLO", handler: CPU::handle_slo, addressing_mode: AddressingMode::AbsoluteY, bytes: 3, cycles: 7 },
+    Operand { opcode: 0x03, name: "SLO", handler: CPU::handle_slo, addressing_mode: AddressingMode::IndirectX, bytes: 2, cycles: 8 },
+    Operand { opcode: 0x13, name: "SLO", handler: CPU::handle_slo, addressing_mode: AddressingMode::IndirectY, bytes: 2, cycles: 8 },
 
     // SRE/LSE
-    0x47u8 => Operand { opcode: 0x47, name: "SRE", handler: CPU::handle_sre, addressing_mode: AddressingMode::ZeroPage, bytes: 2, cycles: 5 },
-    0x57u8 => Operand { opcode: 0x57, name: "SRE", handler: CPU::handle_sre, addressing_mode: AddressingMode::ZeroPageX, bytes: 2, cycles: 6 },
-    0x4Fu8 => Operand { opcode: 0x4F, name: "SRE", handler: CPU::handle_sre, addressing_mode: AddressingMode::Absolute, bytes: 3, cycles: 6 },
-    0x5Fu8 => Operand { opcode: 0x5F, name: "SRE", handler: CPU::handle_sre, addressing_mode: AddressingMode::AbsoluteX, bytes: 3, cycles: 7 },
-    0x5Bu8 => Operand { opcode: 0x5B, name: "SRE", handler: CPU::handle_sre, addressing_mode: AddressingMode::AbsoluteY, bytes: 3, cycles: 7 },
-    0x43u8 => Operand { opcode: 0x43, name: "SRE", handler: CPU::handle_sre, addressing_mode: AddressingMode::IndirectX, bytes: 2, cycles: 8 },
-    0x53u8 => Operand { opcode: 0x53, name: "SRE", handler: CPU::handle_sre, addressing_mode: AddressingMode::IndirectY, bytes: 2, cycles: 8 },
+    Operand { opcode: 0x47, name: "SRE", handler: CPU::handle_sre, addressing_mode: AddressingMode::ZeroPage, bytes: 2, cycles: 5 },
+    Operand { opcode: 0x57, name: "SRE", handler: CPU::handle_sre, addressing_mode: AddressingMode::ZeroPageX, bytes: 2, cycles: 6 },
+    Operand { opcode: 0x4F, name: "SRE", handler: CPU::handle_sre, addressing_mode: AddressingMode::Absolute, bytes: 3, cycles: 6 },
+    Operand { opcode: 0x5F, name: "SRE", handler: CPU::handle_sre, addressing_mode: AddressingMode::AbsoluteX, bytes: 3, cycles: 7 },
+    Operand { opcode: 0x5B, name: "SRE", handler: CPU::handle_sre, addressing_mode: AddressingMode::AbsoluteY, bytes: 3, cycles: 7 },
+    Operand { opcode: 0x43, name: "SRE", handler: CPU::handle_sre, addressing_mode: AddressingMode::IndirectX, bytes: 2, cycles: 8 },
+    Operand { opcode: 0x53, name: "SRE", handler: CPU::handle_sre, addressing_mode: AddressingMode::IndirectY, bytes: 2, cycles: 8 },
 
     // SXA/SHX/XAS
-    0x9Eu8 => Operand { opcode: 0x9E, name: "SXA", handler: CPU::handle_sxa, addressing_mode: AddressingMode::AbsoluteY, bytes: 3, cycles: 5 },
+    Operand { opcode: 0x9E, name: "SXA", handler: CPU::handle_sxa, addressing_mode: AddressingMode::AbsoluteY, bytes: 3, cycles: 5 },
 
     // SYA/SHY/SAY
-    0x9cu8 => Operand { opcode: 0x9C, name: "SYA", handler: CPU::handle_sya, addressing_mode: AddressingMode::AbsoluteX, bytes: 3, cycles: 5 },
+    Operand { opcode: 0x9C, name: "SYA", handler: CPU::handle_sya, addressing_mode: AddressingMode::AbsoluteX, bytes: 3, cycles: 5 },
 
     // TOP/SKW
-    0x0Cu8 => Operand { opcode: 0x0C, name: "TOP", handler: CPU::handle_top, addressing_mode: AddressingMode::Absolute, bytes: 3, cycles: 4 },
-    0x1Cu8 => Operand { opcode: 0x1C, name: "TOP", handler: CPU::handle_top, addressing_mode: AddressingMode::AbsoluteX, bytes: 3, cycles: 4 /* +1 if page crossed */ },
-    0x3Cu8 => Operand { opcode: 0x3C, name: "TOP", handler: CPU::handle_top, addressing_mode: AddressingMode::AbsoluteX, bytes: 3, cycles: 4 /* +1 if page crossed */ },
-    0x5Cu8 => Operand { opcode: 0x5C, name: "TOP", handler: CPU::handle_top, addressing_mode: AddressingMode::AbsoluteX, bytes: 3, cycles: 4 /* +1 if page crossed */ },
-    0x7Cu8 => Operand { opcode: 0x7C, name: "TOP", handler: CPU::handle_top, addressing_mode: AddressingMode::AbsoluteX, bytes: 3, cycles: 4 /* +1 if page crossed */ },
-    0xDCu8 => Operand { opcode: 0xDC, name: "TOP", handler: CPU::handle_top, addressing_mode: AddressingMode::AbsoluteX, bytes: 3, cycles: 4 /* +1 if page crossed */ },
-    0xFCu8 => Operand { opcode: 0xFC, name: "TOP", handler: CPU::handle_top, addressing_mode: AddressingMode::AbsoluteX, bytes: 3, cycles: 4 /* +1 if page crossed */ },
+    Operand { opcode: 0x0C, name: "TOP", handler: CPU::handle_top, addressing_mode: AddressingMode::Absolute, bytes: 3, cycles: 4 },
+    Operand { opcode: 0x1C, name: "TOP", handler: CPU::handle_top, addressing_mode: AddressingMode::AbsoluteX, bytes: 3, cycles: 4 /* +1 if page crossed */ },
+    Operand { opcode: 0x3C, name: "TOP", handler: CPU::handle_top, addressing_mode: AddressingMode::AbsoluteX, bytes: 3, cycles: 4 /* +1 if page crossed */ },
+    Operand { opcode: 0x5C, name: "TOP", handler: CPU::handle_top, addressing_mode: AddressingMode::AbsoluteX, bytes: 3, cycles: 4 /* +1 if page crossed */ },
+    Operand { opcode: 0x7C, name: "TOP", handler: CPU::handle_top, addressing_mode: AddressingMode::AbsoluteX, bytes: 3, cycles: 4 /* +1 if page crossed */ },
+    Operand { opcode: 0xDC, name: "TOP", handler: CPU::handle_top, addressing_mode: AddressingMode::AbsoluteX, bytes: 3, cycles: 4 /* +1 if page crossed */ },
+    Operand { opcode: 0xFC, name: "TOP", handler: CPU::handle_top, addressing_mode: AddressingMode::AbsoluteX, bytes: 3, cycles: 4 /* +1 if page crossed */ },
 
     // XAA/ANE
-    0x8Bu8 => Operand { opcode: 0x8B, name: "XAA", handler: CPU::handle_xaa, addressing_mode: AddressingMode::Immediate, bytes: 2, cycles: 2 },
+    Operand { opcode: 0x8B, name: "XAA", handler: CPU::handle_xaa, addressing_mode: AddressingMode::Immediate, bytes: 2, cycles: 2 },
 
     // XAS/SHS/TAS
-    0x9Bu8 => Operand { opcode: 0x9B, name: "XAS", handler: CPU::handle_xas, addressing_mode: AddressingMode::AbsoluteY, bytes: 3, cycles: 5 },
+    Operand { opcode: 0x9B, name: "XAS", handler: CPU::handle_xas, addressing_mode: AddressingMode::AbsoluteY, bytes: 3, cycles: 5 },
+
+];
+
+// Decode table indexed directly by opcode, built at compile time from OPERANDS.
+// This avoids a hash lookup on every executed instruction.
+static OPERAND_TABLE: [Option<Operand>; 256] = build_operand_table();
+
+const fn build_operand_table() -> [Option<Operand>; 256] {
+    let mut table: [Option<Operand>; 256] = [None; 256];
+    let mut i = 0;
+    while i < OPERANDS.len() {
+        let operand = OPERANDS[i];
+        if table[operand.opcode as usize].is_some() {
+            panic!("Duplicate opcode in OPERANDS");
+        }
+        table[operand.opcode as usize] = Some(operand);
+        i += 1;
+    }
+    table
+}
 
-};
+// Returns the definition of an opcode, if it is supported.
+pub fn lookup_operand(opcode: u8) -> Option<Operand> {
+    OPERAND_TABLE[opcode as usize]
+}
+
+// Returns true if the opcode has a handler in the opcode table.
+pub fn is_opcode_supported(opcode: u8) -> bool {
+    lookup_operand(opcode).is_some()
+}
 
 #[allow(dead_code)]
 impl CPU {
@@ -543,23 +642,43 @@ impl CPU {
     const PRG_ROM_BASE_ADDRESS: u16 = 0x8000;
     const STACK_BASE_ADDRESS: u16 = 0x0100;
     const STACK_ADDRESS_DEFAULT_COLD_START: u8 = 0xFF;
-    const STACK_ADDRESS_DEFAULT_WARM_START: u8 = 0xFD;
+    // SP is 0x00 when the CPU is powered on, the reset sequence then brings it to 0xFD
+    pub const STACK_ADDRESS_POWER_ON: u8 = 0x00;
     const RESET_VECTOR_ADDRESS: u16 = 0xFFFC;
 
-    pub(crate) fn read_u8(&self, addr: u16) -> u8 {
+    pub fn read_u8(&self, addr: u16) -> u8 {
+        self.sync_ppu_for_access(addr, false);
         self.bus.read_u8(addr)
     }
 
-    pub(crate) fn write_u8(& mut self, addr: u16, value: u8) {
+    pub fn write_u8(& mut self, addr: u16, value: u8) {
+        if self.event_log.is_some() {
+            self.record_write_event(addr, value);
+        }
+        self.sync_ppu_for_access(addr, true);
         self.bus.write_u8(addr, value);
+        match addr {
+            0x2000..=0x3FFF if self.bus.has_ppu() => self.raise_ppu_nmi(),
+            0x4014 if self.bus.has_ppu() => self.oam_dma(value),
+            _ => {}
+        }
+    }
+
+    // Side-effect-free read, see Bus::peek_u8. Used by tracing and debugger views.
+    pub fn peek_u8(&self, addr: u16) -> u8 {
+        self.bus.peek_u8(addr)
     }
 
-    pub(crate) fn read_u16(&self, addr: u16) -> u16 {
+    pub fn peek_u16(&self, addr: u16) -> u16 {
+        u16::from_le_bytes([self.peek_u8(addr), self.peek_u8(addr.wrapping_add(1))])
+    }
+
+    pub fn read_u16(&self, addr: u16) -> u16 {
         // We use little-endian format: low byte at addr, high byte at addr + 1
         return u16::from_le_bytes([self.read_u8(addr), self.read_u8(addr + 1)]);
     }
 
-    pub(crate) fn write_u16(& mut self, addr: u16, value: u16) {
+    pub fn write_u16(& mut self, addr: u16, value: u16) {
         // We use little-endian format: low byte at addr, high byte at addr + 1
         let [low, high] = u16::to_le_bytes(value);
 
@@ -567,7 +686,7 @@ impl CPU {
         self.write_u8(addr + 1, high);
     }
 
-    pub(crate) fn set_status_flag(& mut self, flag: StatusFlag, value: bool) {
+    pub fn set_status_flag(& mut self, flag: StatusFlag, value: bool) {
         if value {
             self.status_register |= 1 << (flag as u8);
         } else {
@@ -575,12 +694,17 @@ impl CPU {
         }
     }
 
-    pub(crate) fn get_status_flag(&self, flag: StatusFlag) -> bool {
+    pub fn get_status_flag(&self, flag: StatusFlag) -> bool {
         (self.status_register & (1 << (flag as u8))) != 0
     }
 
+    // True when ADC and SBC must use BCD arithmetic.
+    pub fn is_decimal_mode_active(&self) -> bool {
+        self.variant == CpuVariant::Nmos6502 && self.get_status_flag(StatusFlag::DecimalMode)
+    }
+
     /// Pushes a byte onto the stack.
-    pub(crate) fn push_u8(&mut self, value: u8) {
+    pub fn push_u8(&mut self, value: u8) {
         let stack_addr = Self::STACK_BASE_ADDRESS + self.stack_pointer as u16;
         self.write_u8(stack_addr, value);
         self.stack_pointer = self.stack_pointer.wrapping_sub(1);
@@ -588,15 +712,21 @@ impl CPU {
 
     /// Pushes a 16-bit word onto the stack.
     /// The high byte is pushed first, then the low byte, so they are stored in little-endian format on the stack.
-    pub(crate) fn push_u16(&mut self, value: u16) {
+    pub fn push_u16(&mut self, value: u16) {
         let [low, high] = value.to_le_bytes();
         // Push high byte first, then low byte
         self.push_u8(high);
         self.push_u8(low);
     }
 
+    // Cycle spent before pulling from the stack: the CPU reads the top of the stack while it
+    // increments SP, and ignores the value.
+    pub fn dummy_stack_read(&self) {
+        self.read_u8(Self::STACK_BASE_ADDRESS + self.stack_pointer as u16);
+    }
+
     /// Pops a byte from the stack.
-    pub(crate) fn pop_u8(&mut self) -> u8 {
+    pub fn pop_u8(&mut self) -> u8 {
         self.stack_pointer = self.stack_pointer.wrapping_add(1);
         let stack_addr = Self::STACK_BASE_ADDRESS + self.stack_pointer as u16;
         self.read_u8(stack_addr)
@@ -604,95 +734,178 @@ impl CPU {
 
     /// Pops a 16-bit word from the stack.
     /// The low byte is popped first, then the high byte, as they are stored in little-endian format on the stack.
-    pub(crate) fn pop_u16(&mut self) -> u16 {
+    pub fn pop_u16(&mut self) -> u16 {
         let low = self.pop_u8();
         let high = self.pop_u8();
         // Combine into a u16 value
         u16::from_le_bytes([low, high])
     }
 
-    pub(crate) fn load_program(& mut self, program: &[u8]) {
-        // let start_address = CPU::PRG_ROM_BASE_ADDRESS as usize;
-        // let end_address = start_address + program.len();
-
-        // if end_address > self.memory.len() {
-        //     panic!("Program size exceeds memory bounds");
-        // }
-
-        for i in 0..(program.len() as u16) {
-            self.write_u8(0x0000 + i, program[i as usize]);
-        }
-        self.write_u16(0xFFFC, 0x0000); // Set reset vector to start of program
-        self.program_counter = self.read_u16(CPU::RESET_VECTOR_ADDRESS);
+    // Writes the program at $0000 and starts it there, see Loader for other addresses
+    pub fn load_program(& mut self, program: &[u8]) {
+        Loader::new(0x0000).load(self, program).expect("Failed to load program");
     }
 
-    pub(crate) fn reset(&mut self) {
-        self.accumulator = 0;
-        self.x_register = 0;
-        self.status_register = 0x24; // 0010 0100 (Unused + Interrupt Disable)
-        self.stack_pointer = CPU::STACK_ADDRESS_DEFAULT_WARM_START;
+    // Soft reset (reset button). The CPU runs the interrupt sequence with its stack writes turned
+    // into reads: SP is decremented by 3 without writing memory, and interrupts are disabled.
+    // A, X, Y, the other flags and RAM keep their value. See power_on for a cold boot.
+    pub fn reset(&mut self) {
+        self.stack_pointer = self.stack_pointer.wrapping_sub(3);
+        self.set_status_flag(StatusFlag::InterruptDisable, true);
 
         // 0xFFFC corresponds to the reset vector address.
         self.program_counter = self.read_u16(CPU::RESET_VECTOR_ADDRESS);
-        self.cycles = 8; // Reset takes 8 cycles
+        self.cycles += 7; // Reset takes 7 cycles
         self.halted = false;
+        self.reset_interrupts();
+        self.call_stack.clear();
     }
 
     // Helper function to check if two addresses are on different pages
-    pub(crate) fn page_crossed(&self, addr1: u16, addr2: u16) -> bool {
+    pub fn page_crossed(&self, addr1: u16, addr2: u16) -> bool {
         (addr1 & 0xFF00) != (addr2 & 0xFF00)
     }
 
-    pub fn run(& mut self) {
-                self.run_with_callback(|_| {});
+    pub fn run(& mut self) -> CpuJam {
+                self.run_with_callback(|_| {})
     }
 
-    pub fn run_with_callback<F>(&mut self, mut callback: F)
+    // Runs until the CPU jams on a KIL opcode, and returns where it did.
+    pub fn run_with_callback<F>(&mut self, mut callback: F) -> CpuJam
     where
         F: FnMut(&mut CPU),
     {
         loop {
-            if self.halted {
-                break;
+            if let Some(jam) = self.jam() {
+                return jam;
             }
             callback(self);
-            let pc_before_instruction = self.program_counter;
-            let opcode = self.read_u8(pc_before_instruction);
-            // println!("PC: {:04X} Opcode: {:02X}", pc_before_instruction, opcode);
-
-            if let Some(operand_info) = OPERAND_MAP.get(&opcode) {
-                // Fetch operand based on addressing mode
-                let (operand_value, operand_address) = match operand_info.addressing_mode {
-                    AddressingMode::Implicit => (None, None),
-                    AddressingMode::Accumulator => (Some(self.accumulator), None),
-                    _ => {
-                        // Pass PC + 1 to get operand, as PC currently points to the opcode
-                        let (addr, page_crossed) = self.get_operand_address(operand_info.addressing_mode, pc_before_instruction + 1);
-                        if page_crossed {
-                            match operand_info.name {
-                                "ADC" | "AND" | "CMP" | "EOR" | "LDA" | "LDX" | "LDY" | "ORA" | "SBC" => {
-                                    self.cycles += 1;
-                                }
-                                // "STA", "STX", "STY" and others do not take the penalty
-                                _ => {}
+            self.step();
+        }
+    }
+
+    // Executes a single instruction and returns the number of cycles it took.
+    // A jammed CPU does nothing until it is reset.
+    pub fn step(&mut self) -> u64 {
+        if self.halted {
+            return 0;
+        }
+        let cycles_before = self.cycles;
+        let pc_before_instruction = self.program_counter;
+        let interrupt_disable_before = self.get_status_flag(StatusFlag::InterruptDisable);
+        self.bus.set_access_context(cycles_before, pc_before_instruction);
+        let opcode = self.read_u8(pc_before_instruction);
+        if let Some(counts) = &mut self.execution_counts {
+            counts.record(pc_before_instruction, opcode);
+        }
+        // println!("PC: {:04X} Opcode: {:02X}", pc_before_instruction, opcode);
+
+        if let Some(operand_info) = OPERAND_TABLE[opcode as usize] {
+            // Fetch operand based on addressing mode
+            // Every cycle is a bus access: one-byte instructions read the byte after the opcode and ignore it
+            let (operand_value, operand_address) = match operand_info.addressing_mode {
+                AddressingMode::Implicit => {
+                    self.read_u8(pc_before_instruction.wrapping_add(1));
+                    (None, None)
+                }
+                AddressingMode::Accumulator => {
+                    self.read_u8(pc_before_instruction.wrapping_add(1));
+                    (Some(self.accumulator), None)
+                }
+                // JSR reads its operand itself, interleaved with the pushes
+                _ if operand_info.name == "JSR" => (None, None),
+                _ => {
+                    // Pass PC + 1 to get operand, as PC currently points to the opcode
+                    let (addr, page_crossed) = self.get_operand_address(operand_info.addressing_mode, pc_before_instruction + 1);
+                    if page_crossed {
+                        match operand_info.name {
+                            "ADC" | "AND" | "CMP" | "EOR" | "LDA" | "LDX" | "LDY" | "ORA" | "SBC" | "LAX" | "LAR" | "TOP" => {
+                                self.cycles += 1;
                             }
+                            // "STA", "STX", "STY" and others do not take the penalty
+                            _ => {}
                         }
-                        (Some(self.read_u8(addr)), Some(addr))
                     }
-                };
+                    self.access_operand(&operand_info, addr, page_crossed)
+                }
+            };
+
+            // Execute the instruction and collect any additional cycles the handler returns
+            let handler_extra = (operand_info.handler)(self, operand_value, operand_address);
+
+            // Add base cycles plus any additional cycles reported by handler
+            self.cycles += operand_info.cycles as u64 + handler_extra as u64;
+            self.instructions += 1;
+
+            // Jumps and taken branches set the program counter themselves, possibly to their own
+            // address (`forever: JMP forever`), so it is only advanced for the other instructions.
+            // KIL leaves it on the opcode.
+            let jumped = matches!(operand_info.name, "JMP" | "JSR" | "RTS" | "RTI" | "BRK")
+                || (matches!(operand_info.addressing_mode, AddressingMode::Relative) && handler_extra > 0);
+            if !jumped && !self.halted {
+                self.program_counter = self.program_counter.wrapping_add(operand_info.bytes as u16);
+            }
+            self.track_call_stack(operand_info.name, pc_before_instruction, cycles_before);
+            if jumped {
+                self.track_branch(operand_info.name, pc_before_instruction, cycles_before);
+            }
+            self.run_ppu_events();
+            self.sync_apu_irqs();
+            if self.frozen_memory.timing == FreezeTiming::EveryInstruction && !self.frozen_memory.is_empty() {
+                self.frozen_memory.apply(&mut self.bus);
+            }
 
-                // Execute the instruction and collect any additional cycles the handler returns
-                let handler_extra = (operand_info.handler)(self, operand_value, operand_address);
+            // Interrupts are polled before the last cycle, except for a taken branch that does not cross
+            // a page: its extra cycle does not poll, so the poll of the previous cycle is used.
+            let taken_branch_without_page_cross = matches!(operand_info.addressing_mode, AddressingMode::Relative) && handler_extra == 1;
+            let poll_cycle = if taken_branch_without_page_cross { cycles_before + 1 } else { self.cycles - 1 };
+            self.poll_after_instruction(operand_info.name, poll_cycle, interrupt_disable_before);
+        } else {
+            panic!("Unimplemented opcode: {:02X}", opcode);
+        }
+        self.cycles - cycles_before
+    }
+
+    // Interrupt polling done during `poll_cycle` of each instruction (see interrupts.rs).
+    fn poll_after_instruction(&mut self, name: &str, poll_cycle: u64, interrupt_disable_before: bool) {
+        let interrupt_disable = match name {
+            // BRK is an interrupt sequence itself: the first instruction of its handler always runs
+            "BRK" => return,
+            "KIL" => return,
+            // The I flag is changed after polling
+            "CLI" | "SEI" | "PLP" => interrupt_disable_before,
+            _ => self.get_status_flag(StatusFlag::InterruptDisable),
+        };
+        if let Some(interrupt) = self.poll_interrupts(poll_cycle, interrupt_disable) {
+            self.service_interrupt(interrupt);
+        }
+    }
 
-                // Add base cycles plus any additional cycles reported by handler
-                self.cycles += operand_info.cycles as u64 + handler_extra as u64;
+    // Performs the bus accesses of an instruction on its memory operand, including the spurious ones
+    // done by the real CPU, since mappers and I/O registers react to every access:
+    // - Indexed addressing first reads the address before the high byte is fixed up. Reads only do it
+    //   when a page is crossed (the read is redone at the right address), writes and RMW always do it.
+    // - Read-modify-write instructions write the original value back before writing the result.
+    // Returns the operand value passed to the instruction handler (None for writes and jumps).
+    fn access_operand(&mut self, operand_info: &Operand, addr: u16, page_crossed: bool) -> (Option<u8>, Option<u16>) {
+        let access = operand_access(operand_info);
+        let indexed = matches!(
+            operand_info.addressing_mode,
+            AddressingMode::AbsoluteX | AddressingMode::AbsoluteY | AddressingMode::IndirectY
+        );
+        if indexed && (page_crossed || access != OperandAccess::Read) && access != OperandAccess::Jump {
+            // The carry of the low byte addition is not applied to the high byte yet
+            let uncorrected_addr = if page_crossed { addr.wrapping_sub(0x100) } else { addr };
+            self.read_u8(uncorrected_addr);
+        }
 
-                // If the program counter was not changed by a jump or branch, advance it.
-                if self.program_counter == pc_before_instruction {
-                    self.program_counter = self.program_counter.wrapping_add(operand_info.bytes as u16);
-                }
-            } else {
-                panic!("Unimplemented opcode: {:02X}", opcode);
+        match access {
+            OperandAccess::Read => (Some(self.read_u8(addr)), Some(addr)),
+            OperandAccess::Write | OperandAccess::Jump => (None, Some(addr)),
+            OperandAccess::ReadModifyWrite => {
+                let value = self.read_u8(addr);
+                self.write_u8(addr, value);
+                (Some(value), Some(addr))
             }
         }
     }
@@ -701,7 +914,7 @@ impl CPU {
     /// `condition` indicates whether the branch should be taken.
     /// `offset` is the signed 8-bit relative offset.
     /// Returns additional cycles: 0 if not taken, +1 if taken, +2 if page crossed.
-    pub(crate) fn branch(&mut self, condition: bool, offset: i8) -> u8 {
+    pub fn branch(&mut self, condition: bool, offset: i8) -> u8 {
         let mut additional_cycles: u8 = 0;
 
         if condition {
@@ -709,6 +922,12 @@ impl CPU {
             let pc_next = self.program_counter.wrapping_add(2);
             let target_pc = pc_next.wrapping_add(offset as u16);
 
+            // The extra cycles read the next opcode, then the target before its high byte is fixed up
+            self.read_u8(pc_next);
+            if self.page_crossed(pc_next, target_pc) {
+                self.read_u8((pc_next & 0xFF00) | (target_pc & 0x00FF));
+            }
+
             self.program_counter = target_pc;
 
             additional_cycles += 1; // branch taken
@@ -722,18 +941,29 @@ impl CPU {
     }
 
     // Helper to get effective address based on addressing mode
-    pub(crate) fn get_operand_address(&self, mode: AddressingMode, addr: u16) -> (u16, bool) {
+    pub fn get_operand_address(&self, mode: AddressingMode, addr: u16) -> (u16, bool) {
+        self.resolve_operand_address(mode, addr, CPU::read_u8)
+    }
+
+    // Same as get_operand_address, but only peeks memory so the bus state is left untouched.
+    pub fn peek_operand_address(&self, mode: AddressingMode, addr: u16) -> (u16, bool) {
+        self.resolve_operand_address(mode, addr, CPU::peek_u8)
+    }
+
+    fn resolve_operand_address(&self, mode: AddressingMode, addr: u16, read_u8: fn(&CPU, u16) -> u8) -> (u16, bool) {
+        let read_u16 = |addr: u16| u16::from_le_bytes([read_u8(self, addr), read_u8(self, addr.wrapping_add(1))]);
+
         match mode {
-            AddressingMode::Absolute => (self.read_u16(addr), false),
+            AddressingMode::Absolute => (read_u16(addr), false),
 
             AddressingMode::AbsoluteX => {
-                let base = self.read_u16(addr);
+                let base = read_u16(addr);
                 let final_addr = base.wrapping_add(self.x_register as u16);
                 (final_addr, self.page_crossed(base, final_addr))
             }
 
             AddressingMode::AbsoluteY => {
-                let base = self.read_u16(addr);
+                let base = read_u16(addr);
                 let final_addr = base.wrapping_add(self.y_register as u16);
                 (final_addr, self.page_crossed(base, final_addr))
             }
@@ -741,29 +971,31 @@ impl CPU {
             AddressingMode::Immediate => (addr, false),
 
             AddressingMode::Indirect => {
-                let ptr = self.read_u16(addr);
-                let low = self.read_u8(ptr);
+                let ptr = read_u16(addr);
+                let low = read_u8(self, ptr);
                 let high = if ptr & 0x00FF == 0x00FF {
                     // page boundary bug: wrap to beginning of same page
-                    self.read_u8(ptr & 0xFF00)
+                    read_u8(self, ptr & 0xFF00)
                 } else {
-                    self.read_u8(ptr + 1)
+                    read_u8(self, ptr + 1)
                 };
                 (u16::from_le_bytes([low, high]), false)
             }
 
+            // Indexed zero page modes read the base address while X or Y is added
             AddressingMode::IndirectX => {
-                let base = self.read_u8(addr);
+                let base = read_u8(self, addr);
+                read_u8(self, base as u16);
                 let ptr = base.wrapping_add(self.x_register);
-                let low = self.read_u8(ptr as u16);
-                let high = self.read_u8(ptr.wrapping_add(1) as u16);
+                let low = read_u8(self, ptr as u16);
+                let high = read_u8(self, ptr.wrapping_add(1) as u16);
                 (u16::from_le_bytes([low, high]), false)
             }
 
             AddressingMode::IndirectY => {
-                let base = self.read_u8(addr);
-                let low = self.read_u8(base as u16);
-                let high = self.read_u8(base.wrapping_add(1) as u16);
+                let base = read_u8(self, addr);
+                let low = read_u8(self, base as u16);
+                let high = read_u8(self, base.wrapping_add(1) as u16);
                 let base_addr = u16::from_le_bytes([low, high]);
                 let final_addr = base_addr.wrapping_add(self.y_register as u16);
                 (final_addr, self.page_crossed(base_addr, final_addr))
@@ -773,15 +1005,17 @@ impl CPU {
                 (addr, false)
             }
 
-            AddressingMode::ZeroPage => (self.read_u8(addr) as u16, false),
+            AddressingMode::ZeroPage => (read_u8(self, addr) as u16, false),
 
             AddressingMode::ZeroPageX => {
-                let base = self.read_u8(addr);
+                let base = read_u8(self, addr);
+                read_u8(self, base as u16);
                 (base.wrapping_add(self.x_register) as u16, false)
             }
 
             AddressingMode::ZeroPageY => {
-                let base = self.read_u8(addr);
+                let base = read_u8(self, addr);
+                read_u8(self, base as u16);
                 (base.wrapping_add(self.y_register) as u16, false)
             }
 
@@ -793,85 +1027,105 @@ impl CPU {
     }
 }
 
-pub(crate) fn trace(cpu: &mut CPU) -> String {
-    let pc = cpu.program_counter;
-    let code = cpu.read_u8(pc);
-    let ops = OPERAND_MAP.get(&code).expect(&format!("Opcode {:x} is not supported", code));
+// How an instruction uses its memory operand.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OperandAccess {
+    Read,
+    Write,
+    ReadModifyWrite,
+    // JMP and JSR only use the address
+    Jump,
+}
 
-    let mut hex_dump = vec![];
-    hex_dump.push(code);
+pub fn operand_access(operand: &Operand) -> OperandAccess {
+    match operand.name {
+        "STA" | "STX" | "STY" | "AAX" | "AXA" | "SXA" | "SYA" | "XAS" => OperandAccess::Write,
+        "ASL" | "LSR" | "ROL" | "ROR" | "INC" | "DEC" | "SLO" | "SRE" | "RLA" | "RRA" | "DCP" | "ISC" => OperandAccess::ReadModifyWrite,
+        "JMP" | "JSR" => OperandAccess::Jump,
+        _ => OperandAccess::Read,
+    }
+}
+
+// The 56 mnemonics of the official 6502 instruction set.
+const OFFICIAL_MNEMONICS: [&str; 56] = [
+    "ADC", "AND", "ASL", "BCC", "BCS", "BEQ", "BIT", "BMI", "BNE", "BPL", "BRK", "BVC", "BVS", "CLC",
+    "CLD", "CLI", "CLV", "CMP", "CPX", "CPY", "DEC", "DEX", "DEY", "EOR", "INC", "INX", "INY", "JMP",
+    "JSR", "LDA", "LDX", "LDY", "LSR", "NOP", "ORA", "PHA", "PHP", "PLA", "PLP", "ROL", "ROR", "RTI",
+    "RTS", "SBC", "SEC", "SED", "SEI", "STA", "STX", "STY", "TAX", "TAY", "TSX", "TXA", "TXS", "TYA",
+];
+
+// Returns true for the undocumented opcodes. NOP and SBC also have unofficial variants.
+pub fn is_unofficial_operand(operand: &Operand) -> bool {
+    match operand.name {
+        "NOP" => operand.opcode != 0xEA,
+        "SBC" => operand.opcode == 0xEB,
+        name => !OFFICIAL_MNEMONICS.contains(&name),
+    }
+}
+
+// nestest.log prefixes unofficial opcodes with a "*" and uses other common names for some of them.
+fn write_nestest_mnemonic(out: &mut String, operand: &Operand) {
+    if !is_unofficial_operand(operand) {
+        out.push(' ');
+        out.push_str(operand.name);
+        return;
+    }
+    let name = match operand.name {
+        "DOP" | "TOP" => "NOP",
+        "AAX" => "SAX",
+        "ISC" => "ISB",
+        name => name,
+    };
+    out.push('*');
+    out.push_str(name);
+}
+
+// Formats the instruction at the program counter like nestest.log does.
+// Memory is only peeked, so tracing never changes the emulation state.
+pub fn trace(cpu: &CPU) -> String {
+    let mut line = String::new();
+    write_trace(cpu, &mut line);
+    line
+}
+
+// Same as `trace`, appended to `out`. Reusing the buffer from one instruction to the next
+// avoids any allocation while tracing.
+pub fn write_trace(cpu: &CPU, out: &mut String) {
+    let (scanline, dot) = cpu.ppu_position();
+    write_trace_instruction(cpu, out);
+    write!(out, " PPU:{:3},{:3} CYC:{}", scanline, dot, cpu.cycles).expect("Writing to a String cannot fail");
+}
+
+// Instruction and registers part of the trace, without the timing columns.
+pub fn trace_instruction(cpu: &CPU) -> String {
+    let mut line = String::new();
+    write_trace_instruction(cpu, &mut line);
+    line
+}
+
+pub fn write_trace_instruction(cpu: &CPU, out: &mut String) {
+    // Only fails if the String cannot allocate, which aborts anyway
+    write_trace_columns(cpu, out).expect("Writing to a String cannot fail");
+}
+
+fn write_trace_columns(cpu: &CPU, out: &mut String) -> fmt::Result {
+    let pc = cpu.program_counter;
+    let opcode = cpu.peek_u8(pc);
+    let ops = lookup_operand(opcode).unwrap_or_else(|| panic!("Opcode {:x} is not supported", opcode));
+    let mut bytes = [opcode, 0, 0];
+    for offset in 1..ops.bytes as usize {
+        bytes[offset] = cpu.peek_u8(pc.wrapping_add(offset as u16));
+    }
+    let bytes = &bytes[..ops.bytes as usize];
 
     let (mem_addr, stored_value) = match ops.addressing_mode {
         AddressingMode::Immediate | AddressingMode::Implicit | AddressingMode::Accumulator => (0, 0),
         _ => {
-            let (addr, _) = cpu.get_operand_address(ops.addressing_mode, pc + 1);
-            (addr, cpu.read_u8(addr))
+            let (addr, _) = cpu.peek_operand_address(ops.addressing_mode, pc + 1);
+            (addr, cpu.peek_u8(addr))
         }
     };
 
-    let tmp_ops = match ops.bytes {
-        1 => match ops.opcode {
-            0x0A | 0x4A | 0x2A | 0x6A => "A ".to_string(), // Accumulator instructions
-            _ => String::from("")
-        },
-        2 => {
-            let address: u8 = cpu.read_u8(pc + 1);
-            hex_dump.push(address);
-
-            match ops.addressing_mode {
-                AddressingMode::Immediate => format!("#${:02X}", address),
-                AddressingMode::ZeroPage => format!("${:02X} = {:02X}", mem_addr, stored_value),
-                AddressingMode::ZeroPageX => format!("${:02X},X @ {:02X} = {:02X}", address, mem_addr, stored_value),
-                AddressingMode::ZeroPageY => format!("${:02X},Y @ {:02X} = {:02X}", address, mem_addr, stored_value),
-                AddressingMode::IndirectX => format!("(${:02X},X) @ {:02X} = {:04X} = {:02X}", address, (address.wrapping_add(cpu.x_register)), mem_addr, stored_value),
-                AddressingMode::IndirectY => format!("(${:02X}),Y = {:04X} @ {:04X} = {:02X}", address, (mem_addr.wrapping_sub(cpu.y_register as u16)), mem_addr, stored_value),
-                AddressingMode::Relative => {
-                    let offset = cpu.read_u8(pc + 1) as i8;
-                    let target = pc.wrapping_add(2).wrapping_add(offset as u16);
-                    format!("${:04X}", target)
-                },
-                _ => panic!("Unexpected addressing mode {:?} for 2 byte instruction", ops.addressing_mode),
-            }
-        },
-        3 => {
-            let address_lo = cpu.read_u8(pc + 1);
-            let address_hi = cpu.read_u8(pc + 2);
-            hex_dump.push(address_lo);
-            hex_dump.push(address_hi);
-
-            let address = cpu.read_u16(pc + 1);
-
-            match ops.addressing_mode {
-                AddressingMode::Absolute => {
-                     if ops.opcode == 0x4C || ops.opcode == 0x20 { // JMP, JSR
-                         format!("${:04X}", mem_addr)
-                     } else {
-                         format!("${:04X} = {:02X}", mem_addr, stored_value)
-                     }
-                },
-                AddressingMode::AbsoluteX => format!("${:04X},X @ {:04X} = {:02X}", address, mem_addr, stored_value),
-                AddressingMode::AbsoluteY => format!("${:04X},Y @ {:04X} = {:02X}", address, mem_addr, stored_value),
-                AddressingMode::Indirect => { // JMP Indirect
-                    let jump_addr = if address & 0x00FF == 0x00FF {
-                        let lo = cpu.read_u8(address);
-                        let hi = cpu.read_u8(address & 0xFF00);
-                        u16::from_le_bytes([lo, hi])
-                    } else {
-                        cpu.read_u16(address)
-                    };
-                    format!("(${:04X}) = {:04X}", address, jump_addr)
-                },
-                _ => panic!("Unexpected addressing mode {:?} for 3 byte instruction", ops.addressing_mode),
-            }
-        },
-        _ => String::from("")
-    };
-
-    let hex_str = hex_dump.iter()
-        .map(|z| format!("{:02X}", z))
-        .collect::<Vec<String>>()
-        .join(" ");
-
     // Nestest alignment:
     // 00-03: PC
     // 04-05: Space
@@ -879,20 +1133,55 @@ pub(crate) fn trace(cpu: &mut CPU) -> String {
     // 15:    Space
     // 16-47: Assembly
     // 48...: Registers
-    let asm_str = format!("{:04X}  {:<8} {: >4} {}", pc, hex_str, ops.name, tmp_ops)
-        .trim()
-        .to_string();
-
-    format!(
-        "{:47} A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} PPU:  0,  0 CYC:{}", 
-        asm_str, cpu.accumulator, cpu.x_register, cpu.y_register, cpu.status_register, cpu.stack_pointer, cpu.cycles
-    ).to_uppercase()
+    let start = out.len();
+    write!(out, "{:04X} ", pc)?;
+    for byte in bytes {
+        write!(out, " {:02X}", byte)?;
+    }
+    for _ in bytes.len()..3 {
+        out.push_str("   ");
+    }
+    out.push(' ');
+    write_nestest_mnemonic(out, &ops);
+    out.push(' ');
+    write_operand(out, ops.addressing_mode, pc, bytes, &cpu.labels)?;
+
+    // Memory operands are annotated with the effective address and the value stored there
+    match ops.addressing_mode {
+        AddressingMode::ZeroPage => write!(out, " = {:02X}", stored_value)?,
+        AddressingMode::ZeroPageX | AddressingMode::ZeroPageY => write!(out, " @ {:02X} = {:02X}", mem_addr, stored_value)?,
+        AddressingMode::IndirectX => write!(out, " @ {:02X} = {:04X} = {:02X}", bytes[1].wrapping_add(cpu.x_register), mem_addr, stored_value)?,
+        AddressingMode::IndirectY => write!(out, " = {:04X} @ {:04X} = {:02X}", mem_addr.wrapping_sub(cpu.y_register as u16), mem_addr, stored_value)?,
+        AddressingMode::Absolute => {
+            if ops.opcode != 0x4C && ops.opcode != 0x20 { // JMP, JSR
+                write!(out, " = {:02X}", stored_value)?;
+            }
+        },
+        AddressingMode::AbsoluteX | AddressingMode::AbsoluteY => write!(out, " @ {:04X} = {:02X}", mem_addr, stored_value)?,
+        AddressingMode::Indirect => write!(out, " = {:04X}", mem_addr)?, // JMP Indirect
+        AddressingMode::Immediate | AddressingMode::Implicit | AddressingMode::Accumulator | AddressingMode::Relative => {}
+    }
+
+    // Implicit instructions leave trailing spaces, the assembly column is then padded to 47 characters
+    let assembly_end = start + out[start..].trim_end().len();
+    out.truncate(assembly_end);
+    for _ in out[start..].chars().count()..47 {
+        out.push(' ');
+    }
+
+    write!(
+        out,
+        " A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X}",
+        cpu.accumulator, cpu.x_register, cpu.y_register, cpu.status_register, cpu.stack_pointer
+    )
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::access_log::{AccessKind, AccessLog};
     use crate::bus::Bus;
-    use crate::cpu6502::{AddressingMode, new_cpu, StatusFlag};
+    use crate::cpu6502::{AddressingMode, lookup_operand, new_cpu, operand_access, trace, write_trace, OperandAccess, StatusFlag, CPU};
+    use crate::joypad::JoypadButton;
     use crate::rom::Rom;
 
     #[test]
@@ -1120,6 +1409,18 @@ mod tests {
         assert_eq!(target_address, 0x1234, "Indirect addressing did not simulate page boundary bug correctly");
     }
 
+    #[test]
+    fn test_peek_has_no_side_effects() {
+        let mut cpu = new_cpu(Bus::new(Rom::test_rom()));
+        cpu.write_u8(0x0801, 0x42);
+        assert_eq!(cpu.peek_u8(0x0001), 0x42, "peek should follow RAM mirroring");
+        assert_eq!(cpu.peek_u8(0x8000), 0xEA, "peek should read PRG ROM");
+        assert_eq!(cpu.peek_u16(0xFFFC), cpu.read_u16(0xFFFC));
+
+        // Peeking PPU registers does not clear their flags (see ppu::tests)
+        assert_eq!(cpu.peek_u8(0x2002), 0x00);
+    }
+
     #[test]
     fn test_stack_push_pop_u8() {
         let mut cpu = new_cpu(Bus::new(Rom::test_rom()));
@@ -1143,4 +1444,258 @@ mod tests {
         assert_eq!(popped_value, 0x1234);
         assert_eq!(cpu.stack_pointer, 0xFF);
     }
+
+    // The controller port is used to observe the bus accesses: each read of 0x4016 shifts the next button out.
+    fn cpu_with_program(program: &[u8]) -> CPU {
+        let mut cpu = new_cpu(Bus::new(Rom::test_rom()));
+        for (offset, byte) in program.iter().enumerate() {
+            cpu.write_u8(0x0600 + offset as u16, *byte);
+        }
+        cpu.program_counter = 0x0600;
+        cpu.bus.joypads_mut().set_button_pressed(0, JoypadButton::A, true);
+        cpu.write_u8(0x4016, 1);
+        cpu.write_u8(0x4016, 0);
+        cpu
+    }
+
+    #[test]
+    fn test_indexed_read_dummy_read_on_page_cross() {
+        // LDA $4020,X with X = 0xF6 reads 0x4116, after a dummy read of 0x4016
+        let mut cpu = cpu_with_program(&[0xBD, 0x20, 0x40]);
+        cpu.x_register = 0xF6;
+        cpu.step();
+        // The A button was shifted out by the dummy read
+        assert_eq!(cpu.read_u8(0x4016) & 1, 0);
+
+        // Without a page cross, there is no dummy read
+        let mut cpu = cpu_with_program(&[0xBD, 0x00, 0x40]);
+        cpu.x_register = 0x16;
+        cpu.step();
+        assert_eq!(cpu.accumulator, 0x41);
+        assert_eq!(cpu.read_u8(0x4016) & 1, 0);
+        let mut cpu = cpu_with_program(&[0xBD, 0x00, 0x40]);
+        cpu.x_register = 0x00;
+        cpu.step();
+        assert_eq!(cpu.read_u8(0x4016) & 1, 1);
+    }
+
+    #[test]
+    fn test_write_does_not_read_its_operand() {
+        // STA $4017
+        let mut cpu = cpu_with_program(&[0x8D, 0x17, 0x40]);
+        cpu.step();
+        assert_eq!(cpu.read_u8(0x4016) & 1, 1);
+
+        // STA $4000,X always does a dummy read, even without a page cross: here at 0x4016
+        let mut cpu = cpu_with_program(&[0x9D, 0x00, 0x40]);
+        cpu.x_register = 0x16;
+        cpu.accumulator = 0x00;
+        cpu.step();
+        assert_eq!(cpu.read_u8(0x4016) & 1, 0);
+    }
+
+    #[test]
+    fn test_read_modify_write_writes_original_value_first() {
+        // INC $4016 reads 0x41 (A pressed, open bus upper bits), writes it back (strobe on, which restarts the read sequence),
+        // then writes 0x42 (strobe off). Without the first write, the next read would return the B button.
+        let mut cpu = cpu_with_program(&[0xEE, 0x16, 0x40]);
+        cpu.step();
+        assert_eq!(cpu.read_u8(0x4016) & 1, 1);
+        assert_eq!(cpu.read_u8(0x4016) & 1, 0);
+    }
+
+    #[test]
+    fn test_jump_to_itself_loops() {
+        // forever: JMP forever
+        let mut cpu = cpu_with_program(&[0x4C, 0x00, 0x06]);
+        cpu.step();
+        assert_eq!(cpu.program_counter, 0x0600);
+
+        // BNE to itself (offset -2) loops while taken
+        let mut cpu = cpu_with_program(&[0xD0, 0xFE]);
+        cpu.set_status_flag(StatusFlag::Zero, false);
+        cpu.step();
+        assert_eq!(cpu.program_counter, 0x0600);
+        cpu.set_status_flag(StatusFlag::Zero, true);
+        cpu.step();
+        assert_eq!(cpu.program_counter, 0x0602);
+    }
+
+    // Reference cycle counts of every opcode (https://www.nesdev.org/wiki/6502_cycle_times),
+    // 0 for the KIL opcodes. Branches: +1 when taken, +2 when taken to another page.
+    const REFERENCE_CYCLES: [u8; 256] = [
+        7, 6, 0, 8, 3, 3, 5, 5, 3, 2, 2, 2, 4, 4, 6, 6, // 0x00
+        2, 5, 0, 8, 4, 4, 6, 6, 2, 4, 2, 7, 4, 4, 7, 7, // 0x10
+        6, 6, 0, 8, 3, 3, 5, 5, 4, 2, 2, 2, 4, 4, 6, 6, // 0x20
+        2, 5, 0, 8, 4, 4, 6, 6, 2, 4, 2, 7, 4, 4, 7, 7, // 0x30
+        6, 6, 0, 8, 3, 3, 5, 5, 3, 2, 2, 2, 3, 4, 6, 6, // 0x40
+        2, 5, 0, 8, 4, 4, 6, 6, 2, 4, 2, 7, 4, 4, 7, 7, // 0x50
+        6, 6, 0, 8, 3, 3, 5, 5, 4, 2, 2, 2, 5, 4, 6, 6, // 0x60
+        2, 5, 0, 8, 4, 4, 6, 6, 2, 4, 2, 7, 4, 4, 7, 7, // 0x70
+        2, 6, 2, 6, 3, 3, 3, 3, 2, 2, 2, 2, 4, 4, 4, 4, // 0x80
+        2, 6, 0, 6, 4, 4, 4, 4, 2, 5, 2, 5, 5, 5, 5, 5, // 0x90
+        2, 6, 2, 6, 3, 3, 3, 3, 2, 2, 2, 2, 4, 4, 4, 4, // 0xA0
+        2, 5, 0, 5, 4, 4, 4, 4, 2, 4, 2, 4, 4, 4, 4, 4, // 0xB0
+        2, 6, 2, 8, 3, 3, 5, 5, 2, 2, 2, 2, 4, 4, 6, 6, // 0xC0
+        2, 5, 0, 8, 4, 4, 6, 6, 2, 4, 2, 7, 4, 4, 7, 7, // 0xD0
+        2, 6, 2, 8, 3, 3, 5, 5, 2, 2, 2, 2, 4, 4, 6, 6, // 0xE0
+        2, 5, 0, 8, 4, 4, 6, 6, 2, 4, 2, 7, 4, 4, 7, 7, // 0xF0
+    ];
+
+    // Opcodes taking one more cycle when the indexed address crosses a page: the reads
+    // (official and unofficial) in the (zp),Y, abs,Y and abs,X modes. Stores and
+    // read-modify-write instructions always take the extra cycle, which is in their base count.
+    const PAGE_CROSS_PENALTY: [u8; 32] = [
+        0x11, 0x19, 0x1C, 0x1D, 0x31, 0x39, 0x3C, 0x3D, 0x51, 0x59, 0x5C, 0x5D, 0x71, 0x79, 0x7C, 0x7D,
+        0xB1, 0xB3, 0xB9, 0xBB, 0xBC, 0xBD, 0xBE, 0xBF, 0xD1, 0xD9, 0xDC, 0xDD, 0xF1, 0xF9, 0xFC, 0xFD,
+    ];
+
+    // Runs `opcode` at `pc` with its operand pointing (after indexing by X = Y = 0x10) to
+    // 0x0290, or to 0x0308 when `cross_page`, and returns the cycles taken, the new PC and
+    // the number of bus accesses done.
+    fn run_opcode(opcode: u8, pc: u16, operand: [u8; 2], cross_page: bool, status: u8) -> (u64, u16, usize) {
+        let mut cpu = new_cpu(Bus::new(Rom::test_rom()));
+        let base: u16 = if cross_page { 0x02F8 } else { 0x0280 };
+        for pointer in [0x40u16, 0x50] {
+            cpu.write_u8(pointer, base as u8);
+            cpu.write_u8(pointer + 1, (base >> 8) as u8);
+        }
+        cpu.write_u8(pc, opcode);
+        cpu.write_u8(pc + 1, operand[0]);
+        cpu.write_u8(pc + 2, operand[1]);
+        cpu.program_counter = pc;
+        cpu.x_register = 0x10;
+        cpu.y_register = 0x10;
+        cpu.stack_pointer = 0xFD;
+        cpu.status_register = status;
+        let mut log = AccessLog::new(16);
+        log.watch_range(0x0000..=0xFFFF);
+        cpu.bus.enable_access_log(log);
+        let cycles = cpu.step();
+        (cycles, cpu.program_counter, cpu.bus.access_log().unwrap().len())
+    }
+
+    #[test]
+    fn test_cycle_counts_match_reference() {
+        let mut errors = Vec::new();
+        for opcode in 0..=255u8 {
+            let Some(operand) = lookup_operand(opcode) else {
+                continue;
+            };
+            let reference = REFERENCE_CYCLES[opcode as usize] as u64;
+            if reference == 0 {
+                continue;
+            }
+
+            let cases: Vec<(&str, (u64, u16, usize), u64)> = match operand.addressing_mode {
+                AddressingMode::Relative => {
+                    // Taken with one of the two flag states, not taken with the other
+                    let mut cases = Vec::new();
+                    for status in [0x24, 0xE7] {
+                        let run = run_opcode(opcode, 0x0600, [0x10, 0], false, status);
+                        let taken = run.1 != 0x0602;
+                        cases.push((if taken { "taken" } else { "not taken" }, run, reference + taken as u64));
+                        let run = run_opcode(opcode, 0x06F0, [0x20, 0], false, status);
+                        if taken {
+                            cases.push(("taken to another page", run, reference + 2));
+                        }
+                    }
+                    cases
+                }
+                AddressingMode::AbsoluteX | AddressingMode::AbsoluteY | AddressingMode::IndirectY => {
+                    let penalty = PAGE_CROSS_PENALTY.contains(&opcode) as u64;
+                    let operand = |base: u16| if matches!(operand.addressing_mode, AddressingMode::IndirectY) { [0x40, 0] } else { base.to_le_bytes() };
+                    vec![
+                        ("same page", run_opcode(opcode, 0x0600, operand(0x0280), false, 0x24), reference),
+                        ("page crossed", run_opcode(opcode, 0x0600, operand(0x02F8), true, 0x24), reference + penalty),
+                    ]
+                }
+                _ => vec![("", run_opcode(opcode, 0x0600, [0x40, 0x02], false, 0x24), reference)],
+            };
+            for (case, (actual, _, accesses), expected) in cases {
+                if actual != expected {
+                    errors.push(format!("{:02X} {} {:?} {}: {} cycles, expected {}", opcode, operand.name, operand.addressing_mode, case, actual, expected));
+                }
+                // Every cycle of the 6502 is a read or a write
+                if accesses as u64 != actual {
+                    errors.push(format!("{:02X} {} {:?} {}: {} bus accesses in {} cycles", opcode, operand.name, operand.addressing_mode, case, accesses, actual));
+                }
+            }
+        }
+        assert!(errors.is_empty(), "Cycle count mismatches:\n{}", errors.join("\n"));
+    }
+
+    // Bus accesses of one instruction run at `pc` on a flat bus, as (address, value, kind)
+    fn bus_accesses(program: &[u8], pc: u16, setup: impl FnOnce(&mut CPU)) -> Vec<(u16, u8, AccessKind)> {
+        let mut cpu = new_cpu(Bus::new_flat());
+        cpu.stack_pointer = 0xFD;
+        for (offset, byte) in program.iter().enumerate() {
+            cpu.write_u8(pc + offset as u16, *byte);
+        }
+        cpu.program_counter = pc;
+        setup(&mut cpu);
+        let mut log = AccessLog::new(16);
+        log.watch_range(0x0000..=0xFFFF);
+        cpu.bus.enable_access_log(log);
+        cpu.step();
+        let log = cpu.bus.disable_access_log().unwrap();
+        log.entries().map(|access| (access.address, access.value, access.kind)).collect()
+    }
+
+    #[test]
+    fn test_bus_accesses_of_each_cycle() {
+        use AccessKind::{Read, Write};
+
+        // JSR $1234: the high byte of the target is read after the pushes
+        assert_eq!(bus_accesses(&[0x20, 0x34, 0x12], 0x0600, |_| {}), vec![
+            (0x0600, 0x20, Read), (0x0601, 0x34, Read), (0x01FD, 0x00, Read),
+            (0x01FD, 0x06, Write), (0x01FC, 0x02, Write), (0x0602, 0x12, Read),
+        ]);
+
+        // RTS to 0x0603: dummy reads of the next byte, of the stack and of the pulled address
+        assert_eq!(bus_accesses(&[0x60, 0xEA], 0x0700, |cpu| cpu.write_u16(0x01FE, 0x0602)), vec![
+            (0x0700, 0x60, Read), (0x0701, 0xEA, Read), (0x01FD, 0x00, Read),
+            (0x01FE, 0x02, Read), (0x01FF, 0x06, Read), (0x0602, 0x00, Read),
+        ]);
+
+        // BNE +0x20 at 0x06F0, taken to the next page: reads the next opcode, then 0x0612 before 0x0712 is fixed up
+        assert_eq!(bus_accesses(&[0xD0, 0x20], 0x06F0, |_| {}), vec![
+            (0x06F0, 0xD0, Read), (0x06F1, 0x20, Read), (0x06F2, 0x00, Read), (0x0612, 0x00, Read),
+        ]);
+
+        // LDA ($F0,X) with X = 0x20: the pointer is read at 0x10 after a dummy read of 0xF0
+        assert_eq!(bus_accesses(&[0xA1, 0xF0], 0x0600, |cpu| { cpu.x_register = 0x20; cpu.write_u16(0x0010, 0x0300); }), vec![
+            (0x0600, 0xA1, Read), (0x0601, 0xF0, Read), (0x00F0, 0x00, Read),
+            (0x0010, 0x00, Read), (0x0011, 0x03, Read), (0x0300, 0x00, Read),
+        ]);
+
+        // PHA: the byte after the opcode is read while the push is prepared
+        assert_eq!(bus_accesses(&[0x48, 0x99], 0x0600, |cpu| cpu.accumulator = 0x42), vec![
+            (0x0600, 0x48, Read), (0x0601, 0x99, Read), (0x01FD, 0x42, Write),
+        ]);
+    }
+
+    #[test]
+    fn test_operand_access() {
+        let access = |opcode| operand_access(&lookup_operand(opcode).unwrap());
+        assert_eq!(access(0xBD), OperandAccess::Read); // LDA abs,X
+        assert_eq!(access(0x9D), OperandAccess::Write); // STA abs,X
+        assert_eq!(access(0x1E), OperandAccess::ReadModifyWrite); // ASL abs,X
+        assert_eq!(access(0xDB), OperandAccess::ReadModifyWrite); // DCP abs,Y
+        assert_eq!(access(0x20), OperandAccess::Jump); // JSR
+    }
+
+    #[test]
+    fn test_write_trace_reuses_buffer() {
+        let mut cpu = cpu_with_program(&[0xA9, 0x10, 0x8D, 0x00, 0x02, 0xEA]);
+        let mut buffer = String::with_capacity(128);
+        let allocation = buffer.as_ptr();
+        for _ in 0..3 {
+            buffer.clear();
+            write_trace(&cpu, &mut buffer);
+            assert_eq!(buffer, trace(&cpu));
+            cpu.step();
+        }
+        assert_eq!(buffer.as_ptr(), allocation);
+    }
 }
@@ -0,0 +1,139 @@
+use crate::nes::Nes;
+use crate::rewind::Rewind;
+
+// Reverse execution for the debugger. The emulation is deterministic, so the state before the
+// last instruction is rebuilt by restoring the closest rewind snapshot taken before it and
+// running again up to that instruction: `step_back` finds how many instructions separate the
+// snapshot from the current position (the cycle counter tells where it is), then replays all of
+// them but the last one.
+// Snapshots are taken every `interval` steps, and at each frame run through the debugger, so a
+// step back never replays more than a frame. The savestates hold the whole machine, pending
+// interrupts included, so the replay is exact. What they leave out is not rolled back: the
+// framebuffer (drawn again from the next frame) and the debugging records (branch trace, event
+// log, execution counts), while the call stack is cleared.
+
+const DEFAULT_SNAPSHOTS: usize = 64;
+const DEFAULT_INTERVAL: u32 = 1000;
+
+pub struct Debugger {
+    rewind: Rewind,
+    // A snapshot is taken every `interval` steps
+    interval: u32,
+    steps_since_snapshot: u32,
+}
+
+impl Default for Debugger {
+    fn default() -> Self {
+        Self::new(DEFAULT_SNAPSHOTS, DEFAULT_INTERVAL)
+    }
+}
+
+impl Debugger {
+    pub fn new(snapshots: usize, interval: u32) -> Self {
+        Self { rewind: Rewind::new(snapshots, 1), interval: interval.max(1), steps_since_snapshot: 0 }
+    }
+
+    pub fn run_frame(&mut self, nes: &mut Nes) -> u64 {
+        self.rewind.capture_now(&nes.cpu);
+        self.steps_since_snapshot = 1;
+        nes.run_frame()
+    }
+
+    // Runs a single instruction (see Nes::step) and returns its cycles.
+    pub fn step(&mut self, nes: &mut Nes) -> u64 {
+        if self.steps_since_snapshot == 0 {
+            self.rewind.capture_now(&nes.cpu);
+        }
+        self.steps_since_snapshot = (self.steps_since_snapshot + 1) % self.interval;
+        nes.step()
+    }
+
+    // Goes back to the state before the last instruction executed.
+    pub fn step_back(&mut self, nes: &mut Nes) -> Result<(), String> {
+        let (target, instructions_before) = (nes.cpu.cycles, nes.cpu.instructions);
+        let snapshot = self.rewind.snapshot_before(target)?;
+
+        // Instructions from the snapshot to the current position
+        nes.cpu.load_state(&snapshot)?;
+        let mut instructions = 0u32;
+        while nes.cpu.cycles < target && !nes.cpu.halted {
+            nes.cpu.step();
+            instructions += 1;
+        }
+        if nes.cpu.cycles != target {
+            return Err(format!("Re-execution diverged: cycle {} instead of {}", nes.cpu.cycles, target));
+        }
+
+        nes.cpu.load_state(&snapshot)?;
+        for _ in 1..instructions {
+            nes.cpu.step();
+        }
+        nes.cpu.instructions = instructions_before.saturating_sub(1);
+        nes.watches.update(&nes.cpu);
+        self.steps_since_snapshot = (instructions - 1) % self.interval;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::debugger::Debugger;
+    use crate::labels::Labels;
+    use crate::nes::Nes;
+    use crate::rom::Rom;
+
+    #[test]
+    fn test_step_back() {
+        let mut nes = Nes::new(Rom::test_rom()).unwrap();
+        let mut debugger = Debugger::new(8, 3);
+        assert!(debugger.step_back(&mut nes).is_err());
+
+        nes.watches.add("PC", &Labels::new()).unwrap();
+        let mut states = vec![nes.cpu.save_state()];
+        for _ in 0..10 {
+            debugger.step(&mut nes);
+            states.push(nes.cpu.save_state());
+        }
+
+        // Back to each previous instruction, across snapshots
+        for expected in states.iter().rev().skip(1).take(9) {
+            debugger.step_back(&mut nes).expect("step back should succeed");
+            assert_eq!(&nes.cpu.save_state(), expected);
+        }
+        assert_eq!(nes.watches.watches()[0].value, Some(Ok(nes.cpu.program_counter as i64)));
+
+        // Stepping forward again replays the same instructions
+        debugger.step(&mut nes);
+        assert_eq!(nes.cpu.save_state(), states[2]);
+    }
+
+    #[test]
+    fn test_step_back_over_an_nmi() {
+        let mut nes = Nes::new(Rom::test_rom()).unwrap();
+        // A snapshot before each instruction: the last one is taken while the NMI is pending
+        let mut debugger = Debugger::new(8, 1);
+        nes.cpu.write_u8(0x2000, 0x80);
+        let stack_pointer = nes.cpu.stack_pointer;
+        let mut before = nes.cpu.save_state();
+        // Until the NMI of the first vblank is serviced
+        while nes.cpu.stack_pointer == stack_pointer {
+            before = nes.cpu.save_state();
+            debugger.step(&mut nes);
+        }
+        debugger.step_back(&mut nes).unwrap();
+        assert_eq!(nes.cpu.save_state(), before);
+        debugger.step(&mut nes);
+        assert_eq!(nes.cpu.stack_pointer, stack_pointer - 3);
+    }
+
+    #[test]
+    fn test_step_back_across_frames() {
+        let mut nes = Nes::new(Rom::test_rom()).unwrap();
+        let mut debugger = Debugger::default();
+        debugger.run_frame(&mut nes);
+        let before = nes.cpu.save_state();
+        debugger.step(&mut nes);
+        debugger.step_back(&mut nes).unwrap();
+        assert_eq!(nes.cpu.save_state(), before);
+    }
+}
@@ -0,0 +1,216 @@
+use std::fmt;
+use crate::bus::Bus;
+use crate::cpu6502::{lookup_operand, AddressingMode};
+use crate::labels::Labels;
+
+// A single decoded instruction.
+// The disassembler only peeks memory and does not need a CPU, so it can decode any memory range
+// (e.g. for a debugger view) without changing the emulation state.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DisasmLine {
+    pub address: u16,
+    // Raw bytes of the instruction (opcode followed by its operands)
+    pub bytes: Vec<u8>,
+    pub name: &'static str,
+    // Operand in standard 6502 assembly syntax, e.g. "$1234,X" (empty for implicit instructions)
+    pub operand: String,
+}
+
+impl DisasmLine {
+    // Raw bytes as space separated hexadecimal, e.g. "4C F5 C5"
+    pub fn hex_bytes(&self) -> String {
+        self.bytes.iter()
+            .map(|byte| format!("{:02X}", byte))
+            .collect::<Vec<String>>()
+            .join(" ")
+    }
+
+    // Address following this instruction
+    pub fn next_address(&self) -> u16 {
+        self.address.wrapping_add(self.bytes.len() as u16)
+    }
+}
+
+impl fmt::Display for DisasmLine {
+    // Same layout as nestest.log, e.g. "C000  4C F5 C5  JMP $C5F5"
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let line = format!("{:04X}  {:<8}  {} {}", self.address, self.hex_bytes(), self.name, self.operand);
+        write!(f, "{}", line.trim_end())
+    }
+}
+
+// Decodes `count` consecutive instructions starting at `addr`.
+pub fn disassemble(bus: &Bus, addr: u16, count: usize) -> Vec<DisasmLine> {
+    disassemble_with_labels(bus, addr, count, &Labels::new())
+}
+
+// Same as `disassemble`, addresses having a label being replaced by it, e.g. "JSR reset_handler".
+pub fn disassemble_with_labels(bus: &Bus, addr: u16, count: usize, labels: &Labels) -> Vec<DisasmLine> {
+    let mut lines = Vec::with_capacity(count);
+    let mut address = addr;
+    for _ in 0..count {
+        let line = disassemble_instruction_with_labels(bus, address, labels);
+        address = line.next_address();
+        lines.push(line);
+    }
+    lines
+}
+
+// Decodes the instruction located at `addr`.
+// Unknown opcodes are decoded as a single data byte (".DB").
+pub fn disassemble_instruction(bus: &Bus, addr: u16) -> DisasmLine {
+    disassemble_instruction_with_labels(bus, addr, &Labels::new())
+}
+
+pub fn disassemble_instruction_with_labels(bus: &Bus, addr: u16, labels: &Labels) -> DisasmLine {
+    let opcode = bus.peek_u8(addr);
+
+    let Some(operand_info) = lookup_operand(opcode) else {
+        return DisasmLine {
+            address: addr,
+            bytes: vec![opcode],
+            name: ".DB",
+            operand: format!("${:02X}", opcode),
+        };
+    };
+
+    let bytes: Vec<u8> = (0..operand_info.bytes as u16)
+        .map(|offset| bus.peek_u8(addr.wrapping_add(offset)))
+        .collect();
+    let operand = format_operand(operand_info.addressing_mode, addr, &bytes, labels);
+
+    DisasmLine {
+        address: addr,
+        bytes,
+        name: operand_info.name,
+        operand,
+    }
+}
+
+// Formats the operand of an instruction in standard 6502 assembly syntax.
+// Immediate values are never replaced by labels.
+fn format_operand(mode: AddressingMode, addr: u16, bytes: &[u8], labels: &Labels) -> String {
+    let mut operand = String::new();
+    write_operand(&mut operand, mode, addr, bytes, labels).expect("Writing to a String cannot fail");
+    operand
+}
+
+// Same as `format_operand`, written to `out` without allocating (used by the trace).
+pub fn write_operand(out: &mut impl fmt::Write, mode: AddressingMode, addr: u16, bytes: &[u8], labels: &Labels) -> fmt::Result {
+    let byte = bytes.get(1).copied().unwrap_or(0);
+    let word = u16::from_le_bytes([byte, bytes.get(2).copied().unwrap_or(0)]);
+
+    match mode {
+        AddressingMode::Implicit => Ok(()),
+        AddressingMode::Accumulator => out.write_str("A"),
+        AddressingMode::Immediate => write!(out, "#${:02X}", byte),
+        AddressingMode::ZeroPage => labels.write_address(out, byte as u16, true),
+        AddressingMode::ZeroPageX | AddressingMode::ZeroPageY => {
+            labels.write_address(out, byte as u16, true)?;
+            out.write_str(if matches!(mode, AddressingMode::ZeroPageX) { ",X" } else { ",Y" })
+        }
+        AddressingMode::IndirectX => {
+            out.write_str("(")?;
+            labels.write_address(out, byte as u16, true)?;
+            out.write_str(",X)")
+        }
+        AddressingMode::IndirectY => {
+            out.write_str("(")?;
+            labels.write_address(out, byte as u16, true)?;
+            out.write_str("),Y")
+        }
+        AddressingMode::Relative => {
+            // Branches are relative to the next instruction (PC + 2)
+            let target = addr.wrapping_add(2).wrapping_add(byte as i8 as u16);
+            labels.write_address(out, target, false)
+        }
+        AddressingMode::Absolute => labels.write_address(out, word, false),
+        AddressingMode::AbsoluteX | AddressingMode::AbsoluteY => {
+            labels.write_address(out, word, false)?;
+            out.write_str(if matches!(mode, AddressingMode::AbsoluteX) { ",X" } else { ",Y" })
+        }
+        AddressingMode::Indirect => {
+            out.write_str("(")?;
+            labels.write_address(out, word, false)?;
+            out.write_str(")")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bus::Bus;
+    use crate::disasm::{disassemble, disassemble_instruction, disassemble_with_labels};
+    use crate::labels::Labels;
+
+    fn bus_with_program(addr: u16, program: &[u8]) -> Bus {
+        let mut bus = Bus::new_flat();
+        for (offset, byte) in program.iter().enumerate() {
+            bus.write_u8(addr + offset as u16, *byte);
+        }
+        bus
+    }
+
+    #[test]
+    fn test_disassemble_addressing_modes() {
+        let program = [
+            0xA9, 0x10,       // LDA #$10
+            0x8D, 0x00, 0x02, // STA $0200
+            0xBD, 0x34, 0x12, // LDA $1234,X
+            0xB1, 0x20,       // LDA ($20),Y
+            0x0A,             // ASL A
+            0x6C, 0xFC, 0xFF, // JMP ($FFFC)
+            0xD0, 0xFC,       // BNE -4
+            0xE8,             // INX
+        ];
+        let bus = bus_with_program(0x0600, &program);
+        let lines: Vec<String> = disassemble(&bus, 0x0600, 8).iter().map(|line| line.to_string()).collect();
+
+        assert_eq!(lines, vec![
+            "0600  A9 10     LDA #$10",
+            "0602  8D 00 02  STA $0200",
+            "0605  BD 34 12  LDA $1234,X",
+            "0608  B1 20     LDA ($20),Y",
+            "060A  0A        ASL A",
+            "060B  6C FC FF  JMP ($FFFC)",
+            "060E  D0 FC     BNE $060C",
+            "0610  E8        INX",
+        ]);
+    }
+
+    #[test]
+    fn test_disassemble_does_not_need_a_cpu() {
+        let bus = bus_with_program(0x8000, &[0x4C, 0xF5, 0xC5]);
+        let line = disassemble_instruction(&bus, 0x8000);
+        assert_eq!(line.name, "JMP");
+        assert_eq!(line.operand, "$C5F5");
+        assert_eq!(line.bytes, vec![0x4C, 0xF5, 0xC5]);
+        assert_eq!(line.next_address(), 0x8003);
+    }
+
+    #[test]
+    fn test_disassemble_with_labels() {
+        let program = [
+            0x20, 0xF2, 0xC5, // JSR $C5F2
+            0xB5, 0x10,       // LDA $10,X
+            0x8D, 0x05, 0x02, // STA $0205
+            0xA9, 0x10,       // LDA #$10
+            0xD0, 0xF4,       // BNE -12
+        ];
+        let bus = bus_with_program(0x0600, &program);
+        let mut labels = Labels::new();
+        labels.insert(0xC5F2, "reset_handler", 1);
+        labels.insert(0x0010, "counter", 1);
+        labels.insert(0x0200, "oam", 0x100);
+        labels.insert(0x0600, "main_loop", 1);
+
+        let lines: Vec<String> = disassemble_with_labels(&bus, 0x0600, 5, &labels).iter().map(|line| line.to_string()).collect();
+        assert_eq!(lines, vec![
+            "0600  20 F2 C5  JSR reset_handler",
+            "0603  B5 10     LDA counter,X",
+            "0605  8D 05 02  STA oam+5",
+            "0608  A9 10     LDA #$10",
+            "060A  D0 F4     BNE main_loop",
+        ]);
+    }
+}
@@ -0,0 +1,87 @@
+use crate::cpu6502::CPU;
+
+// When the DMC channel of the APU needs the next byte of its sample, it halts the CPU and reads it
+// from memory itself (DMA). The CPU can only be halted on a read cycle, so the stall depends on
+// what the CPU is doing: usually 4 cycles, 3 if the DMA lands on a write cycle, and 2 after the
+// second or third write of a sequence (e.g. the pushes of an interrupt).
+// While halted, the CPU keeps repeating the read it was doing. This is harmless for memory, but
+// registers with read side effects see an extra read: a controller (0x4016/0x4017) loses a bit,
+// and PPUDATA (0x2007) skips a byte. Games that use DMC samples read the controllers twice to
+// work around it. The glitch is only emulated when `dmc_dma_read_glitch` is enabled.
+
+// What the CPU bus is doing on the cycle the DMA wants to halt.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CpuBusActivity {
+    Read(u16),
+    Write,
+    // Second or third of consecutive writes
+    ConsecutiveWrite,
+}
+
+impl CpuBusActivity {
+    fn stall_cycles(&self) -> u64 {
+        match self {
+            CpuBusActivity::Read(_) => 4,
+            CpuBusActivity::Write => 3,
+            CpuBusActivity::ConsecutiveWrite => 2,
+        }
+    }
+}
+
+impl CPU {
+    // Fetches the DMC sample byte at `sample_address`, stalling the CPU, and returns it.
+    pub fn dmc_dma(&mut self, sample_address: u16, activity: CpuBusActivity) -> u8 {
+        let mut reads = 1;
+        if let CpuBusActivity::Read(address) = activity && self.dmc_dma_read_glitch {
+            // The read that was interrupted is done again once the CPU resumes
+            self.read_u8(address);
+            reads += 1;
+        }
+        self.cycles += activity.stall_cycles();
+        self.bus.skip_cycles(activity.stall_cycles().saturating_sub(reads));
+        self.read_u8(sample_address)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bus::Bus;
+    use crate::cpu6502::new_cpu;
+    use crate::dmc_dma::CpuBusActivity;
+    use crate::joypad::JoypadButton;
+    use crate::rom::Rom;
+
+    #[test]
+    fn test_stall_cycles() {
+        let mut cpu = new_cpu(Bus::new(Rom::test_rom()));
+        assert_eq!(cpu.dmc_dma(0xC000, CpuBusActivity::Read(0x0000)), 0xEA);
+        assert_eq!(cpu.cycles, 4);
+        cpu.dmc_dma(0xC000, CpuBusActivity::Write);
+        assert_eq!(cpu.cycles, 7);
+        cpu.dmc_dma(0xC000, CpuBusActivity::ConsecutiveWrite);
+        assert_eq!(cpu.cycles, 9);
+    }
+
+    #[test]
+    fn test_controller_double_read_glitch() {
+        let mut cpu = new_cpu(Bus::new(Rom::test_rom()));
+        cpu.bus.joypads_mut().set_button_pressed(0, JoypadButton::A, true);
+        cpu.bus.joypads_mut().set_button_pressed(0, JoypadButton::B, true);
+
+        // Without the glitch, the controller is not affected
+        cpu.write_u8(0x4016, 1);
+        cpu.write_u8(0x4016, 0);
+        cpu.dmc_dma(0xC000, CpuBusActivity::Read(0x4016));
+        assert_eq!(cpu.read_u8(0x4016) & 1, 1);
+        assert_eq!(cpu.read_u8(0x4016) & 1, 1);
+        assert_eq!(cpu.read_u8(0x4016) & 1, 0);
+
+        // With the glitch, the DMA during the read of A makes the CPU see B instead
+        cpu.dmc_dma_read_glitch = true;
+        cpu.write_u8(0x4016, 1);
+        cpu.write_u8(0x4016, 0);
+        cpu.bus.joypads_mut().set_button_pressed(0, JoypadButton::B, false);
+        cpu.dmc_dma(0xC000, CpuBusActivity::Read(0x4016));
+        assert_eq!(cpu.read_u8(0x4016) & 1, 0);
+    }
+}
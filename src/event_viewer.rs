@@ -0,0 +1,155 @@
+use crate::cpu6502::CPU;
+
+// Event viewer: notable events of a frame with the PPU position they happened at, so a frontend can
+// draw them over a 341x262 map of the frame like the event viewer of Mesen (e.g. to check that a
+// scroll split is written during hblank). The events of the previous frame are kept while the
+// current one is recorded, since the previous frame is the one on screen.
+// Events are positioned at the start of the instruction causing them.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EventKind {
+    Nmi,
+    Irq,
+    PpuCtrlWrite(u8),
+    PpuMaskWrite(u8),
+    PpuScrollWrite(u8),
+    // Write to the cartridge space (0x8000 - 0xFFFF), which configures the mapper
+    MapperWrite { address: u16, value: u8 },
+    // Positioned at the dot of the hit. The cycle and PC are the CPU's once the instruction during
+    // which the PPU was caught up past it is done (e.g. a PPUSTATUS read polling for it).
+    SpriteZeroHit,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Event {
+    pub kind: EventKind,
+    pub scanline: u64,
+    pub dot: u64,
+    pub cycle: u64,
+    // Program counter of the instruction causing the event (the interrupted one for NMI and IRQ)
+    pub pc: u16,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct EventLog {
+    frame: u64,
+    current: Vec<Event>,
+    previous: Vec<Event>,
+}
+
+impl EventLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&mut self, frame: u64, event: Event) {
+        if frame != self.frame {
+            // Buffers are swapped, so recording does not allocate once they have grown
+            self.previous = std::mem::take(&mut self.current);
+            self.current = Vec::with_capacity(self.previous.capacity());
+            if frame != self.frame + 1 {
+                // Frames without any event in between
+                self.previous.clear();
+            }
+            self.frame = frame;
+        }
+        self.current.push(event);
+    }
+
+    // Events of the frame being emulated, in order
+    pub fn current_frame(&self) -> &[Event] {
+        &self.current
+    }
+
+    // Events of the last completed frame (the one displayed)
+    pub fn previous_frame(&self) -> &[Event] {
+        &self.previous
+    }
+}
+
+impl CPU {
+    pub fn enable_event_log(&mut self) {
+        self.event_log = Some(EventLog::new());
+    }
+
+    pub fn disable_event_log(&mut self) {
+        self.event_log = None;
+    }
+
+    // Records an event at the current position. Does nothing while the event log is disabled.
+    pub fn record_event(&mut self, kind: EventKind, pc: u16) {
+        if self.event_log.is_none() {
+            return;
+        }
+        let (scanline, dot) = self.ppu_position();
+        let frame = self.frame_number();
+        let event = Event { kind, scanline, dot, cycle: self.cycles, pc };
+        if let Some(log) = &mut self.event_log {
+            log.record(frame, event);
+        }
+    }
+
+    // Records an event of a component at the PPU position it happened at (e.g. the PPU for sprite
+    // zero hits, which it finds while being caught up).
+    pub fn record_event_at(&mut self, kind: EventKind, frame: u64, scanline: u64, dot: u64) {
+        let event = Event { kind, scanline, dot, cycle: self.cycles, pc: self.program_counter };
+        if let Some(log) = &mut self.event_log {
+            log.record(frame, event);
+        }
+    }
+
+    // Registers whose writes are shown in the event viewer
+    pub fn record_write_event(&mut self, addr: u16, value: u8) {
+        let kind = match addr {
+            0x2000..=0x3FFF => match addr & 0x0007 {
+                0 => EventKind::PpuCtrlWrite(value),
+                1 => EventKind::PpuMaskWrite(value),
+                5 => EventKind::PpuScrollWrite(value),
+                _ => return,
+            },
+            0x8000..=0xFFFF => EventKind::MapperWrite { address: addr, value },
+            _ => return,
+        };
+        self.record_event(kind, self.program_counter);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bus::Bus;
+    use crate::cpu6502::new_cpu;
+    use crate::event_viewer::{Event, EventKind, EventLog};
+    use crate::frame::{PPU_DOTS_PER_FRAME, PPU_DOTS_PER_SCANLINE};
+    use crate::rom::Rom;
+
+    #[test]
+    fn test_frames() {
+        let event = |cycle| Event { kind: EventKind::Nmi, scanline: 241, dot: 1, cycle, pc: 0 };
+        let mut log = EventLog::new();
+        log.record(0, event(1));
+        log.record(0, event(2));
+        log.record(1, event(3));
+        assert_eq!(log.previous_frame().len(), 2);
+        assert_eq!(log.current_frame(), &[event(3)]);
+
+        // Frame 2 had no event
+        log.record(3, event(4));
+        assert!(log.previous_frame().is_empty());
+    }
+
+    #[test]
+    fn test_mapper_write_position() {
+        let mut cpu = new_cpu(Bus::new(Rom::test_rom()));
+        cpu.enable_event_log();
+        // Scanline 20, dot 30 of the second frame
+        cpu.cycles = (PPU_DOTS_PER_FRAME + 20 * PPU_DOTS_PER_SCANLINE + 30) / 3;
+        cpu.program_counter = 0xC123;
+        cpu.write_u8(0x8000, 0x42);
+        cpu.write_u8(0x0000, 0x42);
+
+        let events = cpu.event_log.as_ref().unwrap().current_frame();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].kind, EventKind::MapperWrite { address: 0x8000, value: 0x42 });
+        assert_eq!((events[0].scanline, events[0].dot, events[0].pc), (20, 30, 0xC123));
+    }
+}
@@ -0,0 +1,152 @@
+use std::fmt::Write as _;
+use crate::cpu6502::{lookup_operand, CPU};
+use crate::labels::Labels;
+
+// Execution counters for profiling: how many times the instruction at each address, and each
+// opcode, was executed since the counters were enabled. The hot spot report shows where a game
+// spends its time (e.g. its wait-for-vblank loop or a slow decompression routine) and which
+// opcodes dominate, to know which handlers are worth optimizing in the emulator.
+// Counters are keyed by CPU address: with bank switching, the code of different banks mapped at
+// the same address shares a counter. Counting costs a memory access per instruction, so it is only
+// done while enabled.
+
+#[derive(Debug, Clone)]
+pub struct ExecutionCounts {
+    by_address: Vec<u64>,
+    by_opcode: [u64; 256],
+}
+
+impl Default for ExecutionCounts {
+    fn default() -> Self {
+        Self { by_address: vec![0; 0x10000], by_opcode: [0; 256] }
+    }
+}
+
+impl ExecutionCounts {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&mut self, pc: u16, opcode: u8) {
+        self.by_address[pc as usize] += 1;
+        self.by_opcode[opcode as usize] += 1;
+    }
+
+    pub fn clear(&mut self) {
+        self.by_address.fill(0);
+        self.by_opcode.fill(0);
+    }
+
+    pub fn count_at(&self, address: u16) -> u64 {
+        self.by_address[address as usize]
+    }
+
+    pub fn count_of(&self, opcode: u8) -> u64 {
+        self.by_opcode[opcode as usize]
+    }
+
+    pub fn total(&self) -> u64 {
+        self.by_opcode.iter().sum()
+    }
+
+    // The `count` most executed addresses, most executed first (lowest address first on ties)
+    pub fn hottest_addresses(&self, count: usize) -> Vec<(u16, u64)> {
+        hottest(&self.by_address, count).into_iter().map(|(index, executions)| (index as u16, executions)).collect()
+    }
+
+    pub fn hottest_opcodes(&self, count: usize) -> Vec<(u8, u64)> {
+        hottest(&self.by_opcode, count).into_iter().map(|(index, executions)| (index as u8, executions)).collect()
+    }
+
+    // Text report of the hottest addresses (with their label) and opcodes, with their share of
+    // all the instructions executed.
+    pub fn report(&self, count: usize, labels: &Labels) -> String {
+        let total = self.total().max(1) as f64;
+        let mut report = String::new();
+        // Writing to a String cannot fail
+        let _ = writeln!(report, "{} instructions executed", self.total());
+        let _ = writeln!(report, "Hottest addresses:");
+        for (address, executions) in self.hottest_addresses(count) {
+            let label = labels.lookup(address).map(|label| format!(" {}", label)).unwrap_or_default();
+            let _ = writeln!(report, "  {:04X}{:<20} {:>12} {:6.2}%", address, label, executions, executions as f64 * 100.0 / total);
+        }
+        let _ = writeln!(report, "Hottest opcodes:");
+        for (opcode, executions) in self.hottest_opcodes(count) {
+            let name = lookup_operand(opcode).map_or("???", |operand| operand.name);
+            let _ = writeln!(report, "  {:02X} {:<17} {:>12} {:6.2}%", opcode, name, executions, executions as f64 * 100.0 / total);
+        }
+        report
+    }
+}
+
+// Indexes of the `count` highest non-zero counters
+fn hottest(counters: &[u64], count: usize) -> Vec<(usize, u64)> {
+    let mut hottest: Vec<(usize, u64)> = counters.iter().copied().enumerate().filter(|(_, executions)| *executions > 0).collect();
+    hottest.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    hottest.truncate(count);
+    hottest
+}
+
+impl CPU {
+    pub fn enable_execution_counts(&mut self) {
+        self.execution_counts = Some(Box::new(ExecutionCounts::new()));
+    }
+
+    // Stops counting and returns the counters
+    pub fn disable_execution_counts(&mut self) -> Option<ExecutionCounts> {
+        self.execution_counts.take().map(|counts| *counts)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bus::Bus;
+    use crate::cpu6502::new_cpu;
+    use crate::execution_counts::ExecutionCounts;
+    use crate::labels::Labels;
+
+    #[test]
+    fn test_hottest() {
+        let mut counts = ExecutionCounts::new();
+        counts.record(0xC000, 0xEA);
+        counts.record(0xC001, 0xEA);
+        counts.record(0xC001, 0xEA);
+        counts.record(0x8000, 0xA9);
+        counts.record(0xC002, 0xA9);
+        assert_eq!(counts.total(), 5);
+        assert_eq!(counts.hottest_addresses(2), vec![(0xC001, 2), (0x8000, 1)]);
+        assert_eq!(counts.hottest_opcodes(10), vec![(0xEA, 3), (0xA9, 2)]);
+
+        let mut labels = Labels::new();
+        labels.insert(0xC001, "wait_vblank", 1);
+        let report = counts.report(1, &labels);
+        assert!(report.contains("C001 wait_vblank"), "unexpected report: {}", report);
+        assert!(report.contains("40.00%"), "unexpected report: {}", report);
+        assert!(report.contains("EA NOP"), "unexpected report: {}", report);
+
+        counts.clear();
+        assert!(counts.hottest_addresses(1).is_empty());
+    }
+
+    #[test]
+    fn test_counted_by_the_cpu() {
+        // loop: DEX; BNE loop; NOP
+        let mut cpu = new_cpu(Bus::new_flat());
+        for (offset, byte) in [0xCA, 0xD0, 0xFD, 0xEA].iter().enumerate() {
+            cpu.bus.poke_u8(0x0600 + offset as u16, *byte).unwrap();
+        }
+        cpu.program_counter = 0x0600;
+        cpu.x_register = 3;
+        cpu.step();
+        assert!(cpu.execution_counts.is_none());
+
+        cpu.enable_execution_counts();
+        for _ in 0..6 {
+            cpu.step();
+        }
+        let counts = cpu.disable_execution_counts().unwrap();
+        assert_eq!((counts.count_at(0x0600), counts.count_at(0x0601), counts.count_at(0x0603)), (2, 3, 1));
+        assert_eq!(counts.count_of(0xD0), 3);
+        assert!(cpu.execution_counts.is_none());
+    }
+}
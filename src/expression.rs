@@ -0,0 +1,335 @@
+use std::fmt;
+use crate::cpu6502::CPU;
+use crate::labels::Labels;
+
+// Debugger expressions over registers and memory, e.g. `word[0x00FD]`, `A + X` or
+// `[oam + 4] == $FF && PC >= $C000`, parsed once and evaluated against the CPU as often as needed
+// (watches, and conditions of breakpoints). Memory is only peeked, so evaluating an expression
+// never changes the emulation state.
+// - values: $hex, 0xhex, %binary, decimal, or a label name (its address)
+// - registers: A, X, Y, P, SP (or S), PC
+// - memory: byte[address] (or [address]) and word[address] (little-endian)
+// - operators, from the lowest precedence: || && | ^ & (== !=) (< <= > >=) (<< >>) (+ -) (* / %),
+//   and the unary - ! ~. Comparisons and logical operators give 1 or 0.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Register {
+    A,
+    X,
+    Y,
+    P,
+    Sp,
+    Pc,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    Number(i64),
+    Register(Register),
+    Byte(Box<Node>),
+    Word(Box<Node>),
+    Unary(&'static str, Box<Node>),
+    Binary(&'static str, Box<Node>, Box<Node>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(i64),
+    Name(String),
+    Operator(&'static str),
+}
+
+// Longest first, so that "<<" is not read as two "<"
+const OPERATORS: [&str; 25] = [
+    "<<", ">>", "<=", ">=", "==", "!=", "&&", "||",
+    "+", "-", "*", "/", "%", "<", ">", "&", "^", "|", "!", "~", "(", ")", "[", "]", "=",
+];
+
+// Binary operators by precedence level, from the lowest
+const PRECEDENCE: [&[&str]; 10] = [
+    &["||"], &["&&"], &["|"], &["^"], &["&"], &["==", "!="], &["<", "<=", ">", ">="], &["<<", ">>"], &["+", "-"], &["*", "/", "%"],
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expression {
+    text: String,
+    root: Node,
+}
+
+impl Expression {
+    // Label names are resolved when parsing, with `labels`.
+    pub fn parse(text: &str, labels: &Labels) -> Result<Self, String> {
+        let tokens = tokenize(text)?;
+        let mut parser = Parser { tokens: &tokens, position: 0, labels };
+        let root = parser.binary(0)?;
+        if let Some(token) = tokens.get(parser.position) {
+            return Err(format!("Unexpected `{}` in `{}`", token, text.trim()));
+        }
+        Ok(Self { text: text.trim().to_string(), root })
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn evaluate(&self, cpu: &CPU) -> Result<i64, String> {
+        evaluate(&self.root, cpu)
+    }
+
+    // For conditions: any value other than 0 is true
+    pub fn is_true(&self, cpu: &CPU) -> Result<bool, String> {
+        Ok(self.evaluate(cpu)? != 0)
+    }
+}
+
+impl fmt::Display for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.text)
+    }
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Token::Number(value) => write!(f, "{}", value),
+            Token::Name(name) => write!(f, "{}", name),
+            Token::Operator(operator) => write!(f, "{}", operator),
+        }
+    }
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut rest = text.trim_start();
+    while let Some(c) = rest.chars().next() {
+        let length = if let Some(operator) = OPERATORS.iter().find(|operator| rest.starts_with(*operator)) {
+            // `%` is also the prefix of binary numbers, when a value is expected
+            let binary = *operator == "%" && !matches!(tokens.last(), Some(Token::Number(_) | Token::Name(_)) | Some(Token::Operator(")" | "]")));
+            if binary {
+                let digits = rest[1..].find(|c: char| c != '0' && c != '1').map_or(rest.len(), |end| end + 1);
+                let value = i64::from_str_radix(&rest[1..digits], 2).map_err(|_| format!("Invalid binary number in `{}`", text.trim()))?;
+                tokens.push(Token::Number(value));
+                digits
+            } else {
+                tokens.push(Token::Operator(operator));
+                operator.len()
+            }
+        } else if c == '$' || c.is_ascii_alphanumeric() || c == '_' {
+            let end = rest[1..].find(|c: char| !(c.is_ascii_alphanumeric() || c == '_')).map_or(rest.len(), |end| end + 1);
+            let word = &rest[..end];
+            let hex = word.strip_prefix('$').or_else(|| word.strip_prefix("0x")).or_else(|| word.strip_prefix("0X"));
+            let token = match hex {
+                Some(digits) => Token::Number(i64::from_str_radix(digits, 16).map_err(|_| format!("Invalid number `{}`", word))?),
+                None if c.is_ascii_digit() => Token::Number(word.parse().map_err(|_| format!("Invalid number `{}`", word))?),
+                None => Token::Name(word.to_string()),
+            };
+            tokens.push(token);
+            end
+        } else {
+            return Err(format!("Unexpected `{}` in `{}`", c, text.trim()));
+        };
+        rest = rest[length..].trim_start();
+    }
+    Ok(tokens)
+}
+
+struct Parser<'a> {
+    tokens: &'a [Token],
+    position: usize,
+    labels: &'a Labels,
+}
+
+impl Parser<'_> {
+    fn next(&mut self) -> Option<&Token> {
+        let token = self.tokens.get(self.position);
+        self.position += 1;
+        token
+    }
+
+    fn eat(&mut self, operator: &str) -> bool {
+        let found = matches!(self.tokens.get(self.position), Some(Token::Operator(next)) if *next == operator);
+        if found {
+            self.position += 1;
+        }
+        found
+    }
+
+    fn expect(&mut self, operator: &str) -> Result<(), String> {
+        match self.eat(operator) {
+            true => Ok(()),
+            false => Err(format!("Expected `{}`", operator)),
+        }
+    }
+
+    fn binary(&mut self, level: usize) -> Result<Node, String> {
+        if level == PRECEDENCE.len() {
+            return self.unary();
+        }
+        let mut left = self.binary(level + 1)?;
+        while let Some(Token::Operator(operator)) = self.tokens.get(self.position) {
+            if !PRECEDENCE[level].contains(operator) {
+                break;
+            }
+            self.position += 1;
+            let right = self.binary(level + 1)?;
+            left = Node::Binary(operator, Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<Node, String> {
+        if let Some(Token::Operator(operator @ ("-" | "!" | "~"))) = self.tokens.get(self.position) {
+            self.position += 1;
+            return Ok(Node::Unary(operator, Box::new(self.unary()?)));
+        }
+        self.primary()
+    }
+
+    fn memory(&mut self) -> Result<Box<Node>, String> {
+        let address = self.binary(0)?;
+        self.expect("]")?;
+        Ok(Box::new(address))
+    }
+
+    fn primary(&mut self) -> Result<Node, String> {
+        match self.next().cloned() {
+            Some(Token::Number(value)) => Ok(Node::Number(value)),
+            Some(Token::Operator("(")) => {
+                let node = self.binary(0)?;
+                self.expect(")")?;
+                Ok(node)
+            }
+            Some(Token::Operator("[")) => Ok(Node::Byte(self.memory()?)),
+            Some(Token::Name(name)) => {
+                let register = match name.to_ascii_uppercase().as_str() {
+                    "A" => Some(Register::A),
+                    "X" => Some(Register::X),
+                    "Y" => Some(Register::Y),
+                    "P" => Some(Register::P),
+                    "SP" | "S" => Some(Register::Sp),
+                    "PC" => Some(Register::Pc),
+                    _ => None,
+                };
+                if let Some(register) = register {
+                    return Ok(Node::Register(register));
+                }
+                match name.to_ascii_lowercase().as_str() {
+                    "byte" if self.eat("[") => Ok(Node::Byte(self.memory()?)),
+                    "word" if self.eat("[") => Ok(Node::Word(self.memory()?)),
+                    _ => match self.labels.address_of(&name) {
+                        Some(address) => Ok(Node::Number(address as i64)),
+                        None => Err(format!("Unknown register or label `{}`", name)),
+                    },
+                }
+            }
+            Some(token) => Err(format!("Unexpected `{}`", token)),
+            None => Err("Unexpected end of expression".to_string()),
+        }
+    }
+}
+
+fn evaluate(node: &Node, cpu: &CPU) -> Result<i64, String> {
+    Ok(match node {
+        Node::Number(value) => *value,
+        Node::Register(register) => match register {
+            Register::A => cpu.accumulator as i64,
+            Register::X => cpu.x_register as i64,
+            Register::Y => cpu.y_register as i64,
+            Register::P => cpu.status_register as i64,
+            Register::Sp => cpu.stack_pointer as i64,
+            Register::Pc => cpu.program_counter as i64,
+        },
+        Node::Byte(address) => cpu.peek_u8(evaluate(address, cpu)? as u16) as i64,
+        Node::Word(address) => cpu.peek_u16(evaluate(address, cpu)? as u16) as i64,
+        Node::Unary(operator, operand) => {
+            let value = evaluate(operand, cpu)?;
+            match *operator {
+                "-" => value.wrapping_neg(),
+                "!" => (value == 0) as i64,
+                _ => !value,
+            }
+        }
+        Node::Binary(operator, left, right) => {
+            let left = evaluate(left, cpu)?;
+            // Short-circuit, like in C
+            match *operator {
+                "&&" if left == 0 => return Ok(0),
+                "||" if left != 0 => return Ok(1),
+                _ => {}
+            }
+            let right = evaluate(right, cpu)?;
+            match *operator {
+                "+" => left.wrapping_add(right),
+                "-" => left.wrapping_sub(right),
+                "*" => left.wrapping_mul(right),
+                "/" | "%" if right == 0 => return Err("Division by zero".to_string()),
+                "/" => left.wrapping_div(right),
+                "%" => left.wrapping_rem(right),
+                "<<" => left.wrapping_shl(right as u32),
+                ">>" => left.wrapping_shr(right as u32),
+                "&" => left & right,
+                "^" => left ^ right,
+                "|" => left | right,
+                "==" => (left == right) as i64,
+                "!=" => (left != right) as i64,
+                "<" => (left < right) as i64,
+                "<=" => (left <= right) as i64,
+                ">" => (left > right) as i64,
+                ">=" => (left >= right) as i64,
+                _ => (right != 0) as i64, // && and || with the left operand already checked
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bus::Bus;
+    use crate::cpu6502::{new_cpu, CPU};
+    use crate::expression::Expression;
+    use crate::labels::Labels;
+
+    fn cpu() -> CPU {
+        let mut cpu = new_cpu(Bus::new_flat());
+        cpu.accumulator = 0x10;
+        cpu.x_register = 0x05;
+        cpu.stack_pointer = 0xFD;
+        cpu.program_counter = 0xC000;
+        cpu.bus.poke_u8(0x00FD, 0x34).unwrap();
+        cpu.bus.poke_u8(0x00FE, 0x12).unwrap();
+        cpu
+    }
+
+    fn eval(text: &str) -> Result<i64, String> {
+        let mut labels = Labels::new();
+        labels.insert(0x00FD, "pointer", 2);
+        Expression::parse(text, &labels)?.evaluate(&cpu())
+    }
+
+    #[test]
+    fn test_evaluate() {
+        assert_eq!(eval("word[0x00FD]"), Ok(0x1234));
+        assert_eq!(eval("A + X"), Ok(0x15));
+        assert_eq!(eval("[$FD] + byte[pointer + 1] * 2"), Ok(0x34 + 0x24));
+        assert_eq!(eval("word[pointer]"), Ok(0x1234));
+        assert_eq!(eval("(a + x) * 2"), Ok(0x2A));
+        assert_eq!(eval("SP == $FD && PC >= $C000"), Ok(1));
+        assert_eq!(eval("S < %11 || !X"), Ok(0));
+        assert_eq!(eval("1 << 4 | 1"), Ok(17));
+        assert_eq!(eval("-X + ~0"), Ok(-6));
+        assert_eq!(eval("A % 3"), Ok(1));
+        // The right operand is not evaluated
+        assert_eq!(eval("0 && 1 / 0"), Ok(0));
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(eval("A / 0"), Err("Division by zero".to_string()));
+        assert!(eval("missing + 1").unwrap_err().contains("Unknown register or label"));
+        assert!(eval("word[$10").is_err());
+        assert!(eval("A +").is_err());
+        assert!(eval("A X").is_err());
+        assert!(eval("A # 1").is_err());
+        assert!(eval("$GG").is_err());
+    }
+}
@@ -0,0 +1,206 @@
+// Family BASIC keyboard (HVC-007), plugged into the Famicom expansion port. It is used by Family
+// BASIC, and by games with a level editor such as Lode Runner to name and save levels.
+// The 72 keys form a matrix of 9 rows of 2 columns of 4 keys, scanned through the controller ports:
+// - write 0x4016: bit 0 resets to row 0, bit 1 selects the column (going from 1 to 0 moves to the
+//   next row), bit 2 enables the keyboard (disabled, every key reads as pressed),
+// - read 0x4017: bits 1-4 are the 4 keys of the selected row and column, 0 when pressed.
+// Keys are named as printed on the keyboard. While the frontend passes the host keyboard through
+// (see `Hotkey::KeyboardPassthrough`), its keys are translated by `host_key`.
+
+use crate::savestate::{StateReader, StateWriter};
+
+// NES 2.0 expansion device number of the keyboard
+pub const FAMILY_BASIC_KEYBOARD_DEVICE: u8 = 0x23;
+
+const ROWS: usize = 9;
+
+// [row][column][bit 1 to bit 4]
+const KEY_MATRIX: [[[&str; 4]; 2]; ROWS] = [
+    [["F8", "RETURN", "[", "]"], ["KANA", "RSHIFT", "YEN", "STOP"]],
+    [["F7", "@", ":", ";"], ["_", "/", "-", "^"]],
+    [["F6", "O", "L", "K"], [".", ",", "P", "0"]],
+    [["F5", "I", "U", "J"], ["M", "N", "9", "8"]],
+    [["F4", "Y", "G", "H"], ["B", "V", "7", "6"]],
+    [["F3", "T", "R", "D"], ["F", "C", "5", "4"]],
+    [["F2", "W", "S", "A"], ["X", "Z", "E", "3"]],
+    [["F1", "ESC", "Q", "CTR"], ["LSHIFT", "GRPH", "1", "2"]],
+    [["CLR", "UP", "RIGHT", "LEFT"], ["DOWN", "SPACE", "DEL", "INS"]],
+];
+
+// Host keys (SDL names) whose keyboard key has another name
+const HOST_KEYS: [(&str, &str); 16] = [
+    ("Return", "RETURN"), ("Escape", "ESC"), ("Left Ctrl", "CTR"), ("Left Shift", "LSHIFT"),
+    ("Right Shift", "RSHIFT"), ("Left Alt", "GRPH"), ("Right Alt", "KANA"), ("Home", "CLR"),
+    ("Insert", "INS"), ("Delete", "DEL"), ("Backspace", "DEL"), ("Space", "SPACE"),
+    ("End", "STOP"), ("\\", "YEN"), ("=", "^"), ("'", ":"),
+];
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FamilyBasicKeyboard {
+    // Pressed keys of each row and column, bit N being read on bit N + 1
+    keys: [[u8; 2]; ROWS],
+    row: usize,
+    column: usize,
+    enabled: bool,
+}
+
+impl FamilyBasicKeyboard {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn position(key: &str) -> Option<(usize, usize, usize)> {
+        (0..ROWS).flat_map(|row| (0..2).flat_map(move |column| (0..4).map(move |bit| (row, column, bit))))
+            .find(|(row, column, bit)| KEY_MATRIX[*row][*column][*bit] == key)
+    }
+
+    // Keyboard key typed by a host key, if any. Letters, digits, arrows, F1 - F8 and most
+    // punctuation keep their place.
+    pub fn host_key(host_key: &str) -> Option<&'static str> {
+        if let Some((_, key)) = HOST_KEYS.iter().find(|(host, _)| *host == host_key) {
+            return Some(key);
+        }
+        let upper = host_key.to_uppercase();
+        KEY_MATRIX.iter().flatten().flatten().find(|key| **key == upper).copied()
+    }
+
+    // `key` is a keyboard key name (see KEY_MATRIX). Returns an error for unknown keys.
+    pub fn set_key_pressed(&mut self, key: &str, pressed: bool) -> Result<(), String> {
+        let (row, column, bit) = Self::position(key).ok_or_else(|| format!("Unknown Family BASIC key `{}`", key))?;
+        if pressed {
+            self.keys[row][column] |= 1 << bit;
+        } else {
+            self.keys[row][column] &= !(1 << bit);
+        }
+        Ok(())
+    }
+
+    // Key event of the host keyboard in passthrough mode. Returns false if the key has no equivalent.
+    pub fn set_host_key_pressed(&mut self, host_key: &str, pressed: bool) -> bool {
+        match Self::host_key(host_key) {
+            Some(key) => self.set_key_pressed(key, pressed).is_ok(),
+            None => false,
+        }
+    }
+
+    pub fn release_all(&mut self) {
+        self.keys = [[0; 2]; ROWS];
+    }
+
+    // Write to 0x4016
+    pub fn write(&mut self, data: u8) {
+        let column = ((data >> 1) & 1) as usize;
+        if data & 1 != 0 {
+            self.row = 0;
+        } else if self.column == 1 && column == 0 {
+            self.row += 1;
+        }
+        self.column = column;
+        self.enabled = data & 0b100 != 0;
+    }
+
+    // Bits 1-4 of 0x4017. After the last row, no key is pressed.
+    pub fn read(&self) -> u8 {
+        if !self.enabled {
+            return 0;
+        }
+        let pressed = self.keys.get(self.row).map_or(0, |columns| columns[self.column]);
+        (!pressed & 0x0F) << 1
+    }
+
+    pub fn save_state(&self, writer: &mut StateWriter) {
+        for columns in &self.keys {
+            writer.write_bytes(columns);
+        }
+        // Every row past the last one reads the same
+        writer.write_u8(self.row.min(ROWS) as u8);
+        writer.write_u8(self.column as u8);
+        writer.write_bool(self.enabled);
+    }
+
+    pub fn load_state(&mut self, reader: &mut StateReader) -> Result<(), String> {
+        for columns in &mut self.keys {
+            let bytes = reader.read_bytes(2)?;
+            *columns = [bytes[0] & 0x0F, bytes[1] & 0x0F];
+        }
+        self.row = (reader.read_u8()? as usize).min(ROWS);
+        self.column = (reader.read_u8()? & 1) as usize;
+        self.enabled = reader.read_bool()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bus::Bus;
+    use crate::family_keyboard::FamilyBasicKeyboard;
+    use crate::rom::Rom;
+
+    // Scans the matrix like Family BASIC does: 2 reads per row
+    fn scan(keyboard: &mut FamilyBasicKeyboard) -> Vec<u8> {
+        let mut reads = Vec::new();
+        keyboard.write(0x05);
+        for _ in 0..9 {
+            keyboard.write(0x04);
+            reads.push(keyboard.read());
+            keyboard.write(0x06);
+            reads.push(keyboard.read());
+        }
+        reads
+    }
+
+    #[test]
+    fn test_matrix_scan() {
+        let mut keyboard = FamilyBasicKeyboard::new();
+        keyboard.set_key_pressed("A", true).unwrap();
+        keyboard.set_key_pressed("RETURN", true).unwrap();
+        keyboard.set_key_pressed("SPACE", true).unwrap();
+        assert!(keyboard.set_key_pressed("F9", true).is_err());
+
+        let mut expected = vec![0x1E; 18];
+        expected[0] = 0x1E & !0b0000_0100; // Row 0, column 0: RETURN on bit 2
+        expected[12] = 0x1E & !0b0001_0000; // Row 6, column 0: A on bit 4
+        expected[17] = 0x1E & !0b0000_0100; // Row 8, column 1: SPACE on bit 2
+        assert_eq!(scan(&mut keyboard), expected);
+
+        // Past the last row
+        keyboard.write(0x04);
+        assert_eq!(keyboard.read(), 0x1E);
+        // Disabled
+        keyboard.write(0x00);
+        assert_eq!(keyboard.read(), 0);
+    }
+
+    #[test]
+    fn test_host_keys() {
+        assert_eq!(FamilyBasicKeyboard::host_key("q"), Some("Q"));
+        assert_eq!(FamilyBasicKeyboard::host_key("Return"), Some("RETURN"));
+        assert_eq!(FamilyBasicKeyboard::host_key("Backspace"), Some("DEL"));
+        assert_eq!(FamilyBasicKeyboard::host_key("F12"), None);
+
+        let mut keyboard = FamilyBasicKeyboard::new();
+        assert!(keyboard.set_host_key_pressed("Left Shift", true));
+        assert!(!keyboard.set_host_key_pressed("Page Up", true));
+        keyboard.release_all();
+        assert_eq!(scan(&mut keyboard), vec![0x1E; 18]);
+    }
+
+    #[test]
+    fn test_expansion_port() {
+        let mut bus = Bus::new(Rom::test_rom());
+        assert!(bus.family_keyboard().is_none());
+        bus.set_family_keyboard(Some(FamilyBasicKeyboard::new()));
+        bus.family_keyboard_mut().unwrap().set_key_pressed("F8", true).unwrap();
+
+        bus.write_u8(0x4016, 0x05);
+        bus.write_u8(0x4016, 0x04);
+        // F8 is bit 1 of row 0, column 0; bit 0 is controller 2, with no button pressed; bit 6 is open bus
+        assert_eq!(bus.read_u8(0x4017), 0b0101_1100);
+
+        // NES 2.0 header requesting the keyboard
+        let mut rom = Rom::test_rom();
+        rom.header.flags_7 |= 0b0000_1000;
+        rom.header.reserved[4] = 0x23;
+        assert!(Bus::new(rom).family_keyboard().is_some());
+    }
+}
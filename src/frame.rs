@@ -0,0 +1,327 @@
+use crate::cpu6502::CPU;
+use crate::freeze::FreezeTiming;
+use crate::ppu::NmiEdge;
+use crate::scheduler::EventKind;
+
+// An NTSC frame lasts 262 scanlines of 341 PPU dots, and the PPU runs 3 times faster than the CPU.
+// A frame is therefore 29780.67 CPU cycles long, so frame boundaries are computed in PPU dots
+// to avoid drifting over long runs.
+pub const PPU_DOTS_PER_SCANLINE: u64 = 341;
+pub const SCANLINES_PER_FRAME: u64 = 262;
+pub const PPU_DOTS_PER_FRAME: u64 = PPU_DOTS_PER_SCANLINE * SCANLINES_PER_FRAME;
+pub const PPU_DOTS_PER_CPU_CYCLE: u64 = 3;
+// Last scanline of vblank, followed by the pre-render scanline
+const LAST_VBLANK_SCANLINE: u64 = 260;
+// Position of the start of vblank (dot 1 of scanline 241) in a frame
+const VBLANK_START_DOT: u64 = 241 * PPU_DOTS_PER_SCANLINE + 1;
+
+// Overclocking inserts "virtual" scanlines after the last vblank scanline. The PPU is paused during
+// them (the frame is not rendered and the vblank flag stays set), but the CPU keeps running, so
+// games doing too much work per frame get more time, which reduces slowdowns and sprite flicker.
+// NMI still happens once per frame so the game logic and music stay at 60 updates per second,
+// and the APU must not be clocked during the extra scanlines, otherwise the music would play slower.
+// The frame is longer in CPU time, so the emulator needs more host time to run each frame.
+
+// Frames are counted on a timeline of PPU dots: the dots elapsed in CPU time, plus the dots the PPU
+// skipped on odd frames (the PPU is then one dot ahead of the CPU time), so frame boundaries follow
+// the frames the PPU draws.
+
+impl CPU {
+    // Index of the frame the CPU is currently executing.
+    pub fn frame_number(&self) -> u64 {
+        self.frame_dots() / self.dots_per_frame()
+    }
+
+    // Number of overclocking scanlines added to each frame (0 disables overclocking).
+    // Must be set before running, since changing the frame length shifts the frame boundaries.
+    pub fn set_overclock_scanlines(&mut self, extra_scanlines: u16) {
+        self.extra_scanlines = extra_scanlines as u64;
+    }
+
+    // Length of a frame in PPU dots, including the overclocking scanlines.
+    pub fn dots_per_frame(&self) -> u64 {
+        PPU_DOTS_PER_FRAME + self.extra_scanlines * PPU_DOTS_PER_SCANLINE
+    }
+
+    // True while the CPU runs an overclocking scanline, during which the PPU and the APU are paused.
+    pub fn is_overclock_scanline(&self) -> bool {
+        let scanline = (self.frame_dots() % self.dots_per_frame()) / PPU_DOTS_PER_SCANLINE;
+        scanline > LAST_VBLANK_SCANLINE && scanline <= LAST_VBLANK_SCANLINE + self.extra_scanlines
+    }
+
+    // Number of PPU dots elapsed since power-on.
+    pub fn ppu_dots(&self) -> u64 {
+        self.cycles * PPU_DOTS_PER_CPU_CYCLE + self.alignment_dots()
+    }
+
+    // Position on the timeline of the frames: the PPU dots elapsed plus the dots skipped by odd frames.
+    pub fn frame_dots(&self) -> u64 {
+        self.ppu_dots() + self.bus.ppu().skipped_dots()
+    }
+
+    // PPU dots the CPU accesses see in advance with the power-on alignment: 0 for alignments 0 and
+    // 1, 1 for 2 and 3, the only 2 cases a dot-based PPU tells apart (see PowerOnConfig::ppu_alignment)
+    fn alignment_dots(&self) -> u64 {
+        self.ppu_alignment / 2
+    }
+
+    // Position of the PPU (scanline, dot) matching the current CPU cycle, including the dots it
+    // has not caught up yet.
+    // During overclocking scanlines, the PPU stays at the end of the last vblank scanline.
+    pub fn ppu_position(&self) -> (u64, u64) {
+        let dots = self.frame_dots() % self.dots_per_frame();
+        let scanline = dots / PPU_DOTS_PER_SCANLINE;
+        match scanline {
+            _ if scanline <= LAST_VBLANK_SCANLINE => (scanline, dots % PPU_DOTS_PER_SCANLINE),
+            _ if self.is_overclock_scanline() => (LAST_VBLANK_SCANLINE, PPU_DOTS_PER_SCANLINE - 1),
+            _ => (SCANLINES_PER_FRAME - 1, dots % PPU_DOTS_PER_SCANLINE),
+        }
+    }
+
+    // Dots the PPU is paused before `frame_dots` on the timeline, during the overclocking scanlines.
+    fn paused_dots(&self, frame_dots: u64) -> u64 {
+        let extra_dots = self.extra_scanlines * PPU_DOTS_PER_SCANLINE;
+        let pause_start = (LAST_VBLANK_SCANLINE + 1) * PPU_DOTS_PER_SCANLINE;
+        let in_frame = (frame_dots % self.dots_per_frame()).saturating_sub(pause_start).min(extra_dots);
+        frame_dots / self.dots_per_frame() * extra_dots + in_frame
+    }
+
+    // Catches the PPU up with the start of CPU cycle `cycle`.
+    pub fn sync_ppu(&self, cycle: u64) {
+        if !self.bus.has_ppu() {
+            return;
+        }
+        let elapsed = cycle * PPU_DOTS_PER_CPU_CYCLE + self.alignment_dots();
+        let ppu = self.bus.ppu();
+        let target = elapsed - self.paused_dots(elapsed + ppu.skipped_dots());
+        let dots = target.saturating_sub(ppu.dots);
+        drop(ppu);
+        self.bus.run_ppu(dots);
+    }
+
+    // Catches the PPU up before an access to its registers, so it sees the PPU of its cycle, and
+    // before a write to the cartridge, whose bank switching and mirroring changes only apply to the
+    // dots that follow it.
+    pub fn sync_ppu_for_access(&self, addr: u16, write: bool) {
+        if (0x2000..=0x3FFF).contains(&addr) || (write && addr >= 0x4020) {
+            self.sync_ppu(self.bus.access_cycle() + 1);
+        }
+    }
+
+    // The PPU runs lazily: besides the accesses above, it is only caught up at the start of each
+    // vblank, when it raises its NMI, and at the end of each frame. Called after each instruction.
+    pub fn run_ppu_events(&mut self) {
+        if self.scheduler.is_empty() {
+            self.schedule_vblank();
+        }
+        while let Some((_, kind)) = self.scheduler.pop_due(self.cycles) {
+            if kind == EventKind::Nmi {
+                self.sync_ppu(self.cycles);
+                self.raise_ppu_nmi();
+                self.schedule_vblank();
+            }
+        }
+        if !self.bus.has_ppu() {
+            return;
+        }
+        if let Some((frame, scanline, dot)) = self.bus.ppu_mut().take_sprite_zero_hit() {
+            self.record_event_at(crate::event_viewer::EventKind::SpriteZeroHit, frame, scanline as u64, dot as u64);
+        }
+    }
+
+    // Schedules the next start of vblank the PPU has not reached. The prediction can be a dot early
+    // (the PPU may still skip the dot of an odd frame), in which case the event comes back until
+    // the PPU gets there.
+    fn schedule_vblank(&mut self) {
+        if !self.bus.has_ppu() {
+            return;
+        }
+        let ppu = self.bus.ppu();
+        let frame = ppu.last_vblank().map_or(0, |frame| frame + 1);
+        let frame_dots = frame * self.dots_per_frame() + VBLANK_START_DOT;
+        let elapsed = frame_dots.saturating_sub(ppu.skipped_dots() + 1 + self.alignment_dots());
+        drop(ppu);
+        let cycle = (elapsed / PPU_DOTS_PER_CPU_CYCLE).max(self.cycles + 1);
+        self.scheduler.schedule(cycle, EventKind::Nmi);
+    }
+
+    // Requests the NMI raised by the PPU, if any, on the cycle the PPU raised it.
+    pub fn raise_ppu_nmi(&mut self) {
+        let Some(edge) = self.bus.ppu_mut().take_nmi() else {
+            return;
+        };
+        self.request_nmi_at(self.nmi_edge_cycle(edge));
+    }
+
+    // CPU cycle on which the PPU raised `edge`.
+    pub fn nmi_edge_cycle(&self, edge: NmiEdge) -> u64 {
+        match edge {
+            NmiEdge::Vblank { frame } => {
+                let frame_dots = frame * self.dots_per_frame() + VBLANK_START_DOT;
+                let elapsed = frame_dots.saturating_sub(self.bus.ppu().skipped_dots() + self.alignment_dots());
+                elapsed / PPU_DOTS_PER_CPU_CYCLE
+            }
+            NmiEdge::Enabled => self.bus.access_cycle().saturating_sub(1),
+        }
+    }
+
+    // Executes instructions until the next frame boundary is reached (or the CPU halts),
+    // without any frontend, and returns the hash of the frame.
+    // An instruction is never split, so a frame can overshoot its boundary by a few cycles;
+    // the overshoot is absorbed by the next frame.
+    pub fn run_frame(&mut self) -> u64 {
+        if self.frozen_memory.timing == FreezeTiming::EveryFrame {
+            self.frozen_memory.apply(&mut self.bus);
+        }
+        let frame_end = (self.frame_number() + 1) * self.dots_per_frame();
+        // First CPU cycle at or after the frame boundary, which the dots skipped so far bring earlier
+        let skipped_dots = self.bus.ppu().skipped_dots();
+        self.run_until((frame_end - skipped_dots - self.alignment_dots()).div_ceil(PPU_DOTS_PER_CPU_CYCLE));
+        self.sync_ppu(self.cycles);
+        self.bus.joypads_mut().end_frame();
+        self.frame_hash()
+    }
+
+    // Deterministic hash identifying the current frame, used to compare runs in tests and stored
+    // by the frame hash corpus. It covers the last complete frame of the PPU framebuffer, and what
+    // the game computed: the registers, the internal RAM and PRG RAM, in a fixed layout. Unlike the
+    // savestates, it does not change with the emulator (cycle counter, format version), but it does
+    // with the palette used for the framebuffer.
+    pub fn frame_hash(&self) -> u64 {
+        let [pc_low, pc_high] = self.program_counter.to_le_bytes();
+        let mut data = vec![self.accumulator, self.x_register, self.y_register, self.status_register, self.stack_pointer, pc_low, pc_high];
+        data.extend_from_slice(self.bus.internal_ram());
+        data.extend_from_slice(self.bus.prg_ram());
+        data.extend_from_slice(self.bus.ppu().framebuffer());
+        fnv1a_hash(&data)
+    }
+}
+
+// 64-bit FNV-1a hash. Unlike `std::hash::DefaultHasher`, its output is guaranteed
+// to be stable across Rust versions, so hashes can be stored in test expectations.
+pub fn fnv1a_hash(data: &[u8]) -> u64 {
+    let mut hash: u64 = 0xCBF2_9CE4_8422_2325;
+    for byte in data {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x0000_0100_0000_01B3);
+    }
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bus::Bus;
+    use crate::cpu6502::new_cpu;
+    use crate::frame::{fnv1a_hash, PPU_DOTS_PER_CPU_CYCLE, PPU_DOTS_PER_FRAME, PPU_DOTS_PER_SCANLINE};
+    use crate::power_on::PowerOnConfig;
+    use crate::rom::Rom;
+
+    #[test]
+    fn test_fnv1a_hash_reference_values() {
+        assert_eq!(fnv1a_hash(b""), 0xCBF2_9CE4_8422_2325);
+        assert_eq!(fnv1a_hash(b"a"), 0xAF63_DC4C_8601_EC8C);
+    }
+
+    #[test]
+    fn test_frame_hash_layout() {
+        let mut cpu = new_cpu(Bus::new(Rom::test_rom()));
+        // Recorded corpora depend on this value: it must not change
+        assert_eq!(cpu.frame_hash(), 0xC685_8B9C_3FC7_6A12);
+        let hash = cpu.frame_hash();
+        cpu.cycles += 1000;
+        cpu.ppu_alignment = 2;
+        assert_eq!(cpu.frame_hash(), hash);
+        cpu.write_u8(0x6000, 1);
+        assert_ne!(cpu.frame_hash(), hash);
+    }
+
+    #[test]
+    fn test_frame_hash_covers_the_framebuffer() {
+        let mut cpus = [0x0F, 0x16].map(|backdrop| {
+            let mut cpu = new_cpu(Bus::new(Rom::test_rom()));
+            cpu.power_on(&PowerOnConfig::default()).unwrap();
+            // Rendering is disabled, the frame shows the palette entry at v
+            cpu.write_u8(0x2006, 0x3F);
+            cpu.write_u8(0x2006, 0x00);
+            cpu.write_u8(0x2007, backdrop);
+            cpu.write_u8(0x2006, 0x3F);
+            cpu.write_u8(0x2006, 0x00);
+            cpu
+        });
+        assert_eq!(cpus[0].frame_hash(), cpus[1].frame_hash());
+        let hashes = cpus.each_mut().map(|cpu| {
+            cpu.run_frame();
+            cpu.run_frame()
+        });
+        assert_ne!(hashes[0], hashes[1]);
+    }
+
+    #[test]
+    fn test_run_frame_stops_at_frame_boundary() {
+        let mut cpu = new_cpu(Bus::new(Rom::test_rom()));
+        cpu.power_on(&PowerOnConfig::default()).unwrap();
+
+        assert_eq!(cpu.frame_number(), 0);
+        cpu.run_frame();
+        assert_eq!(cpu.frame_number(), 1);
+        // The boundary can only be overshot by less than one instruction (7 cycles at most)
+        assert!(cpu.cycles * PPU_DOTS_PER_CPU_CYCLE >= PPU_DOTS_PER_FRAME);
+        assert!(cpu.cycles * PPU_DOTS_PER_CPU_CYCLE < PPU_DOTS_PER_FRAME + 7 * PPU_DOTS_PER_CPU_CYCLE);
+
+        cpu.run_frame();
+        assert_eq!(cpu.frame_number(), 2);
+    }
+
+    #[test]
+    fn test_ppu_position() {
+        let mut cpu = new_cpu(Bus::new(Rom::test_rom()));
+        cpu.cycles = 7;
+        assert_eq!(cpu.ppu_position(), (0, 21));
+        // Values taken from nestest.log
+        cpu.cycles = 26554;
+        assert_eq!(cpu.ppu_position(), (233, 209));
+        // The position wraps around at the end of each frame
+        cpu.cycles = PPU_DOTS_PER_FRAME;
+        assert_eq!(cpu.ppu_position(), (0, 0));
+    }
+
+    #[test]
+    fn test_overclock_scanlines() {
+        let mut cpu = new_cpu(Bus::new(Rom::test_rom()));
+        cpu.power_on(&PowerOnConfig::default()).unwrap();
+        cpu.set_overclock_scanlines(20);
+        assert_eq!(cpu.dots_per_frame(), PPU_DOTS_PER_FRAME + 20 * PPU_DOTS_PER_SCANLINE);
+
+        // Vblank scanlines are unchanged
+        cpu.cycles = 260 * PPU_DOTS_PER_SCANLINE / PPU_DOTS_PER_CPU_CYCLE + 1;
+        assert_eq!(cpu.ppu_position().0, 260);
+        assert!(!cpu.is_overclock_scanline());
+
+        // The PPU is paused during the extra scanlines
+        cpu.cycles = 270 * PPU_DOTS_PER_SCANLINE / PPU_DOTS_PER_CPU_CYCLE;
+        assert!(cpu.is_overclock_scanline());
+        assert_eq!(cpu.ppu_position(), (260, 340));
+
+        // Then the pre-render scanline comes
+        cpu.cycles = (281 * PPU_DOTS_PER_SCANLINE + 5) / PPU_DOTS_PER_CPU_CYCLE;
+        assert!(!cpu.is_overclock_scanline());
+        assert_eq!(cpu.ppu_position(), (261, 5));
+
+        cpu.cycles = 0;
+        cpu.run_frame();
+        assert_eq!(cpu.frame_number(), 1);
+        assert!(cpu.cycles * PPU_DOTS_PER_CPU_CYCLE >= cpu.dots_per_frame());
+    }
+
+    #[test]
+    fn test_run_frame_is_deterministic() {
+        let mut first = new_cpu(Bus::new(Rom::test_rom()));
+        let mut second = new_cpu(Bus::new(Rom::test_rom()));
+        first.power_on(&PowerOnConfig::default()).unwrap();
+        second.power_on(&PowerOnConfig::default()).unwrap();
+
+        for _ in 0..3 {
+            assert_eq!(first.run_frame(), second.run_frame());
+        }
+    }
+}
@@ -0,0 +1,204 @@
+use std::fmt;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use crate::nes::Nes;
+use crate::rom::Rom;
+
+// Frame hash regression corpus: the hash of every frame of a set of ROMs, recorded once and
+// compared on later runs, so that a change of the emulation is noticed on the first frame it
+// shows up. The hashes are the ones of `CPU::run_frame`: the framebuffer, the registers and RAM
+// (see `CPU::frame_hash`), independent of the savestate format.
+// The corpus is a text file, so that changes are readable in diffs:
+//   # comment
+//   rom <path, relative to the corpus file> <CRC-32>
+//   <hash of frame 0, 16 hex digits>
+//   <hash of frame 1>
+//   ...
+// Usage: cargo run -- frame-corpus record <corpus> <frames> <rom>...
+//        cargo run -- frame-corpus check <corpus>
+// or FRAME_CORPUS=<corpus> cargo test frame_corpus
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CorpusEntry {
+    // As written in the corpus, relative to its directory
+    pub path: String,
+    pub crc32: u32,
+    pub hashes: Vec<u64>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FrameCorpus {
+    pub entries: Vec<CorpusEntry>,
+}
+
+// Runs `rom` for `frames` frames and returns the hash of each. Stops with an error when the
+// emulation does (e.g. on a component that is not emulated yet).
+pub fn frame_hashes(rom: Rom, frames: usize) -> Result<Vec<u64>, String> {
+    let mut nes = Nes::new(rom)?;
+    let mut hashes = Vec::with_capacity(frames);
+    for frame in 0..frames {
+        match catch_unwind(AssertUnwindSafe(|| nes.run_frame())) {
+            Ok(hash) => hashes.push(hash),
+            Err(panic) => {
+                let message = panic
+                    .downcast_ref::<&str>()
+                    .map(|message| message.to_string())
+                    .or_else(|| panic.downcast_ref::<String>().cloned())
+                    .unwrap_or_else(|| "Unknown panic".to_string());
+                return Err(format!("Emulation stopped on frame {}: {}", frame, message));
+            }
+        }
+    }
+    Ok(hashes)
+}
+
+impl FrameCorpus {
+    pub fn parse(content: &str) -> Result<Self, String> {
+        let mut corpus = FrameCorpus::default();
+        for (index, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error = |message: &str| format!("Frame corpus line {}: {}", index + 1, message);
+            if let Some(rom) = line.strip_prefix("rom ") {
+                let (path, crc32) = rom.trim().rsplit_once(' ').ok_or_else(|| error("Expected a path and a CRC-32"))?;
+                let crc32 = u32::from_str_radix(crc32, 16).map_err(|_| error("Invalid CRC-32"))?;
+                corpus.entries.push(CorpusEntry { path: path.trim().to_string(), crc32, hashes: Vec::new() });
+            } else {
+                let hash = u64::from_str_radix(line, 16).map_err(|_| error("Invalid frame hash"))?;
+                let entry = corpus.entries.last_mut().ok_or_else(|| error("Frame hash before any ROM"))?;
+                entry.hashes.push(hash);
+            }
+        }
+        Ok(corpus)
+    }
+
+    pub fn load_file(path: &Path) -> Result<Self, String> {
+        let content = std::fs::read_to_string(path).map_err(|error| format!("Failed to read {}: {}", path.display(), error))?;
+        Self::parse(&content)
+    }
+
+    pub fn save_file(&self, path: &Path) -> Result<(), String> {
+        std::fs::write(path, self.to_string()).map_err(|error| format!("Failed to write {}: {}", path.display(), error))
+    }
+
+    // Records `frames` frames of the ROM at `rom_path` (relative to `directory`, the one of the
+    // corpus), replacing its previous entry.
+    pub fn record(&mut self, directory: &Path, rom_path: &str, frames: usize) -> Result<(), String> {
+        let rom = Rom::load_file(&directory.join(rom_path).to_string_lossy())?;
+        let entry = CorpusEntry { path: rom_path.to_string(), crc32: rom.crc32(), hashes: frame_hashes(rom, frames)? };
+        match self.entries.iter_mut().find(|existing| existing.path == entry.path) {
+            Some(existing) => *existing = entry,
+            None => self.entries.push(entry),
+        }
+        Ok(())
+    }
+
+    // Runs every ROM of the corpus again. Returns one message per ROM that differs.
+    pub fn check(&self, directory: &Path) -> Vec<String> {
+        self.entries.iter().filter_map(|entry| check_entry(directory, entry).err()).collect()
+    }
+}
+
+fn check_entry(directory: &Path, entry: &CorpusEntry) -> Result<(), String> {
+    let path: PathBuf = directory.join(&entry.path);
+    let rom = Rom::load_file(&path.to_string_lossy()).map_err(|error| format!("{}: {}", entry.path, error))?;
+    if rom.crc32() != entry.crc32 {
+        return Err(format!("{}: The ROM changed (CRC-32 {:08X}, expected {:08X})", entry.path, rom.crc32(), entry.crc32));
+    }
+    let hashes = frame_hashes(rom, entry.hashes.len()).map_err(|error| format!("{}: {}", entry.path, error))?;
+    match hashes.iter().zip(&entry.hashes).position(|(actual, expected)| actual != expected) {
+        Some(frame) => Err(format!(
+            "{}: Frame {} differs (hash {:016X}, expected {:016X})",
+            entry.path, frame, hashes[frame], entry.hashes[frame]
+        )),
+        None => Ok(()),
+    }
+}
+
+impl fmt::Display for FrameCorpus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "# Frame hash corpus (see src/frame_corpus.rs)")?;
+        for entry in &self.entries {
+            writeln!(f, "rom {} {:08X}", entry.path, entry.crc32)?;
+            for hash in &entry.hashes {
+                writeln!(f, "{:016X}", hash)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use crate::frame_corpus::{frame_hashes, CorpusEntry, FrameCorpus};
+    use crate::rom::Rom;
+
+    fn temp_directory(name: &str) -> std::path::PathBuf {
+        let directory = std::env::temp_dir().join(format!("{}_{}", name, std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        directory
+    }
+
+    // Writes the test cartridge as an iNES file
+    fn write_test_rom(path: &Path, rom: &Rom) {
+        let mut data = vec![0x4E, 0x45, 0x53, 0x1A, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        data.extend_from_slice(&rom.prg_rom);
+        data.extend_from_slice(&rom.chr_rom);
+        std::fs::write(path, data).unwrap();
+    }
+
+    #[test]
+    fn test_corpus_round_trip() {
+        let corpus = FrameCorpus {
+            entries: vec![
+                CorpusEntry { path: "roms/a game.nes".to_string(), crc32: 0x1234ABCD, hashes: vec![1, u64::MAX] },
+                CorpusEntry { path: "b.nes".to_string(), crc32: 0, hashes: vec![] },
+            ],
+        };
+        assert_eq!(FrameCorpus::parse(&corpus.to_string()), Ok(corpus));
+        assert!(FrameCorpus::parse("0123456789ABCDEF").is_err());
+        assert!(FrameCorpus::parse("rom a.nes XYZ").is_err());
+    }
+
+    #[test]
+    fn test_record_and_check() {
+        let directory = temp_directory("frame_corpus");
+        write_test_rom(&directory.join("test.nes"), &Rom::test_rom());
+
+        let mut corpus = FrameCorpus::default();
+        corpus.record(&directory, "test.nes", 5).unwrap();
+        assert_eq!(corpus.entries[0].hashes, frame_hashes(Rom::test_rom(), 5).unwrap());
+        assert!(corpus.check(&directory).is_empty());
+
+        // A regression on frame 3
+        corpus.entries[0].hashes[3] ^= 1;
+        assert_eq!(corpus.check(&directory), vec![format!(
+            "test.nes: Frame 3 differs (hash {:016X}, expected {:016X})",
+            corpus.entries[0].hashes[3] ^ 1,
+            corpus.entries[0].hashes[3]
+        )]);
+
+        // Another ROM under the same name
+        let mut rom = Rom::test_rom();
+        rom.prg_rom[0] = 0x18; // CLC
+        write_test_rom(&directory.join("test.nes"), &rom);
+        assert!(corpus.check(&directory)[0].contains("The ROM changed"));
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    // Checks the corpus given by FRAME_CORPUS, if any
+    #[test]
+    fn test_frame_corpus() {
+        let Ok(path) = std::env::var("FRAME_CORPUS") else {
+            println!("FRAME_CORPUS is not set, skipping the frame hash corpus");
+            return;
+        };
+        let path = Path::new(&path);
+        let corpus = FrameCorpus::load_file(path).unwrap();
+        let failures = corpus.check(path.parent().unwrap_or(Path::new(".")));
+        assert!(failures.is_empty(), "Frame hash regressions:\n{}", failures.join("\n"));
+    }
+}
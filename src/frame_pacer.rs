@@ -0,0 +1,217 @@
+use std::time::{Duration, Instant};
+use crate::audio::APU_SAMPLE_RATE;
+use crate::cpu6502::CPU;
+
+// Frame rate of the NTSC NES (the CPU clock divided by the length of a frame in CPU cycles)
+pub const NTSC_FRAME_RATE: f64 = 60.0988;
+
+// Speeds accepted by `set_speed`, from 10% slow motion to 10x fast-forward
+const MIN_SPEED: f32 = 0.1;
+const MAX_SPEED: f32 = 10.0;
+
+// Decides when the frontend runs the next frame: normal speed, slow motion, fast-forward
+// (capped at a multiple of the normal speed, or uncapped), pause and frame advance.
+#[derive(Debug)]
+pub struct FramePacer {
+    speed: f32,
+    // Runs frames as fast as possible, ignoring `speed`
+    uncapped: bool,
+    paused: bool,
+    // Number of frames to run while paused
+    frames_to_advance: u32,
+    // When the next frame is due
+    next_frame_at: Option<Instant>,
+}
+
+impl Default for FramePacer {
+    fn default() -> Self {
+        Self {
+            speed: 1.0,
+            uncapped: false,
+            paused: false,
+            frames_to_advance: 0,
+            next_frame_at: None,
+        }
+    }
+}
+
+impl FramePacer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // 1.0 is the normal speed, 0.5 is slow motion at half speed, 2.0 is fast-forward at twice the speed.
+    pub fn set_speed(&mut self, speed: f32) -> Result<(), String> {
+        if !(MIN_SPEED..=MAX_SPEED).contains(&speed) {
+            return Err(format!("Invalid speed {}: Expected {} to {}", speed, MIN_SPEED, MAX_SPEED));
+        }
+        self.speed = speed;
+        self.next_frame_at = None;
+        Ok(())
+    }
+
+    pub fn speed(&self) -> f32 {
+        self.speed
+    }
+
+    // Uncapped fast-forward: frames run back to back, as fast as the host allows.
+    pub fn set_uncapped(&mut self, uncapped: bool) {
+        self.uncapped = uncapped;
+        self.next_frame_at = None;
+    }
+
+    pub fn is_uncapped(&self) -> bool {
+        self.uncapped
+    }
+
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    pub fn resume(&mut self) {
+        self.paused = false;
+        self.frames_to_advance = 0;
+        self.next_frame_at = None;
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    // Runs exactly one more frame while paused.
+    pub fn frame_advance(&mut self) {
+        if self.paused {
+            self.frames_to_advance += 1;
+        }
+    }
+
+    // Time between two frames, None when uncapped.
+    pub fn frame_duration(&self) -> Option<Duration> {
+        if self.uncapped {
+            return None;
+        }
+        Some(Duration::from_secs_f64(1.0 / (NTSC_FRAME_RATE * self.speed as f64)))
+    }
+
+    // Rate at which the APU samples are produced in real time, to configure the audio resampler
+    // (see Resampler::set_input_rate). None when uncapped: the audio cannot follow, so it is dropped.
+    pub fn audio_input_rate(&self) -> Option<f64> {
+        if self.uncapped {
+            return None;
+        }
+        Some(APU_SAMPLE_RATE * self.speed as f64)
+    }
+
+    // Returns true if a frame must be run now, consuming a frame advance request while paused.
+    pub fn should_run_frame(&mut self) -> bool {
+        if !self.paused {
+            return true;
+        }
+        if self.frames_to_advance > 0 {
+            self.frames_to_advance -= 1;
+            return true;
+        }
+        false
+    }
+
+    // Runs the next frame if it must be run, and returns its hash.
+    pub fn run_frame(&mut self, cpu: &mut CPU) -> Option<u64> {
+        if !self.should_run_frame() {
+            return None;
+        }
+        Some(cpu.run_frame())
+    }
+
+    // Sleeps until the next frame is due. The deadline advances by a fixed duration, so the time
+    // spent emulating is absorbed and the average frame rate stays exact. After a long hiccup
+    // (more than a few frames late), the schedule is restarted instead of running frames in a burst.
+    pub fn wait_for_next_frame(&mut self) {
+        let Some(frame_duration) = self.frame_duration() else {
+            return;
+        };
+        if self.paused {
+            self.next_frame_at = None;
+            return;
+        }
+
+        let now = Instant::now();
+        let next_frame_at = self.next_frame_at.unwrap_or(now) + frame_duration;
+        if next_frame_at > now {
+            std::thread::sleep(next_frame_at - now);
+            self.next_frame_at = Some(next_frame_at);
+        } else if now - next_frame_at > frame_duration * 4 {
+            self.next_frame_at = Some(now);
+        } else {
+            self.next_frame_at = Some(next_frame_at);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+    use crate::audio::APU_SAMPLE_RATE;
+    use crate::bus::Bus;
+    use crate::cpu6502::new_cpu;
+    use crate::frame_pacer::FramePacer;
+    use crate::power_on::PowerOnConfig;
+    use crate::rom::Rom;
+
+    #[test]
+    fn test_speed() {
+        let mut pacer = FramePacer::new();
+        assert_eq!(pacer.frame_duration().map(|duration| duration.as_micros()), Some(16_639));
+
+        pacer.set_speed(2.0).unwrap();
+        assert_eq!(pacer.frame_duration().map(|duration| duration.as_micros()), Some(8_319));
+        assert_eq!(pacer.audio_input_rate(), Some(APU_SAMPLE_RATE * 2.0));
+
+        pacer.set_speed(0.5).unwrap();
+        assert_eq!(pacer.audio_input_rate(), Some(APU_SAMPLE_RATE * 0.5));
+
+        assert!(pacer.set_speed(0.0).is_err());
+        assert!(pacer.set_speed(f32::NAN).is_err());
+        assert_eq!(pacer.speed(), 0.5);
+
+        pacer.set_uncapped(true);
+        assert_eq!(pacer.frame_duration(), None);
+        assert_eq!(pacer.audio_input_rate(), None);
+    }
+
+    #[test]
+    fn test_pause_and_frame_advance() {
+        let mut cpu = new_cpu(Bus::new(Rom::test_rom()));
+        cpu.power_on(&PowerOnConfig::default()).unwrap();
+        let mut pacer = FramePacer::new();
+
+        assert!(pacer.run_frame(&mut cpu).is_some());
+        assert_eq!(cpu.frame_number(), 1);
+
+        pacer.pause();
+        assert!(pacer.run_frame(&mut cpu).is_none());
+        pacer.frame_advance();
+        assert!(pacer.run_frame(&mut cpu).is_some());
+        assert!(pacer.run_frame(&mut cpu).is_none());
+        assert_eq!(cpu.frame_number(), 2);
+
+        pacer.resume();
+        assert!(pacer.should_run_frame());
+    }
+
+    #[test]
+    fn test_wait_for_next_frame() {
+        let mut pacer = FramePacer::new();
+        pacer.set_speed(10.0).unwrap();
+        let start = Instant::now();
+        for _ in 0..5 {
+            pacer.wait_for_next_frame();
+        }
+        // 5 frames at 10x speed last about 8.3 ms
+        assert!(start.elapsed() >= Duration::from_micros(8_000));
+
+        pacer.set_uncapped(true);
+        let start = Instant::now();
+        pacer.wait_for_next_frame();
+        assert!(start.elapsed() < Duration::from_millis(5));
+    }
+}
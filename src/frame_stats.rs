@@ -0,0 +1,225 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+use crate::audio::AudioBuffer;
+use crate::frame_pacer::NTSC_FRAME_RATE;
+
+// Per-frame performance metrics, recorded by `Nes::run_frame`, for the FPS / performance overlay
+// of the frontends and to diagnose slowdowns. The last `HISTORY_FRAMES` frames are kept, to
+// compute averages and the frame rate over the last two seconds.
+
+const HISTORY_FRAMES: usize = 120;
+
+// Host clock of the statistics: time elapsed since an arbitrary origin. `std::time::Instant` panics
+// on wasm32-unknown-unknown, so the core only reads the time through this: the default clock is
+// `system_clock` on native targets, and there is none on wasm32 until the JS bindings inject the
+// browser's (see wasm.rs). Without a clock, the frames are recorded without host time.
+pub type HostClock = fn() -> Duration;
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn system_clock() -> Duration {
+    static ORIGIN: std::sync::OnceLock<std::time::Instant> = std::sync::OnceLock::new();
+    ORIGIN.get_or_init(std::time::Instant::now).elapsed()
+}
+
+fn default_clock() -> Option<HostClock> {
+    #[cfg(not(target_arch = "wasm32"))]
+    return Some(system_clock);
+    #[cfg(target_arch = "wasm32")]
+    return None;
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct FrameStats {
+    // Index of the frame (see CPU::frame_number)
+    pub frame: u64,
+    pub cpu_cycles: u64,
+    pub instructions: u64,
+    // Host time spent emulating each component during the frame
+    pub cpu_time: Duration,
+    pub ppu_time: Duration,
+    // Samples waiting in the audio buffer at the end of the frame, as a fraction of its capacity
+    pub audio_buffer_fill: Option<f32>,
+}
+
+impl FrameStats {
+    pub fn emulation_time(&self) -> Duration {
+        self.cpu_time + self.ppu_time
+    }
+}
+
+pub struct PerformanceMonitor {
+    // Oldest first, with the time each frame ended
+    history: VecDeque<(Duration, FrameStats)>,
+    audio_buffer: Option<AudioBuffer>,
+    clock: Option<HostClock>,
+}
+
+impl Default for PerformanceMonitor {
+    fn default() -> Self {
+        Self { history: VecDeque::new(), audio_buffer: None, clock: default_clock() }
+    }
+}
+
+impl std::fmt::Debug for PerformanceMonitor {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("PerformanceMonitor").field("frames", &self.history.len()).finish()
+    }
+}
+
+impl PerformanceMonitor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // The buffer whose fill level is sampled at the end of each frame (a handle of the one fed to
+    // the audio callback).
+    pub fn set_audio_buffer(&mut self, audio_buffer: Option<AudioBuffer>) {
+        self.audio_buffer = audio_buffer;
+    }
+
+    pub fn clock(&self) -> Option<HostClock> {
+        self.clock
+    }
+
+    pub fn set_clock(&mut self, clock: Option<HostClock>) {
+        self.clock = clock;
+    }
+
+    // Current time of the host clock, None without clock
+    pub fn now(&self) -> Option<Duration> {
+        self.clock.map(|clock| clock())
+    }
+
+    pub fn record(&mut self, mut stats: FrameStats) {
+        self.record_at(self.now().unwrap_or_default(), &mut stats);
+    }
+
+    fn record_at(&mut self, now: Duration, stats: &mut FrameStats) {
+        if let Some(buffer) = &self.audio_buffer {
+            stats.audio_buffer_fill = Some(buffer.len() as f32 / buffer.capacity().max(1) as f32);
+        }
+        if self.history.len() == HISTORY_FRAMES {
+            self.history.pop_front();
+        }
+        self.history.push_back((now, *stats));
+    }
+
+    pub fn clear(&mut self) {
+        self.history.clear();
+    }
+
+    pub fn last_frame(&self) -> Option<&FrameStats> {
+        self.history.back().map(|(_, stats)| stats)
+    }
+
+    pub fn frames(&self) -> impl Iterator<Item = &FrameStats> {
+        self.history.iter().map(|(_, stats)| stats)
+    }
+
+    // Frames shown per second of host time, over the history. None until 2 frames are recorded.
+    pub fn fps(&self) -> Option<f64> {
+        let (first, _) = self.history.front()?;
+        let (last, _) = self.history.back()?;
+        let elapsed = last.saturating_sub(*first).as_secs_f64();
+        (self.history.len() > 1 && elapsed > 0.0).then(|| (self.history.len() - 1) as f64 / elapsed)
+    }
+
+    pub fn average_emulation_time(&self) -> Option<Duration> {
+        let frames = self.history.len() as u32;
+        (frames > 0).then(|| self.frames().map(FrameStats::emulation_time).sum::<Duration>() / frames)
+    }
+
+    // Share of the duration of a frame on the console spent emulating it: above 1, the host is too
+    // slow to run at full speed.
+    pub fn load(&self) -> Option<f64> {
+        Some(self.average_emulation_time()?.as_secs_f64() * NTSC_FRAME_RATE)
+    }
+
+    // Lines of text for a performance overlay
+    pub fn hud_lines(&self) -> Vec<String> {
+        let Some(last) = self.last_frame() else {
+            return Vec::new();
+        };
+        let milliseconds = |duration: Duration| duration.as_secs_f64() * 1000.0;
+        let mut lines = vec![
+            match self.fps() {
+                Some(fps) => format!("FPS {:.1}", fps),
+                None => "FPS -".to_string(),
+            },
+            format!("Frame {}: {} cycles, {} instructions", last.frame, last.cpu_cycles, last.instructions),
+            format!("CPU {:.2} ms, PPU {:.2} ms", milliseconds(last.cpu_time), milliseconds(last.ppu_time)),
+        ];
+        if let Some(load) = self.load() {
+            lines.push(format!("Load {:.0}%", load * 100.0));
+        }
+        if let Some(fill) = last.audio_buffer_fill {
+            lines.push(format!("Audio buffer {:.0}%", fill * 100.0));
+        }
+        lines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use crate::audio::AudioBuffer;
+    use crate::frame_stats::{FrameStats, PerformanceMonitor, HISTORY_FRAMES};
+    use crate::nes::Nes;
+    use crate::rom::Rom;
+
+    #[test]
+    fn test_averages_and_fps() {
+        let mut monitor = PerformanceMonitor::new();
+        assert_eq!((monitor.fps(), monitor.load()), (None, None));
+        assert!(monitor.hud_lines().is_empty());
+
+        for frame in 0..HISTORY_FRAMES as u64 + 10 {
+            let mut stats = FrameStats { frame, cpu_time: Duration::from_millis(2 + frame % 3), ..FrameStats::default() };
+            monitor.record_at(Duration::from_millis(frame * 20), &mut stats);
+        }
+        assert_eq!(monitor.frames().count(), HISTORY_FRAMES);
+        assert_eq!(monitor.frames().next().unwrap().frame, 10);
+        assert!((monitor.fps().unwrap() - 50.0).abs() < 1e-9);
+        assert_eq!(monitor.average_emulation_time(), Some(Duration::from_millis(3)));
+        assert!((monitor.load().unwrap() - 0.003 * 60.0988).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_recorded_by_the_console() {
+        let mut nes = Nes::new(Rom::test_rom()).unwrap();
+        let buffer = AudioBuffer::new(1000);
+        buffer.push_samples(&[0.0; 250]);
+        nes.performance.set_audio_buffer(Some(buffer));
+
+        nes.run_frame();
+        // Without PPU timing, the whole frame is CPU time
+        assert_eq!(nes.performance.last_frame().unwrap().ppu_time, Duration::ZERO);
+        nes.set_ppu_timing(true);
+        nes.run_frame();
+        let last = *nes.performance.last_frame().unwrap();
+        assert_eq!(last.frame, 1);
+        // About 29781 cycles of 2-cycle NOPs
+        assert!((29770..29790).contains(&last.cpu_cycles));
+        assert!((14880..14900).contains(&last.instructions));
+        assert!(last.ppu_time > Duration::ZERO);
+        assert_eq!(last.audio_buffer_fill, Some(0.25));
+
+        let lines = nes.performance.hud_lines();
+        assert!(lines[0].starts_with("FPS "));
+        assert!(lines[1].starts_with("Frame 1: "));
+        assert_eq!(lines.last().unwrap(), "Audio buffer 25%");
+    }
+
+    #[test]
+    fn test_without_clock() {
+        let mut nes = Nes::new(Rom::test_rom()).unwrap();
+        nes.performance.set_clock(None);
+        nes.set_ppu_timing(true);
+        nes.run_frame();
+        nes.run_frame();
+        let last = *nes.performance.last_frame().unwrap();
+        assert_eq!((last.cpu_time, last.ppu_time), (Duration::ZERO, Duration::ZERO));
+        assert!((29770..29790).contains(&last.cpu_cycles));
+        assert_eq!(nes.performance.fps(), None);
+    }
+}
@@ -0,0 +1,128 @@
+use crate::bus::{Bus, MemoryArea};
+
+// Frozen addresses, the backend of the "freeze" of cheat tools (infinite lives, full health...):
+// each frozen address is written back with its value, either after every instruction (the game
+// never sees another value, but it costs a write per frozen address per instruction) or once at
+// the start of every frame (cheaper, and enough for values the game only updates once per frame).
+// Only RAM and PRG RAM can be frozen. The writes are pokes: they do not show in the access log.
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum FreezeTiming {
+    EveryInstruction,
+    #[default]
+    EveryFrame,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FrozenMemory {
+    // Sorted by address
+    addresses: Vec<(u16, u8)>,
+    pub timing: FreezeTiming,
+}
+
+impl FrozenMemory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Freezes `address` to `value`, replacing the value if it was already frozen.
+    pub fn freeze(&mut self, address: u16, value: u8) -> Result<(), String> {
+        let area = MemoryArea::of(address);
+        if !matches!(area, MemoryArea::Ram | MemoryArea::SaveRam) {
+            return Err(format!("Address {:04X} ({}) cannot be frozen", address, area.name()));
+        }
+        match self.addresses.binary_search_by_key(&address, |(frozen, _)| *frozen) {
+            Ok(position) => self.addresses[position].1 = value,
+            Err(position) => self.addresses.insert(position, (address, value)),
+        }
+        Ok(())
+    }
+
+    // Returns the value the address was frozen to
+    pub fn unfreeze(&mut self, address: u16) -> Option<u8> {
+        let position = self.addresses.binary_search_by_key(&address, |(frozen, _)| *frozen).ok()?;
+        Some(self.addresses.remove(position).1)
+    }
+
+    pub fn clear(&mut self) {
+        self.addresses.clear();
+    }
+
+    pub fn value_of(&self, address: u16) -> Option<u8> {
+        let position = self.addresses.binary_search_by_key(&address, |(frozen, _)| *frozen).ok()?;
+        Some(self.addresses[position].1)
+    }
+
+    // (address, value) pairs, by address
+    pub fn addresses(&self) -> &[(u16, u8)] {
+        &self.addresses
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.addresses.is_empty()
+    }
+
+    // Writes the frozen values to memory
+    pub fn apply(&self, bus: &mut Bus) {
+        for (address, value) in &self.addresses {
+            // Only RAM and PRG RAM are accepted by `freeze`, which can always be poked
+            let _ = bus.poke_u8(*address, *value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::freeze::{FreezeTiming, FrozenMemory};
+    use crate::nes::Nes;
+    use crate::rom::Rom;
+
+    #[test]
+    fn test_freeze_and_unfreeze() {
+        let mut frozen = FrozenMemory::new();
+        frozen.freeze(0x6000, 1).unwrap();
+        frozen.freeze(0x075A, 3).unwrap();
+        frozen.freeze(0x6000, 2).unwrap();
+        assert_eq!(frozen.addresses(), &[(0x075A, 3), (0x6000, 2)]);
+        assert!(frozen.freeze(0x2002, 0).is_err());
+        assert!(frozen.freeze(0x8000, 0).is_err());
+
+        assert_eq!(frozen.unfreeze(0x075A), Some(3));
+        assert_eq!(frozen.unfreeze(0x075A), None);
+        assert_eq!(frozen.value_of(0x6000), Some(2));
+    }
+
+    // Program decrementing 0x0010 in a loop: DEC $10; JMP $C000
+    fn counting_console() -> Nes {
+        let mut nes = Nes::new(Rom::test_rom()).unwrap();
+        for (offset, byte) in [0xC6, 0x10, 0x4C, 0x00, 0xC0].iter().enumerate() {
+            nes.cpu.bus.poke_u8(0xC000 + offset as u16, *byte).unwrap();
+        }
+        nes.cpu.program_counter = 0xC000;
+        nes
+    }
+
+    #[test]
+    fn test_enforced_every_instruction() {
+        let mut nes = counting_console();
+        nes.cpu.frozen_memory.timing = FreezeTiming::EveryInstruction;
+        nes.cpu.frozen_memory.freeze(0x0010, 0x63).unwrap();
+        for _ in 0..5 {
+            nes.step();
+            assert_eq!(nes.cpu.peek_u8(0x0010), 0x63);
+        }
+    }
+
+    #[test]
+    fn test_enforced_every_frame() {
+        let mut nes = counting_console();
+        nes.cpu.bus.poke_u8(0x0011, 5).unwrap();
+        nes.cpu.frozen_memory.freeze(0x0011, 9).unwrap();
+        nes.step();
+        // Not enforced between frames
+        assert_eq!(nes.cpu.peek_u8(0x0011), 5);
+
+        nes.run_frame();
+        assert_eq!(nes.cpu.peek_u8(0x0011), 9);
+    }
+}
@@ -0,0 +1,433 @@
+// Desktop frontend (feature `frontend`): an SDL2 window showing the frames of `Nes::run_frame`,
+// played with the keyboard bindings and the gamepad profiles of the config file. `cargo run -- play
+// <file>` opens it. Key events are forwarded by SDL scancode name (the names `KeyBindings` uses):
+// joypad keys go to the keyboard input provider, and hotkeys drive the emulator (slot 0 savestate,
+// rewind, fast-forward, screenshot, Family BASIC keyboard passthrough, gamepad port swap). SDL game
+// controller events (hotplug, buttons, left stick) go to `GamepadInput`, polled with the keyboard
+// by the console once per frame.
+// Audio waits on the APU; the overlays and the menus are not there yet (see the README roadmap).
+
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::rc::Rc;
+use sdl2::controller::{Axis, Button, GameController};
+use sdl2::event::Event;
+use sdl2::keyboard::{Keycode, Scancode};
+use sdl2::pixels::PixelFormatEnum;
+use crate::config::Config;
+use crate::input_provider::{CompositeInput, ControllerStates, GamepadInput, GamepadProfile, InputProvider, KeyboardInput, StickAxis};
+use crate::key_bindings::{Hotkey, KeyBindings};
+use crate::nes::Nes;
+use crate::ppu::render_thread::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::recorder::{Recorder, RecorderOptions, RecordingFormat};
+
+const WINDOW_SCALE: u32 = 3;
+// Frames rewound per frame shown while the rewind hotkey is held: twice the normal speed
+const REWIND_STEP: u32 = 2;
+// Savestate slot of the save and load hotkeys
+const HOTKEY_SLOT: usize = 0;
+
+// Keyboard shared by the event loop, which forwards the key events, and the console, which polls it
+#[derive(Debug, Clone, Default)]
+struct SharedKeyboard(Rc<RefCell<KeyboardInput>>);
+
+impl InputProvider for SharedKeyboard {
+    fn poll(&mut self) -> ControllerStates {
+        self.0.borrow_mut().poll()
+    }
+}
+
+// Gamepads shared the same way
+#[derive(Debug, Clone, Default)]
+struct SharedGamepads(Rc<RefCell<GamepadInput>>);
+
+impl InputProvider for SharedGamepads {
+    fn poll(&mut self) -> ControllerStates {
+        self.0.borrow_mut().poll()
+    }
+}
+
+// What the gamepad events do, apart from SDL so it can run without it. Pads are identified by their
+// SDL instance id, and their buttons by the names of `GamepadProfile` (see `physical_button`).
+pub struct GamepadHandler {
+    gamepads: SharedGamepads,
+    // Name of each connected pad, to find its profile
+    names: HashMap<usize, String>,
+    profiles: BTreeMap<String, GamepadProfile>,
+    // The profiles changed since the last `take_changed_profiles`
+    changed: bool,
+}
+
+impl GamepadHandler {
+    pub fn new(profiles: &BTreeMap<String, GamepadProfile>) -> Self {
+        let gamepads = SharedGamepads(Rc::new(RefCell::new(GamepadInput::with_profiles(profiles.clone()))));
+        Self { gamepads, names: HashMap::new(), profiles: profiles.clone(), changed: false }
+    }
+
+    // Returns the player the pad was given, if a port is free. A pad without a profile gets the
+    // default one, which is saved so it can be edited in the config file.
+    pub fn connect(&mut self, pad: usize, name: &str) -> Option<usize> {
+        if !self.profiles.contains_key(name) {
+            self.profiles.insert(name.to_string(), GamepadProfile::default());
+            self.changed = true;
+        }
+        self.names.insert(pad, name.to_string());
+        self.gamepads.0.borrow_mut().connect(pad, name)
+    }
+
+    pub fn disconnect(&mut self, pad: usize) {
+        self.names.remove(&pad);
+        self.gamepads.0.borrow_mut().disconnect(pad);
+    }
+
+    pub fn button_event(&mut self, pad: usize, physical: &str, pressed: bool) {
+        self.gamepads.0.borrow_mut().set_physical_button_pressed(pad, physical, pressed);
+    }
+
+    pub fn stick_event(&mut self, pad: usize, axis: StickAxis, value: f32) {
+        self.gamepads.0.borrow_mut().set_stick(pad, axis, value);
+    }
+
+    // Swaps the pads of players 1 and 2. The profile of each pad keeps its new port, so it gets it
+    // back when plugged in again (two pads of the same kind share their profile: the last one wins).
+    pub fn swap_ports(&mut self) {
+        let mut gamepads = self.gamepads.0.borrow_mut();
+        gamepads.swap_players(0, 1);
+        for (pad, name) in &self.names {
+            let (Some(player), Some(profile)) = (gamepads.player(*pad), self.profiles.get_mut(name)) else {
+                continue;
+            };
+            if player < 2 && profile.port != Some(player) {
+                profile.port = Some(player);
+                gamepads.set_profile(name, profile.clone());
+                self.changed = true;
+            }
+        }
+    }
+
+    // The profiles to save in the config file, if they changed since the last call
+    pub fn take_changed_profiles(&mut self) -> Option<&BTreeMap<String, GamepadProfile>> {
+        if !std::mem::take(&mut self.changed) {
+            return None;
+        }
+        Some(&self.profiles)
+    }
+}
+
+// Name of an SDL game controller button in the profiles. SDL names the face buttons after the Xbox
+// layout, the profiles after their position.
+fn physical_button(button: Button) -> &'static str {
+    match button {
+        Button::A => "South",
+        Button::B => "East",
+        Button::X => "West",
+        Button::Y => "North",
+        Button::Back => "Select",
+        Button::Guide => "Mode",
+        Button::Start => "Start",
+        Button::LeftStick => "LeftThumb",
+        Button::RightStick => "RightThumb",
+        Button::LeftShoulder => "LeftTrigger",
+        Button::RightShoulder => "RightTrigger",
+        Button::DPadUp => "DPadUp",
+        Button::DPadDown => "DPadDown",
+        Button::DPadLeft => "DPadLeft",
+        Button::DPadRight => "DPadRight",
+    }
+}
+
+// What the key and gamepad events do, apart from the window so it can run without one.
+pub struct KeyHandler {
+    bindings: KeyBindings,
+    keyboard: SharedKeyboard,
+    gamepads: GamepadHandler,
+    // The keys go to the Family BASIC keyboard instead of the bindings
+    passthrough: bool,
+    rewinding: bool,
+}
+
+impl KeyHandler {
+    // Uses the key bindings and gamepad profiles of `config`. `scancode` translates a key name to
+    // the scancode of the frontend; unknown key names are an error.
+    pub fn new(config: &Config, scancode: impl Fn(&str) -> Option<u32>) -> Result<Self, String> {
+        let keyboard = SharedKeyboard(Rc::new(RefCell::new(config.key_bindings.keyboard_input(scancode)?)));
+        let gamepads = GamepadHandler::new(&config.gamepad_profiles);
+        Ok(Self { bindings: config.key_bindings.clone(), keyboard, gamepads, passthrough: false, rewinding: false })
+    }
+
+    // Makes the keyboard and the gamepads the input provider of the console.
+    pub fn attach(&self, nes: &mut Nes) {
+        let mut input = CompositeInput::new();
+        input.add(Box::new(self.keyboard.clone()));
+        input.add(Box::new(self.gamepads.gamepads.clone()));
+        nes.set_input_provider(Some(Box::new(input)));
+    }
+
+    pub fn gamepads(&mut self) -> &mut GamepadHandler {
+        &mut self.gamepads
+    }
+
+    pub fn is_passthrough(&self) -> bool {
+        self.passthrough
+    }
+
+    // Key `name` (with its `scancode`) pressed or released. Key repeats must be filtered out by the caller.
+    pub fn key_event(&mut self, nes: &mut Nes, name: &str, scancode: u32, pressed: bool) -> Result<(), String> {
+        if let Some(hotkey) = self.bindings.hotkey(name) {
+            return self.hotkey(nes, hotkey, pressed);
+        }
+        if self.passthrough {
+            if let Some(keyboard) = nes.cpu.bus.family_keyboard_mut() {
+                keyboard.set_host_key_pressed(name, pressed);
+            }
+        } else if pressed {
+            self.keyboard.0.borrow_mut().key_down(scancode);
+        } else {
+            self.keyboard.0.borrow_mut().key_up(scancode);
+        }
+        Ok(())
+    }
+
+    fn hotkey(&mut self, nes: &mut Nes, hotkey: Hotkey, pressed: bool) -> Result<(), String> {
+        match (hotkey, pressed) {
+            (Hotkey::Rewind, _) => self.rewinding = pressed,
+            (Hotkey::FastForward, _) => nes.set_uncapped(pressed),
+            (_, false) => {}
+            (Hotkey::SaveState, true) => nes.save_slot(HOTKEY_SLOT)?,
+            (Hotkey::LoadState, true) => {
+                nes.load_slot(HOTKEY_SLOT)?;
+            }
+            (Hotkey::Screenshot, true) => save_screenshot(nes)?,
+            (Hotkey::SwapGamepads, true) => self.gamepads.swap_ports(),
+            (Hotkey::KeyboardPassthrough, true) => {
+                self.passthrough = !self.passthrough;
+                // Keys held when toggling would never be released
+                if let Some(keyboard) = nes.cpu.bus.family_keyboard_mut() {
+                    keyboard.release_all();
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Runs the next frame, or goes back while the rewind hotkey is held.
+    pub fn run_frame(&mut self, nes: &mut Nes) -> Result<(), String> {
+        if self.rewinding {
+            nes.rewind(REWIND_STEP)?;
+        } else {
+            nes.run_paced_frame();
+        }
+        Ok(())
+    }
+}
+
+// Saves the last frame to screenshot-<frame>.png (a single frame APNG is a PNG) in the current directory.
+fn save_screenshot(nes: &Nes) -> Result<(), String> {
+    let options = RecorderOptions { frame_count: 1, ..RecorderOptions::default() };
+    let mut recorder = Recorder::new(RecordingFormat::Apng, SCREEN_WIDTH, SCREEN_HEIGHT, options)?;
+    recorder.push_frame(&nes.framebuffer())?;
+    recorder.save(&format!("screenshot-{}.png", nes.cpu.frame_number()))
+}
+
+// Plays until the window is closed or Escape is pressed. `on_frame` is called after each frame
+// (e.g. to flush the battery save). Hotkey errors are printed and the game goes on. Gamepad profiles
+// added or changed while playing are saved to `config_path`.
+pub fn run(nes: &mut Nes, config: &mut Config, config_path: &str, mut on_frame: impl FnMut(&mut Nes) -> Result<(), String>) -> Result<(), String> {
+    let sdl = sdl2::init()?;
+    let window = sdl.video()?
+        .window("NES", SCREEN_WIDTH as u32 * WINDOW_SCALE, SCREEN_HEIGHT as u32 * WINDOW_SCALE)
+        .position_centered()
+        .build()
+        .map_err(|error| error.to_string())?;
+    let mut canvas = window.into_canvas().build().map_err(|error| error.to_string())?;
+    let texture_creator = canvas.texture_creator();
+    let mut texture = texture_creator
+        .create_texture_streaming(PixelFormatEnum::RGB24, SCREEN_WIDTH as u32, SCREEN_HEIGHT as u32)
+        .map_err(|error| error.to_string())?;
+    // Pads already plugged in are reported by ControllerDeviceAdded events too
+    let controller_subsystem = sdl.game_controller()?;
+    // Opened pads by instance id, a pad is closed when dropped
+    let mut controllers: HashMap<u32, GameController> = HashMap::new();
+    let mut events = sdl.event_pump()?;
+
+    let mut keys = KeyHandler::new(config, |name| Scancode::from_name(name).map(|scancode| scancode as u32))?;
+    keys.attach(nes);
+    loop {
+        for event in events.poll_iter() {
+            let result = match event {
+                Event::Quit { .. } => return Ok(()),
+                Event::KeyDown { keycode: Some(Keycode::Escape), .. } if !keys.is_passthrough() => return Ok(()),
+                Event::KeyDown { scancode: Some(scancode), repeat: false, .. } => keys.key_event(nes, scancode.name(), scancode as u32, true),
+                Event::KeyUp { scancode: Some(scancode), .. } => keys.key_event(nes, scancode.name(), scancode as u32, false),
+                Event::ControllerDeviceAdded { which, .. } => match controller_subsystem.open(which) {
+                    Ok(controller) => {
+                        keys.gamepads().connect(controller.instance_id() as usize, &controller.name());
+                        controllers.insert(controller.instance_id(), controller);
+                        Ok(())
+                    }
+                    Err(error) => Err(format!("Failed to open gamepad {}: {}", which, error)),
+                },
+                Event::ControllerDeviceRemoved { which, .. } => {
+                    keys.gamepads().disconnect(which as usize);
+                    controllers.remove(&which);
+                    Ok(())
+                }
+                Event::ControllerButtonDown { which, button, .. } => {
+                    keys.gamepads().button_event(which as usize, physical_button(button), true);
+                    Ok(())
+                }
+                Event::ControllerButtonUp { which, button, .. } => {
+                    keys.gamepads().button_event(which as usize, physical_button(button), false);
+                    Ok(())
+                }
+                // SDL axes go down for positive Y values, the profiles up
+                Event::ControllerAxisMotion { which, axis: Axis::LeftX, value, .. } => {
+                    keys.gamepads().stick_event(which as usize, StickAxis::X, value as f32 / i16::MAX as f32);
+                    Ok(())
+                }
+                Event::ControllerAxisMotion { which, axis: Axis::LeftY, value, .. } => {
+                    keys.gamepads().stick_event(which as usize, StickAxis::Y, -(value as f32) / i16::MAX as f32);
+                    Ok(())
+                }
+                _ => Ok(()),
+            };
+            if let Err(error) = result {
+                eprintln!("{}", error);
+            }
+        }
+        if let Some(profiles) = keys.gamepads().take_changed_profiles() {
+            config.gamepad_profiles = profiles.clone();
+            if let Err(error) = config.save(config_path) {
+                eprintln!("{}", error);
+            }
+        }
+
+        keys.run_frame(nes)?;
+        on_frame(nes)?;
+        texture.update(None, &nes.framebuffer(), SCREEN_WIDTH * 3).map_err(|error| error.to_string())?;
+        canvas.copy(&texture, None, None)?;
+        canvas.present();
+        nes.wait_for_next_frame();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::Config;
+    use crate::family_keyboard::FamilyBasicKeyboard;
+    use crate::frontend::KeyHandler;
+    use crate::input_provider::{GamepadProfile, StickAxis};
+    use crate::nes::Nes;
+    use crate::rom::Rom;
+
+    // Scancodes of the test: the position of the key name in this list
+    const KEYS: [&str; 13] = ["X", "Z", "Right Shift", "Return", "Up", "Down", "Left", "Right", "Backspace", "Tab", "ScrollLock", "A", "F9"];
+
+    fn scancode(name: &str) -> Option<u32> {
+        KEYS.iter().position(|key| *key == name).map(|position| position as u32)
+    }
+
+    fn press(keys: &mut KeyHandler, nes: &mut Nes, name: &str, pressed: bool) {
+        keys.key_event(nes, name, scancode(name).unwrap(), pressed).unwrap();
+    }
+
+    #[test]
+    fn test_joypad_keys() {
+        let mut nes = Nes::new(Rom::test_rom()).unwrap();
+        let mut keys = KeyHandler::new(&Config::default(), scancode).unwrap();
+        keys.attach(&mut nes);
+
+        press(&mut keys, &mut nes, "X", true);
+        press(&mut keys, &mut nes, "Return", true);
+        keys.run_frame(&mut nes).unwrap();
+        // A and Start
+        assert_eq!(nes.cpu.bus.joypads().buttons(0), 0b0000_1001);
+
+        press(&mut keys, &mut nes, "X", false);
+        keys.run_frame(&mut nes).unwrap();
+        assert_eq!(nes.cpu.bus.joypads().buttons(0), 0b0000_1000);
+    }
+
+    #[test]
+    fn test_rewind_hotkey() {
+        let mut nes = Nes::new(Rom::test_rom()).unwrap();
+        let mut keys = KeyHandler::new(&Config::default(), scancode).unwrap();
+        for frame in 0..10 {
+            nes.cpu.write_u8(0x0010, frame);
+            keys.run_frame(&mut nes).unwrap();
+        }
+
+        press(&mut keys, &mut nes, "Backspace", true);
+        keys.run_frame(&mut nes).unwrap();
+        assert_eq!(nes.cpu.read_u8(0x0010), 6);
+        press(&mut keys, &mut nes, "Backspace", false);
+        nes.cpu.write_u8(0x0010, 0x42);
+        keys.run_frame(&mut nes).unwrap();
+        assert_eq!(nes.cpu.read_u8(0x0010), 0x42);
+    }
+
+    #[test]
+    fn test_keyboard_passthrough() {
+        let mut nes = Nes::new(Rom::test_rom()).unwrap();
+        nes.cpu.bus.set_family_keyboard(Some(FamilyBasicKeyboard::new()));
+        let mut keys = KeyHandler::new(&Config::default(), scancode).unwrap();
+        keys.attach(&mut nes);
+
+        press(&mut keys, &mut nes, "ScrollLock", true);
+        press(&mut keys, &mut nes, "ScrollLock", false);
+        assert!(keys.is_passthrough());
+        press(&mut keys, &mut nes, "X", true);
+        press(&mut keys, &mut nes, "A", true);
+        keys.run_frame(&mut nes).unwrap();
+        // Typed on the keyboard, not bound to the joypad
+        assert_eq!(nes.cpu.bus.joypads().buttons(0), 0);
+        let mut expected = FamilyBasicKeyboard::new();
+        expected.set_key_pressed("X", true).unwrap();
+        expected.set_key_pressed("A", true).unwrap();
+        assert_eq!(nes.cpu.bus.family_keyboard(), Some(&expected));
+
+        // Back to the joypad, the keyboard keys are released
+        press(&mut keys, &mut nes, "ScrollLock", true);
+        assert!(!keys.is_passthrough());
+        assert_eq!(nes.cpu.bus.family_keyboard(), Some(&FamilyBasicKeyboard::new()));
+    }
+
+    #[test]
+    fn test_gamepads() {
+        let mut nes = Nes::new(Rom::test_rom()).unwrap();
+        let mut config = Config::default();
+        config.gamepad_profiles.insert("Arcade Stick".to_string(), GamepadProfile { stick_threshold: 80, ..GamepadProfile::default() });
+        let mut keys = KeyHandler::new(&config, scancode).unwrap();
+        keys.attach(&mut nes);
+
+        assert_eq!(keys.gamepads().connect(3, "Arcade Stick"), Some(0));
+        assert!(keys.gamepads().take_changed_profiles().is_none());
+        keys.gamepads().button_event(3, "East", true);
+        keys.gamepads().stick_event(3, StickAxis::X, 0.5);
+        keys.gamepads().stick_event(3, StickAxis::Y, 0.9);
+        // Combined with the keyboard
+        press(&mut keys, &mut nes, "Return", true);
+        keys.run_frame(&mut nes).unwrap();
+        // A, Start and Up: the stick is not pushed past the threshold of the profile on X
+        assert_eq!(nes.cpu.bus.joypads().buttons(0), 0b0001_1001);
+
+        // A new kind of pad gets a default profile to save
+        assert_eq!(keys.gamepads().connect(5, "Xbox Controller"), Some(1));
+        let profiles = keys.gamepads().take_changed_profiles().unwrap().clone();
+        assert_eq!(profiles["Xbox Controller"], GamepadProfile::default());
+        assert!(keys.gamepads().take_changed_profiles().is_none());
+
+        // The swap hotkey exchanges the ports and records them in the profiles
+        press(&mut keys, &mut nes, "F9", true);
+        keys.run_frame(&mut nes).unwrap();
+        assert_eq!(nes.cpu.bus.joypads().buttons(0), 0b0000_1000);
+        assert_eq!(nes.cpu.bus.joypads().buttons(1), 0b0001_0001);
+        let profiles = keys.gamepads().take_changed_profiles().unwrap();
+        assert_eq!((profiles["Arcade Stick"].port, profiles["Xbox Controller"].port), (Some(1), Some(0)));
+
+        // Unplugging releases the buttons of the pad
+        keys.gamepads().disconnect(3);
+        keys.run_frame(&mut nes).unwrap();
+        assert_eq!(nes.cpu.bus.joypads().buttons(1), 0);
+    }
+}
@@ -0,0 +1,439 @@
+use std::collections::BTreeSet;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use crate::cpu6502::CPU;
+
+// Minimal GDB Remote Serial Protocol server, so the 6502 can be debugged from an IDE or any GDB client.
+// More info: https://sourceware.org/gdb/current/onlinedocs/gdb.html/Remote-Protocol.html
+//
+// GDB has no 6502 architecture, so the register layout is our own (all values little-endian):
+// 0: A, 1: X, 2: Y, 3: P, 4: SP (8 bits each), 5: PC (16 bits)
+// It is described to the client by this target description, read with `qXfer:features:read`.
+const REGISTER_COUNT: usize = 6;
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.nes.6502.core">
+    <reg name="a" bitsize="8" type="uint8" regnum="0"/>
+    <reg name="x" bitsize="8" type="uint8" regnum="1"/>
+    <reg name="y" bitsize="8" type="uint8" regnum="2"/>
+    <reg name="p" bitsize="8" type="uint8" regnum="3"/>
+    <reg name="sp" bitsize="8" type="uint8" regnum="4"/>
+    <reg name="pc" bitsize="16" type="code_ptr" regnum="5"/>
+  </feature>
+</target>
+"#;
+
+// How many instructions are executed between two checks for a Ctrl-C from the client
+const INTERRUPT_CHECK_INTERVAL: u32 = 10_000;
+
+// Stop replies, using the POSIX signal numbers GDB expects
+const STOP_TRAP: &str = "S05";
+const STOP_INTERRUPT: &str = "S02";
+const STOP_ILLEGAL_INSTRUCTION: &str = "S04";
+
+// Longest `m` read: the whole address space. Longer lengths come from a broken or hostile client.
+const MAX_READ_LENGTH: usize = 0x10000;
+
+#[derive(Default)]
+pub struct GdbServer {
+    breakpoints: BTreeSet<u16>,
+}
+
+impl GdbServer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Waits for a client on `address` (e.g. "127.0.0.1:9001") and serves it until it detaches.
+    pub fn serve(&mut self, cpu: &mut CPU, address: &str) -> Result<(), String> {
+        let listener = TcpListener::bind(address).map_err(|error| format!("Failed to listen on {}: {}", address, error))?;
+        let (mut stream, _) = listener.accept().map_err(|error| format!("Failed to accept GDB client: {}", error))?;
+        self.serve_client(cpu, &mut stream)
+    }
+
+    fn serve_client(&mut self, cpu: &mut CPU, stream: &mut TcpStream) -> Result<(), String> {
+        let io_error = |error: std::io::Error| format!("GDB connection error: {}", error);
+
+        while let Some(packet) = read_packet(stream).map_err(io_error)? {
+            stream.write_all(b"+").map_err(io_error)?;
+
+            let mut interrupted = || client_interrupted(stream);
+            let response = self.handle_packet(cpu, &packet, &mut interrupted);
+            match response {
+                Some(response) => write_packet(stream, &response).map_err(io_error)?,
+                None => return Ok(()),
+            }
+            if packet.starts_with('D') {
+                return Ok(());
+            }
+        }
+        Ok(())
+    }
+
+    // Handles the content of a packet and returns the response, or None when the session is over.
+    // `interrupted` is polled while the CPU runs freely, to stop on a Ctrl-C from the client.
+    pub fn handle_packet(&mut self, cpu: &mut CPU, packet: &str, interrupted: &mut dyn FnMut() -> bool) -> Option<String> {
+        let (command, arguments) = packet.split_at(packet.len().min(1));
+        let response = match command {
+            "?" => STOP_TRAP.to_string(),
+            "g" => read_registers(cpu),
+            "G" => write_registers(cpu, arguments),
+            "p" => usize::from_str_radix(arguments, 16).ok()
+                .and_then(|register| read_register(cpu, register))
+                .unwrap_or_else(|| "E01".to_string()),
+            "P" => write_register(cpu, arguments),
+            "m" => read_memory(cpu, arguments),
+            "M" => write_memory(cpu, arguments),
+            "Z" | "z" => self.update_breakpoint(command == "Z", arguments),
+            "s" => {
+                cpu.step();
+                stop_reason(cpu, STOP_TRAP)
+            }
+            "c" => self.continue_execution(cpu, interrupted),
+            "H" => "OK".to_string(),
+            // Detaching ends the session after the reply is sent, see serve_client
+            "D" => "OK".to_string(),
+            "k" => return None,
+            "q" if arguments.starts_with("Supported") => "PacketSize=1000;qXfer:features:read+".to_string(),
+            "q" if arguments.starts_with("Xfer:features:read:") => read_target_xml(&arguments["Xfer:features:read:".len()..]),
+            "q" if arguments == "Attached" => "1".to_string(),
+            // Empty response: command not supported
+            _ => String::new(),
+        };
+        Some(response)
+    }
+
+    // Runs until a breakpoint is hit, the CPU halts, or the client interrupts.
+    fn continue_execution(&mut self, cpu: &mut CPU, interrupted: &mut dyn FnMut() -> bool) -> String {
+        let mut steps = 0u32;
+        loop {
+            cpu.step();
+            if cpu.halted || self.breakpoints.contains(&cpu.program_counter) {
+                return stop_reason(cpu, STOP_TRAP);
+            }
+            steps += 1;
+            if steps.is_multiple_of(INTERRUPT_CHECK_INTERVAL) && interrupted() {
+                return STOP_INTERRUPT.to_string();
+            }
+        }
+    }
+
+    // Z0 (software) and Z1 (hardware) breakpoints are handled the same way: "Z0,addr,kind"
+    fn update_breakpoint(&mut self, insert: bool, arguments: &str) -> String {
+        let mut fields = arguments.split(',');
+        let (Some(kind), Some(address)) = (fields.next(), fields.next()) else {
+            return "E01".to_string();
+        };
+        if kind != "0" && kind != "1" {
+            return String::new();
+        }
+        let Ok(address) = u16::from_str_radix(address, 16) else {
+            return "E01".to_string();
+        };
+
+        if insert {
+            self.breakpoints.insert(address);
+        } else {
+            self.breakpoints.remove(&address);
+        }
+        "OK".to_string()
+    }
+}
+
+// "target.xml:offset,length": the part of the target description at offset, prefixed with `m`
+// when there is more to read or `l` for the last part. The XML has no character to escape.
+fn read_target_xml(arguments: &str) -> String {
+    let Some((annex, range)) = arguments.split_once(':') else {
+        return "E01".to_string();
+    };
+    if annex != "target.xml" {
+        return "E00".to_string();
+    }
+    let Some((offset, length)) = range.split_once(',') else {
+        return "E01".to_string();
+    };
+    let (Ok(offset), Ok(length)) = (usize::from_str_radix(offset, 16), usize::from_str_radix(length, 16)) else {
+        return "E01".to_string();
+    };
+    let start = offset.min(TARGET_XML.len());
+    let end = start.saturating_add(length).min(TARGET_XML.len());
+    let marker = if end < TARGET_XML.len() { 'm' } else { 'l' };
+    format!("{}{}", marker, &TARGET_XML[start..end])
+}
+
+fn stop_reason(cpu: &CPU, reason: &str) -> String {
+    if cpu.halted { STOP_ILLEGAL_INSTRUCTION.to_string() } else { reason.to_string() }
+}
+
+fn hex_encode(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn hex_decode(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len()).step_by(2)
+        .map(|index| u8::from_str_radix(hex.get(index..index + 2)?, 16).ok())
+        .collect()
+}
+
+fn register_bytes(cpu: &CPU, register: usize) -> Option<Vec<u8>> {
+    match register {
+        0 => Some(vec![cpu.accumulator]),
+        1 => Some(vec![cpu.x_register]),
+        2 => Some(vec![cpu.y_register]),
+        3 => Some(vec![cpu.status_register]),
+        4 => Some(vec![cpu.stack_pointer]),
+        5 => Some(cpu.program_counter.to_le_bytes().to_vec()),
+        _ => None,
+    }
+}
+
+// Sets a register from its little-endian bytes. Returns false if the size does not match.
+fn set_register(cpu: &mut CPU, register: usize, bytes: &[u8]) -> bool {
+    match (register, bytes) {
+        (0, [value]) => cpu.accumulator = *value,
+        (1, [value]) => cpu.x_register = *value,
+        (2, [value]) => cpu.y_register = *value,
+        (3, [value]) => cpu.status_register = *value,
+        (4, [value]) => cpu.stack_pointer = *value,
+        (5, [low, high]) => cpu.program_counter = u16::from_le_bytes([*low, *high]),
+        _ => return false,
+    }
+    true
+}
+
+fn read_register(cpu: &CPU, register: usize) -> Option<String> {
+    register_bytes(cpu, register).map(|bytes| hex_encode(&bytes))
+}
+
+fn read_registers(cpu: &CPU) -> String {
+    (0..REGISTER_COUNT).filter_map(|register| read_register(cpu, register)).collect()
+}
+
+fn write_registers(cpu: &mut CPU, arguments: &str) -> String {
+    match hex_decode(arguments) {
+        Some(bytes) if bytes.len() == 7 => {
+            for (register, range) in [(0, 0..1), (1, 1..2), (2, 2..3), (3, 3..4), (4, 4..5), (5, 5..7)] {
+                set_register(cpu, register, &bytes[range]);
+            }
+            "OK".to_string()
+        }
+        _ => "E01".to_string(),
+    }
+}
+
+// "Pn=value"
+fn write_register(cpu: &mut CPU, arguments: &str) -> String {
+    let Some((register, value)) = arguments.split_once('=') else {
+        return "E01".to_string();
+    };
+    match (usize::from_str_radix(register, 16), hex_decode(value)) {
+        (Ok(register), Some(bytes)) if set_register(cpu, register, &bytes) => "OK".to_string(),
+        _ => "E01".to_string(),
+    }
+}
+
+// "maddr,length": memory is peeked, so reading it from the debugger has no side effect
+fn read_memory(cpu: &CPU, arguments: &str) -> String {
+    let Some((address, length)) = arguments.split_once(',') else {
+        return "E01".to_string();
+    };
+    match (u16::from_str_radix(address, 16), usize::from_str_radix(length, 16)) {
+        (Ok(address), Ok(length)) if length <= MAX_READ_LENGTH => hex_encode(&cpu.read_range(address, length)),
+        _ => "E01".to_string(),
+    }
+}
+
+// "Maddr,length:data"
+fn write_memory(cpu: &mut CPU, arguments: &str) -> String {
+    let Some((location, data)) = arguments.split_once(':') else {
+        return "E01".to_string();
+    };
+    let Some((address, _)) = location.split_once(',') else {
+        return "E01".to_string();
+    };
+    let (Ok(address), Some(bytes)) = (u16::from_str_radix(address, 16), hex_decode(data)) else {
+        return "E01".to_string();
+    };
+
+    match cpu.write_range(address, &bytes) {
+        Ok(()) => "OK".to_string(),
+        Err(_) => "E02".to_string(),
+    }
+}
+
+fn checksum(data: &str) -> u8 {
+    data.bytes().fold(0u8, |sum, byte| sum.wrapping_add(byte))
+}
+
+// Reads the next "$data#checksum" packet. Acknowledgements and stray bytes are skipped.
+// Returns None when the client disconnects.
+fn read_packet(stream: &mut TcpStream) -> std::io::Result<Option<String>> {
+    let mut byte = [0u8; 1];
+    loop {
+        if stream.read(&mut byte)? == 0 {
+            return Ok(None);
+        }
+        if byte[0] != b'$' {
+            continue;
+        }
+
+        let mut data = Vec::new();
+        loop {
+            if stream.read(&mut byte)? == 0 {
+                return Ok(None);
+            }
+            if byte[0] == b'#' {
+                break;
+            }
+            data.push(byte[0]);
+        }
+        let mut received_checksum = [0u8; 2];
+        stream.read_exact(&mut received_checksum)?;
+
+        let data = String::from_utf8_lossy(&data).to_string();
+        let expected = format!("{:02x}", checksum(&data));
+        if String::from_utf8_lossy(&received_checksum).to_lowercase() == expected {
+            return Ok(Some(data));
+        }
+        // Ask the client to send the packet again
+        stream.write_all(b"-")?;
+    }
+}
+
+fn write_packet(stream: &mut TcpStream, data: &str) -> std::io::Result<()> {
+    write!(stream, "${}#{:02x}", data, checksum(data))
+}
+
+// The client sends a raw 0x03 byte (Ctrl-C) to interrupt a running target.
+fn client_interrupted(stream: &mut TcpStream) -> bool {
+    let mut byte = [0u8; 1];
+    if stream.set_nonblocking(true).is_err() {
+        return false;
+    }
+    let interrupted = matches!(stream.peek(&mut byte), Ok(1) if byte[0] == 0x03);
+    if interrupted {
+        let _ = stream.read(&mut byte);
+    }
+    let _ = stream.set_nonblocking(false);
+    interrupted
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bus::Bus;
+    use crate::cpu6502::{new_cpu, CPU};
+    use crate::gdb_server::{checksum, GdbServer, TARGET_XML};
+
+    fn flat_cpu(program: &[u8]) -> CPU {
+        let mut cpu = new_cpu(Bus::new_flat());
+        for (offset, byte) in program.iter().enumerate() {
+            cpu.write_u8(0x0600 + offset as u16, *byte);
+        }
+        cpu.program_counter = 0x0600;
+        cpu
+    }
+
+    fn handle(server: &mut GdbServer, cpu: &mut CPU, packet: &str) -> String {
+        server.handle_packet(cpu, packet, &mut || false).expect("session should continue")
+    }
+
+    #[test]
+    fn test_checksum() {
+        assert_eq!(checksum("OK"), 0x9A);
+    }
+
+    #[test]
+    fn test_registers() {
+        let mut server = GdbServer::new();
+        let mut cpu = flat_cpu(&[]);
+        cpu.accumulator = 0x12;
+        cpu.stack_pointer = 0xFD;
+
+        assert_eq!(handle(&mut server, &mut cpu, "g"), "12000024fd0006");
+        assert_eq!(handle(&mut server, &mut cpu, "P5=3412"), "OK");
+        assert_eq!(cpu.program_counter, 0x1234);
+        assert_eq!(handle(&mut server, &mut cpu, "p5"), "3412");
+        assert_eq!(handle(&mut server, &mut cpu, "G01020304050607"), "OK");
+        assert_eq!((cpu.accumulator, cpu.stack_pointer, cpu.program_counter), (0x01, 0x05, 0x0706));
+        assert_eq!(handle(&mut server, &mut cpu, "p9"), "E01");
+    }
+
+    #[test]
+    fn test_memory() {
+        let mut server = GdbServer::new();
+        let mut cpu = flat_cpu(&[0xA9, 0x42]);
+
+        assert_eq!(handle(&mut server, &mut cpu, "m600,2"), "a942");
+        assert_eq!(handle(&mut server, &mut cpu, "m0,10000").len(), 0x20000);
+        assert_eq!(handle(&mut server, &mut cpu, "m0,ffffffffffff"), "E01");
+        assert_eq!(handle(&mut server, &mut cpu, "M10,2:beef"), "OK");
+        assert_eq!(cpu.read_u8(0x0011), 0xEF);
+    }
+
+    #[test]
+    fn test_step_and_breakpoints() {
+        let mut server = GdbServer::new();
+        // LDA #$01, INX, INX, INX, KIL
+        let mut cpu = flat_cpu(&[0xA9, 0x01, 0xE8, 0xE8, 0xE8, 0x02]);
+
+        assert_eq!(handle(&mut server, &mut cpu, "s"), "S05");
+        assert_eq!(cpu.program_counter, 0x0602);
+
+        assert_eq!(handle(&mut server, &mut cpu, "Z0,604,1"), "OK");
+        assert_eq!(handle(&mut server, &mut cpu, "c"), "S05");
+        assert_eq!(cpu.program_counter, 0x0604);
+        assert_eq!(cpu.x_register, 2);
+
+        // Without the breakpoint, execution goes on until the CPU halts
+        assert_eq!(handle(&mut server, &mut cpu, "z0,604,1"), "OK");
+        assert_eq!(handle(&mut server, &mut cpu, "c"), "S04");
+        assert_eq!(cpu.x_register, 3);
+    }
+
+    #[test]
+    fn test_continue_can_be_interrupted() {
+        let mut server = GdbServer::new();
+        // JMP $0600
+        let mut cpu = flat_cpu(&[0x4C, 0x00, 0x06]);
+        let response = server.handle_packet(&mut cpu, "c", &mut || true);
+        assert_eq!(response.as_deref(), Some("S02"));
+    }
+
+    #[test]
+    fn test_session_end() {
+        let mut server = GdbServer::new();
+        let mut cpu = flat_cpu(&[]);
+        assert_eq!(server.handle_packet(&mut cpu, "k", &mut || false), None);
+        assert_eq!(handle(&mut server, &mut cpu, "qUnknown"), "");
+    }
+
+    #[test]
+    fn test_target_description() {
+        let mut server = GdbServer::new();
+        let mut cpu = flat_cpu(&[]);
+        assert!(handle(&mut server, &mut cpu, "qSupported:multiprocess+;xmlRegisters=i386").contains("qXfer:features:read+"));
+
+        // Read in chunks, the way GDB does
+        let mut xml = String::new();
+        loop {
+            let response = handle(&mut server, &mut cpu, &format!("qXfer:features:read:target.xml:{:x},40", xml.len()));
+            let (marker, data) = response.split_at(1);
+            xml.push_str(data);
+            if marker == "l" {
+                break;
+            }
+            assert_eq!(marker, "m");
+        }
+        assert_eq!(xml, TARGET_XML);
+        // Registers in the order of the `g` packet
+        let names: Vec<&str> = xml.split("<reg name=\"").skip(1).map(|reg| &reg[..reg.find('"').unwrap()]).collect();
+        assert_eq!(names, ["a", "x", "y", "p", "sp", "pc"]);
+
+        assert_eq!(handle(&mut server, &mut cpu, "qXfer:features:read:other.xml:0,40"), "E00");
+        assert_eq!(handle(&mut server, &mut cpu, "qXfer:features:read:target.xml:zz"), "E01");
+    }
+}
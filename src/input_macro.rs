@@ -0,0 +1,144 @@
+// Input macros: short button sequences recorded from the live input and replayed on demand, e.g. a
+// combo to practice. Unlike a movie, a macro does not replace the input: while it plays, its buttons
+// are added to the ones held by the player. Macros are stored in numbered slots and advance by one
+// entry per frame, so their timing is kept.
+
+pub const MACRO_SLOTS: usize = 10;
+
+// Buttons held on each frame, bit N being `JoypadButton` N
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct InputMacro {
+    pub frames: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Recording {
+    player: usize,
+    slot: usize,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Playback {
+    slot: usize,
+    position: usize,
+}
+
+#[derive(Debug, Default)]
+pub struct InputMacros {
+    slots: [Option<InputMacro>; MACRO_SLOTS],
+    recording: Option<(Recording, InputMacro)>,
+    // Macro playing for each player
+    playbacks: [Option<Playback>; 4],
+}
+
+impl InputMacros {
+    pub fn macro_in_slot(&self, slot: usize) -> Option<&InputMacro> {
+        self.slots.get(slot)?.as_ref()
+    }
+
+    pub fn set_macro(&mut self, slot: usize, input_macro: InputMacro) -> Result<(), String> {
+        let entry = self.slots.get_mut(slot).ok_or_else(|| format!("Invalid macro slot {} (0 to {})", slot, MACRO_SLOTS - 1))?;
+        *entry = Some(input_macro);
+        Ok(())
+    }
+
+    // Starts recording the buttons of `player` into `slot`. The macro starts on the first frame
+    // where a button is pressed, so there is no delay when it is played.
+    pub fn start_recording(&mut self, player: usize, slot: usize) -> Result<(), String> {
+        if slot >= MACRO_SLOTS {
+            return Err(format!("Invalid macro slot {} (0 to {})", slot, MACRO_SLOTS - 1));
+        }
+        self.recording = Some((Recording { player, slot }, InputMacro::default()));
+        Ok(())
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recording.is_some()
+    }
+
+    // Stores the recorded macro in its slot, without the frames after the last button release.
+    pub fn stop_recording(&mut self) {
+        if let Some((recording, mut input_macro)) = self.recording.take() {
+            while input_macro.frames.last() == Some(&0) {
+                input_macro.frames.pop();
+            }
+            self.slots[recording.slot] = Some(input_macro);
+        }
+    }
+
+    // Plays the macro of `slot` on the controller of `player`, from its first frame.
+    pub fn play(&mut self, slot: usize, player: usize) -> Result<(), String> {
+        if self.macro_in_slot(slot).is_none() {
+            return Err(format!("Macro slot {} is empty", slot));
+        }
+        self.playbacks[player] = Some(Playback { slot, position: 0 });
+        Ok(())
+    }
+
+    pub fn stop(&mut self, player: usize) {
+        self.playbacks[player] = None;
+    }
+
+    pub fn is_playing(&self, player: usize) -> bool {
+        self.playbacks[player].is_some()
+    }
+
+    // Buttons added by the macro playing for `player` on the current frame
+    pub fn buttons(&self, player: usize) -> u8 {
+        self.playbacks[player]
+            .and_then(|playback| self.macro_in_slot(playback.slot)?.frames.get(playback.position).copied())
+            .unwrap_or(0)
+    }
+
+    // Called once per frame with the live buttons of each player.
+    pub fn end_frame(&mut self, live_buttons: &[u8; 4]) {
+        if let Some((recording, input_macro)) = &mut self.recording {
+            let buttons = live_buttons[recording.player];
+            if buttons != 0 || !input_macro.frames.is_empty() {
+                input_macro.frames.push(buttons);
+            }
+        }
+
+        for player in 0..self.playbacks.len() {
+            let Some(playback) = &mut self.playbacks[player] else { continue };
+            playback.position += 1;
+            let length = self.slots[playback.slot].as_ref().map_or(0, |input_macro| input_macro.frames.len());
+            if playback.position >= length {
+                self.playbacks[player] = None;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::input_macro::{InputMacro, InputMacros};
+
+    #[test]
+    fn test_record_and_play() {
+        let mut macros = InputMacros::default();
+        macros.start_recording(0, 3).unwrap();
+        for buttons in [0, 0, 0x01, 0x03, 0x00, 0x02, 0x00, 0x00] {
+            macros.end_frame(&[buttons, 0xFF, 0, 0]);
+        }
+        macros.stop_recording();
+        assert_eq!(macros.macro_in_slot(3).unwrap().frames, vec![0x01, 0x03, 0x00, 0x02]);
+
+        macros.play(3, 1).unwrap();
+        let mut played = Vec::new();
+        while macros.is_playing(1) {
+            played.push(macros.buttons(1));
+            macros.end_frame(&[0; 4]);
+        }
+        assert_eq!(played, vec![0x01, 0x03, 0x00, 0x02]);
+        assert_eq!(macros.buttons(1), 0);
+    }
+
+    #[test]
+    fn test_invalid_slots() {
+        let mut macros = InputMacros::default();
+        assert!(macros.play(0, 0).is_err());
+        assert!(macros.start_recording(0, 10).is_err());
+        assert!(macros.set_macro(10, InputMacro::default()).is_err());
+    }
+}
@@ -0,0 +1,468 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt::Debug;
+use std::path::Path;
+use crate::joypad::JoypadButton;
+
+// Sources of controller input, independent of any frontend. The core polls its provider once per
+// frame, before running it (see `Nes::run_frame`), and the returned buttons stay the same for the
+// whole frame. Since the game only sees what `poll` returns at that fixed point, recording these
+// states is enough to replay a run exactly, whatever the provider was.
+
+// Buttons of the 4 players, bit N being `JoypadButton` N
+pub type ControllerStates = [u8; 4];
+
+pub trait InputProvider: Debug {
+    fn poll(&mut self) -> ControllerStates;
+}
+
+// Keyboard: the frontend forwards its key events (by scancode) and the bindings translate them.
+// Several keys can be bound to the same button.
+#[derive(Debug, Default)]
+pub struct KeyboardInput {
+    bindings: HashMap<u32, (usize, JoypadButton)>,
+    held: HashSet<u32>,
+}
+
+impl KeyboardInput {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn bind(&mut self, scancode: u32, player: usize, button: JoypadButton) {
+        self.bindings.insert(scancode, (player % 4, button));
+    }
+
+    pub fn unbind(&mut self, scancode: u32) {
+        self.bindings.remove(&scancode);
+    }
+
+    pub fn key_down(&mut self, scancode: u32) {
+        self.held.insert(scancode);
+    }
+
+    pub fn key_up(&mut self, scancode: u32) {
+        self.held.remove(&scancode);
+    }
+}
+
+impl InputProvider for KeyboardInput {
+    fn poll(&mut self) -> ControllerStates {
+        let mut states = [0; 4];
+        for (player, button) in self.held.iter().filter_map(|scancode| self.bindings.get(scancode)) {
+            states[*player] |= 1 << *button as u8;
+        }
+        states
+    }
+}
+
+// Button mapping of a kind of gamepad, selected by the name the pad reports when connected.
+// Physical buttons are named by their position ("South", "East", "DPadUp", "Start"...), whatever
+// the label printed on them: South is A on an Xbox pad and Cross on a PlayStation pad.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GamepadProfile {
+    // Joypad button name to physical button name, like `Config::key_bindings`
+    pub buttons: BTreeMap<String, String>,
+    // Player the pad is given when connected (0 or 1), if that port is free
+    pub port: Option<usize>,
+    // How far the left stick has to be pushed to press a direction, in percent
+    pub stick_threshold: u8,
+}
+
+impl Default for GamepadProfile {
+    fn default() -> Self {
+        // Same layout as the NES controller: B on the left of A
+        let buttons = [
+            ("a", "East"), ("b", "South"), ("select", "Select"), ("start", "Start"),
+            ("up", "DPadUp"), ("down", "DPadDown"), ("left", "DPadLeft"), ("right", "DPadRight"),
+        ];
+        Self {
+            buttons: buttons.iter().map(|(button, physical)| (button.to_string(), physical.to_string())).collect(),
+            port: None,
+            stick_threshold: 50,
+        }
+    }
+}
+
+impl GamepadProfile {
+    // Joypad buttons pressed by `physical` (none if it is not mapped)
+    fn buttons_of(&self, physical: &str) -> u8 {
+        self.buttons.iter()
+            .filter(|(_, mapped)| mapped.as_str() == physical)
+            .filter_map(|(button, _)| JoypadButton::from_name(button).ok())
+            .fold(0, |buttons, button| buttons | 1 << button as u8)
+    }
+}
+
+// Analog stick axes, -1.0 to 1.0 (right and up being positive)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StickAxis {
+    X,
+    Y,
+}
+
+// NES controller ports gamepads are assigned to when they connect
+const GAMEPAD_PORTS: usize = 2;
+
+#[derive(Debug, Default)]
+struct Gamepad {
+    profile: GamepadProfile,
+    player: Option<usize>,
+    buttons: u8,
+    // Directions pressed through the left stick
+    stick: u8,
+}
+
+// Gamepads: the frontend forwards the connection and button events of each pad (the SDL game
+// controller events in src/frontend.rs), identified by its own id. Pads plugged in while the game runs take the first free port, or the
+// one of their profile; pads can also be assigned manually.
+#[derive(Debug, Default)]
+pub struct GamepadInput {
+    pads: HashMap<usize, Gamepad>,
+    // Profiles by pad name, pads without a profile use the default one
+    profiles: BTreeMap<String, GamepadProfile>,
+}
+
+impl GamepadInput {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_profiles(profiles: BTreeMap<String, GamepadProfile>) -> Self {
+        Self { pads: HashMap::new(), profiles }
+    }
+
+    // Pad `pad` named `name` was plugged in. Returns the player it was assigned to, if a port is free.
+    pub fn connect(&mut self, pad: usize, name: &str) -> Option<usize> {
+        let profile = self.profiles.get(name).cloned().unwrap_or_default();
+        let taken: Vec<usize> = self.pads.iter()
+            .filter(|(id, _)| **id != pad)
+            .filter_map(|(_, gamepad)| gamepad.player)
+            .collect();
+        let player = profile.port
+            .filter(|port| *port < GAMEPAD_PORTS && !taken.contains(port))
+            .or_else(|| (0..GAMEPAD_PORTS).find(|port| !taken.contains(port)));
+        self.pads.insert(pad, Gamepad { profile, player, ..Gamepad::default() });
+        player
+    }
+
+    // Moves a pad to another player, e.g. to swap ports 1 and 2
+    pub fn assign(&mut self, pad: usize, player: usize) {
+        self.pads.entry(pad).or_default().player = Some(player % 4);
+    }
+
+    // Gives the pads of `first` to `second` and the other way around
+    pub fn swap_players(&mut self, first: usize, second: usize) {
+        for gamepad in self.pads.values_mut() {
+            if gamepad.player == Some(first) {
+                gamepad.player = Some(second);
+            } else if gamepad.player == Some(second) {
+                gamepad.player = Some(first);
+            }
+        }
+    }
+
+    // Profile of the pads named `name` connected from now on
+    pub fn set_profile(&mut self, name: &str, profile: GamepadProfile) {
+        self.profiles.insert(name.to_string(), profile);
+    }
+
+    pub fn player(&self, pad: usize) -> Option<usize> {
+        self.pads.get(&pad)?.player
+    }
+
+    // A disconnected pad releases its buttons and its port
+    pub fn disconnect(&mut self, pad: usize) {
+        self.pads.remove(&pad);
+    }
+
+    pub fn set_button_pressed(&mut self, pad: usize, button: JoypadButton, pressed: bool) {
+        let buttons = &mut self.pads.entry(pad).or_default().buttons;
+        if pressed {
+            *buttons |= 1 << button as u8;
+        } else {
+            *buttons &= !(1 << button as u8);
+        }
+    }
+
+    // Physical button event, mapped through the profile of the pad
+    pub fn set_physical_button_pressed(&mut self, pad: usize, physical: &str, pressed: bool) {
+        let gamepad = self.pads.entry(pad).or_default();
+        let buttons = gamepad.profile.buttons_of(physical);
+        if pressed {
+            gamepad.buttons |= buttons;
+        } else {
+            gamepad.buttons &= !buttons;
+        }
+    }
+
+    // Left stick movement: past the threshold of the profile, the stick presses a direction.
+    pub fn set_stick(&mut self, pad: usize, axis: StickAxis, value: f32) {
+        let gamepad = self.pads.entry(pad).or_default();
+        let threshold = gamepad.profile.stick_threshold as f32 / 100.0;
+        let (negative, positive) = match axis {
+            StickAxis::X => (JoypadButton::Left, JoypadButton::Right),
+            StickAxis::Y => (JoypadButton::Down, JoypadButton::Up),
+        };
+        gamepad.stick &= !(1 << negative as u8 | 1 << positive as u8);
+        if value <= -threshold {
+            gamepad.stick |= 1 << negative as u8;
+        } else if value >= threshold {
+            gamepad.stick |= 1 << positive as u8;
+        }
+    }
+}
+
+impl InputProvider for GamepadInput {
+    fn poll(&mut self) -> ControllerStates {
+        let mut states = [0; 4];
+        for gamepad in self.pads.values() {
+            if let Some(player) = gamepad.player {
+                states[player] |= gamepad.buttons | gamepad.stick;
+            }
+        }
+        states
+    }
+}
+
+// Replay file: "NESINPUT", then 4 bytes (players 1 to 4) per frame.
+const REPLAY_MAGIC: &[u8; 8] = b"NESINPUT";
+
+// Plays back recorded states, one entry per poll. Once the end is reached, no button is pressed.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ReplayInput {
+    pub frames: Vec<ControllerStates>,
+    position: usize,
+}
+
+impl ReplayInput {
+    pub fn new(frames: Vec<ControllerStates>) -> Self {
+        Self { frames, position: 0 }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.position >= self.frames.len()
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut data = REPLAY_MAGIC.to_vec();
+        for states in &self.frames {
+            data.extend_from_slice(states);
+        }
+        data
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Self, String> {
+        let frames = data.strip_prefix(REPLAY_MAGIC).ok_or("Invalid replay file: Missing NESINPUT header")?;
+        if frames.len() % 4 != 0 {
+            return Err(format!("Invalid replay file: Truncated frame ({} extra bytes)", frames.len() % 4));
+        }
+        Ok(Self::new(frames.chunks_exact(4).map(|states| [states[0], states[1], states[2], states[3]]).collect()))
+    }
+
+    pub fn load_file<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        let path = path.as_ref();
+        let data = std::fs::read(path).map_err(|error| format!("Failed to read replay file {}: {}", path.display(), error))?;
+        Self::from_bytes(&data)
+    }
+
+    pub fn save_file<P: AsRef<Path>>(&self, path: P) -> Result<(), String> {
+        let path = path.as_ref();
+        std::fs::write(path, self.to_bytes()).map_err(|error| format!("Failed to write replay file {}: {}", path.display(), error))
+    }
+}
+
+impl InputProvider for ReplayInput {
+    fn poll(&mut self) -> ControllerStates {
+        let states = self.frames.get(self.position).copied().unwrap_or([0; 4]);
+        self.position += 1;
+        states
+    }
+}
+
+// Records the states returned by another provider, to save them as a replay.
+#[derive(Debug)]
+pub struct RecordingInput {
+    provider: Box<dyn InputProvider>,
+    pub replay: ReplayInput,
+}
+
+impl RecordingInput {
+    pub fn new(provider: Box<dyn InputProvider>) -> Self {
+        Self { provider, replay: ReplayInput::default() }
+    }
+}
+
+impl InputProvider for RecordingInput {
+    fn poll(&mut self) -> ControllerStates {
+        let states = self.provider.poll();
+        self.replay.frames.push(states);
+        states
+    }
+}
+
+// Combines several providers (e.g. keyboard and gamepads): a button is pressed if any of them
+// presses it. Every provider is polled once per frame.
+#[derive(Debug, Default)]
+pub struct CompositeInput {
+    providers: Vec<Box<dyn InputProvider>>,
+}
+
+impl CompositeInput {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, provider: Box<dyn InputProvider>) {
+        self.providers.push(provider);
+    }
+}
+
+impl InputProvider for CompositeInput {
+    fn poll(&mut self) -> ControllerStates {
+        let mut states = [0; 4];
+        for provider in &mut self.providers {
+            for (state, buttons) in states.iter_mut().zip(provider.poll()) {
+                *state |= buttons;
+            }
+        }
+        states
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use crate::input_provider::{CompositeInput, GamepadInput, GamepadProfile, InputProvider, KeyboardInput, RecordingInput, ReplayInput, StickAxis};
+    use crate::joypad::JoypadButton;
+
+    #[test]
+    fn test_keyboard() {
+        let mut keyboard = KeyboardInput::new();
+        keyboard.bind(30, 0, JoypadButton::A);
+        keyboard.bind(44, 0, JoypadButton::A);
+        keyboard.bind(72, 1, JoypadButton::Up);
+
+        keyboard.key_down(30);
+        keyboard.key_down(44);
+        keyboard.key_down(72);
+        keyboard.key_down(1); // Not bound
+        assert_eq!(keyboard.poll(), [0b0000_0001, 0b0001_0000, 0, 0]);
+
+        // A is still held through the other key
+        keyboard.key_up(30);
+        assert_eq!(keyboard.poll()[0], 0b0000_0001);
+        keyboard.key_up(44);
+        assert_eq!(keyboard.poll()[0], 0);
+    }
+
+    #[test]
+    fn test_gamepads() {
+        let mut gamepads = GamepadInput::new();
+        gamepads.assign(7, 1);
+        gamepads.set_button_pressed(7, JoypadButton::Start, true);
+        // Unassigned pad
+        gamepads.set_button_pressed(3, JoypadButton::B, true);
+        assert_eq!(gamepads.poll(), [0, 0b0000_1000, 0, 0]);
+
+        gamepads.disconnect(7);
+        gamepads.assign(7, 1);
+        assert_eq!(gamepads.poll(), [0; 4]);
+    }
+
+    #[test]
+    fn test_gamepad_hotplug() {
+        let mut profiles = BTreeMap::new();
+        profiles.insert("Arcade Stick".to_string(), GamepadProfile { port: Some(1), ..GamepadProfile::default() });
+        let mut gamepads = GamepadInput::with_profiles(profiles);
+
+        assert_eq!(gamepads.connect(10, "Xbox Controller"), Some(0));
+        assert_eq!(gamepads.connect(11, "Xbox Controller"), Some(1));
+        // Both ports are taken
+        assert_eq!(gamepads.connect(12, "Arcade Stick"), None);
+
+        // Unplugging a pad frees its port for the next one
+        gamepads.set_physical_button_pressed(11, "East", true);
+        gamepads.disconnect(11);
+        assert_eq!(gamepads.poll(), [0; 4]);
+        assert_eq!(gamepads.connect(12, "Arcade Stick"), Some(1));
+
+        // The preferred port of a profile is taken when free
+        gamepads.disconnect(10);
+        gamepads.disconnect(12);
+        assert_eq!(gamepads.connect(12, "Arcade Stick"), Some(1));
+        assert_eq!(gamepads.connect(10, "Xbox Controller"), Some(0));
+    }
+
+    #[test]
+    fn test_gamepad_swap_players() {
+        let mut gamepads = GamepadInput::new();
+        gamepads.connect(1, "Pad");
+        gamepads.connect(2, "Pad");
+        gamepads.assign(3, 2);
+        gamepads.swap_players(0, 1);
+        assert_eq!((gamepads.player(1), gamepads.player(2), gamepads.player(3)), (Some(1), Some(0), Some(2)));
+
+        gamepads.set_profile("Pad", GamepadProfile { port: Some(1), ..GamepadProfile::default() });
+        gamepads.disconnect(2);
+        gamepads.disconnect(1);
+        assert_eq!(gamepads.connect(4, "Pad"), Some(1));
+    }
+
+    #[test]
+    fn test_gamepad_profile_mapping() {
+        let mut profile = GamepadProfile::default();
+        profile.buttons.insert("a".to_string(), "South".to_string());
+        profile.buttons.insert("b".to_string(), "West".to_string());
+        profile.stick_threshold = 30;
+        let mut profiles = BTreeMap::new();
+        profiles.insert("Pad".to_string(), profile);
+        let mut gamepads = GamepadInput::with_profiles(profiles);
+        gamepads.connect(0, "Pad");
+        gamepads.connect(1, "Other");
+
+        gamepads.set_physical_button_pressed(0, "South", true);
+        gamepads.set_physical_button_pressed(0, "LeftTrigger", true); // Not mapped
+        gamepads.set_physical_button_pressed(1, "South", true); // B with the default profile
+        assert_eq!(gamepads.poll(), [0b0000_0001, 0b0000_0010, 0, 0]);
+
+        // Stick thresholds: 30% for the first pad, 50% for the second one
+        gamepads.set_stick(0, StickAxis::X, 0.4);
+        gamepads.set_stick(1, StickAxis::X, -0.4);
+        gamepads.set_stick(1, StickAxis::Y, 0.9);
+        assert_eq!(gamepads.poll(), [0b1000_0001, 0b0001_0010, 0, 0]);
+        gamepads.set_stick(0, StickAxis::X, -0.35);
+        gamepads.set_stick(1, StickAxis::Y, 0.1);
+        assert_eq!(gamepads.poll(), [0b0100_0001, 0b0000_0010, 0, 0]);
+    }
+
+    #[test]
+    fn test_composite_recording_and_replay() {
+        let mut keyboard = KeyboardInput::new();
+        keyboard.bind(30, 0, JoypadButton::A);
+        keyboard.key_down(30);
+        let mut gamepads = GamepadInput::new();
+        gamepads.assign(0, 0);
+        gamepads.set_button_pressed(0, JoypadButton::Right, true);
+
+        let mut composite = CompositeInput::new();
+        composite.add(Box::new(keyboard));
+        composite.add(Box::new(gamepads));
+        let mut recording = RecordingInput::new(Box::new(composite));
+        assert_eq!(recording.poll(), [0b1000_0001, 0, 0, 0]);
+        assert_eq!(recording.poll(), [0b1000_0001, 0, 0, 0]);
+
+        let mut replay = ReplayInput::from_bytes(&recording.replay.to_bytes()).unwrap();
+        assert_eq!(replay, recording.replay);
+        assert_eq!(replay.poll(), [0b1000_0001, 0, 0, 0]);
+        assert_eq!(replay.poll(), [0b1000_0001, 0, 0, 0]);
+        assert!(replay.is_finished());
+        assert_eq!(replay.poll(), [0; 4]);
+    }
+
+    #[test]
+    fn test_invalid_replay() {
+        assert!(ReplayInput::from_bytes(b"NESMOVIE").is_err());
+        assert!(ReplayInput::from_bytes(b"NESINPUT\x01\x02").is_err());
+        assert_eq!(ReplayInput::from_bytes(b"NESINPUT").unwrap().frames.len(), 0);
+    }
+}
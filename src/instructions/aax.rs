@@ -1,7 +1,7 @@
 use crate::cpu6502::CPU;
 
 impl CPU {
-    pub(crate) fn handle_aax(& mut self, _opt_value: Option<u8>, opt_address: Option<u16>) -> u8 {
+    pub fn handle_aax(& mut self, _opt_value: Option<u8>, opt_address: Option<u16>) -> u8 {
         let address = opt_address.expect("BUG: address for AAX should be present");
         let value = self.accumulator & self.x_register;
         self.write_u8(address, value);
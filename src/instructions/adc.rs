@@ -1,12 +1,17 @@
 use crate::cpu6502::{CPU, StatusFlag};
 
 impl CPU {
-    pub(crate) fn handle_adc(& mut self, opt_value: Option<u8>, _opt_address: Option<u16>) -> u8 {
+    pub fn handle_adc(& mut self, opt_value: Option<u8>, _opt_address: Option<u16>) -> u8 {
         let value = opt_value.expect("BUG: memory value of ADC should be present");
 
         // Get current carry flag and operands
         let carry_in = if self.get_status_flag(StatusFlag::Carry) { 1 } else { 0 };
 
+        if self.is_decimal_mode_active() {
+            self.adc_decimal(value, carry_in as u8);
+            return 0;
+        }
+
         // Perform addition
         let sum = (self.accumulator as u16) + (value as u16) + carry_in;
         let result = sum as u8;
@@ -36,14 +41,69 @@ impl CPU {
         self.accumulator = result;
         return 0;
     }
+
+    // BCD addition of the NMOS 6502: each nibble is adjusted when it goes past 9.
+    // Z is computed from the binary sum, N and V from the result before the high nibble is adjusted.
+    fn adc_decimal(&mut self, value: u8, carry_in: u8) {
+        let accumulator = self.accumulator;
+        let binary = accumulator.wrapping_add(value).wrapping_add(carry_in);
+
+        let mut low = (accumulator & 0x0F) + (value & 0x0F) + carry_in;
+        if low > 0x09 {
+            low += 0x06;
+        }
+        let mut high = (accumulator >> 4) as u16 + (value >> 4) as u16 + if low > 0x0F { 1 } else { 0 };
+        let unadjusted = (high << 4) as u8;
+
+        self.set_status_flag(StatusFlag::Zero, binary == 0);
+        self.set_status_flag(StatusFlag::Negative, unadjusted & 0x80 != 0);
+        self.set_status_flag(StatusFlag::Overflow, !(accumulator ^ value) & (accumulator ^ unadjusted) & 0x80 != 0);
+
+        if high > 0x09 {
+            high += 0x06;
+        }
+        self.set_status_flag(StatusFlag::Carry, high > 0x0F);
+        self.accumulator = ((high as u8) << 4) | (low & 0x0F);
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use crate::bus::Bus;
-    use crate::cpu6502::{new_cpu, StatusFlag};
+    use crate::cpu6502::{new_cpu, CpuVariant, StatusFlag};
     use crate::rom::Rom;
 
+    #[test]
+    fn test_adc_decimal_mode() {
+        let mut cpu = new_cpu(Bus::new(Rom::test_rom()));
+        cpu.set_status_flag(StatusFlag::DecimalMode, true);
+        // The NES CPU ignores the decimal flag
+        cpu.accumulator = 0x09;
+        cpu.handle_adc(Some(0x01), None);
+        assert_eq!(cpu.accumulator, 0x0A);
+
+        cpu.variant = CpuVariant::Nmos6502;
+        cpu.accumulator = 0x09;
+        cpu.handle_adc(Some(0x01), None);
+        assert_eq!(cpu.accumulator, 0x10);
+        assert!(!cpu.get_status_flag(StatusFlag::Carry));
+
+        // 58 + 46 + 1 = 105
+        cpu.accumulator = 0x58;
+        cpu.set_status_flag(StatusFlag::Carry, true);
+        cpu.handle_adc(Some(0x46), None);
+        assert_eq!(cpu.accumulator, 0x05);
+        assert!(cpu.get_status_flag(StatusFlag::Carry));
+
+        // 99 + 1 = 100: Z comes from the binary sum (0x9A)
+        cpu.accumulator = 0x99;
+        cpu.set_status_flag(StatusFlag::Carry, false);
+        cpu.handle_adc(Some(0x01), None);
+        assert_eq!(cpu.accumulator, 0x00);
+        assert!(cpu.get_status_flag(StatusFlag::Carry));
+        assert!(!cpu.get_status_flag(StatusFlag::Zero));
+    }
+
     #[test]
     fn test_adc_instruction() {
         let mut cpu = new_cpu(Bus::new(Rom::test_rom()));
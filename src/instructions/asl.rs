@@ -1,7 +1,7 @@
 use crate::cpu6502::{CPU, StatusFlag};
 
 impl CPU {
-    pub(crate) fn handle_asl(& mut self, opt_value: Option<u8>, opt_address: Option<u16>) -> u8 {
+    pub fn handle_asl(& mut self, opt_value: Option<u8>, opt_address: Option<u16>) -> u8 {
         let value = opt_value.expect("BUG: memory value of ASL should be present");
         let result = value << 1;
 
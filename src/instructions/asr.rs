@@ -1,7 +1,7 @@
 use crate::cpu6502::{CPU, StatusFlag};
 
 impl CPU {
-    pub(crate) fn handle_asr(& mut self, opt_value: Option<u8>, _opt_address: Option<u16>) -> u8 {
+    pub fn handle_asr(& mut self, opt_value: Option<u8>, _opt_address: Option<u16>) -> u8 {
         let value = opt_value.expect("BUG: memory value of ASR should be present");
         let temp = self.accumulator & value;
 
@@ -1,10 +1,11 @@
 use crate::cpu6502::{CPU, StatusFlag};
 
 impl CPU {
-    // ATX: AND immediate with accumulator, then transfer accumulator to X
-    pub(crate) fn handle_atx(& mut self, opt_value: Option<u8>, _opt_address: Option<u16>) -> u8 {
+    // ATX (LXA): AND immediate with accumulator, then transfer accumulator to X.
+    // Like XAA, the accumulator is first ORed with the magic constant (see MAGIC_CONSTANT_FF).
+    pub fn handle_atx(& mut self, opt_value: Option<u8>, _opt_address: Option<u16>) -> u8 {
         let value = opt_value.expect("BUG: memory value of ATX should be present");
-        self.accumulator = self.accumulator & value;
+        self.accumulator = (self.accumulator | self.magic_constant) & value;
         self.x_register = self.accumulator;
 
         self.set_status_flag(StatusFlag::Zero, self.accumulator == 0);
@@ -16,17 +17,25 @@ impl CPU {
 #[cfg(test)]
 mod tests {
     use crate::bus::Bus;
-    use crate::cpu6502::{new_cpu, StatusFlag};
+    use crate::cpu6502::{new_cpu, StatusFlag, MAGIC_CONSTANT_EE};
     use crate::rom::Rom;
 
     #[test]
     fn test_atx_and_transfers_to_x() {
         let mut cpu = new_cpu(Bus::new(Rom::test_rom()));
-        cpu.accumulator = 0b1010_1010;
+        cpu.accumulator = 0b0010_0001;
+        // With the default constant 0xFF, the immediate value is loaded as is
         let _ = cpu.handle_atx(Some(0b1100_1100), None);
-        assert_eq!(cpu.accumulator, 0b1000_1000);
-        assert_eq!(cpu.x_register, 0b1000_1000);
+        assert_eq!(cpu.accumulator, 0b1100_1100);
+        assert_eq!(cpu.x_register, 0b1100_1100);
         assert!(!cpu.get_status_flag(StatusFlag::Zero));
         assert!(cpu.get_status_flag(StatusFlag::Negative));
+
+        cpu.magic_constant = MAGIC_CONSTANT_EE;
+        cpu.accumulator = 0b0001_0000;
+        let _ = cpu.handle_atx(Some(0b0001_0001), None);
+        // (0x10 | 0xEE) & 0x11 = 0x10
+        assert_eq!(cpu.accumulator, 0b0001_0000);
+        assert_eq!(cpu.x_register, 0b0001_0000);
     }
 }
@@ -4,7 +4,7 @@ impl CPU {
     // AXS (also called SBX): A & X, store in X, then X - imm (without borrow)
     // Implement behavior observed: X = (A & X) & imm? Older sources show: X = (A & X) AND operand then X = X - operand
     // We'll implement widely-known AXS behaviour: A & X -> temp, temp - value -> X (affects N,Z,C)
-    pub(crate) fn handle_axs(& mut self, opt_value: Option<u8>, _opt_address: Option<u16>) -> u8 {
+    pub fn handle_axs(& mut self, opt_value: Option<u8>, _opt_address: Option<u16>) -> u8 {
         let value = opt_value.expect("BUG: memory value of AXS should be present");
         let temp = self.accumulator & self.x_register;
         // Subtract immediate from temp without borrow (i.e., temp - value), set carry if temp >= value
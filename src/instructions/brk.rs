@@ -1,7 +1,7 @@
 use crate::cpu6502::{CPU, StatusFlag};
 
 impl CPU {
-    pub(crate) fn handle_brk(& mut self, _opt_value: Option<u8>, _opt_address: Option<u16>) -> u8 {
+    pub fn handle_brk(& mut self, _opt_value: Option<u8>, _opt_address: Option<u16>) -> u8 {
         // 1. Push Program Counter + 2 to the stack
         // (PC is incremented by 2 to account for the BRK instruction and its padding byte)
         self.push_u16(self.program_counter + 2);
@@ -16,8 +16,9 @@ impl CPU {
         self.set_status_flag(StatusFlag::InterruptDisable, true);
 
         // 4. Load PC from interrupt vector at 0xFFFE
-        // Note: The BRK instruction shares the IRQ vector.
-        self.program_counter = self.read_u16(0xFFFE);
+        // Note: The BRK instruction shares the IRQ vector. An NMI detected in time hijacks it.
+        let vector = self.interrupt_vector(self.cycles);
+        self.program_counter = self.read_u16(vector);
 
         return 0;
     }
@@ -2,7 +2,7 @@ use crate::cpu6502::{CPU, StatusFlag};
 
 
 impl CPU {
-    pub(crate) fn handle_cpy(& mut self, opt_value: Option<u8>, _opt_address: Option<u16>) -> u8 {
+    pub fn handle_cpy(& mut self, opt_value: Option<u8>, _opt_address: Option<u16>) -> u8 {
         let value = opt_value.expect("BUG: memory value of CPY should be present");
         let result = self.y_register.wrapping_sub(value);
 
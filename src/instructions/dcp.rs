@@ -1,7 +1,7 @@
 use crate::cpu6502::{CPU, StatusFlag};
 
 impl CPU {
-    pub(crate) fn handle_dcp(& mut self, opt_value: Option<u8>, opt_address: Option<u16>) -> u8 {
+    pub fn handle_dcp(& mut self, opt_value: Option<u8>, opt_address: Option<u16>) -> u8 {
         let value = opt_value.expect("BUG: memory value of DCP should be present");
         let address = opt_address.expect("BUG: address of DCP should be present");
 
@@ -1,7 +1,7 @@
 use crate::cpu6502::{CPU, StatusFlag};
 
 impl CPU {
-    pub(crate) fn handle_dec(& mut self, opt_value: Option<u8>, opt_address: Option<u16>) -> u8 {
+    pub fn handle_dec(& mut self, opt_value: Option<u8>, opt_address: Option<u16>) -> u8 {
         let value = opt_value.expect("BUG: memory value of DEC should be present");
         let address = opt_address.expect("BUG: address of DEC should be present");
 
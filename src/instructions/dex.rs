@@ -1,7 +1,7 @@
 use crate::cpu6502::{CPU, StatusFlag};
 
 impl CPU {
-    pub(crate) fn handle_dex(& mut self, _opt_value: Option<u8>, _opt_address: Option<u16>) -> u8 {
+    pub fn handle_dex(& mut self, _opt_value: Option<u8>, _opt_address: Option<u16>) -> u8 {
         let result = self.x_register.wrapping_sub(1);
         self.x_register = result;
 
@@ -1,7 +1,7 @@
 use crate::cpu6502::{CPU, StatusFlag};
 
 impl CPU {
-    pub(crate) fn handle_inx(& mut self, _opt_value: Option<u8>, _opt_address: Option<u16>) -> u8 {
+    pub fn handle_inx(& mut self, _opt_value: Option<u8>, _opt_address: Option<u16>) -> u8 {
         let result = self.x_register.wrapping_add(1);
         self.set_status_flag(StatusFlag::Zero, result == 0);
         self.set_status_flag(StatusFlag::Negative, result & 0x80 != 0);
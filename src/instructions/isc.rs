@@ -2,7 +2,7 @@ use crate::cpu6502::{CPU, StatusFlag};
 
 impl CPU {
     // ISC (ISB): increment memory then SBC (A - M - (1-C))
-    pub(crate) fn handle_isc(& mut self, opt_value: Option<u8>, opt_address: Option<u16>) -> u8 {
+    pub fn handle_isc(& mut self, opt_value: Option<u8>, opt_address: Option<u16>) -> u8 {
         let value = opt_value.expect("BUG: memory value of ISC should be present");
         let address = opt_address.expect("BUG: address of ISC should be present");
 
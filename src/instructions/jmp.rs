@@ -1,7 +1,7 @@
 use crate::cpu6502::CPU;
 
 impl CPU {
-    pub(crate) fn handle_jmp(& mut self, _opt_value: Option<u8>, opt_address: Option<u16>) -> u8 {
+    pub fn handle_jmp(& mut self, _opt_value: Option<u8>, opt_address: Option<u16>) -> u8 {
         let address = opt_address.expect("BUG: address of JMP should be present");
         self.program_counter = address;
         return 0;
@@ -1,16 +1,21 @@
 use crate::cpu6502::CPU;
 
 impl CPU {
-    pub(crate) fn handle_jsr(& mut self, _opt_value: Option<u8>, opt_address: Option<u16>) -> u8 {
-        let target_address = opt_address.expect("BUG: address of JSR should be present");
+    // The operand is read by the handler, since the CPU reads its high byte after the pushes:
+    // low byte, dummy read of the stack, pushes, high byte. When the code runs from the stack page,
+    // the pushes can overwrite the high byte before it is read.
+    pub fn handle_jsr(& mut self, _opt_value: Option<u8>, _opt_address: Option<u16>) -> u8 {
+        let low = self.read_u8(self.program_counter.wrapping_add(1));
+        self.dummy_stack_read();
 
         // JSR is a 3-byte instruction. It pushes the address of its last byte (PC+2)
         // onto the stack. This serves as the "return address minus one" for RTS.
-        let return_address = self.program_counter + 2;
+        let return_address = self.program_counter.wrapping_add(2);
         self.push_u16(return_address);
 
         // Set the program counter to the target address to jump to the subroutine.
-        self.program_counter = target_address;
+        let high = self.read_u8(return_address);
+        self.program_counter = u16::from_le_bytes([low, high]);
         return 0;
     }
 }
@@ -20,17 +25,31 @@ mod tests {
 
     use crate::bus::Bus;
     use crate::cpu6502::new_cpu;
-    use crate::rom::Rom;
 
     #[test]
     fn test_jsr_pushes_return_address_and_jumps() {
-        let mut cpu = new_cpu(Bus::new(Rom::test_rom()));
+        let mut cpu = new_cpu(Bus::new_flat());
+        cpu.stack_pointer = 0xFF;
         cpu.program_counter = 0x8000; // JSR is at 0x8000
-        cpu.handle_jsr(None, Some(0x1234));
+        cpu.write_u16(0x8001, 0x1234);
+        cpu.handle_jsr(None, None);
 
         assert_eq!(cpu.program_counter, 0x1234, "PC should jump to target address");
         assert_eq!(cpu.stack_pointer, 0xFD, "Stack pointer should be decremented by 2");
         // The address of the last byte of the instruction (0x8000 + 2 = 0x8002) should be pushed.
         assert_eq!(cpu.read_u16(0x01FE), 0x8002, "Return address (minus one) should be pushed to the stack");
     }
+
+    #[test]
+    fn test_jsr_reads_high_byte_after_pushes() {
+        let mut cpu = new_cpu(Bus::new_flat());
+        // JSR $1234 at 0x01FD: pushing 0x01FF overwrites the high byte of its operand with 0x01
+        cpu.stack_pointer = 0xFF;
+        cpu.program_counter = 0x01FD;
+        cpu.write_u16(0x01FE, 0x1234);
+        cpu.handle_jsr(None, None);
+
+        assert_eq!(cpu.program_counter, 0x0134);
+        assert_eq!(cpu.stack_pointer, 0xFD);
+    }
 }
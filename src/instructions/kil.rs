@@ -1,18 +1,42 @@
+use std::fmt;
 use crate::cpu6502::CPU;
 
+// Where and how the CPU jammed, for frontends to report the crash.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CpuJam {
+	pub address: u16,
+	pub opcode: u8,
+}
+
+impl fmt::Display for CpuJam {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "CPU crashed at ${:04X} (KIL opcode ${:02X})", self.address, self.opcode)
+	}
+}
+
 impl CPU {
 	// KIL / JAM / HLT — on real 6502 these opcodes halt the CPU permanently.
 	// In this emulator we set a halted flag so the run loop exits cleanly.
-	pub(crate) fn handle_kil(& mut self, _opt_value: Option<u8>, _opt_address: Option<u16>) -> u8 {
+	// The program counter stays on the opcode, like on the hardware, so the jam can be reported.
+	pub fn handle_kil(& mut self, _opt_value: Option<u8>, _opt_address: Option<u16>) -> u8 {
 		self.halted = true;
 		return 0;
 	}
+
+	// Returns the jam when the CPU is stuck on a KIL opcode. Only a reset gets it out of this state.
+	pub fn jam(&self) -> Option<CpuJam> {
+		if !self.halted {
+			return None;
+		}
+		Some(CpuJam { address: self.program_counter, opcode: self.peek_u8(self.program_counter) })
+	}
 }
 
 #[cfg(test)]
 mod tests {
 	use crate::bus::Bus;
 	use crate::cpu6502::new_cpu;
+	use crate::instructions::kil::CpuJam;
 	use crate::rom::Rom;
 
 	#[test]
@@ -25,8 +49,25 @@ mod tests {
 		assert!(cpu.halted);
 	}
 
-	// Note: we avoid testing `run_with_callback` here because the emulator's opcode table
-	// references many handlers; some less-common unofficial handlers may not be present
-	// in this branch and would cause compilation failures when building the full map.
-}
+	#[test]
+	fn test_run_reports_jam() {
+		let mut cpu = new_cpu(Bus::new(Rom::test_rom()));
+		// INX, INX, KIL, INX
+		for (offset, byte) in [0xE8, 0xE8, 0x12, 0xE8].iter().enumerate() {
+			cpu.write_u8(0x0600 + offset as u16, *byte);
+		}
+		cpu.program_counter = 0x0600;
+		assert_eq!(cpu.jam(), None);
 
+		let jam = cpu.run_with_callback(|_| {});
+		assert_eq!(jam, CpuJam { address: 0x0602, opcode: 0x12 });
+		assert_eq!(jam.to_string(), "CPU crashed at $0602 (KIL opcode $12)");
+		assert_eq!(cpu.x_register, 2);
+
+		// The CPU stays stuck
+		cpu.step();
+		assert_eq!(cpu.program_counter, 0x0602);
+		assert_eq!(cpu.x_register, 2);
+		assert_eq!(cpu.jam(), Some(jam));
+	}
+}
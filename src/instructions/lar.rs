@@ -3,7 +3,7 @@ use crate::cpu6502::{CPU, StatusFlag};
 impl CPU {
 	// LAR — AND memory with stack pointer, transfer result to A, X and SP
 	// Flags: N, Z
-	pub(crate) fn handle_lar(& mut self, opt_value: Option<u8>, _opt_address: Option<u16>) -> u8 {
+	pub fn handle_lar(& mut self, opt_value: Option<u8>, _opt_address: Option<u16>) -> u8 {
 		let value = opt_value.expect("BUG: memory value of LAR should be present");
 
 		let result = value & self.stack_pointer;
@@ -2,7 +2,7 @@ use crate::cpu6502::{CPU, StatusFlag};
 
 impl CPU {
     // LAX loads accumulator and X with the memory operand and sets N/Z
-    pub(crate) fn handle_lax(& mut self, opt_value: Option<u8>, _opt_address: Option<u16>) -> u8 {
+    pub fn handle_lax(& mut self, opt_value: Option<u8>, _opt_address: Option<u16>) -> u8 {
         let value = opt_value.expect("BUG: memory value of LAX should be present");
         self.accumulator = value;
         self.x_register = value;
@@ -1,7 +1,7 @@
 use crate::cpu6502::CPU;
 
 impl CPU {
-    pub(crate) fn handle_nop(& mut self, _opt_value: Option<u8>, _opt_address: Option<u16>) -> u8 {
+    pub fn handle_nop(& mut self, _opt_value: Option<u8>, _opt_address: Option<u16>) -> u8 {
         // NOP does nothing.
         return 0;
     }
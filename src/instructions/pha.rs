@@ -1,7 +1,7 @@
 use crate::cpu6502::CPU;
 
 impl CPU {
-    pub(crate) fn handle_pha(& mut self, _opt_value: Option<u8>, _opt_address: Option<u16>) -> u8 {
+    pub fn handle_pha(& mut self, _opt_value: Option<u8>, _opt_address: Option<u16>) -> u8 {
         self.push_u8(self.accumulator);
         return 0;
     }
@@ -1,7 +1,7 @@
 use crate::cpu6502::{CPU, StatusFlag};
 
 impl CPU {
-    pub(crate) fn handle_php(& mut self, _opt_value: Option<u8>, _opt_address: Option<u16>) -> u8 {
+    pub fn handle_php(& mut self, _opt_value: Option<u8>, _opt_address: Option<u16>) -> u8 {
         // When PHP is used, the status register is pushed to the stack
         // with the Break (B) and Unused (U) flags set to 1.
         let mut status = self.status_register;
@@ -1,7 +1,8 @@
 use crate::cpu6502::{CPU, StatusFlag};
 
 impl CPU {
-    pub(crate) fn handle_pla(& mut self, _opt_value: Option<u8>, _opt_address: Option<u16>) -> u8 {
+    pub fn handle_pla(& mut self, _opt_value: Option<u8>, _opt_address: Option<u16>) -> u8 {
+        self.dummy_stack_read();
         let value = self.pop_u8();
         self.accumulator = value;
 
@@ -3,7 +3,7 @@ use crate::cpu6502::{CPU, StatusFlag};
 impl CPU {
     // RLA — rotate memory left (like ROL) then AND accumulator with memory
     // Flags: N,Z,C (based on AND result and rotation carry)
-    pub(crate) fn handle_rla(& mut self, opt_value: Option<u8>, opt_address: Option<u16>) -> u8 {
+    pub fn handle_rla(& mut self, opt_value: Option<u8>, opt_address: Option<u16>) -> u8 {
         let value = opt_value.expect("BUG: memory value of RLA should be present");
 
         // ROL on memory value using current carry
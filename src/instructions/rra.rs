@@ -3,7 +3,7 @@ use crate::cpu6502::{CPU, StatusFlag};
 impl CPU {
     // RRA — rotate right memory (like ROR) then ADC with accumulator
     // Flags: N,V,Z,C (ADC result)
-    pub(crate) fn handle_rra(& mut self, opt_value: Option<u8>, opt_address: Option<u16>) -> u8 {
+    pub fn handle_rra(& mut self, opt_value: Option<u8>, opt_address: Option<u16>) -> u8 {
         let value = opt_value.expect("BUG: memory value of RRA should be present");
 
         // ROR on memory value using current carry
@@ -1,7 +1,8 @@
 use crate::cpu6502::{CPU, StatusFlag};
 
 impl CPU {
-    pub(crate) fn handle_rti(& mut self, _opt_value: Option<u8>, _opt_address: Option<u16>) -> u8 {
+    pub fn handle_rti(& mut self, _opt_value: Option<u8>, _opt_address: Option<u16>) -> u8 {
+        self.dummy_stack_read();
         let popped_status = self.pop_u8();
         self.program_counter = self.pop_u16();
 
@@ -1,10 +1,13 @@
 use crate::cpu6502::CPU;
 
 impl CPU {
-    pub(crate) fn handle_rts(& mut self, _opt_value: Option<u8>, _opt_address: Option<u16>) -> u8 {
+    pub fn handle_rts(& mut self, _opt_value: Option<u8>, _opt_address: Option<u16>) -> u8 {
         // RTS pulls the return address (minus one) from the stack, increments it,
         // and then sets the program counter to that address.
+        self.dummy_stack_read();
         let return_address_minus_one = self.pop_u16();
+        // The last cycle reads the pulled address while incrementing it
+        self.read_u8(return_address_minus_one);
         self.program_counter = return_address_minus_one.wrapping_add(1);
         return 0;
     }
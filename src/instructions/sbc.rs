@@ -1,7 +1,7 @@
 use crate::cpu6502::{CPU, StatusFlag};
 
 impl CPU {
-    pub(crate) fn handle_sbc(& mut self, opt_value: Option<u8>, _opt_address: Option<u16>) -> u8 {
+    pub fn handle_sbc(& mut self, opt_value: Option<u8>, _opt_address: Option<u16>) -> u8 {
         let value = opt_value.expect("BUG: memory value of SBC should be present");
 
         // SBC is implemented as ADC with the operand's bits inverted.
@@ -37,17 +37,68 @@ impl CPU {
                        (signed_accumulator < 0 && signed_value < 0 && signed_result >= 0);
         self.set_status_flag(StatusFlag::Overflow, overflow);
 
-        self.accumulator = result;
+        // The NMOS 6502 sets the flags from the binary result, even in decimal mode
+        self.accumulator = if self.is_decimal_mode_active() {
+            Self::sbc_decimal(self.accumulator, value, carry_in as u8)
+        } else {
+            result
+        };
         return 0;
     }
+
+    // BCD subtraction of the NMOS 6502: each nibble is adjusted when it borrows.
+    fn sbc_decimal(accumulator: u8, value: u8, carry_in: u8) -> u8 {
+        let mut low = (accumulator & 0x0F) as i16 - (value & 0x0F) as i16 - (1 - carry_in as i16);
+        let mut high = (accumulator >> 4) as i16 - (value >> 4) as i16;
+        if low < 0 {
+            low -= 0x06;
+            high -= 1;
+        }
+        if high < 0 {
+            high -= 0x06;
+        }
+        (((high as u8) << 4) & 0xF0) | (low as u8 & 0x0F)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use crate::bus::Bus;
-    use crate::cpu6502::{new_cpu, StatusFlag};
+    use crate::cpu6502::{new_cpu, CpuVariant, StatusFlag};
     use crate::rom::Rom;
 
+    #[test]
+    fn test_sbc_decimal_mode() {
+        let mut cpu = new_cpu(Bus::new(Rom::test_rom()));
+        cpu.variant = CpuVariant::Nmos6502;
+        cpu.set_status_flag(StatusFlag::DecimalMode, true);
+
+        // 46 - 12 = 34
+        cpu.accumulator = 0x46;
+        cpu.set_status_flag(StatusFlag::Carry, true);
+        cpu.handle_sbc(Some(0x12), None);
+        assert_eq!(cpu.accumulator, 0x34);
+        assert!(cpu.get_status_flag(StatusFlag::Carry));
+
+        // 40 - 13 = 27
+        cpu.accumulator = 0x40;
+        cpu.handle_sbc(Some(0x13), None);
+        assert_eq!(cpu.accumulator, 0x27);
+
+        // 32 - 2 - borrow = 29
+        cpu.accumulator = 0x32;
+        cpu.set_status_flag(StatusFlag::Carry, false);
+        cpu.handle_sbc(Some(0x02), None);
+        assert_eq!(cpu.accumulator, 0x29);
+
+        // 12 - 21 = -9, i.e. 91 with a borrow
+        cpu.accumulator = 0x12;
+        cpu.set_status_flag(StatusFlag::Carry, true);
+        cpu.handle_sbc(Some(0x21), None);
+        assert_eq!(cpu.accumulator, 0x91);
+        assert!(!cpu.get_status_flag(StatusFlag::Carry));
+    }
+
     #[test]
     fn test_sbc_basic_subtraction() {
         let mut cpu = new_cpu(Bus::new(Rom::test_rom()));
@@ -3,7 +3,7 @@ use crate::cpu6502::{CPU, StatusFlag};
 impl CPU {
     // SLO — ASL memory then OR with accumulator
     // Flags: N,Z,C (from ASL and OR result)
-    pub(crate) fn handle_slo(& mut self, opt_value: Option<u8>, opt_address: Option<u16>) -> u8 {
+    pub fn handle_slo(& mut self, opt_value: Option<u8>, opt_address: Option<u16>) -> u8 {
         let value = opt_value.expect("BUG: memory value of SLO should be present");
 
         // ASL on memory
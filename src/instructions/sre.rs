@@ -3,7 +3,7 @@ use crate::cpu6502::{CPU, StatusFlag};
 impl CPU {
     // SRE — LSR memory then EOR with accumulator
     // Flags: N,Z,C
-    pub(crate) fn handle_sre(& mut self, opt_value: Option<u8>, opt_address: Option<u16>) -> u8 {
+    pub fn handle_sre(& mut self, opt_value: Option<u8>, opt_address: Option<u16>) -> u8 {
         let value = opt_value.expect("BUG: memory value of SRE should be present");
 
         // LSR on memory
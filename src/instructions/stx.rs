@@ -1,7 +1,7 @@
 use crate::cpu6502::CPU;
 
 impl CPU {
-    pub(crate) fn handle_stx(& mut self, _opt_value: Option<u8>, _opt_address: Option<u16>) -> u8 {
+    pub fn handle_stx(& mut self, _opt_value: Option<u8>, _opt_address: Option<u16>) -> u8 {
         let address = _opt_address.expect("BUG: address of STX should be present");
         self.write_u8(address, self.x_register);
         return 0;
@@ -2,14 +2,21 @@ use crate::cpu6502::{CPU};
 
 impl CPU {
     // SXA (SHX) - AND X register with the high byte of the argument + 1, store result into memory
-    // M = X & (HIGH(arg) + 1)
+    // M = X & (HIGH(arg) + 1), the argument being the address before adding Y.
+    // Like AXA, a page crossing replaces the high byte of the address written to with M.
     // No flags affected.
-    pub(crate) fn handle_sxa(& mut self, _opt_value: Option<u8>, opt_address: Option<u16>) -> u8 {
+    pub fn handle_sxa(& mut self, _opt_value: Option<u8>, opt_address: Option<u16>) -> u8 {
         let address = opt_address.expect("BUG: address of SXA should be present");
 
-        let high = (address >> 8) as u8;
+        let base_address = address.wrapping_sub(self.y_register as u16);
+        let high = (base_address >> 8) as u8;
         let result = self.x_register & high.wrapping_add(1);
-        self.write_u8(address, result);
+        let target = if self.page_crossed(base_address, address) {
+            ((result as u16) << 8) | (address & 0x00FF)
+        } else {
+            address
+        };
+        self.write_u8(target, result);
 
         return 0;
     }
@@ -51,4 +58,17 @@ mod tests {
         // high = 0x01 ; high+1 = 0x02 ; result = X & 0x02 = 0x02
         assert_eq!(cpu.read_u8(addr), 0x02);
     }
+
+    #[test]
+    fn test_sxa_page_crossing_corrupts_address() {
+        let mut cpu = new_cpu(Bus::new(Rom::test_rom()));
+        cpu.x_register = 0x05;
+        cpu.y_register = 0x20;
+        // Base address 0x01F0: the value 0x05 & 0x02 = 0x00 is written at 0x0010 instead of 0x0210
+        cpu.write_u8(0x0210, 0xAA);
+        cpu.write_u8(0x0010, 0xAA);
+        let _ = cpu.handle_sxa(None, Some(0x0210));
+        assert_eq!(cpu.read_u8(0x0210), 0xAA);
+        assert_eq!(cpu.read_u8(0x0010), 0x00);
+    }
 }
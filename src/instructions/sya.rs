@@ -2,14 +2,21 @@ use crate::cpu6502::CPU;
 
 impl CPU {
     // SYA (SHY/SAY) - AND Y register with the high byte of the argument + 1, store result into memory
-    // M = Y & (HIGH(arg) + 1)
+    // M = Y & (HIGH(arg) + 1), the argument being the address before adding X.
+    // Like AXA, a page crossing replaces the high byte of the address written to with M.
     // No flags affected.
-    pub(crate) fn handle_sya(& mut self, _opt_value: Option<u8>, opt_address: Option<u16>) -> u8 {
+    pub fn handle_sya(& mut self, _opt_value: Option<u8>, opt_address: Option<u16>) -> u8 {
         let address = opt_address.expect("BUG: address of SYA should be present");
 
-        let high = (address >> 8) as u8;
+        let base_address = address.wrapping_sub(self.x_register as u16);
+        let high = (base_address >> 8) as u8;
         let result = self.y_register & high.wrapping_add(1);
-        self.write_u8(address, result);
+        let target = if self.page_crossed(base_address, address) {
+            ((result as u16) << 8) | (address & 0x00FF)
+        } else {
+            address
+        };
+        self.write_u8(target, result);
 
         return 0;
     }
@@ -49,4 +56,17 @@ mod tests {
         // result = 0xFF & 0x02 = 0x02
         assert_eq!(cpu.read_u8(addr), 0x02);
     }
+
+    #[test]
+    fn test_sya_page_crossing_corrupts_address() {
+        let mut cpu = new_cpu(Bus::new(Rom::test_rom()));
+        cpu.y_register = 0x05;
+        cpu.x_register = 0x20;
+        // Base address 0x01F0: the value 0x05 & 0x02 = 0x00 is written at 0x0010 instead of 0x0210
+        cpu.write_u8(0x0210, 0xAA);
+        cpu.write_u8(0x0010, 0xAA);
+        let _ = cpu.handle_sya(None, Some(0x0210));
+        assert_eq!(cpu.read_u8(0x0210), 0xAA);
+        assert_eq!(cpu.read_u8(0x0010), 0x00);
+    }
 }
@@ -1,10 +1,10 @@
 use crate::cpu6502::{CPU, StatusFlag};
 
 impl CPU {
-    // XAA / ANE – unofficial: A = (A & X) & imm
-    pub(crate) fn handle_xaa(& mut self, opt_value: Option<u8>, _opt_address: Option<u16>) -> u8 {
+    // XAA / ANE – unofficial: A = (A | magic) & X & imm
+    pub fn handle_xaa(& mut self, opt_value: Option<u8>, _opt_address: Option<u16>) -> u8 {
         let value = opt_value.expect("BUG: memory value of XAA should be present");
-        let result = (self.accumulator & self.x_register) & value;
+        let result = (self.accumulator | self.magic_constant) & self.x_register & value;
         self.accumulator = result;
 
         self.set_status_flag(StatusFlag::Zero, result == 0);
@@ -16,7 +16,7 @@ impl CPU {
 #[cfg(test)]
 mod tests {
     use crate::bus::Bus;
-    use crate::cpu6502::{new_cpu};
+    use crate::cpu6502::{new_cpu, MAGIC_CONSTANT_EE};
     use crate::rom::Rom;
 
     #[test]
@@ -32,4 +32,20 @@ mod tests {
         let _ = cpu.handle_xaa(Some(0x0B), None);
         assert_eq!(cpu.accumulator, 0x0B);
     }
+
+    #[test]
+    fn test_xaa_magic_constant() {
+        let mut cpu = new_cpu(Bus::new(Rom::test_rom()));
+        cpu.magic_constant = MAGIC_CONSTANT_EE;
+        // Bits 0 and 4 of the accumulator are not forced to 1
+        cpu.accumulator = 0x00;
+        cpu.x_register = 0xFF;
+        let _ = cpu.handle_xaa(Some(0xFF), None);
+        assert_eq!(cpu.accumulator, 0xEE);
+
+        cpu.accumulator = 0x11;
+        cpu.x_register = 0xFF;
+        let _ = cpu.handle_xaa(Some(0xFF), None);
+        assert_eq!(cpu.accumulator, 0xFF);
+    }
 }
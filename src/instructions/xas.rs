@@ -3,19 +3,26 @@ use crate::cpu6502::CPU;
 impl CPU {
     // XAS (SHS/TAS) — AND X with A, store result to stack pointer S, then store S & (HIGH(arg)+1) into memory.
     // S = X & A
-    // M = S & (HIGH(arg) + 1)
+    // M = S & (HIGH(arg) + 1), the argument being the address before adding Y.
+    // Like AXA, a page crossing replaces the high byte of the address written to with M.
     // No flags affected.
-    pub(crate) fn handle_xas(& mut self, _opt_value: Option<u8>, opt_address: Option<u16>) -> u8 {
+    pub fn handle_xas(& mut self, _opt_value: Option<u8>, opt_address: Option<u16>) -> u8 {
         let address = opt_address.expect("BUG: address of XAS should be present");
 
         let s = self.x_register & self.accumulator;
         // store into stack pointer
         self.stack_pointer = s;
 
-        let high = (address >> 8) as u8;
+        let base_address = address.wrapping_sub(self.y_register as u16);
+        let high = (base_address >> 8) as u8;
         let mem_val = s & high.wrapping_add(1);
+        let target = if self.page_crossed(base_address, address) {
+            ((mem_val as u16) << 8) | (address & 0x00FF)
+        } else {
+            address
+        };
 
-        self.write_u8(address, mem_val);
+        self.write_u8(target, mem_val);
         return 0;
     }
 }
@@ -0,0 +1,366 @@
+use crate::branch_trace::{ControlFlowChange, ControlFlowKind};
+use crate::call_stack::CallKind;
+use crate::cpu6502::{StatusFlag, CPU};
+use crate::event_viewer::EventKind;
+use crate::savestate::{StateReader, StateWriter};
+
+// NMI and IRQ handling. The CPU samples its interrupt lines during each cycle, but only acts on
+// the sample taken before the last cycle of an instruction ("polling"): an interrupt asserted
+// during the last cycle is only serviced after the next instruction.
+// Hardware quirks exercised by the cpu_interrupts_v2 test ROMs:
+// - CLI, SEI and PLP change the I flag after polling, so the poll sees the old value:
+//   an IRQ is delayed by one instruction after CLI, and can still happen right after SEI.
+//   RTI changes the flag before polling, so it takes effect immediately.
+// - A taken branch that does not cross a page polls during its second cycle, not during its third
+//   (last) one: an interrupt asserted during that second cycle waits for one more instruction.
+// - The vector is fetched at the end of the interrupt sequence (BRK, IRQ or NMI). If an NMI edge
+//   is detected before that, the sequence is hijacked and jumps to the NMI vector instead.
+//   For BRK, the B flag is still set in the pushed status, which is how handlers can tell.
+
+pub const NMI_VECTOR: u16 = 0xFFFA;
+pub const IRQ_VECTOR: u16 = 0xFFFE;
+
+// Cycles of the interrupt sequence (BRK, IRQ and NMI)
+const INTERRUPT_CYCLES: u64 = 7;
+// Cycle of the sequence, counted from 0, after which an NMI edge can no longer hijack the vector fetch
+const HIJACK_LAST_CYCLE: u64 = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Interrupt {
+    Nmi,
+    Irq,
+}
+
+// Devices sharing the IRQ line (open collector: the line is asserted while any of them asserts it).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IrqSource {
+    FrameCounter,
+    Dmc,
+    Mapper,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct InterruptLines {
+    // CPU cycle during which the NMI edge was detected, until it is serviced
+    nmi_edge_at: Option<u64>,
+    // One bit per IrqSource asserting the IRQ line
+    irq_sources: u8,
+    // CPU cycle since which the IRQ line is asserted
+    irq_asserted_at: u64,
+}
+
+impl InterruptLines {
+    pub fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.nmi_edge_at.is_some());
+        writer.write_u64(self.nmi_edge_at.unwrap_or(0));
+        writer.write_u8(self.irq_sources);
+        writer.write_u64(self.irq_asserted_at);
+    }
+
+    pub fn load_state(&mut self, reader: &mut StateReader) -> Result<(), String> {
+        let nmi_edge = reader.read_bool()?;
+        let nmi_edge_at = reader.read_u64()?;
+        self.nmi_edge_at = nmi_edge.then_some(nmi_edge_at);
+        self.irq_sources = reader.read_u8()?;
+        self.irq_asserted_at = reader.read_u64()?;
+        Ok(())
+    }
+}
+
+impl CPU {
+    // Lines saved in the savestates. An NMI the PPU raised while it was caught up, but that the CPU
+    // has not taken yet, is saved as detected: the restored PPU does not raise it again.
+    pub fn saved_interrupts(&self) -> InterruptLines {
+        let mut interrupts = self.interrupts.clone();
+        if interrupts.nmi_edge_at.is_none() && self.bus.has_ppu() {
+            let edge = self.bus.ppu().pending_nmi();
+            interrupts.nmi_edge_at = edge.map(|edge| self.nmi_edge_cycle(edge));
+        }
+        interrupts
+    }
+
+    // Falling edge on the NMI line (the PPU entering vblank with NMI enabled), during the current cycle.
+    pub fn request_nmi(&mut self) {
+        self.request_nmi_at(self.cycles);
+    }
+
+    // Same as request_nmi, for a component running ahead of the CPU within the current instruction.
+    pub fn request_nmi_at(&mut self, cycle: u64) {
+        if self.interrupts.nmi_edge_at.is_none() {
+            self.interrupts.nmi_edge_at = Some(cycle);
+        }
+    }
+
+    pub fn set_irq(&mut self, source: IrqSource, asserted: bool) {
+        self.set_irq_at(source, asserted, self.cycles);
+    }
+
+    pub fn set_irq_at(&mut self, source: IrqSource, asserted: bool, cycle: u64) {
+        let was_asserted = self.is_irq_asserted();
+        if asserted {
+            self.interrupts.irq_sources |= 1 << source as u8;
+        } else {
+            self.interrupts.irq_sources &= !(1 << source as u8);
+        }
+        if !was_asserted && asserted {
+            self.interrupts.irq_asserted_at = cycle;
+        }
+    }
+
+    pub fn is_irq_asserted(&self) -> bool {
+        self.interrupts.irq_sources != 0
+    }
+
+    pub fn is_irq_source_asserted(&self, source: IrqSource) -> bool {
+        self.interrupts.irq_sources & (1 << source as u8) != 0
+    }
+
+    // Follows the frame and DMC IRQ flags of the APU status register: reading 0x4015 acknowledges the
+    // frame IRQ, writing it acknowledges the DMC one. Called after each instruction.
+    pub fn sync_apu_irqs(&mut self) {
+        let frame_irq = self.bus.apu_status().frame_irq();
+        let dmc_irq = self.bus.apu_status().dmc_irq();
+        for (source, asserted) in [(IrqSource::FrameCounter, frame_irq), (IrqSource::Dmc, dmc_irq)] {
+            if self.is_irq_source_asserted(source) != asserted {
+                self.set_irq(source, asserted);
+            }
+        }
+    }
+
+    // Interrupt to service after an instruction whose polling happened during `poll_cycle`,
+    // with the I flag as it was at that time. Signals asserted during the poll cycle are too late.
+    pub fn poll_interrupts(&self, poll_cycle: u64, interrupt_disable: bool) -> Option<Interrupt> {
+        if self.interrupts.nmi_edge_at.is_some_and(|cycle| cycle < poll_cycle) {
+            return Some(Interrupt::Nmi);
+        }
+        if !interrupt_disable && self.is_irq_asserted() && self.interrupts.irq_asserted_at < poll_cycle {
+            return Some(Interrupt::Irq);
+        }
+        None
+    }
+
+    // Runs the 7-cycle interrupt sequence: pushes PC and the status (B clear), sets I and jumps to the vector.
+    pub fn service_interrupt(&mut self, interrupt: Interrupt) {
+        let start = self.cycles;
+        self.push_u16(self.program_counter);
+        let status = (self.status_register & !(1 << StatusFlag::BreakCommand as u8)) | (1 << StatusFlag::Unused as u8);
+        self.push_u8(status);
+        self.set_status_flag(StatusFlag::InterruptDisable, true);
+        let vector = match interrupt {
+            Interrupt::Nmi => self.take_nmi_vector(),
+            Interrupt::Irq => self.interrupt_vector(start),
+        };
+        let interrupted = self.program_counter;
+        self.program_counter = self.read_u16(vector);
+        let kind = if vector == NMI_VECTOR { CallKind::Nmi } else { CallKind::Irq };
+        self.track_interrupt(kind, interrupted, start);
+        let flow = if kind == CallKind::Nmi { ControlFlowKind::Nmi } else { ControlFlowKind::Irq };
+        self.branch_trace.record(ControlFlowChange { kind: flow, from: interrupted, to: self.program_counter, cycle: start });
+        self.record_event(if kind == CallKind::Nmi { EventKind::Nmi } else { EventKind::Irq }, interrupted);
+        self.cycles += INTERRUPT_CYCLES;
+    }
+
+    // Vector of an IRQ or BRK sequence started at cycle `start`, hijacked by an NMI detected in time.
+    pub fn interrupt_vector(&mut self, start: u64) -> u16 {
+        match self.interrupts.nmi_edge_at {
+            Some(cycle) if cycle <= start + HIJACK_LAST_CYCLE => self.take_nmi_vector(),
+            _ => IRQ_VECTOR,
+        }
+    }
+
+    fn take_nmi_vector(&mut self) -> u16 {
+        self.interrupts.nmi_edge_at = None;
+        NMI_VECTOR
+    }
+
+    // A reset drops the NMI edge not serviced yet. IRQ sources keep their line asserted.
+    pub fn reset_interrupts(&mut self) {
+        self.interrupts.nmi_edge_at = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bus::Bus;
+    use crate::cpu6502::{new_cpu, StatusFlag, CPU};
+    use crate::interrupts::IrqSource;
+
+    const IRQ_HANDLER: u16 = 0x0300;
+    const NMI_HANDLER: u16 = 0x0400;
+
+    fn cpu_with_program(program: &[u8]) -> CPU {
+        let mut cpu = new_cpu(Bus::new_flat());
+        for (offset, byte) in program.iter().enumerate() {
+            cpu.write_u8(0x0200 + offset as u16, *byte);
+        }
+        cpu.write_u8(0xFFFA, NMI_HANDLER as u8);
+        cpu.write_u8(0xFFFB, (NMI_HANDLER >> 8) as u8);
+        cpu.write_u8(0xFFFE, IRQ_HANDLER as u8);
+        cpu.write_u8(0xFFFF, (IRQ_HANDLER >> 8) as u8);
+        cpu.program_counter = 0x0200;
+        cpu
+    }
+
+    #[test]
+    fn test_irq_is_serviced_after_instruction() {
+        // NOP, NOP
+        let mut cpu = cpu_with_program(&[0xEA, 0xEA]);
+        cpu.status_register = 0x20;
+        cpu.set_irq(IrqSource::Mapper, true);
+        assert_eq!(cpu.step(), 2 + 7);
+        assert_eq!(cpu.program_counter, IRQ_HANDLER);
+        assert!(cpu.get_status_flag(StatusFlag::InterruptDisable));
+        // Return address and status, with B clear
+        assert_eq!(cpu.pop_u8(), 0x20);
+        assert_eq!(cpu.pop_u16(), 0x0201);
+    }
+
+    #[test]
+    fn test_irq_masked_by_interrupt_disable() {
+        let mut cpu = cpu_with_program(&[0xEA, 0xEA]);
+        cpu.status_register = 0x24;
+        cpu.set_irq(IrqSource::FrameCounter, true);
+        cpu.step();
+        assert_eq!(cpu.program_counter, 0x0201);
+
+        // Acknowledging the only source releases the line
+        cpu.set_irq(IrqSource::FrameCounter, false);
+        assert!(!cpu.is_irq_asserted());
+    }
+
+    #[test]
+    fn test_cli_delays_irq_by_one_instruction() {
+        // CLI, NOP
+        let mut cpu = cpu_with_program(&[0x58, 0xEA]);
+        cpu.status_register = 0x24;
+        cpu.set_irq(IrqSource::Mapper, true);
+        cpu.step();
+        assert_eq!(cpu.program_counter, 0x0201);
+        cpu.step();
+        assert_eq!(cpu.program_counter, IRQ_HANDLER);
+    }
+
+    #[test]
+    fn test_irq_right_after_sei() {
+        // SEI, NOP
+        let mut cpu = cpu_with_program(&[0x78, 0xEA]);
+        cpu.status_register = 0x20;
+        cpu.set_irq(IrqSource::Mapper, true);
+        // SEI polls with I still clear
+        cpu.step();
+        assert_eq!(cpu.program_counter, IRQ_HANDLER);
+        // The pushed status has I set by SEI
+        assert_eq!(cpu.pop_u8(), 0x24);
+    }
+
+    #[test]
+    fn test_plp_delays_interrupt_disable() {
+        // PLP (pulls I clear), NOP
+        let mut cpu = cpu_with_program(&[0x28, 0xEA]);
+        cpu.status_register = 0x24;
+        cpu.push_u8(0x20);
+        cpu.set_irq(IrqSource::Mapper, true);
+        cpu.step();
+        assert_eq!(cpu.program_counter, 0x0201);
+        cpu.step();
+        assert_eq!(cpu.program_counter, IRQ_HANDLER);
+    }
+
+    #[test]
+    fn test_rti_clears_interrupt_disable_immediately() {
+        // RTI to 0x0210 with I clear
+        let mut cpu = cpu_with_program(&[0x40]);
+        cpu.status_register = 0x24;
+        cpu.push_u16(0x0210);
+        cpu.push_u8(0x20);
+        cpu.set_irq(IrqSource::Mapper, true);
+        cpu.step();
+        assert_eq!(cpu.program_counter, IRQ_HANDLER);
+    }
+
+    #[test]
+    fn test_late_interrupt_waits_for_next_instruction() {
+        // NOP, NOP
+        let mut cpu = cpu_with_program(&[0xEA, 0xEA]);
+        // Asserted during the last cycle of the first NOP
+        cpu.request_nmi_at(cpu.cycles + 1);
+        cpu.step();
+        assert_eq!(cpu.program_counter, 0x0201);
+        cpu.step();
+        assert_eq!(cpu.program_counter, NMI_HANDLER);
+    }
+
+    #[test]
+    fn test_taken_branch_delays_interrupt() {
+        // BNE +0 (taken, same page), NOP
+        let mut cpu = cpu_with_program(&[0xD0, 0x00, 0xEA]);
+        cpu.status_register = 0x20;
+        // Asserted during the second cycle of the branch
+        cpu.request_nmi_at(cpu.cycles + 1);
+        assert_eq!(cpu.step(), 3);
+        assert_eq!(cpu.program_counter, 0x0202);
+        cpu.step();
+        assert_eq!(cpu.program_counter, NMI_HANDLER);
+
+        // Not taken: the second cycle is the last one, and the poll happens during the first one
+        let mut cpu = cpu_with_program(&[0xF0, 0x00, 0xEA]);
+        cpu.status_register = 0x20;
+        cpu.request_nmi_at(cpu.cycles);
+        cpu.step();
+        assert_eq!(cpu.program_counter, NMI_HANDLER);
+
+        // Taken across a page: 4 cycles, polled during the third one
+        let mut cpu = cpu_with_program(&[0xD0, 0x80]);
+        cpu.status_register = 0x20;
+        cpu.request_nmi_at(cpu.cycles + 2);
+        assert_eq!(cpu.step(), 4 + 7);
+        assert_eq!(cpu.program_counter, NMI_HANDLER);
+    }
+
+    #[test]
+    fn test_nmi_ignores_interrupt_disable() {
+        let mut cpu = cpu_with_program(&[0xEA]);
+        cpu.status_register = 0x24;
+        cpu.request_nmi();
+        cpu.step();
+        assert_eq!(cpu.program_counter, NMI_HANDLER);
+        // The edge is consumed
+        assert_eq!(cpu.poll_interrupts(u64::MAX, false), None);
+    }
+
+    #[test]
+    fn test_nmi_hijacks_brk() {
+        // BRK
+        let mut cpu = cpu_with_program(&[0x00, 0x00]);
+        cpu.request_nmi_at(cpu.cycles + 3);
+        cpu.step();
+        assert_eq!(cpu.program_counter, NMI_HANDLER);
+        // The B flag tells the NMI handler that a BRK was hijacked
+        assert_eq!(cpu.pop_u8() & 0x10, 0x10);
+        // The NMI is not serviced a second time
+        cpu.write_u8(NMI_HANDLER, 0xEA);
+        cpu.step();
+        assert_eq!(cpu.program_counter, NMI_HANDLER + 1);
+
+        // Too late to hijack: BRK goes to the IRQ vector, then the NMI is serviced
+        let mut cpu = cpu_with_program(&[0x00, 0x00]);
+        cpu.request_nmi_at(cpu.cycles + 5);
+        cpu.step();
+        assert_eq!(cpu.program_counter, IRQ_HANDLER);
+        cpu.write_u8(IRQ_HANDLER, 0xEA);
+        cpu.step();
+        assert_eq!(cpu.program_counter, NMI_HANDLER);
+    }
+
+    #[test]
+    fn test_nmi_hijacks_irq() {
+        let mut cpu = cpu_with_program(&[0xEA]);
+        cpu.status_register = 0x20;
+        cpu.set_irq(IrqSource::Mapper, true);
+        // The NMI edge arrives during the last cycle of the NOP, too late for polling but in time for the vector
+        cpu.request_nmi_at(cpu.cycles + 1);
+        cpu.step();
+        assert_eq!(cpu.program_counter, NMI_HANDLER);
+        assert_eq!(cpu.poll_interrupts(u64::MAX, true), None);
+    }
+}
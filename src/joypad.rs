@@ -0,0 +1,265 @@
+use std::cell::Cell;
+use crate::input_macro::InputMacros;
+
+// Buttons of a standard controller, in the order they are shifted out (bit 0 is read first).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum JoypadButton {
+    A = 0,
+    B = 1,
+    Select = 2,
+    Start = 3,
+    Up = 4,
+    Down = 5,
+    Left = 6,
+    Right = 7,
+}
+
+impl JoypadButton {
+    pub const ALL: [JoypadButton; 8] = [
+        JoypadButton::A, JoypadButton::B, JoypadButton::Select, JoypadButton::Start,
+        JoypadButton::Up, JoypadButton::Down, JoypadButton::Left, JoypadButton::Right,
+    ];
+
+    // Name used in the config file
+    pub fn name(self) -> &'static str {
+        match self {
+            JoypadButton::A => "a",
+            JoypadButton::B => "b",
+            JoypadButton::Select => "select",
+            JoypadButton::Start => "start",
+            JoypadButton::Up => "up",
+            JoypadButton::Down => "down",
+            JoypadButton::Left => "left",
+            JoypadButton::Right => "right",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Result<Self, String> {
+        Self::ALL.into_iter().find(|button| button.name() == name).ok_or_else(|| format!("Unknown joypad button `{}`", name))
+    }
+}
+
+// Signatures returned by the Four Score after the two controllers of a port (reads 17 to 24).
+// Port 1 returns 0,0,0,1,0,0,0,0 and port 2 returns 0,0,1,0,0,0,0,0.
+const FOUR_SCORE_SIGNATURES: [u8; 2] = [0b0000_1000, 0b0000_0100];
+
+// Controllers plugged into the two ports (0x4016 and 0x4017).
+// Writing 1 then 0 to 0x4016 (strobe) latches the buttons, then each read of a port returns the next bit.
+// With the Four Score adapter, each port is shared by two controllers (players 1 and 3 on the
+// first port, players 2 and 4 on the second one) followed by a signature identifying the adapter.
+#[derive(Debug, Default)]
+pub struct Joypads {
+    // Pressed buttons of each player, bit N being `JoypadButton` N
+    buttons: [u8; 4],
+    four_score: bool,
+    strobe: bool,
+    // Number of bits already read from each port since the last strobe.
+    // Reads are done through `&self` by the bus, hence the Cell.
+    read_counts: [Cell<u8>; 2],
+    // Macros playing are combined with the buttons above (see input_macro.rs)
+    macros: InputMacros,
+}
+
+impl Joypads {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set_four_score(&mut self, enabled: bool) {
+        self.four_score = enabled;
+    }
+
+    pub fn is_four_score(&self) -> bool {
+        self.four_score
+    }
+
+    // `player` is 0 to 3. Players 3 and 4 are only visible to the game with the Four Score.
+    pub fn set_button_pressed(&mut self, player: usize, button: JoypadButton, pressed: bool) {
+        if pressed {
+            self.buttons[player] |= 1 << button as u8;
+        } else {
+            self.buttons[player] &= !(1 << button as u8);
+        }
+    }
+
+    pub fn buttons(&self, player: usize) -> u8 {
+        self.buttons[player]
+    }
+
+    pub fn set_buttons(&mut self, player: usize, buttons: u8) {
+        self.buttons[player] = buttons;
+    }
+
+    // Buttons seen by the game: the ones held by the player and the ones of the macro playing.
+    pub fn effective_buttons(&self, player: usize) -> u8 {
+        self.buttons[player] | self.macros.buttons(player)
+    }
+
+    pub fn macros(&self) -> &InputMacros {
+        &self.macros
+    }
+
+    pub fn macros_mut(&mut self) -> &mut InputMacros {
+        &mut self.macros
+    }
+
+    // Advances the macros by one frame.
+    pub fn end_frame(&mut self) {
+        self.macros.end_frame(&self.buttons);
+    }
+
+    // Strobe and read positions of the ports, saved in savestates: a state can be saved in the
+    // middle of a read sequence.
+    pub fn shift_state(&self) -> [u8; 3] {
+        [self.strobe as u8, self.read_counts[0].get(), self.read_counts[1].get()]
+    }
+
+    pub fn set_shift_state(&mut self, state: [u8; 3]) {
+        self.strobe = state[0] != 0;
+        self.read_counts[0].set(state[1]);
+        self.read_counts[1].set(state[2]);
+    }
+
+    // Write to 0x4016. Only bit 0 (strobe) is used by controllers.
+    pub fn write(&mut self, data: u8) {
+        self.strobe = data & 1 != 0;
+        if self.strobe {
+            for count in &self.read_counts {
+                count.set(0);
+            }
+        }
+    }
+
+    // Read of 0x4016 (port 0) or 0x4017 (port 1). Only bit 0 carries the controller data.
+    pub fn read(&self, port: usize) -> u8 {
+        let bit = self.peek(port);
+        if !self.strobe {
+            let index = self.read_counts[port].get();
+            self.read_counts[port].set(index.saturating_add(1));
+        }
+        bit
+    }
+
+    // Bit the next read of `port` returns, without shifting it out (for debugger views).
+    pub fn peek(&self, port: usize) -> u8 {
+        // While strobe is high, the shift register keeps reloading, so the A button is returned
+        if self.strobe {
+            return self.effective_buttons(port) & 1;
+        }
+
+        let index = self.read_counts[port].get();
+        let (data, bit) = match (self.four_score, index) {
+            (_, 0..=7) => (self.effective_buttons(port), index),
+            (true, 8..=15) => (self.effective_buttons(port + 2), index - 8),
+            (true, 16..=23) => (FOUR_SCORE_SIGNATURES[port], index - 16),
+            // Official controllers return 1 once all their bits have been read
+            _ => return 1,
+        };
+        (data >> bit) & 1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bus::Bus;
+    use crate::input_macro::InputMacro;
+    use crate::joypad::{JoypadButton, Joypads};
+    use crate::rom::Rom;
+
+    fn read_bits(joypads: &Joypads, port: usize, count: usize) -> Vec<u8> {
+        (0..count).map(|_| joypads.read(port)).collect()
+    }
+
+    #[test]
+    fn test_standard_controller_read_sequence() {
+        let mut joypads = Joypads::new();
+        joypads.set_button_pressed(0, JoypadButton::A, true);
+        joypads.set_button_pressed(0, JoypadButton::Start, true);
+        joypads.set_button_pressed(0, JoypadButton::Right, true);
+
+        // While strobe is high, the A button is returned on every read
+        joypads.write(1);
+        assert_eq!(read_bits(&joypads, 0, 3), vec![1, 1, 1]);
+
+        joypads.write(0);
+        assert_eq!(read_bits(&joypads, 0, 10), vec![1, 0, 0, 1, 0, 0, 0, 1, 1, 1]);
+
+        // A new strobe restarts the sequence
+        joypads.set_button_pressed(0, JoypadButton::A, false);
+        joypads.write(1);
+        joypads.write(0);
+        assert_eq!(joypads.read(0), 0);
+    }
+
+    #[test]
+    fn test_four_score_read_sequence() {
+        let mut joypads = Joypads::new();
+        joypads.set_four_score(true);
+        joypads.set_button_pressed(0, JoypadButton::A, true);
+        joypads.set_button_pressed(1, JoypadButton::B, true);
+        joypads.set_button_pressed(2, JoypadButton::Select, true);
+        joypads.set_button_pressed(3, JoypadButton::Up, true);
+
+        joypads.write(1);
+        joypads.write(0);
+
+        let port1 = read_bits(&joypads, 0, 25);
+        assert_eq!(port1[..8], [1, 0, 0, 0, 0, 0, 0, 0]); // Player 1
+        assert_eq!(port1[8..16], [0, 0, 1, 0, 0, 0, 0, 0]); // Player 3
+        assert_eq!(port1[16..24], [0, 0, 0, 1, 0, 0, 0, 0]); // Signature
+        assert_eq!(port1[24], 1);
+
+        let port2 = read_bits(&joypads, 1, 24);
+        assert_eq!(port2[..8], [0, 1, 0, 0, 0, 0, 0, 0]); // Player 2
+        assert_eq!(port2[8..16], [0, 0, 0, 0, 1, 0, 0, 0]); // Player 4
+        assert_eq!(port2[16..24], [0, 0, 1, 0, 0, 0, 0, 0]); // Signature
+    }
+
+    #[test]
+    fn test_players_3_and_4_hidden_without_four_score() {
+        let mut joypads = Joypads::new();
+        joypads.set_button_pressed(2, JoypadButton::A, true);
+        joypads.write(1);
+        joypads.write(0);
+        assert_eq!(read_bits(&joypads, 0, 16), [vec![0; 8], vec![1; 8]].concat());
+    }
+
+    #[test]
+    fn test_bus_controller_ports() {
+        let mut bus = Bus::new(Rom::test_rom());
+        bus.joypads_mut().set_button_pressed(1, JoypadButton::B, true);
+
+        bus.write_u8(0x4016, 1);
+        bus.write_u8(0x4016, 0);
+        // The upper bits are open bus, the high byte of the address
+        assert_eq!(bus.read_u8(0x4017), 0x40);
+        assert_eq!(bus.read_u8(0x4017), 0x41);
+        // Peeking returns the next bit (B released here) without advancing the shift register
+        assert_eq!(bus.peek_u8(0x4017), 0x40);
+        assert_eq!(bus.peek_u8(0x4017), 0x40);
+        assert_eq!(bus.read_u8(0x4017), 0x40);
+        bus.joypads_mut().set_button_pressed(1, JoypadButton::Up, true);
+        bus.read_u8(0x4017);
+        assert_eq!(bus.peek_u8(0x4017), 0x41);
+        assert_eq!(bus.read_u8(0x4017), 0x41);
+        assert_eq!(bus.read_u8(0x4016), 0x40);
+    }
+
+    #[test]
+    fn test_macro_composes_with_live_input() {
+        let mut joypads = Joypads::new();
+        joypads.macros_mut().set_macro(0, InputMacro { frames: vec![0b0000_0010] }).unwrap();
+        joypads.macros_mut().play(0, 0).unwrap();
+        joypads.set_button_pressed(0, JoypadButton::A, true);
+
+        joypads.write(1);
+        joypads.write(0);
+        assert_eq!(read_bits(&joypads, 0, 2), vec![1, 1]);
+
+        // The macro lasts one frame
+        joypads.end_frame();
+        joypads.write(1);
+        joypads.write(0);
+        assert_eq!(read_bits(&joypads, 0, 2), vec![1, 0]);
+    }
+}
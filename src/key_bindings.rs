@@ -0,0 +1,203 @@
+use std::collections::BTreeMap;
+use crate::input_provider::KeyboardInput;
+use crate::joypad::JoypadButton;
+
+// Keyboard bindings of the frontend: joypad buttons of each player and emulator hotkeys, stored in
+// the [key_bindings] and [hotkeys] sections of the config file.
+// Keys are identified by name (e.g. "X", "Return", "F5", as SDL names scancodes), so the bindings
+// do not depend on a frontend; the frontend translates names to its own scancodes.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Hotkey {
+    SaveState,
+    LoadState,
+    Rewind,
+    FastForward,
+    Screenshot,
+    // Sends the keyboard to the Family BASIC keyboard instead of the bindings, until pressed again
+    KeyboardPassthrough,
+    // Swaps the ports of the gamepads of players 1 and 2, remembered in their profiles
+    SwapGamepads,
+}
+
+impl Hotkey {
+    pub const ALL: [Hotkey; 7] = [
+        Hotkey::SaveState, Hotkey::LoadState, Hotkey::Rewind, Hotkey::FastForward, Hotkey::Screenshot, Hotkey::KeyboardPassthrough,
+        Hotkey::SwapGamepads,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Hotkey::SaveState => "save_state",
+            Hotkey::LoadState => "load_state",
+            Hotkey::Rewind => "rewind",
+            Hotkey::FastForward => "fast_forward",
+            Hotkey::Screenshot => "screenshot",
+            Hotkey::KeyboardPassthrough => "keyboard_passthrough",
+            Hotkey::SwapGamepads => "swap_gamepads",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Result<Self, String> {
+        Self::ALL.into_iter().find(|hotkey| hotkey.name() == name).ok_or_else(|| format!("Unknown hotkey `{}`", name))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum KeyAction {
+    // `player` is 0 to 3
+    Joypad { player: usize, button: JoypadButton },
+    Hotkey(Hotkey),
+}
+
+impl KeyAction {
+    // Name of a joypad action in the [key_bindings] section: "a" for player 1, "player2.a" for the others
+    pub fn joypad_name(player: usize, button: JoypadButton) -> String {
+        match player {
+            0 => button.name().to_string(),
+            _ => format!("player{}.{}", player + 1, button.name()),
+        }
+    }
+
+    pub fn joypad_from_name(name: &str) -> Result<Self, String> {
+        let (player, button) = match name.strip_prefix("player").and_then(|name| name.split_once('.')) {
+            Some((player, button)) => {
+                let player = player.parse::<usize>().ok().filter(|player| (1..=4).contains(player))
+                    .ok_or_else(|| format!("Invalid player in `{}` (1 to 4)", name))?;
+                (player - 1, button)
+            }
+            None => (0, name),
+        };
+        Ok(KeyAction::Joypad { player, button: JoypadButton::from_name(button)? })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyBindings {
+    // Action to key name. A key triggers at most one action.
+    bindings: BTreeMap<KeyAction, String>,
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        let joypad = [
+            (JoypadButton::A, "X"), (JoypadButton::B, "Z"), (JoypadButton::Select, "Right Shift"), (JoypadButton::Start, "Return"),
+            (JoypadButton::Up, "Up"), (JoypadButton::Down, "Down"), (JoypadButton::Left, "Left"), (JoypadButton::Right, "Right"),
+        ];
+        let hotkeys = [
+            (Hotkey::SaveState, "F5"), (Hotkey::LoadState, "F7"), (Hotkey::Rewind, "Backspace"),
+            (Hotkey::FastForward, "Tab"), (Hotkey::Screenshot, "F12"), (Hotkey::KeyboardPassthrough, "ScrollLock"),
+            (Hotkey::SwapGamepads, "F9"),
+        ];
+        let bindings = joypad.iter()
+            .map(|(button, key)| (KeyAction::Joypad { player: 0, button: *button }, key.to_string()))
+            .chain(hotkeys.iter().map(|(hotkey, key)| (KeyAction::Hotkey(*hotkey), key.to_string())))
+            .collect();
+        Self { bindings }
+    }
+}
+
+impl KeyBindings {
+    // No key bound at all
+    pub fn empty() -> Self {
+        Self { bindings: BTreeMap::new() }
+    }
+
+    // Binds `key` to `action`, replacing the previous key of the action. If the key was used by
+    // another action, that action is unbound and returned, so a remapping menu can report it.
+    pub fn bind(&mut self, action: KeyAction, key: &str) -> Option<KeyAction> {
+        let previous = self.action(key).filter(|previous| *previous != action);
+        if let Some(previous) = previous {
+            self.bindings.remove(&previous);
+        }
+        self.bindings.insert(action, key.to_string());
+        previous
+    }
+
+    pub fn unbind(&mut self, action: KeyAction) {
+        self.bindings.remove(&action);
+    }
+
+    pub fn key(&self, action: KeyAction) -> Option<&str> {
+        self.bindings.get(&action).map(String::as_str)
+    }
+
+    pub fn action(&self, key: &str) -> Option<KeyAction> {
+        self.bindings.iter().find(|(_, bound)| bound.as_str() == key).map(|(action, _)| *action)
+    }
+
+    pub fn hotkey(&self, key: &str) -> Option<Hotkey> {
+        match self.action(key)? {
+            KeyAction::Hotkey(hotkey) => Some(hotkey),
+            KeyAction::Joypad { .. } => None,
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (KeyAction, &str)> {
+        self.bindings.iter().map(|(action, key)| (*action, key.as_str()))
+    }
+
+    // Keyboard input provider for the joypad bindings. `scancode` translates a key name to the
+    // scancode of the frontend; unknown key names are an error.
+    pub fn keyboard_input(&self, scancode: impl Fn(&str) -> Option<u32>) -> Result<KeyboardInput, String> {
+        let mut keyboard = KeyboardInput::new();
+        for (action, key) in self.iter() {
+            if let KeyAction::Joypad { player, button } = action {
+                let code = scancode(key).ok_or_else(|| format!("Unknown key `{}`", key))?;
+                keyboard.bind(code, player, button);
+            }
+        }
+        Ok(keyboard)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::input_provider::InputProvider;
+    use crate::joypad::JoypadButton;
+    use crate::key_bindings::{Hotkey, KeyAction, KeyBindings};
+
+    #[test]
+    fn test_action_names() {
+        assert_eq!(KeyAction::joypad_name(0, JoypadButton::Start), "start");
+        assert_eq!(KeyAction::joypad_name(1, JoypadButton::A), "player2.a");
+        assert_eq!(KeyAction::joypad_from_name("player2.a"), Ok(KeyAction::Joypad { player: 1, button: JoypadButton::A }));
+        assert_eq!(KeyAction::joypad_from_name("left"), Ok(KeyAction::Joypad { player: 0, button: JoypadButton::Left }));
+        assert!(KeyAction::joypad_from_name("player5.a").is_err());
+        assert!(KeyAction::joypad_from_name("turbo_a").is_err());
+        assert_eq!(Hotkey::from_name("fast_forward"), Ok(Hotkey::FastForward));
+    }
+
+    #[test]
+    fn test_rebinding() {
+        let mut bindings = KeyBindings::default();
+        assert_eq!(bindings.hotkey("F5"), Some(Hotkey::SaveState));
+        assert_eq!(bindings.hotkey("X"), None);
+
+        // F5 moves from the savestate hotkey to player 2's A button
+        let player2_a = KeyAction::Joypad { player: 1, button: JoypadButton::A };
+        assert_eq!(bindings.bind(player2_a, "F5"), Some(KeyAction::Hotkey(Hotkey::SaveState)));
+        assert_eq!(bindings.action("F5"), Some(player2_a));
+        assert_eq!(bindings.key(KeyAction::Hotkey(Hotkey::SaveState)), None);
+
+        // Rebinding to the same key does not unbind anything
+        assert_eq!(bindings.bind(player2_a, "F5"), None);
+        bindings.bind(player2_a, "K");
+        assert_eq!(bindings.action("F5"), None);
+    }
+
+    #[test]
+    fn test_keyboard_input() {
+        let scancode = |key: &str| match key {
+            "X" => Some(27),
+            "Z" => Some(29),
+            _ => Some(1000 + key.len() as u32),
+        };
+        let mut keyboard = KeyBindings::default().keyboard_input(scancode).unwrap();
+        keyboard.key_down(27);
+        keyboard.key_down(29);
+        assert_eq!(keyboard.poll(), [0b0000_0011, 0, 0, 0]);
+
+        assert!(KeyBindings::default().keyboard_input(|key| (key != "Return").then_some(1)).is_err());
+    }
+}
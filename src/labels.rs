@@ -0,0 +1,234 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::Path;
+
+// A label covering `size` bytes starting at its address, e.g. a 16 bytes buffer in RAM.
+#[derive(Debug, Clone, PartialEq)]
+struct Label {
+    name: String,
+    size: u16,
+}
+
+// Symbols loaded from the label files of other emulators, so the disassembler and the trace can
+// print `JSR reset_handler` instead of `JSR $C5F2`.
+// Labels are indexed by CPU address. Only NROM cartridges exist for now, so PRG ROM offsets
+// are converted to CPU addresses without bank switching.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Labels {
+    labels: BTreeMap<u16, Label>,
+}
+
+impl Labels {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Loads a FCEUX (.nl) or Mesen (.mlb) label file, depending on its extension.
+    // `prg_rom_size` is needed to map the PRG ROM offsets of Mesen files to CPU addresses.
+    pub fn load_file(path: &str, prg_rom_size: usize) -> Result<Self, String> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|error| format!("Failed to read label file {}: {}", path, error))?;
+        let result = match Path::new(path).extension().and_then(|extension| extension.to_str()) {
+            Some("nl") => Self::from_fceux_nl(&content),
+            Some("mlb") => Self::from_mesen_mlb(&content, prg_rom_size),
+            _ => Err("Unknown label file format, expected a .nl or .mlb file".to_string()),
+        };
+        result.map_err(|error| format!("Invalid label file {}: {}", path, error))
+    }
+
+    // FCEUX name list, one label per line: `$C000#reset_handler#Comment`.
+    // Arrays are written `$0200/100#oam#`, the size being in hexadecimal.
+    // Lines without a name (comment only) are ignored.
+    pub fn from_fceux_nl(content: &str) -> Result<Self, String> {
+        let mut labels = Self::new();
+        for (index, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let mut fields = line.splitn(3, '#');
+            let address = fields.next().unwrap_or_default();
+            let name = fields.next().unwrap_or_default().trim();
+            let (address, size) = match address.split_once('/') {
+                Some((address, size)) => (address, Some(size)),
+                None => (address, None),
+            };
+
+            let address = address.strip_prefix('$')
+                .and_then(|address| u16::from_str_radix(address, 16).ok())
+                .ok_or_else(|| format!("Line {}: Invalid address `{}`", index + 1, address))?;
+            let size = match size {
+                Some(size) => u16::from_str_radix(size, 16)
+                    .map_err(|_| format!("Line {}: Invalid array size `{}`", index + 1, size))?,
+                None => 1,
+            };
+            if !name.is_empty() {
+                labels.insert(address, name, size);
+            }
+        }
+        Ok(labels)
+    }
+
+    // Mesen label file, one label per line: `<memory type>:<offset>[-<end offset>]:<name>[:comment]`.
+    // Both the Mesen 1 memory types (P, R, S, W, G) and the Mesen 2 ones (NesPrgRom, ...) are supported.
+    pub fn from_mesen_mlb(content: &str, prg_rom_size: usize) -> Result<Self, String> {
+        let mut labels = Self::new();
+        for (index, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let mut fields = line.splitn(4, ':');
+            let (Some(memory_type), Some(range), Some(name)) = (fields.next(), fields.next(), fields.next()) else {
+                return Err(format!("Line {}: Expected `type:address:name`", index + 1));
+            };
+            let parse_offset = |offset: &str| {
+                u32::from_str_radix(offset, 16).map_err(|_| format!("Line {}: Invalid address `{}`", index + 1, offset))
+            };
+            let (start, end) = match range.split_once('-') {
+                Some((start, end)) => (parse_offset(start)?, parse_offset(end)?),
+                None => (parse_offset(range)?, parse_offset(range)?),
+            };
+            if end < start {
+                return Err(format!("Line {}: Invalid address range `{}`", index + 1, range));
+            }
+            let size = (end - start + 1).min(0x10000) as u16;
+
+            let addresses: Vec<u32> = match memory_type {
+                // Offset in PRG ROM. A 16KB PRG ROM is mirrored at 0x8000 and 0xC000.
+                "P" | "NesPrgRom" if prg_rom_size > 0 => {
+                    let offset = start % prg_rom_size as u32;
+                    (0x8000..0x10000).step_by(prg_rom_size)
+                        .map(|base| base + offset)
+                        .filter(|address| *address < 0x10000)
+                        .collect()
+                }
+                "P" | "NesPrgRom" => Vec::new(),
+                "R" | "NesInternalRam" => vec![start],
+                "S" | "W" | "NesSaveRam" | "NesWorkRam" => vec![0x6000 + start],
+                "G" | "NesMemory" => vec![start],
+                // Labels of memories the CPU cannot see (CHR ROM, palette, ...) are ignored
+                _ => Vec::new(),
+            };
+            for address in addresses {
+                let address = u16::try_from(address)
+                    .map_err(|_| format!("Line {}: Address `{}` is out of range", index + 1, range))?;
+                if !name.trim().is_empty() {
+                    labels.insert(address, name.trim(), size);
+                }
+            }
+        }
+        Ok(labels)
+    }
+
+    pub fn insert(&mut self, address: u16, name: &str, size: u16) {
+        self.labels.insert(address, Label { name: name.to_string(), size: size.max(1) });
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.labels.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.labels.len()
+    }
+
+    // Name of the label covering `address`, with the offset from the start of the label for arrays
+    // and words, e.g. "oam+4".
+    pub fn lookup(&self, address: u16) -> Option<String> {
+        match self.find(address)? {
+            (name, 0) => Some(name.to_string()),
+            (name, offset) => Some(format!("{}+{}", name, offset)),
+        }
+    }
+
+    // Address of the label called `name` (the lowest one for the labels of a mirrored PRG ROM),
+    // used by the assembler.
+    pub fn address_of(&self, name: &str) -> Option<u16> {
+        self.labels.iter().find(|(_, label)| label.name == name).map(|(&address, _)| address)
+    }
+
+    // Label covering `address` and the offset of the address from its start.
+    fn find(&self, address: u16) -> Option<(&str, u16)> {
+        let (start, label) = self.labels.range(..=address).next_back()?;
+        let offset = address - start;
+        (offset < label.size).then_some((label.name.as_str(), offset))
+    }
+
+    // The label covering `address`, or the address in hexadecimal ("$C5F2" or "$10" for the zero page).
+    pub fn format_address(&self, address: u16, zero_page: bool) -> String {
+        let mut text = String::new();
+        self.write_address(&mut text, address, zero_page).expect("Writing to a String cannot fail");
+        text
+    }
+
+    // Same as `format_address`, written to `out` without allocating.
+    pub fn write_address(&self, out: &mut impl fmt::Write, address: u16, zero_page: bool) -> fmt::Result {
+        match self.find(address) {
+            Some((name, 0)) => out.write_str(name),
+            Some((name, offset)) => write!(out, "{}+{}", name, offset),
+            None if zero_page => write!(out, "${:02X}", address),
+            None => write!(out, "${:04X}", address),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::labels::Labels;
+
+    #[test]
+    fn test_fceux_nl() {
+        let labels = Labels::from_fceux_nl(
+            "$C000#reset_handler#Entry point\n\
+             $0200/100#oam#Sprite buffer\n\
+             $0010##Comment only\n",
+        ).expect("labels should parse");
+
+        assert_eq!(labels.len(), 2);
+        assert_eq!(labels.lookup(0xC000).as_deref(), Some("reset_handler"));
+        assert_eq!(labels.lookup(0xC001), None);
+        assert_eq!(labels.lookup(0x0204).as_deref(), Some("oam+4"));
+        assert_eq!(labels.lookup(0x0300), None);
+        assert_eq!(labels.lookup(0x0010), None);
+        assert!(Labels::from_fceux_nl("C000#reset#").is_err());
+    }
+
+    #[test]
+    fn test_mesen_mlb() {
+        let labels = Labels::from_mesen_mlb(
+            "P:05F2:reset_handler:Entry point\n\
+             R:0010-0011:pointer\n\
+             S:0000:save_data\n\
+             G:2002:PPUSTATUS\n\
+             NesPrgRom:0000:start\n\
+             C:0000:tiles\n",
+            0x4000,
+        ).expect("labels should parse");
+
+        // A 16KB PRG ROM is mirrored, so its labels appear twice
+        assert_eq!(labels.lookup(0x85F2).as_deref(), Some("reset_handler"));
+        assert_eq!(labels.lookup(0xC5F2).as_deref(), Some("reset_handler"));
+        assert_eq!(labels.lookup(0xC000).as_deref(), Some("start"));
+        assert_eq!(labels.lookup(0x0011).as_deref(), Some("pointer+1"));
+        assert_eq!(labels.lookup(0x6000).as_deref(), Some("save_data"));
+        assert_eq!(labels.lookup(0x2002).as_deref(), Some("PPUSTATUS"));
+        assert_eq!(labels.len(), 7);
+        assert_eq!(labels.address_of("reset_handler"), Some(0x85F2));
+        assert_eq!(labels.address_of("missing"), None);
+
+        assert!(Labels::from_mesen_mlb("P:zz:name", 0x4000).is_err());
+        assert!(Labels::from_mesen_mlb("P:0000", 0x4000).is_err());
+    }
+
+    #[test]
+    fn test_format_address() {
+        let mut labels = Labels::new();
+        labels.insert(0x0010, "counter", 1);
+        assert_eq!(labels.format_address(0x0010, true), "counter");
+        assert_eq!(labels.format_address(0x0020, true), "$20");
+        assert_eq!(labels.format_address(0xC5F2, false), "$C5F2");
+    }
+}
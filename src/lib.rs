@@ -0,0 +1,71 @@
+// Emulation core of the NES: the CPU, the bus and the cartridge, plus the debugging, recording
+// and tooling APIs built on top of them. `main.rs` is the command line front of this library.
+
+pub mod cpu6502;
+pub mod instructions;
+pub mod rom;
+pub mod bus;
+pub mod savestate;
+pub mod savestate_slots;
+pub mod savestate_check;
+pub mod rewind;
+pub mod debugger;
+pub mod frame;
+pub mod disasm;
+pub mod memory_viewer;
+pub mod trace_logger;
+pub mod power_on;
+pub mod palette;
+pub mod audio;
+pub mod apu_status;
+pub mod archive;
+pub mod hash;
+pub mod rom_database;
+pub mod config;
+pub mod joypad;
+pub mod battery_save;
+pub mod labels;
+pub mod ppu;
+pub mod mapper;
+pub mod frame_pacer;
+pub mod frame_stats;
+pub mod recorder;
+pub mod nes;
+pub mod interrupts;
+pub mod dmc_dma;
+pub mod oam_dma;
+pub mod scheduler;
+pub mod access_log;
+pub mod call_stack;
+pub mod branch_trace;
+pub mod profiler;
+pub mod apu_state;
+pub mod event_viewer;
+pub mod input_macro;
+pub mod input_provider;
+pub mod netplay;
+pub mod spectator;
+pub mod frame_corpus;
+pub mod trace_compare;
+pub mod loader;
+pub mod asm;
+pub mod expression;
+pub mod watch;
+pub mod freeze;
+pub mod execution_counts;
+pub mod key_bindings;
+pub mod vs_system;
+pub mod rom_info;
+pub mod family_keyboard;
+#[cfg(feature = "frontend")]
+pub mod frontend;
+#[cfg(feature = "gdb-server")]
+pub mod gdb_server;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+#[cfg(test)]
+mod nestest;
+#[cfg(test)]
+mod blargg_tests;
+#[cfg(all(test, feature = "single-step-tests"))]
+mod single_step_tests;
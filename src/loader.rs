@@ -0,0 +1,220 @@
+use crate::bus::Bus;
+use crate::cpu6502::{new_cpu, CPU};
+use crate::rom::{Mirroring, NesHeader, Rom};
+use crate::rom_info::Region;
+
+// Loads raw 6502 binaries that are not packaged as .nes files, such as the CPU test suites
+// (e.g. Klaus Dormann's 6502_functional_test.bin, assembled to be loaded at $0000 of a flat
+// 64KB memory and started at $0400). A binary can either be written into the memory of a CPU
+// (usually one with a flat memory, see `Loader::flat_cpu`), or wrapped in a synthetic NROM
+// cartridge when it fits in $8000-$FFFF, to run it on the whole machine.
+
+const PRG_ROM_BASE_ADDRESS: u16 = 0x8000;
+const PRG_BANK_SIZE: usize = 0x4000;
+const RESET_VECTOR_ADDRESS: u16 = 0xFFFC;
+// NMI, reset and IRQ vectors, at the end of the last bank
+const VECTORS_SIZE: usize = 6;
+// Unused PRG ROM reads as an erased EPROM
+const PRG_FILL: u8 = 0xFF;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Loader {
+    // Where the first byte of the binary goes
+    pub address: u16,
+    // Written to the reset vector, the load address by default
+    pub entry_point: Option<u16>,
+    // Keeps the reset vector of the binary when it covers $FFFC-$FFFD
+    pub keep_reset_vector: bool,
+}
+
+impl Loader {
+    pub fn new(address: u16) -> Self {
+        Self { address, entry_point: None, keep_reset_vector: false }
+    }
+
+    pub fn with_entry_point(mut self, entry_point: u16) -> Self {
+        self.entry_point = Some(entry_point);
+        self
+    }
+
+    pub fn with_reset_vector_kept(mut self) -> Self {
+        self.keep_reset_vector = true;
+        self
+    }
+
+    fn check_size(&self, binary: &[u8]) -> Result<(), String> {
+        if binary.is_empty() {
+            return Err("Empty binary".to_string());
+        }
+        if self.address as usize + binary.len() > 0x10000 {
+            return Err(format!("Binary of {} bytes does not fit at {:04X}", binary.len(), self.address));
+        }
+        Ok(())
+    }
+
+    fn covers_reset_vector(&self, binary: &[u8]) -> bool {
+        self.address as usize + binary.len() > RESET_VECTOR_ADDRESS as usize + 1
+    }
+
+    fn reset_vector(&self, binary: &[u8]) -> Option<u16> {
+        if self.keep_reset_vector && self.covers_reset_vector(binary) {
+            return None;
+        }
+        Some(self.entry_point.unwrap_or(self.address))
+    }
+
+    // Writes the binary into the memory of the CPU, sets the reset vector and jumps to it.
+    // PRG ROM is patched like in the memory editor, so regions that are not emulated yet fail.
+    pub fn load(&self, cpu: &mut CPU, binary: &[u8]) -> Result<(), String> {
+        self.check_size(binary)?;
+        for (offset, &byte) in binary.iter().enumerate() {
+            cpu.bus.poke_u8(self.address + offset as u16, byte)?;
+        }
+        if let Some(vector) = self.reset_vector(binary) {
+            let [low, high] = vector.to_le_bytes();
+            cpu.bus.poke_u8(RESET_VECTOR_ADDRESS, low)?;
+            cpu.bus.poke_u8(RESET_VECTOR_ADDRESS + 1, high)?;
+        }
+        cpu.program_counter = cpu.peek_u16(RESET_VECTOR_ADDRESS);
+        Ok(())
+    }
+
+    // A CPU with a flat 64KB memory holding the binary, ready to run it
+    pub fn flat_cpu(&self, binary: &[u8]) -> Result<CPU, String> {
+        let mut cpu = new_cpu(Bus::new_flat());
+        self.load(&mut cpu, binary)?;
+        Ok(cpu)
+    }
+
+    // Wraps the binary in an NROM cartridge: 16KB of PRG ROM (mirrored at $8000 and $C000) when
+    // the binary fits in one bank without overlapping the mirror of the vectors, 32KB otherwise.
+    pub fn build_rom(&self, binary: &[u8]) -> Result<Rom, String> {
+        self.check_size(binary)?;
+        if self.address < PRG_ROM_BASE_ADDRESS {
+            return Err(format!("A cartridge can only hold a binary loaded at 8000-FFFF, not at {:04X}", self.address));
+        }
+
+        let start = (self.address - PRG_ROM_BASE_ADDRESS) as usize;
+        let end = start + binary.len();
+        let banks = if start >= PRG_BANK_SIZE || end <= PRG_BANK_SIZE - VECTORS_SIZE { 1 } else { 2 };
+        let mask = banks * PRG_BANK_SIZE - 1;
+
+        let mut prg_rom = vec![PRG_FILL; banks * PRG_BANK_SIZE];
+        for (offset, &byte) in binary.iter().enumerate() {
+            prg_rom[(start + offset) & mask] = byte;
+        }
+        if let Some(vector) = self.reset_vector(binary) {
+            let offset = (RESET_VECTOR_ADDRESS - PRG_ROM_BASE_ADDRESS) as usize & mask;
+            prg_rom[offset..offset + 2].copy_from_slice(&vector.to_le_bytes());
+        }
+
+        let header = NesHeader {
+            magic_numbers: [0x4E, 0x45, 0x53, 0x1A],
+            prg_rom_size: banks as u8,
+            chr_rom_size: 1,
+            flags_6: 0,
+            flags_7: 0,
+            prg_ram_size: 0,
+            flags_9: 0,
+            flags_10: 0,
+            reserved: [0; 5],
+        };
+        let rom = Rom {
+            header,
+            mirroring: Mirroring::Horizontal,
+            mapper: 0,
+            prg_rom,
+            chr_rom: vec![0; 0x2000],
+            prg_ram_size: 0x2000,
+            region: Region::Ntsc,
+        };
+        rom.check_validity()?;
+        Ok(rom)
+    }
+}
+
+// Runs until the CPU loops on the same instruction (the way test suites report their result,
+// e.g. with `JMP *`), or halts. Returns the address of the trap, or None after `max_instructions`.
+pub fn run_until_trap(cpu: &mut CPU, max_instructions: u64) -> Option<u16> {
+    for _ in 0..max_instructions {
+        let pc = cpu.program_counter;
+        if cpu.halted {
+            return Some(pc);
+        }
+        cpu.step();
+        if cpu.program_counter == pc {
+            return Some(pc);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bus::Bus;
+    use crate::cpu6502::new_cpu;
+    use crate::loader::{run_until_trap, Loader};
+    use crate::nes::Nes;
+
+    // LDX #$05; loop: INX; CPX #$0A; BNE loop; STX $10; trap: JMP trap
+    fn count_program(address: u16) -> Vec<u8> {
+        let trap = address + 9;
+        let [low, high] = trap.to_le_bytes();
+        vec![0xA2, 0x05, 0xE8, 0xE0, 0x0A, 0xD0, 0xFB, 0x86, 0x10, 0x4C, low, high]
+    }
+
+    #[test]
+    fn test_load_flat() {
+        let mut cpu = Loader::new(0x0400).flat_cpu(&count_program(0x0400)).unwrap();
+        assert_eq!(cpu.program_counter, 0x0400);
+        assert_eq!(cpu.peek_u16(0xFFFC), 0x0400);
+        assert_eq!(run_until_trap(&mut cpu, 100), Some(0x0409));
+        assert_eq!(cpu.peek_u8(0x0010), 0x0A);
+
+        // The entry point is not the first byte
+        let mut program = vec![0xEA; 0x10];
+        program.extend(count_program(0x0210));
+        let mut cpu = Loader::new(0x0200).with_entry_point(0x0210).flat_cpu(&program).unwrap();
+        assert_eq!(run_until_trap(&mut cpu, 100), Some(0x0219));
+
+        // A whole memory image, with its own vectors
+        let mut image = vec![0; 0x10000];
+        image[0x0400..0x040C].copy_from_slice(&count_program(0x0400));
+        image[0xFFFC..0xFFFE].copy_from_slice(&[0x00, 0x04]);
+        let cpu = Loader::new(0x0000).with_reset_vector_kept().flat_cpu(&image).unwrap();
+        assert_eq!(cpu.program_counter, 0x0400);
+
+        assert!(Loader::new(0xFFF0).flat_cpu(&[0; 0x11]).is_err());
+        assert!(Loader::new(0x0000).flat_cpu(&[]).is_err());
+    }
+
+    #[test]
+    fn test_load_into_cartridge_bus() {
+        // PRG ROM is patched, as in the memory editor
+        let mut cpu = new_cpu(Bus::new(crate::rom::Rom::test_rom()));
+        Loader::new(0x8000).load(&mut cpu, &count_program(0x8000)).unwrap();
+        assert_eq!(cpu.program_counter, 0x8000);
+        assert_eq!(run_until_trap(&mut cpu, 100), Some(0x8009));
+    }
+
+    #[test]
+    fn test_build_rom() {
+        // Fits in the upper bank: 16KB, mirrored
+        let rom = Loader::new(0xC000).build_rom(&count_program(0xC000)).unwrap();
+        assert_eq!(rom.prg_rom.len(), 0x4000);
+        assert_eq!(rom.prg_rom[0x3FFC..0x3FFE], [0x00, 0xC0]);
+        assert_eq!(rom.prg_rom[0x100], 0xFF);
+
+        let mut nes = Nes::new(rom).unwrap();
+        assert_eq!(nes.cpu.program_counter, 0xC000);
+        assert_eq!(run_until_trap(&mut nes.cpu, 100), Some(0xC009));
+
+        // Crosses the middle of the address space: 32KB
+        let rom = Loader::new(0xBFFC).build_rom(&count_program(0xBFFC)).unwrap();
+        assert_eq!(rom.prg_rom.len(), 0x8000);
+        assert_eq!(rom.header.prg_rom_size, 2);
+        assert_eq!(rom.prg_rom[0x7FFC..0x7FFE], [0xFC, 0xBF]);
+
+        assert!(Loader::new(0x0400).build_rom(&count_program(0x0400)).is_err());
+    }
+}
@@ -1,19 +1,154 @@
-pub mod cpu6502;
-pub mod instructions;
-pub mod rom;
-pub mod bus;
+use nes::cpu6502::{CPU};
+use nes::rom::Rom;
+use nes::rom_database::RomDatabase;
+use nes::nes::Nes;
+use nes::disasm::disassemble_with_labels;
+use nes::labels::Labels;
+use nes::trace_logger::{parse_address_range, TraceFilter, TraceFormat, TraceLogger};
+use nes::battery_save::SaveManager;
+use nes::savestate_check::{check_savestate_consistency, ConsistencyCheck};
+use nes::frame_corpus::FrameCorpus;
+use nes::trace_compare::TraceComparator;
+use nes::loader::{run_until_trap, Loader};
 
-use crate::cpu6502::trace;
-use crate::cpu6502::{CPU};
-use crate::cpu6502::new_cpu;
-use crate::rom::Rom;
-use crate::bus::Bus;
 
+const ROM_PATH: &str = "./nestest.nes";
 
 fn main() {
-    let rom_data = std::fs::read("./nestest.nes").expect("Failed to read ROM file");
-    let rom = Rom::parse_nes_rom(rom_data).expect("Failed to parse ROM");
-    rom.check_validity().expect("ROM validity check failed");
+    // Usage: cargo run -- info [file] [nes20db.xml]
+    // Prints what is known about a ROM (header, hashes, database match) and whether it can be played.
+    // With a database file, the header is corrected with the entry of the game before it is analyzed.
+    if std::env::args().nth(1).as_deref() == Some("info") {
+        let path = std::env::args().nth(2).unwrap_or_else(|| ROM_PATH.to_string());
+        let mut rom = Rom::load_file(&path).expect("Failed to load ROM");
+        let database = std::env::args().nth(3).map(|path| RomDatabase::load_file(&path).expect("Failed to load ROM database"));
+        let entry = database.as_ref().and_then(|database| database.lookup(&rom)).cloned();
+        if let Some(entry) = &entry {
+            rom.apply_database_entry(entry);
+        }
+        let mut info = rom.analyze();
+        if let Some(entry) = entry {
+            info.database_title = Some(entry.title.into_owned());
+        }
+        info.guess_region_from_file_name(&path);
+        println!("{}", info);
+        return;
+    }
+
+    // Usage: cargo run -- play <file> [config file]
+    // Plays the game in a window (see src/frontend.rs), with the settings of the config file
+    // (config.toml by default: the default settings apply without it). The gamepad profiles added or
+    // changed while playing are saved to it.
+    #[cfg(feature = "frontend")]
+    if std::env::args().nth(1).as_deref() == Some("play") {
+        let path = std::env::args().nth(2).expect("Missing ROM path");
+        let config_path = std::env::args().nth(3).unwrap_or_else(|| "config.toml".to_string());
+        let mut config = nes::config::Config::load(&config_path).expect("Failed to load the config file");
+        let mut nes = Nes::new(Rom::load_file(&path).expect("Failed to load ROM")).expect("Failed to start the console");
+        nes.set_savestate_directory(&config.savestate_directory);
+        nes.set_accuracy(config.accuracy);
+        if let Some(palette_file) = &config.palette_file {
+            nes.set_palette(nes::palette::Palette::load_pal_file(palette_file).expect("Failed to load the palette"));
+        }
+        let mut save_manager = SaveManager::new(&path, &nes.cpu);
+        save_manager.load(&mut nes.cpu).expect("Failed to load battery save");
+        let result = nes::frontend::run(&mut nes, &mut config, &config_path, |nes| save_manager.on_frame(&mut nes.cpu));
+        save_manager.flush(&mut nes.cpu).expect("Failed to write battery save");
+        if let Err(error) = result {
+            eprintln!("{}", error);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    // Usage: cargo run -- check-savestates [file] [frames]
+    // Runs the ROM headless and checks that restoring savestates does not change the emulation.
+    if std::env::args().nth(1).as_deref() == Some("check-savestates") {
+        let path = std::env::args().nth(2).unwrap_or_else(|| ROM_PATH.to_string());
+        let rom = Rom::load_file(&path).expect("Failed to load ROM");
+        let mut check = ConsistencyCheck::default();
+        if let Some(frames) = std::env::args().nth(3) {
+            check.frames = frames.parse().expect("Invalid frame count");
+        }
+        match check_savestate_consistency(&rom, &check) {
+            Ok(checked) => println!("{} savestates checked, no divergence", checked),
+            Err(error) => {
+                eprintln!("{}", error);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    // Usage: cargo run -- hot-spots [file] [frames]
+    // Runs the ROM headless and reports the most executed addresses and opcodes.
+    if std::env::args().nth(1).as_deref() == Some("hot-spots") {
+        let path = std::env::args().nth(2).unwrap_or_else(|| ROM_PATH.to_string());
+        let frames: u32 = std::env::args().nth(3).map_or(600, |frames| frames.parse().expect("Invalid frame count"));
+        let mut nes = Nes::new(Rom::load_file(&path).expect("Failed to load ROM")).expect("Failed to start the console");
+        nes.cpu.enable_execution_counts();
+        for _ in 0..frames {
+            nes.run_frame();
+        }
+        let counts = nes.cpu.disable_execution_counts().expect("Execution counts are enabled");
+        print!("{}", counts.report(20, &nes.cpu.labels));
+        return;
+    }
+
+    // Usage: cargo run -- frame-corpus record <corpus> <frames> <rom>...
+    //        cargo run -- frame-corpus check <corpus>
+    // Records the frame hashes of ROMs into a corpus file, or checks them against it.
+    if std::env::args().nth(1).as_deref() == Some("frame-corpus") {
+        let args: Vec<String> = std::env::args().skip(2).collect();
+        let path = std::path::Path::new(args.get(1).expect("Missing corpus file path"));
+        let directory = path.parent().unwrap_or(std::path::Path::new("."));
+        match args[0].as_str() {
+            "record" => {
+                let mut corpus = if path.exists() { FrameCorpus::load_file(path).expect("Failed to load corpus") } else { FrameCorpus::default() };
+                let frames = args.get(2).expect("Missing frame count").parse().expect("Invalid frame count");
+                for rom in &args[3..] {
+                    corpus.record(directory, rom, frames).expect("Failed to record ROM");
+                }
+                corpus.save_file(path).expect("Failed to write corpus");
+            }
+            "check" => {
+                let failures = FrameCorpus::load_file(path).expect("Failed to load corpus").check(directory);
+                for failure in &failures {
+                    eprintln!("{}", failure);
+                }
+                if !failures.is_empty() {
+                    std::process::exit(1);
+                }
+                println!("No frame hash regression");
+            }
+            command => panic!("Unknown frame-corpus command: {} (expected record or check)", command),
+        }
+        return;
+    }
+
+    // Usage: cargo run -- run-binary <file> <load address in hex> [entry point in hex]
+    // Runs a raw 6502 binary (e.g. a CPU test suite) in a flat 64KB memory until it traps on an
+    // instruction that jumps to itself, and prints where.
+    if std::env::args().nth(1).as_deref() == Some("run-binary") {
+        let args: Vec<String> = std::env::args().skip(2).collect();
+        let parse_address = |arg: &String| u16::from_str_radix(arg.trim_start_matches("0x").trim_start_matches('$'), 16).expect("Invalid address");
+        let binary = std::fs::read(args.first().expect("Missing binary path")).expect("Failed to read binary");
+        let mut loader = Loader::new(parse_address(args.get(1).expect("Missing load address")));
+        if let Some(entry_point) = args.get(2) {
+            loader = loader.with_entry_point(parse_address(entry_point));
+        }
+        let mut cpu = loader.flat_cpu(&binary).expect("Failed to load binary");
+        match run_until_trap(&mut cpu, 1_000_000_000) {
+            Some(address) => println!("Trapped at {:04X} after {} cycles", address, cpu.cycles),
+            None => {
+                eprintln!("No trap after 1000000000 instructions (PC {:04X})", cpu.program_counter);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    let rom = Rom::load_file(ROM_PATH).expect("Failed to load ROM");
 
     // println!("ROM Loaded successfully!");
     // println!("PRG ROM Size: {} bytes", rom.prg_rom.len());
@@ -22,13 +157,101 @@ fn main() {
     // println!("Mirroring: {:?}", rom.mirroring);
     // println!("Header: {:?}", rom.header);
 
-    let bus = Bus::new(rom);
-    let mut cpu: CPU = new_cpu(bus);
-    cpu.reset();
+    let mut nes = Nes::new(rom).expect("Failed to power on");
+    let cpu: &mut CPU = &mut nes.cpu;
+
+    let mut save_manager = SaveManager::new(ROM_PATH, cpu);
+    save_manager.load(cpu).expect("Failed to load battery save");
+
+    // Usage: cargo run -- --disasm [address in hex] [count]
+    // Prints the disassembly of the ROM (from the reset vector by default) instead of running it.
+    let mut args: Vec<String> = std::env::args().collect();
+
+    // Usage: cargo run -- --labels <file.nl|file.mlb> [other options]
+    // Loads a FCEUX or Mesen label file, used by the disassembler and the trace.
+    if args.get(1).map(String::as_str) == Some("--labels") {
+        let path = args.get(2).expect("Missing label file path");
+        cpu.labels = Labels::load_file(path, cpu.bus.rom().prg_rom.len()).expect("Failed to load labels");
+        args.drain(1..3);
+    }
+
+    if args.get(1).map(String::as_str) == Some("--disasm") {
+        let address = args.get(2)
+            .and_then(|arg| u16::from_str_radix(arg.trim_start_matches("0x").trim_start_matches('$'), 16).ok())
+            .unwrap_or(cpu.program_counter);
+        let count = args.get(3).and_then(|arg| arg.parse().ok()).unwrap_or(32);
+        for line in disassemble_with_labels(&cpu.bus, address, count, &cpu.labels) {
+            println!("{}", line);
+        }
+        return;
+    }
+
+    // Usage: cargo run --features gdb-server -- --gdb [address]
+    // Waits for a GDB client (127.0.0.1:9001 by default) and lets it control the CPU.
+    #[cfg(feature = "gdb-server")]
+    if args.get(1).map(String::as_str) == Some("--gdb") {
+        cpu.program_counter = 0xC000;
+        let address = args.get(2).map(String::as_str).unwrap_or("127.0.0.1:9001");
+        nes::gdb_server::GdbServer::new().serve(cpu, address).expect("GDB server failed");
+        return;
+    }
+
+    // Usage: cargo run -- --compare <reference trace> [start address in hex]
+    // Runs in lockstep with a Nintendulator, FCEUX or Mesen trace and stops at the first divergence.
+    if args.get(1).map(String::as_str) == Some("--compare") {
+        let path = args.get(2).expect("Missing reference trace path");
+        let reference = std::fs::read_to_string(path).expect("Failed to read reference trace");
+        if let Some(address) = args.get(3) {
+            cpu.program_counter = u16::from_str_radix(address.trim_start_matches("0x").trim_start_matches('$'), 16).expect("Invalid start address");
+        }
+        match TraceComparator::new(&reference).run(cpu) {
+            Ok(lines) => println!("{} lines match the reference trace", lines),
+            Err(divergence) => {
+                eprintln!("{}", divergence);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    // Usage: cargo run -- --trace <file> [nestest|json|csv] [--pc <range>]... [--control-flow] [--watch <range>]...
+    // Writes the trace to a file instead of stdout, optionally in a machine-readable format.
+    // Filters keep only the instructions located in the ranges (e.g. C000-C0FF), the branches, jumps,
+    // calls and returns, or the instructions accessing the watched addresses (e.g. 0720).
+    let mut logger = match args.get(1).map(String::as_str) {
+        Some("--trace") => {
+            let path = args.get(2).expect("Missing trace file path");
+            let mut logger = TraceLogger::file(path).expect("Failed to open trace file");
+            let mut options = args[3..].iter().map(String::as_str).peekable();
+            match options.next_if(|option| !option.starts_with("--")) {
+                None | Some("nestest") => {}
+                Some("json") => logger.set_format(TraceFormat::JsonLines),
+                Some("csv") => logger.set_format(TraceFormat::Csv),
+                Some(format) => panic!("Unknown trace format: {}", format),
+            }
+            let mut filter = TraceFilter::default();
+            while let Some(option) = options.next() {
+                let mut range = || parse_address_range(options.next().expect("Missing address range")).expect("Invalid address range");
+                match option {
+                    "--pc" => filter.pc_ranges.push(range()),
+                    "--control-flow" => filter.control_flow_only = true,
+                    "--watch" => filter.watched_addresses.push(range()),
+                    option => panic!("Unknown trace option: {}", option),
+                }
+            }
+            logger.set_filter(filter);
+            logger
+        }
+        _ => TraceLogger::stdout(),
+    };
+
     cpu.program_counter = 0xC000;
-    cpu.run_with_callback(move |cpu| {
-       println!("{}", trace(cpu));
+    let jam = cpu.run_with_callback(|cpu| {
+        logger.log(cpu).expect("Failed to write trace");
     });
+    logger.flush().expect("Failed to write trace");
+    eprintln!("{}", jam);
+    save_manager.flush(cpu).expect("Failed to write battery save");
 
     // cpu.run();
 
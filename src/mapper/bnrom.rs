@@ -0,0 +1,116 @@
+use crate::mapper::{bank_index, Mapper};
+use crate::rom::{Mirroring, Rom};
+
+// Mapper 34 covers two unrelated boards, told apart by their CHR memory:
+// - BNROM (Deadly Towers): CHR RAM, writes to 0x8000 - 0xFFFF select a 32KB PRG ROM bank.
+// - NINA-001 (Impossible Mission II): CHR ROM, with registers at the end of PRG RAM:
+//   0x7FFD selects the 32KB PRG ROM bank, 0x7FFE and 0x7FFF the 4KB CHR ROM banks at 0x0000 and 0x1000.
+#[derive(Debug)]
+pub struct Bnrom {
+    mirroring: Mirroring,
+    prg_rom_len: usize,
+    chr_len: usize,
+    nina_001: bool,
+    prg_bank: u8,
+    chr_banks: [u8; 2],
+    bus_conflicts: bool,
+}
+
+impl Bnrom {
+    pub fn new(rom: &Rom) -> Self {
+        Self {
+            mirroring: rom.mirroring,
+            prg_rom_len: rom.prg_rom.len(),
+            chr_len: rom.chr_rom.len(),
+            // BNROM boards only have 8KB of CHR RAM
+            nina_001: rom.chr_rom.len() > 0x2000,
+            prg_bank: 0,
+            chr_banks: [0, 1],
+            bus_conflicts: false,
+        }
+    }
+}
+
+impl Mapper for Bnrom {
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn prg_rom_index(&self, addr: u16) -> usize {
+        bank_index(self.prg_rom_len, self.prg_bank as usize, 0x8000, addr as usize)
+    }
+
+    fn chr_index(&self, addr: u16) -> usize {
+        if !self.nina_001 {
+            return (addr & 0x1FFF) as usize;
+        }
+        let bank = self.chr_banks[(addr as usize >> 12) & 1];
+        bank_index(self.chr_len, bank as usize, 0x1000, addr as usize)
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        match (self.nina_001, addr) {
+            (false, 0x8000..=0xFFFF) => self.prg_bank = value,
+            (true, 0x7FFD) => self.prg_bank = value & 0x01,
+            (true, 0x7FFE) => self.chr_banks[0] = value & 0x0F,
+            (true, 0x7FFF) => self.chr_banks[1] = value & 0x0F,
+            _ => {}
+        }
+    }
+
+    fn has_prg_ram_register(&self, addr: u16) -> bool {
+        self.nina_001 && (0x7FFD..=0x7FFF).contains(&addr)
+    }
+
+    // NINA-001 registers are in PRG RAM, only BNROM has bus conflicts
+    fn bus_conflicts(&self) -> bool {
+        self.bus_conflicts && !self.nina_001
+    }
+
+    fn set_bus_conflicts(&mut self, enabled: bool) {
+        self.bus_conflicts = enabled;
+    }
+
+    fn registers(&self) -> Vec<u8> {
+        vec![self.prg_bank, self.chr_banks[0], self.chr_banks[1]]
+    }
+
+    fn set_registers(&mut self, registers: &[u8]) {
+        self.prg_bank = registers[0];
+        self.chr_banks = [registers[1], registers[2]];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mapper::bnrom::Bnrom;
+    use crate::mapper::Mapper;
+    use crate::rom::Rom;
+
+    #[test]
+    fn test_bnrom() {
+        let mut rom = Rom::test_rom();
+        rom.prg_rom = vec![0; 0x20000];
+        rom.chr_rom = Vec::new();
+        let mut mapper = Bnrom::new(&rom);
+        mapper.write(0x8000, 3);
+        assert_eq!(mapper.prg_rom_index(0x8000), 0x18000);
+        // NINA-001 registers are plain PRG RAM
+        mapper.write(0x7FFD, 0);
+        assert_eq!(mapper.prg_rom_index(0x8000), 0x18000);
+    }
+
+    #[test]
+    fn test_nina_001() {
+        let mut rom = Rom::test_rom();
+        rom.prg_rom = vec![0; 0x10000];
+        rom.chr_rom = vec![0; 0x10000];
+        let mut mapper = Bnrom::new(&rom);
+        mapper.write(0x7FFD, 1);
+        mapper.write(0x7FFE, 5);
+        mapper.write(0x7FFF, 2);
+        assert_eq!(mapper.prg_rom_index(0x8000), 0x8000);
+        assert_eq!(mapper.chr_index(0x0010), 0x5010);
+        assert_eq!(mapper.chr_index(0x1010), 0x2010);
+    }
+}
@@ -0,0 +1,82 @@
+use crate::mapper::{bank_index, Mapper};
+use crate::rom::{Mirroring, Rom};
+
+// Camerica / Codemasters (mapper 71): like UxROM, writes to 0xC000 - 0xFFFF select the 16KB PRG ROM
+// bank at 0x8000, the last bank being fixed at 0xC000. CHR is 8KB of RAM.
+// Fire Hawk also selects a single screen mirroring with bit 4 of writes to 0x9000 - 0x9FFF.
+// Micro Machines, Bee 52, Dizzy games.
+#[derive(Debug)]
+pub struct Camerica {
+    mirroring: Mirroring,
+    prg_rom_len: usize,
+    prg_bank: u8,
+}
+
+impl Camerica {
+    pub fn new(rom: &Rom) -> Self {
+        Self { mirroring: rom.mirroring, prg_rom_len: rom.prg_rom.len(), prg_bank: 0 }
+    }
+}
+
+impl Mapper for Camerica {
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn prg_rom_index(&self, addr: u16) -> usize {
+        let bank = match addr {
+            0x8000..=0xBFFF => self.prg_bank as usize,
+            _ => self.prg_rom_len / 0x4000 - 1,
+        };
+        bank_index(self.prg_rom_len, bank, 0x4000, addr as usize)
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        match addr {
+            0x9000..=0x9FFF => {
+                self.mirroring = if value & 0x10 != 0 { Mirroring::SingleScreenUpper } else { Mirroring::SingleScreenLower };
+            }
+            0xC000..=0xFFFF => self.prg_bank = value & 0x0F,
+            _ => {}
+        }
+    }
+
+    fn registers(&self) -> Vec<u8> {
+        let mirroring = match self.mirroring {
+            Mirroring::SingleScreenLower => 1,
+            Mirroring::SingleScreenUpper => 2,
+            _ => 0,
+        };
+        vec![self.prg_bank, mirroring]
+    }
+
+    fn set_registers(&mut self, registers: &[u8]) {
+        self.prg_bank = registers[0];
+        match registers[1] {
+            1 => self.mirroring = Mirroring::SingleScreenLower,
+            2 => self.mirroring = Mirroring::SingleScreenUpper,
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mapper::camerica::Camerica;
+    use crate::mapper::Mapper;
+    use crate::rom::{Mirroring, Rom};
+
+    #[test]
+    fn test_bank_switching() {
+        let mut rom = Rom::test_rom();
+        rom.prg_rom = vec![0; 0x40000];
+        let mut mapper = Camerica::new(&rom);
+        assert_eq!(mapper.prg_rom_index(0xC000), 0x3C000);
+        mapper.write(0xC000, 2);
+        assert_eq!(mapper.prg_rom_index(0x8123), 0x8123);
+        assert_eq!(mapper.prg_rom_index(0xFFFF), 0x3FFFF);
+
+        mapper.write(0x9000, 0x10);
+        assert_eq!(mapper.mirroring(), Mirroring::SingleScreenUpper);
+    }
+}
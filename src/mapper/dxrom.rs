@@ -0,0 +1,106 @@
+use crate::mapper::{bank_index, Mapper};
+use crate::rom::{Mirroring, Rom};
+
+// DxROM / Namco 108 (mapper 206): the predecessor of the MMC3, without its IRQ counter and
+// mirroring control. An even address in 0x8000 - 0x9FFF selects one of 8 bank registers, and the
+// next odd address writes it:
+// - R0, R1: 2KB CHR banks at 0x0000 and 0x0800
+// - R2 - R5: 1KB CHR banks at 0x1000, 0x1400, 0x1800 and 0x1C00
+// - R6, R7: 8KB PRG ROM banks at 0x8000 and 0xA000
+// 0xC000 - 0xFFFF is fixed to the last 16KB. Babel no Tou, Karnov, Dragon Spirit.
+#[derive(Debug)]
+pub struct Dxrom {
+    mirroring: Mirroring,
+    prg_rom_len: usize,
+    chr_len: usize,
+    selected: u8,
+    registers: [u8; 8],
+}
+
+impl Dxrom {
+    pub fn new(rom: &Rom) -> Self {
+        Self {
+            mirroring: rom.mirroring,
+            prg_rom_len: rom.prg_rom.len(),
+            chr_len: rom.chr_rom.len(),
+            selected: 0,
+            registers: [0, 2, 4, 5, 6, 7, 0, 1],
+        }
+    }
+}
+
+impl Mapper for Dxrom {
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn prg_rom_index(&self, addr: u16) -> usize {
+        let last_bank = self.prg_rom_len / 0x2000 - 1;
+        let bank = match addr {
+            0x8000..=0x9FFF => self.registers[6] as usize,
+            0xA000..=0xBFFF => self.registers[7] as usize,
+            0xC000..=0xDFFF => last_bank - 1,
+            _ => last_bank,
+        };
+        bank_index(self.prg_rom_len, bank, 0x2000, addr as usize)
+    }
+
+    fn chr_index(&self, addr: u16) -> usize {
+        let addr = (addr & 0x1FFF) as usize;
+        match addr {
+            // 2KB banks: the low bit of the register is ignored
+            0x0000..=0x0FFF => bank_index(self.chr_len, (self.registers[addr >> 11] >> 1) as usize, 0x800, addr),
+            _ => bank_index(self.chr_len, self.registers[2 + ((addr - 0x1000) >> 10)] as usize, 0x400, addr),
+        }
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        match (addr, addr & 1) {
+            (0x8000..=0x9FFF, 0) => self.selected = value & 0x07,
+            (0x8000..=0x9FFF, _) => {
+                let mask = if self.selected >= 6 { 0x0F } else { 0x3F };
+                self.registers[self.selected as usize] = value & mask;
+            }
+            _ => {}
+        }
+    }
+
+    fn registers(&self) -> Vec<u8> {
+        let mut registers = self.registers.to_vec();
+        registers.push(self.selected);
+        registers
+    }
+
+    fn set_registers(&mut self, registers: &[u8]) {
+        self.registers.copy_from_slice(&registers[..8]);
+        self.selected = registers[8];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mapper::dxrom::Dxrom;
+    use crate::mapper::Mapper;
+    use crate::rom::Rom;
+
+    #[test]
+    fn test_bank_registers() {
+        let mut rom = Rom::test_rom();
+        rom.prg_rom = vec![0; 0x20000];
+        rom.chr_rom = vec![0; 0x10000];
+        let mut mapper = Dxrom::new(&rom);
+        assert_eq!(mapper.prg_rom_index(0xC000), 0x1C000);
+        assert_eq!(mapper.prg_rom_index(0xE000), 0x1E000);
+
+        for (register, value) in [(6, 3), (7, 0x1F), (0, 5), (5, 0x21)] {
+            mapper.write(0x8000, register);
+            mapper.write(0x8001, value);
+        }
+        assert_eq!(mapper.prg_rom_index(0x8010), 0x6010);
+        // 4-bit PRG bank registers
+        assert_eq!(mapper.prg_rom_index(0xA010), 0x1E010);
+        // R0 = 5: 2KB bank 2
+        assert_eq!(mapper.chr_index(0x0010), 0x1010);
+        assert_eq!(mapper.chr_index(0x1C10), 0x8410);
+    }
+}
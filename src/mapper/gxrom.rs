@@ -0,0 +1,85 @@
+use crate::mapper::{bank_index, Mapper};
+use crate::rom::{Mirroring, Rom};
+
+// GxROM (mapper 66): a single register at 0x8000 - 0xFFFF selects a 32KB PRG ROM bank (bits 4-5)
+// and an 8KB CHR ROM bank (bits 0-1). Super Mario Bros. + Duck Hunt, Dragon Power.
+#[derive(Debug)]
+pub struct Gxrom {
+    mirroring: Mirroring,
+    prg_rom_len: usize,
+    chr_len: usize,
+    prg_bank: u8,
+    chr_bank: u8,
+    bus_conflicts: bool,
+}
+
+impl Gxrom {
+    pub fn new(rom: &Rom) -> Self {
+        Self {
+            mirroring: rom.mirroring,
+            prg_rom_len: rom.prg_rom.len(),
+            chr_len: rom.chr_rom.len(),
+            prg_bank: 0,
+            chr_bank: 0,
+            bus_conflicts: false,
+        }
+    }
+}
+
+impl Mapper for Gxrom {
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn prg_rom_index(&self, addr: u16) -> usize {
+        bank_index(self.prg_rom_len, self.prg_bank as usize, 0x8000, addr as usize)
+    }
+
+    fn chr_index(&self, addr: u16) -> usize {
+        bank_index(self.chr_len, self.chr_bank as usize, 0x2000, addr as usize)
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        if addr >= 0x8000 {
+            self.prg_bank = (value >> 4) & 0x03;
+            self.chr_bank = value & 0x03;
+        }
+    }
+
+    fn bus_conflicts(&self) -> bool {
+        self.bus_conflicts
+    }
+
+    fn set_bus_conflicts(&mut self, enabled: bool) {
+        self.bus_conflicts = enabled;
+    }
+
+    fn registers(&self) -> Vec<u8> {
+        vec![self.prg_bank, self.chr_bank]
+    }
+
+    fn set_registers(&mut self, registers: &[u8]) {
+        self.prg_bank = registers[0];
+        self.chr_bank = registers[1];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mapper::gxrom::Gxrom;
+    use crate::mapper::Mapper;
+    use crate::rom::Rom;
+
+    #[test]
+    fn test_bank_switching() {
+        let mut rom = Rom::test_rom();
+        rom.prg_rom = vec![0; 0x20000];
+        rom.chr_rom = vec![0; 0x8000];
+        let mut mapper = Gxrom::new(&rom);
+        assert_eq!(mapper.prg_rom_index(0xFFFC), 0x7FFC);
+
+        mapper.write(0x8000, 0x32);
+        assert_eq!(mapper.prg_rom_index(0x8000), 0x18000);
+        assert_eq!(mapper.chr_index(0x1234), 0x5234);
+    }
+}
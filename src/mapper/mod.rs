@@ -0,0 +1,171 @@
+use crate::mapper::registry::MapperRegistry;
+use crate::rom::{Mirroring, Rom};
+
+pub mod bnrom;
+pub mod camerica;
+pub mod color_dreams;
+pub mod dxrom;
+pub mod gxrom;
+pub mod nrom;
+pub mod registry;
+pub mod vs_unisystem;
+
+// Hardware of the cartridge, identified by the mapper number of the iNES header.
+// Many mappers change the cartridge wiring at runtime, so the PPU asks the mapper for the
+// nametable mirroring on every VRAM access instead of reading it once from the header.
+// Bank switching is done the same way: the bus asks the mapper where each PRG ROM address is.
+pub trait Mapper: std::fmt::Debug {
+    fn mirroring(&self) -> Mirroring;
+
+    // Index in PRG ROM of a CPU address in cartridge space (0x8000 - 0xFFFF).
+    fn prg_rom_index(&self, addr: u16) -> usize;
+
+    // Index in CHR ROM (or CHR RAM) of a pattern table address (0x0000 - 0x1FFF).
+    fn chr_index(&self, addr: u16) -> usize {
+        (addr & 0x1FFF) as usize
+    }
+
+    // CPU write to 0x4020 - 0xFFFF, where the bank switching registers are, or to 0x4016.
+    // Writes to PRG RAM are stored by the bus before the mapper sees them.
+    fn write(&mut self, _addr: u16, _value: u8) {}
+
+    // Whether `addr` in PRG RAM space (0x6000 - 0x7FFF) is also a register of the cartridge,
+    // like the bank registers of NINA-001. Only used to annotate addresses in debugger views.
+    fn has_prg_ram_register(&self, _addr: u16) -> bool {
+        false
+    }
+
+    // CPU read of the expansion area (0x4020 - 0x5FFF), where MMC5, the FDS and many pirate boards
+    // have registers or RAM. None when nothing drives the data bus there (open bus).
+    fn read_expansion(&self, _addr: u16) -> Option<u8> {
+        None
+    }
+
+    // Same as `read_expansion` without the side effects of the read (e.g. acknowledging an IRQ),
+    // for debugger views and traces. Boards whose registers react to reads override it.
+    fn peek_expansion(&self, addr: u16) -> Option<u8> {
+        self.read_expansion(addr)
+    }
+
+    // Boards made of discrete logic chips (UxROM, CNROM, AxROM, GxROM...) have no chip select on
+    // the PRG ROM: when the CPU writes to a register, the ROM also drives the data bus with the byte
+    // at that address, and the register latches the AND of both. Games avoid it by writing to a byte
+    // holding the same value, some test ROMs check it. Emulating it is an accuracy option, off by default.
+    fn bus_conflicts(&self) -> bool {
+        false
+    }
+
+    // Ignored by mappers without bus conflicts.
+    fn set_bus_conflicts(&mut self, _enabled: bool) {}
+
+    // Bank switching registers, saved in savestates.
+    fn registers(&self) -> Vec<u8> {
+        Vec::new()
+    }
+
+    // Restores `registers()`. The length has already been checked by the caller.
+    fn set_registers(&mut self, _registers: &[u8]) {}
+}
+
+// Index in a ROM of byte `offset` of bank `bank`. The ROM is seen as a list of banks of
+// `bank_size` bytes; bank numbers wrap around it, since the unused bank bits are not connected.
+pub fn bank_index(rom_len: usize, bank: usize, bank_size: usize, offset: usize) -> usize {
+    let banks = (rom_len / bank_size).max(1);
+    (bank % banks) * bank_size + offset % bank_size
+}
+
+// Creates the mapper of the ROM with the built-in mappers, see `MapperRegistry` for the others.
+pub fn new_mapper(rom: &Rom) -> Result<Box<dyn Mapper>, String> {
+    MapperRegistry::builtin().create(rom)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bus::Bus;
+    use crate::mapper::{bank_index, new_mapper, Mapper};
+    use crate::rom::{Mirroring, Rom};
+
+    #[test]
+    fn test_new_mapper() {
+        let mut rom = Rom::test_rom();
+        rom.mirroring = Mirroring::Vertical;
+        assert_eq!(new_mapper(&rom).unwrap().mirroring(), Mirroring::Vertical);
+
+        rom.mapper = 66;
+        assert!(new_mapper(&rom).is_ok());
+        rom.mapper = 4;
+        assert!(new_mapper(&rom).is_err());
+    }
+
+    #[test]
+    fn test_bank_index() {
+        // 4 banks of 32KB: bank 5 is bank 1
+        assert_eq!(bank_index(0x20000, 5, 0x8000, 0x10), 0x8010);
+        // ROM smaller than a bank
+        assert_eq!(bank_index(0x4000, 1, 0x8000, 0x10), 0x0010);
+    }
+
+    #[test]
+    fn test_bus_conflicts() {
+        let mut rom = Rom::test_rom();
+        rom.mapper = 66;
+        rom.prg_rom = vec![0; 0x20000];
+        // Bank number table, a common way to avoid bus conflicts
+        rom.prg_rom[0x7F00] = 0x30;
+        rom.prg_rom[0x7F01] = 0x10;
+        let mut bus = Bus::new(rom);
+
+        bus.write_u8(0xFF00, 0x20);
+        assert_eq!(bus.mapper().prg_rom_index(0x8000), 0x10000);
+
+        bus.mapper_mut().set_bus_conflicts(true);
+        bus.write_u8(0x8000, 0x30);
+        // The ROM drives 0x00 at 0x8000 of bank 2
+        assert_eq!(bus.mapper().prg_rom_index(0x8000), 0x00000);
+        bus.write_u8(0xFF01, 0x30);
+        assert_eq!(bus.mapper().prg_rom_index(0x8000), 0x08000);
+    }
+
+    // A board with a register at 0x5000 and nothing else in the expansion area
+    #[derive(Debug, Default)]
+    struct ExpansionRegister {
+        value: u8,
+    }
+
+    impl Mapper for ExpansionRegister {
+        fn mirroring(&self) -> Mirroring {
+            Mirroring::Horizontal
+        }
+
+        fn prg_rom_index(&self, addr: u16) -> usize {
+            (addr & 0x3FFF) as usize
+        }
+
+        fn write(&mut self, addr: u16, value: u8) {
+            if addr == 0x5000 {
+                self.value = value;
+            }
+        }
+
+        fn read_expansion(&self, addr: u16) -> Option<u8> {
+            (addr == 0x5000).then_some(self.value)
+        }
+    }
+
+    #[test]
+    fn test_expansion_area() {
+        // Nothing there on NROM: the data bus keeps the high byte of the address
+        let mut bus = Bus::new(Rom::test_rom());
+        assert_eq!(bus.read_u8(0x4020), 0x40);
+        assert_eq!(bus.read_u8(0x5FFF), 0x5F);
+
+        assert_eq!(bus.peek_u8(0x4020), 0x40);
+
+        bus.set_mapper(Box::new(ExpansionRegister::default()));
+        bus.write_u8(0x5000, 0xA5);
+        assert_eq!(bus.read_u8(0x5000), 0xA5);
+        assert_eq!(bus.read_u8(0x5001), 0x50);
+        // Debugger views see the same values
+        assert_eq!((bus.peek_u8(0x5000), bus.peek_u8(0x5001)), (0xA5, 0x50));
+    }
+}
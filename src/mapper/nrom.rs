@@ -0,0 +1,41 @@
+use crate::mapper::Mapper;
+use crate::rom::{Mirroring, Rom};
+
+// NROM (mapper 0): no bank switching, the mirroring is soldered on the board.
+#[derive(Debug)]
+pub struct Nrom {
+    mirroring: Mirroring,
+    prg_rom_len: usize,
+}
+
+impl Nrom {
+    pub fn new(rom: &Rom) -> Self {
+        Self { mirroring: rom.mirroring, prg_rom_len: rom.prg_rom.len() }
+    }
+}
+
+impl Mapper for Nrom {
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn prg_rom_index(&self, addr: u16) -> usize {
+        // Shift address down so 0x8000 becomes 0x0000
+        let mut addr = addr - 0x8000;
+
+        // If PRG ROM is 16KB (len = 16384), it is mirrored.
+        // The CPU expects code at 0xC000, but we only have data up to 0x4000.
+        // So we mirror 0xC000-0xFFFF back to 0x8000-0xBFFF.
+        if self.prg_rom_len == 16384 && addr >= 16384 {
+            addr %= 16384;
+        }
+        addr as usize
+    }
+
+    fn write(&mut self, addr: u16, _value: u8) {
+        if addr >= 0x8000 {
+            // PRG ROM is not writable. Ignore writes or log a warning.
+            println!("Attempted write to PRG ROM at address {:04X}", addr);
+        }
+    }
+}
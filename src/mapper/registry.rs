@@ -0,0 +1,161 @@
+use std::collections::BTreeMap;
+use crate::mapper::bnrom::Bnrom;
+use crate::mapper::camerica::Camerica;
+use crate::mapper::color_dreams::ColorDreams;
+use crate::mapper::dxrom::Dxrom;
+use crate::mapper::gxrom::Gxrom;
+use crate::mapper::nrom::Nrom;
+use crate::mapper::vs_unisystem::VsUnisystem;
+use crate::mapper::Mapper;
+use crate::rom::Rom;
+
+// Creates the mapper of a ROM, or explains why the ROM cannot be played with it.
+pub type MapperConstructor = fn(&Rom) -> Result<Box<dyn Mapper>, String>;
+
+#[derive(Debug, Clone, Copy)]
+pub struct MapperEntry {
+    pub name: &'static str,
+    pub constructor: MapperConstructor,
+}
+
+// Mappers known to the emulator, keyed by iNES mapper number and NES 2.0 submapper.
+// Each console owns its registry (see `NesBuilder::mappers`), so frontends and tools can add their
+// own mappers (e.g. for homebrew boards) without touching the other consoles of the process.
+// An entry without submapper is used for all the submappers that have no entry of their own,
+// and for iNES 1.0 ROMs which have no submapper.
+#[derive(Debug, Clone, Default)]
+pub struct MapperRegistry {
+    entries: BTreeMap<(u16, Option<u8>), MapperEntry>,
+}
+
+impl MapperRegistry {
+    // An empty registry, see `builtin` for the one with the mappers of the emulator.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn builtin() -> Self {
+        let mut registry = Self::new();
+        registry.register(0, None, "NROM", new_nrom);
+        registry.register(11, None, "Color Dreams", |rom| Ok(Box::new(ColorDreams::new(rom))));
+        registry.register(34, None, "BNROM / NINA-001", |rom| Ok(Box::new(Bnrom::new(rom))));
+        registry.register(66, None, "GxROM", |rom| Ok(Box::new(Gxrom::new(rom))));
+        registry.register(71, None, "Camerica", |rom| Ok(Box::new(Camerica::new(rom))));
+        registry.register(99, None, "VS Unisystem", |rom| Ok(Box::new(VsUnisystem::new(rom))));
+        registry.register(206, None, "DxROM", |rom| Ok(Box::new(Dxrom::new(rom))));
+        registry
+    }
+
+    // Adds a mapper, replacing the one already registered for the same number and submapper.
+    pub fn register(&mut self, number: u16, submapper: Option<u8>, name: &'static str, constructor: MapperConstructor) {
+        self.entries.insert((number, submapper), MapperEntry { name, constructor });
+    }
+
+    pub fn unregister(&mut self, number: u16, submapper: Option<u8>) -> Option<MapperEntry> {
+        self.entries.remove(&(number, submapper))
+    }
+
+    pub fn lookup(&self, number: u16, submapper: Option<u8>) -> Option<&MapperEntry> {
+        submapper
+            .and_then(|submapper| self.entries.get(&(number, Some(submapper))))
+            .or_else(|| self.entries.get(&(number, None)))
+    }
+
+    pub fn is_supported(&self, number: u16, submapper: Option<u8>) -> bool {
+        self.lookup(number, submapper).is_some()
+    }
+
+    // Registered mappers, sorted by number then submapper.
+    pub fn entries(&self) -> impl Iterator<Item = (u16, Option<u8>, &MapperEntry)> {
+        self.entries.iter().map(|((number, submapper), entry)| (*number, *submapper, entry))
+    }
+
+    pub fn create(&self, rom: &Rom) -> Result<Box<dyn Mapper>, String> {
+        let entry = self.lookup(rom.mapper_number(), rom.submapper())
+            .ok_or_else(|| format!("Unsupported Mapper: ID {}", rom.mapper_number()))?;
+        // Whatever the mapper, there must be code to run
+        if rom.header.prg_rom_size == 0 || rom.prg_rom.is_empty() {
+            return Err(format!("Invalid {} PRG size: the ROM has no PRG ROM", entry.name));
+        }
+        (entry.constructor)(rom)
+    }
+}
+
+fn new_nrom(rom: &Rom) -> Result<Box<dyn Mapper>, String> {
+    // PRG ROM must be either 16KB (1 unit) or 32KB (2 units)
+    if rom.header.prg_rom_size != 1 && rom.header.prg_rom_size != 2 {
+        return Err(format!("Invalid NROM PRG size: {} units (must be 1 or 2)", rom.header.prg_rom_size));
+    }
+    Ok(Box::new(Nrom::new(rom)))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mapper::nrom::Nrom;
+    use crate::mapper::registry::MapperRegistry;
+    use crate::mapper::Mapper;
+    use crate::nes::{Nes, NesBuilder};
+    use crate::rom::{Mirroring, Rom};
+
+    #[derive(Debug)]
+    struct Homebrew;
+
+    impl Mapper for Homebrew {
+        fn mirroring(&self) -> Mirroring {
+            Mirroring::SingleScreenUpper
+        }
+
+        fn prg_rom_index(&self, addr: u16) -> usize {
+            (addr & 0x3FFF) as usize
+        }
+    }
+
+    #[test]
+    fn test_submapper_lookup() {
+        let mut registry = MapperRegistry::builtin();
+        assert_eq!(registry.lookup(66, Some(3)).unwrap().name, "GxROM");
+        assert!(registry.lookup(4, None).is_none());
+
+        registry.register(66, Some(3), "GxROM variant", |rom| Ok(Box::new(Nrom::new(rom))));
+        assert_eq!(registry.lookup(66, Some(3)).unwrap().name, "GxROM variant");
+        assert_eq!(registry.lookup(66, Some(1)).unwrap().name, "GxROM");
+        assert_eq!(registry.lookup(66, None).unwrap().name, "GxROM");
+
+        registry.unregister(66, None);
+        assert!(!registry.is_supported(66, None));
+        assert_eq!(registry.entries().count(), 7);
+    }
+
+    #[test]
+    fn test_create_checks_rom() {
+        let registry = MapperRegistry::builtin();
+        let mut rom = Rom::test_rom();
+        rom.header.prg_rom_size = 3;
+        assert!(registry.create(&rom).is_err());
+        rom.mapper = 66;
+        assert!(registry.create(&rom).is_ok());
+        rom.header.prg_rom_size = 0;
+        assert!(registry.create(&rom).is_err());
+    }
+
+    #[test]
+    fn test_register_custom_mapper() {
+        let mut rom = Rom::test_rom();
+        rom.mapper = 250;
+        assert!(Nes::new(rom.clone()).is_err());
+
+        let mut mappers = MapperRegistry::builtin();
+        mappers.register(250, None, "Homebrew", |_| Ok(Box::new(Homebrew)));
+        assert_eq!(rom.analyze_with(&mappers).mapper_name, Some("Homebrew"));
+        assert!(rom.check_validity_with(&mappers).is_ok());
+        let nes = NesBuilder::new(rom.clone()).mappers(mappers).build().unwrap();
+        assert_eq!(nes.cpu.bus.mapper().mirroring(), Mirroring::SingleScreenUpper);
+
+        // Only the console that registered the mapper knows it
+        assert!(rom.check_validity().is_err());
+        let mut other = Nes::new(Rom::test_rom()).unwrap();
+        assert!(other.load_rom(rom.clone()).is_err());
+        other.mappers_mut().register(250, None, "Homebrew", |_| Ok(Box::new(Homebrew)));
+        assert!(other.load_rom(rom).is_ok());
+    }
+}
@@ -0,0 +1,73 @@
+use crate::mapper::{bank_index, Mapper};
+use crate::rom::{Mirroring, Rom};
+
+// VS Unisystem (mapper 99): bit 2 of writes to 0x4016 selects the 8KB CHR ROM bank, and for the
+// games with 40KB of PRG ROM (Vs. Gumshoe), the 8KB PRG ROM bank at 0x8000 (bank 0 or 4).
+// The board has 4KB of VRAM, so the nametables are never mirrored.
+#[derive(Debug)]
+pub struct VsUnisystem {
+    prg_rom_len: usize,
+    chr_len: usize,
+    bank: u8,
+}
+
+impl VsUnisystem {
+    pub fn new(rom: &Rom) -> Self {
+        Self { prg_rom_len: rom.prg_rom.len(), chr_len: rom.chr_rom.len(), bank: 0 }
+    }
+}
+
+impl Mapper for VsUnisystem {
+    fn mirroring(&self) -> Mirroring {
+        Mirroring::FourScreen
+    }
+
+    fn prg_rom_index(&self, addr: u16) -> usize {
+        let offset = (addr - 0x8000) as usize;
+        let bank = match offset >> 13 {
+            0 if self.prg_rom_len > 0x8000 => 4 * self.bank as usize,
+            bank => bank,
+        };
+        bank_index(self.prg_rom_len, bank, 0x2000, offset)
+    }
+
+    fn chr_index(&self, addr: u16) -> usize {
+        bank_index(self.chr_len, self.bank as usize, 0x2000, addr as usize)
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        if addr == 0x4016 {
+            self.bank = (value >> 2) & 1;
+        }
+    }
+
+    fn registers(&self) -> Vec<u8> {
+        vec![self.bank]
+    }
+
+    fn set_registers(&mut self, registers: &[u8]) {
+        self.bank = registers[0];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mapper::vs_unisystem::VsUnisystem;
+    use crate::mapper::Mapper;
+    use crate::rom::Rom;
+
+    #[test]
+    fn test_bank_switching() {
+        let mut rom = Rom::test_rom();
+        rom.prg_rom = vec![0; 0xA000];
+        rom.chr_rom = vec![0; 0x4000];
+        let mut mapper = VsUnisystem::new(&rom);
+        assert_eq!(mapper.prg_rom_index(0x8010), 0x0010);
+        assert_eq!(mapper.prg_rom_index(0xE010), 0x6010);
+
+        mapper.write(0x4016, 0b0000_0100);
+        assert_eq!(mapper.prg_rom_index(0x8010), 0x8010);
+        assert_eq!(mapper.prg_rom_index(0xA010), 0x2010);
+        assert_eq!(mapper.chr_index(0x0010), 0x2010);
+    }
+}
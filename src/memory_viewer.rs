@@ -0,0 +1,165 @@
+use crate::bus::{MemoryArea, MemoryRegion};
+use crate::cpu6502::CPU;
+
+// Backend for a hex editor: memory can be displayed and edited while the emulation is paused.
+// Reads are peeks, so displaying memory never changes the emulation state.
+impl CPU {
+    // Reads `len` bytes starting at `addr`. The address wraps around after 0xFFFF.
+    pub fn read_range(&self, addr: u16, len: usize) -> Vec<u8> {
+        let mut bytes = vec![0; len];
+        self.bus.read_slice(addr, &mut bytes);
+        bytes
+    }
+
+    // Edits a single byte of memory. PRG ROM can be patched, but PPU and APU registers cannot.
+    pub fn write_byte(&mut self, addr: u16, value: u8) -> Result<(), String> {
+        self.bus.poke_u8(addr, value)
+    }
+
+    // Edits consecutive bytes, e.g. a pasted block. Nothing is written if one of them cannot be edited.
+    pub fn write_range(&mut self, addr: u16, data: &[u8]) -> Result<(), String> {
+        self.bus.write_slice(addr, data)
+    }
+
+    // What an address is wired to, used to annotate the hex editor view (see `Bus::region_of`).
+    pub fn region_of(&self, addr: u16) -> MemoryRegion {
+        self.bus.region_of(addr)
+    }
+
+    // Area of the memory map an address belongs to, used to split the hex editor view in sections.
+    pub fn area_of(&self, addr: u16) -> MemoryArea {
+        MemoryArea::of(addr)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bus::{Bus, MemoryArea, MemoryRegion};
+    use crate::cpu6502::new_cpu;
+    use crate::rom::Rom;
+
+    #[test]
+    fn test_read_range_follows_memory_map() {
+        let mut cpu = new_cpu(Bus::new(Rom::test_rom()));
+        cpu.write_u8(0x0000, 0x01);
+        cpu.write_u8(0x0001, 0x02);
+
+        // RAM is mirrored at 0x0800
+        assert_eq!(cpu.read_range(0x0800, 3), vec![0x01, 0x02, 0x00]);
+        // PRG ROM of the test ROM is filled with NOPs
+        assert_eq!(cpu.read_range(0xFFFE, 2), vec![0xEA, 0xEA]);
+        // Reading PPU registers does not panic
+        assert_eq!(cpu.read_range(0x2000, 8).len(), 8);
+    }
+
+    #[test]
+    fn test_write_byte() {
+        let mut cpu = new_cpu(Bus::new(Rom::test_rom()));
+
+        cpu.write_byte(0x1801, 0x42).expect("RAM should be editable");
+        assert_eq!(cpu.read_u8(0x0001), 0x42);
+
+        // 16KB PRG ROM is mirrored, so patching 0xC000 also changes 0x8000
+        cpu.write_byte(0xC000, 0x4C).expect("PRG ROM should be editable");
+        assert_eq!(cpu.read_u8(0x8000), 0x4C);
+
+        assert!(cpu.write_byte(0x2000, 0x80).is_err());
+    }
+
+    #[test]
+    fn test_read_range_matches_peek() {
+        let mut cpu = new_cpu(Bus::new(Rom::test_rom()));
+        for addr in 0..0x0800 {
+            cpu.write_u8(addr, addr as u8 ^ 0x5A);
+        }
+        cpu.write_u8(0x7FFF, 0x42);
+
+        // The whole address space, and a range crossing RAM mirrors and wrapping after 0xFFFF
+        let expected: Vec<u8> = (0..=0xFFFF).map(|addr| cpu.peek_u8(addr)).collect();
+        assert_eq!(cpu.read_range(0x0000, 0x10000), expected);
+        let expected: Vec<u8> = (0..0x3000u16).map(|offset| cpu.peek_u8(0xF000u16.wrapping_add(offset))).collect();
+        assert_eq!(cpu.read_range(0xF000, 0x3000), expected);
+    }
+
+    #[test]
+    fn test_write_range() {
+        let mut cpu = new_cpu(Bus::new(Rom::test_rom()));
+
+        // Crosses the end of the first RAM mirror
+        cpu.write_range(0x07FE, &[1, 2, 3, 4]).expect("RAM should be editable");
+        assert_eq!(cpu.read_range(0x0000, 2), vec![3, 4]);
+        assert_eq!(cpu.read_range(0x07FE, 2), vec![1, 2]);
+
+        cpu.write_range(0x7FFF, &[0x11, 0x22]).expect("PRG RAM and ROM should be editable");
+        assert_eq!(cpu.read_u8(0x7FFF), 0x11);
+        assert!(cpu.bus.is_prg_ram_dirty());
+        assert_eq!(cpu.read_u8(0xC000), 0x22);
+
+        // Nothing is written when the range reaches the PPU registers
+        assert!(cpu.write_range(0x1FFF, &[0x99, 0x99]).is_err());
+        assert_eq!(cpu.read_u8(0x07FF), 0x02);
+    }
+
+    #[test]
+    fn test_area_of() {
+        let cpu = new_cpu(Bus::new(Rom::test_rom()));
+        assert_eq!(cpu.area_of(0x07FF), MemoryArea::Ram);
+        assert_eq!(cpu.area_of(0x2007), MemoryArea::PpuRegisters);
+        assert_eq!(cpu.area_of(0x4016), MemoryArea::ApuIoRegisters);
+        assert_eq!(cpu.area_of(0x5000), MemoryArea::ExpansionRom);
+        assert_eq!(cpu.area_of(0x6000), MemoryArea::SaveRam);
+        assert_eq!(cpu.area_of(0xFFFC), MemoryArea::PrgRom);
+
+        // Areas cover the whole address space without gaps
+        let mut next_start: u32 = 0x0000;
+        for area in MemoryArea::ALL {
+            let (start, end) = area.range();
+            assert_eq!(start as u32, next_start, "{} should start where the previous area ends", area.name());
+            next_start = end as u32 + 1;
+        }
+        assert_eq!(next_start, 0x10000);
+    }
+
+    #[test]
+    fn test_region_of() {
+        let mut rom = Rom::test_rom();
+        rom.mapper = 66;
+        rom.prg_rom = vec![0; 0x20000];
+        let mut bus = Bus::new(rom);
+        assert_eq!(bus.region_of(0x0801), MemoryRegion::Ram { index: 0x0001 });
+        assert_eq!(bus.region_of(0x3FFF), MemoryRegion::PpuRegister { register: 7 });
+        assert_eq!(bus.region_of(0x4016), MemoryRegion::ApuIoRegister { addr: 0x4016 });
+        assert_eq!(bus.region_of(0x4018), MemoryRegion::OpenBus);
+        assert_eq!(bus.region_of(0x5000), MemoryRegion::OpenBus);
+        assert_eq!(bus.region_of(0x7FFF), MemoryRegion::PrgRam { offset: 0x1FFF });
+        assert_eq!(bus.region_of(0x8010), MemoryRegion::PrgRom { bank: 0, offset: 0x0010 });
+
+        // GxROM: 32KB bank 2
+        bus.write_u8(0x8000, 0x20);
+        assert_eq!(bus.region_of(0xC010), MemoryRegion::PrgRom { bank: 10, offset: 0x14010 });
+        assert_eq!(bus.region_of(0xC010).to_string(), "PRG ROM bank 10 (offset $14010)");
+
+        let cpu = new_cpu(bus);
+        assert_eq!(cpu.region_of(0xC010), MemoryRegion::PrgRom { bank: 10, offset: 0x14010 });
+    }
+
+    #[test]
+    fn test_region_of_nina_001_registers() {
+        // Mapper 34 with 8KB of CHR is BNROM, which has no register in PRG RAM space
+        let mut rom = Rom::test_rom();
+        rom.mapper = 34;
+        rom.prg_rom = vec![0; 0x10000];
+        rom.chr_rom = vec![0; 0x2000];
+        let bus = Bus::new(rom.clone());
+        assert_eq!(bus.region_of(0x7FFF), MemoryRegion::PrgRam { offset: 0x1FFF });
+
+        // NINA-001 has more CHR ROM, switched by the registers at 0x7FFE - 0x7FFF
+        rom.chr_rom = vec![0; 0x10000];
+        let bus = Bus::new(rom);
+        assert_eq!(bus.region_of(0x7FFC), MemoryRegion::PrgRam { offset: 0x1FFC });
+        for addr in 0x7FFD..=0x7FFF {
+            assert_eq!(bus.region_of(addr), MemoryRegion::MapperRegister { addr });
+        }
+        assert_eq!(bus.region_of(0x7FFD).to_string(), "Mapper register $7FFD");
+    }
+}
@@ -0,0 +1,732 @@
+use crate::bus::Bus;
+use crate::palette::Palette;
+use crate::ppu::accuracy::Accuracy;
+use crate::ppu::collisions::FrameCollisions;
+use crate::ppu::pattern_tables::PALETTE_RAM_SIZE;
+use crate::ppu::pixel_info::PixelInfo;
+use std::cell::Ref;
+use crate::cpu6502::{new_cpu, CPU};
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use crate::frame_pacer::FramePacer;
+use crate::frame_stats::{FrameStats, HostClock, PerformanceMonitor};
+use crate::input_provider::InputProvider;
+use crate::mapper::registry::MapperRegistry;
+use crate::ppu::render_thread::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::power_on::PowerOnConfig;
+use crate::recorder::{Recorder, RecorderOptions, RecordingFormat};
+use crate::rewind::Rewind;
+use crate::rom::Rom;
+use crate::savestate_slots::{SlotManager, SlotMetadata, Thumbnail, THUMBNAIL_SCALE};
+use crate::watch::WatchList;
+
+// Default rewind history: a snapshot every 2 frames, for about 20 seconds
+const REWIND_SNAPSHOTS: usize = 600;
+const REWIND_INTERVAL: u32 = 2;
+
+// The console: owns the CPU (and through its bus, the cartridge) for a whole session, so the
+// frontend can swap games, press reset or power cycle without being rebuilt.
+#[derive(Debug)]
+pub struct Nes {
+    pub cpu: CPU,
+    power_on_config: PowerOnConfig,
+    // Mappers the cartridges are created with, used again by `load_rom`
+    mappers: MapperRegistry,
+    // Polled at the start of each frame by `run_frame`; without it, the joypads are set directly
+    input: Option<Box<dyn InputProvider>>,
+    // Where the savestate slots of each game are stored (see savestate_slots.rs)
+    savestate_directory: PathBuf,
+    // Debugger watches, updated after each frame and each step
+    pub watches: WatchList,
+    // Statistics of the last frames, for the performance overlay
+    pub performance: PerformanceMonitor,
+    // Speed, pause and frame advance of `run_paced_frame`
+    pacer: FramePacer,
+    // Gameplay clip being recorded, fed with each frame run
+    recorder: Option<Recorder>,
+    // Snapshots of the last frames for `rewind`, taken by `run_frame`. None disables rewinding.
+    rewind: Option<Rewind>,
+}
+
+impl Nes {
+    // Inserts `rom` and powers the console on.
+    pub fn new(rom: Rom) -> Result<Self, String> {
+        Self::with_config(rom, PowerOnConfig::default())
+    }
+
+    pub fn with_config(rom: Rom, power_on_config: PowerOnConfig) -> Result<Self, String> {
+        Self::with_mappers(rom, power_on_config, MapperRegistry::builtin())
+    }
+
+    fn with_mappers(rom: Rom, power_on_config: PowerOnConfig, mappers: MapperRegistry) -> Result<Self, String> {
+        rom.check_validity_with(&mappers)?;
+        let mut nes = Self { cpu: new_cpu(Bus::with_mappers(rom, &mappers)), power_on_config, mappers, input: None, savestate_directory: PathBuf::from("saves"), watches: WatchList::new(), performance: PerformanceMonitor::new(), pacer: FramePacer::new(), recorder: None, rewind: Some(Rewind::new(REWIND_SNAPSHOTS, REWIND_INTERVAL)) };
+        nes.power_cycle()?;
+        Ok(nes)
+    }
+
+    pub fn rom(&self) -> &Rom {
+        self.cpu.bus.rom()
+    }
+
+    pub fn mappers(&self) -> &MapperRegistry {
+        &self.mappers
+    }
+
+    // RGB24 pixels (256x240) of the last complete frame
+    pub fn framebuffer(&self) -> Ref<'_, [u8]> {
+        Ref::map(self.cpu.bus.ppu(), |ppu| ppu.framebuffer())
+    }
+
+    // Palette used to convert the palette indexes to RGB, from the next complete frame
+    pub fn set_palette(&mut self, palette: Palette) {
+        self.cpu.bus.ppu_mut().set_palette(palette);
+    }
+
+    // Records the layer and palette entry of each pixel from the next scanline drawn, for the
+    // hover tooltips and hitbox views of the debugger (see ppu/pixel_info.rs).
+    pub fn set_pixel_info(&mut self, enabled: bool) {
+        self.cpu.bus.ppu_mut().pixel_info.set_enabled(enabled);
+    }
+
+    // Metadata of the pixel at (x, y), while the pixel info is enabled
+    pub fn pixel_info(&self, x: usize, y: usize) -> Option<PixelInfo> {
+        self.cpu.bus.ppu().pixel_info.pixel(x, y).copied()
+    }
+
+    // Records the sprite zero hits and sprite/background overlaps from the next pixel drawn
+    pub fn set_collisions(&mut self, enabled: bool) {
+        self.cpu.bus.ppu_mut().collisions.set_enabled(enabled);
+    }
+
+    // Collisions of the last complete frame, while recording them
+    pub fn collisions(&self) -> Ref<'_, FrameCollisions> {
+        Ref::map(self.cpu.bus.ppu(), |ppu| ppu.collisions.last_frame())
+    }
+
+    // CHR viewer: both pattern tables (256x128 pixels, RGB24) in palette `palette_index` (0 - 7)
+    pub fn render_pattern_tables(&self, palette_index: usize) -> Vec<u8> {
+        self.cpu.bus.render_pattern_tables(palette_index)
+    }
+
+    // Palette viewer: the 32 palette RAM entries
+    pub fn palette_ram(&self) -> [u8; PALETTE_RAM_SIZE] {
+        self.cpu.bus.ppu().palette_ram()
+    }
+
+    // Mappers registered here are used by the next `load_rom`.
+    pub fn mappers_mut(&mut self) -> &mut MapperRegistry {
+        &mut self.mappers
+    }
+
+    pub fn set_power_on_config(&mut self, power_on_config: PowerOnConfig) {
+        self.power_on_config = power_on_config;
+    }
+
+    pub fn set_input_provider(&mut self, input: Option<Box<dyn InputProvider>>) {
+        self.input = input;
+    }
+
+    // Runs one frame (see CPU::run_frame). The input provider is polled once, before the frame
+    // starts, so the game sees the same buttons for the whole frame.
+    pub fn run_frame(&mut self) -> u64 {
+        if let Some(input) = &mut self.input {
+            let states = input.poll();
+            let joypads = self.cpu.bus.joypads_mut();
+            for (player, buttons) in states.into_iter().enumerate() {
+                joypads.set_buttons(player, buttons);
+            }
+        }
+        let (frame, cycles, instructions) = (self.cpu.frame_number(), self.cpu.cycles, self.cpu.instructions);
+        let started = self.performance.now();
+        self.cpu.bus.ppu_mut().take_run_time();
+        let hash = self.cpu.run_frame();
+        // The PPU runs from the CPU accesses, its time is taken out of the CPU's
+        let ppu_time = self.cpu.bus.ppu_mut().take_run_time();
+        let frame_time = match (started, self.performance.now()) {
+            (Some(started), Some(ended)) => ended.saturating_sub(started),
+            _ => Duration::ZERO,
+        };
+        self.performance.record(FrameStats {
+            frame,
+            cpu_cycles: self.cpu.cycles - cycles,
+            instructions: self.cpu.instructions - instructions,
+            cpu_time: frame_time.saturating_sub(ppu_time),
+            ppu_time,
+            audio_buffer_fill: None,
+        });
+        self.watches.update(&self.cpu);
+        if let Some(recorder) = &mut self.recorder {
+            // Always 256x240, as the recorder was created with
+            let _ = recorder.push_frame(self.cpu.bus.ppu().framebuffer());
+        }
+        if let Some(rewind) = &mut self.rewind {
+            rewind.on_frame(&self.cpu);
+        }
+        hash
+    }
+
+    // Replaces the rewind history, e.g. with a longer one or a snapshot every frame. None stops
+    // taking snapshots.
+    pub fn set_rewind(&mut self, rewind: Option<Rewind>) {
+        self.rewind = rewind;
+    }
+
+    // Goes back about `frames` frames, for press-and-hold rewind: the frontend calls it once per
+    // frame while the hotkey is held, instead of `run_frame`. Returns the number of frames
+    // actually rewound (rounded down to the snapshot interval, and 0 once the history is used up).
+    pub fn rewind(&mut self, frames: u32) -> Result<u32, String> {
+        let rewind = self.rewind.as_mut().ok_or("Rewind is disabled")?;
+        let rewound = rewind.rewind(&mut self.cpu, frames)?;
+        self.watches.update(&self.cpu);
+        Ok(rewound)
+    }
+
+    // Starts recording a clip of the next frames. A clip being recorded is discarded.
+    pub fn start_recording(&mut self, format: RecordingFormat, options: RecorderOptions) -> Result<(), String> {
+        self.recorder = Some(Recorder::new(format, SCREEN_WIDTH, SCREEN_HEIGHT, options)?);
+        Ok(())
+    }
+
+    pub fn recording(&self) -> Option<&Recorder> {
+        self.recorder.as_ref()
+    }
+
+    // Stops the recording and saves the frames recorded so far to `path`.
+    pub fn stop_recording(&mut self, path: &str) -> Result<(), String> {
+        let recorder = self.recorder.take().ok_or("No recording in progress")?;
+        recorder.save(path)
+    }
+
+    // Runs the next frame when the pacer allows it: always, except while paused where only the
+    // frames requested by `frame_advance` run. Returns the hash of the frame, None if none was run.
+    // The frontend calls `wait_for_next_frame` between two calls to keep the speed.
+    pub fn run_paced_frame(&mut self) -> Option<u64> {
+        self.pacer.should_run_frame().then(|| self.run_frame())
+    }
+
+    pub fn wait_for_next_frame(&mut self) {
+        self.pacer.wait_for_next_frame();
+    }
+
+    // 1.0 is the normal speed, below is slow motion, above is fast-forward (see FramePacer::set_speed).
+    pub fn set_speed(&mut self, speed: f32) -> Result<(), String> {
+        self.pacer.set_speed(speed)
+    }
+
+    // Fast-forward as fast as the host allows, ignoring the speed
+    pub fn set_uncapped(&mut self, uncapped: bool) {
+        self.pacer.set_uncapped(uncapped);
+    }
+
+    pub fn pause(&mut self) {
+        self.pacer.pause();
+    }
+
+    pub fn resume(&mut self) {
+        self.pacer.resume();
+    }
+
+    // Runs exactly one more frame while paused.
+    pub fn frame_advance(&mut self) {
+        self.pacer.frame_advance();
+    }
+
+    pub fn pacer(&self) -> &FramePacer {
+        &self.pacer
+    }
+
+    // Runs a single instruction, for the debugger while the emulation is paused. Returns its cycles.
+    pub fn step(&mut self) -> u64 {
+        let cycles = self.cpu.step();
+        self.watches.update(&self.cpu);
+        cycles
+    }
+
+    pub fn set_savestate_directory(&mut self, directory: &str) {
+        self.savestate_directory = PathBuf::from(directory);
+    }
+
+    // Savestate of the whole console: CPU, interrupts, memories, cartridge, PPU, APU status,
+    // controller ports, VS System cabinet, Family BASIC keyboard and overclocking (see
+    // savestate.rs). The settings of the emulator itself (speed, palette, accuracy, watches,
+    // recording, input provider) are not part of it.
+    pub fn save_state(&self) -> Vec<u8> {
+        self.cpu.save_state()
+    }
+
+    // Restores a state produced by `save_state` on the same game. On error, the machine is left untouched.
+    pub fn load_state(&mut self, state: &[u8]) -> Result<(), String> {
+        self.cpu.load_state(state)
+    }
+
+    pub fn slots(&self) -> SlotManager {
+        SlotManager::new(&self.savestate_directory, self.rom())
+    }
+
+    // Saves the machine to `slot` (0 - 9) of the current game, with a thumbnail of the last frame.
+    pub fn save_slot(&self, slot: usize) -> Result<(), String> {
+        let thumbnail = Thumbnail::from_rgb(&self.framebuffer(), SCREEN_WIDTH, SCREEN_HEIGHT, THUMBNAIL_SCALE)?;
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |duration| duration.as_secs());
+        self.slots().save(slot, &self.save_state(), &SlotMetadata { timestamp, thumbnail })
+    }
+
+    // Restores `slot` of the current game. On error, the machine is left untouched.
+    pub fn load_slot(&mut self, slot: usize) -> Result<SlotMetadata, String> {
+        let (metadata, state) = self.slots().load(slot)?;
+        self.load_state(&state)?;
+        Ok(metadata)
+    }
+
+    // Swaps the cartridge. Like on the real console, this requires turning the power off:
+    // the whole machine starts from a cold boot with the new game. The emulator settings
+    // (overclocking, accuracy, render thread, PPU timing) are kept, but the labels belong to the previous game and are dropped.
+    // Battery saves of the previous game must be flushed by the caller before swapping.
+    // On error, the current game keeps running.
+    pub fn load_rom(&mut self, rom: Rom) -> Result<(), String> {
+        rom.check_validity_with(&self.mappers)?;
+        let mut cpu = new_cpu(Bus::with_mappers(rom, &self.mappers));
+        cpu.extra_scanlines = self.cpu.extra_scanlines;
+        cpu.bus.ppu_mut().runner.accuracy = self.accuracy();
+        cpu.bus.ppu_mut().output.set_render_thread(self.cpu.bus.ppu().output.has_render_thread());
+        cpu.bus.ppu_mut().run_clock = self.cpu.bus.ppu().run_clock;
+        cpu.power_on(&self.power_on_config)?;
+        self.cpu = cpu;
+        // The snapshots belong to the previous game
+        if let Some(rewind) = &mut self.rewind {
+            rewind.clear();
+        }
+        Ok(())
+    }
+
+    pub fn accuracy(&self) -> Accuracy {
+        self.cpu.bus.ppu().runner.accuracy
+    }
+
+    // Fast scanline renderer or cycle accurate one (the `accuracy` setting). Can be changed while
+    // running: a scanline in progress is finished dot by dot.
+    pub fn set_accuracy(&mut self, accuracy: Accuracy) {
+        self.cpu.bus.ppu_mut().runner.accuracy = accuracy;
+    }
+
+    // Splits the host time of each frame between the CPU and the PPU in the performance statistics,
+    // for the performance overlay. Off by default, since it reads the host clock each time the PPU
+    // is caught up: the whole frame is then counted as CPU time.
+    pub fn set_ppu_timing(&mut self, enabled: bool) {
+        self.cpu.bus.ppu_mut().run_clock = self.performance.clock().filter(|_| enabled);
+    }
+
+    // Host clock of the performance statistics. Native builds default to the system clock, while
+    // wasm32 has none until the JS bindings set the browser's: without one, no host time is measured.
+    pub fn set_host_clock(&mut self, clock: Option<HostClock>) {
+        let ppu_timing = self.cpu.bus.ppu().run_clock.is_some();
+        self.performance.set_clock(clock);
+        self.set_ppu_timing(ppu_timing);
+    }
+
+    // Converts the frames to RGB on a worker thread, in parallel with the emulation. The frames are
+    // the same as without it.
+    pub fn set_render_thread(&mut self, enabled: bool) {
+        self.cpu.bus.ppu_mut().output.set_render_thread(enabled);
+    }
+
+    // Reset button: the CPU jumps to the reset vector, registers and RAM keep their content
+    // (see CPU::reset), and the PPU ignores most register writes for about a frame (see
+    // Ppu::reset). The APU part of the reset is listed in the README roadmap.
+    pub fn reset(&mut self) {
+        self.cpu.sync_ppu(self.cpu.cycles);
+        self.cpu.bus.ppu_mut().reset();
+        self.cpu.reset();
+    }
+
+    // Power button pressed twice: RAM is lost, the machine boots as if it was just turned on.
+    pub fn power_cycle(&mut self) -> Result<(), String> {
+        self.cpu.power_on(&self.power_on_config)
+    }
+}
+
+// Builds a console with the options that must be known before it is powered on. With a seed,
+// every source of nondeterminism (see PowerOnConfig::seeded) is derived from it, so two consoles
+// built from the same ROM, seed and inputs produce identical traces, as netplay and TAS sync need.
+// Power cycles keep the seeded configuration, and give the same machine again.
+#[derive(Debug, Clone)]
+pub struct NesBuilder {
+    rom: Rom,
+    power_on_config: PowerOnConfig,
+    seed: Option<u64>,
+    mappers: MapperRegistry,
+}
+
+impl NesBuilder {
+    pub fn new(rom: Rom) -> Self {
+        Self { rom, power_on_config: PowerOnConfig::default(), seed: None, mappers: MapperRegistry::builtin() }
+    }
+
+    pub fn power_on_config(mut self, power_on_config: PowerOnConfig) -> Self {
+        self.power_on_config = power_on_config;
+        self
+    }
+
+    // Overrides the values of the power-on configuration that vary between consoles
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    // Replaces the built-in mappers, e.g. with `MapperRegistry::builtin` plus homebrew boards.
+    pub fn mappers(mut self, mappers: MapperRegistry) -> Self {
+        self.mappers = mappers;
+        self
+    }
+
+    pub fn build(self) -> Result<Nes, String> {
+        let power_on_config = match self.seed {
+            Some(seed) => self.power_on_config.seeded(seed),
+            None => self.power_on_config,
+        };
+        Nes::with_mappers(self.rom, power_on_config, self.mappers)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::audio::APU_SAMPLE_RATE;
+    use crate::family_keyboard::FamilyBasicKeyboard;
+    use crate::input_provider::ReplayInput;
+    use crate::loader::Loader;
+    use crate::nes::{Nes, NesBuilder};
+    use crate::palette::Palette;
+    use crate::ppu::accuracy::Accuracy;
+    use crate::ppu::pixel_info::PixelLayer;
+    use crate::power_on::{PowerOnConfig, RamFillPattern};
+    use crate::recorder::{RecorderOptions, RecordingFormat};
+    use crate::rom::Rom;
+
+    fn rom_with_reset_vector(reset_vector: u16) -> Rom {
+        let mut rom = Rom::test_rom();
+        let length = rom.prg_rom.len();
+        rom.prg_rom[length - 4] = reset_vector as u8;
+        rom.prg_rom[length - 3] = (reset_vector >> 8) as u8;
+        rom
+    }
+
+    #[test]
+    fn test_accuracy_setting() {
+        let mut fast = Nes::new(Rom::test_rom()).unwrap();
+        let mut cycle_accurate = Nes::new(Rom::test_rom()).unwrap();
+        cycle_accurate.set_accuracy(Accuracy::CycleAccurate);
+        cycle_accurate.load_rom(Rom::test_rom()).unwrap();
+        assert_eq!(cycle_accurate.accuracy(), Accuracy::CycleAccurate);
+        assert_eq!(fast.accuracy(), Accuracy::Fast);
+        for _ in 0..3 {
+            assert_eq!(fast.run_frame(), cycle_accurate.run_frame());
+        }
+        assert_eq!(*fast.framebuffer(), *cycle_accurate.framebuffer());
+    }
+
+    #[test]
+    fn test_render_thread_setting() {
+        let mut nes = Nes::new(Rom::test_rom()).unwrap();
+        let mut threaded = Nes::new(Rom::test_rom()).unwrap();
+        threaded.set_render_thread(true);
+        for nes in [&mut nes, &mut threaded] {
+            // Backdrop color
+            nes.cpu.write_u8(0x2006, 0x3F);
+            nes.cpu.write_u8(0x2006, 0x00);
+            nes.cpu.write_u8(0x2007, 0x16);
+            nes.run_frame();
+            nes.run_frame();
+        }
+        assert_eq!(*nes.framebuffer(), *threaded.framebuffer());
+        assert!(threaded.framebuffer().iter().any(|byte| *byte != 0));
+    }
+
+    #[test]
+    fn test_pixel_info_of_the_backdrop() {
+        let mut nes = Nes::new(Rom::test_rom()).unwrap();
+        assert_eq!(nes.pixel_info(0, 0), None);
+        nes.set_pixel_info(true);
+        nes.cpu.write_u8(0x2006, 0x3F);
+        nes.cpu.write_u8(0x2006, 0x00);
+        nes.cpu.write_u8(0x2007, 0x16);
+        // Rendering is disabled, the backdrop shows the palette entry at v
+        nes.cpu.write_u8(0x2006, 0x3F);
+        nes.cpu.write_u8(0x2006, 0x00);
+        nes.run_frame();
+        nes.run_frame();
+        let info = nes.pixel_info(255, 239).unwrap();
+        assert_eq!((info.layer, info.color), (PixelLayer::Backdrop, 0x16));
+        assert_eq!(nes.pixel_info(256, 0), None);
+    }
+
+    #[test]
+    fn test_pattern_table_and_palette_viewers() {
+        let mut rom = Rom::test_rom();
+        // Left half of tile 1 of the first pattern table in color 1
+        rom.chr_rom[0x10..0x18].fill(0xF0);
+        let mut nes = Nes::new(rom).unwrap();
+        nes.cpu.write_u8(0x2006, 0x3F);
+        nes.cpu.write_u8(0x2006, 0x00);
+        for color in [0x0F, 0x16] {
+            nes.cpu.write_u8(0x2007, color);
+        }
+        assert_eq!(nes.palette_ram()[..2], [0x0F, 0x16]);
+        assert_eq!(nes.palette_ram()[0x10], 0x0F);
+
+        let sheet = nes.render_pattern_tables(0);
+        assert_eq!(sheet.len(), 256 * 128 * 3);
+        let palette = Palette::default();
+        let pixel = |x: usize, y: usize| {
+            let start = (y * 256 + x) * 3;
+            (sheet[start], sheet[start + 1], sheet[start + 2])
+        };
+        assert_eq!(pixel(8, 0), palette.color(0x16, 0));
+        assert_eq!(pixel(12, 0), palette.color(0x0F, 0));
+        assert_eq!(pixel(128 + 8, 0), palette.color(0x0F, 0));
+    }
+
+    #[test]
+    fn test_recording() {
+        let path = std::env::temp_dir().join(format!("nes_recording_{}.gif", std::process::id()));
+        let path = path.to_str().unwrap();
+        let mut nes = Nes::new(Rom::test_rom()).unwrap();
+        assert!(nes.stop_recording(path).is_err());
+
+        let options = RecorderOptions { frame_count: 3, frame_skip: 1, scale: 2 };
+        nes.start_recording(RecordingFormat::Gif, options).unwrap();
+        for _ in 0..10 {
+            nes.run_frame();
+        }
+        let recorder = nes.recording().unwrap();
+        assert!(recorder.is_complete());
+        assert_eq!(recorder.output_size(), (512, 480));
+        nes.stop_recording(path).unwrap();
+        assert!(nes.recording().is_none());
+        assert_eq!(std::fs::read(path).unwrap()[..6], *b"GIF89a");
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_reset_keeps_ram() {
+        let mut nes = Nes::new(rom_with_reset_vector(0x8000)).unwrap();
+        assert_eq!(nes.cpu.program_counter, 0x8000);
+        nes.cpu.write_u8(0x0010, 0x42);
+        nes.cpu.program_counter = 0x9000;
+
+        nes.reset();
+        assert_eq!(nes.cpu.program_counter, 0x8000);
+        assert_eq!(nes.cpu.read_u8(0x0010), 0x42);
+
+        nes.power_cycle().unwrap();
+        assert_eq!(nes.cpu.read_u8(0x0010), 0x00);
+    }
+
+    #[test]
+    fn test_reset_ignores_ppu_writes_until_the_end_of_vblank() {
+        let mut nes = Nes::new(Rom::test_rom()).unwrap();
+        nes.cpu.write_u8(0x2000, 0x80);
+        nes.cpu.write_u8(0x2001, 0x1E);
+        nes.cpu.write_u8(0x2005, 0x12);
+        nes.reset();
+        let ppu = nes.cpu.bus.ppu();
+        assert_eq!((ppu.ctrl, ppu.mask, ppu.scroll.w), (0, 0, false));
+        drop(ppu);
+
+        nes.cpu.write_u8(0x2000, 0x80);
+        nes.cpu.write_u8(0x2001, 0x1E);
+        nes.cpu.write_u8(0x2006, 0x24);
+        // OAM is still written
+        nes.cpu.write_u8(0x2004, 0x43);
+        let ppu = nes.cpu.bus.ppu();
+        assert_eq!((ppu.ctrl, ppu.mask, ppu.scroll.t), (0, 0, 0));
+        assert_eq!(ppu.oam[0], 0x43);
+        drop(ppu);
+
+        // Until dot 1 of the pre-render scanline
+        while nes.cpu.bus.ppu().clock().scanline != 261 {
+            nes.step();
+            nes.cpu.sync_ppu(nes.cpu.cycles);
+        }
+        nes.step();
+        nes.cpu.write_u8(0x2001, 0x1E);
+        assert_eq!(nes.cpu.bus.ppu().mask, 0x1E);
+    }
+
+    #[test]
+    fn test_load_rom() {
+        let config = PowerOnConfig { ram_fill: RamFillPattern::AllOnes, ..PowerOnConfig::default() };
+        let mut nes = Nes::with_config(rom_with_reset_vector(0x8000), config).unwrap();
+        nes.cpu.write_u8(0x0010, 0x42);
+        nes.cpu.extra_scanlines = 20;
+
+        nes.load_rom(rom_with_reset_vector(0xC123)).unwrap();
+        assert_eq!(nes.cpu.program_counter, 0xC123);
+        assert_eq!(nes.rom().prg_rom[nes.rom().prg_rom.len() - 4], 0x23);
+        assert_eq!(nes.cpu.read_u8(0x0010), 0xFF);
+        assert_eq!(nes.cpu.extra_scanlines, 20);
+
+        // An invalid ROM is rejected and the current game keeps running
+        let mut invalid = rom_with_reset_vector(0x8000);
+        invalid.header.prg_rom_size = 3;
+        assert!(nes.load_rom(invalid).is_err());
+        assert_eq!(nes.cpu.program_counter, 0xC123);
+    }
+
+    #[test]
+    fn test_input_provider_polled_once_per_frame() {
+        let mut nes = Nes::new(Rom::test_rom()).unwrap();
+        nes.set_input_provider(Some(Box::new(ReplayInput::new(vec![[0x01, 0, 0, 0x80], [0x08, 0, 0, 0]]))));
+
+        nes.run_frame();
+        assert_eq!(nes.cpu.bus.joypads().buttons(0), 0x01);
+        assert_eq!(nes.cpu.bus.joypads().buttons(3), 0x80);
+        nes.run_frame();
+        assert_eq!(nes.cpu.bus.joypads().buttons(0), 0x08);
+        assert_eq!(nes.cpu.bus.joypads().buttons(3), 0);
+
+        // Without provider, the buttons are left as they are
+        nes.set_input_provider(None);
+        nes.cpu.bus.joypads_mut().set_buttons(1, 0x10);
+        nes.run_frame();
+        assert_eq!(nes.cpu.bus.joypads().buttons(1), 0x10);
+    }
+
+    #[test]
+    fn test_save_and_load_state() {
+        let mut rom = Rom::test_rom();
+        // VS Unisystem
+        rom.header.flags_7 = 0b0000_0001;
+        let mut nes = Nes::new(rom).unwrap();
+        nes.cpu.bus.set_family_keyboard(Some(FamilyBasicKeyboard::new()));
+        nes.cpu.extra_scanlines = 20;
+        nes.cpu.bus.vs_system_mut().unwrap().set_dip_switches(0b1010_0110);
+        nes.cpu.bus.vs_system_mut().unwrap().set_coin_inserted(1, true);
+        nes.cpu.bus.family_keyboard_mut().unwrap().set_key_pressed("F8", true).unwrap();
+        nes.cpu.write_u8(0x4015, 0x0F);
+        nes.run_frame();
+        // Keyboard enabled, row 0
+        nes.cpu.write_u8(0x4016, 0x05);
+        let state = nes.save_state();
+        let expected = (nes.cpu.read_u8(0x4016), nes.cpu.read_u8(0x4017), nes.cpu.bus.peek_u8(0x4015));
+
+        nes.cpu.extra_scanlines = 0;
+        nes.cpu.bus.vs_system_mut().unwrap().set_dip_switches(0);
+        nes.cpu.bus.vs_system_mut().unwrap().set_coin_inserted(1, false);
+        nes.cpu.bus.family_keyboard_mut().unwrap().release_all();
+        nes.cpu.write_u8(0x4015, 0x00);
+        nes.run_frame();
+        nes.load_state(&state).unwrap();
+
+        assert_eq!(nes.cpu.extra_scanlines, 20);
+        assert_eq!(nes.cpu.bus.vs_system().unwrap().dip_switches(), 0b1010_0110);
+        assert_eq!(nes.save_state(), state);
+        assert_eq!((nes.cpu.read_u8(0x4016), nes.cpu.read_u8(0x4017), nes.cpu.bus.peek_u8(0x4015)), expected);
+
+        // Another cartridge without the keyboard
+        nes.cpu.bus.set_family_keyboard(None);
+        assert!(nes.load_state(&state).unwrap_err().contains("keyboard"));
+    }
+
+    #[test]
+    fn test_rewind() {
+        let mut nes = Nes::new(Rom::test_rom()).unwrap();
+        for frame in 0..30 {
+            nes.cpu.write_u8(0x0010, frame);
+            nes.run_frame();
+        }
+        // A snapshot after every other frame, holding 0, 2... 28
+        assert_eq!(nes.rewind(10), Ok(10));
+        assert_eq!(nes.cpu.read_u8(0x0010), 18);
+        // Back to the first snapshot
+        assert_eq!(nes.rewind(100), Ok(18));
+        assert_eq!(nes.cpu.read_u8(0x0010), 0);
+        assert_eq!(nes.rewind(2), Ok(0));
+
+        // The history of the previous game is dropped
+        nes.run_frame();
+        nes.load_rom(Rom::test_rom()).unwrap();
+        assert!(nes.rewind(2).is_err());
+
+        nes.set_rewind(None);
+        nes.run_frame();
+        assert_eq!(nes.rewind(2), Err("Rewind is disabled".to_string()));
+    }
+
+    #[test]
+    fn test_savestate_slots() {
+        let directory = std::env::temp_dir().join(format!("nes_slots_{}", std::process::id()));
+        let mut nes = Nes::new(Rom::test_rom()).unwrap();
+        nes.set_savestate_directory(directory.to_str().unwrap());
+
+        // Backdrop color
+        nes.cpu.write_u8(0x2006, 0x3F);
+        nes.cpu.write_u8(0x2006, 0x00);
+        nes.cpu.write_u8(0x2007, 0x16);
+        nes.cpu.write_u8(0x2006, 0x3F);
+        nes.cpu.write_u8(0x2006, 0x00);
+        nes.run_frame();
+        nes.run_frame();
+        nes.cpu.write_u8(0x0010, 0x42);
+        nes.save_slot(1).unwrap();
+        nes.cpu.write_u8(0x0010, 0x00);
+
+        let metadata = nes.load_slot(1).unwrap();
+        assert_eq!(nes.cpu.read_u8(0x0010), 0x42);
+        assert_eq!((metadata.thumbnail.width, metadata.thumbnail.height), (64, 60));
+        assert!(metadata.thumbnail.rgb.chunks(3).all(|pixel| pixel == &nes.framebuffer()[..3]));
+        assert_ne!(metadata.thumbnail.rgb[..3], [0, 0, 0]);
+        assert!(metadata.timestamp > 0);
+
+        assert!(nes.load_slot(2).is_err());
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_builder_seed_is_deterministic() {
+        // LDA $10; LDX #$FF; XAA #$FF; STA $0300; JMP $C000: depends on the RAM and the magic constant
+        let program = [0xA5, 0x10, 0xA2, 0xFF, 0x8B, 0xFF, 0x8D, 0x00, 0x03, 0x4C, 0x00, 0xC0];
+        let rom = Loader::new(0xC000).build_rom(&program).unwrap();
+        let run = |seed: u64| {
+            let mut nes = NesBuilder::new(rom.clone()).seed(seed).build().unwrap();
+            let hashes: Vec<u64> = (0..3).map(|_| nes.run_frame()).collect();
+            (hashes, nes.cpu.magic_constant, nes.cpu.ppu_alignment)
+        };
+        assert_eq!(run(1234), run(1234));
+        assert_ne!(run(1234).0, run(4321).0);
+
+        // Without a seed, the configuration is used as is
+        let nes = NesBuilder::new(rom.clone()).build().unwrap();
+        assert_eq!((nes.cpu.magic_constant, nes.cpu.ppu_alignment), (0xFF, 0));
+        assert_eq!(nes.cpu.read_u8(0x0010), 0x00);
+
+        // A power cycle gives the same machine
+        let mut nes = NesBuilder::new(rom).seed(99).build().unwrap();
+        let first = nes.run_frame();
+        nes.power_cycle().unwrap();
+        assert_eq!(nes.run_frame(), first);
+    }
+
+    #[test]
+    fn test_speed_and_frame_advance() {
+        let mut nes = Nes::new(Rom::test_rom()).unwrap();
+        nes.set_speed(0.5).unwrap();
+        assert!(nes.set_speed(20.0).is_err());
+        assert_eq!(nes.pacer().speed(), 0.5);
+        assert_eq!(nes.pacer().audio_input_rate(), Some(APU_SAMPLE_RATE * 0.5));
+
+        assert!(nes.run_paced_frame().is_some());
+        nes.pause();
+        assert!(nes.run_paced_frame().is_none());
+        nes.frame_advance();
+        nes.frame_advance();
+        assert!(nes.run_paced_frame().is_some());
+        assert!(nes.run_paced_frame().is_some());
+        assert!(nes.run_paced_frame().is_none());
+        assert_eq!(nes.cpu.frame_number(), 3);
+        // Frames run by frame advance are recorded like the others
+        assert_eq!(nes.performance.frames().count(), 3);
+
+        nes.resume();
+        assert!(nes.run_paced_frame().is_some());
+    }
+}
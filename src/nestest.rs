@@ -0,0 +1,87 @@
+// Golden-file test: runs nestest.nes in automation mode (starting at 0xC000)
+// and compares every trace line against the canonical nestest.log.
+// More info about nestest can be found here: https://www.qmtpro.com/~nes/misc/nestest.txt
+
+use crate::bus::Bus;
+use crate::cpu6502::{new_cpu, trace};
+use crate::power_on::PowerOnConfig;
+use crate::rom::Rom;
+
+const NESTEST_ROM_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/nestest.nes");
+const NESTEST_LOG_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/nestest.log");
+
+// The APU and I/O registers (0x4000 - 0x401F) are not emulated yet. nestest writes to them
+// at the very end to silence the sound, so the value displayed for them ("$4015 = FF") is masked.
+fn mask_io_register_values(line: &str) -> String {
+    match line.find(" $40") {
+        Some(start) if line[start..].starts_with(" $401") || line[start..].starts_with(" $400") => {
+            let value_start = start + " $40XX = ".len();
+            if line[start + " $40XX".len()..].starts_with(" = ") && line.len() >= value_start + 2 {
+                return format!("{}??{}", &line[..value_start], &line[value_start + 2..]);
+            }
+            line.to_string()
+        }
+        _ => line.to_string(),
+    }
+}
+
+#[test]
+fn test_nestest_matches_golden_log() {
+    let rom_data = std::fs::read(NESTEST_ROM_PATH).expect("Failed to read nestest.nes");
+    let rom = Rom::parse_nes_rom(rom_data).expect("Failed to parse nestest.nes");
+    let golden_log = std::fs::read_to_string(NESTEST_LOG_PATH).expect("Failed to read nestest.log");
+
+    let mut cpu = new_cpu(Bus::new(rom));
+    cpu.power_on(&PowerOnConfig::default()).unwrap();
+    // Automation mode: nestest runs all the tests without a PPU when started at 0xC000
+    cpu.program_counter = 0xC000;
+
+    let mut previous_line = String::new();
+    for (index, expected_line) in golden_log.lines().enumerate() {
+        assert!(!cpu.halted, "CPU halted before line {} of nestest.log:\n  expected: {}", index + 1, expected_line);
+
+        let actual_line = trace(&cpu);
+        let expected = mask_io_register_values(expected_line);
+        let actual = mask_io_register_values(&actual_line);
+        assert!(
+            expected == actual,
+            "nestest diverged at line {}\n  previous: {}\n  expected: {}\n  actual:   {}",
+            index + 1, previous_line, expected, actual
+        );
+
+        previous_line = actual;
+        cpu.step();
+    }
+
+    // nestest stores the error codes of the official and unofficial opcode tests at 0x02 and 0x03
+    assert_eq!(cpu.read_u8(0x0002), 0x00, "nestest reported an error for official opcodes");
+    assert_eq!(cpu.read_u8(0x0003), 0x00, "nestest reported an error for unofficial opcodes");
+}
+
+// nestest does not read the PPU, so its results are the same with every CPU/PPU alignment (only
+// the PPU column of the trace moves)
+#[test]
+fn test_nestest_with_every_ppu_alignment() {
+    let rom_data = std::fs::read(NESTEST_ROM_PATH).expect("Failed to read nestest.nes");
+    let steps = std::fs::read_to_string(NESTEST_LOG_PATH).expect("Failed to read nestest.log").lines().count();
+    for ppu_alignment in 0..4 {
+        let rom = Rom::parse_nes_rom(rom_data.clone()).expect("Failed to parse nestest.nes");
+        let mut cpu = new_cpu(Bus::new(rom));
+        cpu.power_on(&PowerOnConfig { ppu_alignment, ..PowerOnConfig::default() }).unwrap();
+        cpu.program_counter = 0xC000;
+        for _ in 0..steps {
+            cpu.step();
+        }
+        assert_eq!((cpu.read_u8(0x0002), cpu.read_u8(0x0003)), (0x00, 0x00), "alignment {}", ppu_alignment);
+    }
+}
+
+#[test]
+fn test_mask_io_register_values() {
+    assert_eq!(
+        mask_io_register_values("C68B  8D 15 40  STA $4015 = FF    A:02"),
+        "C68B  8D 15 40  STA $4015 = ??    A:02"
+    );
+    assert_eq!(mask_io_register_values("C5F7  86 00     STX $00 = 00"), "C5F7  86 00     STX $00 = 00");
+    assert_eq!(mask_io_register_values("0000  AD 00 40  LDA $4020 = 00"), "0000  AD 00 40  LDA $4020 = 00");
+}
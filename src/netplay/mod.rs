@@ -0,0 +1,287 @@
+use std::collections::VecDeque;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use crate::input_provider::{ControllerStates, InputProvider};
+use crate::rom::Rom;
+
+pub mod rollback;
+
+// Two player netplay in lockstep: each instance runs the whole game and only the inputs are
+// exchanged, which works because the core is deterministic for a given ROM and input sequence.
+// The host is player 1, the client player 2. The buttons pressed on frame N are played on frame
+// N + input delay, which leaves that many frames for them to reach the other side before it has
+// to wait. The first frames are played without input.
+// Messages are sent over TCP (ordered and reliable, so inputs arrive in frame order):
+// - 'H': protocol version (u8), emulator version (u8 length + UTF-8), SHA-1 of the ROM (20 bytes),
+//   input delay (u8, the host's one is used by both sides),
+// - 'I': frame (u64 LE), buttons (u8).
+// See rollback.rs for the version that does not wait for the remote inputs.
+
+pub const DEFAULT_INPUT_DELAY: usize = 2;
+const PROTOCOL_VERSION: u8 = 1;
+const HELLO: u8 = b'H';
+const INPUT: u8 = b'I';
+
+#[derive(Debug, Clone, PartialEq)]
+struct Hello {
+    emulator_version: String,
+    rom_sha1: [u8; 20],
+    input_delay: u8,
+}
+
+#[derive(Debug)]
+pub struct NetplaySession {
+    stream: TcpStream,
+    // Player of this instance (0 for the host, 1 for the client)
+    local_player: usize,
+    input_delay: usize,
+    // Next frame to play
+    frame: u64,
+    // Buttons of the next frames, already sent or received
+    local_inputs: VecDeque<u8>,
+    remote_inputs: VecDeque<u8>,
+    // Input of the local player, read from player 1 of this provider
+    local: Option<Box<dyn InputProvider>>,
+    // First error, once the session is broken
+    error: Option<String>,
+}
+
+impl NetplaySession {
+    // Waits for a client on `listener` and plays as player 1.
+    pub fn host(listener: &TcpListener, rom: &Rom, input_delay: usize) -> Result<Self, String> {
+        Self::start(accept(listener)?, rom, 0, input_delay)
+    }
+
+    // Joins a host and plays as player 2, with the input delay of the host.
+    pub fn connect<A: ToSocketAddrs>(address: A, rom: &Rom) -> Result<Self, String> {
+        Self::start(connect(address)?, rom, 1, 0)
+    }
+
+    fn start(mut stream: TcpStream, rom: &Rom, local_player: usize, input_delay: usize) -> Result<Self, String> {
+        let input_delay = handshake(&mut stream, rom, local_player, input_delay)?;
+        Ok(Self {
+            stream,
+            local_player,
+            input_delay,
+            frame: 0,
+            local_inputs: VecDeque::from(vec![0; input_delay]),
+            remote_inputs: VecDeque::from(vec![0; input_delay]),
+            local: None,
+            error: None,
+        })
+    }
+
+    pub fn local_player(&self) -> usize {
+        self.local_player
+    }
+
+    pub fn input_delay(&self) -> usize {
+        self.input_delay
+    }
+
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    pub fn set_local_input(&mut self, local: Option<Box<dyn InputProvider>>) {
+        self.local = local;
+    }
+
+    // Set when the session broke while polling; the frontend has to stop the game.
+    pub fn error(&self) -> Option<&str> {
+        self.error.as_deref()
+    }
+
+    // Sends the local buttons of this frame and returns the buttons of both players for the frame
+    // to play. Blocks until the remote input of that frame arrives.
+    pub fn advance(&mut self, local_buttons: u8) -> Result<ControllerStates, String> {
+        send_input(&mut self.stream, self.frame + self.input_delay as u64, local_buttons)?;
+        self.local_inputs.push_back(local_buttons);
+
+        if self.remote_inputs.is_empty() {
+            let (frame, buttons) = receive_input(&mut self.stream)?;
+            if frame != self.frame {
+                return Err(format!("Netplay: Received the input of frame {} instead of frame {}", frame, self.frame));
+            }
+            self.remote_inputs.push_back(buttons);
+        }
+
+        let mut states = [0; 4];
+        states[self.local_player] = self.local_inputs.pop_front().unwrap_or(0);
+        states[1 - self.local_player] = self.remote_inputs.pop_front().unwrap_or(0);
+        self.frame += 1;
+        Ok(states)
+    }
+}
+
+fn accept(listener: &TcpListener) -> Result<TcpStream, String> {
+    let (stream, _) = listener.accept().map_err(|error| format!("Netplay: Failed to accept a connection: {}", error))?;
+    Ok(stream)
+}
+
+fn connect<A: ToSocketAddrs>(address: A) -> Result<TcpStream, String> {
+    TcpStream::connect(address).map_err(|error| format!("Netplay: Failed to connect: {}", error))
+}
+
+// Exchanges the 'H' messages and returns the input delay of the session (the host's one).
+fn handshake(stream: &mut TcpStream, rom: &Rom, local_player: usize, input_delay: usize) -> Result<usize, String> {
+    // Inputs are tiny and latency matters more than throughput
+    stream.set_nodelay(true).map_err(connection_error)?;
+    let hello = Hello {
+        emulator_version: env!("CARGO_PKG_VERSION").to_string(),
+        rom_sha1: rom.sha1(),
+        input_delay: input_delay.min(u8::MAX as usize) as u8,
+    };
+    send_hello(stream, &hello)?;
+    let remote = receive_hello(stream)?;
+    if remote.emulator_version != hello.emulator_version {
+        return Err(format!("Netplay: Emulator version mismatch ({} here, {} on the other side)", hello.emulator_version, remote.emulator_version));
+    }
+    if remote.rom_sha1 != hello.rom_sha1 {
+        return Err("Netplay: Both sides must load the same ROM".to_string());
+    }
+    Ok(if local_player == 0 { hello.input_delay } else { remote.input_delay } as usize)
+}
+
+fn send_hello(stream: &mut TcpStream, hello: &Hello) -> Result<(), String> {
+    let version = &hello.emulator_version.as_bytes()[..hello.emulator_version.len().min(u8::MAX as usize)];
+    let mut message = vec![HELLO, PROTOCOL_VERSION, version.len() as u8];
+    message.extend_from_slice(version);
+    message.extend_from_slice(&hello.rom_sha1);
+    message.push(hello.input_delay);
+    stream.write_all(&message).map_err(connection_error)
+}
+
+fn receive_hello(stream: &mut TcpStream) -> Result<Hello, String> {
+    let [kind, protocol, version_length] = read_array(stream)?;
+    if kind != HELLO {
+        return Err("Netplay: Expected a handshake".to_string());
+    }
+    if protocol != PROTOCOL_VERSION {
+        return Err(format!("Netplay: Unsupported protocol version {} (expected {})", protocol, PROTOCOL_VERSION));
+    }
+    let mut version = vec![0; version_length as usize];
+    stream.read_exact(&mut version).map_err(connection_error)?;
+    let rom_sha1 = read_array(stream)?;
+    let [input_delay] = read_array(stream)?;
+    Ok(Hello { emulator_version: String::from_utf8_lossy(&version).to_string(), rom_sha1, input_delay })
+}
+
+fn send_input(stream: &mut TcpStream, frame: u64, buttons: u8) -> Result<(), String> {
+    let mut message = vec![INPUT];
+    message.extend_from_slice(&frame.to_le_bytes());
+    message.push(buttons);
+    stream.write_all(&message).map_err(connection_error)
+}
+
+fn receive_input(stream: &mut TcpStream) -> Result<(u64, u8), String> {
+    let [kind] = read_array(stream)?;
+    if kind != INPUT {
+        return Err(format!("Netplay: Unexpected message type {:#04X}", kind));
+    }
+    let frame = u64::from_le_bytes(read_array(stream)?);
+    let [buttons] = read_array(stream)?;
+    Ok((frame, buttons))
+}
+
+fn read_array<const N: usize>(stream: &mut TcpStream) -> Result<[u8; N], String> {
+    let mut buffer = [0; N];
+    stream.read_exact(&mut buffer).map_err(connection_error)?;
+    Ok(buffer)
+}
+
+fn connection_error(error: std::io::Error) -> String {
+    format!("Netplay: Connection lost: {}", error)
+}
+
+// Polled once per frame by Nes::run_frame like any input. After an error, no button is pressed.
+impl InputProvider for NetplaySession {
+    fn poll(&mut self) -> ControllerStates {
+        if self.error.is_some() {
+            return [0; 4];
+        }
+        let local_buttons = self.local.as_mut().map_or(0, |local| local.poll()[0]);
+        self.advance(local_buttons).unwrap_or_else(|error| {
+            self.error = Some(error);
+            [0; 4]
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+    use std::sync::{Arc, Barrier};
+    use crate::input_provider::ReplayInput;
+    use crate::netplay::{NetplaySession, DEFAULT_INPUT_DELAY};
+    use crate::nes::Nes;
+    use crate::rom::Rom;
+
+    // The sides can be up to `input delay` frames apart: the one finishing first waits for the
+    // other before closing the connection.
+    #[test]
+    fn test_lockstep_inputs() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let end = Arc::new(Barrier::new(2));
+        let client_end = end.clone();
+        let client = std::thread::spawn(move || {
+            let mut session = NetplaySession::connect(address, &Rom::test_rom()).unwrap();
+            assert_eq!(session.input_delay(), 3);
+            let states = (0..6).map(|frame| session.advance(0x10 + frame).unwrap()).collect::<Vec<_>>();
+            client_end.wait();
+            states
+        });
+
+        let mut host = NetplaySession::host(&listener, &Rom::test_rom(), 3).unwrap();
+        let host_states: Vec<_> = (0..6).map(|frame| host.advance(0x20 + frame).unwrap()).collect();
+        end.wait();
+        let client_states = client.join().unwrap();
+
+        // Both sides play the same inputs, delayed by 3 frames
+        assert_eq!(host_states, client_states);
+        assert_eq!(host_states[2], [0, 0, 0, 0]);
+        assert_eq!(host_states[3], [0x20, 0x10, 0, 0]);
+        assert_eq!(host_states[5], [0x22, 0x12, 0, 0]);
+        assert_eq!(host.frame(), 6);
+    }
+
+    fn run_game(mut session: NetplaySession, buttons: u8, end: Arc<Barrier>) -> Vec<(u64, u8, u8)> {
+        session.set_local_input(Some(Box::new(ReplayInput::new(vec![[buttons, 0, 0, 0]; 10]))));
+        let mut nes = Nes::new(Rom::test_rom()).unwrap();
+        nes.set_input_provider(Some(Box::new(session)));
+        let frames = (0..10)
+            .map(|_| (nes.run_frame(), nes.cpu.bus.joypads().buttons(0), nes.cpu.bus.joypads().buttons(1)))
+            .collect();
+        end.wait();
+        frames
+    }
+
+    #[test]
+    fn test_games_stay_in_sync() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let end = Arc::new(Barrier::new(2));
+        let client_end = end.clone();
+        let client = std::thread::spawn(move || {
+            run_game(NetplaySession::connect(address, &Rom::test_rom()).unwrap(), 0x08, client_end)
+        });
+        let host = run_game(NetplaySession::host(&listener, &Rom::test_rom(), DEFAULT_INPUT_DELAY).unwrap(), 0x01, end);
+
+        assert_eq!(host, client.join().unwrap());
+        assert_eq!((host[9].1, host[9].2), (0x01, 0x08));
+    }
+
+    #[test]
+    fn test_handshake_rejects_other_rom() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let client = std::thread::spawn(move || {
+            let mut rom = Rom::test_rom();
+            rom.prg_rom[0] = 0x00;
+            NetplaySession::connect(address, &rom).map(|_| ())
+        });
+        assert!(NetplaySession::host(&listener, &Rom::test_rom(), 2).is_err());
+        assert!(client.join().unwrap().is_err());
+    }
+}
@@ -0,0 +1,350 @@
+use std::collections::VecDeque;
+use std::net::{Shutdown, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::mpsc::{channel, Receiver, TryRecvError};
+use crate::input_provider::ControllerStates;
+use crate::netplay::{accept, connect, handshake, receive_input, send_input};
+use crate::nes::Nes;
+use crate::rom::Rom;
+
+// Rollback netplay (GGPO style), on top of the lockstep protocol of mod.rs: instead of waiting
+// for the remote input of a frame, the frame is played right away with a prediction (the last
+// remote input received). The state before each predicted frame is saved; when the real input
+// arrives and differs from the prediction, that state is loaded and the frames since are played
+// again with the corrected inputs.
+// This needs savestates and frames cheap enough to redo several frames within one (see the
+// savestate and frame benches: both are well under 1 ms). The frames played again are not
+// presented: once there is a PPU and an APU, only the last one of an advance must reach the
+// screen and the speakers.
+
+// How many frames the local side may run ahead of the last confirmed remote input before it
+// waits, which bounds the number of frames played again by a rollback.
+pub const DEFAULT_MAX_PREDICTION: usize = 8;
+
+#[derive(Debug)]
+struct PlayedFrame {
+    // State of the machine before the frame
+    state: Vec<u8>,
+    inputs: ControllerStates,
+}
+
+#[derive(Debug)]
+pub struct RollbackSession {
+    stream: TcpStream,
+    // Remote inputs, read by a thread so that they can be checked without blocking
+    received: Receiver<Result<(u64, u8), String>>,
+    local_player: usize,
+    input_delay: usize,
+    max_prediction: usize,
+    // Next frame to play
+    frame: u64,
+    // Buttons of the next local frames, already sent
+    local_inputs: VecDeque<u8>,
+    // First frame whose remote input has not been received
+    confirmed_frame: u64,
+    // Remote inputs received for the frames not played yet (from `frame` to `confirmed_frame`)
+    remote_inputs: VecDeque<u8>,
+    // Last remote input received, used as the prediction
+    last_remote_input: u8,
+    // Frames played from `confirmed_frame` (or `frame`, if ahead) to `frame`, oldest first
+    history: VecDeque<PlayedFrame>,
+    // Earliest frame played with a wrong prediction, since the last advance
+    mispredicted_frame: Option<u64>,
+    rollbacks: u64,
+    replayed_frames: u64,
+}
+
+impl RollbackSession {
+    // Waits for a client on `listener` and plays as player 1.
+    pub fn host(listener: &TcpListener, rom: &Rom, input_delay: usize, max_prediction: usize) -> Result<Self, String> {
+        Self::start(accept(listener)?, rom, 0, input_delay, max_prediction)
+    }
+
+    // Joins a host and plays as player 2, with the input delay of the host.
+    pub fn connect<A: ToSocketAddrs>(address: A, rom: &Rom, max_prediction: usize) -> Result<Self, String> {
+        Self::start(connect(address)?, rom, 1, 0, max_prediction)
+    }
+
+    fn start(mut stream: TcpStream, rom: &Rom, local_player: usize, input_delay: usize, max_prediction: usize) -> Result<Self, String> {
+        let input_delay = handshake(&mut stream, rom, local_player, input_delay)?;
+        let mut reader = stream.try_clone().map_err(|error| format!("Netplay: Failed to read the connection: {}", error))?;
+        let (sender, received) = channel();
+        std::thread::spawn(move || loop {
+            let input = receive_input(&mut reader);
+            let failed = input.is_err();
+            if sender.send(input).is_err() || failed {
+                break;
+            }
+        });
+        Ok(Self {
+            stream,
+            received,
+            local_player,
+            input_delay,
+            max_prediction: max_prediction.max(1),
+            frame: 0,
+            local_inputs: VecDeque::from(vec![0; input_delay]),
+            confirmed_frame: input_delay as u64,
+            remote_inputs: VecDeque::from(vec![0; input_delay]),
+            last_remote_input: 0,
+            history: VecDeque::new(),
+            mispredicted_frame: None,
+            rollbacks: 0,
+            replayed_frames: 0,
+        })
+    }
+
+    pub fn local_player(&self) -> usize {
+        self.local_player
+    }
+
+    pub fn input_delay(&self) -> usize {
+        self.input_delay
+    }
+
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    // Number of frames played with a predicted remote input
+    pub fn predicted_frames(&self) -> u64 {
+        self.frame.saturating_sub(self.confirmed_frame)
+    }
+
+    pub fn rollbacks(&self) -> u64 {
+        self.rollbacks
+    }
+
+    pub fn replayed_frames(&self) -> u64 {
+        self.replayed_frames
+    }
+
+    // Sends the local buttons of this frame and plays the next frame on `nes`, rolling back first
+    // if a prediction turned out wrong. Only blocks when `max_prediction` frames are predicted.
+    // Returns the buttons the frame was played with.
+    pub fn advance(&mut self, nes: &mut Nes, local_buttons: u8) -> Result<ControllerStates, String> {
+        send_input(&mut self.stream, self.frame + self.input_delay as u64, local_buttons)?;
+        self.local_inputs.push_back(local_buttons);
+
+        while let Some(input) = self.try_receive()? {
+            self.confirm(input)?;
+        }
+        while self.predicted_frames() >= self.max_prediction as u64 {
+            let input = self.receive()?;
+            self.confirm(input)?;
+        }
+        self.roll_back(nes)?;
+
+        let mut inputs = [0; 4];
+        inputs[self.local_player] = self.local_inputs.pop_front().unwrap_or(0);
+        inputs[1 - self.local_player] = match self.remote_inputs.pop_front() {
+            Some(buttons) => buttons,
+            None => self.last_remote_input,
+        };
+        if self.frame >= self.confirmed_frame {
+            self.history.push_back(PlayedFrame { state: nes.cpu.save_state(), inputs });
+        }
+        play(nes, inputs);
+        self.frame += 1;
+        Ok(inputs)
+    }
+
+    // Waits for the remote inputs of every frame played and corrects the game, so that both sides
+    // end up in the same state (e.g. before saving or comparing the games).
+    pub fn synchronize(&mut self, nes: &mut Nes) -> Result<(), String> {
+        while self.confirmed_frame < self.frame {
+            let input = self.receive()?;
+            self.confirm(input)?;
+        }
+        self.roll_back(nes)
+    }
+
+    fn try_receive(&mut self) -> Result<Option<(u64, u8)>, String> {
+        match self.received.try_recv() {
+            Ok(input) => input.map(Some),
+            Err(TryRecvError::Empty) => Ok(None),
+            Err(TryRecvError::Disconnected) => Err("Netplay: Connection lost".to_string()),
+        }
+    }
+
+    fn receive(&mut self) -> Result<(u64, u8), String> {
+        self.received.recv().map_err(|_| "Netplay: Connection lost".to_string())?
+    }
+
+    fn confirm(&mut self, (frame, buttons): (u64, u8)) -> Result<(), String> {
+        if frame != self.confirmed_frame {
+            return Err(format!("Netplay: Received the input of frame {} instead of frame {}", frame, self.confirmed_frame));
+        }
+        if frame < self.frame {
+            let index = self.history.len() - (self.frame - frame) as usize;
+            let played = &mut self.history[index].inputs[1 - self.local_player];
+            if *played != buttons {
+                *played = buttons;
+                self.mispredicted_frame.get_or_insert(frame);
+            }
+        } else {
+            self.remote_inputs.push_back(buttons);
+        }
+        self.last_remote_input = buttons;
+        self.confirmed_frame += 1;
+        Ok(())
+    }
+
+    // Plays again the frames since the first wrong prediction, then forgets the confirmed frames.
+    fn roll_back(&mut self, nes: &mut Nes) -> Result<(), String> {
+        let first_frame = self.frame - self.history.len() as u64;
+        if let Some(frame) = self.mispredicted_frame.take() {
+            let start = (frame - first_frame) as usize;
+            nes.cpu.load_state(&self.history[start].state)?;
+            for (index, played) in self.history.iter_mut().enumerate().skip(start) {
+                if first_frame + index as u64 >= self.confirmed_frame {
+                    played.inputs[1 - self.local_player] = self.last_remote_input;
+                }
+                if index != start {
+                    played.state = nes.cpu.save_state();
+                }
+                play(nes, played.inputs);
+            }
+            self.rollbacks += 1;
+            self.replayed_frames += (self.history.len() - start) as u64;
+        }
+
+        let confirmed = self.confirmed_frame.min(self.frame).saturating_sub(first_frame) as usize;
+        self.history.drain(..confirmed);
+        Ok(())
+    }
+}
+
+impl Drop for RollbackSession {
+    // Stops the reading thread
+    fn drop(&mut self) {
+        let _ = self.stream.shutdown(Shutdown::Both);
+    }
+}
+
+fn play(nes: &mut Nes, inputs: ControllerStates) {
+    let joypads = nes.cpu.bus.joypads_mut();
+    for (player, buttons) in inputs.into_iter().enumerate() {
+        joypads.set_buttons(player, buttons);
+    }
+    nes.cpu.run_frame();
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+    use std::sync::{Arc, Barrier};
+    use std::time::Duration;
+    use crate::input_provider::ControllerStates;
+    use crate::netplay::rollback::{RollbackSession, DEFAULT_MAX_PREDICTION};
+    use crate::nes::Nes;
+    use crate::rom::Rom;
+
+    // Reads the joypads every loop and sums the buttons into $12, so the state depends on every input
+    const JOYPAD_LOOP: [u8; 32] = [
+        0xA9, 0x01, 0x8D, 0x16, 0x40, // LDA #$01; STA $4016
+        0xA9, 0x00, 0x8D, 0x16, 0x40, // LDA #$00; STA $4016
+        0xA2, 0x08,                   // LDX #$08
+        0xAD, 0x16, 0x40,             // LDA $4016
+        0x4A, 0x26, 0x10,             // LSR A; ROL $10
+        0xCA, 0xD0, 0xF7,             // DEX; BNE $800C
+        0xE6, 0x11,                   // INC $11
+        0xA5, 0x10, 0x65, 0x12,       // LDA $10; ADC $12
+        0x85, 0x12,                   // STA $12
+        0x4C, 0x00, 0x80,             // JMP $8000
+    ];
+
+    fn joypad_rom() -> Rom {
+        let mut rom = Rom::test_rom();
+        rom.prg_rom[..JOYPAD_LOOP.len()].copy_from_slice(&JOYPAD_LOOP);
+        let length = rom.prg_rom.len();
+        rom.prg_rom[length - 4] = 0x00;
+        rom.prg_rom[length - 3] = 0x80;
+        rom
+    }
+
+    // Plays 30 frames pressing a different button every 4 frames, then synchronizes.
+    // Returns the inputs of every frame and the final state.
+    fn run_game(mut session: RollbackSession, buttons: u8, end: Arc<Barrier>) -> (Vec<ControllerStates>, Vec<u8>, u64) {
+        let mut nes = Nes::new(joypad_rom()).unwrap();
+        for frame in 0..30 {
+            session.advance(&mut nes, buttons << (frame / 4 % 4)).unwrap();
+        }
+        session.synchronize(&mut nes).unwrap();
+        assert_eq!(session.predicted_frames(), 0);
+        end.wait();
+        let inputs = (0..30).map(|frame| expected_inputs(frame, session.input_delay())).collect();
+        (inputs, nes.cpu.save_state(), session.rollbacks())
+    }
+
+    fn expected_inputs(frame: usize, input_delay: usize) -> ControllerStates {
+        if frame < input_delay {
+            return [0; 4];
+        }
+        let shift = (frame - input_delay) / 4 % 4;
+        [0x01 << shift, 0x10 << shift, 0, 0]
+    }
+
+    // The game played without netplay, with the inputs both sides ended up with
+    fn local_game(inputs: &[ControllerStates]) -> Vec<u8> {
+        let mut nes = Nes::new(joypad_rom()).unwrap();
+        for states in inputs {
+            let joypads = nes.cpu.bus.joypads_mut();
+            for (player, buttons) in states.iter().enumerate() {
+                joypads.set_buttons(player, *buttons);
+            }
+            nes.cpu.run_frame();
+        }
+        nes.cpu.save_state()
+    }
+
+    #[test]
+    fn test_rollback_corrects_predictions() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let end = Arc::new(Barrier::new(2));
+        let client_end = end.clone();
+        let client = std::thread::spawn(move || {
+            let session = RollbackSession::connect(address, &joypad_rom(), DEFAULT_MAX_PREDICTION).unwrap();
+            // Late start: the host has to predict (wrongly) the first inputs of the client
+            std::thread::sleep(Duration::from_millis(200));
+            let (_, state, _) = run_game(session, 0x10, client_end);
+            state
+        });
+        let host = RollbackSession::host(&listener, &joypad_rom(), 1, DEFAULT_MAX_PREDICTION).unwrap();
+        let (inputs, host_state, host_rollbacks) = run_game(host, 0x01, end);
+        let client_state = client.join().unwrap();
+
+        assert!(host_rollbacks > 0);
+        assert_eq!(host_state, client_state);
+        assert_eq!(host_state, local_game(&inputs));
+    }
+
+    #[test]
+    fn test_prediction_is_bounded() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let end = Arc::new(Barrier::new(2));
+        let client_end = end.clone();
+        let client = std::thread::spawn(move || {
+            let mut session = RollbackSession::connect(address, &joypad_rom(), 3).unwrap();
+            let mut nes = Nes::new(joypad_rom()).unwrap();
+            client_end.wait();
+            std::thread::sleep(Duration::from_millis(100));
+            for _ in 0..6 {
+                session.advance(&mut nes, 0x10).unwrap();
+            }
+            client_end.wait();
+        });
+        let mut host = RollbackSession::host(&listener, &joypad_rom(), 0, 3).unwrap();
+        let mut nes = Nes::new(joypad_rom()).unwrap();
+        end.wait();
+        // The client is not playing yet: the host stops after 3 predicted frames
+        for _ in 0..6 {
+            host.advance(&mut nes, 0x01).unwrap();
+            assert!(host.predicted_frames() <= 3);
+        }
+        end.wait();
+        client.join().unwrap();
+        assert!(host.replayed_frames() >= 3);
+    }
+}
@@ -0,0 +1,56 @@
+use crate::cpu6502::CPU;
+
+// Writing a page number to 0x4014 copies that page (0xXX00 - 0xXXFF) to OAM through OAMDATA.
+// The CPU is halted during the copy: one cycle to halt (plus one to align on a read cycle when the
+// DMA starts on an odd cycle), then 256 reads alternating with 256 writes, so 513 or 514 cycles.
+// Games do it once per frame during vblank, it is much faster than writing OAMDATA in a loop.
+
+impl CPU {
+    pub fn oam_dma(&mut self, page: u8) {
+        let halt_cycles = 1 + self.bus.access_cycle() % 2;
+        self.bus.skip_cycles(halt_cycles);
+        for offset in 0..=0xFF {
+            let value = self.read_u8((page as u16) << 8 | offset);
+            // Not recorded in the event log, unlike the CPU writes of OAMDATA
+            self.sync_ppu_for_access(0x2004, true);
+            self.bus.write_u8(0x2004, value);
+        }
+        self.cycles += halt_cycles + 512;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bus::Bus;
+    use crate::cpu6502::new_cpu;
+    use crate::rom::Rom;
+
+    #[test]
+    fn test_oam_dma_copies_page() {
+        let mut cpu = new_cpu(Bus::new(Rom::test_rom()));
+        for offset in 0..=0xFF {
+            cpu.write_u8(0x0200 + offset, offset as u8 ^ 0x5A);
+        }
+        cpu.write_u8(0x2003, 0x00);
+        let cycles = cpu.cycles;
+        cpu.write_u8(0x4014, 0x02);
+
+        let ppu = cpu.bus.ppu();
+        assert!(ppu.oam.iter().enumerate().all(|(index, value)| *value == index as u8 ^ 0x5A));
+        // OAMADDR went through the 256 bytes back to its start
+        assert_eq!(ppu.oam_addr, 0x00);
+        assert!([513, 514].contains(&(cpu.cycles - cycles)));
+    }
+
+    #[test]
+    fn test_oam_dma_alignment_cycle() {
+        // The halt cycle is followed by an alignment cycle when the DMA starts on an odd cycle,
+        // the cycle after the write
+        for (start, expected) in [(0, 514), (1, 513)] {
+            let mut cpu = new_cpu(Bus::new(Rom::test_rom()));
+            cpu.bus.set_access_context(start, 0x8000);
+            cpu.write_u8(0x4014, 0x02);
+            assert_eq!(cpu.cycles, expected, "DMA written on cycle {}", start);
+        }
+    }
+}
@@ -0,0 +1,284 @@
+use std::f64::consts::PI;
+
+// Color as (red, green, blue)
+pub type Rgb = (u8, u8, u8);
+
+// PPUMASK bits affecting the colors
+const PPUMASK_GREYSCALE: u8 = 0b0000_0001;
+const PPUMASK_EMPHASIS_SHIFT: u8 = 5; // Bits 5, 6 and 7: emphasize red, green and blue
+
+// Size of a .pal file: 64 colors of 3 bytes.
+// Some .pal files also contain the 7 emphasized variants of the palette (8 * 192 bytes).
+const PAL_FILE_SIZE: usize = 64 * 3;
+const PAL_FILE_WITH_EMPHASIS_SIZE: usize = 8 * PAL_FILE_SIZE;
+
+// The NES does not output RGB but a composite video signal, so there is no "true" palette.
+// This one is the palette commonly used by emulators.
+const DEFAULT_COLORS: [Rgb; 64] = [
+    (0x80, 0x80, 0x80), (0x00, 0x3D, 0xA6), (0x00, 0x12, 0xB0), (0x44, 0x00, 0x96),
+    (0xA1, 0x00, 0x5E), (0xC7, 0x00, 0x28), (0xBA, 0x06, 0x00), (0x8C, 0x17, 0x00),
+    (0x5C, 0x2F, 0x00), (0x10, 0x45, 0x00), (0x05, 0x4A, 0x00), (0x00, 0x47, 0x2E),
+    (0x00, 0x41, 0x66), (0x00, 0x00, 0x00), (0x05, 0x05, 0x05), (0x05, 0x05, 0x05),
+    (0xC7, 0xC7, 0xC7), (0x00, 0x77, 0xFF), (0x21, 0x55, 0xFF), (0x82, 0x37, 0xFA),
+    (0xEB, 0x2F, 0xB5), (0xFF, 0x29, 0x50), (0xFF, 0x22, 0x00), (0xD6, 0x32, 0x00),
+    (0xC4, 0x62, 0x00), (0x35, 0x80, 0x00), (0x05, 0x8F, 0x00), (0x00, 0x8A, 0x55),
+    (0x00, 0x99, 0xCC), (0x21, 0x21, 0x21), (0x09, 0x09, 0x09), (0x09, 0x09, 0x09),
+    (0xFF, 0xFF, 0xFF), (0x0F, 0xD7, 0xFF), (0x69, 0xA2, 0xFF), (0xD4, 0x80, 0xFF),
+    (0xFF, 0x45, 0xF3), (0xFF, 0x61, 0x8B), (0xFF, 0x88, 0x33), (0xFF, 0x9C, 0x12),
+    (0xFA, 0xBC, 0x20), (0x9F, 0xE3, 0x0E), (0x2B, 0xF0, 0x35), (0x0C, 0xF0, 0xA4),
+    (0x05, 0xFB, 0xFF), (0x5E, 0x5E, 0x5E), (0x0D, 0x0D, 0x0D), (0x0D, 0x0D, 0x0D),
+    (0xFF, 0xFF, 0xFF), (0xA6, 0xFC, 0xFF), (0xB3, 0xEC, 0xFF), (0xDA, 0xAB, 0xEB),
+    (0xFF, 0xA8, 0xF9), (0xFF, 0xAB, 0xB3), (0xFF, 0xD2, 0xB0), (0xFF, 0xEF, 0xA6),
+    (0xFF, 0xF7, 0x9C), (0xD7, 0xE8, 0x95), (0xA6, 0xED, 0xAF), (0xA2, 0xF2, 0xDA),
+    (0x99, 0xFF, 0xFC), (0xDD, 0xDD, 0xDD), (0x11, 0x11, 0x11), (0x11, 0x11, 0x11),
+];
+
+// Colors of the RGB PPUs (2C03 and 2C05) of the VS System and PlayChoice-10, which output each
+// channel on 3 bits instead of a composite signal. Octal digits are the red, green and blue levels.
+const RGB_PPU_LEVELS: [u16; 64] = [
+    0o333, 0o014, 0o006, 0o326, 0o403, 0o503, 0o510, 0o420, 0o320, 0o120, 0o031, 0o040, 0o022, 0o000, 0o000, 0o000,
+    0o555, 0o036, 0o027, 0o407, 0o507, 0o704, 0o700, 0o630, 0o430, 0o140, 0o040, 0o053, 0o044, 0o000, 0o000, 0o000,
+    0o777, 0o357, 0o447, 0o637, 0o707, 0o737, 0o740, 0o750, 0o660, 0o360, 0o070, 0o276, 0o077, 0o000, 0o000, 0o000,
+    0o777, 0o567, 0o657, 0o757, 0o747, 0o755, 0o764, 0o772, 0o773, 0o572, 0o473, 0o276, 0o467, 0o000, 0o000, 0o000,
+];
+
+// Palettes available without any external file.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PalettePreset {
+    Default,
+    // Computed from the voltage levels of the 2C02 composite video signal
+    Ntsc,
+    // RGB PPU of the arcade systems
+    Rgb,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Palette {
+    colors: [Rgb; 64],
+    // Colors for each of the 8 emphasis combinations, when provided by the .pal file.
+    // Otherwise emphasis is approximated by dimming the other channels.
+    emphasis_colors: Option<Vec<[Rgb; 64]>>,
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Self::preset(PalettePreset::Default)
+    }
+}
+
+impl Palette {
+    pub fn preset(preset: PalettePreset) -> Self {
+        let colors = match preset {
+            PalettePreset::Default => DEFAULT_COLORS,
+            PalettePreset::Ntsc => std::array::from_fn(|index| ntsc_color(index as u8)),
+            PalettePreset::Rgb => std::array::from_fn(|index| rgb_ppu_color(RGB_PPU_LEVELS[index])),
+        };
+        Self { colors, emphasis_colors: None }
+    }
+
+    // Parses the content of a .pal file (192 bytes, or 1536 bytes with the emphasized palettes).
+    pub fn from_pal_bytes(data: &[u8]) -> Result<Self, String> {
+        let parse_colors = |chunk: &[u8]| -> [Rgb; 64] {
+            std::array::from_fn(|index| (chunk[index * 3], chunk[index * 3 + 1], chunk[index * 3 + 2]))
+        };
+
+        match data.len() {
+            PAL_FILE_SIZE => Ok(Self { colors: parse_colors(data), emphasis_colors: None }),
+            PAL_FILE_WITH_EMPHASIS_SIZE => {
+                let emphasis_colors: Vec<[Rgb; 64]> = data.chunks(PAL_FILE_SIZE).map(parse_colors).collect();
+                Ok(Self { colors: emphasis_colors[0], emphasis_colors: Some(emphasis_colors) })
+            }
+            len => Err(format!(
+                "Invalid palette file: Expected {} or {} bytes, got {}",
+                PAL_FILE_SIZE, PAL_FILE_WITH_EMPHASIS_SIZE, len
+            )),
+        }
+    }
+
+    pub fn load_pal_file(path: &str) -> Result<Self, String> {
+        let data = std::fs::read(path).map_err(|error| format!("Failed to read palette file {}: {}", path, error))?;
+        Self::from_pal_bytes(&data)
+    }
+
+    // Color displayed for a palette index (0x00 - 0x3F), taking the PPUMASK register into account.
+    pub fn color(&self, index: u8, ppumask: u8) -> Rgb {
+        let mut index = (index & 0x3F) as usize;
+        // Greyscale mode only keeps the grey column of the palette
+        if ppumask & PPUMASK_GREYSCALE != 0 {
+            index &= 0x30;
+        }
+
+        let emphasis = (ppumask >> PPUMASK_EMPHASIS_SHIFT) as usize;
+        match &self.emphasis_colors {
+            Some(emphasis_colors) => emphasis_colors[emphasis][index],
+            None => apply_emphasis(self.colors[index], emphasis as u8),
+        }
+    }
+
+    // The 64 colors as displayed with the given PPUMASK value.
+    pub fn lookup_table(&self, ppumask: u8) -> [Rgb; 64] {
+        std::array::from_fn(|index| self.color(index as u8, ppumask))
+    }
+
+    // Palette lookup stage of the rendering: converts a scanline of palette indexes to RGB24 pixels.
+    // Games change PPUMASK between scanlines (e.g. to dim part of the screen), so the PPU must
+    // call this with the PPUMASK value of each scanline rather than once per frame.
+    pub fn render_scanline(&self, indexes: &[u8], ppumask: u8, rgb: &mut [u8]) {
+        let table = self.lookup_table(ppumask);
+        for (index, pixel) in indexes.iter().zip(rgb.chunks_exact_mut(3)) {
+            let (red, green, blue) = table[(*index & 0x3F) as usize];
+            pixel.copy_from_slice(&[red, green, blue]);
+        }
+    }
+}
+
+// Emphasizing a color actually darkens the other ones: each emphasis bit attenuates
+// the two channels it does not emphasize.
+fn apply_emphasis(color: Rgb, emphasis: u8) -> Rgb {
+    const ATTENUATION: f64 = 0.816;
+    let (mut red, mut green, mut blue) = (color.0 as f64, color.1 as f64, color.2 as f64);
+    if emphasis & 0b001 != 0 {
+        green *= ATTENUATION;
+        blue *= ATTENUATION;
+    }
+    if emphasis & 0b010 != 0 {
+        red *= ATTENUATION;
+        blue *= ATTENUATION;
+    }
+    if emphasis & 0b100 != 0 {
+        red *= ATTENUATION;
+        green *= ATTENUATION;
+    }
+    (red.round() as u8, green.round() as u8, blue.round() as u8)
+}
+
+// Generates a palette color by decoding the composite signal of the 2C02.
+// The low 4 bits of the index select the phase of the color wave (the hue),
+// the next 2 bits select its voltage levels (the brightness).
+// More info: https://www.nesdev.org/wiki/NTSC_video
+fn ntsc_color(index: u8) -> Rgb {
+    const LOW_LEVELS: [f64; 4] = [0.350, 0.518, 0.962, 1.550];
+    const HIGH_LEVELS: [f64; 4] = [1.094, 1.506, 1.962, 1.962];
+    const BLACK: f64 = 0.518;
+    const WHITE: f64 = 1.962;
+
+    let hue = (index & 0x0F) as usize;
+    let level = ((index >> 4) & 0x03) as usize;
+
+    // The signal is sampled 12 times per color cycle and demodulated into YIQ
+    let (mut y, mut i, mut q) = (0.0, 0.0, 0.0);
+    for phase in 0..12 {
+        let voltage = match hue {
+            0 => HIGH_LEVELS[level],
+            13 => LOW_LEVELS[level],
+            14 | 15 => BLACK,
+            _ if (hue + phase + 8) % 12 < 6 => HIGH_LEVELS[level],
+            _ => LOW_LEVELS[level],
+        };
+        let signal = (voltage - BLACK) / (WHITE - BLACK);
+        let angle = PI * phase as f64 / 6.0;
+        y += signal;
+        i += signal * angle.cos();
+        q += signal * angle.sin();
+    }
+    // Averaging a square wave halves its amplitude, so the chroma is scaled back by 2
+    y /= 12.0;
+    i /= 6.0;
+    q /= 6.0;
+
+    let to_u8 = |value: f64| (value * 255.0).round().clamp(0.0, 255.0) as u8;
+    (
+        to_u8(y + 0.946882 * i + 0.623557 * q),
+        to_u8(y - 0.274788 * i - 0.635691 * q),
+        to_u8(y - 1.108545 * i + 1.709007 * q),
+    )
+}
+
+fn rgb_ppu_color(levels: u16) -> Rgb {
+    let to_u8 = |level: u16| ((level & 0o7) * 255 / 7) as u8;
+    (to_u8(levels >> 6), to_u8(levels >> 3), to_u8(levels))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::palette::{Palette, PalettePreset};
+
+    #[test]
+    fn test_default_palette() {
+        let palette = Palette::default();
+        assert_eq!(palette.color(0x0D, 0), (0x00, 0x00, 0x00));
+        assert_eq!(palette.color(0x30, 0), (0xFF, 0xFF, 0xFF));
+        // Only the low 6 bits are used
+        assert_eq!(palette.color(0x70, 0), palette.color(0x30, 0));
+    }
+
+    #[test]
+    fn test_greyscale_and_emphasis() {
+        let palette = Palette::default();
+        // Greyscale maps every color to the first column
+        assert_eq!(palette.color(0x16, 0b0000_0001), palette.color(0x10, 0));
+
+        // Emphasizing red dims green and blue
+        let (red, green, blue) = palette.color(0x30, 0b0010_0000);
+        assert_eq!(red, 0xFF);
+        assert!(green < 0xFF && blue < 0xFF);
+    }
+
+    #[test]
+    fn test_render_scanline() {
+        let palette = Palette::default();
+        let indexes = [0x0D, 0x16, 0x30];
+        let mut rgb = [0; 9];
+
+        palette.render_scanline(&indexes, 0, &mut rgb);
+        assert_eq!(rgb[..6], [0x00, 0x00, 0x00, 0xFF, 0x22, 0x00]);
+
+        // Greyscale with blue emphasis, as used to dim the screen when pausing
+        let ppumask = 0b1000_0001;
+        palette.render_scanline(&indexes, ppumask, &mut rgb);
+        let (red, green, blue) = palette.color(0x10, ppumask);
+        assert_eq!(rgb[3..6], [red, green, blue]);
+        assert!(red < blue);
+    }
+
+    #[test]
+    fn test_ntsc_preset() {
+        let palette = Palette::preset(PalettePreset::Ntsc);
+        assert_eq!(palette.color(0x0F, 0), (0, 0, 0));
+        assert_eq!(palette.color(0x30, 0), (0xFF, 0xFF, 0xFF));
+        // Greys have no saturation
+        let (red, green, blue) = palette.color(0x00, 0);
+        assert!(red == green && green == blue);
+        // Hues follow the hardware order: 0x12 is blue, 0x16 is red and 0x1A is green
+        let (red, green, blue) = palette.color(0x12, 0);
+        assert!(blue > red && blue > green);
+        let (red, green, blue) = palette.color(0x16, 0);
+        assert!(red > green && red > blue);
+        let (red, green, blue) = palette.color(0x1A, 0);
+        assert!(green > red && green > blue);
+    }
+
+    #[test]
+    fn test_rgb_preset() {
+        let palette = Palette::preset(PalettePreset::Rgb);
+        assert_eq!(palette.color(0x0D, 0), (0, 0, 0));
+        assert_eq!(palette.color(0x20, 0), (0xFF, 0xFF, 0xFF));
+        assert_eq!(palette.color(0x16, 0), (0xFF, 0, 0));
+    }
+
+    #[test]
+    fn test_load_pal_bytes() {
+        let mut data: Vec<u8> = (0..192).map(|byte| byte as u8).collect();
+        let palette = Palette::from_pal_bytes(&data).expect("192 bytes palette should load");
+        assert_eq!(palette.color(0x01, 0), (3, 4, 5));
+        // Without emphasized palettes in the file, emphasis is approximated
+        assert_ne!(palette.color(0x01, 0b1000_0000), (3, 4, 5));
+
+        data.extend(std::iter::repeat_n(0x42, 192 * 7));
+        let palette = Palette::from_pal_bytes(&data).expect("1536 bytes palette should load");
+        assert_eq!(palette.color(0x01, 0), (3, 4, 5));
+        assert_eq!(palette.color(0x01, 0b1000_0000), (0x42, 0x42, 0x42));
+
+        assert!(Palette::from_pal_bytes(&data[..100]).is_err());
+    }
+}
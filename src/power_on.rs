@@ -0,0 +1,219 @@
+use crate::cpu6502::{CPU, MAGIC_CONSTANT_FF};
+use crate::ppu::open_bus::DECAY_CYCLES;
+use crate::scheduler::Scheduler;
+
+// Content of the internal RAM at power-on.
+// The real hardware leaves RAM in an unpredictable state, and a few games (or their bugs)
+// depend on it, so it is configurable to make runs reproducible.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RamFillPattern {
+    AllZero,
+    AllOnes,
+    // Same pattern as FCEUX: blocks of 4 bytes alternating between 0x00 and 0xFF
+    Fceux,
+    // Pseudo-random bytes, always the same for a given seed
+    Random { seed: u64 },
+}
+
+impl RamFillPattern {
+    pub fn fill(&self, ram: &mut [u8]) {
+        match self {
+            RamFillPattern::AllZero => ram.fill(0x00),
+            RamFillPattern::AllOnes => ram.fill(0xFF),
+            RamFillPattern::Fceux => {
+                for (addr, byte) in ram.iter_mut().enumerate() {
+                    *byte = if addr & 0x04 != 0 { 0xFF } else { 0x00 };
+                }
+            }
+            RamFillPattern::Random { seed } => {
+                let mut state = *seed;
+                for byte in ram.iter_mut() {
+                    *byte = splitmix64(&mut state) as u8;
+                }
+            }
+        }
+    }
+}
+
+// SplitMix64 generator: small, fast and stable, so a seed always produces the same RAM content.
+fn splitmix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+// Values of the XAA magic constant measured on different consoles
+const MAGIC_CONSTANTS: [u8; 5] = [0x00, 0xEE, 0xEF, 0xFE, 0xFF];
+
+// State of the machine when it is powered on.
+// The default configuration matches the state the emulator has always started in.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PowerOnConfig {
+    pub ram_fill: RamFillPattern,
+    pub status_register: u8,
+    // CPU/PPU clock alignment (0 to 3): master clock cycles the PPU dot divider is ahead of the CPU
+    // one. The CPU divides the master clock by 12 and the PPU by 4, and both dividers power on in a
+    // random state, so NTSC consoles have 4 alignments. The CPU drives its accesses 6 master clocks
+    // into its cycle, by when the PPU has run 1 dot of the cycle with alignments 0 and 1, and 2 with
+    // alignments 2 and 3. The PPU is emulated dot by dot, so only these 2 cases are: 0 and 1 (and 2
+    // and 3) differ by where the access lands within a dot, which is not modeled, and behave the same.
+    // - 0 (default) and 1: after the 7 cycles of the reset sequence, the PPU is at dot 21 of
+    //   scanline 0.
+    // - 2 and 3: the PPU is at dot 22, and everything the CPU sees of it (PPUSTATUS flags, NMI,
+    //   frame boundaries) comes one dot earlier, so a third of the PPUSTATUS reads landing next to
+    //   the vblank flag change see it one CPU cycle earlier.
+    // The ppu_vbl_nmi test ROMs are run with both (see blargg_tests.rs).
+    pub ppu_alignment: u8,
+    // Analog constant of the unstable XAA opcode (see CPU::magic_constant)
+    pub magic_constant: u8,
+    // Decay delay of the PPU open bus latch in CPU cycles (see ppu::open_bus)
+    pub ppu_open_bus_decay: Option<u64>,
+}
+
+impl Default for PowerOnConfig {
+    fn default() -> Self {
+        Self {
+            ram_fill: RamFillPattern::AllZero,
+            status_register: 0x24, // 0010 0100 (Unused + Interrupt Disable)
+            ppu_alignment: 0,
+            magic_constant: MAGIC_CONSTANT_FF,
+            ppu_open_bus_decay: Some(DECAY_CYCLES),
+        }
+    }
+}
+
+impl PowerOnConfig {
+    // Replaces every value that differs between consoles or power-ons (RAM content, CPU/PPU
+    // alignment, XAA magic constant, open bus decay delay) with one derived from `seed`, so that
+    // two machines given the same seed and inputs run identically.
+    pub fn seeded(self, seed: u64) -> Self {
+        let mut state = seed;
+        let ram_seed = splitmix64(&mut state);
+        let ppu_alignment = (splitmix64(&mut state) % 4) as u8;
+        let magic_constant = MAGIC_CONSTANTS[(splitmix64(&mut state) % MAGIC_CONSTANTS.len() as u64) as usize];
+        // The capacitor of the latch holds its charge for roughly 0.5 to 1.5 times the usual delay
+        let ppu_open_bus_decay = Some(DECAY_CYCLES / 2 + splitmix64(&mut state) % DECAY_CYCLES);
+        Self { ram_fill: RamFillPattern::Random { seed: ram_seed }, ppu_alignment, magic_constant, ppu_open_bus_decay, ..self }
+    }
+}
+
+impl CPU {
+    // Cold boots the machine: RAM is filled, then the CPU goes through its reset sequence.
+    pub fn power_on(&mut self, config: &PowerOnConfig) -> Result<(), String> {
+        if config.ppu_alignment > 3 {
+            return Err(format!("Invalid PPU alignment: {} (expected 0 to 3)", config.ppu_alignment));
+        }
+
+        config.ram_fill.fill(self.bus.internal_ram_mut());
+        self.bus.power_on_ppu(config.ppu_open_bus_decay);
+        self.accumulator = 0;
+        self.x_register = 0;
+        self.y_register = 0;
+        self.stack_pointer = CPU::STACK_ADDRESS_POWER_ON;
+        self.cycles = 0;
+        self.scheduler = Scheduler::new();
+        self.reset();
+        self.status_register = config.status_register;
+        self.ppu_alignment = config.ppu_alignment as u64;
+        self.magic_constant = config.magic_constant;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bus::Bus;
+    use crate::cpu6502::new_cpu;
+    use crate::power_on::{PowerOnConfig, RamFillPattern};
+    use crate::rom::Rom;
+
+    #[test]
+    fn test_ram_fill_patterns() {
+        let mut ram = [0x12u8; 16];
+
+        RamFillPattern::AllOnes.fill(&mut ram);
+        assert!(ram.iter().all(|byte| *byte == 0xFF));
+
+        RamFillPattern::Fceux.fill(&mut ram);
+        assert_eq!(ram[..8], [0x00, 0x00, 0x00, 0x00, 0xFF, 0xFF, 0xFF, 0xFF]);
+        assert_eq!(ram[8..], ram[..8]);
+
+        let mut first = [0u8; 16];
+        let mut second = [0u8; 16];
+        RamFillPattern::Random { seed: 42 }.fill(&mut first);
+        RamFillPattern::Random { seed: 42 }.fill(&mut second);
+        assert_eq!(first, second);
+        RamFillPattern::Random { seed: 43 }.fill(&mut second);
+        assert_ne!(first, second);
+    }
+
+    #[test]
+    fn test_power_on() {
+        let mut cpu = new_cpu(Bus::new(Rom::test_rom()));
+        cpu.y_register = 0x55;
+        let config = PowerOnConfig { ram_fill: RamFillPattern::AllOnes, status_register: 0x34, ppu_alignment: 2, ..PowerOnConfig::default() };
+        cpu.power_on(&config).expect("power on should succeed");
+
+        assert_eq!(cpu.read_u8(0x0000), 0xFF);
+        assert_eq!(cpu.read_u8(0x07FF), 0xFF);
+        assert_eq!(cpu.y_register, 0x00);
+        assert_eq!(cpu.status_register, 0x34);
+        assert_eq!(cpu.cycles, 7);
+        assert_eq!(cpu.ppu_position(), (0, 22));
+
+        let config = PowerOnConfig { ppu_alignment: 4, ..PowerOnConfig::default() };
+        assert!(cpu.power_on(&config).is_err());
+    }
+
+    #[test]
+    fn test_ppu_alignment() {
+        let mut cpu = new_cpu(Bus::new(Rom::test_rom()));
+        let positions: Vec<(u64, u64)> = (0..4)
+            .map(|ppu_alignment| {
+                cpu.power_on(&PowerOnConfig { ppu_alignment, ..PowerOnConfig::default() }).expect("power on should succeed");
+                cpu.ppu_position()
+            })
+            .collect();
+        // 7 cycles of reset: 21 dots
+        assert_eq!(positions, vec![(0, 21), (0, 21), (0, 22), (0, 22)]);
+    }
+
+    #[test]
+    fn test_soft_reset_differs_from_power_on() {
+        let mut cpu = new_cpu(Bus::new(Rom::test_rom()));
+        cpu.power_on(&PowerOnConfig::default()).expect("power on should succeed");
+        assert_eq!((cpu.accumulator, cpu.x_register, cpu.y_register, cpu.stack_pointer), (0, 0, 0, 0xFD));
+
+        cpu.accumulator = 0x11;
+        cpu.x_register = 0x22;
+        cpu.y_register = 0x33;
+        cpu.status_register = 0xA1;
+        cpu.write_u8(0x0200, 0x44);
+        let cycles = cpu.cycles;
+        cpu.reset();
+        assert_eq!((cpu.accumulator, cpu.x_register, cpu.y_register), (0x11, 0x22, 0x33));
+        assert_eq!(cpu.stack_pointer, 0xFA);
+        // Only the interrupt disable flag changes
+        assert_eq!(cpu.status_register, 0xA5);
+        assert_eq!(cpu.read_u8(0x0200), 0x44);
+        assert_eq!(cpu.cycles, cycles + 7);
+
+        cpu.power_on(&PowerOnConfig::default()).expect("power on should succeed");
+        assert_eq!((cpu.accumulator, cpu.x_register, cpu.y_register, cpu.stack_pointer), (0, 0, 0, 0xFD));
+        assert_eq!(cpu.read_u8(0x0200), 0x00);
+        assert_eq!(cpu.cycles, 7);
+    }
+
+    #[test]
+    fn test_power_on_is_deterministic() {
+        let config = PowerOnConfig { ram_fill: RamFillPattern::Random { seed: 7 }, ..PowerOnConfig::default() };
+        let mut first = new_cpu(Bus::new(Rom::test_rom()));
+        let mut second = new_cpu(Bus::new(Rom::test_rom()));
+        first.power_on(&config).unwrap();
+        second.power_on(&config).unwrap();
+
+        assert_eq!(first.run_frame(), second.run_frame());
+    }
+}
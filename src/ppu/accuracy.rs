@@ -0,0 +1,260 @@
+use crate::ppu::scroll::ScrollRegisters;
+use crate::ppu::status::PpuStatus;
+use crate::ppu::timing::PpuClock;
+
+// How precisely the PPU is emulated. Both levels share the registers (PpuStatus, ScrollRegisters)
+// and the frame timing (PpuClock), only the granularity of the catch-up changes:
+// - Fast: the PPU advances a whole visible scanline at a time and renders it at once, with the
+//   scroll registers as they are at its start. Mid-scanline register writes take effect on the next
+//   scanline. Meant for low-power and wasm targets.
+// - CycleAccurate: the PPU advances dot by dot and renders pixel by pixel, so mid-scanline effects,
+//   the PPUSTATUS vblank race and the v corruptions of register accesses during rendering are emulated.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Accuracy {
+    #[default]
+    Fast,
+    CycleAccurate,
+}
+
+impl Accuracy {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Accuracy::Fast => "fast",
+            Accuracy::CycleAccurate => "cycle-accurate",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Result<Self, String> {
+        match name {
+            "fast" => Ok(Accuracy::Fast),
+            "cycle-accurate" => Ok(Accuracy::CycleAccurate),
+            _ => Err(format!("Unknown accuracy `{}` (expected `fast` or `cycle-accurate`)", name)),
+        }
+    }
+}
+
+// Rendering stage driven by `PpuRunner`, which calls the method matching the accuracy level.
+// `status` receives the sprite zero hit and sprite overflow flags.
+pub trait Renderer {
+    // Fast: renders visible scanline `scanline` (0 - 239) at once.
+    fn render_scanline(&mut self, scanline: u16, scroll: &ScrollRegisters, status: &mut PpuStatus);
+
+    // CycleAccurate: outputs the pixel of `dot` (1 - 256) of visible scanline `scanline`, with the
+    // scroll registers as they were before the updates of that dot.
+    fn render_dot(&mut self, scanline: u16, dot: u16, scroll: &ScrollRegisters, status: &mut PpuStatus);
+}
+
+// Dots of a scanline with an effect: dot 1 sets or clears the vblank flags, the others update the
+// scroll registers (see ScrollRegisters::tick). A fast scanline only ticks the clock on these dots.
+const EFFECT_DOTS: [u16; 37] = [
+    1, 8, 16, 24, 32, 40, 48, 56, 64, 72, 80, 88, 96, 104, 112, 120, 128, 136, 144, 152, 160, 168,
+    176, 184, 192, 200, 208, 216, 224, 232, 240, 248, 256, 257, 280, 328, 336,
+];
+
+const DOTS_PER_SCANLINE: u64 = 341;
+
+#[derive(Debug, Clone, Default)]
+pub struct PpuRunner {
+    pub clock: PpuClock,
+    pub accuracy: Accuracy,
+    // Dots the PPU is behind the CPU. In fast mode, they are only run once a whole scanline is due.
+    pending_dots: u64,
+}
+
+impl PpuRunner {
+    pub fn new(accuracy: Accuracy) -> Self {
+        Self { accuracy, ..Self::default() }
+    }
+
+    pub fn pending_dots(&self) -> u64 {
+        self.pending_dots
+    }
+
+    // Restores the dots a savestate was behind.
+    pub fn set_pending_dots(&mut self, pending_dots: u64) {
+        self.pending_dots = pending_dots;
+    }
+
+    // Catches up with `dots` more PPU dots of CPU time.
+    pub fn run<R: Renderer>(
+        &mut self,
+        dots: u64,
+        rendering_enabled: bool,
+        status: &mut PpuStatus,
+        scroll: &mut ScrollRegisters,
+        renderer: &mut R,
+    ) {
+        self.pending_dots += dots;
+        status.vblank_race = self.accuracy == Accuracy::CycleAccurate;
+
+        loop {
+            // Only the visible scanlines are run at once: the others render nothing, and running them
+            // dot by dot sets and clears the vblank flags on time. A scanline started in cycle
+            // accurate mode is also finished dot by dot.
+            let whole_scanline = self.accuracy == Accuracy::Fast && self.clock.dot == 0 && self.clock.scanline < 240;
+            if !whole_scanline {
+                if self.pending_dots == 0 {
+                    break;
+                }
+                self.run_dot(rendering_enabled, status, scroll, renderer);
+                continue;
+            }
+            if self.pending_dots < DOTS_PER_SCANLINE {
+                break;
+            }
+            self.pending_dots -= DOTS_PER_SCANLINE;
+            self.run_scanline(rendering_enabled, status, scroll, renderer);
+        }
+    }
+
+    fn run_dot<R: Renderer>(&mut self, rendering_enabled: bool, status: &mut PpuStatus, scroll: &mut ScrollRegisters, renderer: &mut R) {
+        self.pending_dots -= 1;
+        let previous_scroll = scroll.clone();
+        self.clock.tick(rendering_enabled, status, scroll);
+        let (scanline, dot) = (self.clock.scanline, self.clock.dot);
+        if rendering_enabled && scanline < 240 && (1..=256).contains(&dot) {
+            renderer.render_dot(scanline, dot, &previous_scroll, status);
+        }
+    }
+
+    // Runs the visible scanline starting at the current position (dot 0).
+    fn run_scanline<R: Renderer>(&mut self, rendering_enabled: bool, status: &mut PpuStatus, scroll: &mut ScrollRegisters, renderer: &mut R) {
+        if rendering_enabled {
+            renderer.render_scanline(self.clock.scanline, scroll, status);
+        }
+        for dot in EFFECT_DOTS {
+            self.clock.dot = dot - 1;
+            self.clock.tick(rendering_enabled, status, scroll);
+        }
+        // The last tick moves to the next scanline
+        self.clock.dot = DOTS_PER_SCANLINE as u16 - 1;
+        self.clock.tick(rendering_enabled, status, scroll);
+    }
+
+    // Register accesses that update v. With CycleAccurate, accesses while rendering collide with the
+    // updates of v on the current dot (see the `*_while_rendering` methods of ScrollRegisters); the
+    // fast mode does not know the current dot, so it keeps the plain register behavior.
+    pub fn write_ppuctrl(&self, data: u8, rendering_enabled: bool, scroll: &mut ScrollRegisters) {
+        match self.glitch_position(rendering_enabled) {
+            Some((scanline, dot)) => scroll.write_ppuctrl_while_rendering(data, scanline, dot),
+            None => scroll.write_ppuctrl(data),
+        }
+    }
+
+    pub fn write_ppuscroll(&self, data: u8, rendering_enabled: bool, scroll: &mut ScrollRegisters) {
+        match self.glitch_position(rendering_enabled) {
+            Some((scanline, dot)) => scroll.write_ppuscroll_while_rendering(data, scanline, dot),
+            None => scroll.write_ppuscroll(data),
+        }
+    }
+
+    pub fn write_ppuaddr(&self, data: u8, rendering_enabled: bool, scroll: &mut ScrollRegisters) {
+        match self.glitch_position(rendering_enabled) {
+            Some((scanline, dot)) => scroll.write_ppuaddr_while_rendering(data, scanline, dot),
+            None => scroll.write_ppuaddr(data),
+        }
+    }
+
+    // After a PPUDATA read or write, `increment` being 1 or 32 (PPUCTRL bit 2)
+    pub fn increment_vram_address(&self, increment: u16, rendering_enabled: bool, scroll: &mut ScrollRegisters) {
+        match self.glitch_position(rendering_enabled) {
+            Some((scanline, _)) => scroll.increment_vram_address_while_rendering(increment, scanline),
+            None => scroll.increment_vram_address(increment),
+        }
+    }
+
+    // The PPU position, when register accesses can glitch
+    fn glitch_position(&self, rendering_enabled: bool) -> Option<(u16, u16)> {
+        let glitches = rendering_enabled && self.accuracy == Accuracy::CycleAccurate;
+        glitches.then_some((self.clock.scanline, self.clock.dot))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ppu::accuracy::{Accuracy, PpuRunner, Renderer};
+    use crate::ppu::scroll::ScrollRegisters;
+    use crate::ppu::status::PpuStatus;
+    use crate::ppu::timing::PPUSTATUS_VBLANK;
+
+    #[derive(Default)]
+    struct CountingRenderer {
+        scanlines: Vec<u16>,
+        dots: usize,
+    }
+
+    impl Renderer for CountingRenderer {
+        fn render_scanline(&mut self, scanline: u16, _scroll: &ScrollRegisters, _status: &mut PpuStatus) {
+            self.scanlines.push(scanline);
+        }
+
+        fn render_dot(&mut self, _scanline: u16, _dot: u16, _scroll: &ScrollRegisters, _status: &mut PpuStatus) {
+            self.dots += 1;
+        }
+    }
+
+    // Runs 2 frames (one even, one odd) and returns the final state
+    fn run_frames(accuracy: Accuracy) -> (PpuRunner, PpuStatus, ScrollRegisters, CountingRenderer) {
+        let mut runner = PpuRunner::new(accuracy);
+        let (mut status, mut scroll, mut renderer) = (PpuStatus::new(), ScrollRegisters::new(), CountingRenderer::default());
+        scroll.t = 0x0C1F;
+        // CPU cycles of 3 dots
+        for _ in 0..(89342 + 89341 + 3 * 341) / 3 {
+            runner.run(3, true, &mut status, &mut scroll, &mut renderer);
+        }
+        (runner, status, scroll, renderer)
+    }
+
+    #[test]
+    fn test_accuracy_names() {
+        for accuracy in [Accuracy::Fast, Accuracy::CycleAccurate] {
+            assert_eq!(Accuracy::from_name(accuracy.name()), Ok(accuracy));
+        }
+        assert!(Accuracy::from_name("exact").is_err());
+    }
+
+    #[test]
+    fn test_levels_share_timing() {
+        let (fast, fast_status, fast_scroll, fast_renderer) = run_frames(Accuracy::Fast);
+        let (accurate, accurate_status, accurate_scroll, accurate_renderer) = run_frames(Accuracy::CycleAccurate);
+
+        assert_eq!((fast.clock.frame, fast.clock.scanline, fast.clock.dot), (2, 3, 0));
+        assert_eq!((accurate.clock.frame, accurate.clock.scanline, accurate.clock.dot), (2, 3, 0));
+        assert_eq!(fast_status.flags & PPUSTATUS_VBLANK, accurate_status.flags & PPUSTATUS_VBLANK);
+        assert_eq!(fast_scroll, accurate_scroll);
+
+        assert_eq!(fast_renderer.scanlines.len(), 240 * 2 + 3);
+        assert_eq!(accurate_renderer.dots, 256 * (240 * 2 + 3));
+        assert!(accurate_status.vblank_race && !fast_status.vblank_race);
+    }
+
+    #[test]
+    fn test_register_glitches_need_cycle_accuracy() {
+        let increment_during_frame = |accuracy: Accuracy, rendering_enabled: bool| {
+            let mut runner = PpuRunner::new(accuracy);
+            let (mut status, mut scroll, mut renderer) = (PpuStatus::new(), ScrollRegisters::new(), CountingRenderer::default());
+            runner.run(341 * 10 + 100, rendering_enabled, &mut status, &mut scroll, &mut renderer);
+            scroll.v = 0;
+            runner.increment_vram_address(32, rendering_enabled, &mut scroll);
+            scroll.v
+        };
+        assert_eq!(increment_during_frame(Accuracy::CycleAccurate, true), 0x1001);
+        assert_eq!(increment_during_frame(Accuracy::CycleAccurate, false), 32);
+        assert_eq!(increment_during_frame(Accuracy::Fast, true), 32);
+    }
+
+    #[test]
+    fn test_switching_levels() {
+        let mut runner = PpuRunner::new(Accuracy::CycleAccurate);
+        let (mut status, mut scroll, mut renderer) = (PpuStatus::new(), ScrollRegisters::new(), CountingRenderer::default());
+        runner.run(100, true, &mut status, &mut scroll, &mut renderer);
+        runner.accuracy = Accuracy::Fast;
+        runner.run(341, true, &mut status, &mut scroll, &mut renderer);
+        // The first scanline is finished dot by dot, the second one waits for all its dots
+        assert_eq!((runner.clock.scanline, runner.clock.dot), (1, 0));
+        assert!(renderer.scanlines.is_empty());
+        runner.run(241, true, &mut status, &mut scroll, &mut renderer);
+        assert_eq!((runner.clock.scanline, runner.clock.dot), (2, 0));
+        assert_eq!(renderer.scanlines, vec![1]);
+    }
+}
@@ -0,0 +1,186 @@
+use crate::ppu::pixel_info::SpritePixel;
+use crate::ppu::render_thread::SCREEN_WIDTH;
+use crate::ppu::sprites::is_sprite_zero_hit;
+
+// Collision view for speedrunners and ROM hackers: where sprite zero hit happened during a frame
+// and where each sprite overlapped opaque background pixels, as rectangles to draw over the frame.
+// Games time split screens on sprite zero hit, and many collision glitches come from sprites
+// overlapping the background one frame early or late, which these rectangles make visible.
+// The renderer feeds it each pixel drawn with the same data as its pixel multiplexer (see
+// pixel_info.rs).
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rect {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Rect {
+    fn pixel(x: usize, y: usize) -> Self {
+        Self { x, y, width: 1, height: 1 }
+    }
+
+    // Grows the rectangle to include the pixel (x, y)
+    fn extend(&mut self, x: usize, y: usize) {
+        let right = (self.x + self.width).max(x + 1);
+        let bottom = (self.y + self.height).max(y + 1);
+        self.x = self.x.min(x);
+        self.y = self.y.min(y);
+        self.width = right - self.x;
+        self.height = bottom - self.y;
+    }
+
+    pub fn contains(&self, x: usize, y: usize) -> bool {
+        (self.x..self.x + self.width).contains(&x) && (self.y..self.y + self.height).contains(&y)
+    }
+}
+
+fn extend_area(area: &mut Option<Rect>, x: usize, y: usize) {
+    match area {
+        Some(rect) => rect.extend(x, y),
+        None => *area = Some(Rect::pixel(x, y)),
+    }
+}
+
+// Region where a sprite overlapped opaque background pixels during a frame
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SpriteOverlap {
+    // Index of the sprite in OAM
+    pub sprite: u8,
+    pub area: Rect,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FrameCollisions {
+    // Pixel (x, scanline) whose hit set the sprite zero hit flag, the first of the frame
+    pub sprite_zero_hit: Option<(usize, usize)>,
+    // Every pixel of the frame where sprite zero hit the background, not only the first one
+    pub sprite_zero_hit_area: Option<Rect>,
+    // Sorted by sprite index
+    pub overlaps: Vec<SpriteOverlap>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CollisionRecorder {
+    enabled: bool,
+    current: FrameCollisions,
+    last_frame: FrameCollisions,
+}
+
+impl CollisionRecorder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        self.current = FrameCollisions::default();
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    // Records a visible scanline: which background pixels are opaque, the first opaque sprite pixel
+    // of each column (the one the multiplexer sees), and PPUMASK for the sprite zero hit conditions.
+    pub fn record_scanline(&mut self, scanline: u16, background_opaque: &[bool; SCREEN_WIDTH], sprites: &[Option<SpritePixel>; SCREEN_WIDTH], ppumask: u8) {
+        for (x, sprite) in sprites.iter().enumerate() {
+            self.record_pixel(x, scanline, background_opaque[x], *sprite, ppumask);
+        }
+    }
+
+    // Records pixel `x` of a visible scanline, for the renderer drawing dot by dot
+    pub fn record_pixel(&mut self, x: usize, scanline: u16, background_opaque: bool, sprite: Option<SpritePixel>, ppumask: u8) {
+        if !self.enabled {
+            return;
+        }
+        let y = scanline as usize;
+        let Some(sprite) = sprite.filter(|sprite| sprite.value & 0x03 != 0) else {
+            return;
+        };
+        if !background_opaque {
+            return;
+        }
+        if sprite.index == 0 && is_sprite_zero_hit(x as u8, true, true, ppumask) {
+            self.current.sprite_zero_hit.get_or_insert((x, y));
+            extend_area(&mut self.current.sprite_zero_hit_area, x, y);
+        }
+        let overlaps = &mut self.current.overlaps;
+        match overlaps.binary_search_by_key(&sprite.index, |overlap| overlap.sprite) {
+            Ok(position) => overlaps[position].area.extend(x, y),
+            Err(position) => overlaps.insert(position, SpriteOverlap { sprite: sprite.index, area: Rect::pixel(x, y) }),
+        }
+    }
+
+    // Called at the end of each frame (start of vblank): the collisions recorded become the last frame's.
+    pub fn end_frame(&mut self) {
+        self.last_frame = std::mem::take(&mut self.current);
+    }
+
+    pub fn last_frame(&self) -> &FrameCollisions {
+        &self.last_frame
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ppu::collisions::{CollisionRecorder, Rect};
+    use crate::ppu::pixel_info::SpritePixel;
+    use crate::ppu::render_thread::SCREEN_WIDTH;
+
+    const RENDERING: u8 = 0b0001_1000;
+
+    fn sprite(index: u8) -> Option<SpritePixel> {
+        Some(SpritePixel { index, palette: 4, value: 1, behind_background: false })
+    }
+
+    #[test]
+    fn test_rect_extend() {
+        let mut rect = Rect::pixel(10, 20);
+        rect.extend(12, 18);
+        rect.extend(11, 19);
+        assert_eq!(rect, Rect { x: 10, y: 18, width: 3, height: 3 });
+        assert!(rect.contains(12, 20));
+        assert!(!rect.contains(13, 20));
+    }
+
+    #[test]
+    fn test_record_frame() {
+        let mut recorder = CollisionRecorder::new();
+        let mut background = [false; SCREEN_WIDTH];
+        let mut sprites = [None; SCREEN_WIDTH];
+        background[2..40].fill(true);
+        sprites[4] = sprite(0);
+        sprites[20] = sprite(0);
+        sprites[30] = sprite(5);
+        sprites[31] = Some(SpritePixel { value: 0, ..sprite(5).unwrap() });
+        // No background behind it
+        sprites[50] = sprite(6);
+
+        // Disabled: nothing is recorded
+        recorder.record_scanline(30, &background, &sprites, RENDERING);
+        recorder.end_frame();
+        assert!(recorder.last_frame().overlaps.is_empty());
+
+        recorder.set_enabled(true);
+        recorder.record_scanline(30, &background, &sprites, RENDERING);
+        sprites[20] = None;
+        sprites[21] = sprite(0);
+        recorder.record_scanline(31, &background, &sprites, RENDERING);
+        // Not visible until the end of the frame
+        assert!(recorder.last_frame().sprite_zero_hit.is_none());
+        recorder.end_frame();
+
+        let collisions = recorder.last_frame();
+        // x = 4 is in the clipped left columns, so the flag is set at x = 20
+        assert_eq!(collisions.sprite_zero_hit, Some((20, 30)));
+        assert_eq!(collisions.sprite_zero_hit_area, Some(Rect { x: 20, y: 30, width: 2, height: 2 }));
+        let overlaps: Vec<(u8, Rect)> = collisions.overlaps.iter().map(|overlap| (overlap.sprite, overlap.area)).collect();
+        assert_eq!(overlaps, vec![(0, Rect { x: 4, y: 30, width: 18, height: 2 }), (5, Rect { x: 30, y: 30, width: 1, height: 2 })]);
+
+        recorder.end_frame();
+        assert_eq!(recorder.last_frame().overlaps.len(), 0);
+    }
+}
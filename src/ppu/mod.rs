@@ -0,0 +1,955 @@
+// The PPU (2C02) and its components.
+pub mod accuracy;
+pub mod collisions;
+pub mod open_bus;
+pub mod palette_ram;
+pub mod pattern_tables;
+pub mod pixel_info;
+pub mod render_thread;
+pub mod renderer;
+pub mod sprite_evaluation;
+pub mod sprites;
+pub mod tile_decoder;
+pub mod status;
+pub mod timing;
+pub mod scroll;
+pub mod vram;
+
+use crate::frame::{PPU_DOTS_PER_FRAME, PPU_DOTS_PER_SCANLINE};
+use crate::frame_stats::HostClock;
+use crate::mapper::Mapper;
+use crate::palette::Palette;
+use crate::ppu::accuracy::{Accuracy, PpuRunner};
+use crate::ppu::collisions::CollisionRecorder;
+use crate::ppu::open_bus::PpuOpenBus;
+use crate::ppu::palette_ram::PaletteRam;
+use crate::ppu::pattern_tables::{render_pattern_tables, PALETTE_RAM_SIZE, PATTERN_TABLE_SIZE};
+use crate::ppu::pixel_info::{PixelInfo, PixelInfoBuffer};
+use crate::ppu::render_thread::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::ppu::renderer::{FrameOutput, ScanlineRenderer};
+use crate::ppu::scroll::ScrollRegisters;
+use crate::ppu::sprite_evaluation::SpriteEvaluator;
+use crate::ppu::sprites::ScanlineSprites;
+use crate::ppu::status::PpuStatus;
+use crate::ppu::timing::{PpuClock, PPUSTATUS_VBLANK, PRE_RENDER_SCANLINE};
+use crate::ppu::vram::Vram;
+use crate::savestate::{StateReader, StateWriter};
+use std::time::Duration;
+
+// Registers, mirrored every 8 bytes in 0x2000 - 0x3FFF
+pub const PPUCTRL: u16 = 0;
+pub const PPUMASK: u16 = 1;
+pub const PPUSTATUS: u16 = 2;
+pub const OAMADDR: u16 = 3;
+pub const OAMDATA: u16 = 4;
+pub const PPUSCROLL: u16 = 5;
+pub const PPUADDR: u16 = 6;
+pub const PPUDATA: u16 = 7;
+
+const PPUCTRL_INCREMENT_32: u8 = 0b0000_0100;
+const PPUCTRL_NMI: u8 = 0b1000_0000;
+const PPUMASK_GREYSCALE: u8 = 0b0000_0001;
+const PPUMASK_RENDERING: u8 = 0b0001_1000;
+
+pub const OAM_SIZE: usize = 256;
+const CHR_RAM_SIZE: usize = 0x2000;
+// Each sprite is 4 bytes in OAM: Y, tile, attributes, X
+const OAM_ATTRIBUTE_BYTE: u8 = 2;
+
+// What the cartridge connects to the PPU bus: the CHR ROM, banked by the mapper, which also wires the
+// nametable mirroring.
+#[derive(Clone, Copy)]
+pub struct Cartridge<'a> {
+    pub mapper: &'a dyn Mapper,
+    pub chr_rom: &'a [u8],
+}
+
+// NMI raised by the PPU, for the CPU to time the interrupt.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NmiEdge {
+    // Start of the vblank of frame `frame` (dot 1 of scanline 241)
+    Vblank { frame: u64 },
+    // A PPUCTRL write enabled the NMI while the vblank flag was set, on the cycle of the write
+    Enabled,
+}
+
+// The PPU as seen by the CPU through its 8 registers. It is owned by the bus and runs behind the
+// CPU, which catches it up (see `CPU::sync_ppu`) before accessing its registers, so they are read
+// and written on the PPU dot of their cycle, and at the events of `CPU::run_ppu_events`.
+#[derive(Debug)]
+pub struct Ppu {
+    pub ctrl: u8,
+    pub mask: u8,
+    pub status: PpuStatus,
+    pub scroll: ScrollRegisters,
+    pub runner: PpuRunner,
+    pub oam_addr: u8,
+    pub oam: [u8; OAM_SIZE],
+    pub vram: Vram,
+    pub palette_ram: PaletteRam,
+    // Pattern tables of the cartridges without CHR ROM
+    pub chr_ram: Vec<u8>,
+    // PPUDATA reads return the byte fetched by the previous read
+    pub read_buffer: u8,
+    pub open_bus: PpuOpenBus,
+    // Dots run since power-on. The PPU is paused during overclocking scanlines, so it can be
+    // behind the CPU time.
+    pub dots: u64,
+    // Host time spent running the PPU, for the performance overlay (see `take_run_time`). Only
+    // measured with `run_clock` set: the PPU is caught up at each register access, and reading the
+    // host clock that often slows the emulation down.
+    run_time: Duration,
+    pub run_clock: Option<HostClock>,
+    nmi: Option<NmiEdge>,
+    pub sprite_evaluator: SpriteEvaluator,
+    pub scanline_sprites: ScanlineSprites,
+    pub output: FrameOutput,
+    // Layer and palette entry of each pixel, while enabled
+    pub pixel_info: PixelInfoBuffer,
+    // Sprite zero hits and sprite/background overlaps of the frame, while enabled
+    pub collisions: CollisionRecorder,
+    // Last frame whose vblank started, its picture is in the framebuffer
+    completed_frame: Option<u64>,
+    // After a reset, frame whose pre-render scanline ends the reset (see `reset`)
+    reset_until_frame: Option<u64>,
+}
+
+impl Ppu {
+    pub fn new(chr_ram: bool) -> Self {
+        Self {
+            ctrl: 0,
+            mask: 0,
+            status: PpuStatus::new(),
+            scroll: ScrollRegisters::new(),
+            runner: PpuRunner::new(Accuracy::default()),
+            oam_addr: 0,
+            oam: [0; OAM_SIZE],
+            vram: Vram::new(),
+            palette_ram: PaletteRam::new(),
+            chr_ram: if chr_ram { vec![0; CHR_RAM_SIZE] } else { Vec::new() },
+            read_buffer: 0,
+            open_bus: PpuOpenBus::new(),
+            dots: 0,
+            run_time: Duration::ZERO,
+            run_clock: None,
+            nmi: None,
+            sprite_evaluator: SpriteEvaluator::new(),
+            scanline_sprites: ScanlineSprites::default(),
+            output: FrameOutput::new(),
+            pixel_info: PixelInfoBuffer::new(),
+            collisions: CollisionRecorder::new(),
+            completed_frame: None,
+            reset_until_frame: None,
+        }
+    }
+
+    pub fn clock(&self) -> &PpuClock {
+        &self.runner.clock
+    }
+
+    pub fn rendering_enabled(&self) -> bool {
+        self.mask & PPUMASK_RENDERING != 0
+    }
+
+    // Dots skipped by the odd frames since power-on: the clock is that many dots ahead of the dots run.
+    pub fn skipped_dots(&self) -> u64 {
+        self.clock_dots().map_or(0, |dots| dots.saturating_sub(self.dots))
+    }
+
+    // Position of the clock plus the dots it is behind, in dots since power-on (None on overflow,
+    // for a corrupted savestate).
+    fn clock_dots(&self) -> Option<u64> {
+        let clock = self.clock();
+        clock.frame.checked_mul(PPU_DOTS_PER_FRAME)?
+            .checked_add(clock.scanline as u64 * PPU_DOTS_PER_SCANLINE + clock.dot as u64)?
+            .checked_add(self.runner.pending_dots())
+    }
+
+    // Runs `dots` more dots.
+    pub fn run(&mut self, dots: u64, cartridge: Cartridge) {
+        if dots == 0 {
+            return;
+        }
+        let started = self.run_clock.map(|clock| (clock, clock()));
+        let rendering_enabled = self.rendering_enabled();
+        let chr = if self.chr_ram.is_empty() { cartridge.chr_rom } else { &self.chr_ram };
+        let mut renderer = ScanlineRenderer {
+            output: &mut self.output,
+            palette_ram: &self.palette_ram,
+            vram: &self.vram,
+            oam: &self.oam,
+            chr,
+            mapper: cartridge.mapper,
+            evaluator: &mut self.sprite_evaluator,
+            sprites: &mut self.scanline_sprites,
+            pixel_info: &mut self.pixel_info,
+            collisions: &mut self.collisions,
+            ctrl: self.ctrl,
+            mask: self.mask,
+        };
+        self.runner.run(dots, rendering_enabled, &mut self.status, &mut self.scroll, &mut renderer);
+        self.dots += dots;
+        if self.status.take_nmi(&self.runner.clock) && self.ctrl & PPUCTRL_NMI != 0 {
+            self.nmi = Some(NmiEdge::Vblank { frame: self.runner.clock.frame });
+        }
+        if self.last_vblank() != self.completed_frame {
+            self.completed_frame = self.last_vblank();
+            let backdrop = self.backdrop();
+            if self.pixel_info.is_enabled() {
+                let info = PixelInfo { color: backdrop, ..PixelInfo::default() };
+                for scanline in (0..SCREEN_HEIGHT as u16).filter(|scanline| !self.output.is_drawn(*scanline)) {
+                    let _ = self.pixel_info.record_scanline(scanline, &[info; SCREEN_WIDTH]);
+                }
+            }
+            self.output.finish_frame(backdrop, self.mask);
+            self.collisions.end_frame();
+        }
+        if let Some((clock, started)) = started {
+            self.run_time += clock().saturating_sub(started);
+        }
+    }
+
+    // Last frame whose vblank started (dot 1 of scanline 241).
+    pub fn last_vblank(&self) -> Option<u64> {
+        let clock = self.clock();
+        match (clock.scanline, clock.dot) {
+            (241, 1..) | (242.., _) => Some(clock.frame),
+            _ => clock.frame.checked_sub(1),
+        }
+    }
+
+    // Color displayed while rendering is disabled. When v points to palette RAM, the PPU outputs
+    // that entry instead of the backdrop, which some games and demos use to draw colors.
+    fn backdrop(&self) -> u8 {
+        let addr = self.scroll.vram_address() & 0x3FFF;
+        if !self.rendering_enabled() && addr >= 0x3F00 {
+            self.palette_ram.read(addr, false)
+        } else {
+            self.palette_ram.backdrop()
+        }
+    }
+
+    // Host time spent running the PPU since the last call
+    pub fn take_run_time(&mut self) -> Duration {
+        std::mem::take(&mut self.run_time)
+    }
+
+    // RGB24 pixels (256x240) of the last complete frame.
+    pub fn framebuffer(&self) -> &[u8] {
+        self.output.framebuffer()
+    }
+
+    pub fn set_palette(&mut self, palette: Palette) {
+        self.output.set_palette(palette);
+    }
+
+    // Both pattern tables (256x128 pixels, RGB24) as the PPU sees them through the CHR banks of the
+    // mapper, in palette `palette_index` (0 - 7), for the CHR viewer.
+    pub fn render_pattern_tables(&self, cartridge: Cartridge, palette_index: usize) -> Vec<u8> {
+        let chr = if self.chr_ram.is_empty() { cartridge.chr_rom } else { &self.chr_ram };
+        let pattern_tables: Vec<u8> = (0..2 * PATTERN_TABLE_SIZE as u16)
+            .map(|addr| if chr.is_empty() { 0 } else { chr[cartridge.mapper.chr_index(addr) % chr.len()] })
+            .collect();
+        render_pattern_tables(&pattern_tables, &self.palette_ram(), palette_index, self.output.palette())
+    }
+
+    // The 32 palette RAM entries (0x3F00 - 0x3F1F), mirrors included, for the palette viewer.
+    pub fn palette_ram(&self) -> [u8; PALETTE_RAM_SIZE] {
+        self.palette_ram.entries()
+    }
+
+    // Last frame drawn in the framebuffer.
+    pub fn completed_frame(&self) -> Option<u64> {
+        self.completed_frame
+    }
+
+    // NMI raised since the last call, if any.
+    pub fn take_nmi(&mut self) -> Option<NmiEdge> {
+        self.nmi.take()
+    }
+
+    // NMI raised but not taken by the CPU yet, without taking it (for savestates).
+    pub fn pending_nmi(&self) -> Option<NmiEdge> {
+        self.nmi
+    }
+
+    // Read of `register` (0 - 7) by the CPU on cycle `now`.
+    pub fn read_register(&mut self, register: u16, now: u64, cartridge: Cartridge) -> u8 {
+        match register {
+            PPUSTATUS => {
+                let flags = self.status.read(&self.runner.clock);
+                self.scroll.read_ppustatus();
+                self.open_bus.read(flags, PpuOpenBus::PPUSTATUS_DRIVEN_BITS, now)
+            }
+            OAMDATA => {
+                let value = self.oamdata();
+                self.open_bus.read(value, self.oam_driven_bits(), now)
+            }
+            PPUDATA => {
+                let addr = self.scroll.vram_address() & 0x3FFF;
+                let value = if addr >= 0x3F00 {
+                    // Palette reads are not buffered, but they still fill the buffer with the
+                    // nametable byte "under" the palette
+                    self.read_buffer = self.read_memory(addr - 0x1000, cartridge);
+                    let value = self.read_memory(addr, cartridge);
+                    self.open_bus.read(value, PpuOpenBus::PALETTE_DRIVEN_BITS, now)
+                } else {
+                    let fetched = self.read_memory(addr, cartridge);
+                    let value = std::mem::replace(&mut self.read_buffer, fetched);
+                    self.open_bus.read(value, 0xFF, now)
+                };
+                self.increment_vram_address();
+                value
+            }
+            // Write-only registers
+            _ => self.open_bus.value(now),
+        }
+    }
+
+    // Value a read of `register` would return, without its side effects.
+    pub fn peek_register(&self, register: u16, now: u64, cartridge: Cartridge) -> u8 {
+        let open_bus = self.open_bus.value(now);
+        match register {
+            PPUSTATUS => {
+                let driven_bits = PpuOpenBus::PPUSTATUS_DRIVEN_BITS;
+                (self.status.flags & driven_bits) | (open_bus & !driven_bits)
+            }
+            OAMDATA => {
+                let driven_bits = self.oam_driven_bits();
+                (self.oamdata() & driven_bits) | (open_bus & !driven_bits)
+            }
+            PPUDATA => {
+                let addr = self.scroll.vram_address() & 0x3FFF;
+                match addr {
+                    0x3F00..=0x3FFF => {
+                        let driven_bits = PpuOpenBus::PALETTE_DRIVEN_BITS;
+                        (self.read_memory(addr, cartridge) & driven_bits) | (open_bus & !driven_bits)
+                    }
+                    _ => self.read_buffer,
+                }
+            }
+            _ => open_bus,
+        }
+    }
+
+    // Write of `data` to `register` (0 - 7) by the CPU on cycle `now`.
+    pub fn write_register(&mut self, register: u16, data: u8, now: u64, cartridge: Cartridge) {
+        self.open_bus.write(data, now);
+        let rendering_enabled = self.rendering_enabled();
+        match register {
+            PPUCTRL | PPUMASK | PPUSCROLL | PPUADDR if self.is_resetting() => {}
+            PPUCTRL => {
+                // The NMI output is the vblank flag AND the enable bit: enabling it during vblank
+                // raises the NMI right away
+                let enabled = data & PPUCTRL_NMI != 0 && self.ctrl & PPUCTRL_NMI == 0;
+                if enabled && self.status.flags & PPUSTATUS_VBLANK != 0 {
+                    self.nmi = Some(NmiEdge::Enabled);
+                }
+                self.ctrl = data;
+                self.runner.write_ppuctrl(data, rendering_enabled, &mut self.scroll);
+            }
+            PPUMASK => self.mask = data,
+            OAMADDR => self.oam_addr = data,
+            OAMDATA => {
+                // While rendering, OAM is busy with the sprite evaluation: the write is lost and
+                // only the sprite index of OAMADDR (bits 2-7) is incremented
+                if rendering_enabled && self.is_rendering_scanline() {
+                    self.oam_addr = self.oam_addr.wrapping_add(4);
+                } else {
+                    self.oam[self.oam_addr as usize] = data;
+                    self.oam_addr = self.oam_addr.wrapping_add(1);
+                }
+            }
+            PPUSCROLL => self.runner.write_ppuscroll(data, rendering_enabled, &mut self.scroll),
+            PPUADDR => self.runner.write_ppuaddr(data, rendering_enabled, &mut self.scroll),
+            PPUDATA => {
+                self.write_memory(self.scroll.vram_address() & 0x3FFF, data, cartridge);
+                self.increment_vram_address();
+            }
+            // PPUSTATUS is read-only
+            _ => {}
+        }
+    }
+
+    // Bits driven by an OAMDATA read: the attribute bytes have no storage for bits 2-4.
+    fn oam_driven_bits(&self) -> u8 {
+        if self.oam_addr % 4 == OAM_ATTRIBUTE_BYTE {
+            PpuOpenBus::OAM_ATTRIBUTE_DRIVEN_BITS
+        } else {
+            0xFF
+        }
+    }
+
+    // OAMDATA reads return the byte on the OAM bus. During the sprite evaluation (dots 1 - 256 of
+    // the visible scanlines, only run dot by dot with CycleAccurate), it is the byte the evaluation
+    // last read, 0xFF while secondary OAM is cleared.
+    fn oamdata(&self) -> u8 {
+        let clock = self.clock();
+        let evaluating = self.rendering_enabled() && clock.scanline < 240 && (1..=256).contains(&clock.dot);
+        if evaluating && self.runner.accuracy == Accuracy::CycleAccurate {
+            self.sprite_evaluator.oam_bus()
+        } else {
+            self.oam[self.oam_addr as usize]
+        }
+    }
+
+    // Frame, scanline and dot of the last sprite zero hit, once.
+    pub fn take_sprite_zero_hit(&mut self) -> Option<(u64, u16, u16)> {
+        let (scanline, dot) = self.status.take_sprite_zero_hit()?;
+        let clock = &self.runner.clock;
+        // The PPU may have been caught up past the end of the frame
+        let frame = if (scanline, dot) <= (clock.scanline, clock.dot) { clock.frame } else { clock.frame - 1 };
+        Some((frame, scanline, dot))
+    }
+
+    // Reset button: PPUCTRL, PPUMASK (turning rendering and the NMI off), the scroll, the write
+    // toggle and the PPUDATA read buffer are cleared, and writes to PPUCTRL, PPUMASK, PPUSCROLL and
+    // PPUADDR are ignored until the end of the next vblank. The memories and v are kept.
+    pub fn reset(&mut self) {
+        self.ctrl = 0;
+        self.mask = 0;
+        self.scroll.t = 0;
+        self.scroll.x = 0;
+        self.scroll.w = false;
+        self.read_buffer = 0;
+        let clock = &self.runner.clock;
+        let before_pre_render_line = (clock.scanline, clock.dot) < (PRE_RENDER_SCANLINE, 1);
+        self.reset_until_frame = Some(if before_pre_render_line { clock.frame } else { clock.frame + 1 });
+    }
+
+    fn is_resetting(&self) -> bool {
+        let clock = &self.runner.clock;
+        self.reset_until_frame.is_some_and(|frame| (clock.frame, clock.scanline, clock.dot) < (frame, PRE_RENDER_SCANLINE, 1))
+    }
+
+    // Visible and pre-render scanlines, during which the PPU fetches from its memories.
+    fn is_rendering_scanline(&self) -> bool {
+        let scanline = self.runner.clock.scanline;
+        scanline < 240 || scanline == 261
+    }
+
+    fn increment_vram_address(&mut self) {
+        let increment = if self.ctrl & PPUCTRL_INCREMENT_32 != 0 { 32 } else { 1 };
+        let rendering_enabled = self.rendering_enabled();
+        self.runner.increment_vram_address(increment, rendering_enabled, &mut self.scroll);
+    }
+
+    // Pattern tables of the cartridge: its CHR RAM, or its CHR ROM.
+    pub fn chr<'a>(&'a self, cartridge: Cartridge<'a>) -> &'a [u8] {
+        if self.chr_ram.is_empty() { cartridge.chr_rom } else { &self.chr_ram }
+    }
+
+    // Reads the PPU address space (0x0000 - 0x3FFF):
+    // 0x0000 - 0x1FFF: pattern tables, on the cartridge
+    // 0x2000 - 0x3EFF: nametables, in VRAM
+    // 0x3F00 - 0x3FFF: palette RAM
+    pub fn read_memory(&self, addr: u16, cartridge: Cartridge) -> u8 {
+        match addr & 0x3FFF {
+            addr @ 0x0000..=0x1FFF => {
+                let chr = self.chr(cartridge);
+                chr[cartridge.mapper.chr_index(addr) % chr.len()]
+            }
+            addr @ 0x2000..=0x3EFF => self.vram.read(addr, cartridge.mapper),
+            addr => self.palette_ram.read(addr, self.mask & PPUMASK_GREYSCALE != 0),
+        }
+    }
+
+    fn write_memory(&mut self, addr: u16, data: u8, cartridge: Cartridge) {
+        match addr {
+            // CHR ROM is read-only
+            0x0000..=0x1FFF if !self.chr_ram.is_empty() => {
+                let index = cartridge.mapper.chr_index(addr) % self.chr_ram.len();
+                self.chr_ram[index] = data;
+            }
+            0x0000..=0x1FFF => {}
+            0x2000..=0x3EFF => self.vram.write(addr, data, cartridge.mapper),
+            _ => self.palette_ram.write(addr, data),
+        }
+    }
+
+    pub fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.ctrl);
+        writer.write_u8(self.mask);
+        writer.write_u8(self.status.flags);
+        writer.write_u8(self.status.latches());
+        writer.write_u16(self.scroll.v);
+        writer.write_u16(self.scroll.t);
+        writer.write_u8(self.scroll.x);
+        writer.write_bool(self.scroll.w);
+        writer.write_u16(self.runner.clock.scanline);
+        writer.write_u16(self.runner.clock.dot);
+        writer.write_u64(self.runner.clock.frame);
+        writer.write_u64(self.runner.pending_dots());
+        writer.write_u64(self.dots);
+        writer.write_u8(self.oam_addr);
+        writer.write_bytes(&self.oam);
+        let (ciram, cartridge_vram) = self.vram.contents();
+        writer.write_bytes(ciram);
+        writer.write_bytes(cartridge_vram);
+        writer.write_bytes(self.palette_ram.data());
+        writer.write_bytes(&self.chr_ram);
+        writer.write_u8(self.read_buffer);
+        let (latch, refreshed_at) = self.open_bus.latch();
+        writer.write_u8(latch);
+        for cycle in refreshed_at {
+            writer.write_u64(cycle);
+        }
+        writer.write_bool(self.reset_until_frame.is_some());
+        writer.write_u64(self.reset_until_frame.unwrap_or(0));
+    }
+
+    // Reads a state written by `save_state` into this PPU, which must be freshly created for the
+    // same cartridge (see `restore_state`).
+    pub fn load_state(&mut self, reader: &mut StateReader) -> Result<(), String> {
+        self.ctrl = reader.read_u8()?;
+        self.mask = reader.read_u8()?;
+        self.status.flags = reader.read_u8()?;
+        self.status.set_latches(reader.read_u8()?);
+        self.scroll.v = reader.read_u16()?;
+        self.scroll.t = reader.read_u16()?;
+        self.scroll.x = reader.read_u8()?;
+        self.scroll.w = reader.read_bool()?;
+        self.runner.clock.scanline = reader.read_u16()?;
+        self.runner.clock.dot = reader.read_u16()?;
+        self.runner.clock.frame = reader.read_u64()?;
+        if self.runner.clock.scanline > 261 || self.runner.clock.dot > 340 {
+            return Err("Invalid savestate: PPU position out of the frame".to_string());
+        }
+        self.runner.set_pending_dots(reader.read_u64()?);
+        self.dots = reader.read_u64()?;
+        self.oam_addr = reader.read_u8()?;
+        self.oam.copy_from_slice(reader.read_bytes(OAM_SIZE)?);
+        let ciram = reader.read_bytes(0x800)?;
+        let cartridge_vram = reader.read_bytes(0x800)?;
+        self.vram.set_contents(ciram, cartridge_vram);
+        self.palette_ram.set_data(reader.read_bytes(32)?);
+        let chr_ram = reader.read_bytes(self.chr_ram.len())?;
+        self.chr_ram.copy_from_slice(chr_ram);
+        self.read_buffer = reader.read_u8()?;
+        let latch = reader.read_u8()?;
+        let mut refreshed_at = [0; 8];
+        for cycle in refreshed_at.iter_mut() {
+            *cycle = reader.read_u64()?;
+        }
+        self.open_bus.set_latch(latch, refreshed_at);
+        let resetting = reader.read_bool()?;
+        let reset_until_frame = reader.read_u64()?;
+        self.reset_until_frame = resetting.then_some(reset_until_frame);
+        if !matches!(self.clock_dots(), Some(dots) if dots >= self.dots) {
+            return Err("Invalid savestate: PPU clock behind the dots run".to_string());
+        }
+        Ok(())
+    }
+
+    // Applies the emulated state of `state`, loaded with `load_state`, keeping the settings of this
+    // PPU (accuracy, open bus decay).
+    pub fn restore_state(&mut self, state: Ppu) {
+        let accuracy = self.runner.accuracy;
+        self.ctrl = state.ctrl;
+        self.mask = state.mask;
+        self.status = state.status;
+        self.scroll = state.scroll;
+        self.runner = state.runner;
+        self.runner.accuracy = accuracy;
+        self.oam_addr = state.oam_addr;
+        self.oam = state.oam;
+        self.vram = state.vram;
+        self.palette_ram = state.palette_ram;
+        self.chr_ram = state.chr_ram;
+        self.read_buffer = state.read_buffer;
+        let (latch, refreshed_at) = state.open_bus.latch();
+        self.open_bus.set_latch(latch, refreshed_at);
+        self.dots = state.dots;
+        self.reset_until_frame = state.reset_until_frame;
+        self.nmi = None;
+        // The framebuffer and the sprites of the current scanline are not part of the state, they
+        // are drawn again from the next frame and scanline
+        self.completed_frame = self.last_vblank();
+        self.sprite_evaluator = SpriteEvaluator::new();
+        self.scanline_sprites = ScanlineSprites::default();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bus::Bus;
+    use crate::cpu6502::{new_cpu, CPU};
+    use crate::event_viewer::EventKind;
+    use crate::palette::Palette;
+    use crate::power_on::PowerOnConfig;
+    use crate::ppu::accuracy::Accuracy;
+    use crate::ppu::OAM_SIZE;
+    use crate::ppu::collisions::{Rect, SpriteOverlap};
+    use crate::ppu::pixel_info::PixelLayer;
+    use crate::ppu::open_bus::DECAY_CYCLES;
+    use crate::ppu::render_thread::SCREEN_WIDTH;
+    use crate::ppu::timing::{PPUSTATUS_SPRITE_OVERFLOW, PPUSTATUS_SPRITE_ZERO_HIT};
+    use crate::rom::Rom;
+
+    // CPU spinning on `JMP $0300`
+    fn powered_on_cpu(rom: Rom) -> CPU {
+        let mut cpu = new_cpu(Bus::new(rom));
+        cpu.power_on(&PowerOnConfig::default()).expect("power on should succeed");
+        for (offset, byte) in [0x4C, 0x00, 0x03].into_iter().enumerate() {
+            cpu.write_u8(0x0300 + offset as u16, byte);
+        }
+        cpu.program_counter = 0x0300;
+        cpu
+    }
+
+    fn set_ppu_address(cpu: &mut CPU, addr: u16) {
+        cpu.write_u8(0x2006, (addr >> 8) as u8);
+        cpu.write_u8(0x2006, addr as u8);
+    }
+
+    #[test]
+    fn test_vblank_flag_and_nmi() {
+        let mut cpu = powered_on_cpu(Rom::test_rom());
+        cpu.write_u8(0x2000, 0x80);
+        let stack_pointer = cpu.stack_pointer;
+        // Vblank starts on dot 1 of scanline 241
+        let vblank_cycle = (241 * 341 + 1) / 3;
+
+        cpu.run_until(vblank_cycle - 3);
+        assert_eq!(cpu.read_u8(0x2002) & 0x80, 0x00);
+        assert_eq!(cpu.stack_pointer, stack_pointer);
+
+        cpu.run_until(vblank_cycle + 10);
+        // The NMI pushed PC and the status
+        assert_eq!(cpu.stack_pointer, stack_pointer.wrapping_sub(3));
+        assert_eq!(cpu.read_u8(0x2002) & 0x80, 0x80);
+        // Reading PPUSTATUS clears the flag
+        assert_eq!(cpu.read_u8(0x2002) & 0x80, 0x00);
+    }
+
+    #[test]
+    fn test_enabling_nmi_during_vblank() {
+        let mut cpu = powered_on_cpu(Rom::test_rom());
+        let stack_pointer = cpu.stack_pointer;
+        cpu.run_until((241 * 341 + 1) / 3 + 10);
+        assert_eq!(cpu.stack_pointer, stack_pointer);
+
+        // STA $2000 with A = 0x80, then NOP
+        cpu.accumulator = 0x80;
+        for (offset, byte) in [0x8D, 0x00, 0x20, 0xEA].into_iter().enumerate() {
+            cpu.write_u8(0x0400 + offset as u16, byte);
+        }
+        cpu.program_counter = 0x0400;
+        cpu.step();
+        // The write is on the last cycle of STA, after its interrupt polling
+        assert_eq!(cpu.stack_pointer, stack_pointer);
+        cpu.step();
+        assert_eq!(cpu.stack_pointer, stack_pointer.wrapping_sub(3));
+    }
+
+    #[test]
+    fn test_ppudata_reads_are_buffered() {
+        let mut cpu = powered_on_cpu(Rom::test_rom());
+        set_ppu_address(&mut cpu, 0x2000);
+        cpu.write_u8(0x2007, 0x11);
+        cpu.write_u8(0x2007, 0x22);
+
+        set_ppu_address(&mut cpu, 0x2000);
+        // The first read returns the stale buffer
+        cpu.read_u8(0x2007);
+        assert_eq!(cpu.read_u8(0x2007), 0x11);
+        assert_eq!(cpu.read_u8(0x2007), 0x22);
+
+        // PPUCTRL bit 2 increments the address by 32
+        cpu.write_u8(0x2000, 0x04);
+        set_ppu_address(&mut cpu, 0x2400);
+        cpu.write_u8(0x2007, 0x33);
+        cpu.write_u8(0x2007, 0x44);
+        assert_eq!(cpu.bus.ppu().scroll.vram_address(), 0x2440);
+    }
+
+    #[test]
+    fn test_palette_reads_are_not_buffered() {
+        let mut cpu = powered_on_cpu(Rom::test_rom());
+        set_ppu_address(&mut cpu, 0x2F40);
+        cpu.write_u8(0x2007, 0x55);
+        set_ppu_address(&mut cpu, 0x3F00);
+        cpu.write_u8(0x2007, 0xEC);
+
+        // Bits 7-6 come from the open bus latch: 0x40, the last write to PPUADDR
+        cpu.write_u8(0x2006, 0x3F);
+        cpu.write_u8(0x2006, 0x40);
+        assert_eq!(cpu.read_u8(0x2007), 0x6C);
+        // The buffer got the nametable byte under the palette
+        assert_eq!(cpu.bus.ppu().read_buffer, 0x55);
+    }
+
+    #[test]
+    fn test_chr_ram_through_ppudata() {
+        let mut rom = Rom::test_rom();
+        rom.chr_rom = Vec::new();
+        let mut cpu = powered_on_cpu(rom);
+        set_ppu_address(&mut cpu, 0x1234);
+        cpu.write_u8(0x2007, 0x99);
+        set_ppu_address(&mut cpu, 0x1234);
+        cpu.read_u8(0x2007);
+        assert_eq!(cpu.read_u8(0x2007), 0x99);
+
+        // CHR ROM is read-only
+        let mut cpu = powered_on_cpu(Rom::test_rom());
+        set_ppu_address(&mut cpu, 0x1234);
+        cpu.write_u8(0x2007, 0x99);
+        set_ppu_address(&mut cpu, 0x1234);
+        cpu.read_u8(0x2007);
+        assert_eq!(cpu.read_u8(0x2007), 0x00);
+    }
+
+    #[test]
+    fn test_oamdata() {
+        let mut cpu = powered_on_cpu(Rom::test_rom());
+        cpu.write_u8(0x2003, 0x10);
+        cpu.write_u8(0x2004, 0xFF);
+        cpu.write_u8(0x2004, 0xFF);
+        cpu.write_u8(0x2004, 0xFF);
+        cpu.write_u8(0x2003, 0x10);
+        assert_eq!(cpu.read_u8(0x2004), 0xFF);
+        // Reads do not increment OAMADDR
+        assert_eq!(cpu.read_u8(0x2004), 0xFF);
+
+        // Bits 2-4 of the attributes come from the open bus latch
+        cpu.write_u8(0x2003, 0x12);
+        assert_eq!(cpu.read_u8(0x2004), 0xE3 | (0x12 & 0x1C));
+    }
+
+    #[test]
+    fn test_open_bus_latch() {
+        let mut cpu = powered_on_cpu(Rom::test_rom());
+        cpu.write_u8(0x2000, 0x00);
+        cpu.write_u8(0x2001, 0x1F);
+        // Write-only registers and the low bits of PPUSTATUS return the latch
+        assert_eq!(cpu.read_u8(0x2005), 0x1F);
+        assert_eq!(cpu.read_u8(0x2002) & 0x1F, 0x1F);
+        assert_eq!(cpu.peek_u8(0x2003), 0x1F);
+
+        // The latch decays once it is not refreshed for long enough
+        let cycle = cpu.bus.access_cycle();
+        cpu.bus.set_access_context(cycle + DECAY_CYCLES, cpu.program_counter);
+        assert_eq!(cpu.read_u8(0x2005), 0x00);
+    }
+
+    #[test]
+    fn test_peek_has_no_side_effect() {
+        let mut cpu = powered_on_cpu(Rom::test_rom());
+        cpu.run_until((241 * 341 + 1) / 3 + 10);
+        assert_eq!(cpu.peek_u8(0x2002) & 0x80, 0x80);
+        assert_eq!(cpu.peek_u8(0x2002) & 0x80, 0x80);
+        // Mirrors every 8 bytes
+        assert_eq!(cpu.read_u8(0x3FFA) & 0x80, 0x80);
+        assert_eq!(cpu.peek_u8(0x2002) & 0x80, 0x00);
+    }
+
+    #[test]
+    fn test_framebuffer_with_rendering_disabled() {
+        let mut cpu = powered_on_cpu(Rom::test_rom());
+        set_ppu_address(&mut cpu, 0x3F00);
+        cpu.write_u8(0x2007, 0x21);
+        set_ppu_address(&mut cpu, 0x2000);
+        cpu.write_u8(0x2001, 0x01);
+        cpu.run_frame();
+
+        // The whole frame shows the backdrop, in greyscale
+        let ppu = cpu.bus.ppu();
+        assert_eq!(ppu.completed_frame(), Some(0));
+        let color = Palette::default().color(0x21, 0x01);
+        assert!(ppu.framebuffer().chunks(3).all(|pixel| pixel == [color.0, color.1, color.2]));
+    }
+
+    #[test]
+    fn test_framebuffer_shows_palette_entry_at_v() {
+        let mut cpu = powered_on_cpu(Rom::test_rom());
+        set_ppu_address(&mut cpu, 0x3F05);
+        cpu.write_u8(0x2007, 0x16);
+        set_ppu_address(&mut cpu, 0x3F05);
+        cpu.run_frame();
+
+        let color = Palette::default().color(0x16, 0x00);
+        assert_eq!(cpu.bus.ppu().framebuffer()[..3], [color.0, color.1, color.2]);
+    }
+
+    #[test]
+    fn test_odd_frames_skip_a_dot_while_rendering() {
+        let mut cpu = powered_on_cpu(Rom::test_rom());
+        cpu.write_u8(0x2001, 0x08);
+        cpu.run_frame();
+        cpu.run_frame();
+        cpu.run_frame();
+        cpu.sync_ppu(cpu.cycles);
+        // Frame 1 was odd
+        assert_eq!(cpu.bus.ppu().skipped_dots(), 1);
+        assert_eq!(cpu.frame_number(), 3);
+        assert_eq!(cpu.bus.ppu().clock().frame, 3);
+    }
+
+    // Frame 1 of a background with tile 1 (its left half in color 1) at the second column of the
+    // first row, in palette 1, scrolled 4 pixels to the left.
+    fn render_background(accuracy: Accuracy, mask: u8) -> Vec<u8> {
+        let mut rom = Rom::test_rom();
+        rom.chr_rom[0x10..0x18].fill(0xF0);
+        let mut cpu = powered_on_cpu(rom);
+        cpu.bus.ppu_mut().runner.accuracy = accuracy;
+        for (addr, data) in [(0x2001, 0x01), (0x23C0, 0b01), (0x3F00, 0x0F), (0x3F05, 0x16)] {
+            set_ppu_address(&mut cpu, addr);
+            cpu.write_u8(0x2007, data);
+        }
+        cpu.read_u8(0x2002);
+        cpu.write_u8(0x2005, 4);
+        cpu.write_u8(0x2005, 0);
+        cpu.write_u8(0x2000, 0x00);
+        cpu.write_u8(0x2001, mask);
+        cpu.run_frame();
+        cpu.run_frame();
+        assert_eq!(cpu.bus.ppu().completed_frame(), Some(1));
+        cpu.bus.ppu().framebuffer().to_vec()
+    }
+
+    fn pixel(framebuffer: &[u8], x: usize, y: usize) -> (u8, u8, u8) {
+        let start = (y * SCREEN_WIDTH + x) * 3;
+        (framebuffer[start], framebuffer[start + 1], framebuffer[start + 2])
+    }
+
+    #[test]
+    fn test_background() {
+        let palette = Palette::default();
+        let (backdrop, color) = (palette.color(0x0F, 0x00), palette.color(0x16, 0x00));
+        for accuracy in [Accuracy::Fast, Accuracy::CycleAccurate] {
+            let framebuffer = render_background(accuracy, 0x0A);
+            for y in 0..8 {
+                assert_eq!(pixel(&framebuffer, 3, y), backdrop, "{:?}", accuracy);
+                for x in 4..8 {
+                    assert_eq!(pixel(&framebuffer, x, y), color, "{:?}", accuracy);
+                }
+                assert_eq!(pixel(&framebuffer, 8, y), backdrop, "{:?}", accuracy);
+            }
+            assert_eq!(pixel(&framebuffer, 4, 8), backdrop, "{:?}", accuracy);
+
+            // Hidden in the leftmost 8 pixels
+            let framebuffer = render_background(accuracy, 0x08);
+            assert_eq!(pixel(&framebuffer, 4, 0), backdrop, "{:?}", accuracy);
+        }
+        assert_eq!(render_background(Accuracy::Fast, 0x0A), render_background(Accuracy::CycleAccurate, 0x0A));
+    }
+
+    // Tile 1 (its left half in color 1) at the second column of the first row, and the sprites of
+    // `oam` with the same tile. Runs until scanline 20 of frame 1.
+    fn render_sprites(accuracy: Accuracy, oam: &[u8]) -> CPU {
+        let mut rom = Rom::test_rom();
+        rom.chr_rom[0x10..0x18].fill(0xF0);
+        let mut cpu = powered_on_cpu(rom);
+        cpu.bus.ppu_mut().runner.accuracy = accuracy;
+        for (addr, data) in [(0x2001, 0x01), (0x3F00, 0x0F), (0x3F15, 0x2A)] {
+            set_ppu_address(&mut cpu, addr);
+            cpu.write_u8(0x2007, data);
+        }
+        cpu.read_u8(0x2002);
+        cpu.write_u8(0x2005, 0);
+        cpu.write_u8(0x2005, 0);
+        cpu.write_u8(0x2000, 0x00);
+        cpu.write_u8(0x2003, 0);
+        // The other sprites are hidden below the screen
+        for byte in oam.iter().chain(&[0xFF; OAM_SIZE][oam.len()..]) {
+            cpu.write_u8(0x2004, *byte);
+        }
+        cpu.write_u8(0x2001, 0x1E);
+        cpu.run_frame();
+        while cpu.bus.ppu().clock().scanline < 20 {
+            cpu.step();
+        }
+        cpu
+    }
+
+    #[test]
+    fn test_pixel_info() {
+        for accuracy in [Accuracy::Fast, Accuracy::CycleAccurate] {
+            let mut cpu = render_sprites(accuracy, &[2, 0x01, 0x01, 10]);
+            cpu.bus.ppu_mut().pixel_info.set_enabled(true);
+            cpu.run_frame();
+            cpu.run_frame();
+            let ppu = cpu.bus.ppu();
+            let pixel_info = |x, y| ppu.pixel_info.pixel(x, y).unwrap().layer;
+            assert_eq!(pixel_info(8, 0), PixelLayer::Background, "{:?}", accuracy);
+            assert_eq!(pixel_info(10, 3), PixelLayer::Sprite(0), "{:?}", accuracy);
+            assert_eq!(pixel_info(14, 3), PixelLayer::Backdrop, "{:?}", accuracy);
+            let sprite = ppu.pixel_info.pixel(13, 10).unwrap();
+            assert_eq!((sprite.palette, sprite.value, sprite.color), (5, 1, 0x2A), "{:?}", accuracy);
+            assert_eq!(ppu.pixel_info.sprite_pixels(0).count(), 4 * 8, "{:?}", accuracy);
+        }
+    }
+
+    #[test]
+    fn test_collisions() {
+        for accuracy in [Accuracy::Fast, Accuracy::CycleAccurate] {
+            let mut cpu = render_sprites(accuracy, &[2, 0x01, 0x01, 10, 20, 0x01, 0x01, 60]);
+            cpu.bus.ppu_mut().collisions.set_enabled(true);
+            cpu.run_frame();
+            cpu.run_frame();
+            let collisions = cpu.bus.ppu().collisions.last_frame().clone();
+            // The background tile covers x = 8 to 11 of scanlines 0 to 7
+            assert_eq!(collisions.sprite_zero_hit, Some((10, 3)), "{:?}", accuracy);
+            let area = Rect { x: 10, y: 3, width: 2, height: 5 };
+            assert_eq!(collisions.sprite_zero_hit_area, Some(area), "{:?}", accuracy);
+            assert_eq!(collisions.overlaps, vec![SpriteOverlap { sprite: 0, area }], "{:?}", accuracy);
+        }
+    }
+
+    #[test]
+    fn test_sprite_zero_hit_event() {
+        for accuracy in [Accuracy::Fast, Accuracy::CycleAccurate] {
+            let mut cpu = render_sprites(accuracy, &[2, 0x01, 0x01, 10]);
+            cpu.enable_event_log();
+            cpu.run_frame();
+            // Polls PPUSTATUS until the hit of frame 2
+            while cpu.read_u8(0x2002) & PPUSTATUS_SPRITE_ZERO_HIT == 0 {
+                cpu.step();
+            }
+            cpu.step();
+            let log = cpu.event_log.as_ref().unwrap();
+            let hits: Vec<(u64, u64)> = log.current_frame().iter().filter(|event| event.kind == EventKind::SpriteZeroHit).map(|event| (event.scanline, event.dot)).collect();
+            assert_eq!(hits, vec![(3, 11)], "{:?}", accuracy);
+            // Frame 1 was enabled after its hit
+            assert!(log.previous_frame().iter().all(|event| event.kind != EventKind::SpriteZeroHit), "{:?}", accuracy);
+        }
+    }
+
+    #[test]
+    fn test_sprites_and_sprite_zero_hit() {
+        let palette = Palette::default();
+        let (backdrop, color) = (palette.color(0x0F, 0x00), palette.color(0x2A, 0x00));
+        for accuracy in [Accuracy::Fast, Accuracy::CycleAccurate] {
+            // Sprite 0 at Y = 2 is drawn from scanline 3, on top of the background
+            let mut cpu = render_sprites(accuracy, &[2, 0x01, 0x01, 10]);
+            let status = cpu.bus.ppu().status.flags;
+            assert_eq!(status & PPUSTATUS_SPRITE_ZERO_HIT, PPUSTATUS_SPRITE_ZERO_HIT, "{:?}", accuracy);
+            assert_eq!(status & PPUSTATUS_SPRITE_OVERFLOW, 0, "{:?}", accuracy);
+            cpu.run_frame();
+            let ppu = cpu.bus.ppu();
+            let framebuffer = ppu.framebuffer();
+            assert_eq!(pixel(framebuffer, 10, 2), palette.color(ppu.palette_ram.read(0x3F01, false), 0), "{:?}", accuracy);
+            for y in 3..11 {
+                assert_eq!(pixel(framebuffer, 10, y), color, "{:?}", accuracy);
+                assert_eq!(pixel(framebuffer, 13, y), color, "{:?}", accuracy);
+                assert_eq!(pixel(framebuffer, 14, y), backdrop, "{:?}", accuracy);
+            }
+            assert_eq!(pixel(framebuffer, 10, 11), backdrop, "{:?}", accuracy);
+
+            // No hit over a transparent background pixel
+            let cpu = render_sprites(accuracy, &[2, 0x01, 0x01, 12]);
+            assert_eq!(cpu.bus.ppu().status.flags & PPUSTATUS_SPRITE_ZERO_HIT, 0, "{:?}", accuracy);
+        }
+    }
+
+    #[test]
+    fn test_sprite_overflow() {
+        let oam: Vec<u8> = (0..9).flat_map(|sprite| [10, 0x01, 0x00, sprite * 16]).collect();
+        for accuracy in [Accuracy::Fast, Accuracy::CycleAccurate] {
+            let cpu = render_sprites(accuracy, &oam[..32]);
+            assert_eq!(cpu.bus.ppu().status.flags & PPUSTATUS_SPRITE_OVERFLOW, 0, "{:?}", accuracy);
+            let cpu = render_sprites(accuracy, &oam);
+            assert_eq!(cpu.bus.ppu().status.flags & PPUSTATUS_SPRITE_OVERFLOW, PPUSTATUS_SPRITE_OVERFLOW, "{:?}", accuracy);
+        }
+    }
+}
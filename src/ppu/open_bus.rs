@@ -0,0 +1,129 @@
+// The PPU has an 8-bit I/O latch between its registers and the CPU data bus.
+// Every write to a PPU register (0x2000 - 0x2007) fills the latch, and reads only drive the bits
+// the register actually has: the other bits return the stale latch content ("open bus").
+// - Write-only registers (PPUCTRL, PPUMASK, OAMADDR, PPUSCROLL, PPUADDR) return the whole latch.
+// - PPUSTATUS only drives bits 7-5, bits 4-0 come from the latch.
+// - OAMDATA does not drive bits 4-2 of sprite attributes (byte 2 of each sprite).
+// - PPUDATA does not drive bits 7-6 when reading palette RAM.
+// The latch is a capacitor: a bit that is not refreshed decays to 0 after about 600 ms.
+// This is verified by the ppu_open_bus test ROM.
+
+// Decay delay in CPU cycles (600 ms at 1.789773 MHz)
+pub const DECAY_CYCLES: u64 = 1_073_864;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PpuOpenBus {
+    value: u8,
+    // CPU cycle of the last refresh of each bit
+    refreshed_at: [u64; 8],
+    // None disables decay, the latch then keeps its value forever
+    decay_cycles: Option<u64>,
+}
+
+impl Default for PpuOpenBus {
+    fn default() -> Self {
+        Self {
+            value: 0,
+            refreshed_at: [0; 8],
+            decay_cycles: Some(DECAY_CYCLES),
+        }
+    }
+}
+
+impl PpuOpenBus {
+    // Bits driven by the reads that do not drive the whole data bus.
+    pub const PPUSTATUS_DRIVEN_BITS: u8 = 0b1110_0000;
+    pub const OAM_ATTRIBUTE_DRIVEN_BITS: u8 = 0b1110_0011;
+    pub const PALETTE_DRIVEN_BITS: u8 = 0b0011_1111;
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set_decay(&mut self, decay_cycles: Option<u64>) {
+        self.decay_cycles = decay_cycles;
+    }
+
+    // Current latch content at CPU cycle `now`, decayed bits reading 0.
+    pub fn value(&self, now: u64) -> u8 {
+        let Some(decay_cycles) = self.decay_cycles else {
+            return self.value;
+        };
+        (0..8)
+            .filter(|bit| now.saturating_sub(self.refreshed_at[*bit]) < decay_cycles)
+            .fold(0, |value, bit| value | (self.value & (1 << bit)))
+    }
+
+    // Any write to a PPU register drives all the bits.
+    pub fn write(&mut self, data: u8, now: u64) {
+        self.refresh(data, 0xFF, now);
+    }
+
+    // Read of a register only driving `driven_bits` of `data`. The other bits come from the latch.
+    // The driven bits are stored in the latch, so they can be read back from a write-only register.
+    pub fn read(&mut self, data: u8, driven_bits: u8, now: u64) -> u8 {
+        let value = (data & driven_bits) | (self.value(now) & !driven_bits);
+        self.refresh(data, driven_bits, now);
+        value
+    }
+
+    // Latch content and refresh cycle of each bit, for savestates.
+    pub fn latch(&self) -> (u8, [u64; 8]) {
+        (self.value, self.refreshed_at)
+    }
+
+    pub fn set_latch(&mut self, value: u8, refreshed_at: [u64; 8]) {
+        self.value = value;
+        self.refreshed_at = refreshed_at;
+    }
+
+    fn refresh(&mut self, data: u8, driven_bits: u8, now: u64) {
+        self.value = (self.value(now) & !driven_bits) | (data & driven_bits);
+        for bit in 0..8 {
+            if driven_bits & (1 << bit) != 0 {
+                self.refreshed_at[bit] = now;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ppu::open_bus::{PpuOpenBus, DECAY_CYCLES};
+
+    #[test]
+    fn test_write_only_registers_return_latch() {
+        let mut open_bus = PpuOpenBus::new();
+        open_bus.write(0x5A, 0);
+        // Reading PPUCTRL drives no bit
+        assert_eq!(open_bus.read(0x00, 0x00, 10), 0x5A);
+    }
+
+    #[test]
+    fn test_partially_driven_reads() {
+        let mut open_bus = PpuOpenBus::new();
+        open_bus.write(0x1F, 0);
+        // PPUSTATUS with vblank set: bits 4-0 are stale
+        assert_eq!(open_bus.read(0x80, PpuOpenBus::PPUSTATUS_DRIVEN_BITS, 10), 0x9F);
+        // The driven bits refreshed the latch
+        assert_eq!(open_bus.value(20), 0x9F);
+
+        // Palette reads: bits 7-6 are stale
+        open_bus.write(0xC0, 30);
+        assert_eq!(open_bus.read(0x2A, PpuOpenBus::PALETTE_DRIVEN_BITS, 40), 0xEA);
+    }
+
+    #[test]
+    fn test_decay() {
+        let mut open_bus = PpuOpenBus::new();
+        open_bus.write(0xFF, 0);
+        // Bits 7-5 are refreshed later by a PPUSTATUS read, so they decay later
+        open_bus.read(0xE0, PpuOpenBus::PPUSTATUS_DRIVEN_BITS, 1000);
+        assert_eq!(open_bus.value(DECAY_CYCLES - 1), 0xFF);
+        assert_eq!(open_bus.value(DECAY_CYCLES), 0xE0);
+        assert_eq!(open_bus.value(DECAY_CYCLES + 1000), 0x00);
+
+        open_bus.set_decay(None);
+        assert_eq!(open_bus.value(u64::MAX), 0xFF);
+    }
+}
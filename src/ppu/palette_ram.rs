@@ -0,0 +1,105 @@
+use crate::ppu::pattern_tables::PALETTE_RAM_SIZE;
+
+// Palette RAM (0x3F00 - 0x3FFF): 32 bytes mirrored every 0x20.
+// Entry 0 of each palette is transparent, so the PPU has no storage for entry 0 of the sprite
+// palettes: 0x3F10, 0x3F14, 0x3F18 and 0x3F1C are mirrors of 0x3F00, 0x3F04, 0x3F08 and 0x3F0C.
+// Writing 0x3F10 therefore changes the backdrop color, which many games rely on.
+// Only 6 bits are stored, the upper 2 bits of PPUDATA reads come from the open bus.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PaletteRam {
+    data: [u8; PALETTE_RAM_SIZE],
+}
+
+impl Default for PaletteRam {
+    fn default() -> Self {
+        Self { data: [0; PALETTE_RAM_SIZE] }
+    }
+}
+
+impl PaletteRam {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // In greyscale mode (bit 0 of PPUMASK), reads only return the grey column, like the rendering.
+    pub fn read(&self, addr: u16, greyscale: bool) -> u8 {
+        let value = self.data[mirror_palette_address(addr)];
+        if greyscale { value & 0x30 } else { value }
+    }
+
+    pub fn write(&mut self, addr: u16, data: u8) {
+        self.data[mirror_palette_address(addr)] = data & 0x3F;
+    }
+
+    // Color used where every layer is transparent.
+    pub fn backdrop(&self) -> u8 {
+        self.data[0]
+    }
+
+    // Color index of a pixel of palette `palette` (0 - 3 background, 4 - 7 sprites).
+    pub fn color(&self, palette: usize, pixel: u8) -> u8 {
+        match pixel & 0x03 {
+            0 => self.backdrop(),
+            pixel => self.data[(palette % 8) * 4 + pixel as usize],
+        }
+    }
+
+    // The 32 bytes of storage, for savestates.
+    pub fn data(&self) -> &[u8; PALETTE_RAM_SIZE] {
+        &self.data
+    }
+
+    pub fn set_data(&mut self, data: &[u8]) {
+        for (entry, value) in self.data.iter_mut().zip(data) {
+            *entry = value & 0x3F;
+        }
+    }
+
+    // The 32 entries as seen through their addresses, mirrors included, for the palette viewer.
+    pub fn entries(&self) -> [u8; PALETTE_RAM_SIZE] {
+        std::array::from_fn(|index| self.data[mirror_palette_address(index as u16)])
+    }
+}
+
+// Converts a palette address (0x3F00 - 0x3FFF) to an index in the 32 bytes of palette RAM.
+pub fn mirror_palette_address(addr: u16) -> usize {
+    let index = (addr & 0x1F) as usize;
+    if index & 0x13 == 0x10 { index & 0x0F } else { index }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ppu::palette_ram::{mirror_palette_address, PaletteRam};
+
+    #[test]
+    fn test_mirror_palette_address() {
+        assert_eq!(mirror_palette_address(0x3F00), 0x00);
+        assert_eq!(mirror_palette_address(0x3F10), 0x00);
+        assert_eq!(mirror_palette_address(0x3F14), 0x04);
+        assert_eq!(mirror_palette_address(0x3F1C), 0x0C);
+        // Other sprite entries are distinct
+        assert_eq!(mirror_palette_address(0x3F11), 0x11);
+        assert_eq!(mirror_palette_address(0x3F1F), 0x1F);
+        // Mirrored every 0x20 bytes
+        assert_eq!(mirror_palette_address(0x3FE5), 0x05);
+        assert_eq!(mirror_palette_address(0x3FF0), 0x00);
+    }
+
+    #[test]
+    fn test_backdrop_mirroring() {
+        let mut palette_ram = PaletteRam::new();
+        palette_ram.write(0x3F10, 0x21);
+        assert_eq!(palette_ram.backdrop(), 0x21);
+        assert_eq!(palette_ram.read(0x3F00, false), 0x21);
+
+        palette_ram.write(0x3F05, 0xFF);
+        assert_eq!(palette_ram.read(0x3F05, false), 0x3F);
+        assert_eq!(palette_ram.read(0x3F05, true), 0x30);
+
+        // Transparent pixels of sprite palettes show the backdrop, not 0x3F10's neighbours
+        palette_ram.write(0x3F15, 0x16);
+        assert_eq!(palette_ram.color(5, 0), 0x21);
+        assert_eq!(palette_ram.color(5, 1), 0x16);
+        assert_eq!(palette_ram.entries()[0x10], 0x21);
+    }
+}
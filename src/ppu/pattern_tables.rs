@@ -0,0 +1,110 @@
+use crate::palette::{Palette, Rgb};
+
+// Pattern table viewer: decodes the CHR tiles into tile sheets, like the CHR viewers of Mesen and FCEUX.
+// A pattern table (4KB) holds 256 tiles of 8x8 pixels, shown as a 16x16 grid of tiles (128x128 pixels).
+// Each tile is 16 bytes: 8 bytes for bit 0 of each row, then 8 bytes for bit 1, the leftmost pixel
+// being bit 7. The 2-bit pixel values select one of the 4 colors of a palette.
+
+pub const PATTERN_TABLE_SIZE: usize = 0x1000;
+pub const TILE_SHEET_WIDTH: usize = 128;
+pub const TILE_SHEET_HEIGHT: usize = 128;
+
+// Palette RAM (0x3F00 - 0x3F1F): 4 background palettes then 4 sprite palettes of 4 colors.
+pub const PALETTE_RAM_SIZE: usize = 32;
+
+// Decodes pattern table `table` (0 for 0x0000, 1 for 0x1000) into 128x128 pixel values (0 - 3).
+// Missing CHR data (e.g. CHR RAM smaller than 8KB) is decoded as blank tiles.
+pub fn decode_pattern_table(chr: &[u8], table: usize) -> Vec<u8> {
+    let mut pixels = vec![0; TILE_SHEET_WIDTH * TILE_SHEET_HEIGHT];
+    let base = table * PATTERN_TABLE_SIZE;
+    for tile in 0..256 {
+        let tile_x = (tile % 16) * 8;
+        let tile_y = (tile / 16) * 8;
+        for row in 0..8 {
+            let address = base + tile * 16 + row;
+            let low = chr.get(address).copied().unwrap_or(0);
+            let high = chr.get(address + 8).copied().unwrap_or(0);
+            for column in 0..8 {
+                let bit = 7 - column;
+                let value = ((low >> bit) & 1) | (((high >> bit) & 1) << 1);
+                pixels[(tile_y + row) * TILE_SHEET_WIDTH + tile_x + column] = value;
+            }
+        }
+    }
+    pixels
+}
+
+// Renders pattern table `table` to RGB24 with palette `palette_index` (0 - 3 for the background
+// palettes, 4 - 7 for the sprite ones). Pixel value 0 is drawn with the backdrop color, as on screen.
+pub fn render_pattern_table(chr: &[u8], table: usize, palette_ram: &[u8; PALETTE_RAM_SIZE], palette_index: usize, palette: &Palette) -> Vec<u8> {
+    let colors: [u8; 4] = std::array::from_fn(|value| match value {
+        0 => palette_ram[0],
+        _ => palette_ram[(palette_index % 8) * 4 + value],
+    });
+    let indexes: Vec<u8> = decode_pattern_table(chr, table).iter().map(|value| colors[*value as usize]).collect();
+    let mut rgb = vec![0; indexes.len() * 3];
+    palette.render_scanline(&indexes, 0, &mut rgb);
+    rgb
+}
+
+// Both pattern tables, side by side (256x128 pixels, RGB24)
+pub fn render_pattern_tables(chr: &[u8], palette_ram: &[u8; PALETTE_RAM_SIZE], palette_index: usize, palette: &Palette) -> Vec<u8> {
+    let tables = [0, 1].map(|table| render_pattern_table(chr, table, palette_ram, palette_index, palette));
+    let row_size = TILE_SHEET_WIDTH * 3;
+    let mut rgb = Vec::with_capacity(2 * tables[0].len());
+    for y in 0..TILE_SHEET_HEIGHT {
+        for table in &tables {
+            rgb.extend_from_slice(&table[y * row_size..(y + 1) * row_size]);
+        }
+    }
+    rgb
+}
+
+// The 32 colors of palette RAM as RGB, for a palette viewer.
+pub fn palette_ram_colors(palette_ram: &[u8; PALETTE_RAM_SIZE], palette: &Palette) -> [Rgb; PALETTE_RAM_SIZE] {
+    std::array::from_fn(|index| palette.color(palette_ram[index], 0))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::palette::Palette;
+    use crate::ppu::pattern_tables::{decode_pattern_table, palette_ram_colors, render_pattern_tables, PALETTE_RAM_SIZE, TILE_SHEET_WIDTH};
+
+    #[test]
+    fn test_decode_tile() {
+        let mut chr = vec![0; 0x2000];
+        // Tile 1 of the first table, first row: values 0, 1, 2, 3, 0, 0, 0, 0
+        chr[0x10] = 0b0101_0000;
+        chr[0x18] = 0b0011_0000;
+        // Tile 17 (second row of tiles) of the second table, last row: value 3 on the last pixel
+        chr[0x1000 + 17 * 16 + 7] = 0b0000_0001;
+        chr[0x1000 + 17 * 16 + 15] = 0b0000_0001;
+
+        let first = decode_pattern_table(&chr, 0);
+        assert_eq!(&first[8..16], &[0, 1, 2, 3, 0, 0, 0, 0]);
+        let second = decode_pattern_table(&chr, 1);
+        assert_eq!(second[15 * TILE_SHEET_WIDTH + 15], 3);
+        assert_eq!(second.iter().filter(|value| **value != 0).count(), 1);
+
+        // CHR RAM smaller than the pattern tables
+        assert!(decode_pattern_table(&[0xFF; 16], 1).iter().all(|value| *value == 0));
+    }
+
+    #[test]
+    fn test_render_with_palette() {
+        let mut chr = vec![0; 0x2000];
+        chr[0x1008] = 0x80; // First pixel of the second table: value 2
+        let mut palette_ram = [0x0F; PALETTE_RAM_SIZE];
+        palette_ram[0] = 0x21;
+        palette_ram[4 * 5 + 2] = 0x16; // Second sprite palette, color 2
+
+        let palette = Palette::default();
+        let rgb = render_pattern_tables(&chr, &palette_ram, 5, &palette);
+        assert_eq!(rgb.len(), 256 * 128 * 3);
+        let pixel = |x: usize| (rgb[x * 3], rgb[x * 3 + 1], rgb[x * 3 + 2]);
+        assert_eq!(pixel(0), palette.color(0x21, 0));
+        assert_eq!(pixel(128), palette.color(0x16, 0));
+
+        assert_eq!(palette_ram_colors(&palette_ram, &palette)[22], palette.color(0x16, 0));
+    }
+}
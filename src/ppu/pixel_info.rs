@@ -0,0 +1,190 @@
+use crate::ppu::palette_ram::PaletteRam;
+use crate::ppu::render_thread::{SCREEN_HEIGHT, SCREEN_WIDTH};
+
+// Per-pixel metadata of a frame, for hover-to-inspect tooltips and hitbox views of the frontends:
+// which layer produced each pixel (sprite index, background or backdrop) and the palette entry used.
+// Recording costs a copy per scanline, so the buffer is disabled by default and only filled while
+// a debugger view asks for it. The renderer fills it from its pixel multiplexer (see `multiplex`)
+// as it draws, so the scanlines not drawn yet still hold the previous frame.
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum PixelLayer {
+    // Both layers are transparent (or disabled)
+    #[default]
+    Backdrop,
+    Background,
+    // Index of the sprite in OAM (0 - 63)
+    Sprite(u8),
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct PixelInfo {
+    pub layer: PixelLayer,
+    // Palette used (0 - 3 background, 4 - 7 sprites) and pixel value in it (0 - 3)
+    pub palette: u8,
+    pub value: u8,
+    // Color index read from palette RAM (0x00 - 0x3F)
+    pub color: u8,
+}
+
+// Opaque or transparent pixel of the first sprite drawn at a column of the scanline.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SpritePixel {
+    pub index: u8,
+    // Sprite palette (4 - 7) and pixel value (0 - 3)
+    pub palette: u8,
+    pub value: u8,
+    // Priority bit of the attributes: drawn behind opaque background pixels
+    pub behind_background: bool,
+}
+
+impl PixelInfo {
+    // Address of the palette RAM entry the color was read from. Transparent pixels show 0x3F00.
+    pub fn palette_address(&self) -> u16 {
+        match self.value {
+            0 => 0x3F00,
+            value => 0x3F00 + self.palette as u16 * 4 + value as u16,
+        }
+    }
+
+    // Text of a tooltip, e.g. "Sprite 12, palette 5 (0x3F16): color $27"
+    pub fn describe(&self) -> String {
+        let layer = match self.layer {
+            PixelLayer::Backdrop => "Backdrop".to_string(),
+            PixelLayer::Background => "Background".to_string(),
+            PixelLayer::Sprite(index) => format!("Sprite {}", index),
+        };
+        format!("{}, palette {} (0x{:04X}): color ${:02X}", layer, self.palette, self.palette_address(), self.color)
+    }
+}
+
+// Priority multiplexer of the PPU: picks the pixel shown between the background (palette 0 - 3 and
+// pixel value) and the sprite pixel at the same column. An opaque sprite wins unless its priority
+// bit puts it behind an opaque background pixel. The sprite does not fall through to a lower
+// priority sprite in that case, which is how games hide sprites behind the background (e.g. the
+// mushroom rising from a block in Super Mario Bros.).
+pub fn multiplex(background_palette: u8, background_value: u8, sprite: Option<SpritePixel>, palette_ram: &PaletteRam) -> PixelInfo {
+    let background_opaque = background_value & 0x03 != 0;
+    let (layer, palette, value) = match sprite {
+        Some(sprite) if sprite.value & 0x03 != 0 && !(sprite.behind_background && background_opaque) => {
+            (PixelLayer::Sprite(sprite.index), sprite.palette, sprite.value & 0x03)
+        }
+        _ if background_opaque => (PixelLayer::Background, background_palette, background_value & 0x03),
+        _ => (PixelLayer::Backdrop, 0, 0),
+    };
+    PixelInfo { layer, palette, value, color: palette_ram.color(palette as usize, value) }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PixelInfoBuffer {
+    // SCREEN_WIDTH x SCREEN_HEIGHT pixels once enabled, empty otherwise
+    pixels: Vec<PixelInfo>,
+}
+
+impl PixelInfoBuffer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.pixels = if enabled { vec![PixelInfo::default(); SCREEN_WIDTH * SCREEN_HEIGHT] } else { Vec::new() };
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.pixels.is_empty()
+    }
+
+    // Stores the metadata of a visible scanline (0 - 239). Ignored while disabled.
+    pub fn record_scanline(&mut self, scanline: u16, pixels: &[PixelInfo; SCREEN_WIDTH]) -> Result<(), String> {
+        if scanline as usize >= SCREEN_HEIGHT {
+            return Err(format!("Scanline {} is not visible", scanline));
+        }
+        if self.is_enabled() {
+            let start = scanline as usize * SCREEN_WIDTH;
+            self.pixels[start..start + SCREEN_WIDTH].copy_from_slice(pixels);
+        }
+        Ok(())
+    }
+
+    // Stores the metadata of the pixel at (x, y), drawn dot by dot. Ignored while disabled.
+    pub fn record_pixel(&mut self, x: usize, y: usize, pixel: PixelInfo) {
+        if let Some(stored) = self.pixels.get_mut(y * SCREEN_WIDTH + x) {
+            *stored = pixel;
+        }
+    }
+
+    // Metadata of the pixel at (x, y) of the last frame, None while disabled or outside the screen.
+    pub fn pixel(&self, x: usize, y: usize) -> Option<&PixelInfo> {
+        (x < SCREEN_WIDTH).then(|| self.pixels.get(y * SCREEN_WIDTH + x)).flatten()
+    }
+
+    // Coordinates of the pixels drawn by sprite `index`, for hitbox-style highlighting.
+    pub fn sprite_pixels(&self, index: u8) -> impl Iterator<Item = (usize, usize)> + '_ {
+        self.pixels
+            .iter()
+            .enumerate()
+            .filter(move |(_, pixel)| pixel.layer == PixelLayer::Sprite(index))
+            .map(|(position, _)| (position % SCREEN_WIDTH, position / SCREEN_WIDTH))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ppu::palette_ram::PaletteRam;
+    use crate::ppu::pixel_info::{multiplex, PixelInfo, PixelInfoBuffer, PixelLayer, SpritePixel};
+    use crate::ppu::render_thread::SCREEN_WIDTH;
+
+    fn palette_ram() -> PaletteRam {
+        let mut palette_ram = PaletteRam::new();
+        // Each entry holds its index, the backdrop (and its mirrors) being 0
+        for addr in (0x3F00..0x3F20u16).filter(|addr| addr & 0x03 != 0) {
+            palette_ram.write(addr, (addr & 0x1F) as u8);
+        }
+        palette_ram
+    }
+
+    #[test]
+    fn test_multiplex_priority() {
+        let palette_ram = palette_ram();
+        let sprite = SpritePixel { index: 12, palette: 5, value: 2, behind_background: false };
+
+        let pixel = multiplex(1, 3, Some(sprite), &palette_ram);
+        assert_eq!(pixel, PixelInfo { layer: PixelLayer::Sprite(12), palette: 5, value: 2, color: 0x16 });
+        assert_eq!(pixel.describe(), "Sprite 12, palette 5 (0x3F16): color $16");
+
+        // Behind an opaque background pixel, but in front of a transparent one
+        let behind = SpritePixel { behind_background: true, ..sprite };
+        assert_eq!(multiplex(1, 3, Some(behind), &palette_ram).layer, PixelLayer::Background);
+        assert_eq!(multiplex(1, 0, Some(behind), &palette_ram).layer, PixelLayer::Sprite(12));
+
+        // Transparent sprite pixels show the background, then the backdrop
+        let transparent = SpritePixel { value: 0, ..sprite };
+        assert_eq!(multiplex(1, 3, Some(transparent), &palette_ram).color, 0x07);
+        let backdrop = multiplex(1, 0, Some(transparent), &palette_ram);
+        assert_eq!((backdrop.layer, backdrop.palette_address(), backdrop.color), (PixelLayer::Backdrop, 0x3F00, 0x00));
+    }
+
+    #[test]
+    fn test_buffer() {
+        let mut buffer = PixelInfoBuffer::new();
+        let mut scanline = [PixelInfo::default(); SCREEN_WIDTH];
+        scanline[10].layer = PixelLayer::Sprite(3);
+        scanline[11].layer = PixelLayer::Sprite(3);
+
+        // Disabled: nothing is stored
+        buffer.record_scanline(20, &scanline).unwrap();
+        assert!(buffer.pixel(10, 20).is_none());
+
+        buffer.set_enabled(true);
+        buffer.record_scanline(20, &scanline).unwrap();
+        assert!(buffer.record_scanline(240, &scanline).is_err());
+        assert_eq!(buffer.pixel(10, 20).unwrap().layer, PixelLayer::Sprite(3));
+        assert_eq!(buffer.pixel(10, 21).unwrap().layer, PixelLayer::Backdrop);
+        assert!(buffer.pixel(256, 0).is_none());
+        assert_eq!(buffer.sprite_pixels(3).collect::<Vec<_>>(), vec![(10, 20), (11, 20)]);
+
+        buffer.set_enabled(false);
+        assert!(!buffer.is_enabled());
+        assert_eq!(buffer.sprite_pixels(3).count(), 0);
+    }
+}
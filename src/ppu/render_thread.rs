@@ -0,0 +1,130 @@
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread::JoinHandle;
+use crate::palette::Palette;
+
+// Framebuffer composition on a worker thread, optional (see `FrameOutput::set_render_thread`).
+// The emulation thread only records what each scanline needs (the palette index of its 256 pixels
+// and the PPUMASK value in effect) and queues it; the worker converts it to RGB. The emulation
+// thread is therefore not blocked by pixel work, and both run in parallel during a frame.
+// `finish_frame` is the synchronization point: it waits for the worker to process every queued
+// scanline and returns the frame, which is identical to a single-threaded render. Between two
+// calls, the framebuffer must not be read.
+
+pub const SCREEN_WIDTH: usize = 256;
+pub const SCREEN_HEIGHT: usize = 240;
+const FRAME_SIZE: usize = SCREEN_WIDTH * SCREEN_HEIGHT * 3;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScanlineSnapshot {
+    pub scanline: u16,
+    pub indexes: [u8; SCREEN_WIDTH],
+    pub ppumask: u8,
+}
+
+#[derive(Debug)]
+enum Message {
+    Scanline(Box<ScanlineSnapshot>),
+    EndFrame,
+}
+
+#[derive(Debug)]
+pub struct RenderThread {
+    sender: Option<Sender<Message>>,
+    frames: Receiver<Vec<u8>>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl RenderThread {
+    pub fn new(palette: Palette) -> Self {
+        let (sender, messages) = channel();
+        let (frame_sender, frames) = channel();
+        let handle = std::thread::spawn(move || {
+            let mut framebuffer = vec![0; FRAME_SIZE];
+            for message in messages {
+                match message {
+                    Message::Scanline(snapshot) => {
+                        let start = snapshot.scanline as usize * SCREEN_WIDTH * 3;
+                        let row = &mut framebuffer[start..start + SCREEN_WIDTH * 3];
+                        palette.render_scanline(&snapshot.indexes, snapshot.ppumask, row);
+                    }
+                    Message::EndFrame => {
+                        // The next frame starts from a copy, so unrendered scanlines keep the previous image
+                        if frame_sender.send(framebuffer.clone()).is_err() {
+                            return;
+                        }
+                    }
+                }
+            }
+        });
+        Self { sender: Some(sender), frames, handle: Some(handle) }
+    }
+
+    // Queues a visible scanline (0 - 239). Returns immediately.
+    pub fn submit_scanline(&self, snapshot: ScanlineSnapshot) -> Result<(), String> {
+        if snapshot.scanline as usize >= SCREEN_HEIGHT {
+            return Err(format!("Scanline {} is not visible", snapshot.scanline));
+        }
+        self.send(Message::Scanline(Box::new(snapshot)))
+    }
+
+    // Waits until the queued scanlines are rendered and returns the RGB24 frame.
+    pub fn finish_frame(&self) -> Result<Vec<u8>, String> {
+        self.send(Message::EndFrame)?;
+        self.frames.recv().map_err(|_| "Render thread stopped".to_string())
+    }
+
+    fn send(&self, message: Message) -> Result<(), String> {
+        match &self.sender {
+            Some(sender) => sender.send(message).map_err(|_| "Render thread stopped".to_string()),
+            None => Err("Render thread stopped".to_string()),
+        }
+    }
+}
+
+impl Drop for RenderThread {
+    fn drop(&mut self) {
+        // Closing the queue ends the worker loop
+        self.sender.take();
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::palette::Palette;
+    use crate::ppu::render_thread::{RenderThread, ScanlineSnapshot, SCREEN_HEIGHT, SCREEN_WIDTH};
+
+    fn snapshot(scanline: u16, frame: u8) -> ScanlineSnapshot {
+        let indexes = std::array::from_fn(|x| (x as u8).wrapping_add(scanline as u8).wrapping_mul(frame) & 0x3F);
+        // Dimmed status bar at the bottom of the screen
+        let ppumask = if scanline >= 200 { 0b1000_0001 } else { 0 };
+        ScanlineSnapshot { scanline, indexes, ppumask }
+    }
+
+    #[test]
+    fn test_same_frame_as_single_threaded() {
+        let palette = Palette::default();
+        let render_thread = RenderThread::new(palette.clone());
+
+        for frame in 1..=3 {
+            let mut expected = vec![0; SCREEN_WIDTH * SCREEN_HEIGHT * 3];
+            for scanline in 0..SCREEN_HEIGHT as u16 {
+                let snapshot = snapshot(scanline, frame);
+                let start = scanline as usize * SCREEN_WIDTH * 3;
+                palette.render_scanline(&snapshot.indexes, snapshot.ppumask, &mut expected[start..start + SCREEN_WIDTH * 3]);
+                render_thread.submit_scanline(snapshot).unwrap();
+            }
+            assert_eq!(render_thread.finish_frame().unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn test_invalid_scanline() {
+        let render_thread = RenderThread::new(Palette::default());
+        assert!(render_thread.submit_scanline(snapshot(240, 1)).is_err());
+        // Nothing rendered yet: black frame
+        assert!(render_thread.finish_frame().unwrap().iter().all(|byte| *byte == 0));
+    }
+}
@@ -0,0 +1,370 @@
+use crate::mapper::Mapper;
+use crate::palette::Palette;
+use crate::ppu::accuracy::Renderer;
+use crate::ppu::collisions::CollisionRecorder;
+use crate::ppu::palette_ram::PaletteRam;
+use crate::ppu::pixel_info::{multiplex, PixelInfo, PixelInfoBuffer, SpritePixel};
+use crate::ppu::render_thread::{RenderThread, ScanlineSnapshot, SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::ppu::OAM_SIZE;
+use crate::ppu::scroll::ScrollRegisters;
+use crate::ppu::sprite_evaluation::SpriteEvaluator;
+use crate::ppu::sprites::{is_sprite_zero_hit, ScanlineSprites, SpriteRow};
+use crate::ppu::status::PpuStatus;
+use crate::ppu::tile_decoder::decode_tiles;
+use crate::ppu::vram::Vram;
+
+// Output of the PPU: the palette index of each pixel of the frame being drawn, and the PPUMASK value
+// in effect on each scanline. When the frame is complete (at the start of vblank), the palette lookup
+// stage converts each scanline to RGB with its own PPUMASK value, so greyscale and color emphasis
+// changes between scanlines (e.g. to dim part of the screen) are displayed.
+// With a render thread, each scanline is queued to it once drawn, and converted while the emulation
+// goes on; the frame is collected from it when complete.
+#[derive(Debug)]
+pub struct FrameOutput {
+    indexes: Vec<u8>,
+    masks: [u8; SCREEN_HEIGHT],
+    // Scanlines drawn in the frame. The others had rendering disabled and show the backdrop color.
+    drawn: [bool; SCREEN_HEIGHT],
+    // Scanlines queued to the render thread
+    submitted: [bool; SCREEN_HEIGHT],
+    palette: Palette,
+    render_thread: Option<RenderThread>,
+    // RGB24 pixels of the last complete frame
+    framebuffer: Vec<u8>,
+}
+
+impl Default for FrameOutput {
+    fn default() -> Self {
+        Self {
+            indexes: vec![0; SCREEN_WIDTH * SCREEN_HEIGHT],
+            masks: [0; SCREEN_HEIGHT],
+            drawn: [false; SCREEN_HEIGHT],
+            submitted: [false; SCREEN_HEIGHT],
+            palette: Palette::default(),
+            render_thread: None,
+            framebuffer: vec![0; SCREEN_WIDTH * SCREEN_HEIGHT * 3],
+        }
+    }
+}
+
+impl FrameOutput {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn palette(&self) -> &Palette {
+        &self.palette
+    }
+
+    // Takes effect from the next complete frame.
+    pub fn set_palette(&mut self, palette: Palette) {
+        self.palette = palette;
+        if self.render_thread.is_some() {
+            self.render_thread = Some(RenderThread::new(self.palette.clone()));
+            self.submitted = [false; SCREEN_HEIGHT];
+        }
+    }
+
+    pub fn has_render_thread(&self) -> bool {
+        self.render_thread.is_some()
+    }
+
+    // Converts the scanlines to RGB on a worker thread (see `RenderThread`) or on the emulation
+    // thread. Takes effect from the next complete frame.
+    pub fn set_render_thread(&mut self, enabled: bool) {
+        self.render_thread = enabled.then(|| RenderThread::new(self.palette.clone()));
+        self.submitted = [false; SCREEN_HEIGHT];
+    }
+
+    pub fn framebuffer(&self) -> &[u8] {
+        &self.framebuffer
+    }
+
+    // Palette indexes of visible scanline `scanline`, displayed with PPUMASK value `mask`.
+    pub fn scanline_mut(&mut self, scanline: u16, mask: u8) -> &mut [u8] {
+        let scanline = scanline as usize;
+        self.drawn[scanline] = true;
+        self.masks[scanline] = mask;
+        &mut self.indexes[scanline * SCREEN_WIDTH..(scanline + 1) * SCREEN_WIDTH]
+    }
+
+    pub fn is_drawn(&self, scanline: u16) -> bool {
+        self.drawn[scanline as usize]
+    }
+
+    // Called once the last pixel of visible scanline `scanline` is drawn.
+    pub fn end_scanline(&mut self, scanline: u16) {
+        let scanline = scanline as usize;
+        if let Some(render_thread) = &self.render_thread {
+            let indexes = &self.indexes[scanline * SCREEN_WIDTH..(scanline + 1) * SCREEN_WIDTH];
+            let snapshot = ScanlineSnapshot { scanline: scanline as u16, indexes: indexes.try_into().unwrap(), ppumask: self.masks[scanline] };
+            // A stopped thread is replaced at the end of the frame
+            self.submitted[scanline] = render_thread.submit_scanline(snapshot).is_ok();
+        }
+    }
+
+    // Converts the frame to RGB. The scanlines that were not drawn show `backdrop` with `mask`.
+    pub fn finish_frame(&mut self, backdrop: u8, mask: u8) {
+        for scanline in 0..SCREEN_HEIGHT {
+            if !self.drawn[scanline] {
+                self.indexes[scanline * SCREEN_WIDTH..(scanline + 1) * SCREEN_WIDTH].fill(backdrop);
+                self.masks[scanline] = mask;
+            }
+            if !self.submitted[scanline] {
+                self.end_scanline(scanline as u16);
+            }
+        }
+        match self.render_thread.as_ref().map(RenderThread::finish_frame) {
+            Some(Ok(framebuffer)) => self.framebuffer = framebuffer,
+            frame => {
+                // No render thread, or it stopped: it is started again for the next frame
+                if frame.is_some() {
+                    self.set_render_thread(true);
+                }
+                for (scanline, indexes) in self.indexes.chunks_exact(SCREEN_WIDTH).enumerate() {
+                    let rgb = &mut self.framebuffer[scanline * SCREEN_WIDTH * 3..(scanline + 1) * SCREEN_WIDTH * 3];
+                    self.palette.render_scanline(indexes, self.masks[scanline], rgb);
+                }
+            }
+        }
+        self.drawn = [false; SCREEN_HEIGHT];
+        self.submitted = [false; SCREEN_HEIGHT];
+    }
+}
+
+// Background tiles covering a scanline, with the one uncovered by fine X scrolling
+const TILES_PER_SCANLINE: usize = SCREEN_WIDTH / 8 + 1;
+
+const PPUCTRL_SPRITE_TABLE: u8 = 0b0000_1000;
+const PPUCTRL_BACKGROUND_TABLE: u8 = 0b0001_0000;
+const PPUCTRL_SPRITE_SIZE_16: u8 = 0b0010_0000;
+const PPUMASK_BACKGROUND_LEFT: u8 = 0b0000_0010;
+const PPUMASK_SPRITES_LEFT: u8 = 0b0000_0100;
+const PPUMASK_BACKGROUND: u8 = 0b0000_1000;
+const PPUMASK_SPRITES: u8 = 0b0001_0000;
+const SPRITE_PALETTE: u8 = 0b0000_0011;
+const SPRITE_BEHIND_BACKGROUND: u8 = 0b0010_0000;
+const SPRITE_FLIP_HORIZONTAL: u8 = 0b0100_0000;
+const SPRITE_FLIP_VERTICAL: u8 = 0b1000_0000;
+
+// Renderer driven by the PpuRunner, with the PPU state it draws from.
+// The background is fetched from v: at the start of a scanline, v already points 2 tiles ahead
+// (they were fetched at the end of the previous scanline), and fine X selects the first pixel.
+// The sprites of a scanline are evaluated during the previous one (see `SpriteEvaluator`), which
+// also fetches their pattern rows: a scanline draws the sprites of `sprites`, then replaces them
+// with its own evaluation once its last pixel is out.
+pub struct ScanlineRenderer<'a> {
+    pub output: &'a mut FrameOutput,
+    pub palette_ram: &'a PaletteRam,
+    pub vram: &'a Vram,
+    pub oam: &'a [u8; OAM_SIZE],
+    pub chr: &'a [u8],
+    pub mapper: &'a dyn Mapper,
+    pub evaluator: &'a mut SpriteEvaluator,
+    pub sprites: &'a mut ScanlineSprites,
+    pub pixel_info: &'a mut PixelInfoBuffer,
+    pub collisions: &'a mut CollisionRecorder,
+    pub ctrl: u8,
+    pub mask: u8,
+}
+
+impl ScanlineRenderer<'_> {
+    // Pattern row bitplanes and attribute palette of the background tile at v.
+    fn fetch_tile(&self, v: u16) -> (u8, u8, u8) {
+        let tile = self.vram.read(0x2000 | (v & 0x0FFF), self.mapper) as u16;
+        let attribute = self.vram.read(0x23C0 | (v & 0x0C00) | ((v >> 4) & 0x38) | ((v >> 2) & 0x07), self.mapper);
+        let palette = (attribute >> (((v >> 4) & 4) | (v & 2))) & 0b11;
+        let table = if self.ctrl & PPUCTRL_BACKGROUND_TABLE != 0 { 0x1000 } else { 0 };
+        let addr = table | (tile << 4) | (v >> 12);
+        (self.read_chr(addr), self.read_chr(addr + 8), palette)
+    }
+
+    fn read_chr(&self, addr: u16) -> u8 {
+        if self.chr.is_empty() {
+            return 0;
+        }
+        self.chr[self.mapper.chr_index(addr) % self.chr.len()]
+    }
+
+    fn sprite_height(&self) -> u8 {
+        if self.ctrl & PPUCTRL_SPRITE_SIZE_16 != 0 { 16 } else { 8 }
+    }
+
+    // Keeps the sprites found by the evaluation of `scanline`, with their pattern rows.
+    fn fetch_sprites(&mut self, scanline: u16) {
+        let height = self.sprite_height();
+        let mut sprites = ScanlineSprites { scanline: Some(scanline), ..ScanlineSprites::default() };
+        sprites.count = self.evaluator.sprite_count();
+        sprites.sprite_zero = self.evaluator.sprite_zero_found();
+        let found = self.evaluator.secondary_oam.chunks_exact(4).zip(self.evaluator.sprite_indexes());
+        for (row, (bytes, index)) in sprites.rows.iter_mut().zip(found) {
+            let [y, tile, attributes, x] = [bytes[0], bytes[1], bytes[2], bytes[3]];
+            let mut line = scanline.wrapping_sub(y as u16) as u8 % height;
+            if attributes & SPRITE_FLIP_VERTICAL != 0 {
+                line = height - 1 - line;
+            }
+            let addr = if height == 16 {
+                ((tile as u16 & 1) << 12) | (((tile & 0xFE) as u16 + (line / 8) as u16) << 4) | (line % 8) as u16
+            } else {
+                let table = if self.ctrl & PPUCTRL_SPRITE_TABLE != 0 { 0x1000 } else { 0 };
+                table | ((tile as u16) << 4) | line as u16
+            };
+            let (mut low, mut high) = (self.read_chr(addr), self.read_chr(addr + 8));
+            if attributes & SPRITE_FLIP_HORIZONTAL != 0 {
+                (low, high) = (low.reverse_bits(), high.reverse_bits());
+            }
+            *row = SpriteRow { index: *index, x, attributes, low, high };
+        }
+        *self.sprites = sprites;
+    }
+
+    // Pixel `x` of `scanline`, from the background pixel `pixel` (0 - 3) of palette `palette` and
+    // the sprites on top of or behind it. Sets the sprite zero hit flag.
+    fn pixel(&mut self, scanline: u16, x: usize, palette: u8, pixel: u8, status: &mut PpuStatus) -> PixelInfo {
+        let background_shown = self.mask & PPUMASK_BACKGROUND != 0 && (x >= 8 || self.mask & PPUMASK_BACKGROUND_LEFT != 0);
+        let background = if background_shown { pixel } else { 0 };
+
+        let sprites_shown = self.mask & PPUMASK_SPRITES != 0 && (x >= 8 || self.mask & PPUMASK_SPRITES_LEFT != 0);
+        let sprites = if self.sprites.scanline == scanline.checked_sub(1) && sprites_shown { self.sprites.count } else { 0 };
+        // The first opaque sprite pixel is the one multiplexed, even behind the background
+        let sprite = self.sprites.rows[..sprites]
+            .iter()
+            .enumerate()
+            .find_map(|(slot, row)| row.pixel(x as u8).filter(|value| *value != 0).map(|value| (slot, row, value)));
+        let sprite = sprite.map(|(slot, row, value)| {
+            if slot == 0 && self.sprites.sprite_zero && is_sprite_zero_hit(x as u8, pixel != 0, true, self.mask) {
+                status.set_sprite_zero_hit(scanline, x as u16 + 1);
+            }
+            SpritePixel {
+                index: row.index,
+                palette: 4 + (row.attributes & SPRITE_PALETTE),
+                value,
+                behind_background: row.attributes & SPRITE_BEHIND_BACKGROUND != 0,
+            }
+        });
+        self.collisions.record_pixel(x, scanline, background != 0, sprite, self.mask);
+        multiplex(palette, background, sprite, self.palette_ram)
+    }
+}
+
+// Value (0 - 3) of pixel `column` (0 - 7, left to right) of a tile row.
+fn tile_pixel(low: u8, high: u8, column: u8) -> u8 {
+    let bit = 7 - column;
+    ((low >> bit) & 1) | (((high >> bit) & 1) << 1)
+}
+
+// v moved `tiles` tiles back, switching horizontal nametable when going past the left edge.
+fn previous_tiles(v: u16, tiles: u16) -> u16 {
+    let coarse_x = v & 0x1F;
+    if coarse_x >= tiles {
+        v - tiles
+    } else {
+        ((v & !0x1F) | (coarse_x + 32 - tiles)) ^ 0x0400
+    }
+}
+
+impl Renderer for ScanlineRenderer<'_> {
+    // The sprite evaluation of the scanline runs at once, after it is drawn.
+    fn render_scanline(&mut self, scanline: u16, scroll: &ScrollRegisters, status: &mut PpuStatus) {
+        let mut scroll = ScrollRegisters { v: previous_tiles(scroll.v, 2), ..scroll.clone() };
+        let (mut low, mut high, mut attributes) = ([0; TILES_PER_SCANLINE], [0; TILES_PER_SCANLINE], [0; TILES_PER_SCANLINE]);
+        for tile in 0..TILES_PER_SCANLINE {
+            (low[tile], high[tile], attributes[tile]) = self.fetch_tile(scroll.v);
+            scroll.increment_coarse_x();
+        }
+        let mut pixels = [0; TILES_PER_SCANLINE * 8];
+        decode_tiles(&low, &high, &attributes, &mut pixels);
+        let mut infos = [PixelInfo::default(); SCREEN_WIDTH];
+        for (x, pixel) in pixels[scroll.x as usize..][..SCREEN_WIDTH].iter().enumerate() {
+            infos[x] = self.pixel(scanline, x, pixel >> 2, pixel & 0b11, status);
+        }
+        for (index, info) in self.output.scanline_mut(scanline, self.mask).iter_mut().zip(&infos) {
+            *index = info.color;
+        }
+        self.output.end_scanline(scanline);
+        let _ = self.pixel_info.record_scanline(scanline, &infos);
+
+        let height = self.sprite_height();
+        for dot in 1..=256 {
+            self.evaluator.tick(dot, scanline, self.oam, height, status);
+        }
+        self.fetch_sprites(scanline);
+    }
+
+    // `scroll` is as it was before the updates of `dot`, so v points 2 tiles past the tile of the
+    // previous pixel. The sprite evaluation runs along, setting the overflow flag on its dot.
+    fn render_dot(&mut self, scanline: u16, dot: u16, scroll: &ScrollRegisters, status: &mut PpuStatus) {
+        let x = dot as usize - 1;
+        let position = (x % 8) as u16 + scroll.x as u16;
+        let (low, high, palette) = self.fetch_tile(previous_tiles(scroll.v, 2 - position / 8));
+        let info = self.pixel(scanline, x, palette, tile_pixel(low, high, (position % 8) as u8), status);
+        self.output.scanline_mut(scanline, self.mask)[x] = info.color;
+        self.pixel_info.record_pixel(x, scanline as usize, info);
+
+        let height = self.sprite_height();
+        self.evaluator.tick(dot, scanline, self.oam, height, status);
+        if dot == 256 {
+            self.output.end_scanline(scanline);
+            self.fetch_sprites(scanline);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::palette::Palette;
+    use crate::ppu::render_thread::{SCREEN_HEIGHT, SCREEN_WIDTH};
+    use crate::ppu::renderer::{previous_tiles, FrameOutput};
+
+    #[test]
+    fn test_each_scanline_uses_its_ppumask() {
+        let mut output = FrameOutput::new();
+        // Scanline 10 in greyscale and with all the emphasis bits, scanline 11 in colors
+        output.scanline_mut(10, 0b1110_0001).fill(0x16);
+        output.scanline_mut(11, 0x00).fill(0x16);
+        output.finish_frame(0x0F, 0x00);
+
+        let palette = Palette::default();
+        let pixel = |x: usize, y: usize| {
+            let start = (y * SCREEN_WIDTH + x) * 3;
+            let rgb = &output.framebuffer()[start..start + 3];
+            (rgb[0], rgb[1], rgb[2])
+        };
+        assert_eq!(pixel(0, 10), palette.color(0x16, 0b1110_0001));
+        assert_eq!(pixel(255, 11), palette.color(0x16, 0x00));
+        assert_ne!(pixel(0, 10), pixel(0, 11));
+        // Scanlines that were not drawn show the backdrop
+        assert_eq!(pixel(0, 0), palette.color(0x0F, 0x00));
+        assert_eq!(pixel(0, SCREEN_HEIGHT - 1), palette.color(0x0F, 0x00));
+    }
+
+    #[test]
+    fn test_render_thread_gives_the_same_frame() {
+        let mut outputs = [FrameOutput::new(), FrameOutput::new()];
+        outputs[1].set_render_thread(true);
+        for output in outputs.iter_mut() {
+            for frame in 1..=2u8 {
+                for scanline in 0..200 {
+                    let indexes = output.scanline_mut(scanline, if scanline < 100 { 0 } else { 0b0100_0001 });
+                    for (x, index) in indexes.iter_mut().enumerate() {
+                        *index = (x as u8 ^ scanline as u8).wrapping_mul(frame) & 0x3F;
+                    }
+                    // The last scanlines are queued at the end of the frame
+                    if scanline < 150 {
+                        output.end_scanline(scanline);
+                    }
+                }
+                output.finish_frame(0x21, 0x00);
+            }
+        }
+        assert!(outputs[1].has_render_thread());
+        assert_eq!(outputs[0].framebuffer(), outputs[1].framebuffer());
+    }
+
+    #[test]
+    fn test_previous_tiles_wrap_to_the_other_nametable() {
+        assert_eq!(previous_tiles(0x2025, 2), 0x2023);
+        assert_eq!(previous_tiles(0x2021, 2), 0x243F);
+        assert_eq!(previous_tiles(0x2420, 1), 0x203F);
+    }
+}
@@ -0,0 +1,335 @@
+// Internal scroll registers of the PPU ("loopy" registers, named after the person who documented them).
+// Games set the scroll through PPUCTRL, PPUSCROLL and PPUADDR, which all write the same registers:
+// - v: current VRAM address (15 bits), used for rendering and PPUDATA accesses
+// - t: temporary VRAM address (15 bits), the scroll of the top left onscreen tile
+// - x: fine X scroll (3 bits)
+// - w: first or second write toggle, shared by PPUSCROLL and PPUADDR
+// v and t are laid out as: 0yyy NNYY YYYX XXXX (fine Y, nametable, coarse Y, coarse X).
+// Rendering copies t to v at specific dots, so a write in the middle of a frame takes effect at the
+// next copy: horizontal bits at dot 257 of each scanline, vertical bits during the pre-render scanline.
+// Writing PPUADDR mid-frame sets v immediately, which is how split screens (status bars) are done.
+// While rendering, register accesses that land on a dot where rendering updates v collide with
+// it (the `*_while_rendering` methods), which some games and demos rely on for raster effects.
+
+const COARSE_X: u16 = 0x001F;
+const COARSE_Y: u16 = 0x03E0;
+const NAMETABLE_X: u16 = 0x0400;
+const NAMETABLE_Y: u16 = 0x0800;
+const FINE_Y: u16 = 0x7000;
+const HORIZONTAL_BITS: u16 = COARSE_X | NAMETABLE_X;
+const VERTICAL_BITS: u16 = COARSE_Y | NAMETABLE_Y | FINE_Y;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ScrollRegisters {
+    pub v: u16,
+    pub t: u16,
+    pub x: u8,
+    pub w: bool,
+}
+
+impl ScrollRegisters {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // PPUCTRL (0x2000): bits 0-1 select the base nametable.
+    pub fn write_ppuctrl(&mut self, data: u8) {
+        self.t = (self.t & !(NAMETABLE_X | NAMETABLE_Y)) | (((data & 0b11) as u16) << 10);
+    }
+
+    // PPUSTATUS (0x2002) reads reset the write toggle.
+    pub fn read_ppustatus(&mut self) {
+        self.w = false;
+    }
+
+    // PPUSCROLL (0x2005): X scroll on the first write, Y scroll on the second one.
+    pub fn write_ppuscroll(&mut self, data: u8) {
+        if !self.w {
+            self.t = (self.t & !COARSE_X) | (data >> 3) as u16;
+            self.x = data & 0b111;
+        } else {
+            self.t = (self.t & !(COARSE_Y | FINE_Y)) | (((data >> 3) as u16) << 5) | (((data & 0b111) as u16) << 12);
+        }
+        self.w = !self.w;
+    }
+
+    // PPUADDR (0x2006): high byte on the first write, low byte on the second one, which also copies t to v.
+    pub fn write_ppuaddr(&mut self, data: u8) {
+        if !self.w {
+            // Bit 14 is cleared, the address being only 14 bits wide
+            self.t = (self.t & 0x00FF) | (((data & 0b0011_1111) as u16) << 8);
+        } else {
+            self.t = (self.t & 0xFF00) | data as u16;
+            self.v = self.t;
+        }
+        self.w = !self.w;
+    }
+
+    // VRAM address used by PPUDATA (0x2007), which increments v by 1 or 32 (PPUCTRL bit 2) after each access.
+    pub fn vram_address(&self) -> u16 {
+        self.v & 0x3FFF
+    }
+
+    pub fn increment_vram_address(&mut self, increment: u16) {
+        self.v = self.v.wrapping_add(increment) & 0x7FFF;
+    }
+
+    // The glitches below apply on the scanlines where rendering updates v, at the dot of the access
+    // (after `tick` ran for it).
+
+    // PPUCTRL written while rendering.
+    pub fn write_ppuctrl_while_rendering(&mut self, data: u8, scanline: u16, dot: u16) {
+        self.write_ppuctrl(data);
+        self.horizontal_copy_glitch(NAMETABLE_X, scanline, dot);
+    }
+
+    // PPUSCROLL written while rendering.
+    pub fn write_ppuscroll_while_rendering(&mut self, data: u8, scanline: u16, dot: u16) {
+        let first_write = !self.w;
+        self.write_ppuscroll(data);
+        if first_write {
+            self.horizontal_copy_glitch(COARSE_X, scanline, dot);
+        }
+    }
+
+    // PPUADDR written while rendering. When the t to v copy of the second write lands on a dot where
+    // rendering increments v, the bus conflict leaves the new address ANDed with the incremented one:
+    // the horizontal bits on the coarse X increments, all of them on dot 257 (horizontal copy).
+    pub fn write_ppuaddr_while_rendering(&mut self, data: u8, scanline: u16, dot: u16) {
+        if !self.w {
+            self.write_ppuaddr(data);
+            self.horizontal_copy_glitch(NAMETABLE_X, scanline, dot);
+            return;
+        }
+        let incremented = self.v;
+        self.write_ppuaddr(data);
+        if !is_rendering_scanline(scanline) {
+            return;
+        }
+        let fetching_tiles = (1..=256).contains(&dot) || (321..=336).contains(&dot);
+        if dot == 257 {
+            self.v &= incremented;
+        } else if fetching_tiles && dot.is_multiple_of(8) {
+            self.v &= incremented | !HORIZONTAL_BITS;
+        }
+    }
+
+    // PPUDATA accessed while rendering: instead of the 1 or 32 increment, v gets both the coarse X
+    // and the Y increments of rendering (the PPU uses the same increment circuits).
+    pub fn increment_vram_address_while_rendering(&mut self, increment: u16, scanline: u16) {
+        if is_rendering_scanline(scanline) {
+            self.increment_coarse_x();
+            self.increment_y();
+        } else {
+            self.increment_vram_address(increment);
+        }
+    }
+
+    // A write to t landing on dot 257 is also seen by the horizontal copy, which already ran for
+    // this dot: the `written` horizontal bits of v get their new value.
+    fn horizontal_copy_glitch(&mut self, written: u16, scanline: u16, dot: u16) {
+        if is_rendering_scanline(scanline) && dot == 257 {
+            self.v = (self.v & !written) | (self.t & written);
+        }
+    }
+
+    // Horizontal position pointed by v and fine X, in pixels (0 to 511 over the two horizontal nametables).
+    pub fn scroll_x(&self) -> u16 {
+        ((self.v & NAMETABLE_X) >> 2) | ((self.v & COARSE_X) << 3) | self.x as u16
+    }
+
+    // Vertical position pointed by v, in pixels (0 to 479 over the two vertical nametables).
+    pub fn scroll_y(&self) -> u16 {
+        let nametable = if self.v & NAMETABLE_Y != 0 { 240 } else { 0 };
+        nametable + ((self.v & COARSE_Y) >> 5) * 8 + ((self.v & FINE_Y) >> 12)
+    }
+
+    // Dot 256 of each rendered scanline: moves v to the next pixel row.
+    pub fn increment_y(&mut self) {
+        if self.v & FINE_Y != FINE_Y {
+            self.v += 0x1000;
+            return;
+        }
+        self.v &= !FINE_Y;
+        let mut coarse_y = (self.v & COARSE_Y) >> 5;
+        if coarse_y == 29 {
+            // Last row of the nametable: switch to the other vertical nametable
+            coarse_y = 0;
+            self.v ^= NAMETABLE_Y;
+        } else if coarse_y == 31 {
+            // Rows 30 and 31 hold the attribute table, going past them wraps without switching
+            coarse_y = 0;
+        } else {
+            coarse_y += 1;
+        }
+        self.v = (self.v & !COARSE_Y) | (coarse_y << 5);
+    }
+
+    // Every 8 dots while fetching tiles: moves v to the next tile.
+    pub fn increment_coarse_x(&mut self) {
+        if self.v & COARSE_X == 31 {
+            self.v &= !COARSE_X;
+            self.v ^= NAMETABLE_X;
+        } else {
+            self.v += 1;
+        }
+    }
+
+    // Dot 257 of each rendered scanline.
+    pub fn copy_horizontal_bits(&mut self) {
+        self.v = (self.v & !HORIZONTAL_BITS) | (self.t & HORIZONTAL_BITS);
+    }
+
+    // Dots 280 to 304 of the pre-render scanline.
+    pub fn copy_vertical_bits(&mut self) {
+        self.v = (self.v & !VERTICAL_BITS) | (self.t & VERTICAL_BITS);
+    }
+
+    // Applies the scroll register updates the PPU does at `dot` of `scanline` when rendering is enabled.
+    // Scanlines 0 to 239 are visible, 261 is the pre-render scanline.
+    pub fn tick(&mut self, scanline: u16, dot: u16) {
+        if !is_rendering_scanline(scanline) {
+            return;
+        }
+        let fetching_tiles = (1..=256).contains(&dot) || (321..=336).contains(&dot);
+        if fetching_tiles && dot.is_multiple_of(8) {
+            self.increment_coarse_x();
+        }
+        match dot {
+            256 => self.increment_y(),
+            257 => self.copy_horizontal_bits(),
+            280..=304 if scanline == 261 => self.copy_vertical_bits(),
+            _ => {}
+        }
+    }
+}
+
+// Visible scanlines and the pre-render scanline
+fn is_rendering_scanline(scanline: u16) -> bool {
+    scanline < 240 || scanline == 261
+}
+
+#[cfg(test)]
+// Binary literals are grouped like the fields of v and t
+#[allow(clippy::unusual_byte_groupings)]
+mod tests {
+    use crate::ppu::scroll::ScrollRegisters;
+
+    #[test]
+    fn test_register_writes() {
+        // Example from https://www.nesdev.org/wiki/PPU_scrolling
+        let mut scroll = ScrollRegisters::new();
+        scroll.write_ppuctrl(0b10);
+        scroll.read_ppustatus();
+        scroll.write_ppuscroll(0b0111_1101);
+        assert_eq!(scroll.t, 0b000_10_00000_01111);
+        assert_eq!(scroll.x, 0b101);
+        assert!(scroll.w);
+        scroll.write_ppuscroll(0b0101_1110);
+        assert_eq!(scroll.t, 0b110_10_01011_01111);
+        assert!(!scroll.w);
+
+        scroll.write_ppuaddr(0b0011_1101);
+        assert_eq!(scroll.t, 0b011_11_01011_01111);
+        scroll.write_ppuaddr(0b1111_0000);
+        assert_eq!(scroll.t, 0b011_11_01111_10000);
+        assert_eq!(scroll.v, scroll.t);
+    }
+
+    #[test]
+    fn test_scroll_position() {
+        let mut scroll = ScrollRegisters::new();
+        scroll.write_ppuctrl(0b01);
+        scroll.write_ppuscroll(13);
+        scroll.write_ppuscroll(100);
+        scroll.copy_horizontal_bits();
+        scroll.copy_vertical_bits();
+        assert_eq!(scroll.scroll_x(), 256 + 13);
+        assert_eq!(scroll.scroll_y(), 100);
+    }
+
+    #[test]
+    fn test_increment_y_wraps_nametables() {
+        let mut scroll = ScrollRegisters::new();
+        // Fine Y 7, coarse Y 29: the last pixel row of the nametable
+        scroll.v = 0b111_00_11101_00000;
+        scroll.increment_y();
+        assert_eq!(scroll.v, 0b000_10_00000_00000);
+
+        // Coarse Y 31 wraps without switching nametable
+        scroll.v = 0b111_00_11111_00000;
+        scroll.increment_y();
+        assert_eq!(scroll.v, 0);
+    }
+
+    #[test]
+    fn test_mid_frame_split() {
+        // Status bar at the top, with the rest of the screen scrolled horizontally
+        let mut scroll = ScrollRegisters::new();
+        for scanline in 0..32 {
+            for dot in 1..=340 {
+                scroll.tick(scanline, dot);
+            }
+        }
+        // The two first tiles of the next scanline were prefetched at the end of the previous one
+        assert_eq!(scroll.scroll_x(), 16);
+
+        // The game changes the X scroll during scanline 32. It is only applied from dot 257.
+        scroll.write_ppuscroll(80);
+        scroll.write_ppuscroll(0);
+        for dot in 1..=256 {
+            scroll.tick(32, dot);
+        }
+        assert_ne!(scroll.scroll_x(), 80);
+        scroll.tick(32, 257);
+        assert_eq!(scroll.scroll_x(), 80);
+        // The vertical scroll is not reloaded before the next frame
+        assert_eq!(scroll.scroll_y(), 33);
+    }
+
+    #[test]
+    fn test_ppudata_while_rendering() {
+        let mut scroll = ScrollRegisters::new();
+        scroll.v = 0b001_00_00010_11111;
+        scroll.increment_vram_address_while_rendering(32, 100);
+        assert_eq!(scroll.v, 0b010_01_00010_00000);
+        // In vblank, the usual increment
+        scroll.increment_vram_address_while_rendering(32, 241);
+        assert_eq!(scroll.v, 0b010_01_00011_00000);
+    }
+
+    #[test]
+    fn test_ppuaddr_while_rendering() {
+        let write = |v: u16, dot: u16| {
+            let mut scroll = ScrollRegisters { v, ..ScrollRegisters::new() };
+            scroll.write_ppuaddr_while_rendering(0x2F, 10, dot);
+            scroll.write_ppuaddr_while_rendering(0x76, 10, dot);
+            scroll.v
+        };
+        // Between increments, the plain copy
+        assert_eq!(write(0x0000, 13), 0x2F76);
+        // On a coarse X increment, the horizontal bits are ANDed with v
+        assert_eq!(write(0x0003, 16), 0x2B62);
+        // On the horizontal copy, all the bits are
+        assert_eq!(write(0x0F03, 257), 0x0F02);
+        // Outside rendering, no glitch
+        let mut scroll = ScrollRegisters::new();
+        scroll.write_ppuaddr_while_rendering(0x2F, 250, 16);
+        scroll.write_ppuaddr_while_rendering(0x76, 250, 16);
+        assert_eq!(scroll.v, 0x2F76);
+    }
+
+    #[test]
+    fn test_horizontal_copy_glitch() {
+        let mut scroll = ScrollRegisters::new();
+        scroll.write_ppuscroll_while_rendering(0b1010_1000, 20, 257);
+        assert_eq!(scroll.v, 0b1_0101);
+        // The second write only sets the vertical bits of t
+        scroll.write_ppuscroll_while_rendering(0xFF, 20, 257);
+        assert_eq!(scroll.v, 0b1_0101);
+        scroll.write_ppuctrl_while_rendering(0b11, 20, 257);
+        assert_eq!(scroll.v, 0b0_01_00000_10101);
+        scroll.write_ppuctrl_while_rendering(0b00, 20, 258);
+        assert_eq!(scroll.v, 0b0_01_00000_10101);
+    }
+}
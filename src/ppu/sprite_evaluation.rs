@@ -0,0 +1,285 @@
+use crate::ppu::sprites::{SpriteEvaluation, SPRITES_PER_SCANLINE};
+use crate::ppu::status::PpuStatus;
+use crate::ppu::timing::PPUSTATUS_SPRITE_OVERFLOW;
+
+// Dot by dot sprite evaluation of a visible scanline, into secondary OAM (8 sprites of 4 bytes):
+// - dots 1 - 64: secondary OAM is filled with 0xFF, one byte every two dots,
+// - dots 65 - 256: odd dots read a byte of OAM, even dots write it to secondary OAM.
+//   The Y byte of each sprite is copied, and if the sprite is on the scanline the 3 other bytes
+//   follow. Once secondary OAM is full, writes are ignored and the overflow search starts, with
+//   the byte index bug described in `evaluate_sprites`. The overflow flag is set on the dot the
+//   9th sprite is found, and the evaluation ends by reading the Y byte of the next sprites.
+// The byte on the OAM bus is what OAMDATA (0x2004) reads return during rendering.
+// Only runs on visible scanlines with rendering enabled; the caller checks it.
+
+const SECONDARY_OAM_SIZE: usize = SPRITES_PER_SCANLINE * 4;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Phase {
+    // Copying the Y byte of sprite n
+    Scan,
+    // Copying byte m of an in-range sprite
+    Copy,
+    // Secondary OAM is full, looking for a 9th sprite
+    Overflow,
+    // 9th sprite found: its 3 next bytes are read (the count is in `copied`)
+    OverflowFound,
+    // All 64 sprites evaluated
+    Done,
+}
+
+#[derive(Debug, Clone)]
+pub struct SpriteEvaluator {
+    pub secondary_oam: [u8; SECONDARY_OAM_SIZE],
+    // Sprites found, and their index in OAM
+    count: usize,
+    indexes: [u8; SPRITES_PER_SCANLINE],
+    n: usize,
+    m: usize,
+    copied: usize,
+    phase: Phase,
+    // Byte read on the last odd dot
+    latch: u8,
+    overflow: bool,
+}
+
+impl Default for SpriteEvaluator {
+    fn default() -> Self {
+        Self {
+            secondary_oam: [0xFF; SECONDARY_OAM_SIZE],
+            count: 0,
+            indexes: [0; SPRITES_PER_SCANLINE],
+            n: 0,
+            m: 0,
+            copied: 0,
+            phase: Phase::Done,
+            latch: 0xFF,
+            overflow: false,
+        }
+    }
+}
+
+impl SpriteEvaluator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Runs dot `dot` of visible scanline `scanline`. Dots outside 1 - 256 do nothing.
+    pub fn tick(&mut self, dot: u16, scanline: u16, oam: &[u8; 256], sprite_height: u8, status: &mut PpuStatus) {
+        match dot {
+            1..=64 => {
+                if dot == 1 {
+                    *self = Self { secondary_oam: self.secondary_oam, ..Self::default() };
+                }
+                if dot.is_multiple_of(2) {
+                    self.secondary_oam[dot as usize / 2 - 1] = 0xFF;
+                }
+                self.latch = 0xFF;
+            }
+            65..=256 => {
+                if dot == 65 {
+                    self.phase = Phase::Scan;
+                }
+                if dot % 2 == 1 {
+                    self.latch = oam[self.n * 4 + self.m];
+                } else {
+                    self.evaluate(scanline, sprite_height, status);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn evaluate(&mut self, scanline: u16, sprite_height: u8, status: &mut PpuStatus) {
+        let in_range = scanline.wrapping_sub(self.latch as u16) < sprite_height as u16;
+        match self.phase {
+            Phase::Scan => {
+                self.secondary_oam[self.count * 4] = self.latch;
+                if in_range {
+                    self.indexes[self.count] = self.n as u8;
+                    self.m = 1;
+                    self.phase = Phase::Copy;
+                } else {
+                    self.next_sprite();
+                }
+            }
+            Phase::Copy => {
+                self.secondary_oam[self.count * 4 + self.m] = self.latch;
+                self.m += 1;
+                if self.m == 4 {
+                    self.m = 0;
+                    self.count += 1;
+                    self.next_sprite();
+                }
+            }
+            Phase::Overflow => {
+                if in_range {
+                    self.overflow = true;
+                    status.flags |= PPUSTATUS_SPRITE_OVERFLOW;
+                    self.copied = 0;
+                    self.phase = Phase::OverflowFound;
+                    self.next_byte();
+                } else {
+                    // Hardware bug: m should stay 0
+                    self.m = (self.m + 1) % 4;
+                    self.n += 1;
+                    if self.n == 64 {
+                        self.finish();
+                    }
+                }
+            }
+            Phase::OverflowFound => {
+                self.copied += 1;
+                if self.copied == 3 {
+                    self.finish();
+                } else {
+                    self.next_byte();
+                }
+            }
+            Phase::Done => self.n = (self.n + 1) % 64,
+        }
+    }
+
+    fn next_sprite(&mut self) {
+        self.n += 1;
+        if self.n == 64 {
+            self.finish();
+        } else if self.count == SPRITES_PER_SCANLINE {
+            self.phase = Phase::Overflow;
+        } else {
+            self.phase = Phase::Scan;
+        }
+    }
+
+    // Next byte of OAM, carrying into the sprite index
+    fn next_byte(&mut self) {
+        self.m += 1;
+        if self.m == 4 {
+            self.m = 0;
+            self.n += 1;
+            if self.n == 64 {
+                self.finish();
+            }
+        }
+    }
+
+    fn finish(&mut self) {
+        self.n %= 64;
+        self.m = 0;
+        self.phase = Phase::Done;
+    }
+
+    // Byte returned by OAMDATA reads during rendering
+    pub fn oam_bus(&self) -> u8 {
+        self.latch
+    }
+
+    pub fn sprite_count(&self) -> usize {
+        self.count
+    }
+
+    // Index in OAM of the sprites found
+    pub fn sprite_indexes(&self) -> &[u8] {
+        &self.indexes[..self.count]
+    }
+
+    // True when sprite 0 is in secondary OAM, for sprite zero hit on the next scanline
+    pub fn sprite_zero_found(&self) -> bool {
+        self.count > 0 && self.indexes[0] == 0
+    }
+
+    pub fn evaluation(&self) -> SpriteEvaluation {
+        SpriteEvaluation { sprites: self.indexes[..self.count].to_vec(), overflow: self.overflow }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ppu::sprite_evaluation::SpriteEvaluator;
+    use crate::ppu::status::PpuStatus;
+    use crate::ppu::timing::PPUSTATUS_SPRITE_OVERFLOW;
+
+    fn run(evaluator: &mut SpriteEvaluator, oam: &[u8; 256], scanline: u16, dots: std::ops::RangeInclusive<u16>, status: &mut PpuStatus) {
+        for dot in dots {
+            evaluator.tick(dot, scanline, oam, 8, status);
+        }
+    }
+
+    #[test]
+    fn test_secondary_oam() {
+        let mut oam = [0xFF; 256];
+        oam[4..8].copy_from_slice(&[20, 0x31, 0x02, 0x40]);
+        oam[12..16].copy_from_slice(&[14, 0x32, 0x41, 0x80]);
+        oam[63 * 4] = 50;
+        let mut evaluator = SpriteEvaluator::new();
+        evaluator.secondary_oam = [0; 32];
+        let mut status = PpuStatus::new();
+
+        // Cleared during dots 1 - 64
+        run(&mut evaluator, &oam, 20, 1..=63, &mut status);
+        assert_eq!(evaluator.secondary_oam[31], 0);
+        assert_eq!(evaluator.oam_bus(), 0xFF);
+        run(&mut evaluator, &oam, 20, 64..=64, &mut status);
+        assert_eq!(evaluator.secondary_oam, [0xFF; 32]);
+
+        run(&mut evaluator, &oam, 20, 65..=256, &mut status);
+        assert_eq!(&evaluator.secondary_oam[..8], &[20, 0x31, 0x02, 0x40, 14, 0x32, 0x41, 0x80]);
+        // The Y byte of out of range sprites is still copied to the next free slot
+        assert_eq!(evaluator.secondary_oam[8], 50);
+        assert_eq!(evaluator.secondary_oam[9], 0xFF);
+        assert_eq!(evaluator.sprite_count(), 2);
+        assert!(!evaluator.sprite_zero_found());
+        assert_eq!(evaluator.evaluation().sprites, vec![1, 3]);
+    }
+
+    #[test]
+    fn test_evaluation_timing() {
+        let mut oam = [0xFF; 256];
+        oam[0] = 10;
+        let mut evaluator = SpriteEvaluator::new();
+        let mut status = PpuStatus::new();
+
+        // Sprite 0: Y read at dot 65, written at 66, then 3 bytes until dot 72
+        run(&mut evaluator, &oam, 10, 1..=65, &mut status);
+        assert_eq!(evaluator.oam_bus(), 10);
+        assert_eq!(evaluator.sprite_count(), 0);
+        run(&mut evaluator, &oam, 10, 66..=71, &mut status);
+        assert_eq!(evaluator.sprite_count(), 0);
+        run(&mut evaluator, &oam, 10, 72..=72, &mut status);
+        assert_eq!(evaluator.sprite_count(), 1);
+        assert!(evaluator.sprite_zero_found());
+    }
+
+    #[test]
+    fn test_overflow_dot() {
+        let mut oam = [0xFF; 256];
+        for sprite in 0..9 {
+            oam[sprite * 4] = 10;
+        }
+        let mut evaluator = SpriteEvaluator::new();
+        let mut status = PpuStatus::new();
+
+        // 8 sprites copied in 64 dots (65 - 128), the 9th is compared at dot 130
+        run(&mut evaluator, &oam, 10, 1..=129, &mut status);
+        assert_eq!(status.flags & PPUSTATUS_SPRITE_OVERFLOW, 0);
+        run(&mut evaluator, &oam, 10, 130..=130, &mut status);
+        assert_eq!(status.flags & PPUSTATUS_SPRITE_OVERFLOW, PPUSTATUS_SPRITE_OVERFLOW);
+        // Secondary OAM is not overwritten by the 9th sprite
+        run(&mut evaluator, &oam, 10, 131..=340, &mut status);
+        assert_eq!(evaluator.sprite_count(), 8);
+        assert_eq!(evaluator.secondary_oam[28..32], oam[28..32]);
+    }
+
+    #[test]
+    fn test_new_scanline_restarts() {
+        let mut oam = [0xFF; 256];
+        oam[0] = 10;
+        let mut evaluator = SpriteEvaluator::new();
+        let mut status = PpuStatus::new();
+        run(&mut evaluator, &oam, 10, 1..=256, &mut status);
+        assert_eq!(evaluator.sprite_count(), 1);
+        run(&mut evaluator, &oam, 30, 1..=256, &mut status);
+        assert_eq!(evaluator.sprite_count(), 0);
+        assert_eq!(evaluator.secondary_oam, [0xFF; 32]);
+    }
+}
@@ -0,0 +1,190 @@
+use crate::ppu::sprite_evaluation::SpriteEvaluator;
+use crate::ppu::status::PpuStatus;
+
+// Sprite zero hit and sprite overflow flags of PPUSTATUS.
+
+// PPUMASK bits used by sprite zero hit
+const PPUMASK_SHOW_BACKGROUND_LEFT: u8 = 0b0000_0010;
+const PPUMASK_SHOW_SPRITES_LEFT: u8 = 0b0000_0100;
+const PPUMASK_SHOW_BACKGROUND: u8 = 0b0000_1000;
+const PPUMASK_SHOW_SPRITES: u8 = 0b0001_0000;
+
+// Maximum number of sprites drawn on a scanline
+pub const SPRITES_PER_SCANLINE: usize = 8;
+
+// Returns true if an opaque pixel of sprite 0 overlapping an opaque background pixel at column `x`
+// sets the sprite zero hit flag. The hit is not detected:
+// - when background or sprite rendering is disabled,
+// - in the 8 leftmost columns when either of them is clipped by PPUMASK,
+// - at x = 255, because of how the pixel pipeline is built.
+pub fn is_sprite_zero_hit(x: u8, background_opaque: bool, sprite_opaque: bool, ppumask: u8) -> bool {
+    if !background_opaque || !sprite_opaque || x == 255 {
+        return false;
+    }
+    let rendering = PPUMASK_SHOW_BACKGROUND | PPUMASK_SHOW_SPRITES;
+    if ppumask & rendering != rendering {
+        return false;
+    }
+    let left_columns = PPUMASK_SHOW_BACKGROUND_LEFT | PPUMASK_SHOW_SPRITES_LEFT;
+    x >= 8 || ppumask & left_columns == left_columns
+}
+
+// Finds the first column of a scanline where sprite zero hits the background.
+// `background_opaque` tells which of the 256 background pixels are opaque, and `sprite_row` is the
+// row of sprite 0 on this scanline (bit 7 being the leftmost pixel, after horizontal flipping).
+// The flag is set at dot x + 1, since dots 1 to 256 output pixels 0 to 255, which is what split
+// scrolling games such as Super Mario Bros. wait for.
+pub fn find_sprite_zero_hit(background_opaque: &[bool; 256], sprite_x: u8, sprite_row: u8, ppumask: u8) -> Option<u8> {
+    (0..8u16)
+        .map(|offset| (offset, sprite_x as u16 + offset))
+        .take_while(|(_, x)| *x < 256)
+        .map(|(offset, x)| (sprite_row & (0x80 >> offset) != 0, x as u8))
+        .find(|(sprite_opaque, x)| is_sprite_zero_hit(*x, background_opaque[*x as usize], *sprite_opaque, ppumask))
+        .map(|(_, x)| x)
+}
+
+// Pattern row of a sprite on a scanline, fetched at the end of the previous scanline.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct SpriteRow {
+    // Index of the sprite in OAM
+    pub index: u8,
+    pub x: u8,
+    pub attributes: u8,
+    // Bitplanes, bit 7 being the leftmost pixel (after horizontal flipping)
+    pub low: u8,
+    pub high: u8,
+}
+
+impl SpriteRow {
+    // Value (0 - 3) of the sprite pixel at column `x` of the scanline, if the sprite covers it.
+    pub fn pixel(&self, x: u8) -> Option<u8> {
+        let offset = x.checked_sub(self.x).filter(|offset| *offset < 8)?;
+        let bit = 7 - offset;
+        Some(((self.low >> bit) & 1) | (((self.high >> bit) & 1) << 1))
+    }
+}
+
+// Sprites found by the evaluation of a scanline, which are drawn on the next one.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ScanlineSprites {
+    // Scanline evaluated, the sprites are only drawn on the one after it
+    pub scanline: Option<u16>,
+    pub rows: [SpriteRow; SPRITES_PER_SCANLINE],
+    pub count: usize,
+    // The first row is sprite 0, which can hit the background
+    pub sprite_zero: bool,
+}
+
+// Result of the sprite evaluation of a scanline.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpriteEvaluation {
+    // Index in OAM of the sprites visible on the scanline, at most 8
+    pub sprites: Vec<u8>,
+    // Sprite overflow flag of PPUSTATUS
+    pub overflow: bool,
+}
+
+// Evaluates which sprites of `oam` are on `scanline`, running the PPU sprite evaluation of
+// dots 1 to 256 (see `SpriteEvaluator`).
+// Once 8 sprites are found, the hardware keeps looking for a 9th one to set the overflow flag,
+// but wrongly increments the byte index within a sprite along with the sprite index. It therefore
+// compares the tile, attribute or X byte of the next sprites as if they were Y coordinates, which
+// causes both false positives and false negatives. Games rely on this exact behavior.
+pub fn evaluate_sprites(oam: &[u8; 256], scanline: u16, sprite_height: u8) -> SpriteEvaluation {
+    let mut evaluator = SpriteEvaluator::new();
+    let mut status = PpuStatus::new();
+    for dot in 1..=256 {
+        evaluator.tick(dot, scanline, oam, sprite_height, &mut status);
+    }
+    evaluator.evaluation()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ppu::sprites::{evaluate_sprites, find_sprite_zero_hit, is_sprite_zero_hit, SpriteRow};
+
+    const RENDERING: u8 = 0b0001_1000;
+    const RENDERING_WITH_LEFT_COLUMNS: u8 = 0b0001_1110;
+
+    #[test]
+    fn test_sprite_zero_hit_conditions() {
+        assert!(is_sprite_zero_hit(100, true, true, RENDERING));
+        assert!(!is_sprite_zero_hit(100, false, true, RENDERING));
+        assert!(!is_sprite_zero_hit(100, true, false, RENDERING));
+        assert!(!is_sprite_zero_hit(100, true, true, 0b0000_1000));
+        // Left column clipping
+        assert!(!is_sprite_zero_hit(7, true, true, RENDERING));
+        assert!(!is_sprite_zero_hit(7, true, true, 0b0001_1010));
+        assert!(is_sprite_zero_hit(7, true, true, RENDERING_WITH_LEFT_COLUMNS));
+        assert!(is_sprite_zero_hit(8, true, true, RENDERING));
+        // x = 255 never hits
+        assert!(!is_sprite_zero_hit(255, true, true, RENDERING_WITH_LEFT_COLUMNS));
+    }
+
+    #[test]
+    fn test_find_sprite_zero_hit() {
+        let mut background = [false; 256];
+        background[102] = true;
+        background[103] = true;
+        // Sprite pixels 0 and 3 are opaque: x = 100 and x = 103
+        assert_eq!(find_sprite_zero_hit(&background, 100, 0b1001_0000, RENDERING), Some(103));
+        assert_eq!(find_sprite_zero_hit(&background, 100, 0b1000_0000, RENDERING), None);
+
+        // The last pixel of a sprite at x = 248 is x = 255, which never hits
+        background[255] = true;
+        assert_eq!(find_sprite_zero_hit(&background, 248, 0b0000_0001, RENDERING), None);
+        background[254] = true;
+        assert_eq!(find_sprite_zero_hit(&background, 248, 0b0000_0011, RENDERING), Some(254));
+    }
+
+    #[test]
+    fn test_evaluate_sprites() {
+        // Unused sprites are hidden below the screen (Y = 0xFF)
+        let mut oam = [0xFF; 256];
+        for sprite in 0..8 {
+            oam[sprite * 4] = 10;
+        }
+        let evaluation = evaluate_sprites(&oam, 12, 8);
+        assert_eq!(evaluation.sprites, (0..8).collect::<Vec<u8>>());
+        assert!(!evaluation.overflow);
+
+        // A 9th sprite in range sets the overflow flag
+        oam[8 * 4] = 12;
+        assert!(evaluate_sprites(&oam, 12, 8).overflow);
+        // But not on the scanline above it
+        assert!(!evaluate_sprites(&oam, 11, 8).overflow);
+        // 8x16 sprites are taller
+        assert!(evaluate_sprites(&oam, 20, 16).overflow);
+    }
+
+    #[test]
+    fn test_sprite_overflow_hardware_bug() {
+        let mut oam = [0xFF; 256];
+        for sprite in 0..8 {
+            oam[sprite * 4] = 10;
+        }
+        // Sprite 8 is out of range, so sprite 9 is checked using its tile byte instead of its Y
+        oam[9 * 4] = 10;
+        oam[9 * 4 + 1] = 0xFF;
+        assert!(!evaluate_sprites(&oam, 10, 8).overflow);
+
+        // False positive: the tile byte of sprite 9 looks like an in-range Y
+        oam[9 * 4] = 0xFF;
+        oam[9 * 4 + 1] = 10;
+        assert!(evaluate_sprites(&oam, 10, 8).overflow);
+    }
+
+    #[test]
+    fn test_sprite_row_pixels() {
+        let row = SpriteRow { index: 0, x: 250, attributes: 0, low: 0b1000_0001, high: 0b0000_0011 };
+        assert_eq!(row.pixel(249), None);
+        assert_eq!(row.pixel(250), Some(1));
+        assert_eq!(row.pixel(251), Some(0));
+        assert_eq!(row.pixel(255), Some(0));
+        // A sprite covers 8 columns
+        let row = SpriteRow { x: 8, ..row };
+        assert_eq!(row.pixel(14), Some(2));
+        assert_eq!(row.pixel(15), Some(3));
+        assert_eq!(row.pixel(16), None);
+    }
+}
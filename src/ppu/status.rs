@@ -0,0 +1,141 @@
+use crate::ppu::timing::{PpuClock, PPUSTATUS_SPRITE_OVERFLOW, PPUSTATUS_SPRITE_ZERO_HIT, PPUSTATUS_VBLANK};
+
+// PPUSTATUS (0x2002) flags and the NMI raised at the start of vblank.
+// Reading PPUSTATUS clears the vblank flag. When the read happens right when the flag is set
+// (dot 1 of scanline 241), the PPU and the CPU race:
+// - one dot before, the read returns the flag clear, and the flag is not set during this frame,
+// - on the same dot or one dot later, the read returns the flag set and clears it,
+// - in both cases, the NMI of this frame is not raised.
+// Games polling PPUSTATUS in a loop miss frames because of it, and timing test ROMs check it.
+// The race is only emulated when `vblank_race` is enabled, since it needs the CPU reads to be
+// timed to the PPU dot, which the fast accuracy level does not do.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PpuStatus {
+    pub flags: u8,
+    pub vblank_race: bool,
+    // Set by a read one dot before vblank, until the end of the frame
+    vblank_suppressed: bool,
+    nmi_pending: bool,
+    // Scanline and dot where the sprite zero hit flag was set, until taken for the event viewer
+    sprite_zero_hit_at: Option<(u16, u16)>,
+}
+
+impl PpuStatus {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Read of PPUSTATUS by the CPU while the PPU is at `clock`. Only bits 7-5 are returned,
+    // the others come from the open bus.
+    pub fn read(&mut self, clock: &PpuClock) -> u8 {
+        if self.vblank_race && clock.scanline == 241 {
+            match clock.dot {
+                0 => {
+                    self.vblank_suppressed = true;
+                    self.nmi_pending = false;
+                }
+                1 | 2 => self.nmi_pending = false,
+                _ => {}
+            }
+        }
+        let value = self.flags & (PPUSTATUS_VBLANK | PPUSTATUS_SPRITE_ZERO_HIT | PPUSTATUS_SPRITE_OVERFLOW);
+        self.flags &= !PPUSTATUS_VBLANK;
+        value
+    }
+
+    // Dot 1 of scanline 241.
+    pub fn start_vblank(&mut self) {
+        if !self.vblank_suppressed {
+            self.flags |= PPUSTATUS_VBLANK;
+            self.nmi_pending = true;
+        }
+    }
+
+    // Dot 1 of the pre-render scanline.
+    pub fn end_vblank(&mut self) {
+        self.flags &= !(PPUSTATUS_VBLANK | PPUSTATUS_SPRITE_ZERO_HIT | PPUSTATUS_SPRITE_OVERFLOW);
+        self.vblank_suppressed = false;
+        self.nmi_pending = false;
+    }
+
+    pub fn set_sprite_zero_hit(&mut self, scanline: u16, dot: u16) {
+        if self.flags & PPUSTATUS_SPRITE_ZERO_HIT == 0 {
+            self.flags |= PPUSTATUS_SPRITE_ZERO_HIT;
+            self.sprite_zero_hit_at = Some((scanline, dot));
+        }
+    }
+
+    pub fn take_sprite_zero_hit(&mut self) -> Option<(u16, u16)> {
+        self.sprite_zero_hit_at.take()
+    }
+
+    // Internal latches (vblank suppressed, NMI pending) as bits 0 and 1, saved in savestates.
+    pub fn latches(&self) -> u8 {
+        self.vblank_suppressed as u8 | (self.nmi_pending as u8) << 1
+    }
+
+    pub fn set_latches(&mut self, latches: u8) {
+        self.vblank_suppressed = latches & 0b01 != 0;
+        self.nmi_pending = latches & 0b10 != 0;
+    }
+
+    // Returns true once when the NMI of this frame has to be raised (if PPUCTRL enables it).
+    // The CPU sees the vblank flag change 2 dots late, so with the race emulated the NMI is only
+    // released from dot 3 of scanline 241, after the reads that can still cancel it.
+    pub fn take_nmi(&mut self, clock: &PpuClock) -> bool {
+        if self.vblank_race && clock.scanline == 241 && clock.dot < 3 {
+            return false;
+        }
+        std::mem::take(&mut self.nmi_pending)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ppu::scroll::ScrollRegisters;
+    use crate::ppu::status::PpuStatus;
+    use crate::ppu::timing::{PpuClock, PPUSTATUS_VBLANK};
+
+    // Runs a frame with a PPUSTATUS read at `read_dot` of scanline 241,
+    // and returns the value read and whether the NMI was raised.
+    fn read_near_vblank(vblank_race: bool, read_dot: u16) -> (u8, bool) {
+        let mut clock = PpuClock::new();
+        let mut status = PpuStatus { vblank_race, ..PpuStatus::default() };
+        let mut scroll = ScrollRegisters::new();
+        let (mut value, mut nmi) = (0, false);
+        while clock.scanline != 261 {
+            clock.tick(true, &mut status, &mut scroll);
+            if (clock.scanline, clock.dot) == (241, read_dot) {
+                value = status.read(&clock);
+            }
+            nmi |= status.take_nmi(&clock);
+        }
+        (value & PPUSTATUS_VBLANK, nmi)
+    }
+
+    #[test]
+    fn test_vblank_race() {
+        // One dot before: flag read clear and never set, no NMI
+        assert_eq!(read_near_vblank(true, 0), (0, false));
+        // Same dot or one later: flag read set, no NMI
+        assert_eq!(read_near_vblank(true, 1), (PPUSTATUS_VBLANK, false));
+        assert_eq!(read_near_vblank(true, 2), (PPUSTATUS_VBLANK, false));
+        // Later reads do not affect the NMI
+        assert_eq!(read_near_vblank(true, 3), (PPUSTATUS_VBLANK, true));
+    }
+
+    #[test]
+    fn test_without_race() {
+        assert_eq!(read_near_vblank(false, 0), (0, true));
+        assert_eq!(read_near_vblank(false, 1), (PPUSTATUS_VBLANK, true));
+    }
+
+    #[test]
+    fn test_read_clears_vblank() {
+        let mut status = PpuStatus::new();
+        status.start_vblank();
+        let clock = PpuClock { scanline: 250, dot: 10, frame: 0 };
+        assert_eq!(status.read(&clock), PPUSTATUS_VBLANK);
+        assert_eq!(status.read(&clock), 0);
+    }
+}
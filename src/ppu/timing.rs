@@ -0,0 +1,116 @@
+use crate::ppu::scroll::ScrollRegisters;
+use crate::ppu::status::PpuStatus;
+
+// Position of the PPU in the frame, advanced one dot at a time.
+// A frame is 262 scanlines of 341 dots: 240 visible scanlines, an idle one, 20 of vblank
+// (241 - 260) and the pre-render scanline (261), which fetches the first tiles of the next frame.
+// On the pre-render scanline, the PPU:
+// - clears the vblank, sprite zero hit and sprite overflow flags at dot 1,
+// - reloads the vertical scroll from t during dots 280 - 304 (see ScrollRegisters::tick),
+// - skips its last dot on odd frames when rendering is enabled, so these frames are one dot
+//   shorter. This keeps the NTSC color subcarrier phase alternating between frames.
+
+pub const PPUSTATUS_SPRITE_OVERFLOW: u8 = 0b0010_0000;
+pub const PPUSTATUS_SPRITE_ZERO_HIT: u8 = 0b0100_0000;
+pub const PPUSTATUS_VBLANK: u8 = 0b1000_0000;
+
+const DOTS_PER_SCANLINE: u16 = 341;
+const VBLANK_SCANLINE: u16 = 241;
+pub const PRE_RENDER_SCANLINE: u16 = 261;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PpuClock {
+    pub scanline: u16,
+    pub dot: u16,
+    pub frame: u64,
+}
+
+impl PpuClock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_odd_frame(&self) -> bool {
+        self.frame % 2 == 1
+    }
+
+    // Advances to the next dot and applies its effects on PPUSTATUS and the scroll registers.
+    // `rendering_enabled` is true when PPUMASK enables the background or the sprites.
+    // The NMI raised at the start of vblank is then given by `PpuStatus::take_nmi`.
+    pub fn tick(&mut self, rendering_enabled: bool, status: &mut PpuStatus, scroll: &mut ScrollRegisters) {
+        let skip_dot = rendering_enabled && self.is_odd_frame() && self.scanline == PRE_RENDER_SCANLINE && self.dot == 339;
+        self.dot += if skip_dot { 2 } else { 1 };
+        if self.dot >= DOTS_PER_SCANLINE {
+            self.dot = 0;
+            self.scanline += 1;
+            if self.scanline > PRE_RENDER_SCANLINE {
+                self.scanline = 0;
+                self.frame += 1;
+            }
+        }
+
+        if rendering_enabled {
+            scroll.tick(self.scanline, self.dot);
+        }
+        match (self.scanline, self.dot) {
+            (VBLANK_SCANLINE, 1) => status.start_vblank(),
+            (PRE_RENDER_SCANLINE, 1) => status.end_vblank(),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ppu::scroll::ScrollRegisters;
+use crate::ppu::status::PpuStatus;
+    use crate::ppu::timing::{PpuClock, PPUSTATUS_SPRITE_ZERO_HIT, PPUSTATUS_VBLANK};
+
+    // Number of dots until the next frame starts
+    fn frame_length(clock: &mut PpuClock, rendering_enabled: bool) -> u64 {
+        let (mut status, mut scroll) = (PpuStatus::new(), ScrollRegisters::new());
+        let frame = clock.frame;
+        let mut dots = 0;
+        while clock.frame == frame {
+            clock.tick(rendering_enabled, &mut status, &mut scroll);
+            dots += 1;
+        }
+        dots
+    }
+
+    #[test]
+    fn test_odd_frame_skip() {
+        let mut clock = PpuClock::new();
+        assert_eq!(frame_length(&mut clock, true), 89342);
+        assert_eq!(frame_length(&mut clock, true), 89341);
+        assert_eq!(frame_length(&mut clock, true), 89342);
+        // Only when rendering
+        assert_eq!(frame_length(&mut clock, false), 89342);
+        assert_eq!((clock.scanline, clock.dot), (0, 0));
+    }
+
+    #[test]
+    fn test_pre_render_scanline() {
+        let mut clock = PpuClock::new();
+        let (mut status, mut scroll) = (PpuStatus::new(), ScrollRegisters::new());
+        let mut nmis = 0;
+        while (clock.scanline, clock.dot) != (241, 1) {
+            clock.tick(true, &mut status, &mut scroll);
+            nmis += status.take_nmi(&clock) as u32;
+        }
+        assert_eq!(nmis, 1);
+        assert_eq!(status.flags, PPUSTATUS_VBLANK);
+
+        status.flags |= PPUSTATUS_SPRITE_ZERO_HIT | 0x1F;
+        scroll.t = 0x7BE0;
+        while (clock.scanline, clock.dot) != (261, 1) {
+            clock.tick(true, &mut status, &mut scroll);
+        }
+        // Low bits are open bus, not cleared
+        assert_eq!(status.flags, 0x1F);
+        while clock.dot != 305 {
+            clock.tick(true, &mut status, &mut scroll);
+        }
+        assert_eq!(scroll.v & 0x7BE0, 0x7BE0);
+    }
+}
@@ -0,0 +1,125 @@
+use crate::mapper::Mapper;
+use crate::rom::Mirroring;
+
+// Nametable memory of the PPU (0x2000 - 0x3EFF).
+// The console only has 2KB of VRAM (CIRAM) for 4 nametables of 1KB, so two of them are mirrors of
+// the other two, depending on how the cartridge wires the VRAM address lines:
+// - Horizontal: 0x2000 = 0x2400 and 0x2800 = 0x2C00 (vertical scrolling games)
+// - Vertical: 0x2000 = 0x2800 and 0x2400 = 0x2C00 (horizontal scrolling games)
+// - Single screen: all 4 nametables are the same 1KB
+// - Four screen: the cartridge provides 2KB of extra VRAM, so each nametable is distinct
+// 0x3000 - 0x3EFF mirrors 0x2000 - 0x2EFF.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Vram {
+    ciram: Vec<u8>,
+    // Extra VRAM of four screen cartridges (nametables 2 and 3)
+    cartridge_vram: Vec<u8>,
+}
+
+impl Default for Vram {
+    fn default() -> Self {
+        Self {
+            ciram: vec![0; 0x800],
+            cartridge_vram: vec![0; 0x800],
+        }
+    }
+}
+
+impl Vram {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // The mirroring is asked to the mapper on every access, since some mappers change it at runtime.
+    pub fn read(&self, addr: u16, mapper: &dyn Mapper) -> u8 {
+        let index = mirror_nametable_address(addr, mapper.mirroring());
+        match index {
+            0x000..=0x7FF => self.ciram[index],
+            _ => self.cartridge_vram[index - 0x800],
+        }
+    }
+
+    pub fn write(&mut self, addr: u16, data: u8, mapper: &dyn Mapper) {
+        let index = mirror_nametable_address(addr, mapper.mirroring());
+        match index {
+            0x000..=0x7FF => self.ciram[index] = data,
+            _ => self.cartridge_vram[index - 0x800] = data,
+        }
+    }
+
+    // CIRAM and the four screen VRAM, for savestates.
+    pub fn contents(&self) -> (&[u8], &[u8]) {
+        (&self.ciram, &self.cartridge_vram)
+    }
+
+    pub fn set_contents(&mut self, ciram: &[u8], cartridge_vram: &[u8]) {
+        self.ciram.copy_from_slice(ciram);
+        self.cartridge_vram.copy_from_slice(cartridge_vram);
+    }
+}
+
+// Converts a nametable address to an index in the 4KB of nametables (2KB CIRAM + 2KB four screen VRAM).
+pub fn mirror_nametable_address(addr: u16, mirroring: Mirroring) -> usize {
+    let nametable = ((addr & 0x0FFF) >> 10) as usize;
+    let offset = (addr & 0x03FF) as usize;
+    let physical_nametable = match mirroring {
+        Mirroring::Horizontal => nametable >> 1,
+        Mirroring::Vertical => nametable & 1,
+        Mirroring::SingleScreenLower => 0,
+        Mirroring::SingleScreenUpper => 1,
+        Mirroring::FourScreen => nametable,
+    };
+    physical_nametable * 0x400 + offset
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mapper::Mapper;
+    use crate::ppu::vram::{mirror_nametable_address, Vram};
+    use crate::rom::Mirroring;
+
+    // Mapper switching the mirroring at runtime, like MMC1
+    #[derive(Debug)]
+    struct SwitchableMirroring(Mirroring);
+
+    impl Mapper for SwitchableMirroring {
+        fn mirroring(&self) -> Mirroring {
+            self.0
+        }
+
+        fn prg_rom_index(&self, addr: u16) -> usize {
+            (addr - 0x8000) as usize
+        }
+    }
+
+    #[test]
+    fn test_mirror_nametable_address() {
+        let nametables = [0x2005, 0x2405, 0x2805, 0x2C05];
+        let mirrored = |mirroring| nametables.map(|addr| mirror_nametable_address(addr, mirroring));
+
+        assert_eq!(mirrored(Mirroring::Horizontal), [0x005, 0x005, 0x405, 0x405]);
+        assert_eq!(mirrored(Mirroring::Vertical), [0x005, 0x405, 0x005, 0x405]);
+        assert_eq!(mirrored(Mirroring::SingleScreenLower), [0x005; 4]);
+        assert_eq!(mirrored(Mirroring::SingleScreenUpper), [0x405; 4]);
+        assert_eq!(mirrored(Mirroring::FourScreen), [0x005, 0x405, 0x805, 0xC05]);
+        // 0x3000 - 0x3EFF mirrors 0x2000 - 0x2EFF
+        assert_eq!(mirror_nametable_address(0x3C05, Mirroring::FourScreen), 0xC05);
+    }
+
+    #[test]
+    fn test_mirroring_changed_by_mapper() {
+        let mut vram = Vram::new();
+        let mut mapper = SwitchableMirroring(Mirroring::Vertical);
+        vram.write(0x2400, 0x42, &mapper);
+        assert_eq!(vram.read(0x2C00, &mapper), 0x42);
+        assert_eq!(vram.read(0x2000, &mapper), 0x00);
+
+        mapper.0 = Mirroring::SingleScreenUpper;
+        assert_eq!(vram.read(0x2000, &mapper), 0x42);
+
+        mapper.0 = Mirroring::FourScreen;
+        vram.write(0x2C00, 0x99, &mapper);
+        assert_eq!(vram.read(0x2400, &mapper), 0x42);
+        assert_eq!(vram.read(0x2C00, &mapper), 0x99);
+    }
+}
@@ -0,0 +1,158 @@
+use std::collections::BTreeMap;
+use crate::cpu6502::CPU;
+use crate::labels::Labels;
+
+// Cycle profiler built on the shadow call stack (see call_stack.rs): the cycles of each instruction
+// are charged to the subroutines and interrupt handlers active while it runs, keyed by their entry
+// address. Homebrew developers can see which routines eat their vblank budget.
+// An instruction belongs to the functions on the stack before it runs: a JSR is charged to its
+// caller, the RTS to the subroutine it leaves. An interrupt is charged to the code it interrupted.
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct FunctionProfile {
+    pub calls: u64,
+    // Cycles spent in the function and in the functions it called
+    pub inclusive_cycles: u64,
+    // Cycles spent in the function itself
+    pub exclusive_cycles: u64,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Profiler {
+    functions: BTreeMap<u16, FunctionProfile>,
+    total_cycles: u64,
+    // Entry addresses of the active functions, reused from one instruction to the next
+    active: Vec<u16>,
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Executes one instruction and charges its cycles.
+    pub fn step(&mut self, cpu: &mut CPU) -> u64 {
+        self.active.clear();
+        self.active.extend(cpu.call_stack().frames().iter().map(|frame| frame.target));
+        let cycles_before = cpu.cycles;
+        let cycles = cpu.step();
+
+        self.total_cycles += cycles;
+        for (index, target) in self.active.iter().enumerate() {
+            // A recursive function is only charged once
+            if !self.active[..index].contains(target) {
+                self.functions.entry(*target).or_default().inclusive_cycles += cycles;
+            }
+        }
+        if let Some(target) = self.active.last() {
+            self.functions.entry(*target).or_default().exclusive_cycles += cycles;
+        }
+        for frame in cpu.call_stack().frames().iter().filter(|frame| frame.cycle >= cycles_before) {
+            self.functions.entry(frame.target).or_default().calls += 1;
+        }
+        cycles
+    }
+
+    // Profiles the execution until the cycle counter reaches `cycle` (or the CPU jams).
+    pub fn run_until(&mut self, cpu: &mut CPU, cycle: u64) {
+        while !cpu.halted && cpu.cycles < cycle {
+            self.step(cpu);
+        }
+    }
+
+    pub fn total_cycles(&self) -> u64 {
+        self.total_cycles
+    }
+
+    pub fn function(&self, address: u16) -> Option<&FunctionProfile> {
+        self.functions.get(&address)
+    }
+
+    pub fn reset(&mut self) {
+        self.functions.clear();
+        self.total_cycles = 0;
+    }
+
+    // Profiled functions, the most expensive (inclusive cycles) first.
+    pub fn report(&self) -> Vec<(u16, FunctionProfile)> {
+        let mut report: Vec<(u16, FunctionProfile)> = self.functions.iter().map(|(address, profile)| (*address, *profile)).collect();
+        report.sort_by_key(|(_, profile)| std::cmp::Reverse(profile.inclusive_cycles));
+        report
+    }
+
+    // Report as a text table, functions being named after their label when there is one.
+    pub fn format_report(&self, labels: &Labels) -> String {
+        let mut text = format!("{:<24} {:>8} {:>12} {:>7} {:>12} {:>7}\n", "Function", "Calls", "Inclusive", "%", "Exclusive", "%");
+        let percent = |cycles: u64| cycles as f64 * 100.0 / self.total_cycles.max(1) as f64;
+        for (address, profile) in self.report() {
+            text.push_str(&format!(
+                "{:<24} {:>8} {:>12} {:>6.2}% {:>12} {:>6.2}%\n",
+                labels.format_address(address, false), profile.calls,
+                profile.inclusive_cycles, percent(profile.inclusive_cycles),
+                profile.exclusive_cycles, percent(profile.exclusive_cycles)
+            ));
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bus::Bus;
+    use crate::cpu6502::{new_cpu, CPU};
+    use crate::labels::Labels;
+    use crate::profiler::Profiler;
+
+    // 0200: JSR $0210, JSR $0210, KIL
+    // 0210: NOP, JSR $0220, RTS
+    // 0220: NOP, NOP, RTS
+    fn cpu_with_program() -> CPU {
+        let mut cpu = new_cpu(Bus::new_flat());
+        let routines: [(u16, &[u8]); 3] = [
+            (0x0200, &[0x20, 0x10, 0x02, 0x20, 0x10, 0x02, 0x02]),
+            (0x0210, &[0xEA, 0x20, 0x20, 0x02, 0x60]),
+            (0x0220, &[0xEA, 0xEA, 0x60]),
+        ];
+        for (address, code) in routines {
+            for (offset, byte) in code.iter().enumerate() {
+                cpu.write_u8(address + offset as u16, *byte);
+            }
+        }
+        cpu.program_counter = 0x0200;
+        cpu.stack_pointer = 0xFD;
+        cpu
+    }
+
+    #[test]
+    fn test_inclusive_and_exclusive_cycles() {
+        let mut cpu = cpu_with_program();
+        let mut profiler = Profiler::new();
+        profiler.run_until(&mut cpu, u64::MAX);
+
+        // Inner: NOP, NOP (2 + 2) and RTS (6)
+        let inner = profiler.function(0x0220).unwrap();
+        assert_eq!((inner.calls, inner.inclusive_cycles, inner.exclusive_cycles), (2, 20, 20));
+        // Outer: NOP (2), JSR (6) and RTS (6), plus the inner routine
+        let outer = profiler.function(0x0210).unwrap();
+        assert_eq!((outer.calls, outer.inclusive_cycles, outer.exclusive_cycles), (2, 48, 28));
+        // Both JSR of the main code are not in any function
+        assert_eq!(profiler.total_cycles(), 48 + 12);
+
+        let report = profiler.report();
+        assert_eq!(report.iter().map(|(address, _)| *address).collect::<Vec<u16>>(), vec![0x0210, 0x0220]);
+    }
+
+    #[test]
+    fn test_format_report_uses_labels() {
+        let mut cpu = cpu_with_program();
+        let mut profiler = Profiler::new();
+        profiler.run_until(&mut cpu, u64::MAX);
+
+        let mut labels = Labels::new();
+        labels.insert(0x0220, "update_sprites", 1);
+        let report = profiler.format_report(&labels);
+        assert!(report.lines().nth(1).unwrap().starts_with("$0210"));
+        assert!(report.lines().nth(2).unwrap().starts_with("update_sprites  "), "unexpected report:\n{}", report);
+        assert!(report.contains("33.33%"));
+    }
+}
@@ -0,0 +1,591 @@
+use std::collections::HashMap;
+use crate::frame_pacer::NTSC_FRAME_RATE;
+use crate::hash::{adler32, crc32};
+
+// Records gameplay clips: a fixed number of RGB24 frames (3 bytes per pixel, as produced by
+// Palette::render_scanline), encoded to an animated GIF or APNG once complete.
+// Both encoders are written by hand to avoid depending on image crates:
+// - GIF uses a local color table per frame (an NES frame never has more than 64 colors, or a few
+//   more with emphasis changes mid-frame) and LZW compression.
+// - APNG stores the frames uncompressed (zlib stored blocks), which is simple and lossless.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RecordingFormat {
+    Gif,
+    Apng,
+}
+
+impl RecordingFormat {
+    // Format matching the extension of `path` (.gif, .png or .apng).
+    pub fn from_path(path: &str) -> Result<Self, String> {
+        let extension = path.rsplit_once('.').map(|(_, extension)| extension.to_ascii_lowercase());
+        match extension.as_deref() {
+            Some("gif") => Ok(RecordingFormat::Gif),
+            Some("png") | Some("apng") => Ok(RecordingFormat::Apng),
+            _ => Err(format!("Unsupported recording format: {} (expected .gif, .png or .apng)", path)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecorderOptions {
+    // Number of frames of the clip
+    pub frame_count: usize,
+    // Frames dropped between two recorded frames (1 records at 30 fps)
+    pub frame_skip: u32,
+    // Integer scaling factor (nearest neighbor)
+    pub scale: u32,
+}
+
+impl Default for RecorderOptions {
+    fn default() -> Self {
+        // 10 seconds at full frame rate
+        Self { frame_count: 600, frame_skip: 0, scale: 1 }
+    }
+}
+
+const MAX_SCALE: u32 = 8;
+
+#[derive(Debug)]
+pub struct Recorder {
+    format: RecordingFormat,
+    options: RecorderOptions,
+    // Size of the frames pushed, before scaling
+    width: usize,
+    height: usize,
+    // Scaled RGB24 frames
+    frames: Vec<Vec<u8>>,
+    // Frames to drop before recording the next one
+    frames_to_skip: u32,
+}
+
+impl Recorder {
+    pub fn new(format: RecordingFormat, width: usize, height: usize, options: RecorderOptions) -> Result<Self, String> {
+        if width == 0 || height == 0 {
+            return Err(format!("Invalid frame size {}x{}", width, height));
+        }
+        if options.frame_count == 0 {
+            return Err("Invalid frame count 0".to_string());
+        }
+        if !(1..=MAX_SCALE).contains(&options.scale) {
+            return Err(format!("Invalid scale {}: Expected 1 to {}", options.scale, MAX_SCALE));
+        }
+        let (scaled_width, scaled_height) = (width * options.scale as usize, height * options.scale as usize);
+        if scaled_width > u16::MAX as usize || scaled_height > u16::MAX as usize {
+            return Err(format!("Frame size {}x{} is too large", scaled_width, scaled_height));
+        }
+        Ok(Self {
+            format,
+            options,
+            width,
+            height,
+            frames: Vec::new(),
+            frames_to_skip: 0,
+        })
+    }
+
+    // Size of the recorded frames, after scaling.
+    pub fn output_size(&self) -> (usize, usize) {
+        let scale = self.options.scale as usize;
+        (self.width * scale, self.height * scale)
+    }
+
+    pub fn recorded_frames(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.frames.len() >= self.options.frame_count
+    }
+
+    // Records an RGB24 frame, unless it is skipped. Returns true once the clip is complete,
+    // the following frames being ignored.
+    pub fn push_frame(&mut self, rgb: &[u8]) -> Result<bool, String> {
+        if rgb.len() != self.width * self.height * 3 {
+            return Err(format!("Invalid frame size: {} bytes, expected {}", rgb.len(), self.width * self.height * 3));
+        }
+        if self.is_complete() {
+            return Ok(true);
+        }
+        if self.frames_to_skip > 0 {
+            self.frames_to_skip -= 1;
+            return Ok(false);
+        }
+        self.frames_to_skip = self.options.frame_skip;
+        let frame = self.scale(rgb);
+        self.frames.push(frame);
+        Ok(self.is_complete())
+    }
+
+    fn scale(&self, rgb: &[u8]) -> Vec<u8> {
+        let scale = self.options.scale as usize;
+        if scale == 1 {
+            return rgb.to_vec();
+        }
+        let mut scaled = Vec::with_capacity(rgb.len() * scale * scale);
+        for row in rgb.chunks_exact(self.width * 3) {
+            let mut scaled_row = Vec::with_capacity(row.len() * scale);
+            for pixel in row.chunks_exact(3) {
+                for _ in 0..scale {
+                    scaled_row.extend_from_slice(pixel);
+                }
+            }
+            for _ in 0..scale {
+                scaled.extend_from_slice(&scaled_row);
+            }
+        }
+        scaled
+    }
+
+    // Encodes the frames recorded so far.
+    pub fn encode(&self) -> Result<Vec<u8>, String> {
+        if self.frames.is_empty() {
+            return Err("No frame recorded".to_string());
+        }
+        match self.format {
+            RecordingFormat::Gif => self.encode_gif(),
+            RecordingFormat::Apng => Ok(self.encode_apng()),
+        }
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        let data = self.encode()?;
+        std::fs::write(path, data).map_err(|e| format!("Cannot write {}: {}", path, e))
+    }
+
+    // Time at which the recorded frame `index` is displayed, in hundredths of a second.
+    fn frame_time_centiseconds(&self, index: usize) -> u64 {
+        let frames = index as f64 * (self.options.frame_skip + 1) as f64;
+        (frames * 100.0 / NTSC_FRAME_RATE).round() as u64
+    }
+
+    fn encode_gif(&self) -> Result<Vec<u8>, String> {
+        let (width, height) = self.output_size();
+        let mut gif = Vec::new();
+        gif.extend_from_slice(b"GIF89a");
+        // Logical screen descriptor, without global color table
+        gif.extend_from_slice(&(width as u16).to_le_bytes());
+        gif.extend_from_slice(&(height as u16).to_le_bytes());
+        gif.extend_from_slice(&[0x00, 0x00, 0x00]);
+        // Loops forever
+        gif.extend_from_slice(&[0x21, 0xFF, 0x0B]);
+        gif.extend_from_slice(b"NETSCAPE2.0");
+        gif.extend_from_slice(&[0x03, 0x01, 0x00, 0x00, 0x00]);
+
+        for (index, frame) in self.frames.iter().enumerate() {
+            // GIF delays are in hundredths of a second: they are rounded so the clip keeps the right
+            // average speed (60 fps alternates 1 and 2 centiseconds)
+            let delay = self.frame_time_centiseconds(index + 1) - self.frame_time_centiseconds(index);
+            gif.extend_from_slice(&[0x21, 0xF9, 0x04, 0x00]);
+            gif.extend_from_slice(&(delay as u16).to_le_bytes());
+            gif.extend_from_slice(&[0x00, 0x00]);
+
+            let (color_table, indexes) = gif_indexed_frame(frame)
+                .map_err(|e| format!("Frame {}: {}", index, e))?;
+            // Image descriptor with a local color table of 256 entries
+            gif.push(0x2C);
+            gif.extend_from_slice(&[0x00, 0x00, 0x00, 0x00]);
+            gif.extend_from_slice(&(width as u16).to_le_bytes());
+            gif.extend_from_slice(&(height as u16).to_le_bytes());
+            gif.push(0x87);
+            gif.extend_from_slice(&color_table);
+
+            gif.push(GIF_MIN_CODE_SIZE);
+            for block in gif_lzw_compress(&indexes).chunks(255) {
+                gif.push(block.len() as u8);
+                gif.extend_from_slice(block);
+            }
+            gif.push(0x00);
+        }
+        gif.push(0x3B);
+        Ok(gif)
+    }
+
+    fn encode_apng(&self) -> Vec<u8> {
+        let (width, height) = self.output_size();
+        let mut png = Vec::new();
+        png.extend_from_slice(&[0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A]);
+
+        // 8-bit RGB, no interlacing
+        let mut ihdr = Vec::new();
+        ihdr.extend_from_slice(&(width as u32).to_be_bytes());
+        ihdr.extend_from_slice(&(height as u32).to_be_bytes());
+        ihdr.extend_from_slice(&[8, 2, 0, 0, 0]);
+        write_png_chunk(&mut png, b"IHDR", &ihdr);
+
+        // Animation control: number of frames, loops forever
+        let mut actl = Vec::new();
+        actl.extend_from_slice(&(self.frames.len() as u32).to_be_bytes());
+        actl.extend_from_slice(&0u32.to_be_bytes());
+        write_png_chunk(&mut png, b"acTL", &actl);
+
+        // Frame delay in seconds, as a fraction of 16-bit values: (frame_skip + 1) / 60.0988
+        let delay_numerator = ((self.options.frame_skip + 1) * 1000).min(u16::MAX as u32) as u16;
+        let delay_denominator = (NTSC_FRAME_RATE * 1000.0).round() as u16;
+        let mut sequence_number = 0u32;
+        for (index, frame) in self.frames.iter().enumerate() {
+            let mut fctl = Vec::new();
+            fctl.extend_from_slice(&sequence_number.to_be_bytes());
+            fctl.extend_from_slice(&(width as u32).to_be_bytes());
+            fctl.extend_from_slice(&(height as u32).to_be_bytes());
+            // X and Y offsets
+            fctl.extend_from_slice(&[0; 8]);
+            fctl.extend_from_slice(&delay_numerator.to_be_bytes());
+            fctl.extend_from_slice(&delay_denominator.to_be_bytes());
+            // No disposal, the frame replaces the previous one
+            fctl.extend_from_slice(&[0, 0]);
+            write_png_chunk(&mut png, b"fcTL", &fctl);
+            sequence_number += 1;
+
+            // Each row starts with its filter type (0: none)
+            let mut scanlines = Vec::with_capacity(frame.len() + height);
+            for row in frame.chunks_exact(width * 3) {
+                scanlines.push(0);
+                scanlines.extend_from_slice(row);
+            }
+            let data = zlib_stored(&scanlines);
+            if index == 0 {
+                // The first frame is the default image, shown by viewers without APNG support
+                write_png_chunk(&mut png, b"IDAT", &data);
+            } else {
+                let mut fdat = sequence_number.to_be_bytes().to_vec();
+                fdat.extend_from_slice(&data);
+                write_png_chunk(&mut png, b"fdAT", &fdat);
+                sequence_number += 1;
+            }
+        }
+        write_png_chunk(&mut png, b"IEND", &[]);
+        png
+    }
+}
+
+// Frame indexes are 8 bits wide
+const GIF_MIN_CODE_SIZE: u8 = 8;
+const GIF_CLEAR_CODE: u16 = 256;
+const GIF_END_CODE: u16 = 257;
+const GIF_MAX_CODE: u16 = 4096;
+
+// Converts an RGB24 frame to a color table of 256 entries and the index of each pixel in it.
+fn gif_indexed_frame(rgb: &[u8]) -> Result<(Vec<u8>, Vec<u8>), String> {
+    let mut color_table = Vec::with_capacity(256 * 3);
+    let mut colors: HashMap<&[u8], u8> = HashMap::new();
+    let mut indexes = Vec::with_capacity(rgb.len() / 3);
+    for pixel in rgb.chunks_exact(3) {
+        let index = match colors.get(pixel) {
+            Some(index) => *index,
+            None => {
+                if colors.len() == 256 {
+                    return Err("More than 256 colors".to_string());
+                }
+                let index = colors.len() as u8;
+                colors.insert(pixel, index);
+                color_table.extend_from_slice(pixel);
+                index
+            }
+        };
+        indexes.push(index);
+    }
+    color_table.resize(256 * 3, 0);
+    Ok((color_table, indexes))
+}
+
+// Variable-length LZW compression of GIF image data: codes start at 9 bits and grow up to 12 bits,
+// a clear code restarting the dictionary once it is full.
+fn gif_lzw_compress(indexes: &[u8]) -> Vec<u8> {
+    let mut output = Vec::new();
+    let mut bit_buffer = 0u32;
+    let mut bit_count = 0;
+    let mut write_code = |code: u16, code_size: u32, output: &mut Vec<u8>| {
+        bit_buffer |= (code as u32) << bit_count;
+        bit_count += code_size;
+        while bit_count >= 8 {
+            output.push(bit_buffer as u8);
+            bit_buffer >>= 8;
+            bit_count -= 8;
+        }
+    };
+
+    let mut dictionary: HashMap<(u16, u8), u16> = HashMap::new();
+    let mut next_code = GIF_END_CODE + 1;
+    let mut code_size = GIF_MIN_CODE_SIZE as u32 + 1;
+    write_code(GIF_CLEAR_CODE, code_size, &mut output);
+
+    let mut prefix: Option<u16> = None;
+    for &index in indexes {
+        let Some(current) = prefix else {
+            prefix = Some(index as u16);
+            continue;
+        };
+        if let Some(code) = dictionary.get(&(current, index)) {
+            prefix = Some(*code);
+            continue;
+        }
+        write_code(current, code_size, &mut output);
+        if next_code < GIF_MAX_CODE {
+            dictionary.insert((current, index), next_code);
+            next_code += 1;
+            // The decoder adds its entries one code later, so it switches to a larger code size
+            // once the code after this one exists
+            if next_code > (1 << code_size) && code_size < 12 {
+                code_size += 1;
+            }
+        } else {
+            write_code(GIF_CLEAR_CODE, code_size, &mut output);
+            dictionary.clear();
+            next_code = GIF_END_CODE + 1;
+            code_size = GIF_MIN_CODE_SIZE as u32 + 1;
+        }
+        prefix = Some(index as u16);
+    }
+    if let Some(current) = prefix {
+        write_code(current, code_size, &mut output);
+        // The decoder adds an entry for this code too, which may grow the code size
+        if next_code < GIF_MAX_CODE && next_code + 1 > (1 << code_size) && code_size < 12 {
+            code_size += 1;
+        }
+    }
+    write_code(GIF_END_CODE, code_size, &mut output);
+    if bit_count > 0 {
+        output.push(bit_buffer as u8);
+    }
+    output
+}
+
+fn write_png_chunk(png: &mut Vec<u8>, chunk_type: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend_from_slice(chunk_type);
+    png.extend_from_slice(data);
+    let crc = crc32(&png[start..]);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+// zlib stream made of uncompressed deflate blocks.
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut zlib = vec![0x78, 0x01];
+    let mut blocks = data.chunks(0xFFFF).peekable();
+    if blocks.peek().is_none() {
+        zlib.extend_from_slice(&[0x01, 0x00, 0x00, 0xFF, 0xFF]);
+    }
+    while let Some(block) = blocks.next() {
+        let last = blocks.peek().is_none();
+        zlib.push(last as u8);
+        zlib.extend_from_slice(&(block.len() as u16).to_le_bytes());
+        zlib.extend_from_slice(&(!(block.len() as u16)).to_le_bytes());
+        zlib.extend_from_slice(block);
+    }
+    zlib.extend_from_slice(&adler32(data).to_be_bytes());
+    zlib
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::archive::inflate;
+    use crate::hash::crc32;
+    use crate::recorder::{gif_lzw_compress, Recorder, RecorderOptions, RecordingFormat};
+
+    // Reference GIF LZW decoder, following the GIF89a specification
+    fn gif_lzw_decompress(data: &[u8]) -> Vec<u8> {
+        let mut output = Vec::new();
+        let mut dictionary: Vec<Vec<u8>> = Vec::new();
+        let mut code_size = 9;
+        let mut previous: Option<usize> = None;
+        let mut bit_position = 0;
+        loop {
+            let mut code = 0usize;
+            for bit in 0..code_size {
+                let byte = data[(bit_position + bit) / 8];
+                code |= (((byte >> ((bit_position + bit) % 8)) & 1) as usize) << bit;
+            }
+            bit_position += code_size;
+            if code == 256 {
+                dictionary = (0..=255u8).map(|index| vec![index]).collect();
+                dictionary.push(Vec::new());
+                dictionary.push(Vec::new());
+                code_size = 9;
+                previous = None;
+                continue;
+            }
+            if code == 257 {
+                return output;
+            }
+            let entry = match previous {
+                None => dictionary[code].clone(),
+                Some(previous) => {
+                    let entry = if code < dictionary.len() {
+                        dictionary[code].clone()
+                    } else {
+                        let mut entry = dictionary[previous].clone();
+                        entry.push(entry[0]);
+                        entry
+                    };
+                    if dictionary.len() < 4096 {
+                        let mut new_entry = dictionary[previous].clone();
+                        new_entry.push(entry[0]);
+                        dictionary.push(new_entry);
+                        if dictionary.len() == 1 << code_size && code_size < 12 {
+                            code_size += 1;
+                        }
+                    }
+                    entry
+                }
+            };
+            output.extend_from_slice(&entry);
+            previous = Some(code);
+        }
+    }
+
+    fn test_frame(width: usize, height: usize, seed: u8) -> Vec<u8> {
+        (0..width * height)
+            .flat_map(|pixel| {
+                let value = (pixel as u8).wrapping_mul(seed) % 48;
+                [value, value.wrapping_add(seed), 0x20]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_gif_lzw_round_trip() {
+        let uniform = vec![7u8; 10_000];
+        assert_eq!(gif_lzw_decompress(&gif_lzw_compress(&uniform)), uniform);
+
+        // Pseudo-random data fills the dictionary several times
+        let mut state = 1u32;
+        let noise: Vec<u8> = (0..50_000)
+            .map(|_| {
+                state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                (state >> 16) as u8
+            })
+            .collect();
+        assert_eq!(gif_lzw_decompress(&gif_lzw_compress(&noise)), noise);
+
+        for length in 0..600 {
+            let data: Vec<u8> = (0..length).map(|i| (i % 3) as u8).collect();
+            assert_eq!(gif_lzw_decompress(&gif_lzw_compress(&data)), data);
+        }
+    }
+
+    #[test]
+    fn test_frame_skip_and_scale() {
+        let options = RecorderOptions { frame_count: 2, frame_skip: 1, scale: 2 };
+        let mut recorder = Recorder::new(RecordingFormat::Gif, 2, 1, options).unwrap();
+        assert_eq!(recorder.output_size(), (4, 2));
+
+        let frame = [1, 2, 3, 4, 5, 6];
+        assert!(!recorder.push_frame(&frame).unwrap());
+        // Skipped
+        assert!(!recorder.push_frame(&[0; 6]).unwrap());
+        assert!(recorder.push_frame(&frame).unwrap());
+        assert_eq!(recorder.recorded_frames(), 2);
+        assert!(recorder.push_frame(&frame).unwrap());
+        assert_eq!(recorder.recorded_frames(), 2);
+        assert_eq!(
+            recorder.frames[1],
+            [1, 2, 3, 1, 2, 3, 4, 5, 6, 4, 5, 6, 1, 2, 3, 1, 2, 3, 4, 5, 6, 4, 5, 6]
+        );
+
+        assert!(recorder.push_frame(&[0; 3]).is_err());
+        assert!(Recorder::new(RecordingFormat::Gif, 256, 240, RecorderOptions { scale: 0, ..Default::default() }).is_err());
+    }
+
+    #[test]
+    fn test_gif() {
+        let options = RecorderOptions { frame_count: 3, frame_skip: 0, scale: 1 };
+        let mut recorder = Recorder::new(RecordingFormat::Gif, 16, 8, options).unwrap();
+        let frames: Vec<Vec<u8>> = (1..=3).map(|seed| test_frame(16, 8, seed)).collect();
+        for frame in &frames {
+            recorder.push_frame(frame).unwrap();
+        }
+        let gif = recorder.encode().unwrap();
+        assert_eq!(&gif[0..6], b"GIF89a");
+        assert_eq!(&gif[6..10], &[16, 0, 8, 0]);
+        assert_eq!(gif.last(), Some(&0x3B));
+
+        // Walks the frames: graphic control extension, image descriptor, color table, image data
+        let mut position = 13 + 19;
+        let mut delays = Vec::new();
+        for frame in &frames {
+            assert_eq!(&gif[position..position + 4], &[0x21, 0xF9, 0x04, 0x00]);
+            delays.push(u16::from_le_bytes([gif[position + 4], gif[position + 5]]));
+            position += 8;
+            assert_eq!(gif[position], 0x2C);
+            assert_eq!(gif[position + 9], 0x87);
+            let color_table = &gif[position + 10..position + 10 + 768];
+            position += 10 + 768;
+            assert_eq!(gif[position], 8);
+            position += 1;
+            let mut data = Vec::new();
+            while gif[position] != 0 {
+                let length = gif[position] as usize;
+                data.extend_from_slice(&gif[position + 1..position + 1 + length]);
+                position += 1 + length;
+            }
+            position += 1;
+            let pixels: Vec<u8> = gif_lzw_decompress(&data)
+                .iter()
+                .flat_map(|index| color_table[*index as usize * 3..*index as usize * 3 + 3].to_vec())
+                .collect();
+            assert_eq!(&pixels, frame);
+        }
+        // 60 fps: 1.66 centiseconds per frame on average
+        assert_eq!(delays, [2, 1, 2]);
+        assert_eq!(position, gif.len() - 1);
+    }
+
+    #[test]
+    fn test_gif_too_many_colors() {
+        let options = RecorderOptions { frame_count: 1, ..Default::default() };
+        let mut recorder = Recorder::new(RecordingFormat::Gif, 300, 1, options).unwrap();
+        let frame: Vec<u8> = (0..300u16).flat_map(|i| [(i >> 8) as u8, i as u8, 0]).collect();
+        recorder.push_frame(&frame).unwrap();
+        assert!(recorder.encode().is_err());
+    }
+
+    #[test]
+    fn test_apng() {
+        let options = RecorderOptions { frame_count: 2, frame_skip: 2, scale: 1 };
+        let mut recorder = Recorder::new(RecordingFormat::Apng, 4, 3, options).unwrap();
+        let frames: Vec<Vec<u8>> = (1..=2).map(|seed| test_frame(4, 3, seed)).collect();
+        for frame in &frames {
+            for _ in 0..3 {
+                recorder.push_frame(frame).unwrap();
+            }
+        }
+        assert!(recorder.is_complete());
+        let png = recorder.encode().unwrap();
+        assert_eq!(&png[0..8], &[0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A]);
+
+        let mut chunks = Vec::new();
+        let mut position = 8;
+        while position < png.len() {
+            let length = u32::from_be_bytes(png[position..position + 4].try_into().unwrap()) as usize;
+            let chunk = &png[position + 4..position + 8 + length];
+            let crc = u32::from_be_bytes(png[position + 8 + length..position + 12 + length].try_into().unwrap());
+            assert_eq!(crc32(chunk), crc);
+            chunks.push((String::from_utf8(chunk[0..4].to_vec()).unwrap(), chunk[4..].to_vec()));
+            position += 12 + length;
+        }
+        let names: Vec<&str> = chunks.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, ["IHDR", "acTL", "fcTL", "IDAT", "fcTL", "fdAT", "IEND"]);
+        assert_eq!(&chunks[1].1[0..4], &[0, 0, 0, 2]);
+        // Sequence numbers and delay (3 / 60.0988 seconds)
+        assert_eq!(&chunks[2].1[0..4], &[0, 0, 0, 0]);
+        assert_eq!(&chunks[4].1[0..4], &[0, 0, 0, 1]);
+        assert_eq!(&chunks[5].1[0..4], &[0, 0, 0, 2]);
+        assert_eq!(u16::from_be_bytes([chunks[2].1[20], chunks[2].1[21]]), 3000);
+        assert_eq!(u16::from_be_bytes([chunks[2].1[22], chunks[2].1[23]]), 60099);
+
+        for (data, frame) in [(&chunks[3].1[..], &frames[0]), (&chunks[5].1[4..], &frames[1])] {
+            let scanlines = inflate(&data[2..data.len() - 4]).unwrap();
+            let expected: Vec<u8> = frame.chunks(12).flat_map(|row| [&[0][..], row].concat()).collect();
+            assert_eq!(scanlines, expected);
+        }
+    }
+
+    #[test]
+    fn test_format_from_path() {
+        assert_eq!(RecordingFormat::from_path("clip.GIF"), Ok(RecordingFormat::Gif));
+        assert_eq!(RecordingFormat::from_path("clip.apng"), Ok(RecordingFormat::Apng));
+        assert!(RecordingFormat::from_path("clip.mp4").is_err());
+    }
+}
@@ -0,0 +1,243 @@
+use std::collections::VecDeque;
+use crate::cpu6502::CPU;
+use crate::savestate::state_cycles;
+
+// Rewind buffer built on top of savestates.
+// Only the most recent snapshot is kept in full. Every older snapshot is stored as a delta
+// against the snapshot captured right after it (XOR of both states, run-length encoded).
+// Consecutive states mostly differ by a few RAM bytes, so the deltas are very small,
+// and dropping the oldest entry never breaks the chain since nothing depends on it.
+#[derive(Debug)]
+pub struct Rewind {
+    // Maximum number of snapshots kept (the full one included)
+    capacity: usize,
+    // A snapshot is captured every `interval` frames
+    interval: u32,
+    frames_since_capture: u32,
+    latest: Option<Vec<u8>>,
+    deltas: VecDeque<Vec<u8>>,
+}
+
+impl Rewind {
+    pub fn new(capacity: usize, interval: u32) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            interval: interval.max(1),
+            frames_since_capture: 0,
+            latest: None,
+            deltas: VecDeque::new(),
+        }
+    }
+
+    // Must be called once per emulated frame. A snapshot is taken every `interval` frames.
+    pub fn on_frame(&mut self, cpu: &CPU) {
+        if self.frames_since_capture == 0 {
+            self.capture(cpu.save_state());
+        }
+        self.frames_since_capture = (self.frames_since_capture + 1) % self.interval;
+    }
+
+    // Number of snapshots currently available.
+    pub fn len(&self) -> usize {
+        self.deltas.len() + if self.latest.is_some() { 1 } else { 0 }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.latest.is_none()
+    }
+
+    pub fn clear(&mut self) {
+        self.latest = None;
+        self.deltas.clear();
+        self.frames_since_capture = 0;
+    }
+
+    fn capture(&mut self, state: Vec<u8>) {
+        if let Some(previous) = self.latest.take() {
+            if previous.len() == state.len() {
+                self.deltas.push_back(encode_delta(&previous, &state));
+            } else {
+                // States of different layouts cannot be diffed, start a new history.
+                self.deltas.clear();
+            }
+        }
+        self.latest = Some(state);
+
+        while self.len() > self.capacity {
+            self.deltas.pop_front();
+        }
+    }
+
+    // Takes a snapshot now, outside of the frame schedule (e.g. while single-stepping in the debugger).
+    pub fn capture_now(&mut self, cpu: &CPU) {
+        self.capture(cpu.save_state());
+    }
+
+    // Drops the snapshots taken at or after `cycle` and returns the most recent one taken before
+    // it, which stays in the buffer. Used to re-execute from a known state up to a given point.
+    pub fn snapshot_before(&mut self, cycle: u64) -> Result<Vec<u8>, String> {
+        while let Some(latest) = self.latest.take() {
+            if state_cycles(&latest)? < cycle {
+                self.latest = Some(latest.clone());
+                return Ok(latest);
+            }
+            self.latest = self.deltas.pop_back().map(|delta| decode_delta(&latest, &delta));
+        }
+        Err(format!("No snapshot before cycle {}", cycle))
+    }
+
+    // Goes back roughly `frames` frames (rounded down to the capture interval) and loads
+    // the corresponding snapshot into the CPU. Snapshots newer than the restored one are dropped.
+    // Returns the number of frames actually rewound, which is smaller when the history is too short.
+    pub fn rewind(&mut self, cpu: &mut CPU, frames: u32) -> Result<u32, String> {
+        let mut state = self.latest.take().ok_or_else(|| "Rewind buffer is empty".to_string())?;
+
+        let steps = ((frames / self.interval) as usize).min(self.deltas.len());
+        for _ in 0..steps {
+            let delta = self.deltas.pop_back().expect("BUG: rewind step should have a delta");
+            state = decode_delta(&state, &delta);
+        }
+
+        let result = cpu.load_state(&state);
+        self.latest = Some(state);
+        self.frames_since_capture = 0;
+        result?;
+
+        Ok(steps as u32 * self.interval)
+    }
+}
+
+// Encodes `older` relative to `newer` as a run-length encoded XOR.
+// The encoding is a sequence of [zero run length][literal length][literal bytes...],
+// each length being a single byte.
+pub fn encode_delta(older: &[u8], newer: &[u8]) -> Vec<u8> {
+    let xored: Vec<u8> = older.iter().zip(newer.iter()).map(|(a, b)| a ^ b).collect();
+    let mut encoded = Vec::new();
+    let mut i = 0;
+
+    while i < xored.len() {
+        let zero_start = i;
+        while i < xored.len() && xored[i] == 0 && i - zero_start < 0xFF {
+            i += 1;
+        }
+        encoded.push((i - zero_start) as u8);
+
+        let literal_start = i;
+        while i < xored.len() && xored[i] != 0 && i - literal_start < 0xFF {
+            i += 1;
+        }
+        encoded.push((i - literal_start) as u8);
+        encoded.extend_from_slice(&xored[literal_start..i]);
+    }
+    encoded
+}
+
+// Rebuilds the older state from the newer one and the delta produced by `encode_delta`.
+pub fn decode_delta(newer: &[u8], delta: &[u8]) -> Vec<u8> {
+    let mut older = newer.to_vec();
+    let mut position = 0;
+    let mut i = 0;
+
+    while i < delta.len() {
+        position += delta[i] as usize;
+        let literal_len = delta[i + 1] as usize;
+        i += 2;
+        for byte in &delta[i..i + literal_len] {
+            older[position] ^= byte;
+            position += 1;
+        }
+        i += literal_len;
+    }
+    older
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bus::Bus;
+    use crate::cpu6502::new_cpu;
+    use crate::rewind::{decode_delta, encode_delta, Rewind};
+    use crate::rom::Rom;
+    use crate::savestate::state_cycles;
+
+    #[test]
+    fn test_delta_round_trip() {
+        let newer = vec![0u8; 600];
+        let mut older = newer.clone();
+        older[0] = 0x12;
+        older[300] = 0x34;
+        older[301] = 0x56;
+        older[599] = 0x78;
+
+        let delta = encode_delta(&older, &newer);
+        assert!(delta.len() < older.len());
+        assert_eq!(decode_delta(&newer, &delta), older);
+    }
+
+    #[test]
+    fn test_rewind_restores_older_snapshot() {
+        let mut cpu = new_cpu(Bus::new(Rom::test_rom()));
+        let mut rewind = Rewind::new(10, 2);
+
+        // Frame N: accumulator holds the frame number
+        for frame in 0..8u8 {
+            cpu.accumulator = frame;
+            cpu.write_u8(0x0010, frame);
+            rewind.on_frame(&cpu);
+        }
+        // Snapshots were taken on frames 0, 2, 4 and 6
+        assert_eq!(rewind.len(), 4);
+
+        let rewound = rewind.rewind(&mut cpu, 4).expect("rewind should succeed");
+        assert_eq!(rewound, 4);
+        assert_eq!(cpu.accumulator, 2);
+        assert_eq!(cpu.read_u8(0x0010), 2);
+        assert_eq!(rewind.len(), 2);
+
+        // Asking for more than available stops at the oldest snapshot
+        let rewound = rewind.rewind(&mut cpu, 100).expect("rewind should succeed");
+        assert_eq!(rewound, 2);
+        assert_eq!(cpu.accumulator, 0);
+    }
+
+    #[test]
+    fn test_rewind_capacity_drops_oldest() {
+        let mut cpu = new_cpu(Bus::new(Rom::test_rom()));
+        let mut rewind = Rewind::new(3, 1);
+
+        for frame in 0..5u8 {
+            cpu.accumulator = frame;
+            rewind.on_frame(&cpu);
+        }
+        assert_eq!(rewind.len(), 3);
+
+        rewind.rewind(&mut cpu, 100).expect("rewind should succeed");
+        assert_eq!(cpu.accumulator, 2);
+    }
+
+    #[test]
+    fn test_snapshot_before() {
+        let mut cpu = new_cpu(Bus::new(Rom::test_rom()));
+        let mut rewind = Rewind::new(10, 1);
+        for cycles in [10, 20, 30] {
+            cpu.cycles = cycles;
+            rewind.capture_now(&cpu);
+        }
+
+        let state = rewind.snapshot_before(30).expect("a snapshot should be found");
+        cpu.load_state(&state).unwrap();
+        assert_eq!(cpu.cycles, 20);
+        // The snapshot taken at cycle 30 was dropped
+        assert_eq!(rewind.len(), 2);
+        let state = rewind.snapshot_before(25).expect("a snapshot should be found");
+        assert_eq!(state_cycles(&state), Ok(20));
+        assert!(rewind.snapshot_before(10).is_err());
+        assert_eq!(rewind.len(), 0);
+    }
+
+    #[test]
+    fn test_rewind_empty_buffer_fails() {
+        let mut cpu = new_cpu(Bus::new(Rom::test_rom()));
+        let mut rewind = Rewind::new(3, 1);
+        assert!(rewind.rewind(&mut cpu, 1).is_err());
+    }
+}
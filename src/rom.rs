@@ -1,14 +1,42 @@
+use std::fmt;
+use crate::archive::extract_rom;
+use crate::hash::{crc32, sha1};
+use crate::mapper::registry::MapperRegistry;
+use crate::rom_database::{self, RomDatabaseEntry};
+use crate::rom_info::Region;
+
 const HEADER_SIZE: usize = 16;
 const MAGIC_NUMBERS: &[u8; 4] = b"NES\x1a";
 
-#[derive(Debug, PartialEq, Clone, Copy)]
-pub enum MapperType {
-    Nrom = 0,  // Mario, Donkey Kong, etc.
-    Mmc1 = 1,  // Zelda, Metroid
-    Uxrom = 2, // Castlevania, Mega Man
-    Cnrom = 3, // Cybernoid
-    Mmc3 = 4,  // Super Mario Bros 3
-    Unknown,
+// Reasons a ROM file cannot be parsed.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RomError {
+    HeaderTooShort { len: usize },
+    NotInesFormat,
+    // The trainer, when present, is counted with the PRG ROM
+    TruncatedPrg { expected: usize, available: usize },
+    TruncatedChr { expected: usize, available: usize },
+}
+
+impl fmt::Display for RomError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RomError::HeaderTooShort { len } => write!(f, "Invalid ROM: File is {} bytes long, the header needs {}", len, HEADER_SIZE),
+            RomError::NotInesFormat => write!(f, "File is not in iNES format"),
+            RomError::TruncatedPrg { expected, available } => {
+                write!(f, "Invalid ROM: PRG ROM is truncated ({} bytes expected, {} available)", expected, available)
+            }
+            RomError::TruncatedChr { expected, available } => {
+                write!(f, "Invalid ROM: CHR ROM is truncated ({} bytes expected, {} available)", expected, available)
+            }
+        }
+    }
+}
+
+impl From<RomError> for String {
+    fn from(error: RomError) -> String {
+        error.to_string()
+    }
 }
 
 #[derive(Debug, PartialEq, Clone, Copy)]
@@ -16,12 +44,15 @@ pub enum Mirroring {
     Vertical,
     Horizontal,
     FourScreen,
+    // Every nametable address maps to the same 1KB. Only selected at runtime by mappers (AxROM, MMC1).
+    SingleScreenLower,
+    SingleScreenUpper,
 }
 
 // NES file header structure (16 bytes)
 #[allow(dead_code)]
 #[derive(Debug, Clone, Copy)]
-pub(crate) struct NesHeader {
+pub struct NesHeader {
     // The first 4 bytes should be "NES" followed by 0x1A (4E 45 53 1A)
     pub magic_numbers: [u8; 4],
     pub prg_rom_size: u8,
@@ -34,22 +65,79 @@ pub(crate) struct NesHeader {
     pub reserved: [u8; 5],
 }
 
+impl NesHeader {
+    // NES 2.0 headers are iNES headers with bits 2-3 of flags_7 set to 0b10, which give a meaning
+    // to the bytes that iNES leaves unused.
+    pub fn is_nes2(&self) -> bool {
+        (self.flags_7 & 0b0000_1100) == 0b0000_1000
+    }
+
+    // Size of PRG RAM in bytes. iNES counts 8KB units in byte 8 (0 meaning 8KB for compatibility),
+    // NES 2.0 stores the volatile and battery backed sizes as shift counts in byte 10.
+    pub fn prg_ram_bytes(&self) -> usize {
+        if !self.is_nes2() {
+            return self.prg_ram_size.max(1) as usize * 0x2000;
+        }
+        let shift_size = |shift: u8| if shift == 0 { 0 } else { 64 << shift };
+        shift_size(self.flags_10 & 0x0F) + shift_size(self.flags_10 >> 4)
+    }
+
+    // Bits 0-1 of flags_7: console the cartridge was made for.
+    // 1 is the VS Unisystem arcade board, 2 the PlayChoice-10 (a NES with an extra 8KB INST-ROM
+    // of instruction screens after CHR ROM, which is ignored since the console part runs alone).
+    pub fn is_vs_unisystem(&self) -> bool {
+        self.flags_7 & 0b0000_0011 == 0b01
+    }
+
+    pub fn is_playchoice10(&self) -> bool {
+        self.flags_7 & 0b0000_0011 == 0b10
+    }
+
+    // Low nibble of byte 13 of NES 2.0 headers: the PPU of a VS System game (see vs_system::VsPpu).
+    pub fn vs_ppu_type(&self) -> u8 {
+        self.reserved[2] & 0x0F
+    }
+
+    // Byte 15 of NES 2.0 headers: the input device the game expects (0 when unspecified).
+    pub fn expansion_device(&self) -> u8 {
+        if self.is_nes2() { self.reserved[4] & 0x3F } else { 0 }
+    }
+}
+
 // ROM structure to hold NES ROM data
 // Parsing is performed by following the header description at this link: (https://formats.kaitai.io/ines/index.html)
 #[allow(dead_code)]
 #[derive(Debug, Clone)]
-pub(crate) struct Rom {
+pub struct Rom {
     pub header: NesHeader,
     pub mirroring: Mirroring,
     pub mapper: u8,
     pub prg_rom: Vec<u8>,
     pub chr_rom: Vec<u8>,
+    // PRG RAM size in bytes. Only the 8KB of most cartridges are emulated by the bus for now.
+    pub prg_ram_size: usize,
+    pub region: Region,
+}
+
+// Values forced by the user when the header of a ROM is wrong, which is common in the dumps in
+// circulation. `None` keeps the value read from the header.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct RomOverrides {
+    pub mapper: Option<u8>,
+    pub mirroring: Option<Mirroring>,
+    pub prg_ram_size: Option<usize>,
+    pub region: Option<Region>,
 }
 
 impl Rom {
-    pub(crate) fn parse_nes_rom(rom_data: Vec<u8>) -> Result<Rom, String> {
+    // Bytes after the CHR ROM are ignored: many old dumps have garbage appended (e.g. a title),
+    // and PlayChoice-10 dumps store their INST-ROM there.
+    pub fn parse_nes_rom(rom_data: Vec<u8>) -> Result<Rom, RomError> {
+        if rom_data.len() < HEADER_SIZE {
+            return Err(RomError::HeaderTooShort { len: rom_data.len() });
+        }
         if &rom_data[0..4] != MAGIC_NUMBERS {
-            return Err("File is not in iNES format".to_string());
+            return Err(RomError::NotInesFormat);
         }
 
         // Parse the iNES header
@@ -78,17 +166,17 @@ impl Rom {
         // If true, the cartridge uses four-screen VRAM layout
         let four_screen = (header.flags_6 & 0b0000_1000) != 0;
 
-        // If true, the mirroring is horizontal instead of vertical
+        // If true, the mirroring is vertical instead of horizontal
         let mirrored = (header.flags_6 & 0b0000_0001) != 0;
 
-        // Bit 0: Mirroring (0=Vertical, 1=Horizontal)
+        // Bit 0: Mirroring (0=Horizontal, 1=Vertical)
         // Bit 3: Four Screen VRAM
         let mirroring = if four_screen {
             Mirroring::FourScreen
         } else if mirrored {
-            Mirroring::Horizontal
-        } else {
             Mirroring::Vertical
+        } else {
+            Mirroring::Horizontal
         };
 
         // Calculate the offset where PRG ROM actually begins.
@@ -104,56 +192,147 @@ impl Rom {
         // Calculate the size of CHR ROM (8KB units)
         let chr_rom_len = header.chr_rom_size as usize * 8192;
 
+        if rom_data.len() < chr_rom_start {
+            let available = rom_data.len() - HEADER_SIZE;
+            return Err(RomError::TruncatedPrg { expected: chr_rom_start - HEADER_SIZE, available });
+        }
+        if rom_data.len() < chr_rom_start + chr_rom_len {
+            let available = rom_data.len() - chr_rom_start;
+            return Err(RomError::TruncatedChr { expected: chr_rom_len, available });
+        }
+
         return Ok(Rom {
             header,
             prg_rom: rom_data[prg_rom_start..(prg_rom_start + prg_rom_len)].to_vec(),
             chr_rom: rom_data[chr_rom_start..(chr_rom_start + chr_rom_len)].to_vec(),
             mirroring,
             mapper,
+            prg_ram_size: header.prg_ram_bytes(),
+            region: Region::from_header(&header),
         });
     }
 
-    // Returns the MapperType based on the mapper ID byte.
-    pub fn get_mapper_type(&self) -> MapperType {
-        match self.mapper {
-            0 => MapperType::Nrom,
-            1 => MapperType::Mmc1,
-            2 => MapperType::Uxrom,
-            3 => MapperType::Cnrom,
-            4 => MapperType::Mmc3,
-            _ => MapperType::Unknown,
+    pub fn parse_nes_rom_with_overrides(rom_data: Vec<u8>, overrides: &RomOverrides) -> Result<Rom, RomError> {
+        let mut rom = Rom::parse_nes_rom(rom_data)?;
+        rom.apply_overrides(overrides);
+        Ok(rom)
+    }
+
+    // Replaces the header values with the ones of `overrides`. Applied after the header
+    // corrections of the ROM database, since the user knows better.
+    pub fn apply_overrides(&mut self, overrides: &RomOverrides) {
+        if let Some(mapper) = overrides.mapper {
+            self.mapper = mapper;
+            // Bits 8-11 of NES 2.0 mapper numbers. Byte 8 is the PRG RAM size of iNES headers.
+            if self.is_nes2() {
+                self.header.prg_ram_size &= 0xF0;
+            }
+        }
+        if let Some(mirroring) = overrides.mirroring {
+            self.mirroring = mirroring;
+        }
+        if let Some(prg_ram_size) = overrides.prg_ram_size {
+            self.prg_ram_size = prg_ram_size;
+        }
+        if let Some(region) = overrides.region {
+            self.region = region;
+        }
+    }
+
+    // Loads a ROM file. Compressed ROMs (.zip and .gz) are extracted in memory first.
+    pub fn load_file(path: &str) -> Result<Rom, String> {
+        let data = std::fs::read(path).map_err(|error| format!("Failed to read ROM file {}: {}", path, error))?;
+        Rom::load_bytes(data)
+    }
+
+    // Same as `load_file`, for the content of a file read by the frontend (e.g. a browser upload).
+    pub fn load_bytes(data: Vec<u8>) -> Result<Rom, String> {
+        let mut rom = Rom::parse_nes_rom(extract_rom(data)?)?;
+        rom.apply_header_corrections();
+        Ok(rom)
+    }
+
+    // If true, the game has a battery backed save RAM at 0x6000
+    pub fn has_battery(&self) -> bool {
+        (self.header.flags_6 & 0b0000_0010) != 0
+    }
+
+    // If true, 512 bytes of code (for copier devices) were stored between the header and PRG ROM
+    pub fn has_trainer(&self) -> bool {
+        (self.header.flags_6 & 0b0000_0100) != 0
+    }
+
+    // CRC-32 of the PRG ROM followed by the CHR ROM, used to identify the game.
+    pub fn crc32(&self) -> u32 {
+        crc32(&[self.prg_rom.as_slice(), self.chr_rom.as_slice()].concat())
+    }
+
+    // SHA-1 of the PRG ROM followed by the CHR ROM.
+    pub fn sha1(&self) -> [u8; 20] {
+        sha1(&[self.prg_rom.as_slice(), self.chr_rom.as_slice()].concat())
+    }
+
+    // Fixes the header values that are known to be wrong in many dumps.
+    // Returns the database entry of the game when it is known.
+    pub fn apply_header_corrections(&mut self) -> Option<&'static RomDatabaseEntry> {
+        // Old tools wrote their name (e.g. "DiskDude!") over bytes 7 to 15 of the header.
+        // Bytes 12 to 15 are always zero in a clean iNES header; when they are not,
+        // the upper mapper nibble read from flags_7 is garbage.
+        if !self.is_nes2() && self.header.reserved[1..].iter().any(|byte| *byte != 0) {
+            self.mapper &= 0x0F;
+        }
+
+        let entry = rom_database::lookup(self)?;
+        self.apply_database_entry(entry);
+        Some(entry)
+    }
+
+    // Replaces the header values with the ones of the database, see `RomDatabase::lookup`.
+    pub fn apply_database_entry(&mut self, entry: &RomDatabaseEntry) {
+        self.mapper = entry.mapper;
+        if let Some(mirroring) = entry.mirroring {
+            self.mirroring = mirroring;
+        }
+    }
+
+    pub fn is_nes2(&self) -> bool {
+        self.header.is_nes2()
+    }
+
+    // Mapper number, with the bits 8-11 stored in the low nibble of byte 8 by NES 2.0 headers.
+    pub fn mapper_number(&self) -> u16 {
+        if self.is_nes2() {
+            (((self.header.prg_ram_size & 0x0F) as u16) << 8) | self.mapper as u16
+        } else {
+            self.mapper as u16
         }
     }
 
+    // Variant of the mapper board, in the high nibble of byte 8 of NES 2.0 headers.
+    pub fn submapper(&self) -> Option<u8> {
+        self.is_nes2().then_some(self.header.prg_ram_size >> 4)
+    }
+
     // Performs a sanity check on the ROM to ensure it is playable by this emulator.
     // This function should be called immediately after loading a ROM.
     pub fn check_validity(&self) -> Result<(), String> {
+        self.check_validity_with(&MapperRegistry::builtin())
+    }
+
+    // Same as `check_validity`, with the mappers of `mappers` instead of the built-in ones.
+    pub fn check_validity_with(&self, mappers: &MapperRegistry) -> Result<(), String> {
         // Check Magic Number
         if self.header.magic_numbers != *MAGIC_NUMBERS {
              return Err("Invalid ROM: Wrong magic numbers".to_string());
         }
 
-        // Check Mapper Support
-        match self.get_mapper_type() {
-            MapperType::Nrom => {
-                // NROM specific checks:
-                // PRG ROM must be either 16KB (1 unit) or 32KB (2 units)
-                if self.header.prg_rom_size != 1 && self.header.prg_rom_size != 2 {
-                     return Err(format!("Invalid NROM PRG size: {} units (must be 1 or 2)", self.header.prg_rom_size));
-                }
-            }
-            MapperType::Unknown => {
-                return Err(format!("Unsupported Mapper: ID {}", self.mapper));
-            }
-            _ => {
-                return Err(format!("Mapper {} ({:?}) is not yet implemented", self.mapper, self.get_mapper_type()));
-            }
-        }
+        // Check Mapper Support, along with the mapper specific checks
+        mappers.create(self)?;
         Ok(())
     }
 
     #[allow(dead_code)]
-    pub(crate) fn test_rom() -> Rom {
+    pub fn test_rom() -> Rom {
         let header = NesHeader {
             magic_numbers: [0x4E, 0x45, 0x53, 0x1A], // "NES" + EOF
             prg_rom_size: 1,
@@ -178,6 +357,149 @@ impl Rom {
             mapper: 0, // Mapper 0 (NROM)
             prg_rom: prg_data,
             chr_rom: chr_data,
+            prg_ram_size: 0x2000,
+            region: Region::Ntsc,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::rom::{Mirroring, Rom, RomError, RomOverrides};
+    use crate::rom_info::Region;
+
+    fn test_rom_data(flags_6: u8, header_tail: &[u8; 9]) -> Vec<u8> {
+        let mut data = vec![0x4E, 0x45, 0x53, 0x1A, 1, 1, flags_6];
+        data.extend_from_slice(header_tail);
+        data.extend(std::iter::repeat_n(0xEA, 16384));
+        data.extend(std::iter::repeat_n(0x00, 8192));
+        data
+    }
+
+    #[test]
+    fn test_parse_mirroring() {
+        let rom = Rom::parse_nes_rom(test_rom_data(0x00, &[0; 9])).unwrap();
+        assert_eq!(rom.mirroring, Mirroring::Horizontal);
+        let rom = Rom::parse_nes_rom(test_rom_data(0x01, &[0; 9])).unwrap();
+        assert_eq!(rom.mirroring, Mirroring::Vertical);
+        let rom = Rom::parse_nes_rom(test_rom_data(0x09, &[0; 9])).unwrap();
+        assert_eq!(rom.mirroring, Mirroring::FourScreen);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(Rom::parse_nes_rom(b"NES".to_vec()).unwrap_err(), RomError::HeaderTooShort { len: 3 });
+        assert_eq!(Rom::parse_nes_rom(vec![0; 16]).unwrap_err(), RomError::NotInesFormat);
+
+        let data = test_rom_data(0x00, &[0; 9]);
+        assert_eq!(
+            Rom::parse_nes_rom(data[..0x1000].to_vec()).unwrap_err(),
+            RomError::TruncatedPrg { expected: 0x4000, available: 0x1000 - 16 }
+        );
+        assert_eq!(
+            Rom::parse_nes_rom(data[..data.len() - 1].to_vec()).unwrap_err(),
+            RomError::TruncatedChr { expected: 0x2000, available: 0x1FFF }
+        );
+        // A trainer is part of the expected PRG data
+        let error = Rom::parse_nes_rom(test_rom_data(0x04, &[0; 9])).unwrap_err();
+        assert_eq!(error, RomError::TruncatedChr { expected: 0x2000, available: 0x1E00 });
+        assert!(error.to_string().contains("CHR ROM is truncated"));
+    }
+
+    #[test]
+    fn test_trailing_bytes_are_ignored() {
+        let mut data = test_rom_data(0x00, &[0; 9]);
+        data.extend_from_slice(b"Dumped by someone");
+        let rom = Rom::parse_nes_rom(data).unwrap();
+        assert_eq!(rom.prg_rom.len(), 0x4000);
+        assert_eq!(rom.chr_rom.len(), 0x2000);
+    }
+
+    #[test]
+    fn test_overrides() {
+        let data = test_rom_data(0x10, &[0; 9]);
+        let rom = Rom::parse_nes_rom(data.clone()).unwrap();
+        assert_eq!((rom.mapper, rom.mirroring, rom.prg_ram_size, rom.region), (1, Mirroring::Horizontal, 0x2000, Region::Ntsc));
+
+        let overrides = RomOverrides {
+            mapper: Some(0),
+            mirroring: Some(Mirroring::Vertical),
+            prg_ram_size: Some(0),
+            region: Some(Region::Pal),
+        };
+        let rom = Rom::parse_nes_rom_with_overrides(data.clone(), &overrides).unwrap();
+        assert_eq!((rom.mapper, rom.mirroring, rom.prg_ram_size, rom.region), (0, Mirroring::Vertical, 0, Region::Pal));
+        assert!(rom.check_validity().is_ok());
+
+        // Values without override are kept
+        let overrides = RomOverrides { mirroring: Some(Mirroring::FourScreen), ..RomOverrides::default() };
+        let rom = Rom::parse_nes_rom_with_overrides(data, &overrides).unwrap();
+        assert_eq!((rom.mapper, rom.mirroring), (1, Mirroring::FourScreen));
+
+        // A mapper override keeps the PRG RAM size of iNES headers, and clears the upper mapper
+        // bits of NES 2.0 ones
+        let overrides = RomOverrides { mapper: Some(2), ..RomOverrides::default() };
+        let rom = Rom::parse_nes_rom_with_overrides(test_rom_data(0x10, &[0, 0x32, 0, 0, 0, 0, 0, 0, 0]), &overrides).unwrap();
+        assert_eq!(rom.header.prg_ram_size, 0x32);
+        let rom = Rom::parse_nes_rom_with_overrides(test_rom_data(0x10, &[0x08, 0x32, 0, 0, 0, 0, 0, 0, 0]), &overrides).unwrap();
+        assert_eq!((rom.mapper_number(), rom.submapper()), (2, Some(3)));
+    }
+
+    #[test]
+    fn test_prg_ram_size() {
+        let mut rom = Rom::test_rom();
+        rom.header.prg_ram_size = 4;
+        assert_eq!(rom.header.prg_ram_bytes(), 0x8000);
+        // NES 2.0: 8KB of PRG RAM (64 << 7) and 2KB of battery backed RAM (64 << 5)
+        rom.header.flags_7 = 0b0000_1000;
+        rom.header.flags_10 = 0x57;
+        assert_eq!(rom.header.prg_ram_bytes(), 0x2800);
+    }
+
+    #[test]
+    fn test_hashes() {
+        let rom = Rom::test_rom();
+        assert_eq!(rom.crc32(), 0x165B_773A);
+        assert_eq!(crate::hash::to_hex(&rom.sha1()), "f6aad99f69d01b84624112c146d2c2be966747e5");
+    }
+
+    #[test]
+    fn test_header_corrections_ignore_garbage_mapper() {
+        // flags_6 says mapper 1 (lower nibble), "DiskDude!" garbage in bytes 7 to 15
+        let mut rom = Rom::parse_nes_rom(test_rom_data(0x10, b"DiskDude!")).unwrap();
+        assert_eq!(rom.mapper, 0x41);
+        rom.apply_header_corrections();
+        assert_eq!(rom.mapper, 0x01);
+
+        // A clean header is left untouched
+        let mut rom = Rom::parse_nes_rom(test_rom_data(0x10, &[0x20, 0, 0, 0, 0, 0, 0, 0, 0])).unwrap();
+        rom.apply_header_corrections();
+        assert_eq!(rom.mapper, 0x21);
+    }
+
+    #[test]
+    fn test_nes2_mapper_number() {
+        let mut rom = Rom::test_rom();
+        rom.mapper = 0x42;
+        rom.header.prg_ram_size = 0x31;
+        assert_eq!(rom.mapper_number(), 0x42);
+        assert_eq!(rom.submapper(), None);
+
+        rom.header.flags_7 = 0b0000_1000;
+        assert_eq!(rom.mapper_number(), 0x142);
+        assert_eq!(rom.submapper(), Some(3));
+    }
+
+    #[test]
+    fn test_check_validity_bank_switching_mappers() {
+        let mut rom = Rom::test_rom();
+        for mapper in [11, 34, 66, 71, 206] {
+            rom.mapper = mapper;
+            assert!(rom.check_validity().is_ok(), "mapper {}", mapper);
         }
+        rom.header.prg_rom_size = 0;
+        assert!(rom.check_validity().is_err());
+        rom.mapper = 4;
+        assert!(rom.check_validity().is_err());
     }
 }
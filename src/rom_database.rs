@@ -0,0 +1,209 @@
+use std::borrow::Cow;
+use crate::hash::to_hex;
+use crate::rom::{Mirroring, Rom};
+
+// Known ROM, identified by the hashes of its PRG ROM followed by its CHR ROM.
+// The iNES header is not hashed, so a dump with a wrong header is still recognized.
+// Embedded entries borrow their strings, entries loaded from a file own them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RomDatabaseEntry {
+    pub crc32: u32,
+    pub sha1: Cow<'static, str>,
+    pub title: Cow<'static, str>,
+    pub mapper: u8,
+    // None when the board controls the mirroring, the header value is then kept
+    pub mirroring: Option<Mirroring>,
+}
+
+// The embedded database only knows the ROMs shipped with the repository, so it is only built with
+// the "rom-database" feature. Games are identified with a database file, see `RomDatabase`.
+#[cfg(feature = "rom-database")]
+const ENTRIES: &[RomDatabaseEntry] = &[
+    RomDatabaseEntry {
+        crc32: 0x158B_0388,
+        sha1: Cow::Borrowed("4131307f0f69f2a5c54b7d438328c5b2a5ed0820"),
+        title: Cow::Borrowed("nestest"),
+        mapper: 0,
+        mirroring: Some(Mirroring::Horizontal),
+    },
+    RomDatabaseEntry {
+        crc32: 0x862A_5C36,
+        sha1: Cow::Borrowed("2942508ac0dbf9eadc3b1486fa276c3c368fd631"),
+        title: Cow::Borrowed("Snake"),
+        mapper: 0,
+        mirroring: Some(Mirroring::Vertical),
+    },
+];
+
+#[cfg(not(feature = "rom-database"))]
+const ENTRIES: &[RomDatabaseEntry] = &[];
+
+// Finds the ROM in the embedded database.
+pub fn lookup(rom: &Rom) -> Option<&'static RomDatabaseEntry> {
+    find_entry(ENTRIES, rom)
+}
+
+// The CRC-32 is used as the key, and the SHA-1 confirms the match since CRC-32 collisions are easy to get.
+fn find_entry<'a>(entries: &'a [RomDatabaseEntry], rom: &Rom) -> Option<&'a RomDatabaseEntry> {
+    let crc32 = rom.crc32();
+    let mut candidates = entries.iter().filter(|entry| entry.crc32 == crc32).peekable();
+    candidates.peek()?;
+
+    let sha1 = to_hex(&rom.sha1());
+    candidates.find(|entry| entry.sha1 == sha1)
+}
+
+// Database loaded at runtime from the NES 2.0 header database of the NesDev community
+// (nes20db.xml), which covers the licensed and unlicensed releases. Each game looks like:
+//   <game>
+//     <!-- Path/Title (Region).nes -->
+//     <rom size="..." crc32="..." sha1="..."/>
+//     <pcb mapper="..." submapper="..." mirroring="V" battery="0"/>
+//     ...
+//   </game>
+// The <rom> hashes cover the PRG ROM followed by the CHR ROM, like `Rom::crc32` and `Rom::sha1`.
+#[derive(Debug, Clone, Default)]
+pub struct RomDatabase {
+    entries: Vec<RomDatabaseEntry>,
+}
+
+impl RomDatabase {
+    pub fn load_file(path: &str) -> Result<Self, String> {
+        let xml = std::fs::read_to_string(path).map_err(|error| format!("Failed to read ROM database {}: {}", path, error))?;
+        Self::parse_nes20db(&xml)
+    }
+
+    // Games with a mapper number above 255 (NES 2.0 only) are skipped: header corrections only
+    // rewrite iNES mapper numbers.
+    pub fn parse_nes20db(xml: &str) -> Result<Self, String> {
+        let mut entries = Vec::new();
+        for (index, game) in xml.split("<game>").skip(1).enumerate() {
+            let game = game.split("</game>").next().unwrap_or(game);
+            let error = |message: &str| format!("Invalid ROM database: game {}: {}", index + 1, message);
+
+            let title = game.split("<!--").nth(1).and_then(|comment| comment.split("-->").next())
+                .map(|path| path.trim().rsplit(['/', '\\']).next().unwrap_or(path).trim_end_matches(".nes").to_string())
+                .ok_or_else(|| error("missing title comment"))?;
+            let rom = tag(game, "rom").ok_or_else(|| error("missing <rom>"))?;
+            let pcb = tag(game, "pcb").ok_or_else(|| error("missing <pcb>"))?;
+
+            let crc32 = attribute(rom, "crc32").and_then(|crc32| u32::from_str_radix(crc32, 16).ok())
+                .ok_or_else(|| error("invalid rom crc32"))?;
+            let sha1 = attribute(rom, "sha1").filter(|sha1| sha1.len() == 40)
+                .ok_or_else(|| error("invalid rom sha1"))?.to_ascii_lowercase();
+            let mapper: u16 = attribute(pcb, "mapper").and_then(|mapper| mapper.parse().ok())
+                .ok_or_else(|| error("invalid pcb mapper"))?;
+            let mirroring = match attribute(pcb, "mirroring") {
+                Some("H") => Some(Mirroring::Horizontal),
+                Some("V") => Some(Mirroring::Vertical),
+                Some("4") => Some(Mirroring::FourScreen),
+                _ => None,
+            };
+            if let Ok(mapper) = u8::try_from(mapper) {
+                entries.push(RomDatabaseEntry { crc32, sha1: Cow::Owned(sha1), title: Cow::Owned(title), mapper, mirroring });
+            }
+        }
+        Ok(Self { entries })
+    }
+
+    pub fn lookup(&self, rom: &Rom) -> Option<&RomDatabaseEntry> {
+        find_entry(&self.entries, rom)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+// Attributes of the first `<name .../>` element of `xml`.
+fn tag<'a>(xml: &'a str, name: &str) -> Option<&'a str> {
+    let start = xml.find(&format!("<{} ", name))?;
+    let end = xml[start..].find('>')?;
+    Some(&xml[start..start + end])
+}
+
+fn attribute<'a>(tag: &'a str, name: &str) -> Option<&'a str> {
+    let start = tag.find(&format!(" {}=\"", name))? + name.len() + 3;
+    let end = tag[start..].find('"')?;
+    Some(&tag[start..start + end])
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+    use crate::rom::{Mirroring, Rom};
+    use crate::rom_database::{find_entry, RomDatabase, RomDatabaseEntry};
+
+    static TEST_ENTRIES: [RomDatabaseEntry; 2] = [
+        // Same CRC-32 as the test ROM, but a different SHA-1
+        RomDatabaseEntry {
+            crc32: 0x165B_773A,
+            sha1: Cow::Borrowed("0000000000000000000000000000000000000000"),
+            title: Cow::Borrowed("Collision"),
+            mapper: 1,
+            mirroring: Some(Mirroring::Vertical),
+        },
+        RomDatabaseEntry {
+            crc32: 0x165B_773A,
+            sha1: Cow::Borrowed("f6aad99f69d01b84624112c146d2c2be966747e5"),
+            title: Cow::Borrowed("Test ROM"),
+            mapper: 0,
+            mirroring: Some(Mirroring::Vertical),
+        },
+    ];
+
+    // Two games in the nes20db.xml format: the test ROM, and a game with a NES 2.0 only mapper
+    const NES20DB: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<nes20db date="2024-01-01">
+<game>
+	<!-- Homebrew/Test ROM (World).nes -->
+	<rom size="24576" crc32="165B773A" sha1="F6AAD99F69D01B84624112C146D2C2BE966747E5"/>
+	<prgrom size="16384" crc32="00000000" sha1="0000000000000000000000000000000000000000" sum16="0000"/>
+	<pcb mapper="66" submapper="0" mirroring="H" battery="0"/>
+	<console type="0" region="0"/>
+</game>
+<game>
+	<!-- Unlicensed/Big Mapper (China).nes -->
+	<rom size="24576" crc32="12345678" sha1="0123456789ABCDEF0123456789ABCDEF01234567"/>
+	<pcb mapper="300" submapper="0" mirroring="V" battery="0"/>
+</game>
+</nes20db>"#;
+
+    #[test]
+    fn test_parse_nes20db() {
+        let database = RomDatabase::parse_nes20db(NES20DB).unwrap();
+        assert_eq!(database.len(), 1);
+
+        let mut rom = Rom::test_rom();
+        let entry = database.lookup(&rom).unwrap().clone();
+        assert_eq!(entry.title, "Test ROM (World)");
+        assert_eq!((entry.mapper, entry.mirroring), (66, Some(Mirroring::Horizontal)));
+
+        rom.apply_database_entry(&entry);
+        assert_eq!((rom.mapper, rom.mirroring), (66, Mirroring::Horizontal));
+
+        assert!(RomDatabase::parse_nes20db("<game><!-- Broken.nes --><rom crc32=\"XYZ\"/></game>").is_err());
+    }
+
+    #[test]
+    fn test_find_entry_checks_sha1() {
+        let rom = Rom::test_rom();
+        assert_eq!(find_entry(&TEST_ENTRIES, &rom).map(|entry| entry.title.as_ref()), Some("Test ROM"));
+
+        let mut other = Rom::test_rom();
+        other.prg_rom[0] = 0x00;
+        assert!(find_entry(&TEST_ENTRIES, &other).is_none());
+    }
+
+    #[cfg(feature = "rom-database")]
+    #[test]
+    fn test_lookup_nestest() {
+        let data = std::fs::read(concat!(env!("CARGO_MANIFEST_DIR"), "/nestest.nes")).expect("Failed to read nestest.nes");
+        let rom = Rom::parse_nes_rom(data).expect("Failed to parse nestest.nes");
+        assert_eq!(crate::rom_database::lookup(&rom).map(|entry| entry.title.as_ref()), Some("nestest"));
+    }
+}
@@ -0,0 +1,219 @@
+use std::fmt;
+use crate::hash::to_hex;
+use crate::mapper::registry::MapperRegistry;
+use crate::rom::{Mirroring, NesHeader, Rom};
+use crate::rom_database;
+
+// TV system a game was made for. Only NTSC timings are emulated for now.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Region {
+    Ntsc,
+    Pal,
+    // Works on both, usually by detecting the console at boot
+    Multiple,
+    // Famiclones of the former USSR, with PAL video and NTSC-like timings
+    Dendy,
+}
+
+impl Region {
+    // NES 2.0 stores the region in byte 12. In iNES headers, the region bits of flags_9 and
+    // flags_10 are rarely filled in by the dumpers, so NTSC is only a guess.
+    pub fn from_header(header: &NesHeader) -> Region {
+        if header.is_nes2() {
+            return match header.reserved[1] & 0b11 {
+                0 => Region::Ntsc,
+                1 => Region::Pal,
+                2 => Region::Multiple,
+                _ => Region::Dendy,
+            };
+        }
+        match (header.flags_9 & 1, header.flags_10 & 0b11) {
+            (_, 1 | 3) => Region::Multiple,
+            (1, _) | (_, 2) => Region::Pal,
+            _ => Region::Ntsc,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Region::Ntsc => "NTSC",
+            Region::Pal => "PAL",
+            Region::Multiple => "NTSC and PAL",
+            Region::Dendy => "Dendy",
+        }
+    }
+}
+
+// Everything known about a ROM, for the `info` command.
+#[derive(Debug, Clone)]
+pub struct RomInfo {
+    pub prg_rom_size: usize,
+    pub chr_rom_size: usize,
+    pub prg_ram_size: usize,
+    pub mapper: u16,
+    pub submapper: Option<u8>,
+    // Name of the mapper when it is supported
+    pub mapper_name: Option<&'static str>,
+    pub mirroring: Mirroring,
+    pub battery: bool,
+    pub trainer: bool,
+    pub nes2: bool,
+    pub vs_unisystem: bool,
+    pub playchoice10: bool,
+    pub region: Region,
+    pub crc32: u32,
+    pub sha1: String,
+    pub database_title: Option<String>,
+    // Result of `Rom::check_validity`
+    pub validity: Result<(), String>,
+}
+
+impl Rom {
+    pub fn analyze(&self) -> RomInfo {
+        self.analyze_with(&MapperRegistry::builtin())
+    }
+
+    // Same as `analyze`, with the mappers of `mappers` instead of the built-in ones.
+    pub fn analyze_with(&self, mappers: &MapperRegistry) -> RomInfo {
+        RomInfo {
+            prg_rom_size: self.prg_rom.len(),
+            chr_rom_size: self.chr_rom.len(),
+            prg_ram_size: self.prg_ram_size,
+            mapper: self.mapper_number(),
+            submapper: self.submapper(),
+            mapper_name: mappers.lookup(self.mapper_number(), self.submapper()).map(|entry| entry.name),
+            mirroring: self.mirroring,
+            battery: self.has_battery(),
+            trainer: self.has_trainer(),
+            nes2: self.is_nes2(),
+            vs_unisystem: self.header.is_vs_unisystem(),
+            playchoice10: self.header.is_playchoice10(),
+            region: self.region,
+            crc32: self.crc32(),
+            sha1: to_hex(&self.sha1()),
+            database_title: rom_database::lookup(self).map(|entry| entry.title.to_string()),
+            validity: self.check_validity_with(mappers),
+        }
+    }
+}
+
+impl RomInfo {
+    // Refines the region with the tags of the GoodNES and No-Intro file names, e.g. "(E)" or "(Europe)",
+    // which are more reliable than iNES headers.
+    pub fn guess_region_from_file_name(&mut self, file_name: &str) {
+        if self.nes2 {
+            return;
+        }
+        const PAL_TAGS: [&str; 6] = ["E", "Europe", "PAL", "Australia", "Germany", "France"];
+        const NTSC_TAGS: [&str; 6] = ["U", "USA", "J", "Japan", "JU", "NTSC"];
+
+        // Tags are in parentheses, No-Intro lists the regions in one tag: "(USA, Europe)"
+        let tags: Vec<&str> = file_name
+            .split('(')
+            .skip(1)
+            .filter_map(|group| group.split(')').next())
+            .flat_map(|group| group.split(','))
+            .map(str::trim)
+            .collect();
+        let has_tag = |known: &[&str]| tags.iter().any(|tag| known.contains(tag));
+        self.region = match (has_tag(&PAL_TAGS), has_tag(&NTSC_TAGS)) {
+            (true, true) => Region::Multiple,
+            (true, false) => Region::Pal,
+            (false, true) => Region::Ntsc,
+            (false, false) => self.region,
+        };
+    }
+}
+
+impl fmt::Display for RomInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let yes_no = |value: bool| if value { "yes" } else { "no" };
+
+        writeln!(f, "PRG ROM:   {} KB", self.prg_rom_size / 1024)?;
+        if self.chr_rom_size == 0 {
+            writeln!(f, "CHR ROM:   none (8 KB of CHR RAM)")?;
+        } else {
+            writeln!(f, "CHR ROM:   {} KB", self.chr_rom_size / 1024)?;
+        }
+        writeln!(f, "PRG RAM:   {} KB", self.prg_ram_size / 1024)?;
+        write!(f, "Mapper:    {}", self.mapper)?;
+        if let Some(submapper) = self.submapper {
+            write!(f, ".{}", submapper)?;
+        }
+        writeln!(f, " ({})", self.mapper_name.unwrap_or("unsupported"))?;
+        writeln!(f, "Mirroring: {:?}", self.mirroring)?;
+        writeln!(f, "Battery:   {}", yes_no(self.battery))?;
+        writeln!(f, "Trainer:   {}", yes_no(self.trainer))?;
+        writeln!(f, "Header:    {}", if self.nes2 { "NES 2.0" } else { "iNES" })?;
+        let console = match (self.vs_unisystem, self.playchoice10) {
+            (true, _) => "VS System",
+            (_, true) => "PlayChoice-10",
+            _ => "NES / Famicom",
+        };
+        writeln!(f, "Console:   {}", console)?;
+        writeln!(f, "Region:    {}", self.region.name())?;
+        writeln!(f, "CRC-32:    {:08X}", self.crc32)?;
+        writeln!(f, "SHA-1:     {}", self.sha1)?;
+        writeln!(f, "Database:  {}", self.database_title.as_deref().unwrap_or("not found"))?;
+        match &self.validity {
+            Ok(()) => write!(f, "Status:    playable"),
+            Err(error) => write!(f, "Status:    {}", error),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::rom::Rom;
+    use crate::rom_info::Region;
+
+    #[test]
+    fn test_analyze() {
+        let mut rom = Rom::test_rom();
+        rom.header.flags_6 = 0b0000_0010;
+        let info = rom.analyze();
+        assert_eq!(info.prg_rom_size, 0x4000);
+        assert_eq!(info.mapper_name, Some("NROM"));
+        assert!(info.battery && !info.trainer);
+        assert_eq!(info.crc32, 0x165B_773A);
+        assert!(info.validity.is_ok());
+
+        let text = info.to_string();
+        assert!(text.contains("Mapper:    0 (NROM)"));
+        assert!(text.contains("Status:    playable"));
+
+        rom.mapper = 4;
+        let info = rom.analyze();
+        assert!(info.validity.is_err());
+        assert!(info.to_string().contains("4 (unsupported)"));
+    }
+
+    #[test]
+    fn test_region() {
+        let mut rom = Rom::test_rom();
+        assert_eq!(Region::from_header(&rom.header), Region::Ntsc);
+        rom.header.flags_9 = 1;
+        assert_eq!(Region::from_header(&rom.header), Region::Pal);
+        rom.header.flags_10 = 3;
+        assert_eq!(Region::from_header(&rom.header), Region::Multiple);
+
+        let mut info = Rom::test_rom().analyze();
+        info.guess_region_from_file_name("Game (Europe).nes");
+        assert_eq!(info.region, Region::Pal);
+        info.guess_region_from_file_name("Game (U) [!].nes");
+        assert_eq!(info.region, Region::Ntsc);
+        info.guess_region_from_file_name("Game (USA, Europe).nes");
+        assert_eq!(info.region, Region::Multiple);
+        // Not a region
+        info.guess_region_from_file_name("Game (Beta).nes");
+        assert_eq!(info.region, Region::Multiple);
+
+        // NES 2.0 headers are trusted
+        rom.header.flags_7 = 0b0000_1000;
+        rom.header.reserved[1] = 3;
+        rom.region = Region::from_header(&rom.header);
+        let mut info = rom.analyze();
+        info.guess_region_from_file_name("Game (U).nes");
+        assert_eq!(info.region, Region::Dendy);
+    }
+}
@@ -0,0 +1,385 @@
+use crate::apu_status::ApuStatus;
+use crate::cpu6502::CPU;
+use crate::family_keyboard::FamilyBasicKeyboard;
+use crate::interrupts::InterruptLines;
+use crate::ppu::Ppu;
+use crate::scheduler::Scheduler;
+
+// Savestates are stored in a small hand-rolled binary format:
+// 0x00 - 0x03: Magic numbers "NSST"
+// 0x04:        Format version
+// 0x05 - ...:  Sections, in a fixed order (CPU registers, interrupt lines, internal RAM, PRG RAM,
+//              mapper registers, controller ports, APU status, PPU, VS System cabinet, Family
+//              BASIC keyboard)
+// All multi-byte values are stored in little-endian format, like the 6502 does.
+// New sections are appended and the version bumped, so older states can still be rejected cleanly.
+const STATE_MAGIC_NUMBERS: &[u8; 4] = b"NSST";
+const STATE_VERSION: u8 = 10;
+
+// Helper used to write values into a savestate buffer.
+#[derive(Default)]
+pub struct StateWriter {
+    data: Vec<u8>,
+}
+
+impl StateWriter {
+    pub fn new() -> Self {
+        Self { data: Vec::new() }
+    }
+
+    pub fn write_u8(&mut self, value: u8) {
+        self.data.push(value);
+    }
+
+    pub fn write_bool(&mut self, value: bool) {
+        self.data.push(value as u8);
+    }
+
+    pub fn write_u16(&mut self, value: u16) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_u64(&mut self, value: u64) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        self.data.extend_from_slice(bytes);
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        self.data
+    }
+}
+
+// Helper used to read values back from a savestate buffer.
+// Every read is bound checked so a truncated state returns an error instead of panicking.
+pub struct StateReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> StateReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data, position: 0 }
+    }
+
+    pub fn read_bytes(&mut self, len: usize) -> Result<&'a [u8], String> {
+        let end = self.position + len;
+        if end > self.data.len() {
+            return Err(format!("Savestate is truncated: expected {} bytes at offset {}", len, self.position));
+        }
+        let bytes = &self.data[self.position..end];
+        self.position = end;
+        Ok(bytes)
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8, String> {
+        Ok(self.read_bytes(1)?[0])
+    }
+
+    pub fn read_bool(&mut self) -> Result<bool, String> {
+        Ok(self.read_u8()? != 0)
+    }
+
+    pub fn read_u16(&mut self) -> Result<u16, String> {
+        let bytes = self.read_bytes(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    pub fn read_u64(&mut self) -> Result<u64, String> {
+        let bytes = self.read_bytes(8)?;
+        let mut buffer = [0u8; 8];
+        buffer.copy_from_slice(bytes);
+        Ok(u64::from_le_bytes(buffer))
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.data.len() - self.position
+    }
+
+    pub fn is_at_end(&self) -> bool {
+        self.position == self.data.len()
+    }
+}
+
+// Cycle counter stored in a state, without loading it (e.g. to pick a snapshot by time).
+pub fn state_cycles(state: &[u8]) -> Result<u64, String> {
+    let mut reader = StateReader::new(state);
+    if reader.read_bytes(4)? != STATE_MAGIC_NUMBERS {
+        return Err("Invalid savestate: Wrong magic numbers".to_string());
+    }
+    // Version, program counter, then SP, A, X, Y and P
+    reader.read_bytes(1 + 2 + 5)?;
+    reader.read_u64()
+}
+
+impl CPU {
+    // Snapshots the whole machine into a byte buffer that can be restored with `load_state`.
+    // The cartridge ROM is not part of the state: it must be the same when loading.
+    pub fn save_state(&self) -> Vec<u8> {
+        // The PPU runs lazily, it is caught up so the state only depends on the cycle it is saved at
+        self.sync_ppu(self.cycles);
+        let mut writer = StateWriter::new();
+        writer.write_bytes(STATE_MAGIC_NUMBERS);
+        writer.write_u8(STATE_VERSION);
+
+        // CPU registers
+        writer.write_u16(self.program_counter);
+        writer.write_u8(self.stack_pointer);
+        writer.write_u8(self.accumulator);
+        writer.write_u8(self.x_register);
+        writer.write_u8(self.y_register);
+        writer.write_u8(self.status_register);
+        writer.write_u64(self.cycles);
+        writer.write_bool(self.halted);
+        writer.write_u8(self.ppu_alignment as u8);
+        writer.write_u64(self.extra_scanlines);
+
+        // NMI edge and IRQ sources not serviced yet
+        self.saved_interrupts().save_state(&mut writer);
+
+        // Internal RAM (0x0000 - 0x07FF)
+        writer.write_bytes(self.bus.internal_ram());
+
+        // PRG RAM (0x6000 - 0x7FFF)
+        writer.write_bytes(self.bus.prg_ram());
+
+        // Mapper bank registers, prefixed by their count
+        let registers = self.bus.mapper().registers();
+        writer.write_u8(registers.len() as u8);
+        writer.write_bytes(&registers);
+
+        // Controller strobe and read positions
+        writer.write_bytes(&self.bus.joypads().shift_state());
+
+        // APU channel, frame IRQ and DMC IRQ flags of 0x4015
+        self.bus.apu_status().save_state(&mut writer);
+
+        // PPU registers, memories and position
+        self.bus.ppu().save_state(&mut writer);
+
+        // Expansion devices, each prefixed by whether the cartridge has it
+        writer.write_bool(self.bus.vs_system().is_some());
+        if let Some(vs_system) = self.bus.vs_system() {
+            vs_system.save_state(&mut writer);
+        }
+        writer.write_bool(self.bus.family_keyboard().is_some());
+        if let Some(keyboard) = self.bus.family_keyboard() {
+            keyboard.save_state(&mut writer);
+        }
+
+        writer.finish()
+    }
+
+    // Restores a state produced by `save_state`.
+    // The state is fully validated before anything is applied, so the machine is left untouched on error.
+    pub fn load_state(&mut self, state: &[u8]) -> Result<(), String> {
+        let mut reader = StateReader::new(state);
+
+        if reader.read_bytes(4)? != STATE_MAGIC_NUMBERS {
+            return Err("Invalid savestate: Wrong magic numbers".to_string());
+        }
+        let version = reader.read_u8()?;
+        if version != STATE_VERSION {
+            return Err(format!("Unsupported savestate version: {} (expected {})", version, STATE_VERSION));
+        }
+
+        let program_counter = reader.read_u16()?;
+        let stack_pointer = reader.read_u8()?;
+        let accumulator = reader.read_u8()?;
+        let x_register = reader.read_u8()?;
+        let y_register = reader.read_u8()?;
+        let status_register = reader.read_u8()?;
+        let cycles = reader.read_u64()?;
+        let halted = reader.read_bool()?;
+        let ppu_alignment = reader.read_u8()?;
+        let extra_scanlines = reader.read_u64()?;
+        let mut interrupts = InterruptLines::default();
+        interrupts.load_state(&mut reader)?;
+        let ram = reader.read_bytes(0x0800)?;
+        let prg_ram = reader.read_bytes(0x2000)?;
+        let register_count = reader.read_u8()? as usize;
+        let mapper_registers = reader.read_bytes(register_count)?;
+        if register_count != self.bus.mapper().registers().len() {
+            return Err("Invalid savestate: Mapper registers do not match the cartridge".to_string());
+        }
+        let joypad_state = reader.read_bytes(3)?;
+        let mut apu_status = ApuStatus::new();
+        apu_status.load_state(&mut reader)?;
+        let mut ppu = Ppu::new(self.bus.rom().chr_rom.is_empty());
+        ppu.load_state(&mut reader)?;
+        if reader.read_bool()? != self.bus.vs_system().is_some() {
+            return Err("Invalid savestate: VS System cabinet does not match the cartridge".to_string());
+        }
+        let mut vs_system = self.bus.vs_system().cloned();
+        if let Some(vs_system) = &mut vs_system {
+            vs_system.load_state(&mut reader)?;
+        }
+        if reader.read_bool()? != self.bus.family_keyboard().is_some() {
+            return Err("Invalid savestate: Family BASIC keyboard does not match the cartridge".to_string());
+        }
+        let mut keyboard = self.bus.family_keyboard().map(|_| FamilyBasicKeyboard::new());
+        if let Some(keyboard) = &mut keyboard {
+            keyboard.load_state(&mut reader)?;
+        }
+
+        if !reader.is_at_end() {
+            return Err("Invalid savestate: Unexpected trailing data".to_string());
+        }
+
+        self.program_counter = program_counter;
+        self.stack_pointer = stack_pointer;
+        self.accumulator = accumulator;
+        self.x_register = x_register;
+        self.y_register = y_register;
+        self.status_register = status_register;
+        self.cycles = cycles;
+        self.interrupts = interrupts;
+        // The PPU events are scheduled again from the restored PPU
+        self.scheduler = Scheduler::new();
+        self.halted = halted;
+        self.ppu_alignment = (ppu_alignment & 0b11) as u64;
+        self.extra_scanlines = extra_scanlines;
+        self.bus.internal_ram_mut().copy_from_slice(ram);
+        if self.bus.prg_ram()[..] != *prg_ram {
+            self.bus.prg_ram_mut().copy_from_slice(prg_ram);
+            // The battery save has to be written with the restored content
+            self.bus.mark_prg_ram_dirty();
+        }
+        self.bus.mapper_mut().set_registers(mapper_registers);
+        self.bus.joypads_mut().set_shift_state([joypad_state[0], joypad_state[1], joypad_state[2]]);
+        *self.bus.apu_status_mut() = apu_status;
+        self.bus.ppu_mut().restore_state(ppu);
+        if let (Some(current), Some(vs_system)) = (self.bus.vs_system_mut(), vs_system) {
+            *current = vs_system;
+        }
+        if let (Some(current), Some(keyboard)) = (self.bus.family_keyboard_mut(), keyboard) {
+            *current = keyboard;
+        }
+        // The call stack is not saved, its frames belong to the previous execution
+        self.call_stack.clear();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bus::Bus;
+    use crate::cpu6502::new_cpu;
+    use crate::interrupts::IrqSource;
+    use crate::rom::Rom;
+
+    #[test]
+    fn test_save_and_load_state_round_trip() {
+        let mut cpu = new_cpu(Bus::new(Rom::test_rom()));
+        cpu.program_counter = 0xC123;
+        cpu.stack_pointer = 0xF0;
+        cpu.accumulator = 0x11;
+        cpu.x_register = 0x22;
+        cpu.y_register = 0x33;
+        cpu.status_register = 0xA5;
+        cpu.cycles = 123456;
+        cpu.ppu_alignment = 3;
+        cpu.write_u8(0x0000, 0x42);
+        cpu.write_u8(0x07FF, 0x99);
+        cpu.write_u8(0x7FFF, 0x77);
+        // PPUADDR 0x2345, then a nametable byte through PPUDATA
+        cpu.write_u8(0x2006, 0x23);
+        cpu.write_u8(0x2006, 0x45);
+        cpu.write_u8(0x2007, 0x66);
+
+        let state = cpu.save_state();
+
+        let mut restored = new_cpu(Bus::new(Rom::test_rom()));
+        restored.load_state(&state).expect("state should load");
+
+        assert_eq!(restored.program_counter, 0xC123);
+        assert_eq!(restored.stack_pointer, 0xF0);
+        assert_eq!(restored.accumulator, 0x11);
+        assert_eq!(restored.x_register, 0x22);
+        assert_eq!(restored.y_register, 0x33);
+        assert_eq!(restored.status_register, 0xA5);
+        assert_eq!(restored.cycles, 123456);
+        assert_eq!(restored.ppu_alignment, 3);
+        assert_eq!(restored.read_u8(0x0000), 0x42);
+        assert_eq!(restored.read_u8(0x07FF), 0x99);
+        assert_eq!(restored.read_u8(0x7FFF), 0x77);
+        assert_eq!(restored.save_state(), state);
+        // The buffered PPUDATA read returns the restored nametable byte
+        restored.write_u8(0x2006, 0x23);
+        restored.write_u8(0x2006, 0x45);
+        restored.read_u8(0x2007);
+        assert_eq!(restored.read_u8(0x2007), 0x66);
+    }
+
+    #[test]
+    fn test_save_and_load_pending_interrupts() {
+        let mut cpu = new_cpu(Bus::new(Rom::test_rom()));
+        // NOP, NOP
+        cpu.write_u8(0x0300, 0xEA);
+        cpu.write_u8(0x0301, 0xEA);
+        cpu.program_counter = 0x0300;
+        // The IRQ line stays asserted, masked by the I flag
+        cpu.status_register = 0x24;
+        // Asserted during the last cycle of the first NOP: pending once it is done
+        cpu.request_nmi_at(cpu.cycles + 1);
+        cpu.set_irq(IrqSource::Mapper, true);
+        cpu.bus.apu_status_mut().set_frame_irq(true);
+        cpu.step();
+        assert_eq!(cpu.program_counter, 0x0301);
+        let state = cpu.save_state();
+
+        let mut restored = new_cpu(Bus::new(Rom::test_rom()));
+        restored.load_state(&state).unwrap();
+        assert_eq!(restored.save_state(), state);
+        assert!(restored.is_irq_source_asserted(IrqSource::Mapper));
+        assert!(restored.bus.apu_status().frame_irq());
+        // The NMI is serviced after the next instruction, like without the savestate
+        cpu.step();
+        restored.step();
+        assert_eq!(restored.program_counter, cpu.read_u16(0xFFFA));
+        assert_eq!(restored.save_state(), cpu.save_state());
+    }
+
+    #[test]
+    fn test_load_state_rejects_invalid_data() {
+        let mut cpu = new_cpu(Bus::new(Rom::test_rom()));
+        let state = cpu.save_state();
+
+        // Wrong magic numbers
+        let mut corrupted = state.clone();
+        corrupted[0] = b'X';
+        assert!(cpu.load_state(&corrupted).is_err());
+
+        // Unknown version
+        let mut corrupted = state.clone();
+        corrupted[4] = 0xFF;
+        assert!(cpu.load_state(&corrupted).is_err());
+
+        // Truncated state leaves the CPU untouched
+        cpu.accumulator = 0x55;
+        assert!(cpu.load_state(&state[..state.len() - 1]).is_err());
+        assert_eq!(cpu.accumulator, 0x55);
+    }
+
+    #[test]
+    fn test_save_and_load_mapper_registers() {
+        let mut rom = Rom::test_rom();
+        rom.mapper = 66;
+        rom.prg_rom = vec![0; 0x10000];
+        rom.prg_rom[0x8000] = 0x42;
+        let mut cpu = new_cpu(Bus::new(rom.clone()));
+        cpu.write_u8(0x8000, 0x10);
+        let state = cpu.save_state();
+
+        let mut restored = new_cpu(Bus::new(rom));
+        assert_eq!(restored.read_u8(0x8000), 0x00);
+        restored.load_state(&state).unwrap();
+        assert_eq!(restored.read_u8(0x8000), 0x42);
+
+        // A state of another mapper is rejected
+        let mut nrom = new_cpu(Bus::new(Rom::test_rom()));
+        assert!(nrom.load_state(&state).is_err());
+    }
+}
@@ -0,0 +1,114 @@
+use crate::input_provider::{ControllerStates, ReplayInput};
+use crate::nes::Nes;
+use crate::rom::Rom;
+
+// Savestate consistency check: runs a game headless, and every `interval` frames saves the machine
+// and loads the state into a new console. Both then run side by side for `compare_frames` frames
+// with the same input, and must produce the same frames. A field missing from the savestate
+// makes the restored console diverge, which ordinary round trip tests do not catch.
+// The frames are compared by their hash, which covers the framebuffer (see CPU::frame_hash).
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ConsistencyCheck {
+    pub frames: usize,
+    pub interval: usize,
+    pub compare_frames: usize,
+    // Seed of the random button presses
+    pub input_seed: u32,
+}
+
+impl Default for ConsistencyCheck {
+    fn default() -> Self {
+        Self { frames: 600, interval: 60, compare_frames: 120, input_seed: 0x1234_5678 }
+    }
+}
+
+// Buttons change every few frames, like a player would press them
+fn random_input(frames: usize, seed: u32) -> Vec<ControllerStates> {
+    let mut state = seed.max(1);
+    let mut next = || {
+        state ^= state << 13;
+        state ^= state >> 17;
+        state ^= state << 5;
+        state
+    };
+    let mut buttons = [0; 4];
+    (0..frames)
+        .map(|_| {
+            let value = next();
+            if value % 8 == 0 {
+                buttons = [(value >> 8) as u8, (value >> 16) as u8, 0, 0];
+            }
+            buttons
+        })
+        .collect()
+}
+
+// Returns the number of savestates checked, or a description of the first divergence.
+pub fn check_savestate_consistency(rom: &Rom, check: &ConsistencyCheck) -> Result<usize, String> {
+    let inputs = random_input(check.frames + check.compare_frames, check.input_seed);
+    let mut nes = Nes::new(rom.clone())?;
+    nes.set_input_provider(Some(Box::new(ReplayInput::new(inputs.clone()))));
+
+    // Restored consoles still being compared, with the frame they were saved at
+    let mut restored: Vec<(usize, Nes)> = Vec::new();
+    let mut checked = 0;
+    for frame in 0..check.frames + check.compare_frames {
+        if frame > 0 && frame < check.frames && frame % check.interval.max(1) == 0 {
+            let state = nes.cpu.save_state();
+            let mut copy = Nes::new(rom.clone())?;
+            copy.cpu.load_state(&state).map_err(|error| format!("Frame {}: {}", frame, error))?;
+            if copy.cpu.save_state() != state {
+                return Err(format!("Frame {}: The loaded state is saved differently", frame));
+            }
+            copy.set_input_provider(Some(Box::new(ReplayInput::new(inputs[frame..].to_vec()))));
+            restored.push((frame, copy));
+            checked += 1;
+        }
+
+        let hash = nes.run_frame();
+        for (saved_frame, copy) in &mut restored {
+            let copy_hash = copy.run_frame();
+            if copy_hash != hash {
+                return Err(format!(
+                    "State saved at frame {} diverged at frame {}: {:016X} instead of {:016X}",
+                    saved_frame, frame, copy_hash, hash
+                ));
+            }
+        }
+        restored.retain(|(saved_frame, _)| frame + 1 - saved_frame < check.compare_frames);
+    }
+    Ok(checked)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::rom::Rom;
+    use crate::savestate_check::{check_savestate_consistency, ConsistencyCheck};
+
+    // Reads controller 1 in a loop and sums the buttons in RAM, so a wrong read is never forgotten
+    const JOYPAD_LOOP: [u8; 32] = [
+        0xA9, 0x01, 0x8D, 0x16, 0x40, // LDA #$01; STA $4016
+        0xA9, 0x00, 0x8D, 0x16, 0x40, // LDA #$00; STA $4016
+        0xA2, 0x08,                   // LDX #$08
+        0xAD, 0x16, 0x40,             // LDA $4016
+        0x4A, 0x26, 0x10,             // LSR A; ROL $10
+        0xCA, 0xD0, 0xF7,             // DEX; BNE $800C
+        0xE6, 0x11,                   // INC $11
+        0xA5, 0x10, 0x65, 0x12,       // LDA $10; ADC $12
+        0x85, 0x12,                   // STA $12
+        0x4C, 0x00, 0x80,             // JMP $8000
+    ];
+
+    #[test]
+    fn test_savestates_are_consistent() {
+        let mut rom = Rom::test_rom();
+        rom.prg_rom[..JOYPAD_LOOP.len()].copy_from_slice(&JOYPAD_LOOP);
+        let length = rom.prg_rom.len();
+        rom.prg_rom[length - 4] = 0x00;
+        rom.prg_rom[length - 3] = 0x80;
+
+        let check = ConsistencyCheck { frames: 120, interval: 7, compare_frames: 20, ..ConsistencyCheck::default() };
+        assert_eq!(check_savestate_consistency(&rom, &check), Ok(17));
+    }
+}
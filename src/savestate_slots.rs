@@ -0,0 +1,188 @@
+use std::path::{Path, PathBuf};
+use crate::rom::Rom;
+use crate::savestate::{StateReader, StateWriter};
+
+// Numbered savestate slots of a game, with the time they were saved and a thumbnail of the screen
+// for the slot menu. Slots are stored in one directory per game, named after the CRC-32 of its
+// PRG and CHR ROM, so renaming or moving the ROM file keeps its slots:
+//   <savestate directory>/<CRC-32>/slot<N>.nsst
+// A slot file holds:
+// 0x00 - 0x03: Magic numbers "NSSL"
+// 0x04:        Format version
+// 0x05 - ...:  Timestamp (seconds since 1970, u64), thumbnail width and height (u16) and its RGB24
+//              pixels, then the savestate (see savestate.rs).
+
+pub const SAVESTATE_SLOTS: usize = 10;
+// Thumbnails are the screen downscaled by this factor (64x60 for a 256x240 frame)
+pub const THUMBNAIL_SCALE: usize = 4;
+
+const SLOT_MAGIC_NUMBERS: &[u8; 4] = b"NSSL";
+const SLOT_VERSION: u8 = 1;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Thumbnail {
+    pub width: usize,
+    pub height: usize,
+    // RGB24
+    pub rgb: Vec<u8>,
+}
+
+impl Thumbnail {
+    // Downscales an RGB24 image, averaging each block of `scale` x `scale` pixels.
+    pub fn from_rgb(rgb: &[u8], width: usize, height: usize, scale: usize) -> Result<Self, String> {
+        if rgb.len() != width * height * 3 {
+            return Err(format!("Expected a {}x{} RGB24 image ({} bytes), got {} bytes", width, height, width * height * 3, rgb.len()));
+        }
+        let (thumbnail_width, thumbnail_height) = (width / scale, height / scale);
+        let mut thumbnail = Vec::with_capacity(thumbnail_width * thumbnail_height * 3);
+        for y in 0..thumbnail_height {
+            for x in 0..thumbnail_width {
+                for channel in 0..3 {
+                    let sum: usize = (0..scale * scale)
+                        .map(|offset| ((y * scale + offset / scale) * width + x * scale + offset % scale) * 3 + channel)
+                        .map(|index| rgb[index] as usize)
+                        .sum();
+                    thumbnail.push((sum / (scale * scale)) as u8);
+                }
+            }
+        }
+        Ok(Self { width: thumbnail_width, height: thumbnail_height, rgb: thumbnail })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SlotMetadata {
+    // Seconds since 1970-01-01 UTC
+    pub timestamp: u64,
+    // Empty when the state was saved without a screenshot
+    pub thumbnail: Thumbnail,
+}
+
+#[derive(Debug, Clone)]
+pub struct SlotManager {
+    directory: PathBuf,
+}
+
+impl SlotManager {
+    pub fn new<P: AsRef<Path>>(savestate_directory: P, rom: &Rom) -> Self {
+        Self { directory: savestate_directory.as_ref().join(format!("{:08X}", rom.crc32())) }
+    }
+
+    pub fn path(&self, slot: usize) -> PathBuf {
+        self.directory.join(format!("slot{}.nsst", slot))
+    }
+
+    fn check_slot(slot: usize) -> Result<(), String> {
+        if slot >= SAVESTATE_SLOTS {
+            return Err(format!("Invalid savestate slot {} (0 to {})", slot, SAVESTATE_SLOTS - 1));
+        }
+        Ok(())
+    }
+
+    pub fn save(&self, slot: usize, state: &[u8], metadata: &SlotMetadata) -> Result<(), String> {
+        Self::check_slot(slot)?;
+        let mut writer = StateWriter::new();
+        writer.write_bytes(SLOT_MAGIC_NUMBERS);
+        writer.write_u8(SLOT_VERSION);
+        writer.write_u64(metadata.timestamp);
+        writer.write_u16(metadata.thumbnail.width as u16);
+        writer.write_u16(metadata.thumbnail.height as u16);
+        writer.write_bytes(&metadata.thumbnail.rgb);
+        writer.write_bytes(state);
+
+        std::fs::create_dir_all(&self.directory)
+            .map_err(|error| format!("Failed to create savestate directory {}: {}", self.directory.display(), error))?;
+        let path = self.path(slot);
+        std::fs::write(&path, writer.finish()).map_err(|error| format!("Failed to write savestate {}: {}", path.display(), error))
+    }
+
+    // Returns the metadata and the savestate of `slot`.
+    pub fn load(&self, slot: usize) -> Result<(SlotMetadata, Vec<u8>), String> {
+        Self::check_slot(slot)?;
+        let path = self.path(slot);
+        let data = match std::fs::read(&path) {
+            Ok(data) => data,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Err(format!("Savestate slot {} is empty", slot)),
+            Err(error) => return Err(format!("Failed to read savestate {}: {}", path.display(), error)),
+        };
+
+        let mut reader = StateReader::new(&data);
+        if reader.read_bytes(4)? != SLOT_MAGIC_NUMBERS {
+            return Err(format!("Invalid savestate slot file {}", path.display()));
+        }
+        let version = reader.read_u8()?;
+        if version != SLOT_VERSION {
+            return Err(format!("Unsupported savestate slot version: {} (expected {})", version, SLOT_VERSION));
+        }
+        let timestamp = reader.read_u64()?;
+        let width = reader.read_u16()? as usize;
+        let height = reader.read_u16()? as usize;
+        let rgb = reader.read_bytes(width * height * 3)?.to_vec();
+        let state = data[data.len() - reader.remaining()..].to_vec();
+        Ok((SlotMetadata { timestamp, thumbnail: Thumbnail { width, height, rgb } }, state))
+    }
+
+    // Metadata of every slot, for the slot menu (None for empty or unreadable slots)
+    pub fn list(&self) -> Vec<Option<SlotMetadata>> {
+        (0..SAVESTATE_SLOTS).map(|slot| self.load(slot).ok().map(|(metadata, _)| metadata)).collect()
+    }
+
+    pub fn delete(&self, slot: usize) -> Result<(), String> {
+        Self::check_slot(slot)?;
+        let path = self.path(slot);
+        match std::fs::remove_file(&path) {
+            Err(error) if error.kind() != std::io::ErrorKind::NotFound => {
+                Err(format!("Failed to delete savestate {}: {}", path.display(), error))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::rom::Rom;
+    use crate::savestate_slots::{SlotManager, SlotMetadata, Thumbnail, SAVESTATE_SLOTS};
+
+    fn temp_directory(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("{}_{}", name, std::process::id()))
+    }
+
+    #[test]
+    fn test_thumbnail_downscale() {
+        // 4x2 image: left half black and white pixels, right half red
+        let rgb = [
+            [0, 0, 0], [255, 255, 255], [255, 0, 0], [255, 0, 0],
+            [255, 255, 255], [0, 0, 0], [255, 0, 0], [255, 0, 0],
+        ].concat();
+        let thumbnail = Thumbnail::from_rgb(&rgb, 4, 2, 2).unwrap();
+        assert_eq!((thumbnail.width, thumbnail.height), (2, 1));
+        assert_eq!(thumbnail.rgb, vec![127, 127, 127, 255, 0, 0]);
+
+        assert!(Thumbnail::from_rgb(&rgb, 4, 4, 2).is_err());
+    }
+
+    #[test]
+    fn test_save_load_and_list() {
+        let directory = temp_directory("savestate_slots");
+        let slots = SlotManager::new(&directory, &Rom::test_rom());
+        assert!(slots.path(3).starts_with(&directory));
+
+        let metadata = SlotMetadata { timestamp: 1_700_000_000, thumbnail: Thumbnail { width: 1, height: 1, rgb: vec![1, 2, 3] } };
+        slots.save(3, b"state", &metadata).unwrap();
+        assert_eq!(slots.load(3).unwrap(), (metadata.clone(), b"state".to_vec()));
+
+        let list = slots.list();
+        assert_eq!(list.len(), SAVESTATE_SLOTS);
+        assert_eq!(list[3], Some(metadata));
+        assert_eq!(list[0], None);
+
+        assert!(slots.load(0).is_err());
+        assert!(slots.save(SAVESTATE_SLOTS, b"state", &list[3].clone().unwrap()).is_err());
+
+        slots.delete(3).unwrap();
+        slots.delete(3).unwrap();
+        assert!(slots.load(3).is_err());
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
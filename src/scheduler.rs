@@ -0,0 +1,188 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use crate::cpu6502::CPU;
+
+// Event-driven timing: instead of ticking every component on every CPU cycle, components schedule
+// the next cycle at which they affect the CPU (the NMI at the start of vblank, the next step of the
+// APU frame counter, a mapper IRQ counter reaching 0). The CPU then runs instructions in a batch
+// until the next event, which is dispatched to the component, who updates its state and schedules
+// its next event. Headless and fast-forward runs only pay for the events that actually happen.
+// Instructions are not split: an event is dispatched before the first instruction starting at or
+// after its cycle, and carries that cycle so interrupts can still be timed precisely.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum EventKind {
+    Nmi,
+    ApuFrameStep,
+    MapperIrq,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Scheduler {
+    // Min-heap ordered by cycle, then by scheduling order for events due on the same cycle
+    events: BinaryHeap<Reverse<(u64, u64, EventKind)>>,
+    next_sequence: u64,
+}
+
+impl Scheduler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn schedule(&mut self, cycle: u64, kind: EventKind) {
+        self.events.push(Reverse((cycle, self.next_sequence, kind)));
+        self.next_sequence += 1;
+    }
+
+    // Removes the pending events of a kind, e.g. when a game disables the NMI or acknowledges an IRQ.
+    pub fn cancel(&mut self, kind: EventKind) {
+        self.events.retain(|Reverse((_, _, event))| *event != kind);
+    }
+
+    pub fn next_event_cycle(&self) -> Option<u64> {
+        self.events.peek().map(|Reverse((cycle, _, _))| *cycle)
+    }
+
+    // Removes and returns the next event if it is due at `now`.
+    pub fn pop_due(&mut self, now: u64) -> Option<(u64, EventKind)> {
+        if self.next_event_cycle()? > now {
+            return None;
+        }
+        self.events.pop().map(|Reverse((cycle, _, kind))| (cycle, kind))
+    }
+
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+}
+
+impl CPU {
+    // Runs instructions until the cycle counter reaches `cycle` (or the CPU jams).
+    pub fn run_until(&mut self, cycle: u64) {
+        while !self.halted && self.cycles < cycle {
+            self.step();
+        }
+    }
+
+    // Runs until `end`, dispatching each event to `handle_event` when it is due.
+    // The handler receives the cycle the event was scheduled for.
+    pub fn run_scheduled<F>(&mut self, scheduler: &mut Scheduler, end: u64, mut handle_event: F)
+    where
+        F: FnMut(&mut CPU, &mut Scheduler, EventKind, u64),
+    {
+        // Events due once `end` is reached are left for the next call
+        while !self.halted && self.cycles < end {
+            while let Some((cycle, kind)) = scheduler.pop_due(self.cycles) {
+                handle_event(self, scheduler, kind, cycle);
+            }
+            let next_stop = scheduler.next_event_cycle().map_or(end, |cycle| cycle.min(end));
+            self.run_until(next_stop);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bus::Bus;
+    use crate::cpu6502::{new_cpu, StatusFlag, CPU};
+    use crate::interrupts::IrqSource;
+    use crate::power_on::PowerOnConfig;
+    use crate::rom::Rom;
+    use crate::scheduler::{EventKind, Scheduler};
+
+    // Flat memory filled with NOPs, interrupt handlers included
+    fn nop_cpu() -> CPU {
+        let mut cpu = new_cpu(Bus::new_flat());
+        for address in 0x0200..0xFFFA {
+            cpu.write_u8(address, 0xEA);
+        }
+        for (vector, handler) in [(0xFFFA, 0x8000u16), (0xFFFE, 0x9000)] {
+            cpu.write_u8(vector, handler as u8);
+            cpu.write_u8(vector + 1, (handler >> 8) as u8);
+        }
+        cpu.program_counter = 0x0200;
+        cpu
+    }
+
+    #[test]
+    fn test_event_order() {
+        let mut scheduler = Scheduler::new();
+        scheduler.schedule(100, EventKind::MapperIrq);
+        scheduler.schedule(50, EventKind::ApuFrameStep);
+        scheduler.schedule(100, EventKind::Nmi);
+        assert_eq!(scheduler.next_event_cycle(), Some(50));
+        assert_eq!(scheduler.pop_due(49), None);
+        assert_eq!(scheduler.pop_due(60), Some((50, EventKind::ApuFrameStep)));
+        // Same cycle: scheduling order
+        assert_eq!(scheduler.pop_due(100), Some((100, EventKind::MapperIrq)));
+
+        scheduler.cancel(EventKind::Nmi);
+        assert!(scheduler.is_empty());
+    }
+
+    #[test]
+    fn test_periodic_event() {
+        let mut cpu = nop_cpu();
+        let mut scheduler = Scheduler::new();
+        scheduler.schedule(1_000, EventKind::ApuFrameStep);
+        let mut steps = Vec::new();
+        cpu.run_scheduled(&mut scheduler, 10_000, |cpu, scheduler, kind, cycle| {
+            assert_eq!(kind, EventKind::ApuFrameStep);
+            // Dispatched before the first instruction starting at or after the event
+            assert!(cpu.cycles >= cycle && cpu.cycles < cycle + 2);
+            steps.push(cycle);
+            scheduler.schedule(cycle + 1_000, EventKind::ApuFrameStep);
+        });
+        assert_eq!(steps, (1..=9).map(|step| step * 1_000).collect::<Vec<u64>>());
+        assert!(cpu.cycles >= 10_000);
+        assert_eq!(scheduler.next_event_cycle(), Some(10_000));
+    }
+
+    #[test]
+    fn test_interrupt_events() {
+        let mut cpu = nop_cpu();
+        let mut scheduler = Scheduler::new();
+        scheduler.schedule(101, EventKind::Nmi);
+        cpu.run_scheduled(&mut scheduler, 200, |cpu, _, kind, cycle| match kind {
+            EventKind::Nmi => cpu.request_nmi_at(cycle),
+            _ => unreachable!(),
+        });
+        assert_eq!(cpu.stack_pointer, 0xFF - 3);
+        assert!(cpu.get_status_flag(StatusFlag::InterruptDisable));
+        assert!((0x8000..0x8100).contains(&cpu.program_counter));
+
+        cpu.status_register = 0x20;
+        scheduler.schedule(300, EventKind::MapperIrq);
+        cpu.run_scheduled(&mut scheduler, 400, |cpu, _, kind, cycle| match kind {
+            EventKind::MapperIrq => cpu.set_irq_at(IrqSource::Mapper, true, cycle),
+            _ => unreachable!(),
+        });
+        assert!((0x9000..0x9100).contains(&cpu.program_counter));
+    }
+
+    #[test]
+    fn test_ppu_runs_until_vblank_lazily() {
+        let mut cpu = new_cpu(Bus::new(Rom::test_rom()));
+        cpu.power_on(&PowerOnConfig::default()).expect("power on should succeed");
+        // Spins on `JMP $0300` with the NMI enabled
+        for (offset, byte) in [0x4C, 0x00, 0x03].into_iter().enumerate() {
+            cpu.write_u8(0x0300 + offset as u16, byte);
+        }
+        cpu.program_counter = 0x0300;
+        cpu.write_u8(0x2000, 0x80);
+
+        cpu.run_until(10_000);
+        assert!(cpu.bus.ppu().dots < 100);
+        while (0x0300..0x0303).contains(&cpu.program_counter) {
+            cpu.step();
+        }
+        // The NMI of the first vblank (dot 82182) is taken after the instruction it interrupts
+        let vblank_cycle = (241 * 341 + 1) / 3;
+        assert!((vblank_cycle..=vblank_cycle + 3 + 7).contains(&cpu.cycles), "{}", cpu.cycles);
+        assert!(cpu.bus.ppu().dots >= vblank_cycle * 3);
+    }
+}
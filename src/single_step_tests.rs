@@ -0,0 +1,156 @@
+// Runs the SingleStepTests CPU vectors (https://github.com/SingleStepTests/65x02, "nes6502" folder).
+// Each opcode has a JSON file (e.g. "a9.json") holding 10,000 test cases describing the CPU and RAM
+// state before and after executing a single instruction, as well as every bus access performed.
+//
+// The files are too big to be shipped with the repository. Download them and run:
+//   SINGLE_STEP_TESTS_PATH=/path/to/nes6502/v1 cargo test --features single-step-tests
+//
+// The final registers and memory content are compared, as well as the bus access of every cycle
+// (address, value, read or write), recorded with an AccessLog watching the whole address space.
+
+use serde::Deserialize;
+use crate::access_log::{AccessKind, AccessLog};
+use crate::bus::Bus;
+use crate::cpu6502::{is_opcode_supported, new_cpu, CPU, MAGIC_CONSTANT_EE};
+
+#[derive(Debug, Deserialize)]
+struct CpuState {
+    pc: u16,
+    s: u8,
+    a: u8,
+    x: u8,
+    y: u8,
+    p: u8,
+    ram: Vec<(u16, u8)>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TestCase {
+    name: String,
+    initial: CpuState,
+    #[serde(rename = "final")]
+    expected: CpuState,
+    // Every bus access performed by the instruction: (address, value, "read" | "write")
+    cycles: Vec<(u16, u8, String)>,
+}
+
+// KIL opcodes jam the CPU: the vectors record the accesses a jammed chip keeps doing on the bus,
+// while the emulator stops fetching until the next reset (see kil.rs), so they are skipped.
+// The unstable opcodes are all run: XAA and ATX with the magic constant of the vectors, and
+// AXA, XAS, SYA and SXA with the address corruption of a page crossing.
+const SKIPPED_OPCODES: [u8; 12] = [0x02, 0x12, 0x22, 0x32, 0x42, 0x52, 0x62, 0x72, 0x92, 0xB2, 0xD2, 0xF2];
+
+fn setup_cpu(state: &CpuState) -> CPU {
+    let mut cpu = new_cpu(Bus::new_flat());
+    cpu.magic_constant = MAGIC_CONSTANT_EE;
+    cpu.program_counter = state.pc;
+    cpu.stack_pointer = state.s;
+    cpu.accumulator = state.a;
+    cpu.x_register = state.x;
+    cpu.y_register = state.y;
+    cpu.status_register = state.p;
+    for (address, value) in &state.ram {
+        cpu.write_u8(*address, *value);
+    }
+    cpu
+}
+
+// Returns a description of the first difference found, if any.
+fn check_test_case(test_case: &TestCase) -> Option<String> {
+    let mut cpu = setup_cpu(&test_case.initial);
+    let mut log = AccessLog::new(test_case.cycles.len() + 8);
+    log.watch_range(0x0000..=0xFFFF);
+    cpu.bus.enable_access_log(log);
+    let cycles = cpu.step();
+    let log = cpu.bus.disable_access_log().expect("access log should be enabled");
+    let expected = &test_case.expected;
+
+    let accesses: Vec<(u16, u8, &str)> = log.entries().map(|access| {
+        let kind = match access.kind {
+            AccessKind::Read => "read",
+            AccessKind::Write => "write",
+        };
+        (access.address, access.value, kind)
+    }).collect();
+    for (cycle, (actual, (address, value, kind))) in accesses.iter().zip(&test_case.cycles).enumerate() {
+        if *actual != (*address, *value, kind.as_str()) {
+            return Some(format!("{}: cycle {} is {:?}, expected {:?}", test_case.name, cycle + 1, actual, (address, value, kind)));
+        }
+    }
+    if accesses.len() != test_case.cycles.len() {
+        return Some(format!("{}: {} bus accesses, expected {}", test_case.name, accesses.len(), test_case.cycles.len()));
+    }
+
+    let registers = [
+        ("PC", cpu.program_counter, expected.pc),
+        ("SP", cpu.stack_pointer as u16, expected.s as u16),
+        ("A", cpu.accumulator as u16, expected.a as u16),
+        ("X", cpu.x_register as u16, expected.x as u16),
+        ("Y", cpu.y_register as u16, expected.y as u16),
+        ("P", cpu.status_register as u16, expected.p as u16),
+    ];
+    for (name, actual, expected) in registers {
+        if actual != expected {
+            return Some(format!("{}: {} is {:04X}, expected {:04X}", test_case.name, name, actual, expected));
+        }
+    }
+
+    for (address, value) in &expected.ram {
+        let actual = cpu.read_u8(*address);
+        if actual != *value {
+            return Some(format!("{}: memory at {:04X} is {:02X}, expected {:02X}", test_case.name, address, actual, value));
+        }
+    }
+
+    if cycles != test_case.cycles.len() as u64 {
+        return Some(format!("{}: took {} cycles, expected {}", test_case.name, cycles, test_case.cycles.len()));
+    }
+    None
+}
+
+#[test]
+fn test_single_step_tests_vectors() {
+    let Ok(directory) = std::env::var("SINGLE_STEP_TESTS_PATH") else {
+        println!("SINGLE_STEP_TESTS_PATH is not set, skipping SingleStepTests vectors");
+        return;
+    };
+
+    let mut failures = Vec::new();
+    for opcode in 0..=255u8 {
+        if !is_opcode_supported(opcode) || SKIPPED_OPCODES.contains(&opcode) {
+            continue;
+        }
+
+        let path = std::path::Path::new(&directory).join(format!("{:02x}.json", opcode));
+        let Ok(content) = std::fs::read_to_string(&path) else {
+            continue;
+        };
+        let test_cases: Vec<TestCase> = serde_json::from_str(&content)
+            .unwrap_or_else(|error| panic!("Failed to parse {}: {}", path.display(), error));
+
+        let failed: Vec<String> = test_cases.iter().filter_map(check_test_case).collect();
+        if let Some(first_failure) = failed.first() {
+            failures.push(format!("Opcode {:02X}: {}/{} failed, first: {}", opcode, failed.len(), test_cases.len(), first_failure));
+        }
+    }
+
+    assert!(failures.is_empty(), "SingleStepTests failures:\n{}", failures.join("\n"));
+}
+
+#[test]
+fn test_check_test_case_compares_each_cycle() {
+    // JSR $1234 at 0x0600, in the format of the vectors
+    let json = r#"{
+        "name": "20 34 12",
+        "initial": {"pc": 1536, "s": 253, "a": 0, "x": 0, "y": 0, "p": 36, "ram": [[1536, 32], [1537, 52], [1538, 18]]},
+        "final": {"pc": 4660, "s": 251, "a": 0, "x": 0, "y": 0, "p": 36, "ram": [[508, 2], [509, 6]]},
+        "cycles": [[1536, 32, "read"], [1537, 52, "read"], [509, 0, "read"], [509, 6, "write"], [508, 2, "write"], [1538, 18, "read"]]
+    }"#;
+    let mut test_case: TestCase = serde_json::from_str(json).unwrap();
+    assert_eq!(check_test_case(&test_case), None);
+
+    // Same registers and memory at the end, but the operand read in the wrong order
+    test_case.cycles.swap(1, 5);
+    let failure = check_test_case(&test_case).unwrap();
+    assert!(failure.starts_with("20 34 12: cycle 2"), "{}", failure);
+}
@@ -0,0 +1,332 @@
+use std::io::{ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::mpsc::{sync_channel, SyncSender, TrySendError};
+use std::sync::Arc;
+use crate::rewind::{decode_delta, encode_delta};
+
+// Spectator mode: broadcasts the frames and the audio of a session over TCP, so remote viewers
+// can watch it live without running the ROM (netplay lobbies, remote debugging).
+// Frames are RGB24 (as produced by the render thread) sent as the run-length encoded XOR against
+// the previous frame (see rewind.rs), which is small since most of the screen rarely changes.
+// A spectator that just joined, or that fell behind, receives a key frame: the XOR against a
+// black frame.
+// Messages:
+// - 'H': protocol version (u8), width (u16 LE), height (u16 LE), audio sample rate (u32 LE),
+// - 'F': frame number (u64 LE), key frame (u8), length (u32 LE), encoded frame,
+// - 'A': sample count (u32 LE), mono samples (i16 LE).
+
+const PROTOCOL_VERSION: u8 = 1;
+const HELLO: u8 = b'H';
+const FRAME: u8 = b'F';
+const AUDIO: u8 = b'A';
+// Messages queued for a spectator before it is considered behind (about half a second)
+const SPECTATOR_BACKLOG: usize = 64;
+
+#[derive(Debug)]
+struct Spectator {
+    messages: SyncSender<Arc<Vec<u8>>>,
+    // Whether the next frame must be a key frame
+    needs_key_frame: bool,
+    // Cleared once the spectator left (its thread stopped)
+    connected: bool,
+}
+
+#[derive(Debug)]
+pub struct SpectatorServer {
+    listener: TcpListener,
+    width: usize,
+    height: usize,
+    sample_rate: u32,
+    spectators: Vec<Spectator>,
+    previous_frame: Vec<u8>,
+    frame: u64,
+}
+
+impl SpectatorServer {
+    // Serves the spectators connecting to `listener`. Nothing blocks the emulation: each spectator
+    // is written to by its own thread.
+    pub fn new(listener: TcpListener, width: usize, height: usize, sample_rate: u32) -> Result<Self, String> {
+        listener.set_nonblocking(true).map_err(|error| format!("Spectator: Failed to configure the socket: {}", error))?;
+        Ok(Self { listener, width, height, sample_rate, spectators: Vec::new(), previous_frame: vec![0; width * height * 3], frame: 0 })
+    }
+
+    pub fn spectator_count(&self) -> usize {
+        self.spectators.len()
+    }
+
+    // Accepts the spectators waiting to connect. Called once per frame, before broadcasting.
+    pub fn accept_pending(&mut self) -> Result<(), String> {
+        loop {
+            match self.listener.accept() {
+                Ok((stream, _)) => self.add_spectator(stream)?,
+                Err(error) if error.kind() == ErrorKind::WouldBlock => return Ok(()),
+                Err(error) => return Err(format!("Spectator: Failed to accept a connection: {}", error)),
+            }
+        }
+    }
+
+    fn add_spectator(&mut self, mut stream: TcpStream) -> Result<(), String> {
+        stream.set_nonblocking(false).map_err(connection_error)?;
+        let mut hello = vec![HELLO, PROTOCOL_VERSION];
+        hello.extend_from_slice(&(self.width as u16).to_le_bytes());
+        hello.extend_from_slice(&(self.height as u16).to_le_bytes());
+        hello.extend_from_slice(&self.sample_rate.to_le_bytes());
+
+        let (messages, received) = sync_channel::<Arc<Vec<u8>>>(SPECTATOR_BACKLOG);
+        std::thread::spawn(move || {
+            if stream.write_all(&hello).is_err() {
+                return;
+            }
+            // Stops when the spectator leaves or the server drops it
+            for message in received {
+                if stream.write_all(&message).is_err() {
+                    return;
+                }
+            }
+        });
+        self.spectators.push(Spectator { messages, needs_key_frame: true, connected: true });
+        Ok(())
+    }
+
+    // Sends an RGB24 frame of `width` x `height` pixels to every spectator.
+    pub fn broadcast_frame(&mut self, rgb: &[u8]) -> Result<(), String> {
+        if rgb.len() != self.previous_frame.len() {
+            return Err(format!("Spectator: Expected a frame of {} bytes, got {}", self.previous_frame.len(), rgb.len()));
+        }
+        let mut delta = None;
+        let mut key_frame = None;
+        for spectator in &mut self.spectators {
+            let message = if spectator.needs_key_frame {
+                key_frame.get_or_insert_with(|| frame_message(self.frame, true, &vec![0; rgb.len()], rgb)).clone()
+            } else {
+                delta.get_or_insert_with(|| frame_message(self.frame, false, &self.previous_frame, rgb)).clone()
+            };
+            spectator.needs_key_frame = !spectator.send(message);
+        }
+        self.spectators.retain(|spectator| spectator.connected);
+        self.previous_frame.copy_from_slice(rgb);
+        self.frame += 1;
+        Ok(())
+    }
+
+    // Sends audio samples (in [-1, 1], at the sample rate announced to the spectators).
+    pub fn broadcast_audio(&mut self, samples: &[f32]) {
+        if self.spectators.is_empty() || samples.is_empty() {
+            return;
+        }
+        let mut message = vec![AUDIO];
+        message.extend_from_slice(&(samples.len() as u32).to_le_bytes());
+        for sample in samples {
+            message.extend_from_slice(&((sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16).to_le_bytes());
+        }
+        let message = Arc::new(message);
+        for spectator in &mut self.spectators {
+            // Audio dropped for a spectator that is behind is not worth a resync
+            spectator.send(message.clone());
+        }
+        self.spectators.retain(|spectator| spectator.connected);
+    }
+}
+
+impl Spectator {
+    // Queues `message`, returning false when it was dropped
+    fn send(&mut self, message: Arc<Vec<u8>>) -> bool {
+        match self.messages.try_send(message) {
+            Ok(()) => true,
+            Err(TrySendError::Full(_)) => false,
+            Err(TrySendError::Disconnected(_)) => {
+                self.connected = false;
+                false
+            }
+        }
+    }
+}
+
+fn frame_message(frame: u64, key_frame: bool, previous: &[u8], rgb: &[u8]) -> Arc<Vec<u8>> {
+    let encoded = encode_delta(previous, rgb);
+    let mut message = vec![FRAME];
+    message.extend_from_slice(&frame.to_le_bytes());
+    message.push(key_frame as u8);
+    message.extend_from_slice(&(encoded.len() as u32).to_le_bytes());
+    message.extend_from_slice(&encoded);
+    Arc::new(message)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SpectatorEvent {
+    // A new frame, available through `SpectatorClient::frame`
+    Frame(u64),
+    Audio(Vec<i16>),
+}
+
+// The viewer side
+#[derive(Debug)]
+pub struct SpectatorClient {
+    stream: TcpStream,
+    width: usize,
+    height: usize,
+    sample_rate: u32,
+    frame: Vec<u8>,
+    // Whether a key frame was received, so that the deltas can be applied
+    synchronized: bool,
+}
+
+impl SpectatorClient {
+    pub fn connect<A: ToSocketAddrs>(address: A) -> Result<Self, String> {
+        let mut stream = TcpStream::connect(address).map_err(|error| format!("Spectator: Failed to connect: {}", error))?;
+        let [kind, protocol] = read_array(&mut stream)?;
+        if kind != HELLO {
+            return Err("Spectator: Expected a handshake".to_string());
+        }
+        if protocol != PROTOCOL_VERSION {
+            return Err(format!("Spectator: Unsupported protocol version {} (expected {})", protocol, PROTOCOL_VERSION));
+        }
+        let width = u16::from_le_bytes(read_array(&mut stream)?) as usize;
+        let height = u16::from_le_bytes(read_array(&mut stream)?) as usize;
+        let sample_rate = u32::from_le_bytes(read_array(&mut stream)?);
+        Ok(Self { stream, width, height, sample_rate, frame: vec![0; width * height * 3], synchronized: false })
+    }
+
+    pub fn size(&self) -> (usize, usize) {
+        (self.width, self.height)
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    // Last frame received (RGB24), black until the first one
+    pub fn frame(&self) -> &[u8] {
+        &self.frame
+    }
+
+    // Blocks until the next frame or audio samples.
+    pub fn next_event(&mut self) -> Result<SpectatorEvent, String> {
+        loop {
+            let [kind] = read_array(&mut self.stream)?;
+            match kind {
+                FRAME => {
+                    let frame = u64::from_le_bytes(read_array(&mut self.stream)?);
+                    let [key_frame] = read_array(&mut self.stream)?;
+                    let length = u32::from_le_bytes(read_array(&mut self.stream)?) as usize;
+                    let mut encoded = vec![0; length];
+                    self.stream.read_exact(&mut encoded).map_err(connection_error)?;
+                    if key_frame != 0 {
+                        self.frame.fill(0);
+                        self.synchronized = true;
+                    } else if !self.synchronized {
+                        continue;
+                    }
+                    self.frame = decode_delta(&self.frame, &encoded);
+                    return Ok(SpectatorEvent::Frame(frame));
+                }
+                AUDIO => {
+                    let count = u32::from_le_bytes(read_array(&mut self.stream)?) as usize;
+                    let mut bytes = vec![0; count * 2];
+                    self.stream.read_exact(&mut bytes).map_err(connection_error)?;
+                    let samples = bytes.chunks_exact(2).map(|sample| i16::from_le_bytes([sample[0], sample[1]])).collect();
+                    return Ok(SpectatorEvent::Audio(samples));
+                }
+                _ => return Err(format!("Spectator: Unexpected message type {:#04X}", kind)),
+            }
+        }
+    }
+}
+
+fn read_array<const N: usize>(stream: &mut TcpStream) -> Result<[u8; N], String> {
+    let mut buffer = [0; N];
+    stream.read_exact(&mut buffer).map_err(connection_error)?;
+    Ok(buffer)
+}
+
+fn connection_error(error: std::io::Error) -> String {
+    format!("Spectator: Connection lost: {}", error)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+    use std::time::Duration;
+    use crate::spectator::{frame_message, SpectatorClient, SpectatorEvent, SpectatorServer};
+
+    const WIDTH: usize = 16;
+    const HEIGHT: usize = 8;
+
+    fn server() -> SpectatorServer {
+        SpectatorServer::new(TcpListener::bind("127.0.0.1:0").unwrap(), WIDTH, HEIGHT, 44100).unwrap()
+    }
+
+    fn wait_for_spectators(server: &mut SpectatorServer, count: usize) {
+        for _ in 0..500 {
+            server.accept_pending().unwrap();
+            if server.spectator_count() == count {
+                return;
+            }
+            std::thread::sleep(Duration::from_millis(2));
+        }
+        panic!("{} spectators expected, {} connected", count, server.spectator_count());
+    }
+
+    fn test_frame(seed: u8) -> Vec<u8> {
+        (0..WIDTH * HEIGHT * 3).map(|i| if i % 7 == 0 { seed } else { (i / 48) as u8 }).collect()
+    }
+
+    #[test]
+    fn test_spectator_receives_frames_and_audio() {
+        let mut server = server();
+        let address = server.listener.local_addr().unwrap();
+        let spectator = std::thread::spawn(move || {
+            let mut client = SpectatorClient::connect(address).unwrap();
+            assert_eq!(client.size(), (WIDTH, HEIGHT));
+            assert_eq!(client.sample_rate(), 44100);
+            let mut events = Vec::new();
+            for _ in 0..3 {
+                let event = client.next_event().unwrap();
+                let frame = client.frame().to_vec();
+                events.push((event, frame));
+            }
+            events
+        });
+        wait_for_spectators(&mut server, 1);
+
+        server.broadcast_frame(&test_frame(1)).unwrap();
+        server.broadcast_audio(&[0.5, -1.0, 2.0]);
+        server.broadcast_frame(&test_frame(2)).unwrap();
+        let events = spectator.join().unwrap();
+
+        assert_eq!(events[0], (SpectatorEvent::Frame(0), test_frame(1)));
+        assert_eq!(events[1].0, SpectatorEvent::Audio(vec![16383, -32767, 32767]));
+        assert_eq!(events[2], (SpectatorEvent::Frame(1), test_frame(2)));
+    }
+
+    #[test]
+    fn test_frames_are_sent_as_deltas() {
+        let key_frame = frame_message(0, true, &vec![0; WIDTH * HEIGHT * 3], &test_frame(1));
+        let delta = frame_message(1, false, &test_frame(1), &test_frame(1));
+        assert!(key_frame.len() > WIDTH * HEIGHT * 2);
+        assert!(delta.len() < 32);
+    }
+
+    #[test]
+    fn test_leaving_spectator_is_dropped() {
+        let mut server = server();
+        let address = server.listener.local_addr().unwrap();
+        let spectator = std::thread::spawn(move || drop(SpectatorClient::connect(address).unwrap()));
+        wait_for_spectators(&mut server, 1);
+        spectator.join().unwrap();
+
+        for _ in 0..500 {
+            server.broadcast_frame(&test_frame(0)).unwrap();
+            if server.spectator_count() == 0 {
+                return;
+            }
+            std::thread::sleep(Duration::from_millis(2));
+        }
+        panic!("The spectator was not dropped");
+    }
+
+    #[test]
+    fn test_frame_size_is_checked() {
+        assert!(server().broadcast_frame(&[0; 12]).is_err());
+    }
+}
@@ -0,0 +1,254 @@
+use std::collections::VecDeque;
+use std::fmt;
+use crate::cpu6502::{trace, CPU};
+
+// Lockstep comparison against a reference trace: each line of the reference log is checked
+// against the CPU before the corresponding instruction runs, and the run stops at the first
+// difference, with the last lines executed for context. Much faster than diffing huge logs.
+// Supported formats (only the PC, registers and cycle count are compared, the disassembly is not):
+// - Nintendulator / nestest.log: "C000  4C F5 C5  JMP $C5F5  A:00 X:00 Y:00 P:24 SP:FD PPU:  0, 21 CYC:7"
+// - FCEUX: "c7  A:00 X:00 Y:00 S:FD P:nvubdIzc  $C000:4C F5 C5  JMP $C5F5"
+// - Mesen: "C000  JMP $C5F5  A:00 X:00 Y:00 S:FD P:nvIzc  V:0 H:21 Cycle:7"
+// Flags are either hexadecimal or letters (uppercase when set, missing letters are clear).
+// The B and unused bits of P are ignored, since emulators display them differently.
+// Cycle counts are compared relative to the first line, as emulators start counting at
+// different values; lines without a cycle count only compare the registers.
+
+// Lines of our own trace printed before a divergence
+const CONTEXT_LINES: usize = 8;
+const IGNORED_FLAGS: u8 = 0b0011_0000;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ReferenceState {
+    pub pc: u16,
+    pub a: u8,
+    pub x: u8,
+    pub y: u8,
+    pub p: u8,
+    pub sp: u8,
+    pub cycles: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Divergence {
+    // Line of the reference trace (starting at 1)
+    pub line: usize,
+    // Fields that differ (e.g. "A", "CYC")
+    pub fields: Vec<&'static str>,
+    pub expected: String,
+    pub actual: String,
+    // Our trace lines before the divergence, oldest first
+    pub context: Vec<String>,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Trace diverged at line {} ({} differ)", self.line, self.fields.join(", "))?;
+        for line in &self.context {
+            writeln!(f, "            {}", line)?;
+        }
+        writeln!(f, "  expected: {}", self.expected)?;
+        write!(f, "  actual:   {}", self.actual)
+    }
+}
+
+// Parses a line of one of the supported formats. Returns None for lines without a PC and
+// registers (headers, blank lines, or interrupt annotations).
+pub fn parse_reference_line(line: &str) -> Option<ReferenceState> {
+    let mut state = ReferenceState::default();
+    let mut registers = 0;
+    let mut pc = None;
+    for (index, token) in line.split_whitespace().enumerate() {
+        if let Some((name, value)) = token.split_once(':') {
+            match name {
+                "A" => (state.a, registers) = (u8::from_str_radix(value, 16).ok()?, registers + 1),
+                "X" => (state.x, registers) = (u8::from_str_radix(value, 16).ok()?, registers + 1),
+                "Y" => (state.y, registers) = (u8::from_str_radix(value, 16).ok()?, registers + 1),
+                "SP" | "S" => (state.sp, registers) = (u8::from_str_radix(value, 16).ok()?, registers + 1),
+                "P" => (state.p, registers) = (parse_flags(value)?, registers + 1),
+                "CYC" | "Cycle" => state.cycles = value.parse().ok(),
+                // FCEUX: "$C000:4C"
+                _ if name.len() == 5 && name.starts_with('$') => pc = pc.or(u16::from_str_radix(&name[1..], 16).ok()),
+                _ => {}
+            }
+        } else if index == 0 && token.len() == 4 {
+            pc = pc.or(u16::from_str_radix(token, 16).ok());
+        } else if let Some(cycles) = token.strip_prefix('c').filter(|cycles| !cycles.is_empty()) {
+            // FCEUX: "c1234"
+            if let Ok(cycles) = cycles.parse() {
+                state.cycles = Some(cycles);
+            }
+        }
+    }
+    state.pc = pc?;
+    (registers == 5).then_some(state)
+}
+
+fn parse_flags(value: &str) -> Option<u8> {
+    // "P:24"; letters that are also hex digits are only used in the longer form
+    if let (2, Ok(flags)) = (value.len(), u8::from_str_radix(value, 16)) {
+        return Some(flags);
+    }
+    let mut flags = 0;
+    for letter in value.chars() {
+        let bit = match letter.to_ascii_lowercase() {
+            'n' => 7,
+            'v' => 6,
+            'u' | '-' => 5,
+            'b' => 4,
+            'd' => 3,
+            'i' => 2,
+            'z' => 1,
+            'c' => 0,
+            _ => return None,
+        };
+        if letter.is_ascii_uppercase() {
+            flags |= 1 << bit;
+        }
+    }
+    Some(flags)
+}
+
+#[derive(Debug)]
+pub struct TraceComparator<'a> {
+    lines: std::iter::Enumerate<std::str::Lines<'a>>,
+    // Reference cycles minus ours, from the first line with a cycle count
+    cycle_offset: Option<i128>,
+    context: VecDeque<String>,
+    compared_lines: usize,
+}
+
+impl<'a> TraceComparator<'a> {
+    pub fn new(reference: &'a str) -> Self {
+        Self { lines: reference.lines().enumerate(), cycle_offset: None, context: VecDeque::new(), compared_lines: 0 }
+    }
+
+    pub fn compared_lines(&self) -> usize {
+        self.compared_lines
+    }
+
+    // Compares the CPU, before its next instruction, with the next line of the reference.
+    // Returns false once the reference is over.
+    pub fn check(&mut self, cpu: &CPU) -> Result<bool, Box<Divergence>> {
+        let (index, line, expected) = loop {
+            let Some((index, line)) = self.lines.next() else {
+                return Ok(false);
+            };
+            match parse_reference_line(line) {
+                Some(state) => break (index, line, state),
+                // Registers that cannot be read are not skipped, the divergence would show up later
+                None if line.contains("A:") => return Err(self.divergence(index + 1, vec!["unreadable line"], line.trim().to_string(), cpu)),
+                None => {}
+            }
+        };
+
+        let mut fields = Vec::new();
+        let registers = [
+            ("PC", cpu.program_counter, expected.pc),
+            ("A", cpu.accumulator as u16, expected.a as u16),
+            ("X", cpu.x_register as u16, expected.x as u16),
+            ("Y", cpu.y_register as u16, expected.y as u16),
+            ("P", (cpu.status_register & !IGNORED_FLAGS) as u16, (expected.p & !IGNORED_FLAGS) as u16),
+            ("SP", cpu.stack_pointer as u16, expected.sp as u16),
+        ];
+        for (name, actual, expected) in registers {
+            if actual != expected {
+                fields.push(name);
+            }
+        }
+        if let Some(cycles) = expected.cycles {
+            let offset = *self.cycle_offset.get_or_insert(cycles as i128 - cpu.cycles as i128);
+            if cycles as i128 - cpu.cycles as i128 != offset {
+                fields.push("CYC");
+            }
+        }
+
+        if !fields.is_empty() {
+            return Err(self.divergence(index + 1, fields, line.trim().to_string(), cpu));
+        }
+        if self.context.len() == CONTEXT_LINES {
+            self.context.pop_front();
+        }
+        self.context.push_back(trace(cpu));
+        self.compared_lines += 1;
+        Ok(true)
+    }
+
+    // Runs the CPU until the end of the reference, or until it halts (which is a divergence if
+    // the reference goes on). Returns the number of lines compared.
+    pub fn run(&mut self, cpu: &mut CPU) -> Result<usize, Box<Divergence>> {
+        while self.check(cpu)? {
+            if cpu.halted {
+                return Err(self.divergence(self.compared_lines + 1, vec!["halted"], "(more instructions)".to_string(), cpu));
+            }
+            cpu.step();
+        }
+        Ok(self.compared_lines)
+    }
+
+    fn divergence(&self, line: usize, fields: Vec<&'static str>, expected: String, cpu: &CPU) -> Box<Divergence> {
+        Box::new(Divergence { line, fields, expected, actual: trace(cpu), context: self.context.iter().cloned().collect() })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bus::Bus;
+    use crate::cpu6502::{new_cpu, CPU};
+    use crate::power_on::PowerOnConfig;
+    use crate::rom::Rom;
+    use crate::trace_compare::{parse_reference_line, ReferenceState, TraceComparator};
+
+    const NESTEST_ROM_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/nestest.nes");
+    const NESTEST_LOG_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/nestest.log");
+
+    fn nestest_cpu() -> CPU {
+        let rom = Rom::parse_nes_rom(std::fs::read(NESTEST_ROM_PATH).unwrap()).unwrap();
+        let mut cpu = new_cpu(Bus::new(rom));
+        cpu.power_on(&PowerOnConfig::default()).unwrap();
+        cpu.program_counter = 0xC000;
+        cpu
+    }
+
+    #[test]
+    fn test_parse_formats() {
+        let expected = ReferenceState { pc: 0xC000, a: 0x01, x: 0x02, y: 0x03, p: 0x24, sp: 0xFD, cycles: Some(7) };
+        let nestest = "C000  4C F5 C5  JMP $C5F5                       A:01 X:02 Y:03 P:24 SP:FD PPU:  0, 21 CYC:7";
+        assert_eq!(parse_reference_line(nestest), Some(expected));
+        let fceux = "c7  A:01 X:02 Y:03 S:FD P:nvUbdIzc  $C000:4C F5 C5  JMP $C5F5";
+        assert_eq!(parse_reference_line(fceux), Some(expected));
+        let mesen = "C000  JMP $C5F5  A:01 X:02 Y:03 S:FD P:nvIzc  V:0 H:21 Cycle:7";
+        assert_eq!(parse_reference_line(mesen), Some(ReferenceState { p: 0x04, ..expected }));
+
+        assert_eq!(parse_reference_line("C000  JMP $C5F5  A:01 X:02 Y:03 S:FD  V:0").map(|state| state.pc), None);
+        assert_eq!(parse_reference_line("FCEUX 2.6.6 - Trace Log File"), None);
+    }
+
+    #[test]
+    fn test_nestest_log_matches() {
+        let reference = std::fs::read_to_string(NESTEST_LOG_PATH).unwrap();
+        // Stops before the end, where the log shows APU registers that are not emulated
+        let reference: String = reference.lines().take(5000).map(|line| format!("{}\n", line)).collect();
+        let mut comparator = TraceComparator::new(&reference);
+        assert_eq!(comparator.run(&mut nestest_cpu()), Ok(5000));
+    }
+
+    #[test]
+    fn test_first_divergence_is_reported() {
+        let reference = std::fs::read_to_string(NESTEST_LOG_PATH).unwrap();
+        let mut lines: Vec<String> = reference.lines().take(20).map(str::to_string).collect();
+        lines[11] = lines[11].replace("X:00", "X:42").replace("CYC:", "CYC:1");
+
+        let reference = lines.join("\n");
+        let divergence = TraceComparator::new(&reference).run(&mut nestest_cpu()).unwrap_err();
+        assert_eq!(divergence.line, 12);
+        assert_eq!(divergence.fields, vec!["X", "CYC"]);
+        assert_eq!(divergence.context.len(), 8);
+        assert_eq!(divergence.context[7], reference.lines().nth(10).unwrap());
+        assert!(divergence.to_string().contains("expected: ") && divergence.to_string().contains("X:42"));
+
+        lines[11] = lines[11].replace("A:", "A:7");
+        let divergence = TraceComparator::new(&lines.join("\n")).run(&mut nestest_cpu()).unwrap_err();
+        assert_eq!((divergence.line, divergence.fields), (12, vec!["unreadable line"]));
+    }
+}
@@ -0,0 +1,478 @@
+use std::collections::VecDeque;
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::ops::RangeInclusive;
+use crate::cpu6502::{lookup_operand, write_trace_instruction, AddressingMode, CPU};
+use crate::disasm::disassemble_instruction_with_labels;
+
+// Where the trace lines are written to.
+pub enum TraceSink {
+    Stdout,
+    File(BufWriter<File>),
+    // Keeps only the last `capacity` lines in memory, e.g. to inspect what happened before a crash.
+    RingBuffer { lines: VecDeque<String>, capacity: usize },
+}
+
+// Layout of the trace lines.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TraceFormat {
+    // Fixed-width text, same layout as nestest.log
+    Nestest,
+    // One JSON object per line
+    JsonLines,
+    // Comma separated values, preceded by a header line
+    Csv,
+}
+
+// Optional columns appended after the registers.
+#[derive(Debug, Clone, Copy)]
+pub struct TraceOptions {
+    // PPU position (scanline, dot), "PPU:233,209"
+    pub ppu_position: bool,
+    // Global cycle counter, "CYC:26554"
+    pub cycles: bool,
+    // Number of bytes pushed on the stack, "SD:2"
+    pub stack_depth: bool,
+}
+
+impl Default for TraceOptions {
+    // Same columns as nestest.log
+    fn default() -> Self {
+        Self { ppu_position: true, cycles: true, stack_depth: false }
+    }
+}
+
+// Selects the instructions that are logged, to keep traces of long sessions small enough to be
+// analyzed. An instruction is logged when it passes every filter that is set.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TraceFilter {
+    // Only instructions located in one of these ranges (any address when empty)
+    pub pc_ranges: Vec<RangeInclusive<u16>>,
+    // Only branches, jumps, subroutine calls and returns, and BRK
+    pub control_flow_only: bool,
+    // Only instructions whose memory operand is in one of these ranges (any instruction when empty)
+    pub watched_addresses: Vec<RangeInclusive<u16>>,
+}
+
+impl TraceFilter {
+    pub fn is_empty(&self) -> bool {
+        *self == TraceFilter::default()
+    }
+
+    // Tells whether the instruction at the program counter is logged. Memory is only peeked.
+    pub fn matches(&self, cpu: &CPU) -> bool {
+        let pc = cpu.program_counter;
+        if !self.pc_ranges.is_empty() && !self.pc_ranges.iter().any(|range| range.contains(&pc)) {
+            return false;
+        }
+        if !self.control_flow_only && self.watched_addresses.is_empty() {
+            return true;
+        }
+        let Some(operand) = lookup_operand(cpu.peek_u8(pc)) else {
+            return false;
+        };
+        if self.control_flow_only
+            && !matches!(operand.addressing_mode, AddressingMode::Relative)
+            && !matches!(operand.name, "JMP" | "JSR" | "RTS" | "RTI" | "BRK")
+        {
+            return false;
+        }
+        if !self.watched_addresses.is_empty() {
+            // Immediate values, jump targets and branch offsets are not memory accesses
+            let accesses_memory = !matches!(
+                operand.addressing_mode,
+                AddressingMode::Immediate | AddressingMode::Implicit | AddressingMode::Accumulator | AddressingMode::Relative
+            ) && !matches!(operand.name, "JMP" | "JSR");
+            if !accesses_memory {
+                return false;
+            }
+            let (address, _) = cpu.peek_operand_address(operand.addressing_mode, pc.wrapping_add(1));
+            if !self.watched_addresses.iter().any(|range| range.contains(&address)) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+// Parses an address ("0720", "$0720") or an inclusive range ("C000-C0FF") in hexadecimal.
+pub fn parse_address_range(text: &str) -> Result<RangeInclusive<u16>, String> {
+    let parse = |address: &str| {
+        u16::from_str_radix(address.trim().trim_start_matches('$'), 16).map_err(|_| format!("Invalid address: {}", address))
+    };
+    let (start, end) = match text.split_once('-') {
+        Some((start, end)) => (parse(start)?, parse(end)?),
+        None => (parse(text)?, parse(text)?),
+    };
+    if start > end {
+        return Err(format!("Invalid address range: {}", text));
+    }
+    Ok(start..=end)
+}
+
+// Logs a trace line before each executed instruction.
+// Logging can be started and stopped at any time, so long sessions can be traced selectively.
+pub struct TraceLogger {
+    sink: TraceSink,
+    options: TraceOptions,
+    format: TraceFormat,
+    running: bool,
+    filter: TraceFilter,
+    csv_header_written: bool,
+    // Reused for every line, so that logging does not allocate
+    line: String,
+}
+
+impl TraceLogger {
+    pub fn new(sink: TraceSink, options: TraceOptions) -> Self {
+        Self { sink, options, format: TraceFormat::Nestest, running: true, filter: TraceFilter::default(), csv_header_written: false, line: String::new() }
+    }
+
+    pub fn stdout() -> Self {
+        Self::new(TraceSink::Stdout, TraceOptions::default())
+    }
+
+    pub fn file(path: &str) -> Result<Self, String> {
+        let file = File::create(path).map_err(|error| format!("Failed to create trace file {}: {}", path, error))?;
+        Ok(Self::new(TraceSink::File(BufWriter::new(file)), TraceOptions::default()))
+    }
+
+    pub fn ring_buffer(capacity: usize) -> Self {
+        let sink = TraceSink::RingBuffer { lines: VecDeque::with_capacity(capacity), capacity };
+        Self::new(sink, TraceOptions::default())
+    }
+
+    pub fn set_options(&mut self, options: TraceOptions) {
+        self.options = options;
+    }
+
+    pub fn set_format(&mut self, format: TraceFormat) {
+        self.format = format;
+        self.csv_header_written = false;
+    }
+
+    pub fn set_filter(&mut self, filter: TraceFilter) {
+        self.filter = filter;
+    }
+
+    pub fn filter(&self) -> &TraceFilter {
+        &self.filter
+    }
+
+    pub fn start(&mut self) {
+        self.running = true;
+    }
+
+    pub fn stop(&mut self) {
+        self.running = false;
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running
+    }
+
+    // Formats the trace line of the instruction at the program counter.
+    // Optional columns only apply to the nestest format, structured formats always contain every field.
+    pub fn format_line(&self, cpu: &CPU) -> String {
+        let mut line = String::new();
+        self.write_formatted_line(cpu, &mut line);
+        line
+    }
+
+    fn write_formatted_line(&self, cpu: &CPU, out: &mut String) {
+        match self.format {
+            TraceFormat::Nestest => self.write_nestest_line(cpu, out),
+            TraceFormat::JsonLines => out.push_str(&TraceRecord::capture(cpu).to_json()),
+            TraceFormat::Csv => out.push_str(&TraceRecord::capture(cpu).to_csv()),
+        }
+    }
+
+    fn write_nestest_line(&self, cpu: &CPU, out: &mut String) {
+        write_trace_instruction(cpu, out);
+        // Writing to a String cannot fail
+        if self.options.ppu_position {
+            let (scanline, dot) = cpu.ppu_position();
+            let _ = write!(out, " PPU:{:3},{:3}", scanline, dot);
+        }
+        if self.options.cycles {
+            let _ = write!(out, " CYC:{}", cpu.cycles);
+        }
+        if self.options.stack_depth {
+            let _ = write!(out, " SD:{}", 0xFF - cpu.stack_pointer);
+        }
+    }
+
+    // Must be called before each instruction, typically from `run_with_callback`.
+    pub fn log(&mut self, cpu: &CPU) -> Result<(), String> {
+        if !self.running || !self.filter.matches(cpu) {
+            return Ok(());
+        }
+
+        if self.format == TraceFormat::Csv && !self.csv_header_written {
+            self.csv_header_written = true;
+            self.write_line(TraceRecord::CSV_HEADER)?;
+        }
+        let mut line = std::mem::take(&mut self.line);
+        line.clear();
+        self.write_formatted_line(cpu, &mut line);
+        let result = self.write_line(&line);
+        self.line = line;
+        result
+    }
+
+    fn write_line(&mut self, line: &str) -> Result<(), String> {
+        match &mut self.sink {
+            TraceSink::Stdout => println!("{}", line),
+            TraceSink::File(writer) => {
+                writeln!(writer, "{}", line).map_err(|error| format!("Failed to write trace: {}", error))?;
+            }
+            TraceSink::RingBuffer { lines, capacity } => {
+                // Once full, the oldest line's buffer is reused for the new one
+                if *capacity > 0 {
+                    let mut buffer = if lines.len() == *capacity { lines.pop_front().unwrap_or_default() } else { String::new() };
+                    buffer.clear();
+                    buffer.push_str(line);
+                    lines.push_back(buffer);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Lines kept by the ring buffer sink, oldest first. Other sinks do not keep any line.
+    pub fn lines(&self) -> Vec<&str> {
+        match &self.sink {
+            TraceSink::RingBuffer { lines, .. } => lines.iter().map(String::as_str).collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    pub fn flush(&mut self) -> Result<(), String> {
+        if let TraceSink::File(writer) = &mut self.sink {
+            writer.flush().map_err(|error| format!("Failed to write trace: {}", error))?;
+        }
+        Ok(())
+    }
+}
+
+// Machine-readable state of the CPU before an instruction is executed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraceRecord {
+    pub pc: u16,
+    // Raw bytes of the instruction, the opcode being the first one
+    pub bytes: Vec<u8>,
+    pub mnemonic: &'static str,
+    pub operand: String,
+    pub a: u8,
+    pub x: u8,
+    pub y: u8,
+    pub p: u8,
+    pub sp: u8,
+    pub cycles: u64,
+}
+
+impl TraceRecord {
+    pub const CSV_HEADER: &'static str = "pc,opcode,operands,mnemonic,operand,a,x,y,p,sp,flags,cycles";
+
+    pub fn capture(cpu: &CPU) -> Self {
+        let line = disassemble_instruction_with_labels(&cpu.bus, cpu.program_counter, &cpu.labels);
+        Self {
+            pc: cpu.program_counter,
+            bytes: line.bytes,
+            mnemonic: line.name,
+            operand: line.operand,
+            a: cpu.accumulator,
+            x: cpu.x_register,
+            y: cpu.y_register,
+            p: cpu.status_register,
+            sp: cpu.stack_pointer,
+            cycles: cpu.cycles,
+        }
+    }
+
+    // Status register as letters, a cleared flag being displayed as '-', e.g. "--U--IZC"
+    pub fn flags(&self) -> String {
+        "NVUBDIZC".chars()
+            .enumerate()
+            .map(|(index, letter)| if self.p & (0x80 >> index) != 0 { letter } else { '-' })
+            .collect()
+    }
+
+    // Operand bytes of the instruction (without the opcode)
+    fn operand_bytes(&self) -> &[u8] {
+        &self.bytes[1..]
+    }
+
+    // Numbers are written as decimal JSON numbers so that tools do not have to parse hexadecimal.
+    // Mnemonics and operands never contain characters that need to be escaped.
+    pub fn to_json(&self) -> String {
+        let operands = self.operand_bytes().iter()
+            .map(|byte| byte.to_string())
+            .collect::<Vec<String>>()
+            .join(",");
+        format!(
+            "{{\"pc\":{},\"opcode\":{},\"operands\":[{}],\"mnemonic\":\"{}\",\"operand\":\"{}\",\"a\":{},\"x\":{},\"y\":{},\"p\":{},\"sp\":{},\"flags\":\"{}\",\"cycles\":{}}}",
+            self.pc, self.bytes[0], operands, self.mnemonic, self.operand,
+            self.a, self.x, self.y, self.p, self.sp, self.flags(), self.cycles
+        )
+    }
+
+    // Values are written in hexadecimal like in the nestest format, operand bytes being separated by spaces.
+    // Operands such as "$10,X" contain commas, so the operand column is quoted.
+    pub fn to_csv(&self) -> String {
+        let operands = self.operand_bytes().iter()
+            .map(|byte| format!("{:02X}", byte))
+            .collect::<Vec<String>>()
+            .join(" ");
+        format!(
+            "{:04X},{:02X},{},{},\"{}\",{:02X},{:02X},{:02X},{:02X},{:02X},{},{}",
+            self.pc, self.bytes[0], operands, self.mnemonic, self.operand,
+            self.a, self.x, self.y, self.p, self.sp, self.flags(), self.cycles
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bus::Bus;
+    use crate::cpu6502::{new_cpu, trace};
+    use crate::power_on::PowerOnConfig;
+    use crate::rom::Rom;
+    use crate::trace_logger::{parse_address_range, TraceFilter, TraceFormat, TraceLogger, TraceOptions, TraceRecord};
+
+    #[test]
+    fn test_default_format_matches_trace() {
+        let mut cpu = new_cpu(Bus::new(Rom::test_rom()));
+        cpu.power_on(&PowerOnConfig::default()).unwrap();
+        let logger = TraceLogger::ring_buffer(1);
+        assert_eq!(logger.format_line(&cpu), trace(&cpu));
+    }
+
+    #[test]
+    fn test_optional_columns() {
+        let mut cpu = new_cpu(Bus::new(Rom::test_rom()));
+        cpu.power_on(&PowerOnConfig::default()).unwrap();
+        let mut logger = TraceLogger::ring_buffer(1);
+
+        logger.set_options(TraceOptions { ppu_position: false, cycles: false, stack_depth: true });
+        let line = logger.format_line(&cpu);
+        assert!(!line.contains("PPU:"));
+        assert!(!line.contains("CYC:"));
+        assert!(line.ends_with("SP:FD SD:2"), "unexpected line: {}", line);
+    }
+
+    #[test]
+    fn test_ring_buffer_keeps_last_lines_and_start_stop() {
+        let mut cpu = new_cpu(Bus::new(Rom::test_rom()));
+        cpu.power_on(&PowerOnConfig::default()).unwrap();
+        let mut logger = TraceLogger::ring_buffer(2);
+
+        for _ in 0..3 {
+            logger.log(&cpu).unwrap();
+            cpu.step();
+        }
+        let lines = logger.lines();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[1].ends_with("CYC:11"), "unexpected line: {}", lines[1]);
+
+        logger.stop();
+        logger.log(&cpu).unwrap();
+        assert!(logger.lines()[1].ends_with("CYC:11"));
+
+        logger.start();
+        logger.log(&cpu).unwrap();
+        assert!(logger.lines()[1].ends_with("CYC:13"));
+    }
+
+    #[test]
+    fn test_structured_formats() {
+        let mut bus = Bus::new_flat();
+        // STA $10,X
+        bus.write_u8(0x0600, 0x95);
+        bus.write_u8(0x0601, 0x10);
+        let mut cpu = new_cpu(bus);
+        cpu.program_counter = 0x0600;
+        cpu.accumulator = 0x42;
+        cpu.x_register = 0x01;
+        cpu.status_register = 0x27;
+        cpu.stack_pointer = 0xFD;
+        cpu.cycles = 7;
+
+        let record = TraceRecord::capture(&cpu);
+        assert_eq!(record.flags(), "--U--IZC");
+        assert_eq!(
+            record.to_json(),
+            r#"{"pc":1536,"opcode":149,"operands":[16],"mnemonic":"STA","operand":"$10,X","a":66,"x":1,"y":0,"p":39,"sp":253,"flags":"--U--IZC","cycles":7}"#
+        );
+        assert_eq!(record.to_csv(), r#"0600,95,10,STA,"$10,X",42,01,00,27,FD,--U--IZC,7"#);
+
+        let mut logger = TraceLogger::ring_buffer(10);
+        logger.set_format(TraceFormat::Csv);
+        logger.log(&cpu).unwrap();
+        logger.log(&cpu).unwrap();
+        // The header is only written once
+        assert_eq!(logger.lines(), vec![TraceRecord::CSV_HEADER, &record.to_csv(), &record.to_csv()]);
+    }
+
+    #[test]
+    fn test_trace_uses_labels() {
+        let mut bus = Bus::new_flat();
+        // JSR $C5F2
+        bus.write_u8(0x0600, 0x20);
+        bus.write_u8(0x0601, 0xF2);
+        bus.write_u8(0x0602, 0xC5);
+        let mut cpu = new_cpu(bus);
+        cpu.program_counter = 0x0600;
+        cpu.labels.insert(0xC5F2, "reset_handler", 1);
+
+        assert!(trace(&cpu).starts_with("0600  20 F2 C5  JSR reset_handler "), "unexpected line: {}", trace(&cpu));
+        assert_eq!(TraceRecord::capture(&cpu).operand, "reset_handler");
+    }
+
+    // Logs the first 6 instructions of:
+    // 0600: LDA #$01; STA $0720; BNE +1; NOP; JSR $0610
+    // 0610: INC $0700,X; RTS
+    fn filtered_trace(filter: TraceFilter) -> Vec<String> {
+        let mut bus = Bus::new_flat();
+        let program: [(u16, &[u8]); 2] = [
+            (0x0600, &[0xA9, 0x01, 0x8D, 0x20, 0x07, 0xD0, 0x01, 0xEA, 0x20, 0x10, 0x06]),
+            (0x0610, &[0xFE, 0x00, 0x07, 0x60]),
+        ];
+        for (address, bytes) in program {
+            for (offset, byte) in bytes.iter().enumerate() {
+                bus.write_u8(address + offset as u16, *byte);
+            }
+        }
+        let mut cpu = new_cpu(bus);
+        cpu.program_counter = 0x0600;
+        cpu.x_register = 0x20;
+        let mut logger = TraceLogger::ring_buffer(10);
+        logger.set_filter(filter);
+        for _ in 0..6 {
+            logger.log(&cpu).unwrap();
+            cpu.step();
+        }
+        logger.lines().iter().map(|line| line[..4].to_string()).collect()
+    }
+
+    #[test]
+    fn test_filters() {
+        assert_eq!(filtered_trace(TraceFilter::default()), vec!["0600", "0602", "0605", "0608", "0610", "0613"]);
+        assert_eq!(filtered_trace(TraceFilter { pc_ranges: vec![0x0610..=0x06FF], ..TraceFilter::default() }), vec!["0610", "0613"]);
+        assert_eq!(filtered_trace(TraceFilter { control_flow_only: true, ..TraceFilter::default() }), vec!["0605", "0608", "0613"]);
+        // STA $0720 and INC $0700,X both touch 0x0720, the JSR target does not count
+        let watch = TraceFilter { watched_addresses: vec![0x0720..=0x0720, 0x0610..=0x0610], ..TraceFilter::default() };
+        assert_eq!(filtered_trace(watch), vec!["0602", "0610"]);
+        let combined = TraceFilter { pc_ranges: vec![0x0600..=0x060F], control_flow_only: true, ..TraceFilter::default() };
+        assert_eq!(filtered_trace(combined), vec!["0605", "0608"]);
+    }
+
+    #[test]
+    fn test_parse_address_range() {
+        assert_eq!(parse_address_range("C000-C0FF"), Ok(0xC000..=0xC0FF));
+        assert_eq!(parse_address_range("$0720"), Ok(0x0720..=0x0720));
+        assert!(parse_address_range("C0FF-C000").is_err());
+        assert!(parse_address_range("XYZ").is_err());
+    }
+}
@@ -0,0 +1,180 @@
+use crate::palette::{Palette, PalettePreset};
+use crate::rom::Rom;
+use crate::savestate::{StateReader, StateWriter};
+
+// The VS Unisystem is the arcade version of the NES: the same CPU and mappers, a coin slot,
+// DIP switches to configure the game (difficulty, lives, price) and an RGB PPU.
+// The extra inputs are read through the controller ports, next to the controller data:
+// 0x4016: bit 2 service button, bits 3-4 DIP switches 1 and 2, bits 5-6 coin slots 1 and 2
+// 0x4017: bits 2-7 DIP switches 3 to 8
+// Writing bit 0 of 0x4020 drives the mechanical coin counter.
+
+// PPU of the cabinet, from the NES 2.0 header. The 2C04 variants output the same colors as the
+// 2C03 in a scrambled order, as a copy protection.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum VsPpu {
+    Rp2c03,
+    Rp2c04(u8),
+    Rc2c05(u8),
+}
+
+impl VsPpu {
+    fn from_header(ppu_type: u8) -> Self {
+        match ppu_type {
+            0x2..=0x5 => VsPpu::Rp2c04(ppu_type - 1),
+            0x8..=0xB => VsPpu::Rc2c05(ppu_type - 7),
+            _ => VsPpu::Rp2c03,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct VsSystem {
+    ppu: VsPpu,
+    // DIP switch N is bit N - 1
+    dip_switches: u8,
+    coins: [bool; 2],
+    service_button: bool,
+    coin_counter: u32,
+    coin_counter_line: bool,
+}
+
+impl VsSystem {
+    pub fn new(rom: &Rom) -> Self {
+        let ppu = if rom.is_nes2() { VsPpu::from_header(rom.header.vs_ppu_type()) } else { VsPpu::Rp2c03 };
+        Self { ppu, dip_switches: 0, coins: [false; 2], service_button: false, coin_counter: 0, coin_counter_line: false }
+    }
+
+    pub fn ppu(&self) -> VsPpu {
+        self.ppu
+    }
+
+    // Colors of the RGB PPU. The scrambled orders of the 2C04 are not emulated,
+    // so their games show wrong colors (see the README roadmap).
+    pub fn palette(&self) -> Palette {
+        Palette::preset(PalettePreset::Rgb)
+    }
+
+    pub fn dip_switches(&self) -> u8 {
+        self.dip_switches
+    }
+
+    pub fn set_dip_switches(&mut self, dip_switches: u8) {
+        self.dip_switches = dip_switches;
+    }
+
+    // `switch` is numbered from 1 to 8, like on the board.
+    pub fn set_dip_switch(&mut self, switch: usize, on: bool) {
+        let mask = 1 << (switch - 1);
+        if on {
+            self.dip_switches |= mask;
+        } else {
+            self.dip_switches &= !mask;
+        }
+    }
+
+    // A coin is seen by the game while the slot switch is held, usually for a few frames.
+    pub fn set_coin_inserted(&mut self, slot: usize, inserted: bool) {
+        self.coins[slot] = inserted;
+    }
+
+    pub fn set_service_button(&mut self, pressed: bool) {
+        self.service_button = pressed;
+    }
+
+    // Coins counted by the cabinet since power on.
+    pub fn coin_counter(&self) -> u32 {
+        self.coin_counter
+    }
+
+    // Bits returned by reads of 0x4016 (port 0) or 0x4017 (port 1), on top of the controller data.
+    pub fn read(&self, port: usize) -> u8 {
+        match port {
+            0 => {
+                (self.service_button as u8) << 2
+                    | (self.dip_switches & 0b11) << 3
+                    | (self.coins[0] as u8) << 5
+                    | (self.coins[1] as u8) << 6
+            }
+            _ => self.dip_switches & 0b1111_1100,
+        }
+    }
+
+    // Bits of the reads of 0x4016 (port 0) or 0x4017 (port 1) driven by the cabinet, the others are open bus.
+    pub fn driven_bits(port: usize) -> u8 {
+        match port {
+            0 => 0b0111_1100,
+            _ => 0b1111_1100,
+        }
+    }
+
+    // Write to 0x4020: the counter advances when the line goes high.
+    pub fn write_coin_counter(&mut self, data: u8) {
+        let line = data & 1 != 0;
+        if line && !self.coin_counter_line {
+            self.coin_counter += 1;
+        }
+        self.coin_counter_line = line;
+    }
+
+    pub fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.dip_switches);
+        writer.write_bool(self.coins[0]);
+        writer.write_bool(self.coins[1]);
+        writer.write_bool(self.service_button);
+        writer.write_u64(self.coin_counter as u64);
+        writer.write_bool(self.coin_counter_line);
+    }
+
+    // The PPU comes from the header, it is not part of the state.
+    pub fn load_state(&mut self, reader: &mut StateReader) -> Result<(), String> {
+        self.dip_switches = reader.read_u8()?;
+        self.coins = [reader.read_bool()?, reader.read_bool()?];
+        self.service_button = reader.read_bool()?;
+        self.coin_counter = reader.read_u64()? as u32;
+        self.coin_counter_line = reader.read_bool()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bus::Bus;
+    use crate::rom::Rom;
+    use crate::vs_system::VsPpu;
+
+    fn vs_rom() -> Rom {
+        let mut rom = Rom::test_rom();
+        rom.header.flags_7 = 0b0000_0001;
+        rom
+    }
+
+    #[test]
+    fn test_only_vs_roms() {
+        assert!(Bus::new(Rom::test_rom()).vs_system().is_none());
+        let mut rom = vs_rom();
+        assert!(rom.check_validity().is_ok());
+
+        // NES 2.0 header with a 2C04-0003 PPU
+        rom.header.flags_7 = 0b0000_1001;
+        rom.header.reserved[2] = 0x04;
+        assert_eq!(Bus::new(rom).vs_system().unwrap().ppu(), VsPpu::Rp2c04(3));
+    }
+
+    #[test]
+    fn test_dip_switches_and_coins() {
+        let mut bus = Bus::new(vs_rom());
+        let vs_system = bus.vs_system_mut().unwrap();
+        vs_system.set_dip_switches(0b1010_0110);
+        vs_system.set_coin_inserted(0, true);
+        vs_system.set_dip_switch(1, true);
+        assert_eq!(bus.read_u8(0x4016) & 0b1111_1110, 0b0011_1000);
+        assert_eq!(bus.read_u8(0x4017) & 0b1111_1110, 0b1010_0100);
+
+        bus.write_u8(0x4020, 1);
+        bus.write_u8(0x4020, 1);
+        bus.write_u8(0x4020, 0);
+        bus.write_u8(0x4020, 1);
+        assert_eq!(bus.vs_system().unwrap().coin_counter(), 2);
+    }
+}
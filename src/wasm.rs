@@ -0,0 +1,94 @@
+// JavaScript bindings of the emulation core, for a browser frontend (feature `wasm`). Built with
+// `cargo build --lib --no-default-features --features wasm --target wasm32-unknown-unknown`, then
+// `wasm-bindgen` to generate the JS glue.
+//
+// The page drives the emulation: it calls `runFrame` from `requestAnimationFrame`, draws
+// `framebuffer` into a canvas `ImageData`, and sets the buttons from its key events. The frame
+// pacer and the savestate slots are not exposed: the first sleeps the thread and the second needs
+// a filesystem, neither of which the browser has.
+
+use std::time::Duration;
+use wasm_bindgen::prelude::*;
+use crate::nes::Nes;
+use crate::ppu::render_thread::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::rom::Rom;
+
+// Browser clock for the performance statistics, in place of `Instant` which is not implemented on
+// wasm32-unknown-unknown.
+fn browser_clock() -> Duration {
+    Duration::from_secs_f64(js_sys::Date::now() / 1000.0)
+}
+
+#[wasm_bindgen(js_name = Nes)]
+pub struct WasmNes {
+    nes: Nes,
+}
+
+#[wasm_bindgen(js_class = Nes)]
+impl WasmNes {
+    // Loads an iNES, NES 2.0, .zip or .gz file and powers the console on.
+    #[wasm_bindgen(constructor)]
+    pub fn new(rom: Vec<u8>) -> Result<WasmNes, JsError> {
+        let mut nes = Nes::new(Rom::load_bytes(rom).map_err(|error| JsError::new(&error))?).map_err(|error| JsError::new(&error))?;
+        nes.set_host_clock(Some(browser_clock));
+        Ok(Self { nes })
+    }
+
+    // Swaps the cartridge, see Nes::load_rom. On error, the current game keeps running.
+    #[wasm_bindgen(js_name = loadRom)]
+    pub fn load_rom(&mut self, rom: Vec<u8>) -> Result<(), JsError> {
+        let rom = Rom::load_bytes(rom).map_err(|error| JsError::new(&error))?;
+        self.nes.load_rom(rom).map_err(|error| JsError::new(&error))
+    }
+
+    #[wasm_bindgen(js_name = runFrame)]
+    pub fn run_frame(&mut self) {
+        self.nes.run_frame();
+    }
+
+    // RGBA pixels (256x240) of the last complete frame, the layout of a canvas `ImageData`
+    pub fn framebuffer(&self) -> Vec<u8> {
+        let mut rgba = Vec::with_capacity(SCREEN_WIDTH * SCREEN_HEIGHT * 4);
+        for pixel in self.nes.framebuffer().chunks_exact(3) {
+            rgba.extend_from_slice(&[pixel[0], pixel[1], pixel[2], 0xFF]);
+        }
+        rgba
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn width(&self) -> usize {
+        SCREEN_WIDTH
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn height(&self) -> usize {
+        SCREEN_HEIGHT
+    }
+
+    // Buttons held by `player` (0 - 3), bit N being `JoypadButton` N: A, B, Select, Start, Up,
+    // Down, Left, Right
+    #[wasm_bindgen(js_name = setButtons)]
+    pub fn set_buttons(&mut self, player: usize, buttons: u8) {
+        self.nes.cpu.bus.joypads_mut().set_buttons(player, buttons);
+    }
+
+    pub fn reset(&mut self) {
+        self.nes.reset();
+    }
+
+    #[wasm_bindgen(js_name = powerCycle)]
+    pub fn power_cycle(&mut self) -> Result<(), JsError> {
+        self.nes.power_cycle().map_err(|error| JsError::new(&error))
+    }
+
+    // Savestate of the whole console (see savestate.rs), for the page to keep in IndexedDB or a download
+    #[wasm_bindgen(js_name = saveState)]
+    pub fn save_state(&self) -> Vec<u8> {
+        self.nes.save_state()
+    }
+
+    #[wasm_bindgen(js_name = loadState)]
+    pub fn load_state(&mut self, state: &[u8]) -> Result<(), JsError> {
+        self.nes.load_state(state).map_err(|error| JsError::new(&error))
+    }
+}
@@ -0,0 +1,130 @@
+use crate::cpu6502::CPU;
+use crate::expression::Expression;
+use crate::labels::Labels;
+
+// Backend of the watch panel: expressions (see expression.rs) re-evaluated by `Nes::run_frame`
+// after every frame, and by `Nes::step` after every instruction while paused. Each watch keeps
+// whether its last update changed its value, so the panel can highlight it.
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Watch {
+    pub expression: Expression,
+    // None until the first update; evaluation errors (e.g. a division by zero) are kept as text
+    pub value: Option<Result<i64, String>>,
+    // The last update changed the value (the first one never does)
+    pub changed: bool,
+}
+
+impl Watch {
+    // Hexadecimal and decimal, e.g. "$34 (52)" or "$1234 (4660)"
+    pub fn formatted_value(&self) -> String {
+        match &self.value {
+            None => "-".to_string(),
+            Some(Err(error)) => format!("<{}>", error),
+            Some(Ok(value @ 0..=0xFF)) => format!("${:02X} ({})", value, value),
+            Some(Ok(value @ 0..=0xFFFF)) => format!("${:04X} ({})", value, value),
+            Some(Ok(value)) => value.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct WatchList {
+    watches: Vec<Watch>,
+}
+
+impl WatchList {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Adds a watch at the end of the list, and returns its index. It is evaluated on the next update.
+    pub fn add(&mut self, text: &str, labels: &Labels) -> Result<usize, String> {
+        let expression = Expression::parse(text, labels)?;
+        self.watches.push(Watch { expression, value: None, changed: false });
+        Ok(self.watches.len() - 1)
+    }
+
+    pub fn remove(&mut self, index: usize) -> Option<Watch> {
+        (index < self.watches.len()).then(|| self.watches.remove(index))
+    }
+
+    pub fn clear(&mut self) {
+        self.watches.clear();
+    }
+
+    pub fn watches(&self) -> &[Watch] {
+        &self.watches
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.watches.is_empty()
+    }
+
+    pub fn update(&mut self, cpu: &CPU) {
+        for watch in &mut self.watches {
+            let value = watch.expression.evaluate(cpu);
+            watch.changed = watch.value.as_ref().is_some_and(|previous| *previous != value);
+            watch.value = Some(value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bus::Bus;
+    use crate::cpu6502::new_cpu;
+    use crate::labels::Labels;
+    use crate::nes::Nes;
+    use crate::rom::Rom;
+    use crate::watch::WatchList;
+
+    #[test]
+    fn test_update_and_changes() {
+        let mut cpu = new_cpu(Bus::new_flat());
+        let mut watches = WatchList::new();
+        assert_eq!(watches.add("word[0x00FD]", &Labels::new()), Ok(0));
+        assert_eq!(watches.add("A + X", &Labels::new()), Ok(1));
+        assert_eq!(watches.add("1 / A", &Labels::new()), Ok(2));
+        assert!(watches.add("A +", &Labels::new()).is_err());
+        assert_eq!(watches.watches()[0].formatted_value(), "-");
+
+        watches.update(&cpu);
+        assert_eq!(watches.watches()[0].value, Some(Ok(0)));
+        assert!(watches.watches().iter().all(|watch| !watch.changed));
+        assert_eq!(watches.watches()[2].formatted_value(), "<Division by zero>");
+
+        cpu.bus.poke_u8(0x00FE, 0x12).unwrap();
+        watches.update(&cpu);
+        let changed: Vec<bool> = watches.watches().iter().map(|watch| watch.changed).collect();
+        assert_eq!(changed, vec![true, false, false]);
+        assert_eq!(watches.watches()[0].formatted_value(), "$1200 (4608)");
+
+        // Only the last update counts
+        watches.update(&cpu);
+        assert!(!watches.watches()[0].changed);
+
+        cpu.accumulator = 1;
+        watches.update(&cpu);
+        assert_eq!(watches.watches()[2].formatted_value(), "$01 (1)");
+        assert!(watches.watches()[2].changed);
+
+        assert_eq!(watches.remove(0).map(|watch| watch.expression.to_string()), Some("word[0x00FD]".to_string()));
+        assert!(watches.remove(5).is_none());
+        assert_eq!(watches.watches().len(), 2);
+    }
+
+    #[test]
+    fn test_updated_by_the_console() {
+        let mut nes = Nes::new(Rom::test_rom()).unwrap();
+        nes.watches.add("PC", &Labels::new()).unwrap();
+        nes.run_frame();
+        let after_frame = nes.watches.watches()[0].value.clone();
+        assert_eq!(after_frame, Some(Ok(nes.cpu.program_counter as i64)));
+
+        // While paused, after each instruction
+        nes.step();
+        assert_eq!(nes.watches.watches()[0].value, Some(Ok(nes.cpu.program_counter as i64)));
+        assert!(nes.watches.watches()[0].changed);
+    }
+}